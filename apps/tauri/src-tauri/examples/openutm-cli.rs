@@ -0,0 +1,91 @@
+//! Drive a running OpenUTM over its control socket
+//!
+//! Enable the socket in settings, then:
+//!
+//! ```text
+//! export OPENUTM_CONTROL_SOCKET=<endpoint from settings>
+//! export OPENUTM_CONTROL_TOKEN=<token from settings>
+//! cargo run --example openutm-cli -- list
+//! cargo run --example openutm-cli -- start <vm-id>
+//! cargo run --example openutm-cli -- stop <vm-id>
+//! ```
+//!
+//! `--socket` and `--token` override the environment.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: openutm-cli [--socket PATH] [--token TOKEN] <list | start <id> | stop <id>>";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut socket = std::env::var("OPENUTM_CONTROL_SOCKET").ok();
+    let mut token = std::env::var("OPENUTM_CONTROL_TOKEN").ok();
+    let mut command = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => socket = args.next(),
+            "--token" => token = args.next(),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => command.push(arg),
+        }
+    }
+    let socket = socket.ok_or("no socket given; set OPENUTM_CONTROL_SOCKET or pass --socket")?;
+    let token = token.ok_or("no token given; set OPENUTM_CONTROL_TOKEN or pass --token")?;
+
+    let (method, params) = match command.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["list"] => ("vm.list", json!({ "token": token })),
+        ["start", id] => ("vm.start", json!({ "token": token, "id": id })),
+        ["stop", id] => ("vm.stop", json!({ "token": token, "id": id })),
+        _ => return Err(USAGE.to_string()),
+    };
+    let result = call(&socket, method, params)?;
+
+    if method == "vm.list" {
+        for vm in result.as_array().into_iter().flatten() {
+            println!(
+                "{}  {:<10}  {}",
+                vm["id"].as_str().unwrap_or_default(),
+                vm["status"].as_str().unwrap_or_default(),
+                vm["name"].as_str().unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Send one request and wait for its response
+fn call(socket: &str, method: &str, params: Value) -> Result<Value, String> {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let mut stream = connect(socket).map_err(|err| format!("cannot connect to {}: {}", socket, err))?;
+    writeln!(stream, "{}", request).map_err(|err| err.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(|err| err.to_string())?;
+    let response: Value = serde_json::from_str(&line).map_err(|err| format!("bad response: {}", err))?;
+    match response.get("error") {
+        Some(error) => Err(format!("{} ({})", error["message"].as_str().unwrap_or("error"), error["code"])),
+        None => Ok(response["result"].clone()),
+    }
+}
+
+#[cfg(unix)]
+fn connect(socket: &str) -> std::io::Result<std::os::unix::net::UnixStream> {
+    std::os::unix::net::UnixStream::connect(socket)
+}
+
+#[cfg(windows)]
+fn connect(socket: &str) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new().read(true).write(true).open(socket)
+}
@@ -1,21 +1,70 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 
-use tauri::State;
+use tauri::{Manager, State};
 use uuid::Uuid;
 
-use crate::config::{ConfigStore, VMRecord};
-use crate::qemu::{self, Accelerator, DisplayConfig, DriveConfig, MachineType, NetdevConfig, QemuCommand};
-use crate::storage::DiskManager;
-use crate::{platform, DisplaySession, QemuInfo, VMConfig, VMStatus, VM};
-
-pub struct CommandState {
-    pub config_store: ConfigStore,
-    pub disk_manager: DiskManager,
-    pub storage_dir: PathBuf,
-    pub qemu_controller: tokio::sync::Mutex<qemu::QemuController>,
-    pub display_sessions: tokio::sync::Mutex<HashMap<String, DisplaySession>>,
-}
+use openutm_core::app::{
+    self, active_accelerator, affinity_warning, apply_disk_resize, attach_display_proxy, begin_reconnect, boot_timings,
+    build_display_session, check_hugepages, check_vm_config, config_changes, default_accelerator, display_host,
+    ensure_has_display, ensure_provisioned, ensure_unique_name, fetch_vm_or_err, finish_recording,
+    force_stop_recording, generate_spice_password, host_limits, hotplug_names, launch_accelerator, map_record_to_vm,
+    mark_disconnected, notify_vm_list_changed, now_ms, parse_vm_status, pending_start_args, refresh_session_liveness,
+    reject_ephemeral, reject_errors, remove_ephemeral_overlay, remove_secret_file, reset_reconnects, save_vm,
+    select_accelerator, set_boot_once, set_session_password, set_session_tls, update_vm_status,
+    validate_cpu_affinity, validate_remote_display, vm_disk_path, vm_display_port, with_stable_mac, AppState,
+    HostCapabilities,
+};
+use openutm_core::control::{self, ControlSocketInfo};
+use openutm_core::error::{CommandError, CommandResult, Error, ErrorCode};
+use openutm_core::i18n::{self, MessageCatalog};
+use openutm_core::service;
+use openutm_core::config::{
+    ConfigStore, ExternalSnapshotRecord, GroupRecord, ProfileRecord, VMRecord, VmCursor, VmSort, VmSummary,
+    SPICE_TICKETING_SETTING, UNIQUE_NAMES_SETTING, DISPLAY_RECONNECT_ATTEMPTS_SETTING,
+    DISPLAY_SESSION_GRACE_SETTING, VIEWER_FULLSCREEN_SETTING, DISPLAY_PORT_RANGE_START_SETTING,
+    DISPLAY_PORT_RANGE_END_SETTING, HEALTH_CHECK_INTERVAL_SETTING, HEALTH_CHECK_THRESHOLD_SETTING, QEMU_BINARY_KEY,
+    METRICS_LISTEN_SETTING, CONTROL_SOCKET_SETTING, CONTROL_TOKEN_SETTING,
+};
+use openutm_core::benchmark::{self, BenchmarkContext, BenchmarkReport};
+use openutm_core::guest::{GuestOs, GuestOsDefaults, ALL_GUEST_OS};
+use openutm_core::qemu::qmp::QmpClient;
+use openutm_core::qemu::{
+    self, DisplayPortRange, IoThrottle, SpiceCompression, MemoryBackend, NetworkConfig, DISK_INTERFACES,
+    MEMORY_BACKENDS, VmPerformance, VmRunningInfo,
+};
+use openutm_core::storage::{self, DiskEntry, DiskInfo, DiskSnapshot};
+use openutm_core::logging;
+use openutm_core::memory_policy::MemoryPolicy;
+use openutm_core::metrics;
+use openutm_core::rate_limit::{self, RateLimit};
+use openutm_core::recording;
+use openutm_core::reconcile::Correction;
+use openutm_core::viewer;
+use openutm_core::spice_tls;
+use openutm_core::paths::{self, MigrationMode};
+use openutm_core::profiles;
+use openutm_core::validation;
+use openutm_core::{
+    platform, AccelerationDiagnostics, ArchInfo, AcceleratorSupport, ActiveAccelerator, BootTimings, ConfigDiff,
+    CpuModelList, DataMigrationStatus, DisplaySession, HostResources, LaunchInfo, PlatformInfo, QemuInfo,
+    RecordingSummary, RemoteDisplay, StartupStatus, VfioDeviceInfo, VMConfig, VMStatus, VMWarning, VmMetrics,
+    VmOverview, OverviewDiskError, VmPage, VM, VmChangeKind, VmCursorPage, ProvisioningJob,
+};
+
+/// Events scanned for the last launch; a launch records at most three
+const BOOT_EVENT_WINDOW: u32 = 20;
+
+const DEFAULT_LOG_LINES: usize = 200;
+const MAX_LOG_LINES: usize = 5000;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Top-level `VM` fields `list_vms_after` can be asked for; `id` always comes back
+const VM_FIELDS: &[&str] = &[
+    "id", "name", "status", "config", "emulated", "accessMethods", "tags", "groupIds", "warnings",
+];
 
 #[derive(Debug, serde::Deserialize)]
 pub struct UpdateVmRequest {
@@ -23,276 +72,267 @@ pub struct UpdateVmRequest {
     pub name: Option<String>,
     pub cpu: Option<u32>,
     pub memory: Option<u32>,
+    pub nested_virt: Option<bool>,
+    pub restart_policy: Option<String>,
+    pub cpu_affinity: Option<Vec<u32>>,
+    pub priority: Option<i32>,
+    pub disk_size_gb: Option<u32>,
+    /// Replaces the whole performance section; checked against the merged CPU count
+    pub performance: Option<VmPerformance>,
+    pub pointer_device: Option<qemu::PointerDevice>,
+    pub rtc: Option<qemu::RtcBase>,
+    pub rng: Option<bool>,
+    /// Replaces every extra network adapter; the VM must be stopped
+    pub networks: Option<Vec<NetworkConfig>>,
 }
 
-fn validate_vm_config(config: &VMConfig) -> std::result::Result<(), String> {
-    if config.name.trim().is_empty() {
-        return Err("VM name cannot be empty".to_string());
-    }
-    if config.memory_mb < 512 {
-        return Err("Memory must be at least 512 MB".to_string());
-    }
-    if config.cpu_cores == 0 {
-        return Err("CPU cores must be at least 1".to_string());
-    }
-    if config.disk_size_gb == 0 {
-        return Err("Disk size must be at least 1 GB".to_string());
-    }
-    if config.boot_order != "disk-first" && config.boot_order != "cdrom-first" {
-        return Err("Boot order must be disk-first or cdrom-first".to_string());
-    }
-    if config.network_type != "nat" && config.network_type != "bridge" {
-        return Err("Network type must be nat or bridge".to_string());
-    }
-
-    Ok(())
+/// Detect QEMU binary and get system accelerator capabilities
+#[tauri::command]
+pub async fn detect_qemu(state: State<'_, AppState>) -> CommandResult<QemuInfo> {
+    state.rate_limiter.check("detect_qemu", None)?;
+    qemu::detector::detect().await.map_err(CommandError::from)
 }
 
-fn parse_vm_status(status: &str) -> VMStatus {
-    match status.to_ascii_lowercase().as_str() {
-        "running" => VMStatus::Running,
-        "paused" => VMStatus::Paused,
-        "error" => VMStatus::Error,
-        _ => VMStatus::Stopped,
-    }
+/// Installed QEMU system binaries, one per guest architecture they run
+#[tauri::command]
+pub async fn list_available_architectures() -> CommandResult<Vec<ArchInfo>> {
+    let inventory = qemu::detector::detect_all().await?;
+    let mut arches: Vec<ArchInfo> = inventory.into_iter().map(|(arch, info)| arch_info(arch, info)).collect();
+    arches.sort_by(|a, b| a.arch.cmp(&b.arch));
+    Ok(arches)
 }
 
-fn status_to_storage(status: &VMStatus) -> &'static str {
-    match status {
-        VMStatus::Running => "running",
-        VMStatus::Paused => "paused",
-        VMStatus::Error => "error",
-        VMStatus::Stopped => "stopped",
+fn arch_info(arch: String, info: QemuInfo) -> ArchInfo {
+    let emulated = platform::is_emulated(&arch, &info.host_arch);
+    ArchInfo {
+        binary_path: info.path.unwrap_or_default(),
+        version: info.version.unwrap_or_default(),
+        accelerator: info.accelerator.filter(|_| !emulated),
+        arch,
     }
 }
 
-fn map_record_to_vm(record: VMRecord) -> VM {
-    let name = record.name.clone();
-
-    VM {
-        id: record.id,
-        name: name.clone(),
-        status: parse_vm_status(&record.status),
-        config: VMConfig {
-            name,
-            memory_mb: record.memory_mb,
-            cpu_cores: record.cpu_cores,
-            disk_size_gb: record.disk_size_gb,
-            os: record.os,
-            install_media_path: record.install_media_path,
-            boot_order: record.boot_order,
-            network_type: record.network_type,
-        },
+/// Use the QEMU binary at `path` for VMs started from now on, and after restarts
+#[tauri::command]
+pub async fn set_qemu_path(state: State<'_, AppState>, path: String) -> CommandResult<QemuInfo> {
+    if path.trim().is_empty() {
+        return Err(CommandError::validation("path", "qemu.path.empty"));
     }
+    let info = qemu::detector::inspect_binary(PathBuf::from(&path)).await.map_err(|err| {
+        CommandError::validation("path", "qemu.path.invalid")
+            .with_param("path", path.clone())
+            .with_param("detail", err.to_string())
+    })?;
+
+    state.config_store.save_setting(QEMU_BINARY_KEY, &path)?;
+    state.qemu_controller.set_qemu_path(path.clone());
+    tracing::info!(path = %path, version = ?info.version, "QEMU binary changed");
+    Ok(info)
 }
 
-#[cfg(target_os = "macos")]
-fn default_accelerator() -> Accelerator {
-    Accelerator::Hvf
-}
-
-#[cfg(target_os = "linux")]
-fn default_accelerator() -> Accelerator {
-    Accelerator::Kvm
-}
-
-#[cfg(target_os = "windows")]
-fn default_accelerator() -> Accelerator {
-    Accelerator::Whpx
+/// Create a new VM with the given configuration
+#[tauri::command]
+pub async fn create_vm(
+    state: State<'_, AppState>,
+    config: VMConfig,
+    passphrase: Option<String>,
+) -> CommandResult<VM> {
+    service::create_vm(&state, config, passphrase).await
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
-fn default_accelerator() -> Accelerator {
-    Accelerator::Tcg
+/// Built-ins are read-only; user profiles can be replaced or deleted
+fn ensure_profile_editable(profile: &ProfileRecord) -> CommandResult<()> {
+    if profile.builtin {
+        return Err(CommandError::new(ErrorCode::Conflict, "profile.builtin").with_param("name", profile.name.clone()));
+    }
+    Ok(())
 }
 
-fn disk_path(storage_dir: &PathBuf, vm_id: &str) -> String {
-    storage_dir
-        .join(format!("{}.qcow2", vm_id))
-        .display()
-        .to_string()
+fn fetch_profile_or_err(config_store: &ConfigStore, id: &str) -> CommandResult<ProfileRecord> {
+    config_store
+        .get_profile(id)?
+        .ok_or_else(|| Error::NotFound(format!("Profile {}", id)).into())
 }
 
-fn resolve_spice_port(vm_id: &str) -> u16 {
-    let mut hash: u16 = 0;
-    for byte in vm_id.as_bytes() {
-        hash = (hash.wrapping_mul(31).wrapping_add(*byte as u16)) % 1000;
-    }
-    5900 + hash
+/// List creation-wizard profiles, built-ins first
+#[tauri::command]
+pub async fn list_profiles(state: State<'_, AppState>) -> CommandResult<Vec<ProfileRecord>> {
+    state.config_store.list_profiles().map_err(CommandError::from)
 }
 
-fn build_start_args(vm: &VMRecord, disk: &str, qmp_socket: &str) -> std::result::Result<Vec<String>, String> {
-    let mut display_options = HashMap::new();
-    display_options.insert("addr".to_string(), "127.0.0.1".to_string());
-    display_options.insert("disable-ticketing".to_string(), "on".to_string());
-
-    let command = QemuCommand::new()
-        .machine(MachineType::Q35)
-        .accel(default_accelerator())
-        .cpu(vm.cpu_cores)
-        .map_err(|e| format!("Invalid CPU config: {}", e))?
-        .memory(vm.memory_mb)
-        .map_err(|e| format!("Invalid memory config: {}", e))?
-        .drive(DriveConfig {
-            id: "disk0".to_string(),
-            file: disk.to_string(),
-            format: "qcow2".to_string(),
-            interface: "virtio".to_string(),
-        })
-        .netdev(NetdevConfig {
-            id: "net0".to_string(),
-            kind: "user".to_string(),
-            options: HashMap::new(),
-        })
-        .display(DisplayConfig {
-            kind: "spice".to_string(),
-            port: Some(resolve_spice_port(&vm.id)),
-            options: display_options,
-        })
-        .usb_tablet();
-
-    let mut args = command.build();
-    if !args.is_empty() {
-        args.remove(0);
-    }
-
-    if let Some(install_media_path) = &vm.install_media_path {
-        args.push("-drive".to_string());
-        args.push(format!(
-            "file={},media=cdrom,if=ide,readonly=on",
-            install_media_path
-        ));
+/// Create or replace a user profile; an empty ID creates a new one
+#[tauri::command]
+pub async fn save_profile(state: State<'_, AppState>, profile: ProfileRecord) -> CommandResult<ProfileRecord> {
+    let name = profile.name.trim();
+    if name.is_empty() {
+        return Err(CommandError::validation("name", "profile.name.empty"));
     }
 
-    args.push("-boot".to_string());
-    if vm.boot_order == "cdrom-first" {
-        args.push("order=d,menu=on".to_string());
-    } else {
-        args.push("order=c,menu=on".to_string());
+    let id = match profile.id.trim() {
+        "" => Uuid::new_v4().to_string(),
+        id => id.to_string(),
+    };
+    if let Some(existing) = state.config_store.get_profile(&id)? {
+        ensure_profile_editable(&existing)?;
     }
 
-    args.push("-qmp".to_string());
-    args.push(format!("unix:{},server=on,wait=off", qmp_socket));
-    args.push("-name".to_string());
-    args.push(vm.name.clone());
-
-    Ok(args)
-}
-
-fn fetch_vm_or_err(config_store: &ConfigStore, id: &str) -> std::result::Result<VMRecord, String> {
-    config_store
-        .get_vm(id)
-        .map_err(|e| e.to_string())?
-        .ok_or_else(|| format!("VM {} not found", id))
-}
-
-fn update_vm_status(config_store: &ConfigStore, vm_id: &str, status: VMStatus) -> std::result::Result<(), String> {
-    let mut record = fetch_vm_or_err(config_store, vm_id)?;
-    record.status = status_to_storage(&status).to_string();
-    config_store.update_vm(&record).map_err(|e| e.to_string())
+    // The VM name comes from the wizard, never the profile
+    let mut config = profile.config;
+    config.remove("name");
+    profiles::merge_config(name, &config, &serde_json::Map::new()).map_err(|err| {
+        CommandError::validation("config", "profile.config.invalid").with_param("detail", err.to_string())
+    })?;
+
+    let record = ProfileRecord {
+        id,
+        name: name.to_string(),
+        builtin: false,
+        config,
+    };
+    state.config_store.save_profile(&record)?;
+    Ok(record)
 }
 
-fn build_display_session(vm_id: &str, status: &str, reconnect_attempts: u32, last_error: Option<String>) -> DisplaySession {
-    let port = resolve_spice_port(vm_id);
-    DisplaySession {
-        vm_id: vm_id.to_string(),
-        protocol: "spice".to_string(),
-        host: "127.0.0.1".to_string(),
-        port,
-        uri: format!("spice://127.0.0.1:{}", port),
-        status: status.to_string(),
-        reconnect_attempts,
-        last_error,
-        connected_at: Some(chrono::Utc::now().to_rfc3339()),
+/// Delete a user profile; built-in profiles are refused
+#[tauri::command]
+pub async fn delete_profile(state: State<'_, AppState>, profile_id: String) -> CommandResult<()> {
+    if profile_id.trim().is_empty() {
+        return Err(CommandError::validation("profile_id", "profile.id.empty"));
     }
+
+    let profile = fetch_profile_or_err(&state.config_store, &profile_id)?;
+    ensure_profile_editable(&profile)?;
+    state.config_store.delete_profile(&profile_id)?;
+    Ok(())
 }
 
-/// Detect QEMU binary and get system accelerator capabilities
+/// Create a VM from a profile, with `overrides` taking precedence over the profile's settings
 #[tauri::command]
-pub async fn detect_qemu() -> std::result::Result<QemuInfo, String> {
-    qemu::detector::detect().await.map_err(|e| e.to_string())
+pub async fn create_vm_from_profile(
+    state: State<'_, AppState>,
+    profile_id: String,
+    name: String,
+    overrides: Option<serde_json::Map<String, serde_json::Value>>,
+    passphrase: Option<String>,
+) -> CommandResult<VM> {
+    let profile = fetch_profile_or_err(&state.config_store, &profile_id)?;
+    let config = profiles::merge_config(&name, &profile.config, &overrides.unwrap_or_default()).map_err(|err| {
+        CommandError::validation("overrides", "profile.config.invalid").with_param("detail", err.to_string())
+    })?;
+    service::create_vm(&state, config, passphrase).await
 }
 
-/// Create a new VM with the given configuration
+/// Import a VirtualBox OVA: convert its disks and register a new VM
 #[tauri::command]
-pub async fn create_vm(state: State<'_, CommandState>, config: VMConfig) -> std::result::Result<VM, String> {
-    validate_vm_config(&config)?;
-
-    let vm_id = Uuid::new_v4().to_string();
-    state
-        .disk_manager
-        .create_disk(&vm_id, config.disk_size_gb)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let record = VMRecord {
-        id: vm_id,
-        name: config.name.clone(),
-        status: "stopped".to_string(),
-        memory_mb: config.memory_mb,
-        cpu_cores: config.cpu_cores,
-        disk_size_gb: config.disk_size_gb,
-        os: config.os.clone(),
-        install_media_path: config.install_media_path.clone(),
-        boot_order: config.boot_order.clone(),
-        network_type: config.network_type.clone(),
-    };
-
-    if let Err(err) = state.config_store.create_vm(&record).map_err(|e| e.to_string()) {
-        let _ = state.disk_manager.delete_disk(&record.id).await;
-        return Err(err);
-    }
-
-    Ok(map_record_to_vm(record))
+pub async fn import_ova(state: State<'_, AppState>, ova_path: String) -> CommandResult<VM> {
+    app::import_ova(&state, &ova_path).await
 }
 
 /// Update VM mutable fields
 #[tauri::command]
 pub async fn update_vm(
-    state: State<'_, CommandState>,
+    state: State<'_, AppState>,
     request: UpdateVmRequest,
-) -> std::result::Result<VM, String> {
+) -> CommandResult<VM> {
     if request.id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
 
     let mut record = fetch_vm_or_err(&state.config_store, &request.id)?;
 
     if let Some(name) = request.name {
         if name.trim().is_empty() {
-            return Err("VM name cannot be empty".to_string());
+            return Err(CommandError::validation("name", "vm.name.empty"));
         }
+        ensure_unique_name(&state.config_store, &name, Some(&record.name))?;
         record.name = name;
     }
-
     if let Some(cpu) = request.cpu {
-        if cpu == 0 {
-            return Err("CPU cores must be at least 1".to_string());
-        }
         record.cpu_cores = cpu;
     }
-
     if let Some(memory) = request.memory {
-        if memory < 512 {
-            return Err("Memory must be at least 512 MB".to_string());
-        }
         record.memory_mb = memory;
     }
+    if let Some(nested_virt) = request.nested_virt {
+        record.nested_virt = nested_virt;
+    }
+    if let Some(restart_policy) = request.restart_policy {
+        record.restart_policy = restart_policy;
+    }
+    if let Some(cpu_affinity) = request.cpu_affinity {
+        record.cpu_affinity = cpu_affinity;
+    }
+    if let Some(priority) = request.priority {
+        record.priority = priority;
+    }
+    if let Some(performance) = request.performance {
+        record.performance = Some(performance);
+    }
+    if let Some(pointer_device) = request.pointer_device {
+        record.pointer_device = Some(pointer_device.as_str().to_string());
+    }
+    if let Some(rtc) = request.rtc {
+        record.rtc = Some(rtc.as_str().to_string());
+    }
+    if let Some(rng) = request.rng {
+        record.rng = Some(rng);
+    }
 
-    state
-        .config_store
-        .update_vm(&record)
-        .map_err(|e| e.to_string())?;
+    let networks = match request.networks {
+        Some(networks) => {
+            if state.qemu_controller.is_running(&record.id) {
+                return Err(CommandError::new(ErrorCode::Conflict, "network.adapter.vmRunning")
+                    .with_details(serde_json::json!({ "field": "networks" })));
+            }
+            let networks: Vec<NetworkConfig> =
+                networks.into_iter().map(|network| with_stable_mac(&record.id, network)).collect();
+            reject_errors(validation::validate_networks(&networks))?;
+            Some(networks)
+        }
+        None => None,
+    };
+
+    let warnings = check_updated_config(&record)?;
+
+    match request.disk_size_gb {
+        Some(new_size_gb) => {
+            ensure_provisioned(&state, &record)?;
+            if state.qemu_controller.is_running(&record.id) {
+                return Err(CommandError::new(ErrorCode::Conflict, "disk.resize.vmRunning")
+                    .with_details(serde_json::json!({ "field": "disk_size_gb" })));
+            }
+            let vm_id = record.id.clone();
+            let disk = vm_disk_path(&state, &vm_id)?;
+            let resize = state.disk_manager.resize_disk_at(&disk, new_size_gb);
+            apply_disk_resize(&state.config_store, &mut record, new_size_gb, resize).await?;
+        }
+        None => state.config_store.update_vm(&record)?,
+    }
+    if let Some(networks) = &networks {
+        state.config_store.replace_networks(&record.id, networks)?;
+    }
+    notify_vm_list_changed(&state, &record.id, VmChangeKind::Updated);
+
+    let mut vm = service::stored_vm(&state.config_store, record)?;
+    vm.warnings.extend(warnings);
+    vm.warnings.extend(affinity_warning(&vm.config.cpu_affinity));
+    Ok(vm)
+}
 
-    Ok(map_record_to_vm(record))
+/// Check an edited VM's merged settings. The disk already exists, and install
+/// media is set elsewhere, so an ISO deleted since then does not block edits.
+fn check_updated_config(record: &VMRecord) -> CommandResult<Vec<VMWarning>> {
+    let mut config = map_record_to_vm(record.clone()).config;
+    config.install_media_path = None;
+    check_vm_config(&config, &host_limits(None))
 }
 
 /// Pick install media file using native dialog
 #[tauri::command]
-pub async fn pick_install_media(id: Option<String>) -> std::result::Result<Option<String>, String> {
+pub async fn pick_install_media(id: Option<String>) -> CommandResult<Option<String>> {
     if let Some(vm_id) = id {
         if vm_id.trim().is_empty() {
-            return Err("VM ID cannot be empty".to_string());
+            return Err(CommandError::validation("id", "vm.id.empty"));
         }
     }
 
@@ -306,338 +346,2512 @@ pub async fn pick_install_media(id: Option<String>) -> std::result::Result<Optio
 /// Set install media path for a VM
 #[tauri::command]
 pub async fn set_install_media(
-    state: State<'_, CommandState>,
+    state: State<'_, AppState>,
     id: String,
     path: String,
-) -> std::result::Result<(), String> {
+) -> CommandResult<()> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
     if path.trim().is_empty() {
-        return Err("Install media path cannot be empty".to_string());
+        return Err(CommandError::validation("path", "vm.installMedia.pathEmpty"));
+    }
+    if !Path::new(&path).is_file() {
+        return Err(CommandError::validation("path", "validation.installMediaPath.notFound").with_param("path", path));
     }
 
     let mut record = fetch_vm_or_err(&state.config_store, &id)?;
     record.install_media_path = Some(path);
-    state.config_store.update_vm(&record).map_err(|e| e.to_string())?;
+    save_vm(&state, &record)?;
     Ok(())
 }
 
 /// Eject install media for a VM
 #[tauri::command]
-pub async fn eject_install_media(state: State<'_, CommandState>, id: String) -> std::result::Result<(), String> {
+pub async fn eject_install_media(state: State<'_, AppState>, id: String) -> CommandResult<()> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
 
     let mut record = fetch_vm_or_err(&state.config_store, &id)?;
     record.install_media_path = None;
-    state.config_store.update_vm(&record).map_err(|e| e.to_string())?;
+    save_vm(&state, &record)?;
     Ok(())
 }
 
 /// Set VM boot order
 #[tauri::command]
 pub async fn set_boot_order(
-    state: State<'_, CommandState>,
+    state: State<'_, AppState>,
     id: String,
     order: String,
-) -> std::result::Result<(), String> {
+) -> CommandResult<()> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
     if order != "disk-first" && order != "cdrom-first" {
-        return Err("Boot order must be disk-first or cdrom-first".to_string());
+        return Err(CommandError::validation("order", "vm.bootOrder.invalid"));
     }
 
     let mut record = fetch_vm_or_err(&state.config_store, &id)?;
     record.boot_order = order;
-    state.config_store.update_vm(&record).map_err(|e| e.to_string())?;
+    save_vm(&state, &record)?;
     Ok(())
 }
 
-/// Start a VM by ID
+/// Boot a VM from `device` ("disk" or "cdrom") on its next start only
 #[tauri::command]
-pub async fn start_vm(state: State<'_, CommandState>, id: String) -> std::result::Result<(), String> {
-    if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
-    }
-
-    let vm_record = fetch_vm_or_err(&state.config_store, &id)?;
-    let qmp_socket = format!("/tmp/openutm-qmp-{}.sock", id);
-    let args = build_start_args(&vm_record, &disk_path(&state.storage_dir, &id), &qmp_socket)?;
-
-    let mut controller = state.qemu_controller.lock().await;
-    controller
-        .start_vm(&id, args, Some(qmp_socket))
-        .await
-        .map_err(|e| e.to_string())?;
+pub async fn boot_once(state: State<'_, AppState>, id: String, device: String) -> CommandResult<()> {
+    set_boot_once(&state, &id, &device)
+}
 
-    update_vm_status(&state.config_store, &id, VMStatus::Running)?;
-    let mut sessions = state.display_sessions.lock().await;
-    if let Some(existing) = sessions.get_mut(&id) {
-        existing.status = "connected".to_string();
-        existing.last_error = None;
-    }
-    Ok(())
+/// Start a VM by ID; a second click within a couple of seconds reports
+/// `alreadyStarting` instead of starting it twice
+#[tauri::command]
+pub async fn start_vm(
+    state: State<'_, AppState>,
+    id: String,
+    passphrase: Option<String>,
+) -> CommandResult<service::StartOutcome> {
+    service::start_vm(&state, &id, passphrase.as_deref()).await
 }
 
 /// Stop a running VM
 #[tauri::command]
-pub async fn stop_vm(state: State<'_, CommandState>, id: String) -> std::result::Result<(), String> {
-    if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
-    }
+pub async fn stop_vm(state: State<'_, AppState>, id: String) -> CommandResult<()> {
+    service::stop_vm(&state, &id).await
+}
+
+/// Start several VMs a few at a time; each VM's status events go out as it
+/// finishes. Returns every VM's outcome, failures included.
+#[tauri::command]
+pub async fn batch_start_vms(
+    state: State<'_, AppState>,
+    ids: Vec<String>,
+) -> CommandResult<BTreeMap<String, service::BatchOutcome>> {
+    Ok(service::batch_start_vms(&state, ids).await)
+}
 
-    let mut controller = state.qemu_controller.lock().await;
-    controller.stop_vm(&id).await.map_err(|e| e.to_string())?;
+/// Stop several VMs a few at a time, like `batch_start_vms`
+#[tauri::command]
+pub async fn batch_stop_vms(
+    state: State<'_, AppState>,
+    ids: Vec<String>,
+) -> CommandResult<BTreeMap<String, service::BatchOutcome>> {
+    Ok(service::batch_stop_vms(&state, ids).await)
+}
 
-    update_vm_status(&state.config_store, &id, VMStatus::Stopped)?;
-    let mut sessions = state.display_sessions.lock().await;
-    if let Some(existing) = sessions.get_mut(&id) {
-        existing.status = "disconnected".to_string();
-        existing.last_error = Some("VM stopped".to_string());
-    }
-    Ok(())
+/// Shut down every running VM concurrently, e.g. before quitting or suspending;
+/// guests get `service::SHUTDOWN_TIMEOUT` to power off before QEMU is killed.
+/// Returns the VMs that failed to stop.
+#[tauri::command]
+pub async fn stop_all_vms(state: State<'_, AppState>) -> CommandResult<Vec<String>> {
+    Ok(service::stop_all_vms(&state).await)
 }
 
 /// Pause a running VM
 #[tauri::command]
-pub async fn pause_vm(state: State<'_, CommandState>, id: String) -> std::result::Result<(), String> {
+pub async fn pause_vm(state: State<'_, AppState>, id: String) -> CommandResult<()> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
 
-    let controller = state.qemu_controller.lock().await;
-    controller.pause_vm(&id).await.map_err(|e| e.to_string())?;
+    state.qemu_controller.pause_vm(&id).await?;
 
-    update_vm_status(&state.config_store, &id, VMStatus::Paused)?;
+    update_vm_status(&state, &id, VMStatus::Paused)?;
     Ok(())
 }
 
 /// Resume a paused VM
 #[tauri::command]
-pub async fn resume_vm(state: State<'_, CommandState>, id: String) -> std::result::Result<(), String> {
+pub async fn resume_vm(state: State<'_, AppState>, id: String) -> CommandResult<()> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
 
-    let controller = state.qemu_controller.lock().await;
-    controller.resume_vm(&id).await.map_err(|e| e.to_string())?;
+    state.qemu_controller.resume_vm(&id).await?;
 
-    update_vm_status(&state.config_store, &id, VMStatus::Running)?;
+    update_vm_status(&state, &id, VMStatus::Running)?;
     Ok(())
 }
 
 /// List all VMs
 #[tauri::command]
-pub async fn list_vms(state: State<'_, CommandState>) -> std::result::Result<Vec<VM>, String> {
-    let records = state.config_store.list_vms().map_err(|e| e.to_string())?;
-    Ok(records.into_iter().map(map_record_to_vm).collect())
+pub async fn list_vms(state: State<'_, AppState>) -> CommandResult<Vec<VM>> {
+    service::list_vms(&state)
+}
+
+fn parse_page_request(limit: Option<u32>, sort_by: Option<&str>) -> CommandResult<(u32, VmSort)> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(CommandError::validation("limit", "vm.list.limitOutOfRange").with_param("max", MAX_PAGE_SIZE));
+    }
+    let sort = match sort_by {
+        Some(value) => VmSort::parse(value)
+            .ok_or_else(|| CommandError::validation("sort_by", "vm.list.sortInvalid"))?,
+        None => VmSort::default(),
+    };
+    Ok((limit, sort))
+}
+
+/// List one page of VMs with the total count, newest first unless `sort_by` says otherwise
+#[tauri::command]
+pub async fn list_vms_paged(
+    state: State<'_, AppState>,
+    offset: Option<u32>,
+    limit: Option<u32>,
+    sort_by: Option<String>,
+) -> CommandResult<VmPage> {
+    let offset = offset.unwrap_or(0);
+    let (limit, sort) = parse_page_request(limit, sort_by.as_deref())?;
+    let (records, total) = state.config_store.list_vms_paged(offset, Some(limit), sort)?;
+    Ok(VmPage {
+        vms: service::with_group_ids(&state.config_store, state.config_store.group_memberships()?, records)?,
+        total,
+        offset,
+        limit,
+    })
+}
+
+fn select_vm_fields(vm: VM, fields: Option<&[String]>) -> CommandResult<serde_json::Value> {
+    let mut value = serde_json::to_value(vm).map_err(Error::from)?;
+    if let (Some(fields), Some(object)) = (fields, value.as_object_mut()) {
+        object.retain(|key, _| key == "id" || fields.iter().any(|field| field == key));
+    }
+    Ok(value)
+}
+
+/// List VMs a page at a time from an opaque cursor, which unlike an offset
+/// neither repeats nor skips rows when VMs are added between pages. `fields`
+/// trims each VM to the named top-level fields.
+#[tauri::command]
+pub async fn list_vms_after(
+    state: State<'_, AppState>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    sort_by: Option<String>,
+    fields: Option<Vec<String>>,
+) -> CommandResult<VmCursorPage> {
+    let (limit, sort) = parse_page_request(limit, sort_by.as_deref())?;
+    let cursor = match cursor.as_deref() {
+        Some(token) => Some(
+            VmCursor::decode(token, sort).ok_or_else(|| CommandError::validation("cursor", "vm.list.cursorInvalid"))?,
+        ),
+        None => None,
+    };
+    if let Some(unknown) = fields.iter().flatten().find(|field| !VM_FIELDS.contains(&field.as_str())) {
+        return Err(CommandError::validation("fields", "vm.list.fieldUnknown").with_param("field", unknown.as_str()));
+    }
+
+    let (records, next) = state.config_store.list_vms_after(cursor.as_ref(), limit, sort)?;
+    let vms = service::with_group_ids(&state.config_store, state.config_store.group_memberships()?, records)?
+        .into_iter()
+        .map(|vm| select_vm_fields(vm, fields.as_deref()))
+        .collect::<CommandResult<Vec<_>>>()?;
+    Ok(VmCursorPage { vms, next_cursor: next.map(|cursor| cursor.encode()) })
+}
+
+/// Id, name, status and last update of every VM, enough to draw the sidebar
+#[tauri::command]
+pub async fn list_vm_summaries(
+    state: State<'_, AppState>,
+    sort_by: Option<String>,
+) -> CommandResult<Vec<VmSummary>> {
+    let (_, sort) = parse_page_request(None, sort_by.as_deref())?;
+    Ok(state.config_store.list_vm_summaries(sort)?)
+}
+
+/// Disks of new VMs still queued or being made, with progress
+#[tauri::command]
+pub async fn get_provisioning_jobs(state: State<'_, AppState>) -> CommandResult<Vec<ProvisioningJob>> {
+    Ok(state.provisioner.jobs())
+}
+
+/// Pid, QMP socket and start time of each VM with a live QEMU process
+#[tauri::command]
+pub async fn list_running_vm_details(state: State<'_, AppState>) -> CommandResult<Vec<VmRunningInfo>> {
+    Ok(state.qemu_controller.get_running_vm_details())
 }
 
 /// Get VM details by ID
 #[tauri::command]
-pub async fn get_vm(state: State<'_, CommandState>, id: String) -> std::result::Result<Option<VM>, String> {
+pub async fn get_vm(state: State<'_, AppState>, id: String) -> CommandResult<Option<VM>> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
 
-    let record = state.config_store.get_vm(&id).map_err(|e| e.to_string())?;
-    Ok(record.map(map_record_to_vm))
+    let record = state.config_store.get_vm(&id)?;
+    record.map(|record| service::stored_vm(&state.config_store, record)).transpose()
 }
 
 /// Delete a VM
 #[tauri::command]
-pub async fn delete_vm(state: State<'_, CommandState>, id: String) -> std::result::Result<(), String> {
+pub async fn delete_vm(state: State<'_, AppState>, id: String) -> CommandResult<()> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
 
-    let maybe_vm = state.config_store.get_vm(&id).map_err(|e| e.to_string())?;
+    let maybe_vm = state.config_store.get_vm(&id)?;
     if maybe_vm.is_none() {
         return Ok(());
     }
 
-    {
-        let mut controller = state.qemu_controller.lock().await;
-        let _ = controller.stop_vm(&id).await;
-    }
+    let _ = state.qemu_controller.stop_vm(&id).await;
+    remove_secret_file(&state, &id);
+    remove_ephemeral_overlay(&state, &id);
+    force_stop_recording(&state, &id).await;
 
-    state.disk_manager.delete_disk(&id).await.map_err(|e| e.to_string())?;
-    state.config_store.delete_vm(&id).map_err(|e| e.to_string())?;
+    // Images under external snapshot overlays go too; the newest overlay is the disk itself
+    for snapshot in state.config_store.external_snapshots(&id)? {
+        state.disk_manager.delete_disk_at(&snapshot.backing_path).await?;
+    }
+    state.disk_manager.delete_disk_at(&vm_disk_path(&state, &id)?).await?;
+    state.config_store.delete_vm(&id)?;
+    notify_vm_list_changed(&state, &id, VmChangeKind::Deleted);
+    if let Err(err) = spice_tls::remove_vm_certificates(&state.paths.spice_tls_dir(), &id) {
+        tracing::warn!(vm_id = %id, error = %err, "failed to remove SPICE certificates");
+    }
     state.display_sessions.lock().await.remove(&id);
+    state.spice_passwords.lock().await.remove(&id);
+    state.display_proxies.lock().await.remove(&id);
 
     Ok(())
 }
 
-/// Get platform acceleration capabilities
+/// Move a stopped VM's disk image into `new_storage_dir`, e.g. onto a volume with more space
 #[tauri::command]
-pub async fn get_platform_info() -> std::result::Result<String, String> {
-    platform::get_platform_info().map_err(|e| e.to_string())
+pub async fn relocate_vm_disk(
+    state: State<'_, AppState>,
+    vm_id: String,
+    new_storage_dir: String,
+) -> CommandResult<()> {
+    app::relocate_vm_disk(&state, &vm_id, &new_storage_dir).await
 }
 
-/// Open display session for a running VM
+/// Move every file in the storage directory into `new_dir` and create disks
+/// there from now on; returns the new directory. All VMs must be stopped.
+/// Copies are verified and the new paths recorded before any original is
+/// deleted, so a failure leaves the old directory in use. Progress goes out
+/// as `UiEvent::StorageMigration` after each file.
 #[tauri::command]
-pub async fn open_display(state: State<'_, CommandState>, id: String) -> std::result::Result<DisplaySession, String> {
+pub async fn migrate_storage(state: State<'_, AppState>, new_dir: String) -> CommandResult<String> {
+    app::relocate_storage(&state, &new_dir).await
+}
+
+/// Disk image of a stopped VM whose contents can be checksummed
+fn checksum_target(state: &AppState, id: &str) -> CommandResult<String> {
     if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+        return Err(CommandError::validation("id", "vm.id.empty"));
     }
 
-    let _ = fetch_vm_or_err(&state.config_store, &id)?;
-    let controller = state.qemu_controller.lock().await;
-    if !controller.is_running(&id) {
-        return Err(format!("VM {} not running", id));
+    let record = fetch_vm_or_err(&state.config_store, id)?;
+    if record.raw_device_path.is_some() {
+        return Err(CommandError::validation("id", "disk.checksum.rawDevice"));
     }
-    drop(controller);
+    ensure_provisioned(state, &record)?;
+    // A running guest keeps writing, so the digest would be stale immediately
+    if state.qemu_controller.is_running(id) {
+        return Err(CommandError::new(ErrorCode::Conflict, "disk.checksum.vmRunning")
+            .with_details(serde_json::json!({ "field": "id" })));
+    }
+    vm_disk_path(state, id)
+}
 
-    let mut sessions = state.display_sessions.lock().await;
-    if let Some(existing) = sessions.get_mut(&id) {
-        if existing.status == "disconnected" || existing.status == "error" {
-            existing.status = "connected".to_string();
-            existing.reconnect_attempts += 1;
-            existing.last_error = None;
-            existing.connected_at = Some(chrono::Utc::now().to_rfc3339());
+/// Hash the VM's disk and save the digest to `<disk>.sha256` for `verify_vm_disk`
+#[tauri::command]
+pub async fn checksum_vm_disk(state: State<'_, AppState>, id: String) -> CommandResult<String> {
+    let disk_path = checksum_target(&state, &id)?;
+    // Hashing reads the whole image, so keep it off the async runtime
+    let disk_manager = state.disk_manager.clone();
+    let checksum = tokio::task::spawn_blocking(move || -> crate::Result<String> {
+        let checksum = disk_manager.compute_checksum_at(&disk_path)?;
+        disk_manager.save_checksum_at(&disk_path, &checksum)?;
+        Ok(checksum)
+    })
+    .await
+    .map_err(|e| CommandError::new(ErrorCode::Internal, "error.internal").with_param("detail", e.to_string()))??;
+    tracing::info!(vm_id = %id, "disk checksum saved");
+    Ok(checksum)
+}
+
+/// Whether the VM's disk still matches its saved checksum; a mismatch is
+/// `false`, not an error
+#[tauri::command]
+pub async fn verify_vm_disk(state: State<'_, AppState>, id: String) -> CommandResult<bool> {
+    let disk_path = checksum_target(&state, &id)?;
+    if !storage::checksum_path(&disk_path).exists() {
+        return Err(CommandError::validation("id", "disk.checksum.missing"));
+    }
+
+    let disk_manager = state.disk_manager.clone();
+    let intact = tokio::task::spawn_blocking(move || disk_manager.verify_disk_integrity_at(&disk_path))
+        .await
+        .map_err(|e| CommandError::new(ErrorCode::Internal, "error.internal").with_param("detail", e.to_string()))??;
+    if !intact {
+        tracing::warn!(vm_id = %id, "disk does not match its saved checksum");
+    }
+    Ok(intact)
+}
+
+/// How long a live snapshot may take to write out or read back the guest's RAM
+const SNAPSHOT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// Image of a VM that can hold internal snapshots, plus the VM's QMP socket while it runs
+fn snapshot_target(state: &AppState, vm_id: &str) -> CommandResult<(VMRecord, String, Option<String>)> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let record = fetch_vm_or_err(&state.config_store, vm_id)?;
+    if record.raw_device_path.is_some() {
+        return Err(CommandError::validation("vm_id", "snapshot.rawDevice"));
+    }
+    ensure_provisioned(state, &record)?;
+    reject_ephemeral(&record)?;
+    let disk_path = vm_disk_path(state, vm_id)?;
+    Ok((record, disk_path, state.qemu_controller.qmp_socket(vm_id)))
+}
+
+/// Snapshots of the VM's disk. A running VM holds its image locked, so they
+/// come from QEMU instead of `qemu-img`.
+async fn vm_snapshots(
+    state: &AppState,
+    disk_path: &str,
+    qmp_socket: Option<&str>,
+) -> CommandResult<Vec<DiskSnapshot>> {
+    match qmp_socket {
+        Some(socket) => {
+            let blocks = QmpClient::new(socket.to_string()).execute("query-block", None).await?;
+            let image = qemu::qmp::find_drive(&blocks, "disk0").map(|block| &block["inserted"]["image"]);
+            Ok(image.map(storage::snapshots_from_info).unwrap_or_default())
         }
-        return Ok(existing.clone());
+        None => Ok(state.disk_manager.list_snapshots_at(disk_path).await?),
     }
+}
 
-    let session = build_display_session(&id, "connected", 0, None);
-    sessions.insert(id, session.clone());
-    Ok(session)
+/// Internal snapshots of the VM's disk, oldest first
+#[tauri::command]
+pub async fn list_snapshots(state: State<'_, AppState>, vm_id: String) -> CommandResult<Vec<DiskSnapshot>> {
+    let (_, disk_path, qmp_socket) = snapshot_target(&state, &vm_id)?;
+    vm_snapshots(&state, &disk_path, qmp_socket.as_deref()).await
 }
 
-/// Get display session by VM ID
+/// Snapshot the VM's disk. A running VM's RAM is saved too, so rolling back
+/// resumes it exactly; a stopped VM gets a disk-only snapshot. Returns whether
+/// the snapshot includes RAM.
 #[tauri::command]
-pub async fn get_display(state: State<'_, CommandState>, id: String) -> std::result::Result<Option<DisplaySession>, String> {
-    if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+pub async fn create_live_snapshot(state: State<'_, AppState>, vm_id: String, name: String) -> CommandResult<bool> {
+    let (record, disk_path, qmp_socket) = snapshot_target(&state, &vm_id)?;
+    let name = name.trim();
+    if !storage::is_valid_snapshot_name(name) {
+        return Err(CommandError::validation("name", "snapshot.name.invalid"));
+    }
+    let existing = vm_snapshots(&state, &disk_path, qmp_socket.as_deref()).await?;
+    if existing.iter().any(|snapshot| snapshot.name == name) {
+        return Err(CommandError::new(ErrorCode::Conflict, "snapshot.name.exists")
+            .with_param("name", name)
+            .with_details(serde_json::json!({ "field": "name" })));
     }
 
-    let is_running = {
-        let controller = state.qemu_controller.lock().await;
-        controller.is_running(&id)
+    let includes_ram = match qmp_socket {
+        Some(socket) => {
+            QmpClient::new(socket)
+                .run_snapshot_job("snapshot-save", name, "disk0", SNAPSHOT_TIMEOUT)
+                .await?;
+            true
+        }
+        // qemu-img cannot open a LUKS image without its passphrase
+        None if record.encryption_key_ref.is_some() => {
+            return Err(CommandError::validation("vm_id", "snapshot.encrypted"));
+        }
+        None => {
+            state.disk_manager.create_snapshot_at(&disk_path, name).await?;
+            false
+        }
+    };
+    tracing::info!(vm_id = %vm_id, snapshot = %name, includes_ram, "snapshot created");
+    Ok(includes_ram)
+}
+
+/// Roll the VM back to snapshot `name`. A running VM resumes the snapshot's
+/// saved RAM; a stopped VM's disk is rolled back and it boots from that on
+/// the next start. Returns whether RAM was restored.
+#[tauri::command]
+pub async fn restore_snapshot(state: State<'_, AppState>, vm_id: String, name: String) -> CommandResult<bool> {
+    let (record, disk_path, qmp_socket) = snapshot_target(&state, &vm_id)?;
+    let snapshots = vm_snapshots(&state, &disk_path, qmp_socket.as_deref()).await?;
+    let Some(snapshot) = snapshots.into_iter().find(|snapshot| snapshot.name == name) else {
+        return Err(CommandError::new(ErrorCode::NotFound, "snapshot.notFound").with_param("name", name));
     };
 
-    let mut sessions = state.display_sessions.lock().await;
-    if let Some(existing) = sessions.get_mut(&id) {
-        if !is_running && existing.status != "disconnected" {
-            existing.status = "disconnected".to_string();
-            existing.last_error = Some("VM not running".to_string());
+    match qmp_socket {
+        Some(_) if !snapshot.includes_ram => {
+            Err(CommandError::validation("name", "snapshot.restore.needsRam").with_param("name", name))
+        }
+        Some(socket) => {
+            QmpClient::new(socket)
+                .run_snapshot_job("snapshot-load", &name, "disk0", SNAPSHOT_TIMEOUT)
+                .await?;
+            tracing::info!(vm_id = %vm_id, snapshot = %name, "VM restored from live snapshot");
+            Ok(true)
+        }
+        None if record.encryption_key_ref.is_some() => Err(CommandError::validation("vm_id", "snapshot.encrypted")),
+        None => {
+            state.disk_manager.apply_snapshot_at(&disk_path, &name).await?;
+            Ok(false)
         }
-        return Ok(Some(existing.clone()));
     }
+}
 
-    Ok(None)
+/// External snapshots of the VM, oldest first
+#[tauri::command]
+pub async fn list_external_snapshots(
+    state: State<'_, AppState>,
+    vm_id: String,
+) -> CommandResult<Vec<ExternalSnapshotRecord>> {
+    snapshot_target(&state, &vm_id)?;
+    Ok(state.config_store.external_snapshots(&vm_id)?)
 }
 
-/// Close display session
+/// Freeze the VM's disk as snapshot `name` and continue on a new overlay,
+/// `<vm>-<name>.qcow2`. Unlike an internal snapshot, the frozen image does not
+/// grow with later writes. Works on a running or stopped VM.
 #[tauri::command]
-pub async fn close_display(state: State<'_, CommandState>, id: String) -> std::result::Result<(), String> {
-    if id.trim().is_empty() {
-        return Err("VM ID cannot be empty".to_string());
+pub async fn create_external_snapshot(
+    state: State<'_, AppState>,
+    vm_id: String,
+    name: String,
+) -> CommandResult<ExternalSnapshotRecord> {
+    let (record, disk_path, qmp_socket) = snapshot_target(&state, &vm_id)?;
+    // The overlay would hold the guest's new writes unencrypted
+    if record.encryption_key_ref.is_some() {
+        return Err(CommandError::validation("vm_id", "snapshot.external.encrypted"));
+    }
+    let name = name.trim();
+    if !storage::is_valid_snapshot_name(name) {
+        return Err(CommandError::validation("name", "snapshot.name.invalid"));
+    }
+    let overlay = storage::external_snapshot_path(&disk_path, &vm_id, name);
+    let taken = state.config_store.external_snapshots(&vm_id)?.iter().any(|snapshot| snapshot.name == name);
+    if taken || Path::new(&overlay).exists() {
+        return Err(CommandError::new(ErrorCode::Conflict, "snapshot.name.exists")
+            .with_param("name", name)
+            .with_details(serde_json::json!({ "field": "name" })));
     }
 
-    let mut sessions = state.display_sessions.lock().await;
-    if let Some(existing) = sessions.get_mut(&id) {
-        existing.status = "disconnected".to_string();
-        existing.last_error = Some("Display session closed".to_string());
+    match &qmp_socket {
+        Some(socket) => QmpClient::new(socket.clone()).snapshot_sync("disk0", &overlay).await?,
+        None => state.disk_manager.create_overlay_at(&disk_path, &overlay).await?,
     }
-    Ok(())
+    if let Err(err) = state.config_store.add_external_snapshot(&vm_id, name, &overlay, &disk_path) {
+        // A running VM is already writing to the overlay; merge it back before dropping it
+        let rolled_back = match &qmp_socket {
+            Some(socket) => QmpClient::new(socket.clone())
+                .commit_active_layer("disk0", &disk_path, SNAPSHOT_TIMEOUT)
+                .await
+                .map_err(|commit_err| {
+                    tracing::error!(
+                        vm_id = %vm_id,
+                        overlay = %overlay,
+                        error = %commit_err,
+                        "could not merge back unrecorded overlay"
+                    );
+                })
+                .is_ok(),
+            None => true,
+        };
+        if rolled_back {
+            let _ = std::fs::remove_file(&overlay);
+        }
+        return Err(err.into());
+    }
+    tracing::info!(vm_id = %vm_id, snapshot = %name, overlay = %overlay, "external snapshot created");
+
+    let snapshots = state.config_store.external_snapshots(&vm_id)?;
+    snapshots
+        .into_iter()
+        .find(|snapshot| snapshot.name == name)
+        .ok_or_else(|| CommandError::new(ErrorCode::NotFound, "snapshot.notFound").with_param("name", name))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Merge the newest external snapshot's overlay down into the image beneath,
+/// which becomes the VM's disk again, then delete the overlay. Returns the
+/// name of the snapshot that was merged.
+#[tauri::command]
+pub async fn commit_external_snapshot(state: State<'_, AppState>, vm_id: String) -> CommandResult<String> {
+    let (_, _, qmp_socket) = snapshot_target(&state, &vm_id)?;
+    let Some(top) = state.config_store.external_snapshots(&vm_id)?.pop() else {
+        return Err(CommandError::new(ErrorCode::NotFound, "snapshot.external.none"));
+    };
 
-    #[test]
-    fn test_validate_vm_config_rejects_invalid() {
-        let config = VMConfig {
-            name: String::new(),
-            memory_mb: 256,
-            cpu_cores: 0,
-            disk_size_gb: 0,
-            os: "linux".to_string(),
-            install_media_path: None,
-            boot_order: "disk-first".to_string(),
-            network_type: "nat".to_string(),
-        };
+    match qmp_socket {
+        Some(socket) => {
+            QmpClient::new(socket)
+                .commit_active_layer("disk0", &top.backing_path, SNAPSHOT_TIMEOUT)
+                .await?
+        }
+        None => state.disk_manager.commit_overlay_at(&top.path, &top.backing_path).await?,
+    }
+    state.config_store.remove_external_snapshot(&vm_id, &top.name)?;
+    state.disk_manager.delete_disk_at(&top.path).await?;
+    tracing::info!(vm_id = %vm_id, snapshot = %top.name, "external snapshot committed");
+    Ok(top.name)
+}
 
-        let result = validate_vm_config(&config);
-        assert!(result.is_err());
+/// The VM's network adapters; empty means the single NIC from `network_type`
+#[tauri::command]
+pub async fn list_network_adapters(state: State<'_, AppState>, vm_id: String) -> CommandResult<Vec<NetworkConfig>> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
     }
 
-    #[test]
-    fn test_parse_vm_status_defaults_to_stopped() {
-        assert_eq!(parse_vm_status("unknown"), VMStatus::Stopped);
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    Ok(state.config_store.list_networks(&vm_id)?)
+}
+
+/// Add a network adapter to a stopped VM; it is used from the next start
+#[tauri::command]
+pub async fn add_network_adapter(
+    state: State<'_, AppState>,
+    vm_id: String,
+    network: NetworkConfig,
+) -> CommandResult<NetworkConfig> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
     }
 
-    #[test]
-    fn test_map_record_to_vm_maps_core_fields() {
-        let record = VMRecord {
-            id: "vm-1".to_string(),
-            name: "Ubuntu VM".to_string(),
-            status: "paused".to_string(),
-            memory_mb: 4096,
-            cpu_cores: 4,
-            disk_size_gb: 64,
-            os: "linux".to_string(),
-            install_media_path: None,
-            boot_order: "disk-first".to_string(),
-            network_type: "nat".to_string(),
-        };
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    if state.qemu_controller.is_running(&vm_id) {
+        return Err(CommandError::new(ErrorCode::Conflict, "network.adapter.vmRunning")
+            .with_details(serde_json::json!({ "field": "vm_id" })));
+    }
+    let network = with_stable_mac(&vm_id, network);
+    let mut networks = state.config_store.list_networks(&vm_id)?;
+    networks.push(network.clone());
+    reject_errors(validation::validate_networks(&networks))?;
+
+    state.config_store.add_network(&vm_id, &network)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    tracing::info!(vm_id = %vm_id, adapter = %network.id, kind = %network.kind, "network adapter added");
+    Ok(network)
+}
 
-        let vm = map_record_to_vm(record);
-        assert_eq!(vm.id, "vm-1");
-        assert_eq!(vm.name, "Ubuntu VM");
-        assert_eq!(vm.status, VMStatus::Paused);
-        assert_eq!(vm.config.memory_mb, 4096);
-        assert_eq!(vm.config.cpu_cores, 4);
+/// Remove network adapter `network_id` from a stopped VM
+#[tauri::command]
+pub async fn remove_network_adapter(
+    state: State<'_, AppState>,
+    vm_id: String,
+    network_id: String,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
     }
 
-    #[test]
-    fn test_build_start_args_includes_qmp_and_name() {
-        let record = VMRecord {
-            id: "vm-1".to_string(),
-            name: "Fedora VM".to_string(),
-            status: "stopped".to_string(),
-            memory_mb: 2048,
-            cpu_cores: 2,
-            disk_size_gb: 20,
-            os: "linux".to_string(),
-            install_media_path: Some("/isos/fedora.iso".to_string()),
-            boot_order: "cdrom-first".to_string(),
-            network_type: "nat".to_string(),
-        };
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    if state.qemu_controller.is_running(&vm_id) {
+        return Err(CommandError::new(ErrorCode::Conflict, "network.adapter.vmRunning")
+            .with_details(serde_json::json!({ "field": "vm_id" })));
+    }
+    match state.config_store.remove_network(&vm_id, &network_id) {
+        Err(Error::NotFound(_)) => {
+            return Err(CommandError::new(ErrorCode::NotFound, "network.adapter.notFound").with_param("id", network_id))
+        }
+        result => result?,
+    }
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// Create a VM group, optionally nested under `parent_id`
+#[tauri::command]
+pub async fn create_group(
+    state: State<'_, AppState>,
+    name: String,
+    parent_id: Option<String>,
+) -> CommandResult<String> {
+    if name.trim().is_empty() {
+        return Err(CommandError::validation("name", "group.name.empty"));
+    }
 
-        let args = build_start_args(&record, "/tmp/vm-1.qcow2", "/tmp/openutm-qmp-vm-1.sock")
-            .expect("args should build");
-        let joined = args.join(" ");
+    state
+        .config_store
+        .create_group(name.trim(), parent_id.as_deref())
+        .map_err(CommandError::from)
+}
+
+/// List all VM groups
+#[tauri::command]
+pub async fn list_groups(state: State<'_, AppState>) -> CommandResult<Vec<GroupRecord>> {
+    state.config_store.list_groups().map_err(CommandError::from)
+}
 
-        assert!(joined.contains("-qmp"));
-        assert!(joined.contains("openutm-qmp-vm-1.sock"));
-        assert!(joined.contains("-name Fedora VM"));
-        assert!(joined.contains("-spice"));
-        assert!(joined.contains(&format!("port={}", resolve_spice_port("vm-1"))));
-        assert!(joined.contains("media=cdrom"));
-        assert!(joined.contains("/isos/fedora.iso"));
-        assert!(joined.contains("-boot"));
-        assert!(joined.contains("order=d"));
+/// Delete a group; its VMs are kept
+#[tauri::command]
+pub async fn delete_group(state: State<'_, AppState>, group_id: String) -> CommandResult<()> {
+    if group_id.trim().is_empty() {
+        return Err(CommandError::validation("group_id", "group.id.empty"));
     }
 
-    #[test]
-    fn test_resolve_spice_port_is_stable_and_in_range() {
-        let port = resolve_spice_port("vm-1");
-        assert_eq!(port, resolve_spice_port("vm-1"));
-        assert!((5900..=6899).contains(&port));
+    state.config_store.delete_group(&group_id).map_err(CommandError::from)
+}
+
+/// Add a VM to a group
+#[tauri::command]
+pub async fn add_vm_to_group(
+    state: State<'_, AppState>,
+    vm_id: String,
+    group_id: String,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if group_id.trim().is_empty() {
+        return Err(CommandError::validation("group_id", "group.id.empty"));
     }
 
-    #[test]
-    fn test_build_display_session_defaults() {
-        let session = build_display_session("vm-1", "connected", 0, None);
-        assert_eq!(session.protocol, "spice");
-        assert!(session.uri.starts_with("spice://127.0.0.1:"));
-        assert_eq!(session.status, "connected");
-        assert_eq!(session.reconnect_attempts, 0);
+    state.config_store.add_vm_to_group(&vm_id, &group_id)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// Remove a VM from a group
+#[tauri::command]
+pub async fn remove_vm_from_group(
+    state: State<'_, AppState>,
+    vm_id: String,
+    group_id: String,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if group_id.trim().is_empty() {
+        return Err(CommandError::validation("group_id", "group.id.empty"));
+    }
+
+    state.config_store.remove_vm_from_group(&vm_id, &group_id)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// Start every VM in a group, each after the group members it depends on.
+/// Members that are already running count as started.
+#[tauri::command]
+pub async fn start_group(
+    state: State<'_, AppState>,
+    group_id: String,
+) -> CommandResult<BTreeMap<String, service::BatchOutcome>> {
+    if group_id.trim().is_empty() {
+        return Err(CommandError::validation("group_id", "group.id.empty"));
+    }
+
+    service::start_group(&state, &group_id).await
+}
+
+/// Stop every VM in a group, dependents before what they depend on
+#[tauri::command]
+pub async fn stop_group(
+    state: State<'_, AppState>,
+    group_id: String,
+) -> CommandResult<BTreeMap<String, service::BatchOutcome>> {
+    if group_id.trim().is_empty() {
+        return Err(CommandError::validation("group_id", "group.id.empty"));
+    }
+
+    service::stop_group(&state, &group_id).await
+}
+
+/// VMs that must be running before `vm_id` starts with its group
+#[tauri::command]
+pub async fn get_vm_dependencies(state: State<'_, AppState>, vm_id: String) -> CommandResult<Vec<String>> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    let mut dependencies = state.config_store.all_vm_dependencies()?;
+    Ok(dependencies.remove(&vm_id).unwrap_or_default())
+}
+
+/// Replace what `vm_id` depends on; a dependency cycle is refused
+#[tauri::command]
+pub async fn set_vm_dependencies(
+    state: State<'_, AppState>,
+    vm_id: String,
+    depends_on: Vec<String>,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if depends_on.contains(&vm_id) {
+        return Err(CommandError::validation("depends_on", "vm.dependencies.self"));
+    }
+
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    let mut dependencies = state.config_store.all_vm_dependencies()?;
+    dependencies.insert(vm_id.clone(), depends_on.clone());
+    let vms: Vec<String> = dependencies.keys().cloned().collect();
+    if let Err(cycle) = service::dependency_waves(&vms, &dependencies) {
+        return Err(CommandError::validation("depends_on", "vm.dependencies.cycle")
+            .with_param("vms", cycle.join(", ")));
+    }
+    state.config_store.set_vm_dependencies(&vm_id, &depends_on)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// List the VMs in a group
+#[tauri::command]
+pub async fn list_vms_in_group(
+    state: State<'_, AppState>,
+    group_id: String,
+) -> CommandResult<Vec<VM>> {
+    if group_id.trim().is_empty() {
+        return Err(CommandError::validation("group_id", "group.id.empty"));
+    }
+
+    let records = state
+        .config_store
+        .list_vms_in_group(&group_id)?;
+    service::with_group_ids(&state.config_store, state.config_store.group_memberships()?, records)
+}
+
+const MAX_TAG_LEN: usize = 32;
+
+/// Tags are case-insensitive labels; commas are reserved as the storage separator
+fn normalize_tag(tag: &str) -> CommandResult<String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(CommandError::validation("tag", "tag.empty"));
+    }
+    if tag.chars().count() > MAX_TAG_LEN {
+        return Err(CommandError::validation("tag", "tag.tooLong").with_param("max", MAX_TAG_LEN));
+    }
+    if !tag.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ' ')) {
+        return Err(CommandError::validation("tag", "tag.invalidCharacters"));
+    }
+    Ok(tag)
+}
+
+/// Attach a tag to a VM; adding an existing tag is a no-op
+#[tauri::command]
+pub async fn add_tag(state: State<'_, AppState>, vm_id: String, tag: String) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    let tag = normalize_tag(&tag)?;
+
+    state.config_store.add_tag(&vm_id, &tag)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// Detach a tag from a VM
+#[tauri::command]
+pub async fn remove_tag(state: State<'_, AppState>, vm_id: String, tag: String) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    let tag = normalize_tag(&tag)?;
+
+    state.config_store.remove_tag(&vm_id, &tag)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// List every tag in use
+#[tauri::command]
+pub async fn list_tags(state: State<'_, AppState>) -> CommandResult<Vec<String>> {
+    state.config_store.list_tags().map_err(CommandError::from)
+}
+
+/// List the VMs carrying a tag
+#[tauri::command]
+pub async fn filter_vms_by_tag(state: State<'_, AppState>, tag: String) -> CommandResult<Vec<VM>> {
+    let tag = normalize_tag(&tag)?;
+    let records = state.config_store.list_vms_by_tag(&tag)?;
+    service::with_group_ids(&state.config_store, state.config_store.group_memberships()?, records)
+}
+
+/// Replace a VM's free-form notes; an empty string clears them
+#[tauri::command]
+pub async fn set_notes(state: State<'_, AppState>, vm_id: String, notes: String) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if notes.chars().count() > validation::MAX_NOTES_LEN {
+        return Err(CommandError::validation("notes", "vm.notes.tooLong").with_param("max", validation::MAX_NOTES_LEN));
+    }
+
+    let mut record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    record.notes = notes;
+    save_vm(&state, &record)?;
+    Ok(())
+}
+
+/// Apply I/O limits to a drive of a running VM via QMP
+#[tauri::command]
+pub async fn set_drive_throttle(
+    state: State<'_, AppState>,
+    vm_id: String,
+    drive_id: String,
+    throttle: IoThrottle,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if drive_id.trim().is_empty() {
+        return Err(CommandError::validation("drive_id", "drive.id.empty"));
+    }
+
+    let qmp_socket = state
+        .qemu_controller
+        .qmp_socket(&vm_id)
+        .ok_or_else(|| Error::VmNotRunning(vm_id.clone()))?;
+
+    QmpClient::new(qmp_socket)
+        .execute("block_set_io_throttle", Some(throttle_arguments(&drive_id, &throttle)))
+        .await?;
+    Ok(())
+}
+
+/// Whether guest TRIM on the VM's disk reclaims space in the host image
+#[tauri::command]
+pub async fn get_disk_discard(state: State<'_, AppState>, vm_id: String) -> CommandResult<bool> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    Ok(state.config_store.disk_discard(&vm_id)?)
+}
+
+/// Pass guest TRIM/fstrim through to the VM's disk so a thin image shrinks;
+/// takes effect on the next start
+#[tauri::command]
+pub async fn set_disk_discard(state: State<'_, AppState>, vm_id: String, enabled: bool) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    state.config_store.set_disk_discard(&vm_id, enabled)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// Interface the VM's disk is attached through, one of `DISK_INTERFACES`
+#[tauri::command]
+pub async fn get_disk_interface(state: State<'_, AppState>, vm_id: String) -> CommandResult<String> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    Ok(state.config_store.disk_interface(&vm_id)?)
+}
+
+/// Attach the VM's disk through another interface; virtio-scsi handles
+/// discard on any guest. Takes effect on the next start.
+#[tauri::command]
+pub async fn set_disk_interface(state: State<'_, AppState>, vm_id: String, interface: String) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if !DISK_INTERFACES.contains(&interface.as_str()) {
+        return Err(CommandError::validation("interface", "disk.interface.invalid")
+            .with_param("interfaces", DISK_INTERFACES.join(", ")));
+    }
+
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    state.config_store.set_disk_interface(&vm_id, &interface)?;
+    notify_vm_list_changed(&state, &vm_id, VmChangeKind::Updated);
+    Ok(())
+}
+
+/// Image formats a disk can be hot-added in
+const HOTPLUG_FORMATS: [&str; 2] = ["qcow2", "raw"];
+
+/// Attach a disk image to a running VM as a virtio disk, without a reboot
+#[tauri::command]
+pub async fn hotplug_disk(
+    state: State<'_, AppState>,
+    vm_id: String,
+    path: String,
+    format: String,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if path.trim().is_empty() {
+        return Err(CommandError::validation("path", "drive.path.empty"));
+    }
+    if !HOTPLUG_FORMATS.contains(&format.as_str()) {
+        return Err(CommandError::validation("format", "drive.format.invalid")
+            .with_param("formats", HOTPLUG_FORMATS.join(", ")));
+    }
+    if !Path::new(&path).is_file() {
+        return Err(CommandError::validation("path", "drive.path.notFound").with_param("path", path));
+    }
+    let record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    ensure_provisioned(&state, &record)?;
+    reject_ephemeral(&record)?;
+
+    let qmp_socket = state
+        .qemu_controller
+        .qmp_socket(&vm_id)
+        .ok_or_else(|| Error::VmNotRunning(vm_id.clone()))?;
+    let qmp = QmpClient::new(qmp_socket);
+    let drive_id = Uuid::new_v4();
+    let (device_id, node_name) = hotplug_names(&drive_id);
+
+    qmp.blockdev_add(&node_name, &path, &format).await?;
+    if let Err(err) = qmp.device_add_drive(&device_id, &node_name, "virtio").await {
+        // Leave no orphaned block node holding the image open
+        if let Err(cleanup) = qmp.blockdev_del(&node_name).await {
+            tracing::warn!(vm_id = %vm_id, node = %node_name, error = %cleanup, "failed to remove block node");
+        }
+        return Err(err.into());
+    }
+
+    state
+        .config_store
+        .add_hotplugged_drive(&vm_id, &drive_id.to_string(), &path, "virtio", &format)?;
+    tracing::info!(vm_id = %vm_id, device = %device_id, path = %path, "disk hot-added");
+    Ok(())
+}
+
+/// How long the guest gets to release a hot-added disk
+const UNPLUG_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Detach a hot-added disk, from the running guest too, so it is not attached on the next start
+#[tauri::command]
+pub async fn remove_hotplugged_disk(
+    state: State<'_, AppState>,
+    vm_id: String,
+    drive_id: String,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if drive_id.trim().is_empty() {
+        return Err(CommandError::validation("drive_id", "drive.id.empty"));
+    }
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    let not_found = || CommandError::new(ErrorCode::NotFound, "drive.notFound").with_param("id", drive_id.as_str());
+    let drive = state
+        .config_store
+        .hotplugged_drives(&vm_id)?
+        .into_iter()
+        .find(|drive| drive.id == drive_id)
+        .ok_or_else(not_found)?;
+
+    if let Some(qmp_socket) = state.qemu_controller.qmp_socket(&vm_id) {
+        let uuid = Uuid::parse_str(&drive.id).map_err(|_| not_found())?;
+        let (device_id, node_name) = hotplug_names(&uuid);
+        QmpClient::new(qmp_socket).device_del_drive(&device_id, &node_name, UNPLUG_TIMEOUT).await?;
+    }
+    match state.config_store.remove_hotplugged_drive(&vm_id, &drive_id) {
+        Err(Error::NotFound(_)) => return Err(not_found()),
+        result => result?,
+    }
+    tracing::info!(vm_id = %vm_id, drive = %drive_id, path = %drive.path, "hot-added disk removed");
+    Ok(())
+}
+
+/// Run a QEMU human monitor command for debugging; `quit`/`poweroff` are refused
+#[tauri::command]
+pub async fn send_monitor_command(
+    state: State<'_, AppState>,
+    vm_id: String,
+    command: String,
+) -> CommandResult<String> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    Ok(state.qemu_controller.send_monitor_command(&vm_id, &command).await?)
+}
+
+/// `block_set_io_throttle` takes every limit; 0 means unlimited
+fn throttle_arguments(drive_id: &str, throttle: &IoThrottle) -> serde_json::Value {
+    let read_bps = throttle.read_bps.unwrap_or(0);
+    let write_bps = throttle.write_bps.unwrap_or(0);
+    let read_iops = throttle.read_iops.unwrap_or(0);
+    let write_iops = throttle.write_iops.unwrap_or(0);
+
+    serde_json::json!({
+        "device": drive_id,
+        "bps": 0,
+        "bps_rd": read_bps,
+        "bps_wr": write_bps,
+        "iops": 0,
+        "iops_rd": read_iops,
+        "iops_wr": write_iops,
+    })
+}
+
+/// Pin a VM's QEMU process to host cores; an empty list removes pinning
+#[tauri::command]
+pub async fn set_cpu_affinity(
+    state: State<'_, AppState>,
+    vm_id: String,
+    cores: Vec<u32>,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    validate_cpu_affinity(&cores, platform::host_resources().logical_cpus)?;
+
+    let mut record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    record.cpu_affinity = cores;
+    save_vm(&state, &record)?;
+
+    let pid = state.qemu_controller.pid(&vm_id);
+    if let (Some(pid), false) = (pid, record.cpu_affinity.is_empty()) {
+        platform::set_process_affinity(pid, &record.cpu_affinity)?;
+    }
+    Ok(())
+}
+
+/// Report process-level metrics for a VM, including its effective CPU affinity
+#[tauri::command]
+pub async fn get_vm_metrics(state: State<'_, AppState>, id: String) -> CommandResult<VmMetrics> {
+    service::vm_metrics(&state, &id)
+}
+
+/// VM counts and memory totals; disk totals are filled in separately
+fn tally_overview(records: &[VMRecord]) -> VmOverview {
+    let mut overview = VmOverview::default();
+    for record in records {
+        overview.total += 1;
+        overview.allocated_memory_mb += u64::from(record.memory_mb);
+        let status = parse_vm_status(&record.status);
+        match status {
+            VMStatus::Running => overview.running += 1,
+            VMStatus::Stopped => overview.stopped += 1,
+            VMStatus::Paused => overview.paused += 1,
+            VMStatus::Error => overview.error += 1,
+            VMStatus::Starting | VMStatus::Stopping | VMStatus::Creating => overview.transitioning += 1,
+        }
+        if matches!(status, VMStatus::Running | VMStatus::Paused) {
+            overview.running_memory_mb += u64::from(record.memory_mb);
+        }
+    }
+    overview
+}
+
+/// Aggregate counts, memory and disk use across all VMs in one call
+#[tauri::command]
+pub async fn get_overview(state: State<'_, AppState>) -> CommandResult<VmOverview> {
+    overview(&state).await
+}
+
+async fn overview(state: &AppState) -> CommandResult<VmOverview> {
+    let records = state.config_store.list_vms()?;
+    let mut overview = tally_overview(&records);
+
+    // Raw devices are not images, and one unreadable image should not fail the whole overview
+    for record in records.iter().filter(|record| record.raw_device_path.is_none()) {
+        let info = match vm_disk_path(state, &record.id) {
+            Ok(disk_path) => state
+                .disk_manager
+                .get_disk_info_at(&disk_path, &record.preallocation)
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match info {
+            Ok(info) => {
+                overview.disk_allocated_bytes += info.virtual_size;
+                overview.disk_used_bytes += info.actual_size;
+            }
+            Err(message) => {
+                tracing::debug!(vm_id = %record.id, error = %message, "disk left out of overview");
+                overview.disk_errors.push(OverviewDiskError { vm_id: record.id.clone(), message });
+            }
+        }
+    }
+    Ok(overview)
+}
+
+/// One VM's metrics for the Prometheus exposition
+fn sample_vm(state: &AppState, record: &VMRecord) -> metrics::VmSample {
+    let usage = state
+        .qemu_controller
+        .pid(&record.id)
+        .and_then(|pid| state.process_sampler.sample(pid));
+    let disk_physical_bytes = match record.raw_device_path {
+        Some(_) => None,
+        None => vm_disk_path(state, &record.id)
+            .ok()
+            .and_then(|path| storage::physical_size(Path::new(&path))),
+    };
+    let traffic = state
+        .qemu_controller
+        .launch_command(&record.id)
+        .and_then(|(_, args)| metrics::tap_ifname(&args))
+        .and_then(|ifname| metrics::tap_traffic(&ifname));
+
+    metrics::VmSample {
+        vm_id: record.id.clone(),
+        name: record.name.clone(),
+        status: record.status.clone(),
+        usage,
+        disk_physical_bytes,
+        net_rx_bytes: traffic.map(|(rx, _)| rx),
+        net_tx_bytes: traffic.map(|(_, tx)| tx),
+    }
+}
+
+/// Prometheus text for every VM plus the app counters
+pub fn metrics_exposition(state: &AppState) -> String {
+    let records = state.config_store.list_vms().unwrap_or_else(|err| {
+        tracing::warn!(error = %err, "failed to list VMs for metrics");
+        Vec::new()
+    });
+    let samples: Vec<_> = records.iter().map(|record| sample_vm(state, record)).collect();
+    metrics::render(&samples)
+}
+
+/// Serve `/metrics` on `addr`, or stop serving when `None`. The old listener
+/// keeps running if the new address cannot be bound.
+pub async fn serve_metrics(
+    state: &AppState,
+    app: tauri::AppHandle,
+    addr: Option<std::net::SocketAddr>,
+) -> crate::Result<()> {
+    let mut current = state.metrics_server.lock().await;
+    if current.as_ref().map(|server| server.addr()) == addr {
+        return Ok(());
+    }
+    let replacement = match addr {
+        Some(addr) => Some(
+            metrics::MetricsServer::spawn(addr, move || {
+                let app = app.clone();
+                async move { metrics_exposition(&app.state::<AppState>()) }
+            })
+            .await?,
+        ),
+        None => None,
+    };
+    if let Some(previous) = std::mem::replace(&mut *current, replacement) {
+        previous.shutdown().await;
+    }
+    Ok(())
+}
+
+/// Address the Prometheus metrics server listens on, `None` while it is off
+#[tauri::command]
+pub async fn get_metrics_listen_address(state: State<'_, AppState>) -> CommandResult<Option<String>> {
+    Ok(state.config_store.metrics_listen_address()?.map(|addr| addr.to_string()))
+}
+
+/// Serve Prometheus metrics on `address` (e.g. `0.0.0.0:9464`), or turn the server off with `None`
+#[tauri::command]
+pub async fn set_metrics_listen_address(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    address: Option<String>,
+) -> CommandResult<()> {
+    let addr = match address.as_deref().map(str::trim).filter(|address| !address.is_empty()) {
+        Some(address) => Some(address.parse::<std::net::SocketAddr>().map_err(|_| {
+            CommandError::validation("address", "metrics.invalidAddress").with_param("address", address)
+        })?),
+        None => None,
+    };
+    serve_metrics(&state, app, addr).await?;
+    let setting = addr.map(|addr| addr.to_string()).unwrap_or_default();
+    Ok(state.config_store.save_setting(METRICS_LISTEN_SETTING, &setting)?)
+}
+
+/// Start or stop the control socket to match `enabled`
+pub async fn serve_control(state: &AppState, app: tauri::AppHandle, enabled: bool) -> crate::Result<()> {
+    let mut current = state.control_server.lock().await;
+    if current.is_some() == enabled {
+        return Ok(());
+    }
+    // Drop the old server first so its socket is gone before a new one binds
+    *current = None;
+    if enabled {
+        let endpoint = control::endpoint(&state.paths.runtime_dir);
+        *current = Some(control::ControlServer::spawn(endpoint, move |line| {
+            let app = app.clone();
+            async move {
+                let state = app.state::<AppState>();
+                control::dispatch(&state, &line).await.map(|response| response.to_string())
+            }
+        })?);
+    }
+    Ok(())
+}
+
+fn control_socket_info(state: &AppState) -> CommandResult<ControlSocketInfo> {
+    Ok(ControlSocketInfo {
+        enabled: state.config_store.control_socket_enabled()?,
+        endpoint: control::endpoint(&state.paths.runtime_dir).display().to_string(),
+        token: state.config_store.control_token()?,
+    })
+}
+
+/// Whether the control socket is on, where it listens and the token clients must send
+#[tauri::command]
+pub async fn get_control_socket(state: State<'_, AppState>) -> CommandResult<ControlSocketInfo> {
+    control_socket_info(&state)
+}
+
+/// Turn the control socket on or off; the first enable generates its token
+#[tauri::command]
+pub async fn set_control_socket(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> CommandResult<ControlSocketInfo> {
+    if enabled && state.config_store.control_token()?.is_none() {
+        state
+            .config_store
+            .save_setting(CONTROL_TOKEN_SETTING, &Uuid::new_v4().simple().to_string())?;
+    }
+    serve_control(&state, app, enabled).await?;
+    state
+        .config_store
+        .save_setting(CONTROL_SOCKET_SETTING, if enabled { "true" } else { "false" })?;
+    control_socket_info(&state)
+}
+
+/// Replace the control socket token; requests with the old one are refused from now on
+#[tauri::command]
+pub async fn rotate_control_token(state: State<'_, AppState>) -> CommandResult<ControlSocketInfo> {
+    state
+        .config_store
+        .save_setting(CONTROL_TOKEN_SETTING, &Uuid::new_v4().simple().to_string())?;
+    control_socket_info(&state)
+}
+
+/// Compare a VM's saved config with the one its QEMU process was started with
+#[tauri::command]
+pub async fn get_config_diff(state: State<'_, AppState>, vm_id: String) -> CommandResult<ConfigDiff> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    let applied = state.qemu_controller.launch_command(&vm_id).map(|(_, args)| args);
+    let changes = match &applied {
+        Some(applied) => config_changes(applied, &pending_start_args(&state, &record)?),
+        None => Vec::new(),
+    };
+    Ok(ConfigDiff {
+        vm_id,
+        running: applied.is_some(),
+        restart_required: changes.iter().any(|change| change.requires_restart),
+        changes,
+    })
+}
+
+/// The exact QEMU command line a running VM was started with, for diagnostics
+#[tauri::command]
+pub async fn get_launch_args(state: State<'_, AppState>, vm_id: String) -> CommandResult<LaunchInfo> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let (binary, args) = state
+        .qemu_controller
+        .launch_command(&vm_id)
+        .ok_or_else(|| Error::VmNotRunning(vm_id.clone()))?;
+    Ok(LaunchInfo {
+        vm_id,
+        accelerator: launch_accelerator(&args),
+        binary,
+        args,
+    })
+}
+
+/// Confirm whether a running VM really uses hardware acceleration or fell back to TCG
+#[tauri::command]
+pub async fn get_active_accelerator(state: State<'_, AppState>, vm_id: String) -> CommandResult<ActiveAccelerator> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let not_running = || Error::VmNotRunning(vm_id.clone());
+    let (_, args) = state.qemu_controller.launch_command(&vm_id).ok_or_else(not_running)?;
+    let qmp = QmpClient::new(state.qemu_controller.qmp_socket(&vm_id).ok_or_else(not_running)?);
+
+    let status = qmp.execute("query-status", None).await?;
+    let requested = launch_accelerator(&args);
+    let kvm_enabled = match requested.as_deref() {
+        Some("kvm") => qmp.execute("query-kvm", None).await?["enabled"].as_bool() == Some(true),
+        _ => false,
+    };
+    let (active, hardware_accelerated) = active_accelerator(requested.as_deref(), kvm_enabled);
+    if !hardware_accelerated {
+        tracing::info!(vm_id = %vm_id, requested = ?requested, "VM is running without hardware acceleration");
+    }
+    Ok(ActiveAccelerator {
+        vm_id,
+        requested,
+        active,
+        hardware_accelerated,
+        run_state: status["status"].as_str().unwrap_or("unknown").to_string(),
+    })
+}
+
+/// Startup latency milestones of the VM's most recent launch
+#[tauri::command]
+pub async fn get_vm_boot_time(state: State<'_, AppState>, vm_id: String) -> CommandResult<Option<BootTimings>> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    fetch_vm_or_err(&state.config_store, &vm_id)?;
+    let events = state.config_store.recent_vm_events(&vm_id, BOOT_EVENT_WINDOW)?;
+    Ok(boot_timings(&events))
+}
+
+/// All qcow2 files in the storage directory, including orphans with no VM
+#[tauri::command]
+pub async fn list_disk_files(state: State<'_, AppState>) -> CommandResult<Vec<DiskEntry>> {
+    state.disk_manager.list_all_disks().map_err(CommandError::from)
+}
+
+/// Get disk sizes and the preallocation mode it was created with
+#[tauri::command]
+pub async fn get_disk_info(state: State<'_, AppState>, id: String) -> CommandResult<DiskInfo> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let record = fetch_vm_or_err(&state.config_store, &id)?;
+    ensure_provisioned(&state, &record)?;
+    state
+        .disk_manager
+        .get_disk_info(&id, &record.preallocation)
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Whether VM names must be unique
+#[tauri::command]
+pub async fn get_unique_names(state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.config_store.unique_names_enabled()?)
+}
+
+/// Opt in to (or out of) unique VM names; existing duplicates are left alone
+#[tauri::command]
+pub async fn set_unique_names(state: State<'_, AppState>, enabled: bool) -> CommandResult<()> {
+    Ok(state
+        .config_store
+        .save_setting(UNIQUE_NAMES_SETTING, if enabled { "true" } else { "false" })?)
+}
+
+/// Whether SPICE displays require a per-session password
+#[tauri::command]
+pub async fn get_spice_ticketing(state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.config_store.spice_ticketing_enabled()?)
+}
+
+/// Turn SPICE passwords off (single-user hosts) or back on; applies from the next VM start
+#[tauri::command]
+pub async fn set_spice_ticketing(state: State<'_, AppState>, enabled: bool) -> CommandResult<()> {
+    Ok(state
+        .config_store
+        .save_setting(SPICE_TICKETING_SETTING, if enabled { "true" } else { "false" })?)
+}
+
+/// Whether external viewers open fullscreen
+#[tauri::command]
+pub async fn get_viewer_fullscreen(state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.config_store.viewer_fullscreen_enabled()?)
+}
+
+#[tauri::command]
+pub async fn set_viewer_fullscreen(state: State<'_, AppState>, enabled: bool) -> CommandResult<()> {
+    Ok(state
+        .config_store
+        .save_setting(VIEWER_FULLSCREEN_SETTING, if enabled { "true" } else { "false" })?)
+}
+
+/// Reconnects `open_display` allows per display session before marking it failed
+#[tauri::command]
+pub async fn get_display_reconnect_attempts(state: State<'_, AppState>) -> CommandResult<u32> {
+    Ok(state.config_store.display_reconnect_max_attempts()?)
+}
+
+#[tauri::command]
+pub async fn set_display_reconnect_attempts(state: State<'_, AppState>, attempts: u32) -> CommandResult<()> {
+    if attempts == 0 {
+        return Err(CommandError::validation("attempts", "display.reconnect.invalidMax"));
+    }
+    Ok(state
+        .config_store
+        .save_setting(DISPLAY_RECONNECT_ATTEMPTS_SETTING, &attempts.to_string())?)
+}
+
+/// Seconds a stopped VM's display session is kept before being swept
+#[tauri::command]
+pub async fn get_display_session_grace(state: State<'_, AppState>) -> CommandResult<u64> {
+    Ok(state.config_store.display_session_grace_secs()?)
+}
+
+#[tauri::command]
+pub async fn set_display_session_grace(state: State<'_, AppState>, seconds: u64) -> CommandResult<()> {
+    Ok(state
+        .config_store
+        .save_setting(DISPLAY_SESSION_GRACE_SETTING, &seconds.to_string())?)
+}
+
+/// Host ports display servers are assigned from
+#[tauri::command]
+pub async fn get_display_port_range(state: State<'_, AppState>) -> CommandResult<DisplayPortRange> {
+    Ok(state.config_store.display_port_range()?)
+}
+
+/// Change the display port range; running VMs keep their ports until restarted
+#[tauri::command]
+pub async fn set_display_port_range(state: State<'_, AppState>, range: DisplayPortRange) -> CommandResult<()> {
+    if !range.is_valid() {
+        return Err(
+            CommandError::validation("range", "display.portRange.invalid").with_param("min", qemu::VNC_BASE_PORT)
+        );
+    }
+    state
+        .config_store
+        .save_setting(DISPLAY_PORT_RANGE_START_SETTING, &range.start.to_string())?;
+    Ok(state
+        .config_store
+        .save_setting(DISPLAY_PORT_RANGE_END_SETTING, &range.end.to_string())?)
+}
+
+/// Seconds between QMP health checks of running VMs
+#[tauri::command]
+pub async fn get_health_check_interval(state: State<'_, AppState>) -> CommandResult<u64> {
+    Ok(state.config_store.health_check_interval_secs()?)
+}
+
+#[tauri::command]
+pub async fn set_health_check_interval(state: State<'_, AppState>, seconds: u64) -> CommandResult<()> {
+    if seconds == 0 {
+        return Err(CommandError::validation("seconds", "healthCheck.invalidInterval"));
+    }
+    Ok(state
+        .config_store
+        .save_setting(HEALTH_CHECK_INTERVAL_SETTING, &seconds.to_string())?)
+}
+
+/// The memory ballooning policy, defaults included
+#[tauri::command]
+pub async fn get_memory_policy(state: State<'_, AppState>) -> CommandResult<MemoryPolicy> {
+    Ok(state.config_store.memory_policy()?)
+}
+
+/// Change the memory ballooning policy; VMs started while it is off have no
+/// balloon device and are only covered after a restart
+#[tauri::command]
+pub async fn set_memory_policy(state: State<'_, AppState>, policy: MemoryPolicy) -> CommandResult<()> {
+    if policy.free_threshold_mb == 0 {
+        return Err(CommandError::validation("free_threshold_mb", "memoryPolicy.invalidThreshold"));
+    }
+    if policy.vm_minimum_mb == 0 {
+        return Err(CommandError::validation("vm_minimum_mb", "memoryPolicy.invalidMinimum"));
+    }
+    Ok(state.config_store.save_memory_policy(&policy)?)
+}
+
+/// Whether the memory policy leaves the VM's memory alone
+#[tauri::command]
+pub async fn get_vm_memory_exempt(state: State<'_, AppState>, vm_id: String) -> CommandResult<bool> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    Ok(fetch_vm_or_err(&state.config_store, &vm_id)?.memory_policy_exempt)
+}
+
+/// Exempt a VM from the memory policy or subject it again; a ballooned VM
+/// made exempt gets its memory back at the next policy pass
+#[tauri::command]
+pub async fn set_vm_memory_exempt(state: State<'_, AppState>, vm_id: String, exempt: bool) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let mut record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    record.memory_policy_exempt = exempt;
+    save_vm(&state, &record)?;
+    Ok(())
+}
+
+/// Where the VM's RAM comes from: one of `MEMORY_BACKENDS`
+#[tauri::command]
+pub async fn get_memory_backend(state: State<'_, AppState>, vm_id: String) -> CommandResult<String> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    Ok(record.memory_backend.unwrap_or_else(|| MemoryBackend::Default.as_str().to_string()))
+}
+
+/// Choose the VM's memory backend; takes effect at its next start. Huge pages
+/// must already be reserved for the whole of the VM's memory.
+#[tauri::command]
+pub async fn set_memory_backend(state: State<'_, AppState>, vm_id: String, backend: String) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    let parsed = MemoryBackend::parse(&backend).ok_or_else(|| {
+        CommandError::validation("backend", "vm.memoryBackend.invalid")
+            .with_param("backends", MEMORY_BACKENDS.join(", "))
+    })?;
+
+    let mut record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    if parsed != MemoryBackend::Default && !platform::supports_memory_backends() {
+        return Err(CommandError::new(ErrorCode::PlatformUnsupported, "vm.memoryBackend.unsupported"));
+    }
+    if parsed == MemoryBackend::Hugepages {
+        let pages = platform::hugepages()
+            .ok_or_else(|| CommandError::new(ErrorCode::PlatformUnsupported, "vm.memoryBackend.unsupported"))?;
+        check_hugepages(record.memory_mb, pages.total, pages.page_size_kb)?;
+    }
+    record.memory_backend = (parsed != MemoryBackend::Default).then(|| parsed.as_str().to_string());
+    save_vm(&state, &record)?;
+    Ok(())
+}
+
+/// Health checks a VM may miss in a row before it is marked not responding
+#[tauri::command]
+pub async fn get_health_check_threshold(state: State<'_, AppState>) -> CommandResult<u32> {
+    Ok(state.config_store.health_check_failure_threshold()?)
+}
+
+#[tauri::command]
+pub async fn set_health_check_threshold(state: State<'_, AppState>, checks: u32) -> CommandResult<()> {
+    if checks == 0 {
+        return Err(CommandError::validation("checks", "healthCheck.invalidThreshold"));
+    }
+    Ok(state
+        .config_store
+        .save_setting(HEALTH_CHECK_THRESHOLD_SETTING, &checks.to_string())?)
+}
+
+/// English message templates keyed by message key, for the stored UI locale
+#[tauri::command]
+pub async fn get_message_catalog(state: State<'_, AppState>) -> CommandResult<MessageCatalog> {
+    let locale = state
+        .config_store
+        .get_setting(i18n::LOCALE_SETTING)?
+        .unwrap_or_else(|| i18n::DEFAULT_LOCALE.to_string());
+    Ok(i18n::catalog(&locale))
+}
+
+#[tauri::command]
+pub async fn set_locale(state: State<'_, AppState>, locale: String) -> CommandResult<()> {
+    if !i18n::is_valid_locale(&locale) {
+        return Err(CommandError::validation("locale", "settings.locale.invalid"));
+    }
+    Ok(state.config_store.save_setting(i18n::LOCALE_SETTING, &locale)?)
+}
+
+/// Current per-command rate limits, keyed by command name
+#[tauri::command]
+pub async fn get_rate_limits(state: State<'_, AppState>) -> CommandResult<HashMap<String, RateLimit>> {
+    Ok(state.rate_limiter.limits())
+}
+
+/// Retune a limited command; the value is persisted as `rate_limit.<command>`
+#[tauri::command]
+pub async fn set_rate_limit(state: State<'_, AppState>, command: String, limit: RateLimit) -> CommandResult<()> {
+    if !state.rate_limiter.is_limited(&command) {
+        return Err(CommandError::validation("command", "command.rateLimit.unknown").with_param("command", command));
+    }
+    if limit.calls == 0 || limit.period.is_zero() {
+        return Err(CommandError::validation("limit", "command.rateLimit.invalid"));
+    }
+    state
+        .config_store
+        .save_setting(&format!("{}{}", rate_limit::SETTING_PREFIX, command), &limit.to_setting())?;
+    state.rate_limiter.set_limit(&command, limit);
+    Ok(())
+}
+
+fn parse_log_level(field: &str, level: &str) -> CommandResult<tracing_subscriber::filter::LevelFilter> {
+    logging::parse_level(level).ok_or_else(|| {
+        CommandError::validation(field, "settings.logLevel.invalid").with_param("levels", logging::LOG_LEVELS.join(", "))
+    })
+}
+
+#[tauri::command]
+pub async fn get_log_level(state: State<'_, AppState>) -> CommandResult<String> {
+    Ok(state
+        .config_store
+        .get_setting(logging::LOG_LEVEL_SETTING)?
+        .unwrap_or_else(|| logging::DEFAULT_LOG_LEVEL.to_string()))
+}
+
+/// Persist the minimum app log level and apply it immediately
+#[tauri::command]
+pub async fn set_log_level(state: State<'_, AppState>, level: String) -> CommandResult<()> {
+    let filter = parse_log_level("level", &level)?;
+    state.config_store.save_setting(logging::LOG_LEVEL_SETTING, &level)?;
+    if let Some(handle) = &state.log_handle {
+        logging::set_level(handle, filter)?;
+    }
+    tracing::info!(level = %level, "log level changed");
+    Ok(())
+}
+
+/// Most recent app log lines, optionally only those at or above `level_filter`
+#[tauri::command]
+pub async fn get_app_logs(
+    state: State<'_, AppState>,
+    lines: Option<usize>,
+    level_filter: Option<String>,
+) -> CommandResult<Vec<String>> {
+    let min_level = level_filter
+        .as_deref()
+        .map(|level| parse_log_level("level_filter", level))
+        .transpose()?;
+    let lines = lines.unwrap_or(DEFAULT_LOG_LINES).min(MAX_LOG_LINES);
+    Ok(logging::tail_logs(&state.paths.log_dir, lines, min_level)?)
+}
+
+/// Zip logs, redacted settings and QEMU details for a bug report; returns the archive path
+#[tauri::command]
+pub async fn export_diagnostics(state: State<'_, AppState>) -> CommandResult<String> {
+    let qemu_info = match qemu::detector::detect().await {
+        Ok(info) => serde_json::to_value(info).map_err(Error::from)?,
+        Err(err) => serde_json::json!({ "error": err.to_string() }),
+    };
+    let settings = state.config_store.list_settings()?;
+    let dest = state.paths.log_dir.join(format!(
+        "diagnostics-{}.zip",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S")
+    ));
+
+    logging::export_diagnostics(&state.paths.log_dir, &settings, &qemu_info, &dest)?;
+    tracing::info!(path = %dest.display(), "diagnostics exported");
+    Ok(dest.display().to_string())
+}
+
+/// Get host CPU and memory totals
+#[tauri::command]
+pub async fn get_host_resources() -> CommandResult<HostResources> {
+    Ok(platform::host_resources())
+}
+
+fn migration_status(state: &AppState) -> DataMigrationStatus {
+    let plan = state
+        .legacy_dir
+        .as_deref()
+        .and_then(|legacy_dir| paths::plan_migration(legacy_dir, &state.platform_paths));
+    DataMigrationStatus {
+        needs_migration: plan.is_some(),
+        legacy_dir: plan.map(|plan| plan.legacy_dir.display().to_string()),
+        config_dir: state.platform_paths.config_dir.display().to_string(),
+        data_dir: state.platform_paths.data_dir.display().to_string(),
+        log_dir: state.platform_paths.log_dir.display().to_string(),
+        restart_required: state.paths != state.platform_paths,
+    }
+}
+
+/// Icon and suggested settings for each guest OS family, for the create-VM dialog
+#[tauri::command]
+pub async fn list_guest_os_defaults() -> CommandResult<Vec<GuestOsDefaults>> {
+    Ok(ALL_GUEST_OS.iter().map(GuestOs::defaults).collect())
+}
+
+/// Startup problems the UI should tell the user about, e.g. a database reset from backup
+#[tauri::command]
+pub async fn get_startup_status(state: State<'_, AppState>) -> CommandResult<StartupStatus> {
+    Ok(state.startup_status.clone())
+}
+
+/// What startup reconciliation corrected, e.g. VMs marked stopped because their QEMU was gone
+#[tauri::command]
+pub async fn get_startup_report(state: State<'_, AppState>) -> CommandResult<Vec<Correction>> {
+    Ok(state.startup_status.corrections.clone())
+}
+
+/// Report whether a legacy ~/.openutm directory is waiting to be migrated
+#[tauri::command]
+pub async fn get_data_migration_status(state: State<'_, AppState>) -> CommandResult<DataMigrationStatus> {
+    Ok(migration_status(&state))
+}
+
+/// Move (or symlink) legacy ~/.openutm data into the platform directories.
+/// Only run on user consent; the new locations are used from the next launch.
+#[tauri::command]
+pub async fn migrate_legacy_data(
+    state: State<'_, AppState>,
+    mode: String,
+) -> CommandResult<DataMigrationStatus> {
+    let mode = MigrationMode::parse(&mode)?;
+
+    let plan = state
+        .legacy_dir
+        .as_deref()
+        .and_then(|legacy_dir| paths::plan_migration(legacy_dir, &state.platform_paths))
+        .ok_or_else(|| CommandError::new(ErrorCode::NotFound, "migration.noLegacyData"))?;
+
+    let running = state
+        .qemu_controller
+        .sync_status()
+        .into_iter()
+        .any(|(_, alive)| alive);
+    if running {
+        return Err(CommandError::new(ErrorCode::Conflict, "migration.vmsRunning"));
+    }
+
+    paths::apply_migration(&plan, mode)?;
+    Ok(migration_status(&state))
+}
+
+/// QEMU binary that runs `arch` guests
+fn qemu_binary_for_arch(arch: &str) -> CommandResult<PathBuf> {
+    if !validation::ARCHES.contains(&arch) {
+        return Err(CommandError::validation("arch", "vm.arch.invalid").with_param("arches", validation::ARCHES.join(", ")));
+    }
+
+    let qemu_path = qemu::detector::find_qemu_binary()?;
+    Ok(PathBuf::from(qemu::detector::binary_for_arch(&qemu_path.display().to_string(), arch)))
+}
+
+/// Machine types accepted by the QEMU binary for `arch`, including versioned ones
+#[tauri::command]
+pub async fn list_machine_types(arch: String) -> CommandResult<Vec<String>> {
+    let binary = qemu_binary_for_arch(&arch)?;
+    let mut machines = qemu::detector::query_machines(&binary)?;
+    machines.sort();
+    Ok(machines)
+}
+
+/// Cross-reference the binary's accelerators with the host. TCG always works; the
+/// native hypervisor only when the host exposes it and the guest is not emulated.
+fn accelerator_support(built_in: &[String], native: &str, host_available: bool, emulated: bool) -> Vec<AcceleratorSupport> {
+    let mut names: Vec<&str> = built_in.iter().map(String::as_str).collect();
+    if !names.contains(&native) {
+        names.push(native);
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            let built = built_in.iter().any(|accel| accel == name);
+            let backed = match name {
+                "tcg" => true,
+                _ => name == native && host_available && !emulated,
+            };
+            AcceleratorSupport {
+                name: name.to_string(),
+                built_in: built,
+                usable: built && backed,
+            }
+        })
+        .collect()
+}
+
+/// Accelerators the QEMU binary for `arch` supports, and which of them work on this host
+#[tauri::command]
+pub async fn list_accelerators(arch: String) -> CommandResult<Vec<AcceleratorSupport>> {
+    let binary = qemu_binary_for_arch(&arch)?;
+    let built_in = qemu::detector::query_accels(&binary)?;
+    Ok(accelerator_support(
+        &built_in,
+        default_accelerator().as_str(),
+        platform::has_acceleration(),
+        platform::is_emulated(&arch, &platform::host_arch()),
+    ))
+}
+
+/// CPU models (with QEMU's notes) and feature flags for `arch`
+#[tauri::command]
+pub async fn list_cpu_models(arch: String) -> CommandResult<CpuModelList> {
+    let binary = qemu_binary_for_arch(&arch)?;
+    qemu::detector::query_cpu_models(&binary).map_err(CommandError::from)
+}
+
+/// Describe the host platform, its accelerator, resources and QEMU binary
+#[tauri::command]
+pub async fn get_platform_info(state: State<'_, AppState>) -> CommandResult<PlatformInfo> {
+    let mut info = platform::get_platform_info()?;
+    // Unknown free space is reported as 0 rather than failing the whole query
+    info.free_disk_mb = state.disk_manager.free_space().map_or(0, |bytes| bytes / (1024 * 1024));
+    info.qemu_path = qemu::detector::find_qemu_binary()
+        .ok()
+        .map(|path| path.display().to_string());
+    Ok(info)
+}
+
+/// Host PCI devices bound to vfio-pci and available for passthrough
+#[tauri::command]
+pub async fn list_vfio_devices() -> CommandResult<Vec<VfioDeviceInfo>> {
+    platform::list_vfio_devices().map_err(CommandError::from)
+}
+
+/// Report accelerator availability and nested virtualization support
+#[tauri::command]
+pub async fn diagnose_acceleration() -> CommandResult<AccelerationDiagnostics> {
+    let info = platform::get_platform_info()?;
+    let nested_flag = platform::nested_virt_flag();
+    let details = match &info.accelerator {
+        Some(accelerator) => format!("{} acceleration available on {} {}", accelerator.to_uppercase(), info.os, info.arch),
+        None => format!("No hardware acceleration available on {} {}", info.os, info.arch),
+    };
+
+    Ok(AccelerationDiagnostics {
+        accelerator_available: platform::has_acceleration(),
+        details,
+        nested_virt_supported: nested_flag.is_some(),
+        nested_virt_flag: nested_flag.map(|flag| flag.to_string()),
+    })
+}
+
+/// Time the storage dir and QEMU startup with and without acceleration, for the
+/// host or for one VM's disk location and architecture. Each report is added
+/// to the benchmark history.
+#[tauri::command]
+pub async fn run_benchmark(state: State<'_, AppState>, id: Option<String>) -> CommandResult<BenchmarkReport> {
+    let context = benchmark_context(&state, id.as_deref())?;
+    let binary = PathBuf::from(qemu::detector::binary_for_arch(&state.qemu_controller.qemu_path(), &context.arch));
+    let qmp_socket = state.paths.benchmark_socket();
+    let report = tokio::task::spawn_blocking(move || {
+        benchmark::run_benchmark(&context, benchmark::measure_disk, |accelerator| {
+            benchmark::measure_startup(&binary, accelerator, &qmp_socket)
+        })
+    })
+    .await
+    .map_err(|e| CommandError::new(ErrorCode::Internal, "error.internal").with_param("detail", e.to_string()))?;
+    state.config_store.record_benchmark(&report)?;
+    Ok(report)
+}
+
+/// Past benchmark reports, oldest first
+#[tauri::command]
+pub async fn get_benchmark_history(state: State<'_, AppState>) -> CommandResult<Vec<BenchmarkReport>> {
+    Ok(state.config_store.benchmark_history()?)
+}
+
+/// A VM is benchmarked where its disk lives and with the accelerator it would start with
+fn benchmark_context(state: &AppState, id: Option<&str>) -> CommandResult<BenchmarkContext> {
+    let host = HostCapabilities::detect();
+    let context = BenchmarkContext {
+        vm_id: None,
+        host_arch: host.arch.clone(),
+        arch: host.arch.clone(),
+        accelerator: default_accelerator(),
+        storage_dir: state.disks_dir(),
+        disk_type: None,
+        ran_at_ms: now_ms(),
+    };
+    let Some(id) = id else {
+        return Ok(context);
+    };
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let vm = fetch_vm_or_err(&state.config_store, id)?;
+    let disk = PathBuf::from(vm_disk_path(state, id)?);
+    let format = if vm.raw_device_path.is_some() { "raw" } else { "qcow2" };
+    Ok(BenchmarkContext {
+        vm_id: Some(vm.id.clone()),
+        arch: vm.arch.clone(),
+        accelerator: select_accelerator(&vm, &host),
+        storage_dir: disk.parent().map(Path::to_path_buf).unwrap_or(context.storage_dir),
+        disk_type: Some(format!("{} {}", state.config_store.disk_interface(id)?, format)),
+        ..context
+    })
+}
+
+/// Open display session for a running VM
+#[tauri::command]
+pub async fn open_display(state: State<'_, AppState>, id: String) -> CommandResult<DisplaySession> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let vm = fetch_vm_or_err(&state.config_store, &id)?;
+    ensure_has_display(&vm)?;
+    if !state.qemu_controller.is_running(&id) {
+        return Err(Error::VmNotRunning(id).into());
+    }
+
+    let mut sessions = state.display_sessions.lock().await;
+    if let Some(existing) = sessions.get_mut(&id) {
+        if existing.status != "connected" {
+            let max_attempts = state.config_store.display_reconnect_max_attempts()?;
+            begin_reconnect(existing, max_attempts, chrono::Utc::now())?;
+        }
+        existing.clipboard_sharing = vm.clipboard_sharing;
+        // Reconnecting invalidates the password the previous viewer saw
+        if existing.password_token.is_some() {
+            let password = rotate_spice_password(&state, &id).await?;
+            set_session_password(existing, Some(password));
+        }
+        attach_display_proxy(&state, existing).await?;
+        set_session_tls(&state.paths, &vm, existing);
+        return Ok(existing.clone());
+    }
+
+    let password = state.spice_passwords.lock().await.get(&id).cloned();
+    let port = vm_display_port(&state, &state.config_store.display_port_range()?, &id);
+    let host = display_host(vm.display_listen_address.as_deref());
+    let mut session =
+        build_display_session(&id, &host, port, &vm.display_mode, "connected", vm.clipboard_sharing, password);
+    session.recording = state.recordings.lock().await.contains_key(&id);
+    attach_display_proxy(&state, &mut session).await?;
+    set_session_tls(&state.paths, &vm, &mut session);
+    sessions.insert(id, session.clone());
+    Ok(session)
+}
+
+/// Replace a running VM's SPICE password through QMP and remember it
+async fn rotate_spice_password(state: &AppState, id: &str) -> CommandResult<String> {
+    let password = generate_spice_password();
+    QmpClient::new(state.paths.qmp_socket(id).display().to_string())
+        .execute(
+            "set_password",
+            Some(serde_json::json!({ "protocol": "spice", "password": &password })),
+        )
+        .await?;
+    state.spice_passwords.lock().await.insert(id.to_string(), password.clone());
+    Ok(password)
+}
+
+/// Get display session by VM ID
+#[tauri::command]
+pub async fn get_display(state: State<'_, AppState>, id: String) -> CommandResult<Option<DisplaySession>> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let is_running = state.qemu_controller.is_running(&id);
+
+    let mut sessions = state.display_sessions.lock().await;
+    if let Some(existing) = sessions.get_mut(&id) {
+        refresh_session_liveness(existing, is_running, chrono::Utc::now());
+        return Ok(Some(existing.clone()));
+    }
+
+    Ok(None)
+}
+
+/// Every display session with up-to-date liveness, or only `vm_id`'s when given
+#[tauri::command]
+pub async fn list_displays(
+    state: State<'_, AppState>,
+    vm_id: Option<String>,
+) -> CommandResult<Vec<DisplaySession>> {
+    if vm_id.as_deref().is_some_and(|id| id.trim().is_empty()) {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let now = chrono::Utc::now();
+    let mut sessions = state.display_sessions.lock().await;
+    let mut listed: Vec<DisplaySession> = sessions
+        .values_mut()
+        .filter(|session| vm_id.as_deref().map_or(true, |id| session.vm_id == id))
+        .map(|session| {
+            refresh_session_liveness(session, state.qemu_controller.is_running(&session.vm_id), now);
+            session.clone()
+        })
+        .collect();
+    listed.sort_by(|a, b| a.vm_id.cmp(&b.vm_id).then_with(|| a.session_id.cmp(&b.session_id)));
+    Ok(listed)
+}
+
+/// Clear a display session's reconnect history so `open_display` may retry it,
+/// including after it was marked "failed"
+#[tauri::command]
+pub async fn reset_display(state: State<'_, AppState>, id: String) -> CommandResult<DisplaySession> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let mut sessions = state.display_sessions.lock().await;
+    let session = sessions.get_mut(&id).ok_or_else(|| {
+        CommandError::new(ErrorCode::NotFound, "display.session.notFound")
+            .with_details(serde_json::json!({ "vmId": id }))
+    })?;
+    reset_reconnects(session);
+    if session.status == "failed" {
+        session.status = "disconnected".to_string();
+    }
+    Ok(session.clone())
+}
+
+/// How a VM's display can be reached from other machines
+#[tauri::command]
+pub async fn get_remote_display(state: State<'_, AppState>, vm_id: String) -> CommandResult<RemoteDisplay> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    Ok(RemoteDisplay {
+        listen_address: record.display_listen_address,
+        tls_port: record.spice_tls_port,
+    })
+}
+
+/// Change how a VM's display can be reached from other machines; applies from its next start.
+/// Listening beyond loopback requires SPICE with password ticketing on.
+#[tauri::command]
+pub async fn set_remote_display(
+    state: State<'_, AppState>,
+    vm_id: String,
+    settings: RemoteDisplay,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let mut record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    let listen_address = settings.listen_address.map(|address| address.trim().to_string());
+    let range = state.config_store.display_port_range()?;
+    let taken_ports: BTreeSet<u16> = state
+        .config_store
+        .list_vms()?
+        .into_iter()
+        .filter(|other| other.id != vm_id)
+        .flat_map(|other| [Some(vm_display_port(&state, &range, &other.id)), other.spice_tls_port])
+        .flatten()
+        .collect();
+    validate_remote_display(
+        &record,
+        vm_display_port(&state, &range, &vm_id),
+        listen_address.as_deref(),
+        settings.tls_port,
+        state.config_store.spice_ticketing_enabled()?,
+        &taken_ports,
+    )?;
+    record.display_listen_address = listen_address;
+    record.spice_tls_port = settings.tls_port;
+    save_vm(&state, &record)?;
+    Ok(())
+}
+
+/// A VM's SPICE compression settings, defaults included
+#[tauri::command]
+pub async fn get_display_settings(state: State<'_, AppState>, vm_id: String) -> CommandResult<SpiceCompression> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    Ok(record.spice_compression.unwrap_or_default())
+}
+
+/// Change a VM's SPICE compression settings; a running VM keeps its old ones until
+/// restarted, which `get_config_diff` reports
+#[tauri::command]
+pub async fn update_display_settings(
+    state: State<'_, AppState>,
+    vm_id: String,
+    compression: SpiceCompression,
+) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if let Some((option, value)) = compression.invalid_option() {
+        return Err(CommandError::validation("compression", "display.compression.invalid")
+            .with_param("option", option)
+            .with_param("value", value));
+    }
+
+    let mut record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    record.spice_compression = Some(compression);
+    save_vm(&state, &record)?;
+    Ok(())
+}
+
+/// Whether the VM boots from a throwaway overlay that discards its changes on stop
+#[tauri::command]
+pub async fn get_vm_ephemeral(state: State<'_, AppState>, vm_id: String) -> CommandResult<bool> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    Ok(fetch_vm_or_err(&state.config_store, &vm_id)?.ephemeral)
+}
+
+/// Turn ephemeral mode on or off; a running VM switches at its next start.
+/// Raw devices and encrypted disks cannot sit under a plain qcow2 overlay.
+#[tauri::command]
+pub async fn set_vm_ephemeral(state: State<'_, AppState>, vm_id: String, ephemeral: bool) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+
+    let mut record = fetch_vm_or_err(&state.config_store, &vm_id)?;
+    if ephemeral && (record.raw_device_path.is_some() || record.encryption_key_ref.is_some()) {
+        return Err(CommandError::validation("ephemeral", "vm.ephemeral.unsupported"));
+    }
+    record.ephemeral = ephemeral;
+    save_vm(&state, &record)?;
+    Ok(())
+}
+
+/// Open a running VM's display in virt-viewer's `remote-viewer`, returning its pid.
+/// A viewer already open for the VM is replaced.
+#[tauri::command]
+pub async fn launch_external_viewer(state: State<'_, AppState>, id: String) -> CommandResult<u32> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let vm = fetch_vm_or_err(&state.config_store, &id)?;
+    ensure_has_display(&vm)?;
+    if !state.qemu_controller.is_running(&id) {
+        return Err(Error::VmNotRunning(id).into());
+    }
+
+    let binary = viewer::find_viewer().ok_or_else(|| {
+        CommandError::new(ErrorCode::NotFound, "viewer.notFound")
+            .with_param("hint", viewer::install_hint())
+            .with_details(serde_json::json!({ "tool": "remote-viewer" }))
+    })?;
+    let connection = viewer::ViewerConnection {
+        protocol: vm.display_mode.clone(),
+        host: display_host(vm.display_listen_address.as_deref()),
+        port: vm_display_port(&state, &state.config_store.display_port_range()?, &id),
+        password: state.spice_passwords.lock().await.get(&id).cloned(),
+        title: vm.name.clone(),
+        fullscreen: state.config_store.viewer_fullscreen_enabled()?,
+    };
+
+    // Each launch gets its own file, so a replaced viewer's cleanup cannot remove the new one's
+    let vv_path = state.paths.viewer_file(&id, &Uuid::new_v4().simple().to_string());
+    let mut viewers = state.external_viewers.lock().await;
+    if let Some(previous) = viewers.remove(&id) {
+        previous.stop();
+    }
+    let launched = viewer::launch(&binary, &vv_path, &connection)?;
+    let pid = launched.pid;
+    viewers.insert(id, launched);
+    Ok(pid)
+}
+
+/// Close display session
+#[tauri::command]
+pub async fn close_display(state: State<'_, AppState>, id: String) -> CommandResult<()> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let mut sessions = state.display_sessions.lock().await;
+    if let Some(existing) = sessions.get_mut(&id) {
+        mark_disconnected(existing, "Display session closed");
+        // Reopening after a deliberate close is a fresh connect, not a retry
+        reset_reconnects(existing);
+    }
+    state.display_proxies.lock().await.remove(&id);
+    if let Some(external) = state.external_viewers.lock().await.remove(&id) {
+        external.stop();
+    }
+    Ok(())
+}
+
+/// Start capturing a running VM's display, `fps` frames a second (default 5), until `stop_recording`
+#[tauri::command]
+pub async fn start_recording(state: State<'_, AppState>, id: String, fps: Option<u32>) -> CommandResult<()> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+    let fps = fps.unwrap_or(recording::DEFAULT_FPS);
+    if fps == 0 || fps > recording::MAX_FPS {
+        return Err(CommandError::validation("fps", "recording.invalidFps").with_param("max", recording::MAX_FPS));
+    }
+
+    let vm = fetch_vm_or_err(&state.config_store, &id)?;
+    ensure_has_display(&vm)?;
+    let qmp_socket = state.qemu_controller.qmp_socket(&id).ok_or_else(|| Error::VmNotRunning(id.clone()))?;
+
+    let mut recordings = state.recordings.lock().await;
+    if recordings.contains_key(&id) {
+        return Err(CommandError::new(ErrorCode::Conflict, "recording.alreadyActive").with_param("vmId", id));
+    }
+    let stem = format!("{}-{}", id, chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    let frame_dir = state.paths.recordings_dir().join(stem).with_extension("frames");
+    let capture = recording::start(frame_dir, fps, move |path: PathBuf| {
+        let qmp = QmpClient::new(qmp_socket.clone());
+        async move {
+            let filename = path.display().to_string();
+            qmp.execute("screendump", Some(serde_json::json!({ "filename": filename }))).await?;
+            Ok(())
+        }
+    })?;
+    recordings.insert(id.clone(), capture);
+    drop(recordings);
+
+    if let Some(session) = state.display_sessions.lock().await.get_mut(&id) {
+        session.recording = true;
+    }
+    tracing::info!(vm_id = %id, fps, "display recording started");
+    Ok(())
+}
+
+/// Stop a VM's recording and save it as an mp4, or as a zip of frames without ffmpeg
+#[tauri::command]
+pub async fn stop_recording(state: State<'_, AppState>, id: String) -> CommandResult<RecordingSummary> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    let capture = state.recordings.lock().await.remove(&id).ok_or_else(|| {
+        CommandError::new(ErrorCode::NotFound, "recording.notActive").with_param("vmId", id.clone())
+    })?;
+    if let Some(session) = state.display_sessions.lock().await.get_mut(&id) {
+        session.recording = false;
+    }
+    Ok(finish_recording(&id, capture).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openutm_core::app::disk_path;
+    use openutm_core::paths::AppPaths;
+    use openutm_core::qemu::Accelerator;
+
+    fn stored_disk_record(store: &ConfigStore) -> VMRecord {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Ubuntu".to_string(),
+            ..VMRecord::default()
+        };
+        store.create_vm(&record).unwrap();
+        record
+    }
+
+    fn test_state(temp_dir: &tempfile::TempDir, qemu_binary: &str) -> AppState {
+        let paths = AppPaths::legacy(temp_dir.path(), temp_dir.path().join("run"));
+        paths.ensure_dirs().unwrap();
+        let store = ConfigStore::new(paths.db_path()).unwrap();
+        AppState::new(store, paths, qemu::QemuController::new(qemu_binary.to_string()))
+    }
+
+    #[test]
+    fn test_parse_page_request() {
+        assert_eq!(parse_page_request(None, None).unwrap(), (DEFAULT_PAGE_SIZE, VmSort::CreatedAt));
+        assert_eq!(parse_page_request(Some(10), Some("name")).unwrap(), (10, VmSort::Name));
+
+        let err = parse_page_request(None, Some("created_at DESC; --")).unwrap_err();
+        assert_eq!(err.details, Some(serde_json::json!({ "field": "sort_by" })));
+        assert!(parse_page_request(Some(0), None).is_err());
+        assert!(parse_page_request(Some(MAX_PAGE_SIZE + 1), None).is_err());
+    }
+
+    #[test]
+    fn test_parse_log_level_rejects_unknown() {
+        assert!(parse_log_level("level", "debug").is_ok());
+        let err = parse_log_level("level_filter", "verbose").unwrap_err();
+        assert_eq!(err.details, Some(serde_json::json!({ "field": "level_filter" })));
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("  Work ").unwrap(), "work");
+        assert_eq!(normalize_tag("home-lab_2").unwrap(), "home-lab_2");
+        assert!(normalize_tag("   ").is_err());
+        assert!(normalize_tag("a,b").is_err());
+        let err = normalize_tag(&"x".repeat(MAX_TAG_LEN + 1)).unwrap_err();
+        assert_eq!(err.details, Some(serde_json::json!({ "field": "tag" })));
+    }
+
+    #[test]
+    fn test_arch_info_drops_accelerator_for_emulated_arches() {
+        let info = |path: &str| QemuInfo {
+            detected: true,
+            path: Some(path.to_string()),
+            version: Some("QEMU emulator version 9.1.0".to_string()),
+            accelerator: Some("KVM".to_string()),
+            host_arch: "x86_64".to_string(),
+            supported_machines: Vec::new(),
+            supported_accels: Vec::new(),
+        };
+
+        let native = arch_info("x86_64".to_string(), info("/usr/bin/qemu-system-x86_64"));
+        assert_eq!(native.binary_path, "/usr/bin/qemu-system-x86_64");
+        assert_eq!(native.version, "QEMU emulator version 9.1.0");
+        assert_eq!(native.accelerator.as_deref(), Some("KVM"));
+
+        let emulated = arch_info("riscv64".to_string(), info("/usr/bin/qemu-system-riscv64"));
+        assert_eq!(emulated.arch, "riscv64");
+        assert_eq!(emulated.accelerator, None);
+    }
+
+    #[test]
+    fn test_throttle_arguments_default_to_unlimited() {
+        let throttle = IoThrottle {
+            write_iops: Some(500),
+            ..Default::default()
+        };
+
+        let args = throttle_arguments("disk0", &throttle);
+        assert_eq!(args["device"], "disk0");
+        assert_eq!(args["iops_wr"], 500);
+        assert_eq!(args["bps_rd"], 0);
+    }
+
+    #[test]
+    fn test_overview_tallies_status_and_memory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let base = stored_disk_record(&store);
+        let statuses = [("running", 4096), ("paused", 1024), ("stopped", 2048), ("error", 512), ("starting", 256)];
+        let records: Vec<VMRecord> = statuses
+            .iter()
+            .map(|(status, memory_mb)| VMRecord {
+                status: status.to_string(),
+                memory_mb: *memory_mb,
+                ..base.clone()
+            })
+            .collect();
+
+        let overview = tally_overview(&records);
+        assert_eq!(
+            overview,
+            VmOverview {
+                total: 5,
+                running: 1,
+                stopped: 1,
+                paused: 1,
+                error: 1,
+                transitioning: 1,
+                allocated_memory_mb: 7936,
+                running_memory_mb: 5120,
+                ..VmOverview::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_overview_reports_unreadable_disks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let record = stored_disk_record(&state.config_store);
+
+        let overview = overview(&state).await.unwrap();
+        assert_eq!(overview.total, 1);
+        assert_eq!(overview.disk_allocated_bytes, 0);
+        assert_eq!(overview.disk_errors.len(), 1);
+        assert_eq!(overview.disk_errors[0].vm_id, record.id);
+    }
+
+    #[test]
+    fn test_edits_ignore_removed_install_media() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let mut record = stored_disk_record(&store);
+        record.install_media_path = Some(temp_dir.path().join("gone.iso").display().to_string());
+        assert!(check_updated_config(&record).is_ok());
+
+        record.cpu_cores = 0;
+        assert_eq!(check_updated_config(&record).unwrap_err().message_key, "validation.cpuCores.tooSmall");
+    }
+
+    #[test]
+    fn test_ephemeral_vm_refuses_snapshots() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+        assert!(snapshot_target(&state, &record.id).is_ok());
+
+        record.ephemeral = true;
+        state.config_store.update_vm(&record).unwrap();
+        let err = snapshot_target(&state, &record.id).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Conflict);
+        assert_eq!(err.message_key, "vm.ephemeral.diskLocked");
+    }
+
+    #[test]
+    fn test_benchmark_context_follows_vm_disk() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+
+        let host = benchmark_context(&state, None).unwrap();
+        assert_eq!(host.vm_id, None);
+        assert_eq!(host.storage_dir, state.disks_dir());
+        assert_eq!(host.disk_type, None);
+
+        let mut record = stored_disk_record(&state.config_store);
+        record.arch = if platform::host_arch() == "aarch64" { "x86_64" } else { "aarch64" }.to_string();
+        state.config_store.update_vm(&record).unwrap();
+        let vm = benchmark_context(&state, Some(&record.id)).unwrap();
+        assert_eq!(vm.vm_id.as_deref(), Some(record.id.as_str()));
+        assert_eq!(vm.arch, record.arch);
+        assert_eq!(vm.accelerator, Accelerator::Tcg);
+        assert_eq!(vm.storage_dir, state.disks_dir());
+        assert_eq!(vm.disk_type.as_deref(), Some("virtio qcow2"));
+
+        let err = benchmark_context(&state, Some("missing")).unwrap_err();
+        assert_eq!(err.code, ErrorCode::VmNotFound);
+    }
+
+    #[test]
+    fn test_snapshot_target_needs_a_qcow2_image() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+
+        let (_, image, qmp_socket) = snapshot_target(&state, &record.id).unwrap();
+        assert_eq!(image, disk_path(&state.paths.disks_dir(), &record.id));
+        assert_eq!(qmp_socket, None);
+
+        record.raw_device_path = Some("/dev/sdb".to_string());
+        state.config_store.update_vm(&record).unwrap();
+        let err = snapshot_target(&state, &record.id).unwrap_err();
+        assert_eq!(err.message_key, "snapshot.rawDevice");
+        assert_eq!(snapshot_target(&state, "missing").unwrap_err().code, ErrorCode::VmNotFound);
+    }
+
+    #[test]
+    fn test_select_vm_fields_trims_to_requested_fields() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let vm = map_record_to_vm(stored_disk_record(&state.config_store));
+
+        let trimmed = select_vm_fields(vm.clone(), Some(&["name".to_string(), "status".to_string()])).unwrap();
+        let mut keys: Vec<&String> = trimmed.as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["id", "name", "status"]);
+        assert!(select_vm_fields(vm, None).unwrap().get("config").is_some());
+    }
+
+    #[test]
+    fn test_accelerator_support() {
+        let lookup = |support: &[AcceleratorSupport], name: &str| {
+            support.iter().find(|accel| accel.name == name).map(|accel| (accel.built_in, accel.usable))
+        };
+        let built_in = vec!["kvm".to_string(), "qtest".to_string(), "tcg".to_string()];
+
+        let support = accelerator_support(&built_in, "kvm", true, false);
+        assert_eq!(lookup(&support, "kvm"), Some((true, true)));
+        assert_eq!(lookup(&support, "tcg"), Some((true, true)));
+        assert_eq!(lookup(&support, "qtest"), Some((true, false)));
+
+        // Host without /dev/kvm, and a cross-arch guest
+        assert_eq!(lookup(&accelerator_support(&built_in, "kvm", false, false), "kvm"), Some((true, false)));
+        assert_eq!(lookup(&accelerator_support(&built_in, "kvm", true, true), "kvm"), Some((true, false)));
+
+        // A build without HVF still reports it, as not built in
+        let support = accelerator_support(&["tcg".to_string()], "hvf", true, false);
+        assert_eq!(lookup(&support, "hvf"), Some((false, false)));
+    }
+
+    #[test]
+    fn test_builtin_profiles_cannot_be_edited() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("config.db")).unwrap();
+
+        let builtin = fetch_profile_or_err(&store, "builtin-windows-11").unwrap();
+        let err = ensure_profile_editable(&builtin).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Conflict);
+        assert_eq!(err.message, "Windows 11 is a built-in profile and cannot be changed");
+
+        let err = fetch_profile_or_err(&store, "missing").unwrap_err();
+        assert_eq!(err.code, ErrorCode::NotFound);
     }
 }
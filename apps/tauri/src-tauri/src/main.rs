@@ -4,127 +4,399 @@
 )]
 
 mod commands;
-mod qemu;
-mod platform;
-mod storage;
-mod config;
-mod error;
-
-pub use error::{Error, Result};
-
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct DisplaySession {
-    pub vm_id: String,
-    pub protocol: String,
-    pub host: String,
-    pub port: u16,
-    pub uri: String,
-    pub status: String,
-    pub reconnect_attempts: u32,
-    pub last_error: Option<String>,
-    pub connected_at: Option<String>,
-}
-
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
-pub struct QemuInfo {
-    pub detected: bool,
-    pub path: Option<String>,
-    pub version: Option<String>,
-    pub accelerator: Option<String>,
-}
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
-pub struct VMConfig {
-    pub name: String,
-    pub memory_mb: u32,
-    pub cpu_cores: u32,
-    pub disk_size_gb: u32,
-    pub os: String,
-    #[serde(default)]
-    pub install_media_path: Option<String>,
-    #[serde(default = "default_boot_order")]
-    pub boot_order: String,
-    #[serde(default = "default_network_type")]
-    pub network_type: String,
-}
+pub use openutm_core::{Error, Result};
 
-fn default_boot_order() -> String {
-    "disk-first".to_string()
-}
+use openutm_core::{app, config, logging, metrics, paths, qemu, single_instance, StartupStatus};
+use tauri::{Emitter, Manager};
 
-fn default_network_type() -> String {
-    "nat".to_string()
-}
+const PROCESS_HEALTH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const DISPLAY_HEALTH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+const MEMORY_POLICY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
-pub struct VM {
-    pub id: String,
-    pub name: String,
-    pub status: VMStatus,
-    pub config: VMConfig,
-}
+/// Emitted with a `VmNotResponding` when a running VM stops answering QMP
+const VM_NOT_RESPONDING_EVENT: &str = "vm-not-responding";
+/// Emitted with a `VmStatusEvent` whenever a VM's stored status changes
+const VM_STATUS_CHANGED_EVENT: &str = "vm-status-changed";
+/// Emitted with a `VmListChanged` when a VM is created, edited or deleted
+const VM_LIST_CHANGED_EVENT: &str = "vm-list-changed";
+/// Emitted with a `ProvisioningJob` as a new VM's disk is queued, made and finished
+const VM_PROVISIONING_EVENT: &str = "vm-provisioning-progress";
+const STORAGE_MIGRATION_EVENT: &str = "storage-migration-progress";
+/// Emitted with a `VmCrashed` when a VM's QEMU process dies outside the app
+const VM_CRASHED_EVENT: &str = "vm-crashed";
+/// Emitted with a `BalloonAdjustment` whenever the memory policy resizes a VM's balloon
+const VM_MEMORY_ADJUSTED_EVENT: &str = "vm:memory-adjusted";
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum VMStatus {
-    Running,
-    Stopped,
-    Paused,
-    Error,
+/// Wraps the command handler so every invoke is counted for the metrics endpoint
+fn counting_invokes<R: tauri::Runtime>(
+    handler: impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        metrics::record_command();
+        handler(invoke)
+    }
 }
 
 fn main() {
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    let data_dir = std::path::PathBuf::from(home).join(".openutm");
-    let storage_dir = data_dir.join("disks");
-    std::fs::create_dir_all(&storage_dir).expect("failed to create storage directory");
-
-    let db_path = data_dir.join("config.db");
-    let config_store = config::ConfigStore::new(db_path).expect("failed to init config db");
-    let disk_manager = storage::DiskManager::new(storage_dir.display().to_string());
-
-    let qemu_path = qemu::detector::find_qemu_binary()
-        .map(|path| path.display().to_string())
-        .unwrap_or_else(|_| {
-            if cfg!(target_arch = "aarch64") {
-                "qemu-system-aarch64".to_string()
-            } else {
-                "qemu-system-x86_64".to_string()
+    let bases = paths::BaseDirs::detect();
+    let platform_paths = paths::AppPaths::resolve();
+    let legacy_dir = paths::legacy_dir(&bases);
+
+    // Keep using ~/.openutm until the user agrees to migrate it
+    let app_paths = match legacy_dir
+        .as_deref()
+        .and_then(|legacy_dir| paths::plan_migration(legacy_dir, &platform_paths))
+    {
+        Some(plan) => paths::AppPaths::legacy(&plan.legacy_dir, platform_paths.runtime_dir.clone()),
+        None => platform_paths.clone(),
+    };
+    app_paths.ensure_dirs().expect("failed to create data directories");
+
+    // Held for the life of the process; the OS releases it if the process dies
+    let socket_path = single_instance::socket_path(&app_paths.runtime_dir);
+    let _instance_lock = match single_instance::acquire(&app_paths.data_dir).expect("failed to acquire instance lock") {
+        single_instance::Instance::Primary(lock) => lock,
+        single_instance::Instance::Secondary { pid } => {
+            if let Err(err) = single_instance::forward(&socket_path, single_instance::FOCUS_REQUEST) {
+                let pid = pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+                eprintln!("OpenUTM is already running{} but could not be focused: {}", pid, err);
             }
-        });
-    let qemu_controller = qemu::QemuController::new(qemu_path);
-
-    let state = commands::CommandState {
-        config_store,
-        disk_manager,
-        storage_dir,
-        qemu_controller: tokio::sync::Mutex::new(qemu_controller),
-        display_sessions: tokio::sync::Mutex::new(std::collections::HashMap::new()),
+            return;
+        }
+    };
+
+    let (config_store, database_recovery) =
+        config::ConfigStore::open_or_recover(app_paths.db_path()).expect("failed to init config db");
+    let log_level = config_store
+        .get_setting(logging::LOG_LEVEL_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|level| logging::parse_level(&level))
+        .unwrap_or(tracing_subscriber::filter::LevelFilter::INFO);
+    let log_handle = match logging::init(&app_paths.log_dir, log_level) {
+        Ok(handle) => Some(handle),
+        Err(err) => {
+            eprintln!("logging disabled: {}", err);
+            None
+        }
+    };
+
+    if let Some(recovery) = &database_recovery {
+        tracing::error!(
+            backup = %recovery.backup_path,
+            reason = %recovery.reason,
+            recovered_rows = recovery.recovered_rows,
+            "config database was corrupt and has been reset"
+        );
+    }
+    let integrity_issues = config_store
+        .integrity_check()
+        .unwrap_or_else(|err| vec![err.to_string()]);
+    if integrity_issues.is_empty() {
+        tracing::info!("config database integrity check passed");
+    }
+    for issue in &integrity_issues {
+        tracing::warn!(issue = %issue, "config database integrity problem");
+    }
+    let corrections = openutm_core::reconcile::reconcile(&config_store, &app_paths).unwrap_or_else(|err| {
+        tracing::warn!(error = %err, "startup reconciliation failed");
+        Vec::new()
+    });
+    if !corrections.is_empty() {
+        tracing::info!(count = corrections.len(), "startup reconciliation made corrections");
+    }
+
+    let configured_qemu = config_store.get_setting(config::QEMU_BINARY_KEY).ok().flatten().filter(|path| {
+        let runnable = qemu::detector::is_runnable_qemu(std::path::Path::new(path));
+        if !runnable {
+            tracing::warn!(path = %path, "configured QEMU binary does not run; detecting one instead");
+        }
+        runnable
+    });
+    let qemu_path = configured_qemu.unwrap_or_else(|| {
+        qemu::detector::find_qemu_binary()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|_| {
+                if cfg!(target_arch = "aarch64") {
+                    "qemu-system-aarch64".to_string()
+                } else {
+                    "qemu-system-x86_64".to_string()
+                }
+            })
+    });
+    let qemu_controller = qemu::QemuController::new(qemu_path).with_log_dir(app_paths.log_dir.clone());
+
+    let state = app::AppState {
+        platform_paths,
+        legacy_dir,
+        log_handle,
+        startup_status: StartupStatus {
+            database_recovery,
+            integrity_issues,
+            corrections,
+        },
+        ..app::AppState::new(config_store, app_paths, qemu_controller)
     };
 
     tauri::Builder::default()
         .manage(state)
-        .invoke_handler(tauri::generate_handler![
+        .setup(move |app| {
+            let focus_handle = app.handle().clone();
+            let listening = single_instance::listen(&socket_path, move |request| {
+                if request != single_instance::FOCUS_REQUEST {
+                    return;
+                }
+                if let Some(window) = focus_handle.get_webview_window("main") {
+                    let _ = window.unminimize();
+                    let _ = window.show();
+                    let _ = window.set_focus();
+                }
+            });
+            if let Err(err) = listening {
+                tracing::warn!(error = %err, "not accepting requests from other instances");
+            }
+
+            let handle = app.handle().clone();
+            let mut ui_events = handle.state::<app::AppState>().ui_events.subscribe();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let emitted = match ui_events.recv().await {
+                        Ok(app::UiEvent::StatusChanged(change)) => handle.emit(VM_STATUS_CHANGED_EVENT, change),
+                        Ok(app::UiEvent::Crashed(crash)) => handle.emit(VM_CRASHED_EVENT, crash),
+                        Ok(app::UiEvent::ListChanged(change)) => handle.emit(VM_LIST_CHANGED_EVENT, change),
+                        Ok(app::UiEvent::Provisioning(job)) => handle.emit(VM_PROVISIONING_EVENT, job),
+                        Ok(app::UiEvent::StorageMigration(progress)) => {
+                            handle.emit(STORAGE_MIGRATION_EVENT, progress)
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                            tracing::warn!(missed, "frontend fell behind on VM events");
+                            continue;
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    };
+                    if let Err(err) = emitted {
+                        tracing::warn!(error = %err, "failed to emit VM event");
+                    }
+                }
+            });
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(PROCESS_HEALTH_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let state = handle.state::<app::AppState>();
+                    app::reconcile_vm_processes(&state).await;
+                }
+            });
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(DISPLAY_HEALTH_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let state = handle.state::<app::AppState>();
+                    app::probe_display_sessions(&state.display_sessions).await;
+                    app::sweep_display_sessions(&state).await;
+                }
+            });
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let state = handle.state::<app::AppState>();
+                    // Re-read every cycle so a changed interval applies without a restart
+                    let interval_secs = state
+                        .config_store
+                        .health_check_interval_secs()
+                        .unwrap_or(config::DEFAULT_HEALTH_CHECK_INTERVAL_SECS);
+                    tokio::time::sleep(std::time::Duration::from_secs(interval_secs)).await;
+                    for unresponsive in app::check_vm_health(&state).await {
+                        if let Err(err) = handle.emit(VM_NOT_RESPONDING_EVENT, &unresponsive) {
+                            tracing::warn!(error = %err, "failed to emit VM not responding event");
+                        }
+                    }
+                }
+            });
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(MEMORY_POLICY_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let state = handle.state::<app::AppState>();
+                    for adjustment in app::apply_memory_policy(&state).await {
+                        if let Err(err) = handle.emit(VM_MEMORY_ADJUSTED_EVENT, &adjustment) {
+                            tracing::warn!(error = %err, "failed to emit memory adjusted event");
+                        }
+                    }
+                }
+            });
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state = handle.state::<app::AppState>();
+                let Ok(Some(addr)) = state.config_store.metrics_listen_address() else {
+                    return;
+                };
+                if let Err(err) = commands::serve_metrics(&state, handle.clone(), Some(addr)).await {
+                    tracing::warn!(%addr, error = %err, "failed to start metrics server");
+                }
+            });
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state = handle.state::<app::AppState>();
+                if !state.config_store.control_socket_enabled().unwrap_or(false) {
+                    return;
+                }
+                if let Err(err) = commands::serve_control(&state, handle.clone(), true).await {
+                    tracing::warn!(error = %err, "failed to start control socket");
+                }
+            });
+            Ok(())
+        })
+        .invoke_handler(counting_invokes(tauri::generate_handler![
             commands::detect_qemu,
+            commands::list_available_architectures,
+            commands::set_qemu_path,
             commands::create_vm,
+            commands::import_ova,
             commands::update_vm,
             commands::pick_install_media,
             commands::set_install_media,
             commands::eject_install_media,
             commands::set_boot_order,
+            commands::boot_once,
             commands::start_vm,
             commands::stop_vm,
+            commands::batch_start_vms,
+            commands::batch_stop_vms,
+            commands::stop_all_vms,
             commands::pause_vm,
             commands::resume_vm,
             commands::list_vms,
+            commands::list_vms_paged,
+            commands::list_vms_after,
+            commands::list_vm_summaries,
             commands::get_vm,
             commands::delete_vm,
+            commands::relocate_vm_disk,
+            commands::migrate_storage,
+            commands::checksum_vm_disk,
+            commands::verify_vm_disk,
+            commands::list_snapshots,
+            commands::create_live_snapshot,
+            commands::restore_snapshot,
+            commands::list_external_snapshots,
+            commands::create_external_snapshot,
+            commands::commit_external_snapshot,
+            commands::list_network_adapters,
+            commands::add_network_adapter,
+            commands::remove_network_adapter,
+            commands::create_group,
+            commands::list_groups,
+            commands::delete_group,
+            commands::add_vm_to_group,
+            commands::remove_vm_from_group,
+            commands::list_vms_in_group,
+            commands::start_group,
+            commands::stop_group,
+            commands::get_vm_dependencies,
+            commands::set_vm_dependencies,
+            commands::add_tag,
+            commands::remove_tag,
+            commands::list_tags,
+            commands::filter_vms_by_tag,
+            commands::set_notes,
+            commands::set_drive_throttle,
+            commands::get_disk_discard,
+            commands::set_disk_discard,
+            commands::get_disk_interface,
+            commands::set_disk_interface,
+            commands::hotplug_disk,
+            commands::remove_hotplugged_disk,
+            commands::send_monitor_command,
+            commands::set_cpu_affinity,
+            commands::list_machine_types,
+            commands::list_cpu_models,
+            commands::list_accelerators,
             commands::get_platform_info,
+            commands::list_vfio_devices,
+            commands::get_host_resources,
+            commands::get_unique_names,
+            commands::set_unique_names,
+            commands::get_spice_ticketing,
+            commands::set_spice_ticketing,
+            commands::get_viewer_fullscreen,
+            commands::set_viewer_fullscreen,
+            commands::get_display_session_grace,
+            commands::set_display_session_grace,
+            commands::get_display_port_range,
+            commands::set_display_port_range,
+            commands::get_health_check_interval,
+            commands::set_health_check_interval,
+            commands::get_health_check_threshold,
+            commands::set_health_check_threshold,
+            commands::get_memory_policy,
+            commands::set_memory_policy,
+            commands::get_vm_memory_exempt,
+            commands::set_vm_memory_exempt,
+            commands::get_memory_backend,
+            commands::set_memory_backend,
+            commands::get_metrics_listen_address,
+            commands::set_metrics_listen_address,
+            commands::get_control_socket,
+            commands::set_control_socket,
+            commands::rotate_control_token,
+            commands::get_display_reconnect_attempts,
+            commands::set_display_reconnect_attempts,
+            commands::get_remote_display,
+            commands::set_remote_display,
+            commands::get_display_settings,
+            commands::update_display_settings,
+            commands::get_vm_ephemeral,
+            commands::set_vm_ephemeral,
+            commands::launch_external_viewer,
+            commands::get_disk_info,
+            commands::get_vm_boot_time,
+            commands::get_config_diff,
+            commands::get_launch_args,
+            commands::get_active_accelerator,
+            commands::list_disk_files,
+            commands::get_startup_status,
+            commands::get_startup_report,
+            commands::get_rate_limits,
+            commands::set_rate_limit,
+            commands::get_message_catalog,
+            commands::set_locale,
+            commands::list_guest_os_defaults,
+            commands::list_profiles,
+            commands::save_profile,
+            commands::delete_profile,
+            commands::create_vm_from_profile,
+            commands::get_data_migration_status,
+            commands::migrate_legacy_data,
+            commands::get_vm_metrics,
+            commands::get_overview,
+            commands::get_log_level,
+            commands::set_log_level,
+            commands::get_app_logs,
+            commands::export_diagnostics,
+            commands::diagnose_acceleration,
+            commands::run_benchmark,
+            commands::get_benchmark_history,
+            commands::get_provisioning_jobs,
+            commands::list_running_vm_details,
             commands::open_display,
             commands::get_display,
+            commands::list_displays,
+            commands::reset_display,
             commands::close_display,
-        ])
+            commands::start_recording,
+            commands::stop_recording,
+        ]))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
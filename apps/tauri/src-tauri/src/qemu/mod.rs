@@ -1,7 +0,0 @@
-pub mod detector;
-pub mod controller;
-pub mod qmp;
-pub mod command;
-
-pub use controller::QemuController;
-pub use command::{QemuCommand, Accelerator, MachineType, DriveConfig, NetdevConfig, DisplayConfig};
@@ -1,399 +0,0 @@
-pub struct QmpClient {
-    pub socket_path: String,
-}
-
-impl QmpClient {
-    pub fn new(socket_path: String) -> Self {
-        Self { socket_path }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::qemu::qmp::*;
-    use std::sync::{Arc, Mutex};
-
-    #[test]
-    fn test_qmp_client_creation() {
-        let client = QmpClient::new("/tmp/qemu.sock".to_string());
-        assert_eq!(client.socket_path, "/tmp/qemu.sock");
-    }
-
-    #[test]
-    fn test_qmp_handshake_structure() {
-        let greeting = serde_json::json!({
-            "QMP": {
-                "version": {
-                    "qemu": "7.0.0",
-                    "package": "7.0.0"
-                },
-                "capabilities": ["oob"]
-            }
-        });
-
-        assert!(greeting["QMP"]["version"]["qemu"].is_string());
-        assert!(greeting["QMP"]["capabilities"].is_array());
-    }
-
-    #[test]
-    fn test_qmp_capabilities_command() {
-        let cmd = serde_json::json!({
-            "execute": "qmp_capabilities",
-            "arguments": {},
-            "id": 1
-        });
-
-        assert_eq!(cmd["execute"], "qmp_capabilities");
-        assert_eq!(cmd["id"], 1);
-    }
-
-    #[test]
-    fn test_query_status_command() {
-        let cmd = serde_json::json!({
-            "execute": "query-status",
-            "arguments": {},
-            "id": 2
-        });
-
-        assert_eq!(cmd["execute"], "query-status");
-    }
-
-    #[test]
-    fn test_system_powerdown_command() {
-        let cmd = serde_json::json!({
-            "execute": "system_powerdown",
-            "arguments": {},
-            "id": 3
-        });
-
-        assert_eq!(cmd["execute"], "system_powerdown");
-    }
-
-    #[test]
-    fn test_stop_command() {
-        let cmd = serde_json::json!({
-            "execute": "stop",
-            "arguments": {},
-            "id": 4
-        });
-
-        assert_eq!(cmd["execute"], "stop");
-    }
-
-    #[test]
-    fn test_cont_command() {
-        let cmd = serde_json::json!({
-            "execute": "cont",
-            "arguments": {},
-            "id": 5
-        });
-
-        assert_eq!(cmd["execute"], "cont");
-    }
-
-    #[test]
-    fn test_quit_command() {
-        let cmd = serde_json::json!({
-            "execute": "quit",
-            "arguments": {},
-            "id": 6
-        });
-
-        assert_eq!(cmd["execute"], "quit");
-    }
-
-    #[test]
-    fn test_query_block_command() {
-        let cmd = serde_json::json!({
-            "execute": "query-block",
-            "arguments": {},
-            "id": 7
-        });
-
-        assert_eq!(cmd["execute"], "query-block");
-    }
-
-    #[test]
-    fn test_blockdev_add_command() {
-        let cmd = serde_json::json!({
-            "execute": "blockdev-add",
-            "arguments": {
-                "driver": "qcow2",
-                "node-name": "drive0",
-                "file": {
-                    "driver": "file",
-                    "filename": "/path/to/disk.qcow2"
-                }
-            },
-            "id": 8
-        });
-
-        assert_eq!(cmd["execute"], "blockdev-add");
-        assert_eq!(cmd["arguments"]["driver"], "qcow2");
-    }
-
-    #[test]
-    fn test_successful_response_parsing() {
-        let response = serde_json::json!({
-            "return": {
-                "running": true,
-                "singlestep": false,
-                "status": "running"
-            },
-            "id": 1
-        });
-
-        assert!(response["return"]["running"].as_bool().unwrap());
-        assert_eq!(response["return"]["status"], "running");
-        assert_eq!(response["id"], 1);
-    }
-
-    #[test]
-    fn test_error_response_parsing() {
-        let response = serde_json::json!({
-            "error": {
-                "class": "GenericError",
-                "desc": "VM is not running"
-            },
-            "id": 1
-        });
-
-        assert_eq!(response["error"]["class"], "GenericError");
-        assert!(response["error"]["desc"].as_str().unwrap().contains("running"));
-    }
-
-    #[test]
-    fn test_event_message_parsing() {
-        let event = serde_json::json!({
-            "event": "SHUTDOWN",
-            "data": {
-                "guest": true
-            },
-            "timestamp": {
-                "seconds": 1234567890,
-                "microseconds": 0
-            }
-        });
-
-        assert_eq!(event["event"], "SHUTDOWN");
-        assert!(event["data"]["guest"].as_bool().unwrap());
-    }
-
-    #[test]
-    fn test_message_id_tracking() {
-        let cmd1 = serde_json::json!({
-            "execute": "query-status",
-            "arguments": {},
-            "id": 1
-        });
-
-        let cmd2 = serde_json::json!({
-            "execute": "query-status",
-            "arguments": {},
-            "id": 2
-        });
-
-        assert_ne!(cmd1["id"], cmd2["id"]);
-        assert_eq!(cmd1["id"], 1);
-        assert_eq!(cmd2["id"], 2);
-    }
-
-    #[test]
-    fn test_command_serialization() {
-        let cmd = serde_json::json!({
-            "execute": "query-status",
-            "arguments": {},
-            "id": 1
-        });
-
-        let serialized = serde_json::to_string(&cmd).unwrap();
-        assert!(serialized.contains("\"execute\":\"query-status\""));
-        assert!(serialized.contains("\"id\":1"));
-    }
-
-    #[test]
-    fn test_event_types() {
-        let event_types = vec![
-            "SHUTDOWN",
-            "STOP",
-            "RESUME",
-            "ERROR",
-            "DEVICE_ADDED",
-        ];
-
-        for event_type in event_types {
-            assert!(!event_type.is_empty());
-        }
-    }
-
-    #[test]
-    fn test_error_codes() {
-        let error_codes = vec![
-            "ECONNREFUSED",
-            "ENOENT",
-            "EACCES",
-            "EPIPE",
-            "ECONNRESET",
-        ];
-
-        for code in error_codes {
-            assert!(!code.is_empty());
-        }
-    }
-
-    #[test]
-    fn test_socket_path_validation() {
-        let valid_path = "/tmp/qemu.sock";
-        assert!(valid_path.starts_with('/'));
-        assert!(valid_path.contains("sock"));
-    }
-
-    #[test]
-    fn test_qmp_response_distinguish() {
-        let response = serde_json::json!({
-            "return": { "status": "running" },
-            "id": 1
-        });
-
-        let event = serde_json::json!({
-            "event": "SHUTDOWN",
-            "data": {}
-        });
-
-        assert!(response.get("return").is_some());
-        assert!(response.get("event").is_none());
-        assert!(event.get("event").is_some());
-        assert!(event.get("return").is_none());
-    }
-
-    #[test]
-    fn test_multiple_event_handlers() {
-        let handlers: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(vec![]));
-        
-        let h1 = handlers.clone();
-        let h1_closure = move |_event: String| {
-            h1.lock().unwrap().push("handler1".to_string());
-        };
-
-        let h2 = handlers.clone();
-        let h2_closure = move |_event: String| {
-            h2.lock().unwrap().push("handler2".to_string());
-        };
-
-        h1_closure("SHUTDOWN".to_string());
-        h2_closure("SHUTDOWN".to_string());
-
-        assert_eq!(handlers.lock().unwrap().len(), 2);
-    }
-
-    #[test]
-    fn test_command_with_arguments() {
-        let cmd = serde_json::json!({
-            "execute": "blockdev-add",
-            "arguments": {
-                "driver": "qcow2",
-                "node-name": "drive0",
-                "file": {
-                    "driver": "file",
-                    "filename": "/path/to/disk.qcow2"
-                }
-            },
-            "id": 1
-        });
-
-        assert!(cmd["arguments"].is_object());
-        assert!(cmd["arguments"]["file"].is_object());
-    }
-
-    #[test]
-    fn test_connection_state_transitions() {
-        let states = vec!["disconnected", "connecting", "connected", "disconnecting"];
-        
-        assert_eq!(states[0], "disconnected");
-        assert_eq!(states[states.len() - 1], "disconnecting");
-        assert!(states.len() == 4);
-    }
-
-    #[test]
-    fn test_socket_error_handling() {
-        let error_messages = vec![
-            "Connection refused",
-            "Socket disconnected",
-            "Write failed",
-            "QMP command timeout",
-        ];
-
-        for msg in error_messages {
-            assert!(!msg.is_empty());
-        }
-    }
-
-    #[test]
-    fn test_response_matching_by_id() {
-        let cmd_id = 42;
-        let response = serde_json::json!({
-            "return": { "running": true },
-            "id": cmd_id
-        });
-
-        assert_eq!(response["id"], cmd_id);
-    }
-
-    #[test]
-    fn test_event_with_timestamp() {
-        let event = serde_json::json!({
-            "event": "SHUTDOWN",
-            "data": { "guest": true },
-            "timestamp": {
-                "seconds": 1234567890,
-                "microseconds": 123456
-            }
-        });
-
-        assert!(event["timestamp"]["seconds"].is_number());
-        assert_eq!(event["timestamp"]["microseconds"], 123456);
-    }
-
-    #[test]
-    fn test_qmp_capabilities_response() {
-        let response = serde_json::json!({
-            "return": {},
-            "id": 1
-        });
-
-        assert!(response["return"].is_object());
-        assert_eq!(response["id"], 1);
-    }
-
-    #[test]
-    fn test_pending_commands_tracking() {
-        let pending = Arc::new(Mutex::new(std::collections::HashMap::new()));
-        
-        pending.lock().unwrap().insert(1, "cmd1");
-        pending.lock().unwrap().insert(2, "cmd2");
-        
-        assert_eq!(pending.lock().unwrap().len(), 2);
-        assert!(pending.lock().unwrap().contains_key(&1));
-        
-        pending.lock().unwrap().remove(&1);
-        assert_eq!(pending.lock().unwrap().len(), 1);
-    }
-
-    #[test]
-    fn test_message_buffer_handling() {
-        let mut buffer = String::new();
-        buffer.push_str(r#"{"id":1,"return":"ok"}"#);
-        buffer.push('\n');
-        buffer.push_str(r#"{"id":2"#);
-
-        let lines: Vec<&str> = buffer.lines().collect();
-        assert_eq!(lines.len(), 2);
-    }
-
-    #[test]
-    fn test_json_parsing_errors() {
-        let invalid_json = "{ invalid }";
-        let result: Result<serde_json::Value, _> = serde_json::from_str(invalid_json);
-        assert!(result.is_err());
-    }
-}
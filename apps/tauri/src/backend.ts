@@ -12,7 +12,7 @@ export interface CreateVmRequest {
   installMediaPath?: string;
   bootOrder: "disk-first" | "cdrom-first";
   networkType: "nat" | "bridge";
-  os: "linux" | "windows" | "macos" | "other";
+  os: "linux" | "windows" | "macos" | "bsd" | "other";
 }
 
 export interface UpdateVmRequest {
@@ -0,0 +1,3525 @@
+//! Runtime state of the VM manager and the work that needs it
+//!
+//! `AppState` is what the Tauri commands, the control socket and the
+//! background tasks share: the config store, running QEMU processes, display
+//! sessions and provisioning jobs. Launching a VM, reconciling crashed
+//! processes, health checks, the memory policy and storage relocation live
+//! here so every frontend runs the same code.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::control;
+use crate::error::{CommandError, CommandResult, Error, ErrorCode};
+use crate::i18n;
+use crate::provisioning::{self, Provisioner};
+use crate::config::{ConfigStore, VMRecord, VmEvent, DEFAULT_HEALTH_CHECK_THRESHOLD, STORAGE_DIR_KEY};
+use crate::guest::GuestOs;
+use crate::qemu::qmp::QmpClient;
+use crate::qemu::{
+    self, Accelerator, DisplayPortRange, HotplugDisk, QemuCommand, SpiceTls, generate_stable_mac, hugepages_needed,
+    MemoryBackend, NetworkConfig, LOOPBACK_LISTEN_ADDRESS, BootDevice,
+};
+use crate::storage::{self, DiskManager, StorageMigrationProgress};
+use crate::logging;
+use crate::memory_policy::{self, BalloonAdjustment, BalloonedVm};
+use crate::metrics;
+use crate::rate_limit::RateLimiter;
+use crate::display_proxy;
+use crate::ova_import;
+use crate::recording;
+use crate::viewer;
+use crate::spice_tls;
+use crate::paths::{self, AppPaths};
+use crate::validation::{self, HostLimits, Severity, ValidationIssue};
+use crate::{
+    platform, BootTimings, ConfigChange, DisplaySession, PlatformInfo, RecordingSummary, StartupStatus, VMConfig,
+    VMStatus, VMWarning, VmCrashed, VmNotResponding, VmStatusEvent, VM, VmChangeKind, VmListChanged,
+    ProvisioningJob,
+};
+
+pub struct AppState {
+    pub config_store: ConfigStore,
+    pub disk_manager: Arc<DiskManager>,
+    /// Disks of new VMs being made in the background
+    pub provisioner: Provisioner,
+    pub paths: AppPaths,
+    /// Where data would live after migrating a legacy `~/.openutm`
+    pub platform_paths: AppPaths,
+    pub legacy_dir: Option<PathBuf>,
+    /// Absent when the log file could not be opened at startup
+    pub log_handle: Option<logging::LevelHandle>,
+    pub startup_status: StartupStatus,
+    pub rate_limiter: RateLimiter,
+    pub qemu_controller: qemu::QemuController,
+    pub display_sessions: tokio::sync::Mutex<HashMap<String, DisplaySession>>,
+    /// Current SPICE password of each running VM that uses ticketing
+    pub spice_passwords: tokio::sync::Mutex<HashMap<String, String>>,
+    /// WebSocket proxy of each VM with an open display, at most one per VM
+    pub display_proxies: tokio::sync::Mutex<HashMap<String, display_proxy::DisplayProxy>>,
+    /// `remote-viewer` launched for each VM, stopped by `close_display`
+    pub external_viewers: tokio::sync::Mutex<HashMap<String, viewer::ExternalViewer>>,
+    /// Display capture in progress for each VM being recorded
+    pub recordings: tokio::sync::Mutex<HashMap<String, recording::Recording>>,
+    pub restart_attempts: tokio::sync::Mutex<HashMap<String, u32>>,
+    /// Boot device for each VM's next start only, dropped once QEMU is launched with it
+    pub boot_once: std::sync::Mutex<HashMap<String, BootDevice>>,
+    /// VMs whose disk is being copied elsewhere; they cannot start until it is done
+    pub moving_disks: std::sync::Mutex<HashSet<String>>,
+    /// Health checks each running VM has missed in a row
+    pub health_check_misses: tokio::sync::Mutex<HashMap<String, u32>>,
+    /// QEMU process sampler shared by `get_vm_metrics` and the metrics server
+    pub process_sampler: metrics::ProcessSampler,
+    /// Prometheus listener, present while enabled in settings
+    pub metrics_server: tokio::sync::Mutex<Option<metrics::MetricsServer>>,
+    /// JSON-RPC control socket, present while enabled in settings
+    pub control_server: tokio::sync::Mutex<Option<control::ControlServer>>,
+    /// Status changes and crashes, forwarded to the frontend by `main.rs`
+    pub ui_events: tokio::sync::broadcast::Sender<UiEvent>,
+}
+
+impl AppState {
+    /// State with nothing running, no legacy data and the rate limits saved in `config_store`
+    pub fn new(config_store: ConfigStore, paths: AppPaths, qemu_controller: qemu::QemuController) -> Self {
+        let rate_limiter = RateLimiter::new().with_settings(&config_store.list_settings().unwrap_or_default());
+        let storage_dir = config_store
+            .get_setting(STORAGE_DIR_KEY)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| paths.disks_dir().display().to_string());
+        let disk_manager = Arc::new(DiskManager::new(storage_dir));
+        Self {
+            provisioner: Provisioner::new(disk_manager.clone(), provisioning::MAX_DISK_JOBS),
+            disk_manager,
+            platform_paths: paths.clone(),
+            paths,
+            legacy_dir: None,
+            log_handle: None,
+            startup_status: StartupStatus::default(),
+            rate_limiter,
+            qemu_controller,
+            config_store,
+            display_sessions: tokio::sync::Mutex::new(HashMap::new()),
+            spice_passwords: tokio::sync::Mutex::new(HashMap::new()),
+            display_proxies: tokio::sync::Mutex::new(HashMap::new()),
+            external_viewers: tokio::sync::Mutex::new(HashMap::new()),
+            recordings: tokio::sync::Mutex::new(HashMap::new()),
+            restart_attempts: tokio::sync::Mutex::new(HashMap::new()),
+            boot_once: std::sync::Mutex::new(HashMap::new()),
+            moving_disks: std::sync::Mutex::new(HashSet::new()),
+            health_check_misses: tokio::sync::Mutex::new(HashMap::new()),
+            process_sampler: metrics::ProcessSampler::new(),
+            metrics_server: tokio::sync::Mutex::new(None),
+            control_server: tokio::sync::Mutex::new(None),
+            ui_events: tokio::sync::broadcast::channel(UI_EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Where VM disks live: the default disks dir unless storage was migrated
+    pub fn disks_dir(&self) -> PathBuf {
+        PathBuf::from(self.disk_manager.storage_dir())
+    }
+}
+
+/// Events a slow frontend may fall behind by before it starts missing some
+const UI_EVENT_CAPACITY: usize = 64;
+
+/// A VM change the frontend hears about without polling
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiEvent {
+    StatusChanged(VmStatusEvent),
+    Crashed(VmCrashed),
+    StorageMigration(StorageMigrationProgress),
+    ListChanged(VmListChanged),
+    Provisioning(ProvisioningJob),
+}
+
+/// Automatic relaunches allowed before a crash-looping VM is left in Error
+const MAX_RESTART_ATTEMPTS: u32 = 3;
+
+/// QEMU object ID of the SPICE password secret
+const SPICE_PASSWORD_SECRET_ID: &str = "spice-password";
+
+/// How long a freshly spawned QEMU gets to open its QMP socket
+const QMP_READY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long after QMP is up a launch keeps listening for the guest's boot event
+const BOOT_EVENT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// `vm_events` kinds making up one launch, in order
+const EVENT_PROCESS_START: &str = "process_start";
+const EVENT_QMP_READY: &str = "qmp_ready";
+const EVENT_BOOT: &str = "boot";
+
+/// `vm_events` kind recorded when a running VM stops answering health checks
+const EVENT_NOT_RESPONDING: &str = "not_responding";
+
+/// How long after launch a QMP timeout is put down to the guest still booting
+const HEALTH_CHECK_BOOT_WINDOW: std::time::Duration = std::time::Duration::from_secs(120);
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// `vm_events` kind recorded when the memory policy resizes a VM's balloon
+const EVENT_MEMORY_ADJUSTED: &str = "memory_adjusted";
+
+/// QMP events taken to mean the guest finished POST
+const BOOT_QMP_EVENTS: [&str; 2] = ["RESET", "POWERUP"];
+
+/// Host facts for validation; the free-space check is skipped when it cannot be read
+pub fn host_limits(disk_manager: Option<&DiskManager>) -> HostLimits {
+    let resources = platform::host_resources();
+    HostLimits {
+        logical_cpus: resources.logical_cpus,
+        total_memory_mb: resources.total_memory_mb,
+        free_disk_bytes: disk_manager.and_then(|manager| manager.free_space().ok()),
+        os: std::env::consts::OS.to_string(),
+        kernel_version: platform::kernel_version(),
+    }
+}
+
+/// Fail with every issue when any rule errors, otherwise surface the warnings.
+/// The host-aware pass then logs memory beyond the host's RAM.
+pub fn check_vm_config(config: &VMConfig, host: &HostLimits) -> CommandResult<Vec<VMWarning>> {
+    let warnings = reject_errors(validation::validate_vm_config(config, host))?;
+    validation::validate_vm_config_extended(config, &platform_info_for(host)).map_err(Error::InvalidConfig)?;
+    Ok(warnings)
+}
+
+/// `host` in the shape `get_platform_info` reports it
+fn platform_info_for(host: &HostLimits) -> PlatformInfo {
+    PlatformInfo {
+        os: host.os.clone(),
+        arch: platform::host_arch(),
+        accelerator: None,
+        cpu_count: host.logical_cpus,
+        total_ram_mb: host.total_memory_mb,
+        // Free space was just checked in bytes; unknown here keeps a rounded figure from failing it again
+        free_disk_mb: 0,
+        qemu_path: None,
+    }
+}
+
+/// Fail on the first error-severity issue; the rest come back as warnings
+pub fn reject_errors(issues: Vec<ValidationIssue>) -> CommandResult<Vec<VMWarning>> {
+    if let Some(first) = issues.iter().find(|issue| issue.severity == Severity::Error) {
+        let key = i18n::validation_key(&first.field, &first.code);
+        let mut error = CommandError::new(ErrorCode::ValidationFailed, &key);
+        for (name, value) in &first.params {
+            error = error.with_param(name, value.clone());
+        }
+        return Err(error.with_details(serde_json::json!({ "field": first.field, "issues": issues })));
+    }
+    Ok(issues
+        .into_iter()
+        .map(|issue| VMWarning {
+            code: issue.code,
+            message: issue.message,
+        })
+        .collect())
+}
+
+pub fn validate_cpu_affinity(cores: &[u32], logical_cpus: u32) -> CommandResult<()> {
+    if let Some(core) = cores.iter().find(|core| **core >= logical_cpus) {
+        return Err(CommandError::validation("cpu_affinity", "vm.cpuAffinity.outOfRange")
+            .with_param("core", *core)
+            .with_param("logicalCpus", logical_cpus));
+    }
+    Ok(())
+}
+
+/// Warn instead of failing when the host cannot honour CPU pinning
+pub fn affinity_warning(cores: &[u32]) -> Option<VMWarning> {
+    if cores.is_empty() || platform::supports_cpu_affinity() {
+        return None;
+    }
+    Some(VMWarning {
+        code: "affinity-unsupported".to_string(),
+        message: "CPU pinning is unsupported on this platform and will be ignored".to_string(),
+    })
+}
+
+/// QEMU secret object id for a VM's LUKS key; only this reference is persisted
+pub fn luks_key_ref(vm_id: &str) -> String {
+    format!("luks-{}", vm_id)
+}
+
+/// Whether a VM whose process exited should be relaunched under its policy
+fn should_restart(policy: &str, exit_code: Option<i32>) -> bool {
+    match policy {
+        "always" => true,
+        "on-failure" => exit_code != Some(0),
+        _ => false,
+    }
+}
+
+pub fn parse_vm_status(status: &str) -> VMStatus {
+    match status.to_ascii_lowercase().as_str() {
+        "running" => VMStatus::Running,
+        "paused" => VMStatus::Paused,
+        "error" => VMStatus::Error,
+        "starting" => VMStatus::Starting,
+        "stopping" => VMStatus::Stopping,
+        "creating" => VMStatus::Creating,
+        _ => VMStatus::Stopped,
+    }
+}
+
+fn status_to_storage(status: &VMStatus) -> &'static str {
+    match status {
+        VMStatus::Running => "running",
+        VMStatus::Paused => "paused",
+        VMStatus::Error => "error",
+        VMStatus::Stopped => "stopped",
+        VMStatus::Starting => "starting",
+        VMStatus::Stopping => "stopping",
+        VMStatus::Creating => "creating",
+    }
+}
+
+/// Access paths for a VM; headless VMs are reached through the QEMU monitor and their serial console
+fn access_methods(display_mode: &str) -> Vec<String> {
+    let mut methods = Vec::new();
+    if display_mode != "none" {
+        methods.push(display_mode.to_string());
+    }
+    methods.push("monitor".to_string());
+    if display_mode == "none" {
+        methods.push("serial".to_string());
+    }
+    methods
+}
+
+pub fn map_record_to_vm(record: VMRecord) -> VM {
+    let name = record.name.clone();
+    let access_methods = access_methods(&record.display_mode);
+    let emulated = platform::is_emulated(&record.arch, &platform::host_arch());
+
+    VM {
+        id: record.id,
+        name: name.clone(),
+        status: parse_vm_status(&record.status),
+        config: VMConfig {
+            name,
+            memory_mb: record.memory_mb,
+            cpu_cores: record.cpu_cores,
+            disk_size_gb: record.disk_size_gb,
+            os: GuestOs::parse(&record.os),
+            install_media_path: record.install_media_path,
+            boot_order: record.boot_order,
+            network_type: record.network_type,
+            nested_virt: record.nested_virt,
+            restart_policy: record.restart_policy,
+            arch: record.arch,
+            cpu_affinity: record.cpu_affinity,
+            priority: record.priority,
+            preallocation: record.preallocation,
+            encrypted: record.encryption_key_ref.is_some(),
+            raw_device_path: record.raw_device_path,
+            notes: record.notes,
+            clipboard_sharing: record.clipboard_sharing,
+            vlan_id: record.vlan_id,
+            display_resolution: record.display_resolution,
+            graphics: record.graphics,
+            machine_type: record.machine_type,
+            display_mode: record.display_mode,
+            boot_menu: record.boot_menu,
+            networks: Vec::new(),
+            performance: record.performance,
+            pointer_device: record.pointer_device.as_deref().and_then(qemu::PointerDevice::parse),
+            rtc: record.rtc.as_deref().and_then(qemu::RtcBase::parse),
+            rng: record.rng,
+        },
+        tags: record.tags,
+        group_ids: Vec::new(),
+        emulated,
+        access_methods,
+        warnings: Vec::new(),
+    }
+}
+
+/// Host facts that influence how a VM is launched
+pub struct HostCapabilities {
+    pub arch: String,
+    pub nested_virt_flag: Option<String>,
+    /// `None` where guest RAM cannot use huge pages
+    pub hugepages: Option<platform::HugePages>,
+}
+
+impl HostCapabilities {
+    pub fn detect() -> Self {
+        Self {
+            arch: platform::host_arch(),
+            nested_virt_flag: platform::nested_virt_flag().map(|flag| flag.to_string()),
+            hugepages: platform::hugepages(),
+        }
+    }
+}
+
+/// Hardware acceleration only works for same-arch guests; fall back to TCG otherwise
+pub fn select_accelerator(vm: &VMRecord, host: &HostCapabilities) -> Accelerator {
+    if platform::is_emulated(&vm.arch, &host.arch) {
+        Accelerator::Tcg
+    } else {
+        default_accelerator()
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn default_accelerator() -> Accelerator {
+    Accelerator::Hvf
+}
+
+#[cfg(target_os = "linux")]
+pub fn default_accelerator() -> Accelerator {
+    Accelerator::Kvm
+}
+
+#[cfg(target_os = "windows")]
+pub fn default_accelerator() -> Accelerator {
+    Accelerator::Whpx
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub fn default_accelerator() -> Accelerator {
+    Accelerator::Tcg
+}
+
+pub fn disk_path(storage_dir: &Path, vm_id: &str) -> String {
+    storage_dir
+        .join(format!("{}.qcow2", vm_id))
+        .display()
+        .to_string()
+}
+
+fn nested_virt_cpu_flag(
+    vm: &VMRecord,
+    accel: &Accelerator,
+    host_flag: Option<&str>,
+) -> CommandResult<Option<String>> {
+    if !vm.nested_virt {
+        return Ok(None);
+    }
+    match (accel, host_flag) {
+        (Accelerator::Tcg, _) | (_, None) => {
+            Err(CommandError::new(ErrorCode::PlatformUnsupported, "vm.nestedVirt.unsupported"))
+        }
+        (_, Some(flag)) => Ok(Some(flag.to_string())),
+    }
+}
+
+/// Fail unless `available` huge pages can hold `memory_mb` of guest RAM
+pub fn check_hugepages(memory_mb: u32, available: u64, page_size_kb: u64) -> CommandResult<()> {
+    let needed = hugepages_needed(memory_mb, page_size_kb);
+    if available < needed {
+        return Err(CommandError::validation("memory_backend", "vm.memoryBackend.hugepages")
+            .with_param("needed", needed)
+            .with_param("available", available)
+            .with_param("pageSizeMb", page_size_kb / 1024));
+    }
+    Ok(())
+}
+
+/// Huge pages must be free when the VM starts, not just reserved
+fn check_memory_backend(vm: &VMRecord, host: &HostCapabilities) -> CommandResult<()> {
+    let Some(backend) = vm.memory_backend.as_deref().and_then(MemoryBackend::parse) else {
+        return Ok(());
+    };
+    match (backend, host.hugepages) {
+        (MemoryBackend::Default, _) => Ok(()),
+        (MemoryBackend::Hugepages, Some(pages)) => check_hugepages(vm.memory_mb, pages.free, pages.page_size_kb),
+        (MemoryBackend::Prealloc, _) if platform::supports_memory_backends() => Ok(()),
+        _ => Err(CommandError::new(ErrorCode::PlatformUnsupported, "vm.memoryBackend.unsupported")),
+    }
+}
+
+/// Where QEMU reads a launch's display credentials from
+#[derive(Debug, Default)]
+struct DisplaySecrets<'a> {
+    /// SPICE password file; `None` turns ticketing off
+    password_file: Option<&'a str>,
+    /// Certificate directory for the VM's SPICE TLS port
+    x509_dir: Option<&'a str>,
+}
+
+fn is_loopback_address(address: &str) -> bool {
+    address.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Host names a VM's SPICE server certificate is issued for
+fn certificate_names(listen_address: Option<&str>) -> Vec<String> {
+    let mut names = vec!["localhost".to_string(), LOOPBACK_LISTEN_ADDRESS.to_string()];
+    match listen_address.and_then(|address| address.parse::<std::net::IpAddr>().ok()) {
+        // A wildcard bind is reached by the host's name
+        Some(ip) if ip.is_unspecified() => names.extend(sysinfo::System::host_name()),
+        Some(ip) if !ip.is_loopback() => names.push(ip.to_string()),
+        _ => {}
+    }
+    names
+}
+
+/// Where a local viewer reaches a display listening on `listen_address`;
+/// loopback still answers for a wildcard bind
+pub fn display_host(listen_address: Option<&str>) -> String {
+    match listen_address.and_then(|address| address.parse::<std::net::IpAddr>().ok()) {
+        Some(ip) if !ip.is_loopback() && !ip.is_unspecified() => ip.to_string(),
+        _ => LOOPBACK_LISTEN_ADDRESS.to_string(),
+    }
+}
+
+/// Check a VM's remote display settings before they are saved. `taken_ports`
+/// are the display and TLS ports of every other VM.
+pub fn validate_remote_display(
+    vm: &VMRecord,
+    display_port: u16,
+    listen_address: Option<&str>,
+    tls_port: Option<u16>,
+    ticketing_enabled: bool,
+    taken_ports: &BTreeSet<u16>,
+) -> CommandResult<()> {
+    if let Some(address) = listen_address {
+        if address.parse::<std::net::IpAddr>().is_err() {
+            return Err(CommandError::validation("listen_address", "display.remote.invalidAddress")
+                .with_param("address", address));
+        }
+        let password_protected = vm.display_mode == "spice" && ticketing_enabled;
+        if !is_loopback_address(address) && !password_protected {
+            return Err(CommandError::validation("listen_address", "display.remote.ticketingRequired"));
+        }
+        // The SPICE password would otherwise cross the network in the clear
+        if !is_loopback_address(address) && tls_port.is_none() {
+            return Err(CommandError::validation("tls_port", "display.remote.tlsRequired"));
+        }
+    }
+    if let Some(port) = tls_port {
+        if vm.display_mode != "spice" {
+            return Err(CommandError::validation("tls_port", "display.tls.spiceOnly"));
+        }
+        if port < 1024 || port == display_port {
+            return Err(CommandError::validation("tls_port", "display.tls.invalidPort").with_param("port", port));
+        }
+        if taken_ports.contains(&port) {
+            return Err(CommandError::new(ErrorCode::Conflict, "display.tls.portInUse")
+                .with_param("port", port)
+                .with_details(serde_json::json!({ "field": "tls_port" })));
+        }
+    }
+    Ok(())
+}
+
+fn build_start_args(
+    vm: &VMRecord,
+    devices: &LaunchDevices,
+    qmp_socket: &str,
+    monitor_socket: &str,
+    secret_file: Option<&str>,
+    display_secrets: &DisplaySecrets,
+    host: &HostCapabilities,
+) -> CommandResult<Vec<String>> {
+    let key_secret = match (&vm.encryption_key_ref, secret_file) {
+        (Some(key_ref), Some(secret_file)) => Some((key_ref.clone(), secret_file)),
+        (Some(_), None) => {
+            return Err(CommandError::validation("passphrase", "vm.start.passphraseRequired"))
+        }
+        (None, _) => None,
+    };
+    let accel = select_accelerator(vm, host);
+    let nested_flag = nested_virt_cpu_flag(vm, &accel, host.nested_virt_flag.as_deref())?;
+    check_memory_backend(vm, host)?;
+    let emulated = platform::is_emulated(&vm.arch, &host.arch);
+
+    let headless = vm.display_mode == "none";
+    let listen_address = vm.display_listen_address.as_deref().unwrap_or(LOOPBACK_LISTEN_ADDRESS);
+    let spice_password_file = display_secrets.password_file.filter(|_| vm.display_mode == "spice");
+    // Ticketing may have been turned off since the address was saved
+    if !headless && !is_loopback_address(listen_address) && spice_password_file.is_none() {
+        return Err(CommandError::validation("listen_address", "display.remote.ticketingRequired"));
+    }
+    let tls_served = vm.spice_tls_port.is_some() && display_secrets.x509_dir.is_some();
+    if !headless && !is_loopback_address(listen_address) && !tls_served {
+        return Err(CommandError::validation("tls_port", "display.remote.tlsRequired"));
+    }
+
+    let disk = &devices.disk;
+    let mut command = QemuCommand::for_vm(vm, &disk.path, accel)?
+        .networks(&devices.networks)
+        .drive_interface("disk0", &disk.interface)
+        .display_port(devices.display_port)
+        .monitor_socket(monitor_socket);
+    if disk.discard {
+        command = command.discard("disk0");
+    }
+    for disk in &devices.hotplugged {
+        command = command.hotplug_disk(disk.clone());
+    }
+    if let Some(path) = devices.serial_socket.as_deref().filter(|_| headless) {
+        command = command.serial_socket(path);
+    }
+    if devices.balloon {
+        command = command.balloon();
+    }
+    if let Some((key_ref, secret_file)) = &key_secret {
+        command = command.object(&format!("secret,id={},file={}", key_ref, qemu::option_value(secret_file)));
+    }
+    // The password reaches QEMU through a file so it never appears in the process list
+    if let Some(password_file) = spice_password_file {
+        command = command
+            .object(&format!("secret,id={},file={}", SPICE_PASSWORD_SECRET_ID, qemu::option_value(password_file)))
+            .spice_password_secret(SPICE_PASSWORD_SECRET_ID);
+    }
+    if let (Some(port), Some(x509_dir)) = (vm.spice_tls_port, display_secrets.x509_dir) {
+        command = command.spice_tls(SpiceTls { port, x509_dir: x509_dir.to_string() });
+    }
+    if emulated {
+        command = command.cpu_model("max").accel_option("thread", "multi");
+    }
+    if let Some(flag) = &nested_flag {
+        command = command.nested_virt(flag);
+    }
+    if let Some(device) = devices.boot_once {
+        command = command.boot_once(device);
+    }
+    command.validate().map_err(Error::InvalidConfig)?;
+
+    Ok(command.build_vm_args(vm, qmp_socket))
+}
+
+/// The VM's own disk as it is attached at launch
+struct PrimaryDisk {
+    path: String,
+    discard: bool,
+    interface: String,
+}
+
+/// Everything stored outside the VM record that is attached at launch
+struct LaunchDevices {
+    disk: PrimaryDisk,
+    /// Empty keeps the single NIC from `network_type`
+    networks: Vec<NetworkConfig>,
+    /// Disks hot-added while it ran, under the ids `hotplug_disk` gave them
+    hotplugged: Vec<HotplugDisk>,
+    /// Where a headless VM's serial console is served
+    serial_socket: Option<String>,
+    /// A balloon device for the memory policy to resize
+    balloon: bool,
+    /// One-off boot device from `boot_once`
+    boot_once: Option<BootDevice>,
+    /// Host port of the VM's display server, from the configured range
+    display_port: u16,
+}
+
+/// An ephemeral VM's disk is its throwaway overlay, which `spawn_vm` creates
+fn launch_devices(state: &AppState, vm: &VMRecord) -> CommandResult<LaunchDevices> {
+    let vm_id = vm.id.as_str();
+    let path = if vm.ephemeral {
+        ephemeral_overlay(state, vm_id).display().to_string()
+    } else {
+        vm_disk_path(state, vm_id)?
+    };
+    Ok(LaunchDevices {
+        disk: PrimaryDisk {
+            path,
+            discard: state.config_store.disk_discard(vm_id)?,
+            interface: state.config_store.disk_interface(vm_id)?,
+        },
+        networks: state.config_store.list_networks(vm_id)?,
+        hotplugged: hotplugged_disks(&state.config_store, vm_id)?,
+        serial_socket: Some(state.paths.serial_socket(vm_id).display().to_string()),
+        balloon: !vm.memory_policy_exempt && !hugepage_backed(vm) && state.config_store.memory_policy()?.enabled,
+        boot_once: state.boot_once.lock().unwrap_or_else(|e| e.into_inner()).get(vm_id).copied(),
+        display_port: state.config_store.display_port_range()?.port_for(vm_id),
+    })
+}
+
+/// Stored hot-added disks, named as when they were plugged in so they can be unplugged
+fn hotplugged_disks(config_store: &ConfigStore, vm_id: &str) -> CommandResult<Vec<HotplugDisk>> {
+    let mut disks = Vec::new();
+    for drive in config_store.hotplugged_drives(vm_id)? {
+        let Ok(drive_id) = Uuid::parse_str(&drive.id) else {
+            tracing::warn!(vm_id = %vm_id, drive = %drive.id, "skipping hot-added disk with an invalid id");
+            continue;
+        };
+        let (device_id, node_name) = hotplug_names(&drive_id);
+        disks.push(HotplugDisk { device_id, node_name, path: drive.path, format: drive.format });
+    }
+    Ok(disks)
+}
+
+/// The display port a running VM was launched with; the range may have changed since.
+/// A stopped VM gets the port it would launch with now.
+pub fn vm_display_port(state: &AppState, range: &DisplayPortRange, vm_id: &str) -> u16 {
+    state
+        .qemu_controller
+        .launch_command(vm_id)
+        .and_then(|(_, args)| qemu::display_port_from_args(&args))
+        .unwrap_or_else(|| range.port_for(vm_id))
+}
+
+/// Huge pages stay reserved for the VM whatever its balloon gives back, so
+/// the memory policy leaves such VMs alone
+fn hugepage_backed(vm: &VMRecord) -> bool {
+    matches!(vm.memory_backend.as_deref().and_then(MemoryBackend::parse), Some(MemoryBackend::Hugepages))
+}
+
+/// Path of a VM's disk image: where it was relocated to, else the disks dir
+pub fn vm_disk_path(state: &AppState, vm_id: &str) -> CommandResult<String> {
+    Ok(state
+        .config_store
+        .disk_location(vm_id)?
+        .unwrap_or_else(|| disk_path(&state.disks_dir(), vm_id)))
+}
+
+/// Refuse to touch a VM's disk while `provisioning` is still making it
+pub fn ensure_provisioned(state: &AppState, vm: &VMRecord) -> CommandResult<()> {
+    if state.provisioner.is_provisioning(&vm.id) {
+        return Err(CommandError::new(ErrorCode::VmProvisioning, "vm.provisioning")
+            .with_param("name", vm.name.as_str())
+            .with_details(serde_json::json!({ "vmId": vm.id })));
+    }
+    Ok(())
+}
+
+/// Refuse to start a VM while its disk is being moved
+fn ensure_disk_settled(state: &AppState, vm: &VMRecord) -> CommandResult<()> {
+    if state.moving_disks.lock().unwrap_or_else(|e| e.into_inner()).contains(&vm.id) {
+        return Err(CommandError::new(ErrorCode::Conflict, "vm.start.diskMoving")
+            .with_param("name", vm.name.as_str())
+            .with_details(serde_json::json!({ "vmId": vm.id })));
+    }
+    Ok(())
+}
+
+/// Marks disks as moving until dropped, so their VMs stay stopped meanwhile
+struct DiskMoveGuard<'a> {
+    moving: &'a std::sync::Mutex<HashSet<String>>,
+    vm_ids: Vec<String>,
+}
+
+impl<'a> DiskMoveGuard<'a> {
+    /// `None` if another move already holds one of the disks
+    fn acquire(state: &'a AppState, vm_ids: Vec<String>) -> Option<Self> {
+        let mut moving = state.moving_disks.lock().unwrap_or_else(|e| e.into_inner());
+        if vm_ids.iter().any(|id| moving.contains(id)) {
+            return None;
+        }
+        moving.extend(vm_ids.iter().cloned());
+        Some(Self { moving: &state.moving_disks, vm_ids })
+    }
+}
+
+impl Drop for DiskMoveGuard<'_> {
+    fn drop(&mut self) {
+        let mut moving = self.moving.lock().unwrap_or_else(|e| e.into_inner());
+        for id in &self.vm_ids {
+            moving.remove(id);
+        }
+    }
+}
+
+pub fn fetch_vm_or_err(config_store: &ConfigStore, id: &str) -> CommandResult<VMRecord> {
+    config_store
+        .get_vm(id)?
+        .ok_or_else(|| CommandError::vm_not_found(id))
+}
+
+/// Attempts at a fresh id before an import gives up on a colliding disk path
+const IMPORT_ID_ATTEMPTS: usize = 3;
+
+/// Everything already claiming the disk path an import of `vm_id` would use:
+/// records with that id or pointing at that path, and a file left on disk.
+fn import_disk_conflicts(config_store: &ConfigStore, disks_dir: &Path, vm_id: &str) -> CommandResult<Vec<String>> {
+    let target = disk_path(disks_dir, vm_id);
+    let mut conflicts: Vec<String> = config_store
+        .list_vms()?
+        .into_iter()
+        .filter(|record| record.id == vm_id || record.raw_device_path.as_deref() == Some(target.as_str()))
+        .map(|record| format!("vm:{}", record.id))
+        .collect();
+    if Path::new(&target).exists() {
+        conflicts.push(format!("file:{}", target));
+    }
+    Ok(conflicts)
+}
+
+/// Pick the id an imported VM is registered under, keeping `candidate` unless its
+/// disk path is already taken. Aborts with a Conflict listing the clashes if fresh
+/// ids keep colliding.
+fn claim_import_id(config_store: &ConfigStore, disks_dir: &Path, candidate: &str) -> CommandResult<String> {
+    let mut vm_id = candidate.to_string();
+    let mut conflicts = import_disk_conflicts(config_store, disks_dir, &vm_id)?;
+    for _ in 0..IMPORT_ID_ATTEMPTS {
+        if conflicts.is_empty() {
+            if vm_id != candidate {
+                tracing::warn!("Import id {} collides with an existing disk; using {}", candidate, vm_id);
+            }
+            return Ok(vm_id);
+        }
+        vm_id = Uuid::new_v4().to_string();
+        conflicts = import_disk_conflicts(config_store, disks_dir, &vm_id)?;
+    }
+    if conflicts.is_empty() {
+        return Ok(vm_id);
+    }
+    Err(CommandError::new(ErrorCode::Conflict, "import.diskConflict")
+        .with_param("path", disk_path(disks_dir, &vm_id))
+        .with_details(serde_json::json!({ "conflicts": conflicts })))
+}
+
+/// Reject `name` if unique names are enabled and another VM already has it.
+/// `current_name` is the VM's own name when renaming, which is not a clash.
+pub fn ensure_unique_name(
+    config_store: &ConfigStore,
+    name: &str,
+    current_name: Option<&str>,
+) -> CommandResult<()> {
+    if !config_store.unique_names_enabled()? {
+        return Ok(());
+    }
+    if current_name.is_some_and(|current| current.trim().eq_ignore_ascii_case(name.trim())) {
+        return Ok(());
+    }
+    if config_store.name_exists(name)? {
+        return Err(CommandError::new(ErrorCode::Conflict, "vm.name.taken")
+            .with_param("name", name.trim())
+            .with_details(serde_json::json!({ "field": "name" })));
+    }
+    Ok(())
+}
+
+/// Grow the disk image, then persist the record; a failed resize leaves the DB untouched
+pub async fn apply_disk_resize(
+    config_store: &ConfigStore,
+    record: &mut VMRecord,
+    new_size_gb: u32,
+    resize: impl std::future::Future<Output = crate::Result<()>>,
+) -> CommandResult<()> {
+    if record.raw_device_path.is_some() {
+        return Err(CommandError::validation("disk_size_gb", "disk.resize.rawDevice"));
+    }
+    if record.encryption_key_ref.is_some() {
+        return Err(CommandError::validation("disk_size_gb", "disk.resize.encrypted"));
+    }
+    if new_size_gb <= record.disk_size_gb {
+        return Err(CommandError::validation("disk_size_gb", "disk.resize.shrink")
+            .with_param("currentGb", record.disk_size_gb));
+    }
+
+    resize.await?;
+    record.disk_size_gb = new_size_gb;
+    config_store.update_vm(record)?;
+    Ok(())
+}
+
+pub fn update_vm_status(state: &AppState, vm_id: &str, status: VMStatus) -> CommandResult<()> {
+    let mut record = fetch_vm_or_err(&state.config_store, vm_id)?;
+    let new_status = status_to_storage(&status);
+    let old_status = std::mem::replace(&mut record.status, new_status.to_string());
+    state.config_store.update_vm(&record)?;
+    if old_status != new_status {
+        // Fails only when nothing is subscribed, e.g. before the window exists
+        let _ = state.ui_events.send(UiEvent::StatusChanged(VmStatusEvent {
+            vm_id: vm_id.to_string(),
+            old_status,
+            new_status: new_status.to_string(),
+        }));
+    }
+    Ok(())
+}
+
+/// Tell the frontend a VM was created, edited or removed
+pub fn notify_vm_list_changed(state: &AppState, vm_id: &str, change: VmChangeKind) {
+    // Fails only when nothing is subscribed, e.g. before the window exists
+    let _ = state.ui_events.send(UiEvent::ListChanged(VmListChanged { vm_id: vm_id.to_string(), change }));
+}
+
+/// Persist an edited VM record and tell the frontend about it
+pub fn save_vm(state: &AppState, record: &VMRecord) -> CommandResult<()> {
+    state.config_store.update_vm(record)?;
+    notify_vm_list_changed(state, &record.id, VmChangeKind::Updated);
+    Ok(())
+}
+
+pub fn build_display_session(
+    vm_id: &str,
+    host: &str,
+    port: u16,
+    protocol: &str,
+    status: &str,
+    clipboard_sharing: bool,
+    password: Option<String>,
+) -> DisplaySession {
+    let mut session = DisplaySession {
+        session_id: Uuid::new_v4().to_string(),
+        vm_id: vm_id.to_string(),
+        protocol: protocol.to_string(),
+        host: host.to_string(),
+        port,
+        uri: String::new(),
+        status: status.to_string(),
+        reconnect_attempts: 0,
+        next_retry_at: None,
+        last_error: None,
+        connected_at: None,
+        disconnected_at: None,
+        seconds_since_connected: None,
+        clipboard_sharing,
+        password_token: None,
+        ws_uri: None,
+        tls_port: None,
+        ca_cert_path: None,
+        recording: false,
+    };
+    set_session_password(&mut session, password);
+    if status == "connected" {
+        mark_connected(&mut session);
+    }
+    session
+}
+
+/// Record that the session is (again) connected as of now
+fn mark_connected(session: &mut DisplaySession) {
+    session.status = "connected".to_string();
+    session.last_error = None;
+    session.connected_at = Some(chrono::Utc::now().to_rfc3339());
+    session.disconnected_at = None;
+}
+
+/// Delay before the first reconnect of a session may be retried; doubles per attempt
+pub const DISPLAY_RECONNECT_BASE_DELAY_MS: u64 = 1_000;
+pub const DISPLAY_RECONNECT_MAX_DELAY_MS: u64 = 60_000;
+
+/// How long after reconnect number `attempts` the next one is allowed
+pub fn reconnect_delay_ms(attempts: u32) -> u64 {
+    let doublings = attempts.saturating_sub(1).min(16);
+    (DISPLAY_RECONNECT_BASE_DELAY_MS << doublings).min(DISPLAY_RECONNECT_MAX_DELAY_MS)
+}
+
+/// Reconnect a session that is not connected, enforcing the backoff schedule.
+/// Once `max_attempts` reconnects are used up the session is "failed" until `reset_display`.
+pub fn begin_reconnect(
+    session: &mut DisplaySession,
+    max_attempts: u32,
+    now: chrono::DateTime<chrono::Utc>,
+) -> CommandResult<()> {
+    let failed = |session: &DisplaySession| {
+        CommandError::new(ErrorCode::Conflict, "display.reconnect.failed")
+            .with_param("attempts", session.reconnect_attempts)
+            .with_details(serde_json::json!({ "vmId": session.vm_id }))
+    };
+    if session.status == "failed" {
+        return Err(failed(session));
+    }
+
+    let next_retry_at = session
+        .next_retry_at
+        .as_deref()
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok());
+    if let Some(next_retry_at) = next_retry_at {
+        let wait_ms = next_retry_at.signed_duration_since(now).num_milliseconds();
+        if wait_ms > 0 {
+            return Err(CommandError::new(ErrorCode::RateLimited, "display.reconnect.tooSoon")
+                .with_param("retryAfterMs", wait_ms)
+                .with_details(serde_json::json!({ "vmId": session.vm_id, "retryAfterMs": wait_ms })));
+        }
+    }
+
+    if session.reconnect_attempts >= max_attempts {
+        tracing::warn!(vm_id = %session.vm_id, attempts = max_attempts, "giving up on display reconnects");
+        session.status = "failed".to_string();
+        session.last_error = Some(format!("Gave up after {} reconnect attempts", session.reconnect_attempts));
+        session.next_retry_at = None;
+        session.ws_uri = None;
+        return Err(failed(session));
+    }
+
+    session.reconnect_attempts += 1;
+    let delay = chrono::Duration::milliseconds(reconnect_delay_ms(session.reconnect_attempts) as i64);
+    session.next_retry_at = Some((now + delay).to_rfc3339());
+    mark_connected(session);
+    Ok(())
+}
+
+/// Forget past reconnects, e.g. once the VM has been restarted
+pub fn reset_reconnects(session: &mut DisplaySession) {
+    session.reconnect_attempts = 0;
+    session.next_retry_at = None;
+}
+
+/// Record that the session lost its display; `disconnected_at` keeps the first time this happened
+pub fn mark_disconnected(session: &mut DisplaySession, reason: &str) {
+    if session.status != "disconnected" {
+        session.disconnected_at = Some(chrono::Utc::now().to_rfc3339());
+    }
+    session.status = "disconnected".to_string();
+    session.last_error = Some(reason.to_string());
+    session.ws_uri = None;
+}
+
+/// Bring a session's liveness fields up to date before handing it out
+pub fn refresh_session_liveness(session: &mut DisplaySession, is_running: bool, now: chrono::DateTime<chrono::Utc>) {
+    if !is_running && session.status != "disconnected" {
+        mark_disconnected(session, "VM not running");
+    }
+    session.seconds_since_connected = seconds_since_connected(session.connected_at.as_deref(), now);
+}
+
+/// VM ids of sessions to drop: the VM no longer exists, or it is stopped and the
+/// session has been disconnected for at least `grace_secs`
+fn stale_display_sessions(
+    sessions: &HashMap<String, DisplaySession>,
+    vm_exists: impl Fn(&str) -> bool,
+    is_running: impl Fn(&str) -> bool,
+    grace_secs: u64,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<String> {
+    sessions
+        .iter()
+        .filter(|(vm_id, session)| {
+            if !vm_exists(vm_id) {
+                return true;
+            }
+            let disconnected_for = seconds_since_connected(session.disconnected_at.as_deref(), now);
+            !is_running(vm_id)
+                && session.status == "disconnected"
+                && disconnected_for.is_some_and(|secs| secs >= grace_secs)
+        })
+        .map(|(vm_id, _)| vm_id.clone())
+        .collect()
+}
+
+/// Drop display sessions (with their proxies and external viewers) that
+/// `stale_display_sessions` selects, catching any close path that was missed
+pub async fn sweep_display_sessions(state: &AppState) {
+    let grace_secs = match state.config_store.display_session_grace_secs() {
+        Ok(secs) => secs,
+        Err(err) => {
+            tracing::error!(error = %err, "failed to read display session grace period");
+            return;
+        }
+    };
+
+    let now = chrono::Utc::now();
+    let mut sessions = state.display_sessions.lock().await;
+    for (vm_id, session) in sessions.iter_mut() {
+        refresh_session_liveness(session, state.qemu_controller.is_running(vm_id), now);
+    }
+    // A lookup error keeps the session; the next sweep tries again
+    let vm_exists = |vm_id: &str| !matches!(state.config_store.get_vm(vm_id), Ok(None));
+    let is_running = |vm_id: &str| state.qemu_controller.is_running(vm_id);
+    let stale = stale_display_sessions(&sessions, vm_exists, is_running, grace_secs, now);
+    if stale.is_empty() {
+        return;
+    }
+
+    let mut proxies = state.display_proxies.lock().await;
+    let mut viewers = state.external_viewers.lock().await;
+    for vm_id in stale {
+        if let Some(session) = sessions.remove(&vm_id) {
+            tracing::info!(vm_id = %vm_id, session_id = %session.session_id, "swept display session");
+        }
+        proxies.remove(&vm_id);
+        if let Some(external) = viewers.remove(&vm_id) {
+            external.stop();
+        }
+    }
+}
+
+/// Whole seconds from `connected_at` to `now`; `None` if never connected
+fn seconds_since_connected(connected_at: Option<&str>, now: chrono::DateTime<chrono::Utc>) -> Option<u64> {
+    let connected_at = chrono::DateTime::parse_from_rfc3339(connected_at?).ok()?;
+    Some(now.signed_duration_since(connected_at).num_seconds().max(0) as u64)
+}
+
+/// How long a health probe waits for the display port to accept
+const DISPLAY_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Check that the display port of every connected session still accepts TCP
+/// connections, flipping the session to "error" when it does not.
+/// `open_display` recovers such sessions.
+pub async fn probe_display_sessions(sessions: &tokio::sync::Mutex<HashMap<String, DisplaySession>>) {
+    let targets: Vec<(String, String, u16)> = sessions
+        .lock()
+        .await
+        .values()
+        .filter(|session| session.status == "connected")
+        .map(|session| (session.vm_id.clone(), session.host.clone(), session.port))
+        .collect();
+
+    for (vm_id, host, port) in targets {
+        let connect = tokio::net::TcpStream::connect((host.as_str(), port));
+        let probe = tokio::time::timeout(DISPLAY_PROBE_TIMEOUT, connect).await;
+        let failure = match probe {
+            Ok(Ok(_)) => continue,
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+
+        let mut sessions = sessions.lock().await;
+        // The session may have been closed or moved to another port while probing
+        let still_connected = sessions
+            .get_mut(&vm_id)
+            .filter(|session| session.status == "connected" && session.port == port);
+        if let Some(session) = still_connected {
+            tracing::warn!(vm_id = %vm_id, port, error = %failure, "display port not accepting connections");
+            session.status = "error".to_string();
+            session.last_error = Some(format!("Display port {} not accepting connections: {}", port, failure));
+        }
+    }
+}
+
+/// Point the session (and its URI) at a new SPICE password
+pub fn set_session_password(session: &mut DisplaySession, password: Option<String>) {
+    let authority = match session.host.parse::<std::net::IpAddr>() {
+        Ok(ip) => std::net::SocketAddr::new(ip, session.port).to_string(),
+        Err(_) => format!("{}:{}", session.host, session.port),
+    };
+    session.uri = match &password {
+        Some(password) => format!("{}://{}?password={}", session.protocol, authority, password),
+        None => format!("{}://{}", session.protocol, authority),
+    };
+    session.password_token = password;
+}
+
+/// Random per-session SPICE password
+pub fn generate_spice_password() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// Bridge the session's display port to a WebSocket, reusing the VM's proxy if it has one
+pub async fn attach_display_proxy(state: &AppState, session: &mut DisplaySession) -> CommandResult<()> {
+    let host = session
+        .host
+        .parse()
+        .unwrap_or(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST));
+    let target = std::net::SocketAddr::new(host, session.port);
+    let mut proxies = state.display_proxies.lock().await;
+    session.ws_uri = Some(display_proxy::ensure_proxy(&mut proxies, &session.vm_id, target).await?);
+    Ok(())
+}
+
+/// Point remote viewers at the VM's SPICE TLS port and the CA to trust
+pub fn set_session_tls(paths: &AppPaths, vm: &VMRecord, session: &mut DisplaySession) {
+    let ca_cert = paths.spice_tls_dir().join(&vm.id).join(spice_tls::CA_CERT_FILE);
+    // Certificates are created at launch, so a port saved since then is not served yet
+    session.tls_port = vm.spice_tls_port.filter(|_| vm.display_mode == "spice" && ca_cert.exists());
+    session.ca_cert_path = session.tls_port.map(|_| ca_cert.display().to_string());
+}
+
+/// Spawn QEMU for a stored VM and mark it running
+#[tracing::instrument(skip_all, fields(vm_id = %id))]
+pub async fn launch_vm(state: &AppState, id: &str, passphrase: Option<&str>) -> CommandResult<()> {
+    let mut vm_record = fetch_vm_or_err(&state.config_store, id)?;
+    // VMs created before MACs were persisted get theirs on first start
+    if vm_record.mac_address.is_none() {
+        let mac = generate_stable_mac(id);
+        state.config_store.set_vm_mac(id, &mac)?;
+        vm_record.mac_address = Some(mac);
+    }
+    if state.qemu_controller.is_running(id) {
+        return Err(Error::VmAlreadyRunning(id.to_string()).into());
+    }
+    ensure_provisioned(state, &vm_record)?;
+    ensure_disk_settled(state, &vm_record)?;
+
+    let started = spawn_vm(state, &vm_record, passphrase);
+    run_transition(state, id, VMStatus::Starting, VMStatus::Running, VMStatus::Stopped, started).await?;
+
+    let mut sessions = state.display_sessions.lock().await;
+    if vm_record.display_mode == "none" {
+        // A session left over from before the VM went headless
+        sessions.remove(id);
+    } else if let Some(existing) = sessions.get_mut(id) {
+        reset_reconnects(existing);
+        mark_connected(existing);
+        // The relaunched QEMU has a fresh password
+        set_session_password(existing, state.spice_passwords.lock().await.get(id).cloned());
+    }
+    Ok(())
+}
+
+/// The arguments `spawn_vm` would start the VM with now, without writing any launch files
+pub fn pending_start_args(state: &AppState, vm: &VMRecord) -> CommandResult<Vec<String>> {
+    let id = vm.id.as_str();
+    let secret_file = vm
+        .encryption_key_ref
+        .as_ref()
+        .map(|_| state.paths.secret_file(id).display().to_string());
+    let password_file = (vm.display_mode == "spice" && state.config_store.spice_ticketing_enabled()?)
+        .then(|| state.paths.spice_password_file(id).display().to_string());
+    let x509_dir = vm
+        .spice_tls_port
+        .filter(|_| vm.display_mode == "spice")
+        .map(|_| state.paths.spice_tls_dir().join(id).display().to_string());
+
+    build_start_args(
+        vm,
+        &launch_devices(state, vm)?,
+        &state.paths.qmp_socket(id).display().to_string(),
+        &state.paths.monitor_socket(id).display().to_string(),
+        secret_file.as_deref(),
+        &DisplaySecrets {
+            password_file: password_file.as_deref(),
+            x509_dir: x509_dir.as_deref(),
+        },
+        &HostCapabilities::detect(),
+    )
+}
+
+/// Settings a running VM picks up without a restart; `-name` only labels the process
+pub const LIVE_SETTINGS: [&str; 1] = ["name"];
+
+/// The setting a QEMU option belongs to; `None` for per-launch plumbing such as sockets and secrets
+pub fn arg_setting(flag: &str, value: Option<&str>) -> Option<&'static str> {
+    let value = value.unwrap_or_default();
+    let setting = match flag {
+        "-name" => "name",
+        "-m" => "memoryMb",
+        "-smp" => "cpuCores",
+        "-machine" | "-accel" => "machine",
+        "-cpu" => "cpu",
+        "-drive" if value.contains("media=cdrom") => "installMedia",
+        "-drive" => "disk",
+        "-netdev" => "network",
+        "-device" if value.starts_with("virtio-net") => "network",
+        "-device" if value.starts_with("virtio-rng") => "rng",
+        "-device" | "-chardev" if value.contains("vdagent") || value.starts_with("virtio-serial") => "clipboardSharing",
+        "-spice" | "-vnc" | "-display" => "display",
+        "-device" | "-vga" => "devices",
+        "-boot" => "boot",
+        "-no-reboot" => "restartPolicy",
+        "-rtc" => "rtc",
+        "-qmp" | "-monitor" | "-object" => return None,
+        _ => "other",
+    };
+    Some(setting)
+}
+
+/// Group QEMU arguments by the setting they come from
+pub fn settings_from_args(args: &[String]) -> BTreeMap<&'static str, Vec<String>> {
+    let mut settings: BTreeMap<&'static str, Vec<String>> = BTreeMap::new();
+    let mut args = args.iter().peekable();
+    while let Some(flag) = args.next() {
+        let value = args.next_if(|next| !next.starts_with('-'));
+        if let Some(setting) = arg_setting(flag, value.map(String::as_str)) {
+            settings.entry(setting).or_default().push(match value {
+                Some(value) => format!("{} {}", flag, value),
+                None => flag.clone(),
+            });
+        }
+    }
+    settings
+}
+
+/// Settings whose arguments differ between the running process and the next start
+pub fn config_changes(applied: &[String], pending: &[String]) -> Vec<ConfigChange> {
+    let applied = settings_from_args(applied);
+    let pending = settings_from_args(pending);
+    let fields: BTreeSet<&'static str> = applied.keys().chain(pending.keys()).copied().collect();
+    fields
+        .into_iter()
+        .filter(|field| applied.get(field) != pending.get(field))
+        .map(|field| ConfigChange {
+            field: field.to_string(),
+            applied: applied.get(field).map(|args| args.join(" ")),
+            pending: pending.get(field).map(|args| args.join(" ")),
+            requires_restart: !LIVE_SETTINGS.contains(&field),
+        })
+        .collect()
+}
+
+/// Accelerator named by `-accel`, without its properties
+pub fn launch_accelerator(args: &[String]) -> Option<String> {
+    let position = args.iter().position(|arg| arg == "-accel")?;
+    let value = args.get(position + 1)?;
+    Some(value.split(',').next().unwrap_or_default().to_string())
+}
+
+/// The accelerator a VM runs on and whether it is hardware virtualization.
+/// QEMU exits rather than fall back when HVF or WHPX cannot start, so only KVM
+/// needs `kvm_enabled` from `query-kvm` to confirm it.
+pub fn active_accelerator(requested: Option<&str>, kvm_enabled: bool) -> (String, bool) {
+    match requested {
+        Some("kvm") if kvm_enabled => ("kvm".to_string(), true),
+        Some("kvm") | None => ("tcg".to_string(), false),
+        Some(accel @ ("hvf" | "whpx")) => (accel.to_string(), true),
+        Some(other) => (other.to_string(), false),
+    }
+}
+
+/// Hold `during` in the DB while `work` runs, then record `on_success` or `on_failure`
+pub async fn run_transition(
+    state: &AppState,
+    vm_id: &str,
+    during: VMStatus,
+    on_success: VMStatus,
+    on_failure: VMStatus,
+    work: impl std::future::Future<Output = CommandResult<()>>,
+) -> CommandResult<()> {
+    update_vm_status(state, vm_id, during)?;
+    match work.await {
+        Ok(()) => update_vm_status(state, vm_id, on_success),
+        Err(err) => {
+            if let Err(status_err) = update_vm_status(state, vm_id, on_failure) {
+                tracing::error!(vm_id = %vm_id, error = %status_err, "failed to record VM status");
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Launch QEMU and wait until its QMP socket is connectable
+async fn spawn_vm(state: &AppState, vm_record: &VMRecord, passphrase: Option<&str>) -> CommandResult<()> {
+    let id = vm_record.id.as_str();
+    if let Some(path) = &vm_record.raw_device_path {
+        tracing::warn!(vm_id = %id, device = %path, "starting VM with raw device passthrough");
+    }
+    let qmp_socket = state.paths.qmp_socket(id).display().to_string();
+    let monitor_socket = state.paths.monitor_socket(id).display().to_string();
+    let devices = launch_devices(state, vm_record)?;
+    if vm_record.ephemeral {
+        // A fresh overlay each launch, so the guest always boots from the untouched disk
+        state
+            .disk_manager
+            .create_overlay_at(&vm_disk_path(state, id)?, &devices.disk.path)
+            .await?;
+    }
+
+    let secret_file = match (&vm_record.encryption_key_ref, passphrase) {
+        (Some(_), Some(passphrase)) => {
+            let path = state.paths.secret_file(id);
+            if let Err(err) = storage::write_secret_file(&path, passphrase) {
+                remove_secret_file(state, id);
+                remove_ephemeral_overlay(state, id);
+                return Err(err.into());
+            }
+            Some(path.display().to_string())
+        }
+        _ => None,
+    };
+
+    let tls_certificates = match vm_record.spice_tls_port.filter(|_| vm_record.display_mode == "spice") {
+        Some(_) => match spice_tls::ensure_vm_certificates(
+            &state.paths.spice_tls_dir(),
+            id,
+            &certificate_names(vm_record.display_listen_address.as_deref()),
+        ) {
+            Ok(certificates) => Some(certificates),
+            Err(err) => {
+                remove_secret_file(state, id);
+                remove_ephemeral_overlay(state, id);
+                return Err(err.into());
+            }
+        },
+        None => None,
+    };
+    let x509_dir = tls_certificates.map(|certificates| certificates.x509_dir.display().to_string());
+
+    // QEMU reads the password file once at startup; it is deleted as soon as QMP is up
+    let spice_password = (vm_record.display_mode == "spice" && state.config_store.spice_ticketing_enabled()?)
+        .then(generate_spice_password);
+    let spice_password_file = match &spice_password {
+        Some(password) => {
+            let path = state.paths.spice_password_file(id);
+            if let Err(err) = storage::write_secret_file(&path, password) {
+                let _ = std::fs::remove_file(&path);
+                remove_secret_file(state, id);
+                remove_ephemeral_overlay(state, id);
+                return Err(err.into());
+            }
+            Some(path)
+        }
+        None => None,
+    };
+    let remove_spice_password_file = || {
+        if let Some(path) = &spice_password_file {
+            let _ = std::fs::remove_file(path);
+        }
+    };
+
+    let password_file = spice_password_file.as_ref().map(|path| path.display().to_string());
+    let display_secrets = DisplaySecrets {
+        password_file: password_file.as_deref(),
+        x509_dir: x509_dir.as_deref(),
+    };
+    let args = match build_start_args(
+        vm_record,
+        &devices,
+        &qmp_socket,
+        &monitor_socket,
+        secret_file.as_deref(),
+        &display_secrets,
+        &HostCapabilities::detect(),
+    ) {
+        Ok(args) => args,
+        Err(err) => {
+            remove_secret_file(state, id);
+            remove_ephemeral_overlay(state, id);
+            remove_spice_password_file();
+            return Err(err);
+        }
+    };
+
+    if let Some(vlan_id) = vm_record.vlan_id {
+        let tap = qemu::command::vlan_tap_name(id);
+        if let Err(err) = platform::ensure_vlan_interface(vlan_id, &tap, qemu::command::vm_net_multiqueue(vm_record)) {
+            remove_secret_file(state, id);
+            remove_ephemeral_overlay(state, id);
+            remove_spice_password_file();
+            return Err(err.into());
+        }
+    }
+
+    let controller = &state.qemu_controller;
+    let binary = qemu::detector::binary_for_arch(&controller.qemu_path(), &vm_record.arch);
+    let pid = match controller
+        .start_vm_with_binary(&binary, id, args, Some(qmp_socket.clone()), Some(monitor_socket))
+        .await
+    {
+        Ok(pid) => pid,
+        Err(err) => {
+            remove_secret_file(state, id);
+            remove_ephemeral_overlay(state, id);
+            remove_spice_password_file();
+            return Err(err.into());
+        }
+    };
+    record_vm_event(&state.config_store, id, EVENT_PROCESS_START);
+    // Consumed by this launch; later starts use the saved boot order again
+    state.boot_once.lock().unwrap_or_else(|e| e.into_inner()).remove(id);
+
+    if !vm_record.cpu_affinity.is_empty() {
+        if let Err(err) = platform::set_process_affinity(pid, &vm_record.cpu_affinity) {
+            tracing::warn!(vm_id = %id, error = %err, "failed to apply CPU affinity");
+        }
+    }
+    if vm_record.priority != 0 {
+        if let Err(err) = platform::set_process_priority(pid, vm_record.priority) {
+            tracing::warn!(vm_id = %id, error = %err, "failed to apply process priority");
+        }
+    }
+
+    let ready = QmpClient::new(qmp_socket.clone()).wait_until_ready(QMP_READY_TIMEOUT).await;
+    // QEMU has read the passphrase by the time QMP answers, so it does not stay on disk while the VM runs
+    remove_secret_file(state, id);
+    remove_spice_password_file();
+    if let Err(err) = ready {
+        tracing::error!(vm_id = %id, error = %err, "QMP socket never became ready");
+        let _ = state.qemu_controller.stop_vm(id).await;
+        remove_ephemeral_overlay(state, id);
+        return Err(err.into());
+    }
+    record_vm_event(&state.config_store, id, EVENT_QMP_READY);
+    watch_boot_event(state.config_store.clone(), id.to_string(), qmp_socket);
+
+    let mut passwords = state.spice_passwords.lock().await;
+    match spice_password {
+        Some(password) => passwords.insert(id.to_string(), password),
+        None => passwords.remove(id),
+    };
+    Ok(())
+}
+
+pub fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+/// Timestamp a lifecycle event; failing to record it never fails the caller
+fn record_vm_event(config_store: &ConfigStore, vm_id: &str, kind: &str) {
+    if let Err(err) = config_store.record_vm_event(vm_id, kind, now_ms()) {
+        tracing::warn!(vm_id = %vm_id, kind, error = %err, "failed to record VM event");
+    }
+}
+
+/// Record the guest's first boot event in the background, if it arrives in time
+fn watch_boot_event(config_store: ConfigStore, vm_id: String, qmp_socket: String) {
+    tokio::spawn(async move {
+        match QmpClient::new(qmp_socket)
+            .wait_for_event(&BOOT_QMP_EVENTS, BOOT_EVENT_TIMEOUT)
+            .await
+        {
+            Ok(_) => record_vm_event(&config_store, &vm_id, EVENT_BOOT),
+            Err(err) => tracing::debug!(vm_id = %vm_id, error = %err, "no boot event observed"),
+        }
+    });
+}
+
+/// Timings of the last launch in `events` (oldest first): its process start,
+/// the QMP-ready that followed, and the boot event after that if any.
+/// `None` if no launch got as far as QMP.
+pub fn boot_timings(events: &[VmEvent]) -> Option<BootTimings> {
+    let start = events.iter().rposition(|event| event.kind == EVENT_PROCESS_START)?;
+    let mut launch = events[start + 1..].iter();
+    let qmp_ready = launch.find(|event| event.kind == EVENT_QMP_READY)?;
+    let boot_event = launch.find(|event| event.kind == EVENT_BOOT);
+    Some(BootTimings {
+        process_start_ms: events[start].at_ms,
+        qmp_ready_ms: qmp_ready.at_ms,
+        boot_event_ms: boot_event.map(|event| event.at_ms),
+    })
+}
+
+/// Drop the passphrase file written for an encrypted VM's last launch
+pub fn remove_secret_file(state: &AppState, id: &str) {
+    let _ = std::fs::remove_file(state.paths.secret_file(id));
+}
+
+/// Overlay an ephemeral VM writes to, in the current storage dir
+fn ephemeral_overlay(state: &AppState, id: &str) -> PathBuf {
+    paths::ephemeral_overlay(&state.disks_dir(), id)
+}
+
+/// Discard the overlay an ephemeral VM's last launch wrote to, and with it the guest's changes
+pub fn remove_ephemeral_overlay(state: &AppState, id: &str) {
+    let _ = std::fs::remove_file(ephemeral_overlay(state, id));
+}
+
+/// Handle VMs whose QEMU process died outside the app: relaunch them per
+/// their restart policy, or mark them as errored.
+pub async fn reconcile_vm_processes(state: &AppState) {
+    let exited = {
+        let controller = &state.qemu_controller;
+        controller
+            .sync_status()
+            .into_iter()
+            .filter(|(_, alive)| !alive)
+            .filter_map(|(vm_id, _)| controller.take_exit(&vm_id).map(|exit| (vm_id, exit)))
+            .collect::<Vec<_>>()
+    };
+
+    for (vm_id, exit) in exited {
+        let exit_code = exit.code;
+        tracing::warn!(vm_id = %vm_id, pid = exit.pid, exit_code = ?exit_code, "QEMU process exited unexpectedly");
+        remove_secret_file(state, &vm_id);
+        remove_ephemeral_overlay(state, &vm_id);
+        let _ = state.ui_events.send(UiEvent::Crashed(VmCrashed { vm_id: vm_id.clone(), pid: exit.pid, exit_code }));
+
+        let policy = fetch_vm_or_err(&state.config_store, &vm_id)
+            .map(|record| record.restart_policy)
+            .unwrap_or_default();
+        if should_restart(&policy, exit_code) {
+            let attempts = {
+                let mut restart_attempts = state.restart_attempts.lock().await;
+                let attempts = restart_attempts.entry(vm_id.clone()).or_insert(0);
+                *attempts += 1;
+                *attempts
+            };
+
+            if attempts <= MAX_RESTART_ATTEMPTS {
+                tracing::info!(vm_id = %vm_id, attempt = attempts, "restarting VM");
+                // Encrypted VMs need the user's passphrase and fail here
+                match launch_vm(state, &vm_id, None).await {
+                    Ok(()) => continue,
+                    Err(err) => tracing::error!(vm_id = %vm_id, error = %err, "failed to restart VM"),
+                }
+            } else {
+                tracing::error!(vm_id = %vm_id, "restart limit reached");
+            }
+        }
+
+        if let Err(err) = update_vm_status(state, &vm_id, VMStatus::Error) {
+            tracing::error!(vm_id = %vm_id, error = %err, "failed to record VM status");
+        }
+
+        state.spice_passwords.lock().await.remove(&vm_id);
+        state.display_proxies.lock().await.remove(&vm_id);
+        force_stop_recording(state, &vm_id).await;
+        let mut sessions = state.display_sessions.lock().await;
+        if let Some(existing) = sessions.get_mut(&vm_id) {
+            mark_disconnected(existing, "VM process exited");
+            existing.recording = false;
+        }
+    }
+}
+
+/// What a health check changed about a VM
+#[derive(Debug, PartialEq)]
+enum HealthTransition {
+    Unchanged,
+    /// Missed its `threshold`th check in a row
+    NotResponding,
+    /// Answered again after having been marked not responding
+    Recovered,
+}
+
+/// Whether a failed health check should count as a miss; QMP timeouts while
+/// the VM is still in its boot window are expected and ignored
+fn counts_as_miss(error: &Error, uptime: std::time::Duration) -> bool {
+    !(matches!(error, Error::QmpTimeout(_)) && uptime < HEALTH_CHECK_BOOT_WINDOW)
+}
+
+/// Count a health check result against `vm_id`'s run of misses
+fn record_health_check(
+    misses: &mut HashMap<String, u32>,
+    vm_id: &str,
+    responded: bool,
+    threshold: u32,
+) -> HealthTransition {
+    if responded {
+        return match misses.remove(vm_id) {
+            Some(missed) if missed >= threshold => HealthTransition::Recovered,
+            _ => HealthTransition::Unchanged,
+        };
+    }
+    let missed = misses.entry(vm_id.to_string()).or_insert(0);
+    *missed += 1;
+    if *missed == threshold {
+        HealthTransition::NotResponding
+    } else {
+        HealthTransition::Unchanged
+    }
+}
+
+/// Ping every running VM with QMP `query-status` and mark the ones that
+/// stopped answering as errored. A live process alone misses wedged guests.
+/// Returns the VMs newly found not responding.
+pub async fn check_vm_health(state: &AppState) -> Vec<VmNotResponding> {
+    let threshold = state.config_store.health_check_failure_threshold().unwrap_or_else(|err| {
+        tracing::warn!(error = %err, "failed to read health check threshold");
+        DEFAULT_HEALTH_CHECK_THRESHOLD
+    });
+    let running = state.qemu_controller.get_running_vm_details();
+    state
+        .health_check_misses
+        .lock()
+        .await
+        .retain(|vm_id, _| running.iter().any(|info| &info.vm_id == vm_id));
+
+    let mut not_responding = Vec::new();
+    for info in running {
+        let vm_id = info.vm_id;
+        let Ok(record) = fetch_vm_or_err(&state.config_store, &vm_id) else { continue };
+        // Launching VMs may not have QMP up yet and stopping ones are going away anyway
+        if matches!(record.status.as_str(), "starting" | "stopping") {
+            continue;
+        }
+        let Some(qmp_socket) = info.qmp_socket else { continue };
+        let status = match QmpClient::new(qmp_socket).execute("query-status", None).await {
+            Ok(status) => Some(status),
+            Err(err) if !counts_as_miss(&err, info.started_at.elapsed().unwrap_or_default()) => continue,
+            Err(_) => None,
+        };
+
+        let transition = {
+            let mut misses = state.health_check_misses.lock().await;
+            record_health_check(&mut misses, &vm_id, status.is_some(), threshold)
+        };
+        match transition {
+            HealthTransition::Unchanged => {}
+            HealthTransition::NotResponding => {
+                tracing::warn!(vm_id = %vm_id, missed_checks = threshold, "VM is not responding");
+                if let Err(err) = update_vm_status(state, &vm_id, VMStatus::Error) {
+                    tracing::error!(vm_id = %vm_id, error = %err, "failed to record VM status");
+                }
+                record_vm_event(&state.config_store, &vm_id, EVENT_NOT_RESPONDING);
+                not_responding.push(VmNotResponding { vm_id, missed_checks: threshold });
+            }
+            HealthTransition::Recovered => {
+                tracing::info!(vm_id = %vm_id, "VM is responding again");
+                let paused = status.as_ref().and_then(|status| status["status"].as_str()) == Some("paused");
+                let recovered = if paused { VMStatus::Paused } else { VMStatus::Running };
+                if let Err(err) = update_vm_status(state, &vm_id, recovered) {
+                    tracing::error!(vm_id = %vm_id, error = %err, "failed to record VM status");
+                }
+            }
+        }
+    }
+    not_responding
+}
+
+/// Resize the balloons of running VMs to match host memory pressure, per the
+/// memory policy. VMs started without a balloon device are left alone.
+pub async fn apply_memory_policy(state: &AppState) -> Vec<BalloonAdjustment> {
+    let policy = match state.config_store.memory_policy() {
+        Ok(policy) => policy,
+        Err(err) => {
+            tracing::warn!(error = %err, "failed to read memory policy");
+            return Vec::new();
+        }
+    };
+    if !policy.enabled {
+        return Vec::new();
+    }
+
+    let mut vms = Vec::new();
+    for vm_id in state.qemu_controller.get_running_vms() {
+        let Ok(record) = fetch_vm_or_err(&state.config_store, &vm_id) else { continue };
+        if record.status != "running" || hugepage_backed(&record) {
+            continue;
+        }
+        let Some(qmp_socket) = state.qemu_controller.qmp_socket(&vm_id) else { continue };
+        let Ok(actual) = QmpClient::new(qmp_socket).query_balloon().await else { continue };
+        vms.push(BalloonedVm {
+            vm_id,
+            allocated_mb: record.memory_mb,
+            current_mb: (actual / BYTES_PER_MB) as u32,
+            exempt: record.memory_policy_exempt,
+        });
+    }
+    if vms.is_empty() {
+        return Vec::new();
+    }
+
+    let free_mb = platform::host_resources().available_memory_mb;
+    let mut applied = Vec::new();
+    for adjustment in memory_policy::balloon_targets(&policy, free_mb, &vms) {
+        let vm_id = adjustment.vm_id.as_str();
+        let Some(qmp_socket) = state.qemu_controller.qmp_socket(vm_id) else { continue };
+        let target = u64::from(adjustment.target_mb) * BYTES_PER_MB;
+        match QmpClient::new(qmp_socket).set_balloon(target).await {
+            Ok(()) => {
+                tracing::info!(
+                    vm_id = %vm_id,
+                    previous_mb = adjustment.previous_mb,
+                    target_mb = adjustment.target_mb,
+                    free_mb,
+                    "memory policy resized balloon"
+                );
+                record_vm_event(&state.config_store, vm_id, EVENT_MEMORY_ADJUSTED);
+                applied.push(adjustment);
+            }
+            Err(err) => tracing::warn!(vm_id = %vm_id, error = %err, "failed to resize balloon"),
+        }
+    }
+    applied
+}
+/// Headless VMs have no display to open; point the caller at the other access methods
+pub fn ensure_has_display(vm: &VMRecord) -> CommandResult<()> {
+    if vm.display_mode != "none" {
+        return Ok(());
+    }
+    Err(CommandError::new(ErrorCode::Conflict, "display.headless")
+        .with_param("vmId", vm.id.clone())
+        .with_details(serde_json::json!({ "accessMethods": access_methods(&vm.display_mode) })))
+}
+
+/// Give an adapter without a MAC one derived from the VM and adapter ids, so
+/// it keeps the same address across launches and differs between VMs
+pub fn with_stable_mac(vm_id: &str, mut network: NetworkConfig) -> NetworkConfig {
+    if network.mac.is_none() {
+        network.mac = Some(generate_stable_mac(&format!("{}:{}", vm_id, network.id)));
+    }
+    network
+}
+
+/// QEMU device id and block node name for a hot-added drive. Both must start
+/// with a letter, and node names are limited to 31 characters.
+pub fn hotplug_names(drive_id: &Uuid) -> (String, String) {
+    let simple = drive_id.simple().to_string();
+    (format!("disk-{}", simple), format!("node-{}", &simple[..24]))
+}
+
+pub fn set_boot_once(state: &AppState, id: &str, device: &str) -> CommandResult<()> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+    let device = BootDevice::parse(device)
+        .ok_or_else(|| CommandError::validation("device", "vm.bootOnce.invalid").with_param("device", device))?;
+    let record = fetch_vm_or_err(&state.config_store, id)?;
+    if device == BootDevice::Cdrom && record.install_media_path.is_none() {
+        return Err(CommandError::validation("device", "vm.bootOnce.noMedia"));
+    }
+    state
+        .boot_once
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(record.id, device);
+    Ok(())
+}
+
+/// Refuse disk operations on an ephemeral VM: a running one writes to its
+/// throwaway overlay, not the image these commands would act on
+pub fn reject_ephemeral(record: &VMRecord) -> CommandResult<()> {
+    if record.ephemeral {
+        return Err(CommandError::new(ErrorCode::Conflict, "vm.ephemeral.diskLocked")
+            .with_details(serde_json::json!({ "field": "vm_id" })));
+    }
+    Ok(())
+}
+
+/// Move a stopped VM's disk image into `new_storage_dir`, e.g. onto a volume with more space
+pub async fn relocate_vm_disk(state: &AppState, vm_id: &str, new_storage_dir: &str) -> CommandResult<()> {
+    if vm_id.trim().is_empty() {
+        return Err(CommandError::validation("vm_id", "vm.id.empty"));
+    }
+    if new_storage_dir.trim().is_empty() {
+        return Err(CommandError::validation("new_storage_dir", "disk.relocate.dirEmpty"));
+    }
+
+    let record = fetch_vm_or_err(&state.config_store, vm_id)?;
+    if record.raw_device_path.is_some() {
+        return Err(CommandError::validation("vm_id", "disk.relocate.rawDevice"));
+    }
+    ensure_provisioned(state, &record)?;
+    reject_ephemeral(&record)?;
+    // Held before the running check, so a start cannot slip in between
+    let _moving = DiskMoveGuard::acquire(state, vec![vm_id.to_string()]).ok_or_else(|| {
+        CommandError::new(ErrorCode::Conflict, "disk.relocate.inProgress")
+            .with_details(serde_json::json!({ "field": "vm_id" }))
+    })?;
+    if state.qemu_controller.is_running(vm_id) {
+        return Err(CommandError::new(ErrorCode::Conflict, "disk.relocate.vmRunning")
+            .with_details(serde_json::json!({ "field": "vm_id" })));
+    }
+    // Overlays record their backing file by path, so the chain would break
+    if !state.config_store.external_snapshots(vm_id)?.is_empty() {
+        return Err(CommandError::validation("vm_id", "disk.relocate.externalSnapshots"));
+    }
+
+    let source = PathBuf::from(vm_disk_path(state, vm_id)?);
+    let destination = PathBuf::from(disk_path(&PathBuf::from(new_storage_dir.trim()), vm_id));
+    // Copying and hashing a large image takes a while, so keep it off the async runtime
+    let config_store = state.config_store.clone();
+    let (id, from, to) = (vm_id.to_string(), source.clone(), destination.clone());
+    tokio::task::spawn_blocking(move || relocate_disk_file(&config_store, &id, &from, &to))
+        .await
+        .map_err(|e| CommandError::new(ErrorCode::Internal, "error.internal").with_param("detail", e.to_string()))??;
+    state.disk_manager.invalidate_info(vm_id);
+    tracing::info!(vm_id = %vm_id, from = %source.display(), to = %destination.display(), "disk relocated");
+    Ok(())
+}
+
+/// Copy the image (rename cannot cross filesystems), verify it, record the new
+/// path, then delete the original. A failed step undoes the ones before it.
+fn relocate_disk_file(config_store: &ConfigStore, vm_id: &str, source: &Path, destination: &Path) -> CommandResult<()> {
+    if destination == source {
+        return Err(CommandError::validation("new_storage_dir", "disk.relocate.samePath"));
+    }
+    if destination.exists() {
+        return Err(CommandError::new(ErrorCode::Conflict, "disk.relocate.targetExists")
+            .with_param("path", destination.display().to_string())
+            .with_details(serde_json::json!({ "field": "new_storage_dir" })));
+    }
+    if let Some(dir) = destination.parent() {
+        std::fs::create_dir_all(dir).map_err(Error::from)?;
+    }
+
+    storage::copy_verified(source, destination)?;
+    if let Err(err) = config_store.set_disk_location(vm_id, &destination.display().to_string()) {
+        let _ = std::fs::remove_file(destination);
+        return Err(err.into());
+    }
+    if let Err(err) = std::fs::remove_file(source) {
+        // Keep using the original rather than leave two copies around
+        if config_store.set_disk_location(vm_id, &source.display().to_string()).is_ok() {
+            let _ = std::fs::remove_file(destination);
+        }
+        return Err(Error::from(err).into());
+    }
+    // The saved checksum still describes the moved image
+    let saved_checksum = storage::checksum_path(&source.display().to_string());
+    if saved_checksum.exists() {
+        let moved_checksum = storage::checksum_path(&destination.display().to_string());
+        if std::fs::copy(&saved_checksum, moved_checksum).is_ok() {
+            let _ = std::fs::remove_file(saved_checksum);
+        }
+    }
+    Ok(())
+}
+
+pub async fn relocate_storage(state: &AppState, new_dir: &str) -> CommandResult<String> {
+    let new_dir = new_dir.trim();
+    if new_dir.is_empty() {
+        return Err(CommandError::validation("new_dir", "storage.migrate.dirEmpty"));
+    }
+    // Every disk is about to move; held before the running check, so no VM can start meanwhile
+    let vm_ids = state.config_store.list_vms()?.into_iter().map(|vm| vm.id).collect();
+    let _moving = DiskMoveGuard::acquire(state, vm_ids)
+        .ok_or_else(|| CommandError::new(ErrorCode::Conflict, "storage.migrate.inProgress"))?;
+    let running = state
+        .qemu_controller
+        .sync_status()
+        .into_iter()
+        .any(|(_, alive)| alive);
+    if running {
+        return Err(CommandError::new(ErrorCode::Conflict, "storage.migrate.vmsRunning"));
+    }
+    // Overlays record their backing file by path, so the chain would break
+    for vm in state.config_store.list_vms()? {
+        ensure_provisioned(state, &vm)?;
+        if !state.config_store.external_snapshots(&vm.id)?.is_empty() {
+            return Err(CommandError::validation("new_dir", "storage.migrate.externalSnapshots")
+                .with_param("name", vm.name));
+        }
+    }
+
+    let from_dir = state.disks_dir();
+    let to_dir = PathBuf::from(new_dir);
+    std::fs::create_dir_all(&to_dir).map_err(Error::from)?;
+    if std::fs::canonicalize(&from_dir).ok() == Some(std::fs::canonicalize(&to_dir).map_err(Error::from)?) {
+        return Err(CommandError::validation("new_dir", "storage.migrate.samePath"));
+    }
+
+    let files = storage::storage_files(&from_dir)?;
+    let required_bytes = files
+        .iter()
+        .map(|file| std::fs::metadata(file).map(|metadata| metadata.len()))
+        .sum::<std::io::Result<u64>>()
+        .map_err(Error::from)?;
+    let available_bytes = storage::free_space_in(&to_dir)?;
+    if available_bytes < required_bytes {
+        return Err(Error::InsufficientSpace {
+            required_mb: required_bytes / (1024 * 1024),
+            available_mb: available_bytes / (1024 * 1024),
+        }
+        .into());
+    }
+
+    let events = state.ui_events.clone();
+    let target = to_dir.clone();
+    let copied = tokio::task::spawn_blocking(move || {
+        storage::copy_storage_files(&files, &target, |progress| {
+            let _ = events.send(UiEvent::StorageMigration(progress));
+        })
+    })
+    .await
+    .map_err(|e| CommandError::new(ErrorCode::Internal, "error.internal").with_param("detail", e.to_string()))??;
+
+    let moves: Vec<(String, String)> = copied
+        .iter()
+        .map(|(from, to)| (from.display().to_string(), to.display().to_string()))
+        .collect();
+    let storage_dir = to_dir.display().to_string();
+    if let Err(err) = state.config_store.relocate_storage(&moves, &storage_dir) {
+        for (_, copy) in &copied {
+            let _ = std::fs::remove_file(copy);
+        }
+        return Err(err.into());
+    }
+    state.disk_manager.set_storage_dir(storage_dir.clone());
+
+    // The copies are in use now, so a leftover original only wastes space
+    for (original, _) in &copied {
+        if let Err(err) = std::fs::remove_file(original) {
+            tracing::warn!(path = %original.display(), error = %err, "failed to remove migrated file");
+        }
+    }
+    tracing::info!(from = %from_dir.display(), to = %storage_dir, files = copied.len(), "storage migrated");
+    Ok(storage_dir)
+}
+
+/// Import a VirtualBox OVA: convert its disks and register a new VM
+pub async fn import_ova(state: &AppState, ova_path: &str) -> CommandResult<VM> {
+    if ova_path.trim().is_empty() {
+        return Err(CommandError::validation("ova_path", "ova.path.empty"));
+    }
+
+    let staging_dir = state.disks_dir().join(format!(".import-{}", Uuid::new_v4()));
+    let source = PathBuf::from(ova_path);
+    let staging = staging_dir.clone();
+
+    let imported = tokio::task::spawn_blocking(move || -> crate::Result<_> {
+        let manifest = ova_import::parse_ova(&source)?;
+        ova_import::extract_ova(&source, &staging)?;
+        let disks = ova_import::convert_ova_disks(&manifest, &staging, &staging.join("converted"))?;
+        Ok((manifest, disks))
+    })
+    .await
+    .map_err(|e| CommandError::new(ErrorCode::Internal, "error.internal").with_param("detail", e.to_string()))?;
+
+    let result = async {
+        let (manifest, disks) = imported?;
+        let primary_disk = disks
+            .first()
+            .ok_or_else(|| CommandError::new(ErrorCode::ValidationFailed, "ova.noDisks"))?;
+        let vm_id = claim_import_id(&state.config_store, &state.disks_dir(), &Uuid::new_v4().to_string())?;
+        std::fs::rename(primary_disk, disk_path(&state.disks_dir(), &vm_id)).map_err(Error::from)?;
+        state.disk_manager.invalidate_info(&vm_id);
+
+        let virtual_size = state
+            .disk_manager
+            .get_virtual_size(&vm_id)
+            .await?;
+        let disk_size_gb = ((virtual_size + (1024 * 1024 * 1024 - 1)) / (1024 * 1024 * 1024)).max(1) as u32;
+
+        let record = VMRecord {
+            id: vm_id.clone(),
+            name: manifest.name.clone(),
+            memory_mb: manifest.memory_mb.max(512),
+            cpu_cores: manifest.cpu.max(1),
+            disk_size_gb,
+            os: ova_import::map_os_type(&manifest.os_type).to_string(),
+            preallocation: "off".to_string(),
+            notes: manifest.annotation.chars().take(validation::MAX_NOTES_LEN).collect(),
+            mac_address: Some(generate_stable_mac(&vm_id)),
+            ..VMRecord::default()
+        };
+        // The descriptor's CPU and memory counts come from the archive, so hold them to the usual limits
+        let warnings = match check_vm_config(&map_record_to_vm(record.clone()).config, &host_limits(None)) {
+            Ok(warnings) => warnings,
+            Err(err) => {
+                let _ = state.disk_manager.delete_disk(&vm_id).await;
+                return Err(err);
+            }
+        };
+        if let Err(err) = state.config_store.create_vm(&record) {
+            let _ = state.disk_manager.delete_disk(&vm_id).await;
+            return Err(err.into());
+        }
+        notify_vm_list_changed(state, &vm_id, VmChangeKind::Created);
+
+        let mut vm = map_record_to_vm(record);
+        vm.warnings.extend(warnings);
+        if disks.len() > 1 {
+            vm.warnings.push(VMWarning {
+                code: "extra-disks-skipped".to_string(),
+                message: format!("Only the first of {} disks was imported", disks.len()),
+            });
+        }
+        if vm.emulated {
+            vm.warnings.push(VMWarning {
+                code: "emulated-arch".to_string(),
+                message: format!(
+                    "{} guests are emulated on this {} host and will run slowly",
+                    vm.config.arch,
+                    platform::host_arch()
+                ),
+            });
+        }
+        Ok(vm)
+    }
+    .await;
+
+    let _ = std::fs::remove_dir_all(&staging_dir);
+    result
+}
+
+/// Encode a stopped capture next to its frame directory
+pub async fn finish_recording(vm_id: &str, capture: recording::Recording) -> crate::Result<RecordingSummary> {
+    let output_stem = capture.frame_dir.with_extension("");
+    let recorded = recording::finish(capture, recording::find_ffmpeg().as_deref(), &output_stem).await?;
+    let path = recorded.path.display().to_string();
+    tracing::info!(vm_id = %vm_id, path = %path, frames = recorded.frames, "display recording saved");
+    Ok(RecordingSummary {
+        vm_id: vm_id.to_string(),
+        path,
+        duration_ms: recorded.duration.as_millis() as u64,
+        frames: recorded.frames,
+        warning: recorded.warning,
+    })
+}
+
+/// End a recording whose VM went away, keeping the frames captured so far
+pub async fn force_stop_recording(state: &AppState, vm_id: &str) {
+    let Some(capture) = state.recordings.lock().await.remove(vm_id) else {
+        return;
+    };
+    let vm_id = vm_id.to_string();
+    tokio::spawn(async move {
+        if let Err(err) = finish_recording(&vm_id, capture).await {
+            tracing::warn!(vm_id = %vm_id, error = %err, "failed to save recording of stopped VM");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UNIQUE_NAMES_SETTING;
+    use crate::memory_policy::MemoryPolicy;
+    use crate::qemu::VmPerformance;
+    use crate::service;
+
+    fn test_devices() -> LaunchDevices {
+        LaunchDevices {
+            disk: test_disk(),
+            networks: Vec::new(),
+            hotplugged: Vec::new(),
+            serial_socket: Some("/tmp/serial.sock".to_string()),
+            balloon: false,
+            boot_once: None,
+            display_port: test_display_port("vm-1"),
+        }
+    }
+
+    /// A connected SPICE session on loopback, as `open_display` makes for a local VM
+    fn spice_session(vm_id: &str, port: u16, clipboard_sharing: bool, password: Option<String>) -> DisplaySession {
+        build_display_session(vm_id, LOOPBACK_LISTEN_ADDRESS, port, "spice", "connected", clipboard_sharing, password)
+    }
+
+    fn test_display_port(vm_id: &str) -> u16 {
+        DisplayPortRange::default().port_for(vm_id)
+    }
+
+    fn test_disk() -> PrimaryDisk {
+        PrimaryDisk {
+            path: "/tmp/vm-1.qcow2".to_string(),
+            discard: false,
+            interface: "virtio".to_string(),
+        }
+    }
+
+    fn native_host(nested_virt_flag: Option<&str>) -> HostCapabilities {
+        HostCapabilities {
+            arch: "x86_64".to_string(),
+            nested_virt_flag: nested_virt_flag.map(|flag| flag.to_string()),
+            hugepages: None,
+        }
+    }
+
+    fn valid_config() -> VMConfig {
+        VMConfig {
+            name: "Ubuntu".to_string(),
+            memory_mb: 2048,
+            cpu_cores: 2,
+            disk_size_gb: 20,
+            os: GuestOs::Linux,
+            install_media_path: None,
+            boot_order: "disk-first".to_string(),
+            network_type: "nat".to_string(),
+            nested_virt: false,
+            restart_policy: "on-failure".to_string(),
+            arch: "x86_64".to_string(),
+            cpu_affinity: Vec::new(),
+            priority: 0,
+            preallocation: "metadata".to_string(),
+            encrypted: false,
+            raw_device_path: None,
+            notes: String::new(),
+            clipboard_sharing: false,
+            vlan_id: None,
+            display_resolution: None,
+            graphics: None,
+            machine_type: None,
+            display_mode: "spice".to_string(),
+            boot_menu: None,
+            networks: Vec::new(),
+            performance: None,
+            pointer_device: None,
+            rtc: None,
+            rng: None,
+        }
+    }
+
+    fn small_host() -> HostLimits {
+        HostLimits {
+            logical_cpus: 4,
+            total_memory_mb: 8192,
+            free_disk_bytes: None,
+            os: "linux".to_string(),
+            kernel_version: Some((6, 8)),
+        }
+    }
+
+    fn stored_disk_record(store: &ConfigStore) -> VMRecord {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Ubuntu".to_string(),
+            ..VMRecord::default()
+        };
+        store.create_vm(&record).unwrap();
+        record
+    }
+
+    fn test_state(temp_dir: &tempfile::TempDir, qemu_binary: &str) -> AppState {
+        let paths = AppPaths::legacy(temp_dir.path(), temp_dir.path().join("run"));
+        paths.ensure_dirs().unwrap();
+        let store = ConfigStore::new(paths.db_path()).unwrap();
+        AppState::new(store, paths, qemu::QemuController::new(qemu_binary.to_string()))
+    }
+
+    fn status_changes(events: &mut tokio::sync::broadcast::Receiver<UiEvent>) -> Vec<(String, String)> {
+        std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|event| match event {
+                UiEvent::StatusChanged(change) => Some((change.old_status, change.new_status)),
+                UiEvent::Crashed(_)
+                | UiEvent::StorageMigration(_)
+                | UiEvent::ListChanged(_)
+                | UiEvent::Provisioning(_) => None,
+            })
+            .collect()
+    }
+
+    fn event(kind: &str, at_ms: u64) -> VmEvent {
+        VmEvent {
+            vm_id: "vm-1".to_string(),
+            kind: kind.to_string(),
+            at_ms,
+        }
+    }
+
+    #[test]
+    fn test_validate_vm_config_rejects_invalid() {
+        let config = VMConfig {
+            name: String::new(),
+            memory_mb: 256,
+            cpu_cores: 0,
+            disk_size_gb: 0,
+            ..valid_config()
+        };
+
+        let err = check_vm_config(&config, &small_host()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ValidationFailed);
+        assert_eq!(err.message, "VM name cannot be empty");
+        assert_eq!(err.message_key, "validation.name.required");
+        let details = err.details.unwrap();
+        assert_eq!(details["field"], "name");
+        let fields: Vec<&str> = details["issues"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|issue| issue["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, vec!["name", "memory_mb", "cpu_cores", "disk_size_gb"]);
+    }
+
+    #[test]
+    fn test_validation_errors_have_catalog_entries() {
+        let forward = |protocol: &str, port: u16| qemu::PortForward {
+            protocol: protocol.to_string(),
+            host_port: port,
+            guest_port: port,
+        };
+        let adapter = |id: &str, kind: &str| NetworkConfig {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+        let configs = [
+            VMConfig { name: String::new(), memory_mb: 256, cpu_cores: 0, disk_size_gb: 0, ..valid_config() },
+            VMConfig {
+                name: format!("{}/", "x".repeat(70)),
+                memory_mb: validation::MAX_MEMORY_MB + 1,
+                cpu_cores: validation::MAX_CPU_CORES + 1,
+                cpu_affinity: vec![99],
+                disk_size_gb: validation::MAX_DISK_SIZE_GB + 1,
+                install_media_path: Some("/nonexistent/os.iso".to_string()),
+                arch: "riscv64".to_string(),
+                priority: 20,
+                network_type: "bridge".to_string(),
+                vlan_id: Some(4095),
+                display_resolution: Some("1x1".to_string()),
+                boot_menu: Some(qemu::BootMenuConfig { enabled: true, splash_time_ms: 70_000 }),
+                machine_type: Some("q35,accel=tcg".to_string()),
+                notes: "x".repeat(validation::MAX_NOTES_LEN + 1),
+                performance: Some(VmPerformance {
+                    iothreads: 500,
+                    aio: Some("io_uring".to_string()),
+                    ..VmPerformance::default()
+                }),
+                networks: vec![
+                    NetworkConfig { mac: Some("zz".to_string()), ..adapter("net0", "nat") },
+                    adapter("net0", "nat"),
+                    adapter("9bad", "nat"),
+                    NetworkConfig { port_forwards: vec![forward("tcp", 0)], ..adapter("net1", "bridge") },
+                    NetworkConfig {
+                        port_forwards: vec![forward("tcp", 2222), forward("tcp", 2222)],
+                        ..adapter("net2", "nat")
+                    },
+                ],
+                ..valid_config()
+            },
+            VMConfig {
+                memory_mb: 16384,
+                vlan_id: Some(10),
+                graphics: Some("qxl".to_string()),
+                arch: "aarch64".to_string(),
+                ..valid_config()
+            },
+            VMConfig { raw_device_path: Some("/home/disk.img".to_string()), encrypted: true, ..valid_config() },
+            VMConfig { disk_size_gb: 200, preallocation: "full".to_string(), ..valid_config() },
+            VMConfig {
+                networks: (0..=qemu::MAX_NETWORK_ADAPTERS).map(|i| adapter(&format!("net{}", i), "nat")).collect(),
+                ..valid_config()
+            },
+        ];
+        let host = HostLimits {
+            free_disk_bytes: Some(100 * 1024 * 1024 * 1024),
+            kernel_version: Some((5, 0)),
+            ..small_host()
+        };
+        let macos = HostLimits { os: "macos".to_string(), ..small_host() };
+
+        let mut codes = BTreeSet::new();
+        let aio_on_macos = VMConfig {
+            performance: Some(VmPerformance { aio: Some("native".to_string()), ..VmPerformance::default() }),
+            ..valid_config()
+        };
+        let issues = configs
+            .iter()
+            .flat_map(|config| validation::validate_vm_config(config, &host))
+            .chain(validation::validate_vm_config(&aio_on_macos, &macos));
+        for issue in issues.filter(|issue| issue.severity == Severity::Error) {
+            let key = i18n::validation_key(&issue.field, &issue.code);
+            assert_eq!(i18n::render(&key, &issue.params), issue.message, "{}", key);
+            codes.insert(format!("{}/{}", issue.field, issue.code));
+        }
+        assert!(codes.len() >= 30, "{:?}", codes);
+    }
+
+    #[test]
+    fn test_check_vm_config_returns_warnings() {
+        let config = VMConfig { cpu_cores: 8, ..valid_config() };
+
+        let warnings = check_vm_config(&config, &small_host()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "exceeds-host");
+
+        // More memory than the host has is warned about, not refused
+        let config = VMConfig { memory_mb: 16384, ..valid_config() };
+        let warnings = check_vm_config(&config, &small_host()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "exceeds-host");
+        assert_eq!(warnings[0].message, "Memory exceeds the host's 8192 MB");
+    }
+
+    #[test]
+    fn test_parse_vm_status_defaults_to_stopped() {
+        assert_eq!(parse_vm_status("unknown"), VMStatus::Stopped);
+    }
+
+    #[test]
+    fn test_map_record_to_vm_maps_core_fields() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Ubuntu VM".to_string(),
+            status: "paused".to_string(),
+            memory_mb: 4096,
+            cpu_cores: 4,
+            disk_size_gb: 64,
+            ..VMRecord::default()
+        };
+
+        let vm = map_record_to_vm(record);
+        assert_eq!(vm.id, "vm-1");
+        assert_eq!(vm.name, "Ubuntu VM");
+        assert_eq!(vm.status, VMStatus::Paused);
+        assert_eq!(vm.config.memory_mb, 4096);
+        assert_eq!(vm.config.cpu_cores, 4);
+    }
+
+    #[test]
+    fn test_build_start_args_encrypted_disk() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Vault".to_string(),
+            encryption_key_ref: Some(luks_key_ref("vm-1")),
+            ..VMRecord::default()
+        };
+
+        let err = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect_err("passphrase is required");
+        assert!(err.message.contains("Passphrase required"));
+
+        let args = build_start_args(
+            &record,
+            &test_devices(),
+            "/tmp/qmp.sock", "/tmp/monitor.sock",
+            Some("/run/openutm/secret-vm-1"),
+            &DisplaySecrets::default(),
+            &native_host(None),
+        )
+        .expect("args should build");
+        let joined = args.join(" ");
+        assert!(joined.contains("-object secret,id=luks-vm-1,file=/run/openutm/secret-vm-1"));
+        assert!(joined.contains("encrypt.key-secret=luks-vm-1"));
+
+        let secrets = DisplaySecrets { password_file: Some("/run/a,b/spice-vm-1"), x509_dir: None };
+        let args = build_start_args(
+            &record,
+            &test_devices(),
+            "/tmp/qmp.sock", "/tmp/monitor.sock",
+            Some("/run/a,b/secret-vm-1"),
+            &secrets,
+            &native_host(None),
+        )
+        .expect("args should build");
+        assert!(args.contains(&"secret,id=luks-vm-1,file=/run/a,,b/secret-vm-1".to_string()));
+        assert!(args.contains(&"secret,id=spice-password,file=/run/a,,b/spice-vm-1".to_string()));
+    }
+
+    #[test]
+    fn test_build_start_args_includes_qmp_and_name() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Fedora VM".to_string(),
+            install_media_path: Some("/isos/fedora.iso".to_string()),
+            boot_order: "cdrom-first".to_string(),
+            mac_address: Some(generate_stable_mac("vm-1")),
+            ..VMRecord::default()
+        };
+
+        let args = build_start_args(&record, &test_devices(), "/tmp/openutm-qmp-vm-1.sock", "/tmp/openutm-monitor-vm-1.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect("args should build");
+        let joined = args.join(" ");
+
+        assert!(joined.contains("-qmp"));
+        assert!(joined.contains("openutm-qmp-vm-1.sock"));
+        assert!(joined.contains("-monitor unix:/tmp/openutm-monitor-vm-1.sock,server=on,wait=off"));
+        assert!(joined.contains("-name Fedora VM"));
+        assert!(joined.contains("-spice"));
+        assert!(joined.contains(&format!("port={}", test_display_port("vm-1"))));
+        assert!(joined.contains("media=cdrom"));
+        assert!(joined.contains("/isos/fedora.iso"));
+        assert!(joined.contains("-boot"));
+        assert!(joined.contains("order=d"));
+        assert!(joined.contains(&format!("-device virtio-net-pci,netdev=net0,mac={}", generate_stable_mac("vm-1"))));
+
+        let adapter = |id: &str, kind: &str| {
+            let network = NetworkConfig {
+                id: id.to_string(),
+                kind: kind.to_string(),
+                mac: None,
+                port_forwards: Vec::new(),
+            };
+            with_stable_mac(&record.id, network)
+        };
+        let networks = vec![adapter("mgmt", "nat"), adapter("data", "bridge")];
+        assert_ne!(networks[0].mac, networks[1].mac);
+        let joined = build_start_args(
+            &record,
+            &LaunchDevices { networks: networks.clone(), ..test_devices() },
+            "/tmp/qmp.sock",
+            "/tmp/monitor.sock",
+            None,
+            &DisplaySecrets::default(),
+            &native_host(None),
+        )
+        .expect("args should build")
+        .join(" ");
+        assert!(!joined.contains("netdev=net0"));
+        assert!(joined.contains("-netdev user,id=mgmt"));
+        assert!(joined.contains(&format!("-netdev bridge,id=data -device virtio-net-pci,netdev=data,mac={}",
+            networks[1].mac.as_deref().unwrap())));
+    }
+
+    #[test]
+    fn test_build_start_args_uses_pinned_machine_type() {
+        let mut record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Pinned".to_string(),
+            machine_type: Some("pc-q35-8.2".to_string()),
+            ..VMRecord::default()
+        };
+
+        let build = |record: &VMRecord| {
+            build_start_args(record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+                .unwrap()
+                .join(" ")
+        };
+        assert!(build(&record).contains("-machine pc-q35-8.2"));
+
+        record.machine_type = None;
+        assert!(build(&record).contains("-machine q35"));
+    }
+
+    #[test]
+    fn test_headless_vm_has_no_display() {
+        let mut record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Build server".to_string(),
+            clipboard_sharing: true,
+            display_resolution: Some("1920x1080".to_string()),
+            display_mode: "none".to_string(),
+            ..VMRecord::default()
+        };
+
+        let args = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .unwrap();
+        let joined = args.join(" ");
+        assert!(joined.contains("-display none -vga none"));
+        assert!(joined.contains("-serial unix:/tmp/serial.sock,server=on,wait=off"));
+        for absent in ["-spice", "-vnc", "spicevmc", "qxl-vga", "xres="] {
+            assert!(!joined.contains(absent), "{} in {}", absent, joined);
+        }
+
+        let err = ensure_has_display(&record).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Conflict);
+        assert_eq!(err.message_key, "display.headless");
+        assert_eq!(err.details, Some(serde_json::json!({ "accessMethods": ["monitor", "serial"] })));
+        assert_eq!(map_record_to_vm(record.clone()).access_methods, vec!["monitor", "serial"]);
+
+        record.display_mode = "vnc".to_string();
+        assert!(ensure_has_display(&record).is_ok());
+        let joined = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .unwrap()
+            .join(" ");
+        assert!(joined.contains(&format!("-vnc 127.0.0.1:{}", test_display_port("vm-1") - qemu::VNC_BASE_PORT)));
+        assert!(!joined.contains("-serial"));
+        assert_eq!(map_record_to_vm(record).access_methods, vec!["vnc", "monitor"]);
+    }
+
+    #[test]
+    fn test_build_start_args_emits_nested_virt_cpu_flag() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Docker Host".to_string(),
+            memory_mb: 4096,
+            cpu_cores: 4,
+            disk_size_gb: 40,
+            nested_virt: true,
+            ..VMRecord::default()
+        };
+
+        let args = build_start_args(&record, &test_devices(), "/tmp/openutm-qmp-vm-1.sock", "/tmp/openutm-monitor-vm-1.sock", None, &DisplaySecrets::default(), &native_host(Some("vmx")));
+        if matches!(default_accelerator(), Accelerator::Tcg) {
+            assert!(args.is_err());
+        } else {
+            let joined = args.expect("args should build").join(" ");
+            assert!(joined.contains("-cpu host,+vmx"));
+        }
+    }
+
+    #[test]
+    fn test_build_start_args_rejects_nested_virt_without_host_support() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Docker Host".to_string(),
+            memory_mb: 4096,
+            cpu_cores: 4,
+            disk_size_gb: 40,
+            nested_virt: true,
+            ..VMRecord::default()
+        };
+
+        let err = build_start_args(&record, &test_devices(), "/tmp/openutm-qmp-vm-1.sock", "/tmp/openutm-monitor-vm-1.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect_err("nested virt should be rejected");
+        assert_eq!(err.code, ErrorCode::PlatformUnsupported);
+        assert_eq!(err.message, "Host does not support nested virtualization");
+    }
+
+    #[test]
+    fn test_build_start_args_checks_free_hugepages() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Database".to_string(),
+            memory_mb: 4096,
+            cpu_cores: 4,
+            disk_size_gb: 40,
+            memory_backend: Some("hugepages".to_string()),
+            ..VMRecord::default()
+        };
+        let mut host = native_host(None);
+        let build = |host: &HostCapabilities| {
+            build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), host)
+        };
+
+        let err = build(&host).expect_err("no huge pages should be rejected");
+        assert_eq!(err.code, ErrorCode::PlatformUnsupported);
+
+        host.hugepages = Some(platform::HugePages { total: 2048, free: 1024, page_size_kb: 2048 });
+        let err = build(&host).expect_err("too few free huge pages should be rejected");
+        assert_eq!(err.code, ErrorCode::ValidationFailed);
+        assert_eq!(err.message, "Needs 2048 huge pages of 2 MB but only 1024 are available");
+
+        host.hugepages = Some(platform::HugePages { total: 2048, free: 2048, page_size_kb: 2048 });
+        let joined = build(&host).expect("args should build").join(" ");
+        assert!(joined.contains("-machine q35,memory-backend=mem0"));
+        assert!(joined.contains("-object memory-backend-file,id=mem0,size=4096M,mem-path=/dev/hugepages,prealloc=on"));
+    }
+
+    #[test]
+    fn test_should_restart_follows_policy() {
+        assert!(should_restart("always", Some(0)));
+        assert!(should_restart("on-failure", Some(1)));
+        assert!(should_restart("on-failure", None));
+        assert!(!should_restart("on-failure", Some(0)));
+        assert!(!should_restart("never", Some(1)));
+    }
+
+    #[test]
+    fn test_build_start_args_never_policy_disables_reboot() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Batch VM".to_string(),
+            restart_policy: "never".to_string(),
+            ..VMRecord::default()
+        };
+
+        let args = build_start_args(&record, &test_devices(), "/tmp/openutm-qmp-vm-1.sock", "/tmp/openutm-monitor-vm-1.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect("args should build");
+        assert!(args.contains(&"-no-reboot".to_string()));
+    }
+
+    #[test]
+    fn test_build_start_args_vlan_networking() {
+        let mut record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Lab VM".to_string(),
+            network_type: "bridge".to_string(),
+            vlan_id: Some(100),
+            ..VMRecord::default()
+        };
+
+        let args = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect("args should build");
+        let netdev = format!(
+            "tap,id=net0,downscript=no,ifname={},script=no,vnet_hdr=on",
+            qemu::command::vlan_tap_name(&record.id)
+        );
+        assert!(args.contains(&netdev), "{:?}", args);
+
+        record.network_type = "nat".to_string();
+        let err = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect_err("VLAN needs bridge networking");
+        assert_eq!(err.code, ErrorCode::ValidationFailed);
+        assert!(err.message.contains("VLAN requires TAP or bridge network"));
+    }
+
+    #[test]
+    fn test_build_start_args_cross_arch_falls_back_to_tcg() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Intel Guest".to_string(),
+            ..VMRecord::default()
+        };
+        let host = HostCapabilities {
+            arch: "aarch64".to_string(),
+            nested_virt_flag: None,
+            hugepages: None,
+        };
+
+        let args = build_start_args(&record, &test_devices(), "/tmp/openutm-qmp-vm-1.sock", "/tmp/openutm-monitor-vm-1.sock", None, &DisplaySecrets::default(), &host)
+            .expect("args should build");
+        let joined = args.join(" ");
+
+        assert!(joined.contains("-accel tcg,thread=multi"));
+        assert!(joined.contains("-cpu max"));
+        assert!(joined.contains("-machine q35"));
+        assert!(!joined.contains("hvf"));
+    }
+
+    #[test]
+    fn test_validate_cpu_affinity_against_host() {
+        assert!(validate_cpu_affinity(&[0, 3], 4).is_ok());
+        assert!(validate_cpu_affinity(&[], 4).is_ok());
+        let err = validate_cpu_affinity(&[4], 4).unwrap_err();
+        assert!(err.message.contains("Core 4"));
+        assert_eq!(err.message_key, "vm.cpuAffinity.outOfRange");
+        assert_eq!(err.params["logicalCpus"], 4);
+        assert_eq!(err.details, Some(serde_json::json!({ "field": "cpu_affinity" })));
+    }
+
+    #[test]
+    fn test_hotplugged_disk_is_attached_at_launch() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let record = stored_disk_record(&state.config_store);
+        let drive_id = Uuid::new_v4();
+        state
+            .config_store
+            .add_hotplugged_drive(&record.id, &drive_id.to_string(), "/data/extra.qcow2", "virtio", "qcow2")
+            .unwrap();
+
+        let devices = launch_devices(&state, &record).unwrap();
+        let (device_id, node_name) = hotplug_names(&drive_id);
+        assert_eq!(devices.hotplugged.len(), 1);
+        assert_eq!(devices.hotplugged[0].device_id, device_id);
+        assert_eq!(devices.hotplugged[0].node_name, node_name);
+        let secrets = DisplaySecrets::default();
+        let args = build_start_args(&record, &devices, "/tmp/qmp.sock", "/tmp/monitor.sock", None, &secrets, &native_host(None))
+            .unwrap()
+            .join(" ");
+        assert!(args.contains(&format!("virtio-blk-pci,id={},drive={}", device_id, node_name)));
+
+        state.config_store.remove_hotplugged_drive(&record.id, &drive_id.to_string()).unwrap();
+        assert!(launch_devices(&state, &record).unwrap().hotplugged.is_empty());
+    }
+
+    #[test]
+    fn test_ephemeral_vm_launches_from_overlay() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+        let disk = disk_path(&state.paths.disks_dir(), &record.id);
+        assert_eq!(launch_devices(&state, &record).unwrap().disk.path, disk);
+
+        record.ephemeral = true;
+        let overlay = ephemeral_overlay(&state, &record.id);
+        assert_eq!(launch_devices(&state, &record).unwrap().disk.path, overlay.display().to_string());
+
+        std::fs::write(&overlay, b"").unwrap();
+        remove_ephemeral_overlay(&state, &record.id);
+        assert!(!overlay.exists());
+    }
+
+    #[tokio::test]
+    async fn test_vm_cannot_start_while_its_disk_moves() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let record = stored_disk_record(&state.config_store);
+
+        let moving = DiskMoveGuard::acquire(&state, vec![record.id.clone()]).unwrap();
+        assert!(DiskMoveGuard::acquire(&state, vec!["other".to_string(), record.id.clone()]).is_none());
+        let err = launch_vm(&state, &record.id, None).await.unwrap_err();
+        assert_eq!(err.message_key, "vm.start.diskMoving");
+
+        drop(moving);
+        assert!(ensure_disk_settled(&state, &record).is_ok());
+        assert!(DiskMoveGuard::acquire(&state, vec!["other".to_string()]).is_some());
+        assert!(state.moving_disks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_launch_removes_passphrase_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+        record.encryption_key_ref = Some(luks_key_ref(&record.id));
+        record.cpu_cores = 0;
+        state.config_store.update_vm(&record).unwrap();
+
+        assert!(launch_vm(&state, &record.id, Some("hunter2")).await.is_err());
+        assert!(!state.paths.secret_file(&record.id).exists());
+    }
+
+    #[tokio::test]
+    async fn test_launch_without_tls_certificates_removes_passphrase_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+        record.encryption_key_ref = Some(luks_key_ref(&record.id));
+        record.spice_tls_port = Some(5901);
+        state.config_store.update_vm(&record).unwrap();
+        // A file where the certificate dir should be, so no certificate can be issued
+        std::fs::write(state.paths.spice_tls_dir(), b"").unwrap();
+
+        assert!(launch_vm(&state, &record.id, Some("hunter2")).await.is_err());
+        assert!(!state.paths.secret_file(&record.id).exists());
+    }
+
+    #[tokio::test]
+    async fn test_launch_without_spice_password_file_removes_passphrase_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+        record.encryption_key_ref = Some(luks_key_ref(&record.id));
+        state.config_store.update_vm(&record).unwrap();
+        // A directory where the password file should be, so it cannot be written
+        std::fs::create_dir_all(state.paths.spice_password_file(&record.id)).unwrap();
+
+        assert!(launch_vm(&state, &record.id, Some("hunter2")).await.is_err());
+        assert!(!state.paths.secret_file(&record.id).exists());
+    }
+
+    #[test]
+    fn test_balloon_only_for_policy_managed_vms() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+        assert!(!launch_devices(&state, &record).unwrap().balloon);
+
+        let policy = MemoryPolicy { enabled: true, ..MemoryPolicy::default() };
+        state.config_store.save_memory_policy(&policy).unwrap();
+        assert!(launch_devices(&state, &record).unwrap().balloon);
+
+        record.memory_backend = Some("hugepages".to_string());
+        assert!(!launch_devices(&state, &record).unwrap().balloon);
+        record.memory_backend = None;
+        record.memory_policy_exempt = true;
+        assert!(!launch_devices(&state, &record).unwrap().balloon);
+    }
+
+    #[test]
+    fn test_boot_once_applies_to_next_launch() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut record = stored_disk_record(&state.config_store);
+
+        let err = set_boot_once(&state, &record.id, "floppy").unwrap_err();
+        assert_eq!(err.message_key, "vm.bootOnce.invalid");
+        let err = set_boot_once(&state, &record.id, "cdrom").unwrap_err();
+        assert_eq!(err.message_key, "vm.bootOnce.noMedia");
+        assert_eq!(launch_devices(&state, &record).unwrap().boot_once, None);
+
+        record.install_media_path = Some("/isos/rescue.iso".to_string());
+        state.config_store.update_vm(&record).unwrap();
+        set_boot_once(&state, &record.id, "cdrom").unwrap();
+        let devices = launch_devices(&state, &record).unwrap();
+        assert_eq!(devices.boot_once, Some(BootDevice::Cdrom));
+
+        let args = build_start_args(
+            &record,
+            &devices,
+            "/tmp/qmp.sock",
+            "/tmp/monitor.sock",
+            None,
+            &DisplaySecrets::default(),
+            &native_host(None),
+        )
+        .unwrap();
+        assert!(args.contains(&"order=c,once=d,menu=on".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_start_sequence_records_transient_status() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let record = stored_disk_record(&state.config_store);
+        let status = |store: &ConfigStore| store.get_vm(&record.id).unwrap().unwrap().status;
+
+        run_transition(&state, &record.id, VMStatus::Starting, VMStatus::Running, VMStatus::Stopped, async {
+            assert_eq!(status(&state.config_store), "starting");
+            assert_eq!(parse_vm_status(&status(&state.config_store)), VMStatus::Starting);
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(status(&state.config_store), "running");
+
+        run_transition(&state, &record.id, VMStatus::Stopping, VMStatus::Stopped, VMStatus::Running, async {
+            assert_eq!(status(&state.config_store), "stopping");
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(status(&state.config_store), "stopped");
+    }
+
+    #[tokio::test]
+    async fn test_failed_start_falls_back_to_stopped() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let record = stored_disk_record(&state.config_store);
+
+        let err = run_transition(&state, &record.id, VMStatus::Starting, VMStatus::Running, VMStatus::Stopped, async {
+            Err(Error::QmpTimeout("connect".to_string()).into())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.code, ErrorCode::QmpTimeout);
+        assert_eq!(state.config_store.get_vm(&record.id).unwrap().unwrap().status, "stopped");
+    }
+
+    #[tokio::test]
+    async fn test_running_vm_keeps_its_launch_display_port() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "sh");
+        let record = stored_disk_record(&state.config_store);
+        let range = DisplayPortRange { start: 7000, end: 7100 };
+        assert_eq!(vm_display_port(&state, &range, &record.id), range.port_for(&record.id));
+
+        // `sh -c` ignores the QEMU arguments after its script
+        let args = ["-c", "sleep 30", "-spice", "port=5999,addr=127.0.0.1"].map(String::from).to_vec();
+        state.qemu_controller.start_vm(&record.id, args, None).await.unwrap();
+        assert_eq!(vm_display_port(&state, &range, &record.id), 5999);
+
+        state.qemu_controller.stop_vm(&record.id).await.unwrap();
+        assert_eq!(vm_display_port(&state, &range, &record.id), range.port_for(&record.id));
+    }
+
+    #[tokio::test]
+    async fn test_start_and_stop_emit_status_changes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "sh");
+        let record = stored_disk_record(&state.config_store);
+        let mut events = state.ui_events.subscribe();
+
+        // What `launch_vm` does once QEMU is up, with a stand-in process
+        let spawned = async {
+            state.qemu_controller.start_vm(&record.id, vec!["-c".to_string(), "sleep 30".to_string()], None).await?;
+            Ok(())
+        };
+        run_transition(&state, &record.id, VMStatus::Starting, VMStatus::Running, VMStatus::Stopped, spawned)
+            .await
+            .unwrap();
+        assert_eq!(
+            status_changes(&mut events),
+            vec![
+                ("stopped".to_string(), "starting".to_string()),
+                ("starting".to_string(), "running".to_string()),
+            ]
+        );
+
+        service::stop_vm(&state, &record.id).await.unwrap();
+        assert_eq!(
+            status_changes(&mut events),
+            vec![
+                ("running".to_string(), "stopping".to_string()),
+                ("stopping".to_string(), "stopped".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vm_list_changes_reach_subscribers() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let mut events = state.ui_events.subscribe();
+
+        notify_vm_list_changed(&state, "vm-1", VmChangeKind::Deleted);
+        match events.try_recv() {
+            Ok(UiEvent::ListChanged(change)) => {
+                assert_eq!(change, VmListChanged { vm_id: "vm-1".to_string(), change: VmChangeKind::Deleted })
+            }
+            _ => panic!("expected a list change"),
+        }
+
+        // Every settings edit saves through `save_vm`
+        let mut record = stored_disk_record(&state.config_store);
+        record.notes = "edited".to_string();
+        save_vm(&state, &record).unwrap();
+        assert_eq!(state.config_store.get_vm(&record.id).unwrap().unwrap().notes, "edited");
+        match events.try_recv() {
+            Ok(UiEvent::ListChanged(change)) => {
+                assert_eq!(change, VmListChanged { vm_id: record.id.clone(), change: VmChangeKind::Updated })
+            }
+            _ => panic!("expected a list change"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_status_is_not_emitted() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "qemu-system-x86_64");
+        let record = stored_disk_record(&state.config_store);
+        let mut events = state.ui_events.subscribe();
+
+        update_vm_status(&state, &record.id, VMStatus::Stopped).unwrap();
+        assert!(status_changes(&mut events).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_crashed_vm_emits_crash_and_error_status() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "sh");
+        let mut record = stored_disk_record(&state.config_store);
+        record.status = "running".to_string();
+        record.restart_policy = "never".to_string();
+        state.config_store.update_vm(&record).unwrap();
+        let pid = state
+            .qemu_controller
+            .start_vm(&record.id, vec!["-c".to_string(), "exit 3".to_string()], None)
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let mut events = state.ui_events.subscribe();
+
+        reconcile_vm_processes(&state).await;
+        assert_eq!(
+            events.try_recv().unwrap(),
+            UiEvent::Crashed(VmCrashed { vm_id: record.id.clone(), pid, exit_code: Some(3) })
+        );
+        assert_eq!(status_changes(&mut events), vec![("running".to_string(), "error".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_relocate_storage_moves_every_disk() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = test_state(&temp_dir, "sh");
+        if storage::free_space_in(temp_dir.path()).is_err() {
+            return;
+        }
+        let record = stored_disk_record(&state.config_store);
+        let old_dir = state.disks_dir();
+        std::fs::write(old_dir.join("vm-1.qcow2"), b"qcow image").unwrap();
+        std::fs::write(old_dir.join("vm-1.qcow2.sha256"), b"digest  vm-1.qcow2\n").unwrap();
+        let extra = old_dir.join("extra.qcow2").display().to_string();
+        state.config_store.add_hotplugged_drive(&record.id, "drive-1", &extra, "virtio", "qcow2").unwrap();
+        std::fs::write(&extra, b"data disk").unwrap();
+        let mut events = state.ui_events.subscribe();
+
+        let new_dir = temp_dir.path().join("big-volume");
+        let moved = relocate_storage(&state, &new_dir.display().to_string()).await.unwrap();
+        assert_eq!(moved, new_dir.display().to_string());
+        assert_eq!(state.disks_dir(), new_dir);
+        assert_eq!(vm_disk_path(&state, &record.id).unwrap(), disk_path(&new_dir, &record.id));
+        assert_eq!(std::fs::read(new_dir.join("vm-1.qcow2")).unwrap(), b"qcow image");
+        assert!(new_dir.join("vm-1.qcow2.sha256").exists());
+        assert!(storage::storage_files(&old_dir).unwrap().is_empty());
+        let moved_extra = new_dir.join("extra.qcow2").display().to_string();
+        assert!(state.config_store.referenced_disk_paths().unwrap().contains(&moved_extra));
+        assert_eq!(state.config_store.get_setting(STORAGE_DIR_KEY).unwrap(), Some(moved.clone()));
+        assert_eq!(ephemeral_overlay(&state, &record.id), new_dir.join("ephemeral-vm-1.qcow2"));
+
+        let progress: Vec<u64> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|event| match event {
+                UiEvent::StorageMigration(progress) => Some(progress.copied_bytes),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(progress.len(), 3);
+        assert_eq!(progress.last(), Some(&(10 + 9 + 19)));
+
+        let err = relocate_storage(&state, &moved).await.unwrap_err();
+        assert_eq!(err.message_key, "storage.migrate.samePath");
+        let err = relocate_storage(&state, "  ").await.unwrap_err();
+        assert_eq!(err.message_key, "storage.migrate.dirEmpty");
+
+        let _moving = DiskMoveGuard::acquire(&state, vec![record.id.clone()]).unwrap();
+        let err = relocate_storage(&state, &old_dir.display().to_string()).await.unwrap_err();
+        assert_eq!(err.message_key, "storage.migrate.inProgress");
+    }
+
+    #[test]
+    fn test_relocate_disk_moves_image_and_records_path() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let record = stored_disk_record(&store);
+        let source = temp_dir.path().join("disks/vm-1.qcow2");
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+        std::fs::write(&source, b"qcow image").unwrap();
+        std::fs::write(temp_dir.path().join("disks/vm-1.qcow2.sha256"), b"digest  vm-1.qcow2\n").unwrap();
+        let destination = temp_dir.path().join("big-volume/vm-1.qcow2");
+
+        relocate_disk_file(&store, &record.id, &source, &destination).unwrap();
+        assert!(!source.exists());
+        assert_eq!(std::fs::read(&destination).unwrap(), b"qcow image");
+        assert!(!temp_dir.path().join("disks/vm-1.qcow2.sha256").exists());
+        assert!(temp_dir.path().join("big-volume/vm-1.qcow2.sha256").exists());
+        let recorded = destination.display().to_string();
+        assert_eq!(store.disk_location(&record.id).unwrap(), Some(recorded));
+
+        // Moving it back onto itself, or over an existing file, is refused
+        let err = relocate_disk_file(&store, &record.id, &destination, &destination).unwrap_err();
+        assert_eq!(err.message_key, "disk.relocate.samePath");
+        std::fs::write(&source, b"other").unwrap();
+        let err = relocate_disk_file(&store, &record.id, &destination, &source).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Conflict);
+        assert!(destination.exists());
+    }
+
+    #[test]
+    fn test_failed_relocation_leaves_no_copy() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let record = stored_disk_record(&store);
+        let destination = temp_dir.path().join("big-volume/vm-1.qcow2");
+
+        let missing = temp_dir.path().join("disks/vm-1.qcow2");
+        assert!(relocate_disk_file(&store, &record.id, &missing, &destination).is_err());
+        assert!(!destination.exists());
+        assert_eq!(store.disk_location(&record.id).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_disk_resize_rejects_shrink() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let mut record = stored_disk_record(&store);
+
+        let err = apply_disk_resize(&store, &mut record, 10, async { Ok(()) }).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::ValidationFailed);
+        assert_eq!(err.details, Some(serde_json::json!({ "field": "disk_size_gb" })));
+        assert!(apply_disk_resize(&store, &mut record, 20, async { Ok(()) }).await.is_err());
+        assert_eq!(store.get_vm("vm-1").unwrap().unwrap().disk_size_gb, 20);
+    }
+
+    #[tokio::test]
+    async fn test_apply_disk_resize_grows_and_persists() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let mut record = stored_disk_record(&store);
+
+        apply_disk_resize(&store, &mut record, 40, async { Ok(()) }).await.unwrap();
+        assert_eq!(record.disk_size_gb, 40);
+        assert_eq!(store.get_vm("vm-1").unwrap().unwrap().disk_size_gb, 40);
+    }
+
+    #[tokio::test]
+    async fn test_apply_disk_resize_failure_keeps_record() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let mut record = stored_disk_record(&store);
+
+        let resize = async { Err(Error::QemuError("qemu-img resize failed".to_string())) };
+        assert!(apply_disk_resize(&store, &mut record, 40, resize).await.is_err());
+        assert_eq!(record.disk_size_gb, 20);
+        assert_eq!(store.get_vm("vm-1").unwrap().unwrap().disk_size_gb, 20);
+    }
+
+    #[test]
+    fn test_claim_import_id_regenerates_on_collision() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let disks_dir = temp_dir.path().join("disks");
+        std::fs::create_dir_all(&disks_dir).unwrap();
+        // An import carrying the id of an existing VM must not land on its disk
+        let existing = stored_disk_record(&store);
+
+        let conflicts = import_disk_conflicts(&store, &disks_dir, &existing.id).unwrap();
+        assert_eq!(conflicts, vec!["vm:vm-1".to_string()]);
+        let claimed = claim_import_id(&store, &disks_dir, &existing.id).unwrap();
+        assert_ne!(claimed, existing.id);
+        assert!(import_disk_conflicts(&store, &disks_dir, &claimed).unwrap().is_empty());
+
+        // An orphaned disk file with no record also blocks the id
+        std::fs::write(disks_dir.join("orphan.qcow2"), b"qcow").unwrap();
+        assert_ne!(claim_import_id(&store, &disks_dir, "orphan").unwrap(), "orphan");
+
+        assert_eq!(claim_import_id(&store, &disks_dir, "fresh").unwrap(), "fresh");
+    }
+
+    #[test]
+    fn test_ensure_unique_name_rejects_duplicates_when_enabled() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let mut record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Ubuntu".to_string(),
+            ..VMRecord::default()
+        };
+        store.create_vm(&record).unwrap();
+        record.id = "vm-2".to_string();
+        store.create_vm(&record).unwrap();
+
+        // Off by default, so existing duplicate names keep working
+        assert!(ensure_unique_name(&store, "Ubuntu", None).is_ok());
+
+        store.save_setting(UNIQUE_NAMES_SETTING, "true").unwrap();
+        let err = ensure_unique_name(&store, "ubuntu", None).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Conflict);
+        assert_eq!(err.details, Some(serde_json::json!({ "field": "name" })));
+        assert!(ensure_unique_name(&store, "Ubuntu", Some("Ubuntu")).is_ok());
+        assert!(ensure_unique_name(&store, "Fedora", None).is_ok());
+    }
+
+    #[test]
+    fn test_build_start_args_raw_device() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Passthrough".to_string(),
+            raw_device_path: Some("/dev/sdb".to_string()),
+            ..VMRecord::default()
+        };
+
+        let args = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect("args should build");
+        assert!(args.contains(&"file=/dev/sdb,format=raw,if=virtio,id=disk0,cache=none,aio=native".to_string()));
+        assert!(!args.iter().any(|arg| arg.contains("vm-1.qcow2")));
+    }
+
+    #[test]
+    fn test_discard_applies_to_primary_disk() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Passthrough".to_string(),
+            ..VMRecord::default()
+        };
+        let mut devices = test_devices();
+        devices.disk.discard = true;
+        let args = build_start_args(&record, &devices, "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect("args should build");
+        assert!(args.contains(
+            &"file=/tmp/vm-1.qcow2,format=qcow2,if=virtio,id=disk0,discard=unmap,detect-zeroes=unmap".to_string()
+        ));
+
+        devices.disk.interface = qemu::VIRTIO_SCSI_INTERFACE.to_string();
+        let args = build_start_args(&record, &devices, "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .expect("args should build");
+        assert!(args.contains(&"virtio-scsi-pci,id=scsi0".to_string()));
+        assert!(args.contains(
+            &"file=/tmp/vm-1.qcow2,format=qcow2,if=none,id=disk0,discard=unmap,detect-zeroes=unmap".to_string()
+        ));
+        assert!(args.contains(&"scsi-hd,drive=disk0,bus=scsi0.0".to_string()));
+    }
+
+    #[test]
+    fn test_affinity_warning_only_when_unsupported() {
+        assert!(affinity_warning(&[]).is_none());
+        assert_eq!(affinity_warning(&[0]).is_some(), !platform::supports_cpu_affinity());
+    }
+
+    #[test]
+    fn test_build_display_session_defaults() {
+        let session = spice_session("vm-1", test_display_port("vm-1"), true, None);
+        assert_eq!(session.protocol, "spice");
+        assert!(session.uri.starts_with("spice://127.0.0.1:"));
+        assert_eq!(session.status, "connected");
+        assert_eq!(session.reconnect_attempts, 0);
+        assert!(session.clipboard_sharing);
+    }
+
+    #[test]
+    fn test_spice_password_stays_out_of_argv() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Desktop".to_string(),
+            ..VMRecord::default()
+        };
+        let password = generate_spice_password();
+        let password_file = "/run/openutm/spice-vm-1";
+
+        let secrets = DisplaySecrets { password_file: Some(password_file), x509_dir: None };
+        let joined = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &secrets, &native_host(None))
+            .unwrap()
+            .join(" ");
+        assert!(joined.contains("-object secret,id=spice-password,file=/run/openutm/spice-vm-1"));
+        assert!(joined.contains("password-secret=spice-password"));
+        assert!(!joined.contains("disable-ticketing"));
+        assert!(!joined.contains(&password));
+
+        // Ticketing turned off keeps the old passwordless server
+        let joined = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .unwrap()
+            .join(" ");
+        assert!(joined.contains("disable-ticketing=on"));
+        assert!(!joined.contains("password-secret"));
+    }
+
+    #[test]
+    fn test_spice_tls_args_for_remote_display() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Desktop".to_string(),
+            display_listen_address: Some("192.168.1.20".to_string()),
+            spice_tls_port: Some(5999),
+            ..VMRecord::default()
+        };
+        let remote = DisplaySecrets {
+            password_file: Some("/run/openutm/spice-vm-1"),
+            x509_dir: Some("/data/spice-tls/vm-1"),
+        };
+
+        let joined = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &remote, &native_host(None))
+            .unwrap()
+            .join(" ");
+        assert!(joined.contains("tls-port=5999,x509-dir=/data/spice-tls/vm-1"));
+        assert!(joined.contains("addr=192.168.1.20"));
+        assert!(joined.contains("password-secret=spice-password"));
+
+        // Without ticketing the remote bind is refused rather than left open
+        let no_password = DisplaySecrets { password_file: None, ..remote };
+        let err = build_start_args(&record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &no_password, &native_host(None))
+            .expect_err("remote display needs a password");
+        assert_eq!(err.message_key, "display.remote.ticketingRequired");
+        let no_tls = VMRecord { spice_tls_port: None, ..record.clone() };
+        let err = build_start_args(&no_tls, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &remote, &native_host(None))
+            .expect_err("remote display needs TLS");
+        assert_eq!(err.message_key, "display.remote.tlsRequired");
+
+        // A plain session stays on loopback with no TLS listener
+        let plain = VMRecord { display_listen_address: None, spice_tls_port: None, ..record };
+        let joined = build_start_args(&plain, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+            .unwrap()
+            .join(" ");
+        assert!(joined.contains("addr=127.0.0.1"));
+        assert!(!joined.contains("tls-port"));
+        assert!(!joined.contains("x509-dir"));
+    }
+
+    #[test]
+    fn test_validate_remote_display() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Desktop".to_string(),
+            ..VMRecord::default()
+        };
+
+        let port = test_display_port("vm-1");
+        let none = BTreeSet::new();
+        assert!(validate_remote_display(&record, port, Some("127.0.0.1"), None, false, &none).is_ok());
+        assert!(validate_remote_display(&record, port, Some("0.0.0.0"), Some(5999), true, &none).is_ok());
+        let err = validate_remote_display(&record, port, Some("0.0.0.0"), None, false, &none).unwrap_err();
+        assert_eq!(err.message_key, "display.remote.ticketingRequired");
+        let err = validate_remote_display(&record, port, Some("0.0.0.0"), None, true, &none).unwrap_err();
+        assert_eq!(err.message_key, "display.remote.tlsRequired");
+        let err = validate_remote_display(&record, port, Some("lan-host"), None, true, &none).unwrap_err();
+        assert_eq!(err.message_key, "display.remote.invalidAddress");
+        let err = validate_remote_display(&record, port, None, Some(443), true, &none).unwrap_err();
+        assert_eq!(err.message_key, "display.tls.invalidPort");
+        let err = validate_remote_display(&record, port, None, Some(port), true, &none).unwrap_err();
+        assert_eq!(err.message_key, "display.tls.invalidPort");
+        let taken = BTreeSet::from([5999]);
+        let err = validate_remote_display(&record, port, None, Some(5999), true, &taken).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Conflict);
+        assert_eq!(err.message_key, "display.tls.portInUse");
+
+        let vnc = VMRecord { display_mode: "vnc".to_string(), ..record };
+        let err = validate_remote_display(&vnc, port, Some("192.168.1.20"), None, true, &none).unwrap_err();
+        assert_eq!(err.message_key, "display.remote.ticketingRequired");
+        let err = validate_remote_display(&vnc, port, None, Some(5999), true, &none).unwrap_err();
+        assert_eq!(err.message_key, "display.tls.spiceOnly");
+    }
+
+    #[test]
+    fn test_display_host() {
+        assert_eq!(display_host(None), "127.0.0.1");
+        assert_eq!(display_host(Some("0.0.0.0")), "127.0.0.1");
+        assert_eq!(display_host(Some("192.168.1.20")), "192.168.1.20");
+        let mut session = build_display_session("vm-1", "fd00::2", 5900, "spice", "connected", false, None);
+        assert_eq!(session.uri, "spice://[fd00::2]:5900");
+        set_session_password(&mut session, Some("secret".to_string()));
+        assert_eq!(session.uri, "spice://[fd00::2]:5900?password=secret");
+    }
+
+    #[test]
+    fn test_certificate_names() {
+        assert_eq!(certificate_names(None), vec!["localhost", "127.0.0.1"]);
+        assert_eq!(certificate_names(Some("192.168.1.20")), vec!["localhost", "127.0.0.1", "192.168.1.20"]);
+        assert_eq!(certificate_names(Some("::1")), vec!["localhost", "127.0.0.1"]);
+    }
+
+    #[test]
+    fn test_config_changes_between_launches() {
+        let record = VMRecord {
+            id: "vm-1".to_string(),
+            name: "Desktop".to_string(),
+            ..VMRecord::default()
+        };
+        let args_for = |record: &VMRecord| {
+            build_start_args(record, &test_devices(), "/tmp/qmp.sock", "/tmp/monitor.sock", None, &DisplaySecrets::default(), &native_host(None))
+                .unwrap()
+        };
+        let applied = args_for(&record);
+        assert!(config_changes(&applied, &applied).is_empty());
+
+        let renamed = VMRecord { name: "Renamed".to_string(), ..record.clone() };
+        let changes = config_changes(&applied, &args_for(&renamed));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "name");
+        assert!(!changes[0].requires_restart);
+
+        let resized = VMRecord { memory_mb: 4096, clipboard_sharing: true, ..record.clone() };
+        let changes = config_changes(&applied, &args_for(&resized));
+        let fields: Vec<&str> = changes.iter().map(|change| change.field.as_str()).collect();
+        assert_eq!(fields, vec!["clipboardSharing", "memoryMb"]);
+        assert!(changes.iter().all(|change| change.requires_restart));
+        assert_eq!(changes[0].applied, None);
+        assert_eq!(changes[1].applied.as_deref(), Some("-m 2048"));
+        assert_eq!(changes[1].pending.as_deref(), Some("-m 4096"));
+    }
+
+    #[test]
+    fn test_launch_accelerator() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(launch_accelerator(&args(&["-m", "2048", "-accel", "tcg,thread=multi"])).as_deref(), Some("tcg"));
+        assert_eq!(launch_accelerator(&args(&["-accel", "kvm"])).as_deref(), Some("kvm"));
+        assert_eq!(launch_accelerator(&args(&["-m", "2048"])), None);
+        assert_eq!(launch_accelerator(&args(&["-accel"])), None);
+    }
+
+    #[test]
+    fn test_active_accelerator() {
+        assert_eq!(active_accelerator(Some("kvm"), true), ("kvm".to_string(), true));
+        // KVM requested but not enabled means the guest is being emulated
+        assert_eq!(active_accelerator(Some("kvm"), false), ("tcg".to_string(), false));
+        assert_eq!(active_accelerator(Some("hvf"), false), ("hvf".to_string(), true));
+        assert_eq!(active_accelerator(Some("whpx"), false), ("whpx".to_string(), true));
+        assert_eq!(active_accelerator(Some("tcg"), false), ("tcg".to_string(), false));
+        assert_eq!(active_accelerator(None, false), ("tcg".to_string(), false));
+    }
+
+    #[test]
+    fn test_rotating_password_updates_session() {
+        let first = generate_spice_password();
+        let port = test_display_port("vm-1");
+        let mut session = spice_session("vm-1", port, false, Some(first.clone()));
+        assert_eq!(session.password_token.as_deref(), Some(first.as_str()));
+        assert!(session.uri.ends_with(&format!("?password={}", first)));
+
+        let second = generate_spice_password();
+        assert_ne!(first, second);
+        set_session_password(&mut session, Some(second.clone()));
+        assert_eq!(session.password_token.as_deref(), Some(second.as_str()));
+        assert!(session.uri.ends_with(&format!("?password={}", second)));
+        assert!(!session.uri.contains(&first));
+
+        set_session_password(&mut session, None);
+        assert_eq!(session.uri, format!("spice://127.0.0.1:{}", session.port));
+    }
+
+    #[test]
+    fn test_boot_timings_use_last_launch() {
+        let events = vec![
+            event(EVENT_PROCESS_START, 100),
+            event(EVENT_QMP_READY, 400),
+            event(EVENT_BOOT, 2_000),
+            event(EVENT_PROCESS_START, 10_000),
+            event(EVENT_QMP_READY, 10_300),
+        ];
+        assert_eq!(
+            boot_timings(&events),
+            Some(BootTimings {
+                process_start_ms: 10_000,
+                qmp_ready_ms: 10_300,
+                boot_event_ms: None,
+            })
+        );
+
+        let booted = [&events[..], &[event(EVENT_BOOT, 12_500)]].concat();
+        assert_eq!(boot_timings(&booted).unwrap().boot_event_ms, Some(12_500));
+    }
+
+    #[test]
+    fn test_boot_timings_need_qmp_ready() {
+        assert_eq!(boot_timings(&[]), None);
+        // A launch that never reached QMP has no timings, even if an older one did
+        let events = vec![
+            event(EVENT_PROCESS_START, 100),
+            event(EVENT_QMP_READY, 400),
+            event(EVENT_PROCESS_START, 900),
+        ];
+        assert_eq!(boot_timings(&events), None);
+        // A boot event before QMP was ready belongs to nothing
+        let events = vec![event(EVENT_PROCESS_START, 100), event(EVENT_BOOT, 200), event(EVENT_QMP_READY, 400)];
+        assert_eq!(boot_timings(&events).unwrap().boot_event_ms, None);
+    }
+
+    #[test]
+    fn test_connected_session_records_timestamp() {
+        let before = chrono::Utc::now();
+        let mut session = spice_session("vm-1", test_display_port("vm-1"), false, None);
+        let connected_at = chrono::DateTime::parse_from_rfc3339(session.connected_at.as_deref().unwrap()).unwrap();
+        assert!(connected_at >= before - chrono::Duration::seconds(1));
+
+        let later = connected_at.with_timezone(&chrono::Utc) + chrono::Duration::seconds(42);
+        assert_eq!(seconds_since_connected(session.connected_at.as_deref(), later), Some(42));
+        assert_eq!(seconds_since_connected(None, later), None);
+
+        session.status = "error".to_string();
+        session.last_error = Some("gone".to_string());
+        session.connected_at = None;
+        mark_connected(&mut session);
+        assert_eq!(session.status, "connected");
+        assert_eq!(session.last_error, None);
+        assert!(session.connected_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_probe_flips_session_when_port_closes() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut session = spice_session("vm-1", test_display_port("vm-1"), false, None);
+        session.port = listener.local_addr().unwrap().port();
+        let sessions = tokio::sync::Mutex::new(HashMap::from([("vm-1".to_string(), session)]));
+
+        probe_display_sessions(&sessions).await;
+        assert_eq!(sessions.lock().await["vm-1"].status, "connected");
+
+        drop(listener);
+        probe_display_sessions(&sessions).await;
+        let session = sessions.lock().await["vm-1"].clone();
+        assert_eq!(session.status, "error");
+        assert!(session.last_error.unwrap().contains("not accepting connections"));
+    }
+
+    #[test]
+    fn test_display_sessions_get_unique_ids() {
+        let ids: std::collections::HashSet<String> = (0..50)
+            .map(|_| spice_session("vm-1", 5900, false, None).session_id)
+            .collect();
+        assert_eq!(ids.len(), 50);
+    }
+
+    #[test]
+    fn test_sweep_drops_missing_and_long_stopped_vms() {
+        let now = chrono::Utc::now();
+        let disconnected_for = |vm_id: &str, secs: i64| {
+            let mut session = spice_session(vm_id, test_display_port(vm_id), false, None);
+            mark_disconnected(&mut session, "VM stopped");
+            session.disconnected_at = Some((now - chrono::Duration::seconds(secs)).to_rfc3339());
+            (vm_id.to_string(), session)
+        };
+        let sessions = HashMap::from([
+            disconnected_for("deleted", 1),
+            disconnected_for("stopped-long", 600),
+            disconnected_for("stopped-recently", 30),
+            disconnected_for("closed-but-running", 600),
+            (
+                "connected".to_string(),
+                spice_session("connected", test_display_port("connected"), false, None),
+            ),
+        ]);
+
+        let vm_exists = |vm_id: &str| vm_id != "deleted";
+        let is_running = |vm_id: &str| vm_id == "closed-but-running" || vm_id == "connected";
+        let mut stale = stale_display_sessions(&sessions, vm_exists, is_running, 300, now);
+        stale.sort();
+        assert_eq!(stale, vec!["deleted".to_string(), "stopped-long".to_string()]);
+
+        // A longer grace keeps the stopped VM's session a while more
+        assert_eq!(stale_display_sessions(&sessions, vm_exists, is_running, 3600, now), vec!["deleted".to_string()]);
+    }
+
+    #[test]
+    fn test_hotplug_names_are_valid_qemu_ids() {
+        let drive_id = Uuid::new_v4();
+        let (device_id, node_name) = hotplug_names(&drive_id);
+        assert_eq!(device_id, format!("disk-{}", drive_id.simple()));
+        assert!(node_name.starts_with("node-"));
+        assert!(node_name.len() <= 31);
+        assert_ne!(hotplug_names(&Uuid::new_v4()).1, node_name);
+    }
+
+    #[test]
+    fn test_health_check_marks_after_threshold_misses() {
+        let mut misses = HashMap::new();
+        assert_eq!(record_health_check(&mut misses, "vm-1", false, 3), HealthTransition::Unchanged);
+        assert_eq!(record_health_check(&mut misses, "vm-1", false, 3), HealthTransition::Unchanged);
+        // An answer in between starts the count over
+        assert_eq!(record_health_check(&mut misses, "vm-1", true, 3), HealthTransition::Unchanged);
+        assert!(misses.is_empty());
+
+        for _ in 0..2 {
+            record_health_check(&mut misses, "vm-1", false, 3);
+        }
+        assert_eq!(record_health_check(&mut misses, "vm-1", false, 3), HealthTransition::NotResponding);
+        // Reported once, not on every further miss
+        assert_eq!(record_health_check(&mut misses, "vm-1", false, 3), HealthTransition::Unchanged);
+        assert_eq!(record_health_check(&mut misses, "vm-1", true, 3), HealthTransition::Recovered);
+    }
+
+    #[test]
+    fn test_qmp_timeouts_during_boot_are_not_misses() {
+        let timeout = Error::QmpTimeout("query-status".to_string());
+        assert!(!counts_as_miss(&timeout, std::time::Duration::from_secs(30)));
+        assert!(counts_as_miss(&timeout, HEALTH_CHECK_BOOT_WINDOW));
+        // A refused or closed socket is a real failure even while booting
+        let closed = Error::QemuError("QMP socket closed".to_string());
+        assert!(counts_as_miss(&closed, std::time::Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_reconnect_backoff_until_failed() {
+        let start = chrono::Utc::now();
+        let mut session = spice_session("vm-1", test_display_port("vm-1"), false, None);
+        let mut now = start;
+        let mut schedule = Vec::new();
+        for _ in 0..5 {
+            // The health probe finds the port dead again
+            session.status = "error".to_string();
+            begin_reconnect(&mut session, 5, now).unwrap();
+            assert_eq!(session.status, "connected");
+            let next = chrono::DateTime::parse_from_rfc3339(session.next_retry_at.as_deref().unwrap()).unwrap();
+            let delay = next.signed_duration_since(now).num_milliseconds();
+            schedule.push(delay);
+
+            // Retrying early is refused with the remaining wait
+            session.status = "error".to_string();
+            let err = begin_reconnect(&mut session, 5, now + chrono::Duration::milliseconds(delay / 2)).unwrap_err();
+            assert_eq!(err.code, ErrorCode::RateLimited);
+            assert_eq!(err.message_key, "display.reconnect.tooSoon");
+            assert_eq!(err.details.unwrap()["retryAfterMs"], delay - delay / 2);
+            now = next.with_timezone(&chrono::Utc);
+        }
+        assert_eq!(schedule, vec![1_000, 2_000, 4_000, 8_000, 16_000]);
+        assert_eq!(session.reconnect_attempts, 5);
+
+        let err = begin_reconnect(&mut session, 5, now).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Conflict);
+        assert_eq!(err.message_key, "display.reconnect.failed");
+        assert_eq!(session.status, "failed");
+        // Failed is terminal, however long the frontend waits
+        let later = now + chrono::Duration::hours(1);
+        assert_eq!(begin_reconnect(&mut session, 5, later).unwrap_err().message_key, "display.reconnect.failed");
+
+        reset_reconnects(&mut session);
+        session.status = "disconnected".to_string();
+        begin_reconnect(&mut session, 5, later).unwrap();
+        assert_eq!(session.reconnect_attempts, 1);
+    }
+
+    #[test]
+    fn test_reconnect_delay_is_capped() {
+        assert_eq!(reconnect_delay_ms(1), DISPLAY_RECONNECT_BASE_DELAY_MS);
+        assert_eq!(reconnect_delay_ms(7), DISPLAY_RECONNECT_MAX_DELAY_MS);
+        assert_eq!(reconnect_delay_ms(u32::MAX), DISPLAY_RECONNECT_MAX_DELAY_MS);
+    }
+
+    #[test]
+    fn test_disconnect_time_survives_repeat_disconnects() {
+        let mut session = spice_session("vm-1", test_display_port("vm-1"), false, None);
+        assert_eq!(session.disconnected_at, None);
+
+        mark_disconnected(&mut session, "VM stopped");
+        session.disconnected_at = Some("2026-01-01T00:00:00+00:00".to_string());
+        refresh_session_liveness(&mut session, false, chrono::Utc::now());
+        assert_eq!(session.disconnected_at.as_deref(), Some("2026-01-01T00:00:00+00:00"));
+        assert_eq!(session.last_error.as_deref(), Some("VM stopped"));
+
+        mark_connected(&mut session);
+        assert_eq!(session.disconnected_at, None);
+    }
+}
@@ -0,0 +1,332 @@
+//! Disk and acceleration benchmarks for diagnosing slow VMs
+//!
+//! The disk test writes a scratch file in the storage dir with `qemu-img
+//! bench`, falling back to timed plain writes when qemu-img is missing or
+//! cannot open the file uncached. The acceleration test times QEMU from spawn
+//! to its QMP greeting under TCG and under the hardware accelerator, which is
+//! dominated by accelerator setup. Measurements block, so callers run them
+//! off the async runtime.
+
+use crate::error::Error;
+use crate::qemu::Accelerator;
+use crate::Result;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Reports kept in the settings history, oldest dropped first
+pub const MAX_BENCHMARK_HISTORY: usize = 20;
+
+const SCRATCH_FILE: &str = ".openutm-benchmark.img";
+const DISK_TEST_MB: u64 = 64;
+const CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Longest a benchmark QEMU gets to answer on QMP before the run is abandoned
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What is being measured and where
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkContext {
+    /// `None` benchmarks the host as a whole
+    pub vm_id: Option<String>,
+    pub host_arch: String,
+    /// Guest architecture the startup test boots
+    pub arch: String,
+    /// Accelerator compared against TCG; TCG alone is measured once
+    pub accelerator: Accelerator,
+    /// Directory the disk test writes to
+    pub storage_dir: PathBuf,
+    /// The VM's disk bus and format, such as `virtio qcow2`
+    pub disk_type: Option<String>,
+    pub ran_at_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskBenchmark {
+    /// `qemu-img-bench`, or `write` for the fallback
+    pub method: String,
+    pub bytes: u64,
+    pub mb_per_sec: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupBenchmark {
+    pub accelerator: String,
+    /// Spawn to QMP greeting; `None` when QEMU failed to start
+    pub ready_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkReport {
+    pub vm_id: Option<String>,
+    pub ran_at_ms: u64,
+    pub host_arch: String,
+    pub arch: String,
+    pub accelerator: String,
+    pub storage_dir: String,
+    pub disk_type: Option<String>,
+    pub disk: Option<DiskBenchmark>,
+    pub disk_error: Option<String>,
+    /// TCG first, then the accelerator when it is not TCG
+    pub startup: Vec<StartupBenchmark>,
+    /// How many times faster the accelerator started than TCG
+    pub speedup: Option<f64>,
+}
+
+/// Run both tests with the given measurements and assemble the report; a
+/// failed measurement is reported instead of failing the whole run
+pub fn run_benchmark(
+    context: &BenchmarkContext,
+    measure_disk: impl FnOnce(&Path) -> Result<DiskBenchmark>,
+    mut measure_startup: impl FnMut(&Accelerator) -> Result<Duration>,
+) -> BenchmarkReport {
+    let (disk, disk_error) = match measure_disk(&context.storage_dir) {
+        Ok(disk) => (Some(disk), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+
+    let mut accelerators = vec![Accelerator::Tcg];
+    if context.accelerator != Accelerator::Tcg {
+        accelerators.push(context.accelerator.clone());
+    }
+    let timings: Vec<(Accelerator, Result<Duration>)> = accelerators
+        .into_iter()
+        .map(|accelerator| {
+            let timing = measure_startup(&accelerator);
+            (accelerator, timing)
+        })
+        .collect();
+    let speedup = match timings.as_slice() {
+        [(_, Ok(tcg)), (_, Ok(accelerated))] if !accelerated.is_zero() => {
+            Some(tcg.as_secs_f64() / accelerated.as_secs_f64())
+        }
+        _ => None,
+    };
+    let startup = timings
+        .into_iter()
+        .map(|(accelerator, timing)| StartupBenchmark {
+            accelerator: accelerator.as_str().to_string(),
+            ready_ms: timing.as_ref().ok().map(|elapsed| elapsed.as_millis() as u64),
+            error: timing.err().map(|err| err.to_string()),
+        })
+        .collect();
+
+    BenchmarkReport {
+        vm_id: context.vm_id.clone(),
+        ran_at_ms: context.ran_at_ms,
+        host_arch: context.host_arch.clone(),
+        arch: context.arch.clone(),
+        accelerator: context.accelerator.as_str().to_string(),
+        storage_dir: context.storage_dir.display().to_string(),
+        disk_type: context.disk_type.clone(),
+        disk,
+        disk_error,
+        startup,
+        speedup,
+    }
+}
+
+/// Sequential write throughput in `dir`; the scratch file is always removed
+pub fn measure_disk(dir: &Path) -> Result<DiskBenchmark> {
+    let path = dir.join(SCRATCH_FILE);
+    let result = qemu_img_bench(&path).or_else(|err| {
+        tracing::debug!(error = %err, "qemu-img bench unavailable, timing plain writes");
+        timed_writes(&path)
+    });
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+fn qemu_img_bench(path: &Path) -> Result<DiskBenchmark> {
+    let path_arg = path.display().to_string();
+    let size = format!("{}M", DISK_TEST_MB);
+    run_tool("qemu-img", &["create", "-q", "-f", "raw", &path_arg, &size])?;
+    let count = DISK_TEST_MB.to_string();
+    let output = run_tool(
+        "qemu-img",
+        &["bench", "-w", "-t", "none", "-f", "raw", "-c", &count, "-s", "1M", &path_arg],
+    )?;
+    let seconds = parse_bench_seconds(&output)
+        .ok_or_else(|| Error::QemuError(format!("unexpected qemu-img bench output: {}", output.trim())))?;
+    Ok(throughput("qemu-img-bench", DISK_TEST_MB * CHUNK_BYTES as u64, seconds))
+}
+
+fn timed_writes(path: &Path) -> Result<DiskBenchmark> {
+    let chunk = vec![0x5a_u8; CHUNK_BYTES];
+    let started = Instant::now();
+    let mut file = std::fs::File::create(path)?;
+    for _ in 0..DISK_TEST_MB {
+        file.write_all(&chunk)?;
+    }
+    file.sync_all()?;
+    Ok(throughput("write", DISK_TEST_MB * CHUNK_BYTES as u64, started.elapsed().as_secs_f64()))
+}
+
+fn throughput(method: &str, bytes: u64, seconds: f64) -> DiskBenchmark {
+    DiskBenchmark {
+        method: method.to_string(),
+        bytes,
+        mb_per_sec: bytes as f64 / (1024.0 * 1024.0) / seconds.max(f64::EPSILON),
+    }
+}
+
+fn run_tool(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        return Err(Error::QemuError(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// `Run completed in 0.412 seconds.` from `qemu-img bench`
+fn parse_bench_seconds(output: &str) -> Option<f64> {
+    output.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("Run completed in ")?
+            .strip_suffix(" seconds.")?
+            .parse()
+            .ok()
+    })
+}
+
+/// Spawn `binary` with no machine and time it until QMP greets; QEMU is
+/// killed afterwards either way
+pub fn measure_startup(binary: &Path, accelerator: &Accelerator, qmp_socket: &Path) -> Result<Duration> {
+    let _ = std::fs::remove_file(qmp_socket);
+    let started = Instant::now();
+    let mut child = Command::new(binary)
+        .args(["-machine", "none", "-accel", accelerator.as_str(), "-nodefaults", "-display", "none", "-qmp"])
+        .arg(format!("unix:{},server=on,wait=off", qmp_socket.display()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let ready = wait_for_greeting(&mut child, qmp_socket, started);
+    let elapsed = started.elapsed();
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_file(qmp_socket);
+    ready.map(|()| elapsed)
+}
+
+#[cfg(unix)]
+fn wait_for_greeting(child: &mut std::process::Child, qmp_socket: &Path, started: Instant) -> Result<()> {
+    use std::io::BufRead;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Err(Error::QemuError(format!("QEMU exited during startup ({})", status)));
+        }
+        if let Ok(stream) = std::os::unix::net::UnixStream::connect(qmp_socket) {
+            stream.set_read_timeout(Some(STARTUP_TIMEOUT))?;
+            let mut greeting = String::new();
+            std::io::BufReader::new(stream).read_line(&mut greeting)?;
+            return Ok(());
+        }
+        if started.elapsed() > STARTUP_TIMEOUT {
+            return Err(Error::QmpTimeout("greeting".to_string()));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_for_greeting(_child: &mut std::process::Child, _qmp_socket: &Path, _started: Instant) -> Result<()> {
+    Err(Error::PlatformError("QMP over unix sockets is not supported on this platform".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(accelerator: Accelerator) -> BenchmarkContext {
+        BenchmarkContext {
+            vm_id: Some("vm-1".to_string()),
+            host_arch: "x86_64".to_string(),
+            arch: "x86_64".to_string(),
+            accelerator,
+            storage_dir: PathBuf::from("/data/disks"),
+            disk_type: Some("virtio qcow2".to_string()),
+            ran_at_ms: 1_700_000_000_000,
+        }
+    }
+
+    fn disk(dir: &Path) -> Result<DiskBenchmark> {
+        assert_eq!(dir, Path::new("/data/disks"));
+        Ok(throughput("qemu-img-bench", 64 * 1024 * 1024, 0.5))
+    }
+
+    #[test]
+    fn test_report_compares_accelerator_with_tcg() {
+        let report = run_benchmark(&context(Accelerator::Kvm), disk, |accelerator| match accelerator {
+            Accelerator::Tcg => Ok(Duration::from_millis(600)),
+            _ => Ok(Duration::from_millis(150)),
+        });
+
+        assert_eq!(report.vm_id.as_deref(), Some("vm-1"));
+        assert_eq!(report.accelerator, "kvm");
+        assert_eq!(report.storage_dir, "/data/disks");
+        assert_eq!(report.disk.as_ref().unwrap().mb_per_sec, 128.0);
+        assert_eq!(report.disk_error, None);
+        let timings: Vec<(&str, Option<u64>)> = report
+            .startup
+            .iter()
+            .map(|startup| (startup.accelerator.as_str(), startup.ready_ms))
+            .collect();
+        assert_eq!(timings, vec![("tcg", Some(600)), ("kvm", Some(150))]);
+        assert_eq!(report.speedup, Some(4.0));
+    }
+
+    #[test]
+    fn test_report_keeps_failed_measurements() {
+        let report = run_benchmark(
+            &context(Accelerator::Kvm),
+            |_| Err(Error::PlatformError("read-only filesystem".to_string())),
+            |accelerator| match accelerator {
+                Accelerator::Tcg => Ok(Duration::from_millis(600)),
+                _ => Err(Error::QemuError("Could not access KVM kernel module".to_string())),
+            },
+        );
+
+        assert_eq!(report.disk, None);
+        assert!(report.disk_error.unwrap().contains("read-only filesystem"));
+        assert_eq!(report.startup[1].ready_ms, None);
+        assert!(report.startup[1].error.as_ref().unwrap().contains("KVM"));
+        assert_eq!(report.speedup, None);
+    }
+
+    #[test]
+    fn test_tcg_only_host_measures_once() {
+        let mut runs = 0;
+        let report = run_benchmark(&context(Accelerator::Tcg), disk, |_| {
+            runs += 1;
+            Ok(Duration::from_millis(600))
+        });
+        assert_eq!(runs, 1);
+        assert_eq!(report.startup.len(), 1);
+        assert_eq!(report.speedup, None);
+    }
+
+    #[test]
+    fn test_parse_bench_seconds() {
+        let output = "Sending 64 write requests, 1048576 bytes each, 64 in parallel\n\
+                      Run completed in 0.412 seconds.\n";
+        assert_eq!(parse_bench_seconds(output), Some(0.412));
+        assert_eq!(parse_bench_seconds("qemu-img: Could not open"), None);
+    }
+
+    #[test]
+    fn test_measure_disk_removes_scratch_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let result = measure_disk(temp_dir.path()).unwrap();
+        assert!(result.mb_per_sec > 0.0);
+        assert_eq!(result.bytes, DISK_TEST_MB * CHUNK_BYTES as u64);
+        assert!(!temp_dir.path().join(SCRATCH_FILE).exists());
+    }
+}
@@ -0,0 +1,3148 @@
+use crate::Result;
+use crate::benchmark::{BenchmarkReport, MAX_BENCHMARK_HISTORY};
+use crate::error::Error;
+use crate::memory_policy::MemoryPolicy;
+use crate::qemu::{BootMenuConfig, DisplayPortRange, NetworkConfig, SpiceCompression, VmPerformance};
+use rusqlite::{Connection, params};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Clone)]
+pub struct ConfigStore {
+    backend: Backend,
+}
+
+#[derive(Clone)]
+enum Backend {
+    /// A database file, opened afresh for every call
+    File(PathBuf),
+    /// One in-memory database shared by every clone of the store
+    Memory(Arc<Mutex<Connection>>),
+}
+
+/// The connection one `ConfigStore` call works with
+enum StoreConnection<'a> {
+    Owned(Connection),
+    Shared(MutexGuard<'a, Connection>),
+}
+
+impl Deref for StoreConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            Self::Owned(conn) => conn,
+            Self::Shared(conn) => conn,
+        }
+    }
+}
+
+impl DerefMut for StoreConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        match self {
+            Self::Owned(conn) => conn,
+            Self::Shared(conn) => conn,
+        }
+    }
+}
+
+/// Setting that, when "true", makes VM names unique (case-insensitive)
+pub const UNIQUE_NAMES_SETTING: &str = "unique_names";
+
+/// Setting that, when "false", starts SPICE without a password (the old behavior)
+pub const SPICE_TICKETING_SETTING: &str = "spice_ticketing";
+
+/// Setting that, when "true", opens external viewers fullscreen
+pub const VIEWER_FULLSCREEN_SETTING: &str = "viewer_fullscreen";
+
+/// Setting holding the address the Prometheus metrics server listens on; empty or unset leaves it off
+pub const METRICS_LISTEN_SETTING: &str = "metrics_listen_address";
+
+/// Setting that, when "true", serves the JSON-RPC control socket
+pub const CONTROL_SOCKET_SETTING: &str = "control_socket";
+
+/// Setting holding the token every control socket request must carry
+pub const CONTROL_TOKEN_SETTING: &str = "control_token";
+
+/// Setting holding a user-chosen QEMU binary that replaces the detected one
+pub const QEMU_BINARY_KEY: &str = "qemu_binary";
+
+/// Setting holding the directory disks were migrated to; unset means the default disks dir
+pub const STORAGE_DIR_KEY: &str = "storage_dir";
+
+/// Setting holding the most recent benchmark reports as a JSON array, oldest first
+pub const BENCHMARK_HISTORY_SETTING: &str = "benchmark_history";
+
+/// Setting holding how many seconds a stopped VM's display session is kept
+pub const DISPLAY_SESSION_GRACE_SETTING: &str = "display_session_grace_secs";
+pub const DEFAULT_DISPLAY_SESSION_GRACE_SECS: u64 = 300;
+
+/// Setting holding how many reconnects a display session gets before it is marked failed
+pub const DISPLAY_RECONNECT_ATTEMPTS_SETTING: &str = "display_reconnect_max_attempts";
+pub const DEFAULT_DISPLAY_RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Settings holding the first and last host port display servers are assigned from
+pub const DISPLAY_PORT_RANGE_START_SETTING: &str = "display_port_range_start";
+pub const DISPLAY_PORT_RANGE_END_SETTING: &str = "display_port_range_end";
+
+/// Setting holding how many seconds pass between QMP health checks of running VMs
+pub const HEALTH_CHECK_INTERVAL_SETTING: &str = "health_check_interval_secs";
+pub const DEFAULT_HEALTH_CHECK_INTERVAL_SECS: u64 = 15;
+
+/// Setting holding how many health checks in a row a VM may miss before it is marked not responding
+pub const HEALTH_CHECK_THRESHOLD_SETTING: &str = "health_check_failure_threshold";
+pub const DEFAULT_HEALTH_CHECK_THRESHOLD: u32 = 3;
+
+/// Settings holding the memory ballooning policy; see `ConfigStore::memory_policy`
+pub const MEMORY_POLICY_ENABLED_SETTING: &str = "memory_policy_enabled";
+pub const MEMORY_POLICY_THRESHOLD_SETTING: &str = "memory_policy_free_threshold_mb";
+pub const MEMORY_POLICY_MINIMUM_SETTING: &str = "memory_policy_vm_minimum_mb";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VMRecord {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+    pub memory_mb: u32,
+    pub cpu_cores: u32,
+    pub disk_size_gb: u32,
+    pub os: String,
+    pub install_media_path: Option<String>,
+    pub boot_order: String,
+    pub network_type: String,
+    pub nested_virt: bool,
+    pub restart_policy: String,
+    pub arch: String,
+    pub cpu_affinity: Vec<u32>,
+    pub priority: i32,
+    pub preallocation: String,
+    pub encryption_key_ref: Option<String>,
+    pub raw_device_path: Option<String>,
+    pub notes: String,
+    pub clipboard_sharing: bool,
+    pub vlan_id: Option<u16>,
+    pub display_resolution: Option<String>,
+    pub graphics: Option<String>,
+    pub machine_type: Option<String>,
+    pub display_mode: String,
+    pub boot_menu: Option<BootMenuConfig>,
+    /// Read from `vm_tags`; not written by `create_vm`/`update_vm`
+    pub tags: Vec<String>,
+    /// NIC MAC address, kept in `networks`; written by `create_vm` (when set) and `set_vm_mac`
+    pub mac_address: Option<String>,
+    /// Address QEMU's display listens on; `None` keeps it on loopback
+    pub display_listen_address: Option<String>,
+    /// SPICE TLS port, served alongside the plain port when set
+    pub spice_tls_port: Option<u16>,
+    /// SPICE compression settings; `None` uses `SpiceCompression::default()`
+    pub spice_compression: Option<SpiceCompression>,
+    /// Boot from a throwaway overlay so guest writes are discarded on stop
+    pub ephemeral: bool,
+    /// Never ballooned by the memory policy
+    pub memory_policy_exempt: bool,
+    /// How guest RAM is backed on the host (`MemoryBackend`); `None` is ordinary memory
+    pub memory_backend: Option<String>,
+    /// I/O thread, NIC multiqueue and AIO tuning; `None` keeps QEMU's defaults
+    pub performance: Option<VmPerformance>,
+    /// Tablet the guest gets (`PointerDevice`); `None` picks one for the guest OS
+    pub pointer_device: Option<String>,
+    /// RTC base (`RtcBase`); `None` picks one for the guest OS
+    pub rtc: Option<String>,
+    /// Attach a VirtIO RNG; `None` follows the guest OS default
+    pub rng: Option<bool>,
+}
+
+/// A stopped x86_64 Linux VM with the settings a new VM gets unless told otherwise
+impl Default for VMRecord {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            name: String::new(),
+            status: "stopped".to_string(),
+            memory_mb: 2048,
+            cpu_cores: 2,
+            disk_size_gb: 20,
+            os: "linux".to_string(),
+            install_media_path: None,
+            boot_order: "disk-first".to_string(),
+            network_type: "nat".to_string(),
+            nested_virt: false,
+            restart_policy: "on-failure".to_string(),
+            arch: "x86_64".to_string(),
+            cpu_affinity: Vec::new(),
+            priority: 0,
+            preallocation: "metadata".to_string(),
+            encryption_key_ref: None,
+            raw_device_path: None,
+            notes: String::new(),
+            clipboard_sharing: false,
+            vlan_id: None,
+            display_resolution: None,
+            graphics: None,
+            machine_type: None,
+            display_mode: "spice".to_string(),
+            boot_menu: None,
+            tags: Vec::new(),
+            mac_address: None,
+            display_listen_address: None,
+            spice_tls_port: None,
+            spice_compression: None,
+            ephemeral: false,
+            memory_policy_exempt: false,
+            memory_backend: None,
+            performance: None,
+            pointer_device: None,
+            rtc: None,
+            rng: None,
+        }
+    }
+}
+
+/// A corrupt config DB that was moved aside and replaced at startup
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseRecovery {
+    pub backup_path: String,
+    pub reason: String,
+    /// Rows copied from the backup into the fresh database
+    pub recovered_rows: usize,
+}
+
+/// Tables copied out of a damaged database, parents before children
+const SALVAGE_TABLES: [&str; 12] = [
+    "vms", "configs", "drives", "networks", "groups", "vm_groups", "vm_tags", "vm_dependencies", "settings", "profiles",
+    "vm_events", "external_snapshots",
+];
+
+/// Lifecycle events kept per VM; older ones are pruned on insert
+const MAX_VM_EVENTS: u32 = 200;
+
+fn is_corruption(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::DatabaseError(rusqlite::Error::SqliteFailure(failure, _))
+            if matches!(failure.code, rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+    )
+}
+
+/// Rename `from` (and any SQLite side files) to `to`
+fn move_database(from: &Path, to: &Path) -> Result<()> {
+    std::fs::rename(from, to)?;
+    for suffix in ["-journal", "-wal", "-shm"] {
+        let side_file = PathBuf::from(format!("{}{}", from.display(), suffix));
+        if side_file.exists() {
+            std::fs::rename(&side_file, format!("{}{}", to.display(), suffix))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GroupRecord {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub created_at: String,
+    /// Member VM ids, read from `vm_groups`
+    #[serde(default)]
+    pub vm_ids: Vec<String>,
+}
+
+/// A qcow2 overlay layered over a VM's disk as an external snapshot. The image
+/// beneath stays frozen at the moment the snapshot was taken.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalSnapshotRecord {
+    pub vm_id: String,
+    pub name: String,
+    /// The overlay, which took over as the VM's disk
+    pub path: String,
+    /// The image the overlay was layered over
+    pub backing_path: String,
+    pub created_at: String,
+}
+
+/// A disk hot-added to a running VM, kept so it is attached again on the next start
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HotpluggedDrive {
+    pub id: String,
+    pub path: String,
+    pub interface: String,
+    pub format: String,
+}
+
+/// A creation-wizard profile; `config` holds only the `VMConfig` fields it presets
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRecord {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub builtin: bool,
+    #[serde(default)]
+    pub config: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A timestamped step in a VM's lifecycle, such as a boot milestone
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VmEvent {
+    pub vm_id: String,
+    pub kind: String,
+    /// Milliseconds since the Unix epoch
+    pub at_ms: u64,
+}
+
+fn row_to_profile(row: &rusqlite::Row) -> rusqlite::Result<ProfileRecord> {
+    let config: String = row.get(3)?;
+    Ok(ProfileRecord {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        builtin: row.get(2)?,
+        config: serde_json::from_str(&config).unwrap_or_default(),
+    })
+}
+
+/// Orderings accepted by `list_vms_paged`; only these map to SQL, so the
+/// caller's sort key never reaches the query text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VmSort {
+    Name,
+    #[default]
+    CreatedAt,
+    Status,
+}
+
+impl VmSort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "name" => Some(Self::Name),
+            "created_at" => Some(Self::CreatedAt),
+            "status" => Some(Self::Status),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::CreatedAt => "created_at",
+            Self::Status => "status",
+        }
+    }
+
+    /// Trailing keys keep pages stable when the primary key ties
+    fn order_by(self) -> &'static str {
+        match self {
+            Self::Name => "name COLLATE NOCASE ASC, id ASC",
+            Self::CreatedAt => "created_at DESC, rowid DESC",
+            Self::Status => "status ASC, name COLLATE NOCASE ASC, id ASC",
+        }
+    }
+
+    /// The expressions of `order_by`, each with whether it sorts descending
+    fn keys(self) -> &'static [(&'static str, bool)] {
+        match self {
+            Self::Name => &[("name COLLATE NOCASE", false), ("id", false)],
+            Self::CreatedAt => &[("created_at", true), ("rowid", true)],
+            Self::Status => &[("status", false), ("name COLLATE NOCASE", false), ("id", false)],
+        }
+    }
+
+    /// Rows strictly after a cursor in this order: `(a > ?) OR (a = ? AND b > ?) ...`
+    fn after_clause(self) -> String {
+        let keys = self.keys();
+        (0..keys.len())
+            .map(|i| {
+                let mut terms: Vec<String> = keys[..i].iter().map(|(key, _)| format!("{} = ?", key)).collect();
+                let (key, descending) = keys[i];
+                terms.push(format!("{} {} ?", key, if descending { "<" } else { ">" }));
+                format!("({})", terms.join(" AND "))
+            })
+            .collect::<Vec<_>>()
+            .join(" OR ")
+    }
+}
+
+/// Where a keyset-paged listing left off: the last row's sort key values under
+/// the sort it was taken with. Unlike an offset it stays put when rows are
+/// inserted or deleted ahead of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmCursor {
+    sort: VmSort,
+    after: Vec<serde_json::Value>,
+}
+
+impl VmCursor {
+    /// The opaque token handed to the frontend
+    pub fn encode(&self) -> String {
+        serde_json::json!({ "sort": self.sort.as_str(), "after": self.after }).to_string()
+    }
+
+    /// `None` unless `token` came from `encode` under `sort`
+    pub fn decode(token: &str, sort: VmSort) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(token).ok()?;
+        if VmSort::parse(value.get("sort")?.as_str()?)? != sort {
+            return None;
+        }
+        let after = value.get("after")?.as_array()?.clone();
+        let scalar = |key: &serde_json::Value| key.is_string() || key.is_i64() || key.is_null();
+        if after.len() != sort.keys().len() || !after.iter().all(scalar) {
+            return None;
+        }
+        Some(Self { sort, after })
+    }
+}
+
+/// The few columns the sidebar shows, read without decoding whole VM rows
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VmSummary {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+    pub updated_at: String,
+}
+
+fn json_to_sql(value: &serde_json::Value) -> rusqlite::types::Value {
+    match value {
+        serde_json::Value::String(text) => rusqlite::types::Value::Text(text.clone()),
+        serde_json::Value::Number(number) => number
+            .as_i64()
+            .map_or(rusqlite::types::Value::Null, rusqlite::types::Value::Integer),
+        _ => rusqlite::types::Value::Null,
+    }
+}
+
+fn sql_to_json(value: rusqlite::types::Value) -> serde_json::Value {
+    match value {
+        rusqlite::types::Value::Integer(number) => number.into(),
+        rusqlite::types::Value::Text(text) => text.into(),
+        _ => serde_json::Value::Null,
+    }
+}
+
+const VM_COLUMNS: &str = "id, name, status, memory_mb, cpu_cores, disk_size_gb, os, install_media_path,
+                    COALESCE(NULLIF(boot_order, ''), 'disk-first'),
+                    COALESCE(NULLIF(network_type, ''), 'nat'),
+                    COALESCE(nested_virt, 0),
+                    COALESCE(NULLIF(restart_policy, ''), 'on-failure'),
+                    COALESCE(NULLIF(arch, ''), 'x86_64'),
+                    COALESCE(cpu_affinity, ''),
+                    COALESCE(priority, 0),
+                    COALESCE(NULLIF(preallocation, ''), 'off'),
+                    encryption_key_ref,
+                    raw_device_path,
+                    COALESCE(notes, ''),
+                    COALESCE(clipboard_sharing, 0),
+                    vlan_id,
+                    display_resolution,
+                    graphics,
+                    machine_type,
+                    COALESCE(NULLIF(display_mode, ''), 'spice'),
+                    boot_menu,
+                    COALESCE((SELECT group_concat(tag, ',') FROM (SELECT tag FROM vm_tags WHERE vm_id = vms.id ORDER BY tag)), ''),
+                    (SELECT json_extract(config, '$.mac') FROM networks WHERE id = vms.id || ':net0'),
+                    display_listen_address,
+                    spice_tls_port,
+                    spice_compression,
+                    COALESCE(ephemeral, 0),
+                    COALESCE(memory_policy_exempt, 0),
+                    memory_backend,
+                    performance,
+                    pointer_device,
+                    rtc,
+                    rng";
+
+fn row_to_record(row: &rusqlite::Row) -> rusqlite::Result<VMRecord> {
+    Ok(VMRecord {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        status: row.get(2)?,
+        memory_mb: row.get(3)?,
+        cpu_cores: row.get(4)?,
+        disk_size_gb: row.get(5)?,
+        os: row.get(6)?,
+        install_media_path: row.get(7)?,
+        boot_order: row.get(8)?,
+        network_type: row.get(9)?,
+        nested_virt: row.get(10)?,
+        restart_policy: row.get(11)?,
+        arch: row.get(12)?,
+        cpu_affinity: parse_core_list(&row.get::<_, String>(13)?),
+        priority: row.get(14)?,
+        preallocation: row.get(15)?,
+        encryption_key_ref: row.get(16)?,
+        raw_device_path: row.get(17)?,
+        notes: row.get(18)?,
+        clipboard_sharing: row.get(19)?,
+        vlan_id: row.get(20)?,
+        display_resolution: row.get(21)?,
+        graphics: row.get(22)?,
+        machine_type: row.get(23)?,
+        display_mode: row.get(24)?,
+        boot_menu: parse_boot_menu(row.get(25)?),
+        tags: parse_tag_list(&row.get::<_, String>(26)?),
+        mac_address: row.get(27)?,
+        display_listen_address: row.get(28)?,
+        spice_tls_port: row.get(29)?,
+        spice_compression: parse_spice_compression(row.get(30)?),
+        ephemeral: row.get(31)?,
+        memory_policy_exempt: row.get(32)?,
+        memory_backend: row.get(33)?,
+        performance: parse_performance(row.get(34)?),
+        pointer_device: row.get(35)?,
+        rtc: row.get(36)?,
+        rng: row.get(37)?,
+    })
+}
+
+/// Upsert the `networks` row of the VM's only NIC (`net0`)
+fn save_nic_mac(conn: &Connection, vm_id: &str, mac: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO networks (id, vm_id, type, config)
+         VALUES (?1 || ':net0', ?1, 'virtio-net-pci', json_object('mac', ?2))",
+        params![vm_id, mac],
+    )?;
+    Ok(())
+}
+
+/// Point the VM's own entry in `drives` at `path`, creating the entry if needed
+fn write_disk_location(conn: &Connection, vm_id: &str, path: &str) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE drives SET path = ? WHERE vm_id = ? AND hotplugged = 0",
+        params![path, vm_id],
+    )?;
+    if updated == 0 {
+        conn.execute(
+            "INSERT INTO drives (id, vm_id, path, interface, format) VALUES (?, ?, ?, 'virtio', 'qcow2')",
+            params![uuid::Uuid::new_v4().to_string(), vm_id, path],
+        )?;
+    }
+    Ok(())
+}
+
+/// Tags cannot contain commas, so the aggregated list splits cleanly
+fn parse_tag_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Host core lists are stored as comma-separated indices, e.g. `0,2,3`
+fn format_core_list(cores: &[u32]) -> String {
+    cores
+        .iter()
+        .map(|core| core.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Boot menu settings are stored as JSON; unreadable values fall back to the default menu
+fn format_boot_menu(boot_menu: &Option<BootMenuConfig>) -> Option<String> {
+    boot_menu.as_ref().and_then(|config| serde_json::to_string(config).ok())
+}
+
+fn parse_boot_menu(value: Option<String>) -> Option<BootMenuConfig> {
+    value.and_then(|json| serde_json::from_str(&json).ok())
+}
+
+/// SPICE compression is stored as JSON too; unreadable values fall back to the defaults
+fn format_spice_compression(compression: &Option<SpiceCompression>) -> Option<String> {
+    compression.as_ref().and_then(|config| serde_json::to_string(config).ok())
+}
+
+fn parse_spice_compression(value: Option<String>) -> Option<SpiceCompression> {
+    value.and_then(|json| serde_json::from_str(&json).ok())
+}
+
+fn format_performance(performance: &Option<VmPerformance>) -> Option<String> {
+    performance.as_ref().and_then(|tuning| serde_json::to_string(tuning).ok())
+}
+
+fn parse_performance(value: Option<String>) -> Option<VmPerformance> {
+    value.and_then(|json| serde_json::from_str(&json).ok())
+}
+
+fn parse_core_list(value: &str) -> Vec<u32> {
+    value
+        .split(',')
+        .filter_map(|core| core.trim().parse().ok())
+        .collect()
+}
+
+fn table_columns(conn: &Connection, schema: &str, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+impl ConfigStore {
+    pub fn new(db_path: PathBuf) -> Result<Self> {
+        let config = Self { backend: Backend::File(db_path) };
+        config.init_db()?;
+        Ok(config)
+    }
+
+    /// A store backed by a private in-memory database that lives as long as
+    /// the store and its clones; nothing touches the disk
+    pub fn in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        let config = Self { backend: Backend::Memory(Arc::new(Mutex::new(conn))) };
+        config.init_db()?;
+        Ok(config)
+    }
+
+    fn connection(&self) -> Result<StoreConnection<'_>> {
+        Ok(match &self.backend {
+            Backend::File(db_path) => StoreConnection::Owned(Connection::open(db_path)?),
+            Backend::Memory(conn) => StoreConnection::Shared(conn.lock().unwrap_or_else(|e| e.into_inner())),
+        })
+    }
+
+    /// Open the store, replacing a corrupt database with a fresh one.
+    ///
+    /// The damaged file is kept next to the new one as
+    /// `config.db.corrupt-<timestamp>` and any readable rows are copied over.
+    pub fn open_or_recover(db_path: PathBuf) -> Result<(Self, Option<DatabaseRecovery>)> {
+        let reason = match Self::new(db_path.clone()) {
+            Ok(store) => return Ok((store, None)),
+            Err(err) if is_corruption(&err) => err.to_string(),
+            Err(err) => return Err(err),
+        };
+
+        let file_name = db_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let backup_path = db_path.with_file_name(format!(
+            "{}.corrupt-{}",
+            file_name,
+            chrono::Utc::now().format("%Y%m%d-%H%M%S")
+        ));
+        move_database(&db_path, &backup_path)?;
+
+        let store = Self::new(db_path)?;
+        let recovered_rows = store.salvage_from(&backup_path).unwrap_or(0);
+        Ok((
+            store,
+            Some(DatabaseRecovery {
+                backup_path: backup_path.display().to_string(),
+                reason,
+                recovered_rows,
+            }),
+        ))
+    }
+
+    /// Copy whatever rows are still readable from a damaged database
+    fn salvage_from(&self, damaged: &Path) -> Result<usize> {
+        let conn = self.connection()?;
+        conn.execute("ATTACH DATABASE ? AS damaged", [damaged.display().to_string()])?;
+
+        let mut copied = 0;
+        for table in SALVAGE_TABLES {
+            let damaged_columns = table_columns(&conn, "damaged", table)?;
+            let columns = table_columns(&conn, "main", table)?
+                .into_iter()
+                .filter(|column| damaged_columns.contains(column))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if columns.is_empty() {
+                continue;
+            }
+            // One unreadable table should not stop the rest from being recovered
+            if let Ok(rows) = conn.execute(
+                &format!("INSERT OR IGNORE INTO main.{table} ({columns}) SELECT {columns} FROM damaged.{table}"),
+                [],
+            ) {
+                copied += rows;
+            }
+        }
+
+        conn.execute("DETACH DATABASE damaged", [])?;
+        Ok(copied)
+    }
+
+    /// Run `PRAGMA integrity_check`; an empty list means the database is healthy
+    pub fn integrity_check(&self) -> Result<Vec<String>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let results = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(results.into_iter().filter(|result| result != "ok").collect())
+    }
+
+    fn init_db(&self) -> Result<()> {
+        let conn = self.connection()?;
+        
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS vms (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                status TEXT NOT NULL,
+                memory_mb INTEGER NOT NULL,
+                cpu_cores INTEGER NOT NULL,
+                disk_size_gb INTEGER NOT NULL,
+                os TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS configs (
+                vm_id TEXT PRIMARY KEY,
+                boot_order TEXT,
+                network_type TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS drives (
+                id TEXT PRIMARY KEY,
+                vm_id TEXT NOT NULL,
+                path TEXT NOT NULL,
+                interface TEXT,
+                format TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS networks (
+                id TEXT PRIMARY KEY,
+                vm_id TEXT NOT NULL,
+                type TEXT NOT NULL,
+                config TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS groups (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                parent_id TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(parent_id) REFERENCES groups(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS vm_groups (
+                vm_id TEXT NOT NULL,
+                group_id TEXT NOT NULL,
+                PRIMARY KEY(vm_id, group_id),
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE,
+                FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS vm_tags (
+                vm_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY(vm_id, tag),
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS vm_dependencies (
+                vm_id TEXT NOT NULL,
+                depends_on TEXT NOT NULL,
+                PRIMARY KEY(vm_id, depends_on),
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE,
+                FOREIGN KEY(depends_on) REFERENCES vms(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS external_snapshots (
+                vm_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                backing_path TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY(vm_id, name),
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS profiles (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                builtin INTEGER NOT NULL DEFAULT 0,
+                config TEXT NOT NULL DEFAULT '{}',
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS vm_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                vm_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                at_ms INTEGER NOT NULL,
+                FOREIGN KEY(vm_id) REFERENCES vms(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        conn.execute("CREATE INDEX IF NOT EXISTS vm_events_vm_id ON vm_events (vm_id, id)", [])?;
+
+        // Seed missing built-ins only, so a database keeps the built-ins it was created with
+        for profile in crate::profiles::builtin_profiles() {
+            conn.execute(
+                "INSERT OR IGNORE INTO profiles (id, name, builtin, config) VALUES (?, ?, 1, ?)",
+                params![&profile.id, &profile.name, serde_json::to_string(&profile.config)?],
+            )?;
+        }
+
+        self.ensure_column(
+            &conn,
+            "vms",
+            "install_media_path",
+            "install_media_path TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "boot_order",
+            "boot_order TEXT DEFAULT 'disk-first'",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "network_type",
+            "network_type TEXT DEFAULT 'nat'",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "nested_virt",
+            "nested_virt INTEGER DEFAULT 0",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "restart_policy",
+            "restart_policy TEXT DEFAULT 'on-failure'",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "arch",
+            "arch TEXT DEFAULT 'x86_64'",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "cpu_affinity",
+            "cpu_affinity TEXT DEFAULT ''",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "priority",
+            "priority INTEGER DEFAULT 0",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "preallocation",
+            "preallocation TEXT DEFAULT 'off'",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "encryption_key_ref",
+            "encryption_key_ref TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "raw_device_path",
+            "raw_device_path TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "notes",
+            "notes TEXT DEFAULT ''",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "clipboard_sharing",
+            "clipboard_sharing INTEGER DEFAULT 0",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "vlan_id",
+            "vlan_id INTEGER",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "display_resolution",
+            "display_resolution TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "graphics",
+            "graphics TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "machine_type",
+            "machine_type TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "display_mode",
+            "display_mode TEXT DEFAULT 'spice'",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "boot_menu",
+            "boot_menu TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "display_listen_address",
+            "display_listen_address TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "spice_tls_port",
+            "spice_tls_port INTEGER",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "spice_compression",
+            "spice_compression TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "ephemeral",
+            "ephemeral INTEGER DEFAULT 0",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "memory_policy_exempt",
+            "memory_policy_exempt INTEGER DEFAULT 0",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "memory_backend",
+            "memory_backend TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "performance",
+            "performance TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "pointer_device",
+            "pointer_device TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "rtc",
+            "rtc TEXT",
+        )?;
+        self.ensure_column(
+            &conn,
+            "vms",
+            "rng",
+            "rng INTEGER",
+        )?;
+        self.ensure_column(
+            &conn,
+            "drives",
+            "hotplugged",
+            "hotplugged INTEGER NOT NULL DEFAULT 0",
+        )?;
+        self.ensure_column(
+            &conn,
+            "drives",
+            "discard",
+            "discard INTEGER NOT NULL DEFAULT 0",
+        )?;
+
+        conn.execute(
+            "UPDATE vms SET boot_order = 'disk-first' WHERE boot_order IS NULL OR boot_order = ''",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE vms SET network_type = 'nat' WHERE network_type IS NULL OR network_type = ''",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    fn ensure_column(&self, conn: &Connection, table: &str, column: &str, ddl: &str) -> Result<()> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        if !columns.iter().any(|name| name == column) {
+            conn.execute(&format!("ALTER TABLE {} ADD COLUMN {}", table, ddl), [])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn create_vm(&self, vm: &VMRecord) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO vms (id, name, status, memory_mb, cpu_cores, disk_size_gb, os, install_media_path, boot_order, network_type, nested_virt, restart_policy, arch, cpu_affinity, priority, preallocation, encryption_key_ref, raw_device_path, notes, clipboard_sharing, vlan_id, display_resolution, graphics, machine_type, display_mode, boot_menu, display_listen_address, spice_tls_port, spice_compression, ephemeral, memory_policy_exempt, memory_backend, performance, pointer_device, rtc, rng) 
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                &vm.id,
+                &vm.name,
+                &vm.status,
+                vm.memory_mb,
+                vm.cpu_cores,
+                vm.disk_size_gb,
+                &vm.os,
+                &vm.install_media_path,
+                &vm.boot_order,
+                &vm.network_type,
+                vm.nested_virt,
+                &vm.restart_policy,
+                &vm.arch,
+                format_core_list(&vm.cpu_affinity),
+                vm.priority,
+                &vm.preallocation,
+                &vm.encryption_key_ref,
+                &vm.raw_device_path,
+                &vm.notes,
+                vm.clipboard_sharing,
+                vm.vlan_id,
+                &vm.display_resolution,
+                &vm.graphics,
+                &vm.machine_type,
+                &vm.display_mode,
+                format_boot_menu(&vm.boot_menu),
+                &vm.display_listen_address,
+                vm.spice_tls_port,
+                format_spice_compression(&vm.spice_compression),
+                vm.ephemeral,
+                vm.memory_policy_exempt,
+                &vm.memory_backend,
+                format_performance(&vm.performance),
+                &vm.pointer_device,
+                &vm.rtc,
+                vm.rng
+            ],
+        )?;
+        if let Some(mac) = &vm.mac_address {
+            save_nic_mac(&conn, &vm.id, mac)?;
+        }
+        Ok(())
+    }
+
+    /// Move the VM from status `from` to `to` in one statement; false if it
+    /// is gone or no longer `from`, so a concurrent change is never undone
+    pub fn transition_status(&self, vm_id: &str, from: &str, to: &str) -> Result<bool> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "UPDATE vms SET status = ?3, updated_at = CURRENT_TIMESTAMP WHERE id = ?1 AND status = ?2",
+            params![vm_id, from, to],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Persist the MAC address of the VM's NIC
+    pub fn set_vm_mac(&self, vm_id: &str, mac: &str) -> Result<()> {
+        let conn = self.connection()?;
+        save_nic_mac(&conn, vm_id, mac)
+    }
+
+    /// Add a guest network adapter after the VM's existing ones
+    pub fn add_network(&self, vm_id: &str, net: &NetworkConfig) -> Result<()> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "INSERT INTO networks (id, vm_id, type, config)
+             SELECT ?1 || ':nic:' || ?2, id, ?3, ?4 FROM vms WHERE id = ?1",
+            params![vm_id, net.id, net.kind, serde_json::to_string(net)?],
+        )?;
+        if rows == 0 {
+            return Err(Error::NotFound(format!("VM {}", vm_id)));
+        }
+        Ok(())
+    }
+
+    /// The VM's network adapters in the order they were added
+    pub fn list_networks(&self, vm_id: &str) -> Result<Vec<NetworkConfig>> {
+        let conn = self.connection()?;
+        // The `:net0` row only holds the MAC of the NIC `network_type` describes
+        let mut stmt = conn.prepare(
+            "SELECT config FROM networks WHERE vm_id = ?1 AND id != ?1 || ':net0' ORDER BY rowid",
+        )?;
+        let configs = stmt
+            .query_map([vm_id], |row| row.get::<_, String>(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        configs
+            .iter()
+            .map(|config| serde_json::from_str(config).map_err(Error::from))
+            .collect()
+    }
+
+    /// Replace all of the VM's network adapters with `nets`, in order
+    pub fn replace_networks(&self, vm_id: &str, nets: &[NetworkConfig]) -> Result<()> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM networks WHERE vm_id = ?1 AND id != ?1 || ':net0'", [vm_id])?;
+        for net in nets {
+            let rows = tx.execute(
+                "INSERT INTO networks (id, vm_id, type, config)
+                 SELECT ?1 || ':nic:' || ?2, id, ?3, ?4 FROM vms WHERE id = ?1",
+                params![vm_id, net.id, net.kind, serde_json::to_string(net)?],
+            )?;
+            if rows == 0 {
+                return Err(Error::NotFound(format!("VM {}", vm_id)));
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Remove network adapter `network_id` from the VM
+    pub fn remove_network(&self, vm_id: &str, network_id: &str) -> Result<()> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "DELETE FROM networks WHERE id = ?1 || ':nic:' || ?2",
+            params![vm_id, network_id],
+        )?;
+        if rows == 0 {
+            return Err(Error::NotFound(format!("Network adapter {} of VM {}", network_id, vm_id)));
+        }
+        Ok(())
+    }
+
+    pub fn get_vm(&self, id: &str) -> Result<Option<VMRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM vms WHERE id = ?", VM_COLUMNS))?;
+        let result = stmt.query_row([id], row_to_record).ok();
+        
+        Ok(result)
+    }
+
+    pub fn list_vms(&self) -> Result<Vec<VMRecord>> {
+        let (vms, _) = self.list_vms_paged(0, None, VmSort::default())?;
+        Ok(vms)
+    }
+
+    /// One page of VMs plus the total count; `limit: None` returns the rest
+    pub fn list_vms_paged(&self, offset: u32, limit: Option<u32>, sort: VmSort) -> Result<(Vec<VMRecord>, u64)> {
+        let conn = self.connection()?;
+        let total: u64 = conn.query_row("SELECT COUNT(*) FROM vms", [], |row| row.get(0))?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM vms ORDER BY {} LIMIT ? OFFSET ?",
+            VM_COLUMNS,
+            sort.order_by()
+        ))?;
+        // SQLite treats a negative LIMIT as unbounded
+        let limit = limit.map_or(-1, i64::from);
+        let vms = stmt
+            .query_map(params![limit, offset], row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok((vms, total))
+    }
+
+    /// Up to `limit` VMs after `cursor` (from the start when `None`) and the
+    /// cursor for the next page, which is `None` once nothing is left
+    pub fn list_vms_after(
+        &self,
+        cursor: Option<&VmCursor>,
+        limit: u32,
+        sort: VmSort,
+    ) -> Result<(Vec<VMRecord>, Option<VmCursor>)> {
+        let conn = self.connection()?;
+        let mut sql = format!("SELECT {} FROM vms", VM_COLUMNS);
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(cursor) = cursor {
+            if cursor.sort != sort {
+                return Err(Error::InvalidConfig(format!("cursor was taken under sort {}", cursor.sort.as_str())));
+            }
+            sql.push_str(&format!(" WHERE {}", sort.after_clause()));
+            for i in 0..cursor.after.len() {
+                values.extend(cursor.after[..=i].iter().map(json_to_sql));
+            }
+        }
+        // One extra row tells whether another page follows
+        sql.push_str(&format!(" ORDER BY {} LIMIT {}", sort.order_by(), u64::from(limit) + 1));
+        let mut stmt = conn.prepare(&sql)?;
+        let mut vms = stmt
+            .query_map(rusqlite::params_from_iter(values), row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        if vms.len() <= limit as usize {
+            return Ok((vms, None));
+        }
+        vms.truncate(limit as usize);
+        let last = &vms[vms.len() - 1].id;
+        let columns: Vec<&str> = sort.keys().iter().map(|(key, _)| *key).collect();
+        let after = conn.query_row(
+            &format!("SELECT {} FROM vms WHERE id = ?", columns.join(", ")),
+            [last],
+            |row| (0..columns.len()).map(|i| row.get::<_, rusqlite::types::Value>(i).map(sql_to_json)).collect(),
+        )?;
+        Ok((vms, Some(VmCursor { sort, after })))
+    }
+
+    /// Every VM's id, name, status and last update, for views that need no more
+    pub fn list_vm_summaries(&self, sort: VmSort) -> Result<Vec<VmSummary>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, status, COALESCE(updated_at, created_at, '') FROM vms ORDER BY {}",
+            sort.order_by()
+        ))?;
+        let summaries = stmt
+            .query_map([], |row| {
+                Ok(VmSummary {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    status: row.get(2)?,
+                    updated_at: row.get(3)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(summaries)
+    }
+
+    pub fn update_vm(&self, vm: &VMRecord) -> Result<()> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "UPDATE vms SET name = ?, status = ?, memory_mb = ?, cpu_cores = ?, disk_size_gb = ?, os = ?, install_media_path = ?, boot_order = ?, network_type = ?, nested_virt = ?, restart_policy = ?, arch = ?, cpu_affinity = ?, priority = ?, preallocation = ?, encryption_key_ref = ?, raw_device_path = ?, notes = ?, clipboard_sharing = ?, vlan_id = ?, display_resolution = ?, graphics = ?, machine_type = ?, display_mode = ?, boot_menu = ?, display_listen_address = ?, spice_tls_port = ?, spice_compression = ?, ephemeral = ?, memory_policy_exempt = ?, memory_backend = ?, performance = ?, pointer_device = ?, rtc = ?, rng = ?, updated_at = CURRENT_TIMESTAMP 
+             WHERE id = ?",
+            params![
+                &vm.name,
+                &vm.status,
+                vm.memory_mb,
+                vm.cpu_cores,
+                vm.disk_size_gb,
+                &vm.os,
+                &vm.install_media_path,
+                &vm.boot_order,
+                &vm.network_type,
+                vm.nested_virt,
+                &vm.restart_policy,
+                &vm.arch,
+                format_core_list(&vm.cpu_affinity),
+                vm.priority,
+                &vm.preallocation,
+                &vm.encryption_key_ref,
+                &vm.raw_device_path,
+                &vm.notes,
+                vm.clipboard_sharing,
+                vm.vlan_id,
+                &vm.display_resolution,
+                &vm.graphics,
+                &vm.machine_type,
+                &vm.display_mode,
+                format_boot_menu(&vm.boot_menu),
+                &vm.display_listen_address,
+                vm.spice_tls_port,
+                format_spice_compression(&vm.spice_compression),
+                vm.ephemeral,
+                vm.memory_policy_exempt,
+                &vm.memory_backend,
+                format_performance(&vm.performance),
+                &vm.pointer_device,
+                &vm.rtc,
+                vm.rng,
+                &vm.id
+            ],
+        )?;
+        
+        if rows == 0 {
+            return Err(Error::VmNotFound(vm.id.clone()));
+        }
+        
+        Ok(())
+    }
+
+    pub fn delete_vm(&self, id: &str) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute("DELETE FROM vm_groups WHERE vm_id = ?", [id])?;
+        conn.execute("DELETE FROM vm_tags WHERE vm_id = ?", [id])?;
+        conn.execute("DELETE FROM vm_dependencies WHERE vm_id = ?1 OR depends_on = ?1", [id])?;
+        conn.execute("DELETE FROM vm_events WHERE vm_id = ?", [id])?;
+        conn.execute("DELETE FROM external_snapshots WHERE vm_id = ?", [id])?;
+        conn.execute("DELETE FROM networks WHERE vm_id = ?", [id])?;
+        conn.execute("DELETE FROM drives WHERE vm_id = ?", [id])?;
+        conn.execute("DELETE FROM vms WHERE id = ?", [id])?;
+        Ok(())
+    }
+
+    /// Where the VM's disk image was relocated to; `None` means the default disks dir
+    pub fn disk_location(&self, vm_id: &str) -> Result<Option<String>> {
+        let conn = self.connection()?;
+        // An empty path is a row holding only drive options for a disk that was never moved
+        let path = conn
+            .query_row(
+                "SELECT path FROM drives WHERE vm_id = ? AND hotplugged = 0 AND path != '' LIMIT 1",
+                [vm_id],
+                |row| row.get(0),
+            )
+            .ok();
+        Ok(path)
+    }
+
+    /// Record the path of the VM's disk image in the `drives` table
+    pub fn set_disk_location(&self, vm_id: &str, path: &str) -> Result<()> {
+        let conn = self.connection()?;
+        write_disk_location(&conn, vm_id, path)
+    }
+
+    /// Record a storage directory migration in one transaction: each `(old, new)`
+    /// pair repoints the drives using the old path, and `storage_dir` is saved
+    pub fn relocate_storage(&self, moves: &[(String, String)], storage_dir: &str) -> Result<()> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+        for (old, new) in moves {
+            tx.execute("UPDATE drives SET path = ? WHERE path = ?", params![new, old])?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)",
+            params![STORAGE_DIR_KEY, storage_dir],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every disk image path recorded for any VM: relocated and hot-added
+    /// disks, and the images beneath external snapshot overlays
+    pub fn referenced_disk_paths(&self) -> Result<Vec<String>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT path FROM drives WHERE path != '' UNION SELECT backing_path FROM external_snapshots",
+        )?;
+        let paths = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(paths)
+    }
+
+    /// External snapshots of the VM, oldest first; the last one's overlay is the VM's disk
+    pub fn external_snapshots(&self, vm_id: &str) -> Result<Vec<ExternalSnapshotRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT vm_id, name, path, backing_path, created_at FROM external_snapshots
+             WHERE vm_id = ? ORDER BY rowid",
+        )?;
+        let snapshots = stmt
+            .query_map([vm_id], |row| {
+                Ok(ExternalSnapshotRecord {
+                    vm_id: row.get(0)?,
+                    name: row.get(1)?,
+                    path: row.get(2)?,
+                    backing_path: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(snapshots)
+    }
+
+    /// Record overlay `path` as external snapshot `name` and make it the VM's disk
+    pub fn add_external_snapshot(&self, vm_id: &str, name: &str, path: &str, backing_path: &str) -> Result<()> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO external_snapshots (vm_id, name, path, backing_path) VALUES (?, ?, ?, ?)",
+            params![vm_id, name, path, backing_path],
+        )?;
+        write_disk_location(&tx, vm_id, path)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Forget external snapshot `name` once its overlay was merged down; the
+    /// image beneath becomes the VM's disk again
+    pub fn remove_external_snapshot(&self, vm_id: &str, name: &str) -> Result<()> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+        let backing_path: String = tx
+            .query_row(
+                "SELECT backing_path FROM external_snapshots WHERE vm_id = ? AND name = ?",
+                params![vm_id, name],
+                |row| row.get(0),
+            )
+            .map_err(|_| Error::NotFound(format!("External snapshot {} of VM {}", name, vm_id)))?;
+        tx.execute(
+            "DELETE FROM external_snapshots WHERE vm_id = ? AND name = ?",
+            params![vm_id, name],
+        )?;
+        write_disk_location(&tx, vm_id, &backing_path)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Whether guest TRIM on the VM's disk is passed through to the image
+    pub fn disk_discard(&self, vm_id: &str) -> Result<bool> {
+        let conn = self.connection()?;
+        let discard = conn
+            .query_row(
+                "SELECT discard FROM drives WHERE vm_id = ? AND hotplugged = 0 LIMIT 1",
+                [vm_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+        Ok(discard)
+    }
+
+    pub fn set_disk_discard(&self, vm_id: &str, enabled: bool) -> Result<()> {
+        let conn = self.connection()?;
+        let updated = conn.execute(
+            "UPDATE drives SET discard = ? WHERE vm_id = ? AND hotplugged = 0",
+            params![enabled, vm_id],
+        )?;
+        if updated == 0 {
+            conn.execute(
+                "INSERT INTO drives (id, vm_id, path, interface, format, discard)
+                 VALUES (?, ?, '', 'virtio', 'qcow2', ?)",
+                params![uuid::Uuid::new_v4().to_string(), vm_id, enabled],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Interface the VM's own disk is attached through; `virtio` unless changed
+    pub fn disk_interface(&self, vm_id: &str) -> Result<String> {
+        let conn = self.connection()?;
+        let interface: Option<String> = conn
+            .query_row(
+                "SELECT interface FROM drives WHERE vm_id = ? AND hotplugged = 0 LIMIT 1",
+                [vm_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(None);
+        Ok(interface.unwrap_or_else(|| "virtio".to_string()))
+    }
+
+    pub fn set_disk_interface(&self, vm_id: &str, interface: &str) -> Result<()> {
+        let conn = self.connection()?;
+        let updated = conn.execute(
+            "UPDATE drives SET interface = ? WHERE vm_id = ? AND hotplugged = 0",
+            params![interface, vm_id],
+        )?;
+        if updated == 0 {
+            conn.execute(
+                "INSERT INTO drives (id, vm_id, path, interface, format) VALUES (?, ?, '', ?, 'qcow2')",
+                params![uuid::Uuid::new_v4().to_string(), vm_id, interface],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Record a disk attached to the running VM; it does not replace the VM's own disk
+    pub fn add_hotplugged_drive(
+        &self,
+        vm_id: &str,
+        drive_id: &str,
+        path: &str,
+        interface: &str,
+        format: &str,
+    ) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO drives (id, vm_id, path, interface, format, hotplugged) VALUES (?, ?, ?, ?, ?, 1)",
+            params![drive_id, vm_id, path, interface, format],
+        )?;
+        Ok(())
+    }
+
+    /// Disks hot-added to the VM, in the order they were attached
+    pub fn hotplugged_drives(&self, vm_id: &str) -> Result<Vec<HotpluggedDrive>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, path, interface, format FROM drives WHERE vm_id = ? AND hotplugged = 1 ORDER BY rowid",
+        )?;
+        let drives = stmt
+            .query_map([vm_id], |row| {
+                Ok(HotpluggedDrive {
+                    id: row.get(0)?,
+                    path: row.get(1)?,
+                    interface: row.get(2)?,
+                    format: row.get(3)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(drives)
+    }
+
+    /// Forget hot-added disk `drive_id`; the image itself is left in place
+    pub fn remove_hotplugged_drive(&self, vm_id: &str, drive_id: &str) -> Result<()> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "DELETE FROM drives WHERE id = ? AND vm_id = ? AND hotplugged = 1",
+            params![drive_id, vm_id],
+        )?;
+        if rows == 0 {
+            return Err(Error::NotFound(format!("Drive {} of VM {}", drive_id, vm_id)));
+        }
+        Ok(())
+    }
+
+    /// Whether any VM already uses `name`, ignoring case and surrounding whitespace
+    pub fn name_exists(&self, name: &str) -> Result<bool> {
+        let conn = self.connection()?;
+        let exists = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM vms WHERE LOWER(TRIM(name)) = LOWER(TRIM(?)))",
+            [name],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    pub fn unique_names_enabled(&self) -> Result<bool> {
+        Ok(self.get_setting(UNIQUE_NAMES_SETTING)?.as_deref() == Some("true"))
+    }
+
+    /// On unless explicitly turned off
+    pub fn spice_ticketing_enabled(&self) -> Result<bool> {
+        Ok(self.get_setting(SPICE_TICKETING_SETTING)?.as_deref() != Some("false"))
+    }
+
+    pub fn viewer_fullscreen_enabled(&self) -> Result<bool> {
+        Ok(self.get_setting(VIEWER_FULLSCREEN_SETTING)?.as_deref() == Some("true"))
+    }
+
+    /// Grace period before a stopped VM's display session is swept; unparsable values fall back to the default
+    pub fn display_session_grace_secs(&self) -> Result<u64> {
+        Ok(self
+            .get_setting(DISPLAY_SESSION_GRACE_SETTING)?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_DISPLAY_SESSION_GRACE_SECS))
+    }
+
+    /// Reconnects allowed per display session; unparsable values fall back to the default
+    pub fn display_reconnect_max_attempts(&self) -> Result<u32> {
+        Ok(self
+            .get_setting(DISPLAY_RECONNECT_ATTEMPTS_SETTING)?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_DISPLAY_RECONNECT_ATTEMPTS))
+    }
+
+    /// Ports display servers are drawn from; a missing, unparsable or unusable range falls back to the default
+    pub fn display_port_range(&self) -> Result<DisplayPortRange> {
+        let port = |key: &str| -> Result<Option<u16>> {
+            Ok(self.get_setting(key)?.and_then(|value| value.parse().ok()))
+        };
+        let range = match (port(DISPLAY_PORT_RANGE_START_SETTING)?, port(DISPLAY_PORT_RANGE_END_SETTING)?) {
+            (Some(start), Some(end)) => DisplayPortRange { start, end },
+            _ => return Ok(DisplayPortRange::default()),
+        };
+        Ok(if range.is_valid() { range } else { DisplayPortRange::default() })
+    }
+
+    /// Seconds between health checks; unparsable or zero values fall back to the default
+    pub fn health_check_interval_secs(&self) -> Result<u64> {
+        Ok(self
+            .get_setting(HEALTH_CHECK_INTERVAL_SETTING)?
+            .and_then(|value| value.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL_SECS))
+    }
+
+    /// Consecutive missed health checks before a VM is marked not responding
+    pub fn health_check_failure_threshold(&self) -> Result<u32> {
+        Ok(self
+            .get_setting(HEALTH_CHECK_THRESHOLD_SETTING)?
+            .and_then(|value| value.parse().ok())
+            .filter(|threshold| *threshold > 0)
+            .unwrap_or(DEFAULT_HEALTH_CHECK_THRESHOLD))
+    }
+
+    /// The memory ballooning policy; missing or unparsable values fall back to the defaults
+    pub fn memory_policy(&self) -> Result<MemoryPolicy> {
+        let defaults = MemoryPolicy::default();
+        Ok(MemoryPolicy {
+            enabled: self.get_setting(MEMORY_POLICY_ENABLED_SETTING)?.as_deref() == Some("true"),
+            free_threshold_mb: self
+                .get_setting(MEMORY_POLICY_THRESHOLD_SETTING)?
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.free_threshold_mb),
+            vm_minimum_mb: self
+                .get_setting(MEMORY_POLICY_MINIMUM_SETTING)?
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.vm_minimum_mb),
+        })
+    }
+
+    pub fn save_memory_policy(&self, policy: &MemoryPolicy) -> Result<()> {
+        self.save_setting(MEMORY_POLICY_ENABLED_SETTING, &policy.enabled.to_string())?;
+        self.save_setting(MEMORY_POLICY_THRESHOLD_SETTING, &policy.free_threshold_mb.to_string())?;
+        self.save_setting(MEMORY_POLICY_MINIMUM_SETTING, &policy.vm_minimum_mb.to_string())
+    }
+
+    /// Past benchmark reports, oldest first; an unreadable history reads as empty
+    pub fn benchmark_history(&self) -> Result<Vec<BenchmarkReport>> {
+        Ok(self
+            .get_setting(BENCHMARK_HISTORY_SETTING)?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default())
+    }
+
+    /// Append `report`, dropping the oldest past `MAX_BENCHMARK_HISTORY`
+    pub fn record_benchmark(&self, report: &BenchmarkReport) -> Result<()> {
+        let mut history = self.benchmark_history()?;
+        history.push(report.clone());
+        let excess = history.len().saturating_sub(MAX_BENCHMARK_HISTORY);
+        history.drain(..excess);
+        self.save_setting(BENCHMARK_HISTORY_SETTING, &serde_json::to_string(&history)?)
+    }
+
+    /// Where the metrics server should listen, `None` when it is off or the address is unparsable
+    pub fn metrics_listen_address(&self) -> Result<Option<std::net::SocketAddr>> {
+        Ok(self
+            .get_setting(METRICS_LISTEN_SETTING)?
+            .and_then(|value| value.parse().ok()))
+    }
+
+    pub fn control_socket_enabled(&self) -> Result<bool> {
+        Ok(self.get_setting(CONTROL_SOCKET_SETTING)?.as_deref() == Some("true"))
+    }
+
+    /// Token control socket clients authenticate with, `None` until one is generated
+    pub fn control_token(&self) -> Result<Option<String>> {
+        Ok(self.get_setting(CONTROL_TOKEN_SETTING)?.filter(|token| !token.is_empty()))
+    }
+
+    pub fn create_group(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let conn = self.connection()?;
+        if let Some(parent_id) = parent_id {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM groups WHERE id = ?)",
+                [parent_id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(Error::NotFound(format!("Group {}", parent_id)));
+            }
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        conn.execute(
+            "INSERT INTO groups (id, name, parent_id) VALUES (?, ?, ?)",
+            params![&id, name, parent_id],
+        )?;
+        Ok(id)
+    }
+
+    pub fn list_groups(&self) -> Result<Vec<GroupRecord>> {
+        let conn = self.connection()?;
+        let mut members: HashMap<String, Vec<String>> = HashMap::new();
+        let mut stmt = conn.prepare("SELECT group_id, vm_id FROM vm_groups ORDER BY vm_id")?;
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (group_id, vm_id) = row?;
+            members.entry(group_id).or_default().push(vm_id);
+        }
+
+        let mut stmt = conn.prepare("SELECT id, name, parent_id, created_at FROM groups ORDER BY name")?;
+        let groups = stmt
+            .query_map([], |row| {
+                Ok(GroupRecord {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    parent_id: row.get(2)?,
+                    created_at: row.get(3)?,
+                    vm_ids: Vec::new(),
+                })
+            })?
+            .map(|group| {
+                group.map(|mut group| {
+                    group.vm_ids = members.remove(&group.id).unwrap_or_default();
+                    group
+                })
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(groups)
+    }
+
+    /// Groups each VM belongs to, keyed by VM id; VMs in no group are absent
+    pub fn group_memberships(&self) -> Result<HashMap<String, Vec<String>>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT vm_id, group_id FROM vm_groups ORDER BY group_id")?;
+        let mut memberships: HashMap<String, Vec<String>> = HashMap::new();
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (vm_id, group_id) = row?;
+            memberships.entry(vm_id).or_default().push(group_id);
+        }
+        Ok(memberships)
+    }
+
+    /// Groups one VM is filed under
+    pub fn vm_group_ids(&self, vm_id: &str) -> Result<Vec<String>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT group_id FROM vm_groups WHERE vm_id = ? ORDER BY group_id")?;
+        let group_ids = stmt
+            .query_map([vm_id], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(group_ids)
+    }
+
+    /// Replace the VMs `vm_id` must start after; every one of them must exist
+    pub fn set_vm_dependencies(&self, vm_id: &str, depends_on: &[String]) -> Result<()> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM vm_dependencies WHERE vm_id = ?", [vm_id])?;
+        for dependency in depends_on {
+            let rows = tx.execute(
+                "INSERT OR IGNORE INTO vm_dependencies (vm_id, depends_on)
+                 SELECT v.id, d.id FROM vms v, vms d WHERE v.id = ? AND d.id = ?",
+                [vm_id, dependency],
+            )?;
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM vm_dependencies WHERE vm_id = ? AND depends_on = ?)",
+                [vm_id, dependency],
+                |row| row.get(0),
+            )?;
+            if rows == 0 && !exists {
+                return Err(Error::NotFound(format!("VM {} or {}", vm_id, dependency)));
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// What each VM depends on, keyed by VM id; VMs without dependencies are absent
+    pub fn all_vm_dependencies(&self) -> Result<HashMap<String, Vec<String>>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT vm_id, depends_on FROM vm_dependencies ORDER BY depends_on")?;
+        let mut dependencies: HashMap<String, Vec<String>> = HashMap::new();
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (vm_id, depends_on) = row?;
+            dependencies.entry(vm_id).or_default().push(depends_on);
+        }
+        Ok(dependencies)
+    }
+
+    /// Delete a group and its memberships; VMs are kept and child groups move up a level
+    pub fn delete_group(&self, group_id: &str) -> Result<()> {
+        let conn = self.connection()?;
+        let parent_id: Option<String> = conn
+            .query_row("SELECT parent_id FROM groups WHERE id = ?", [group_id], |row| row.get(0))
+            .map_err(|_| Error::NotFound(format!("Group {}", group_id)))?;
+
+        conn.execute(
+            "UPDATE groups SET parent_id = ? WHERE parent_id = ?",
+            params![parent_id, group_id],
+        )?;
+        conn.execute("DELETE FROM vm_groups WHERE group_id = ?", [group_id])?;
+        conn.execute("DELETE FROM groups WHERE id = ?", [group_id])?;
+        Ok(())
+    }
+
+    pub fn add_vm_to_group(&self, vm_id: &str, group_id: &str) -> Result<()> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "INSERT OR IGNORE INTO vm_groups (vm_id, group_id)
+             SELECT v.id, g.id FROM vms v, groups g WHERE v.id = ? AND g.id = ?",
+            [vm_id, group_id],
+        )?;
+        if rows == 0 && !self.is_vm_in_group(&conn, vm_id, group_id)? {
+            return Err(Error::NotFound(format!("VM {} or group {}", vm_id, group_id)));
+        }
+        Ok(())
+    }
+
+    pub fn remove_vm_from_group(&self, vm_id: &str, group_id: &str) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "DELETE FROM vm_groups WHERE vm_id = ? AND group_id = ?",
+            [vm_id, group_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_vms_in_group(&self, group_id: &str) -> Result<Vec<VMRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM vms WHERE id IN (SELECT vm_id FROM vm_groups WHERE group_id = ?) ORDER BY created_at DESC",
+            VM_COLUMNS
+        ))?;
+        let vms = stmt
+            .query_map([group_id], row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(vms)
+    }
+
+    fn is_vm_in_group(&self, conn: &Connection, vm_id: &str, group_id: &str) -> Result<bool> {
+        let exists = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM vm_groups WHERE vm_id = ? AND group_id = ?)",
+            [vm_id, group_id],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    pub fn add_tag(&self, vm_id: &str, tag: &str) -> Result<()> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "INSERT OR IGNORE INTO vm_tags (vm_id, tag) SELECT id, ? FROM vms WHERE id = ?",
+            [tag, vm_id],
+        )?;
+        if rows == 0 {
+            let exists: bool = conn.query_row("SELECT EXISTS(SELECT 1 FROM vms WHERE id = ?)", [vm_id], |row| row.get(0))?;
+            if !exists {
+                return Err(Error::VmNotFound(vm_id.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_tag(&self, vm_id: &str, tag: &str) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute("DELETE FROM vm_tags WHERE vm_id = ? AND tag = ?", [vm_id, tag])?;
+        Ok(())
+    }
+
+    /// Append a lifecycle event, dropping the VM's oldest beyond `MAX_VM_EVENTS`
+    pub fn record_vm_event(&self, vm_id: &str, kind: &str, at_ms: u64) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO vm_events (vm_id, kind, at_ms) VALUES (?, ?, ?)",
+            params![vm_id, kind, at_ms as i64],
+        )?;
+        conn.execute(
+            "DELETE FROM vm_events WHERE vm_id = ?1 AND id NOT IN
+                (SELECT id FROM vm_events WHERE vm_id = ?1 ORDER BY id DESC LIMIT ?2)",
+            params![vm_id, MAX_VM_EVENTS],
+        )?;
+        Ok(())
+    }
+
+    /// The VM's last `limit` events, oldest first
+    pub fn recent_vm_events(&self, vm_id: &str, limit: u32) -> Result<Vec<VmEvent>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT vm_id, kind, at_ms FROM vm_events WHERE vm_id = ? ORDER BY id DESC LIMIT ?",
+        )?;
+        let mut events = stmt
+            .query_map(params![vm_id, limit], |row| {
+                Ok(VmEvent {
+                    vm_id: row.get(0)?,
+                    kind: row.get(1)?,
+                    at_ms: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        events.reverse();
+        Ok(events)
+    }
+
+    /// Every tag in use, alphabetically
+    pub fn list_tags(&self) -> Result<Vec<String>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT DISTINCT tag FROM vm_tags ORDER BY tag")?;
+        let tags = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    pub fn list_vms_by_tag(&self, tag: &str) -> Result<Vec<VMRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM vms WHERE id IN (SELECT vm_id FROM vm_tags WHERE tag = ?) ORDER BY created_at DESC",
+            VM_COLUMNS
+        ))?;
+        let vms = stmt
+            .query_map([tag], row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(vms)
+    }
+
+    /// Built-in profiles first, then the user's alphabetically
+    pub fn list_profiles(&self) -> Result<Vec<ProfileRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, builtin, config FROM profiles ORDER BY builtin DESC, name COLLATE NOCASE, id",
+        )?;
+        let profiles = stmt
+            .query_map([], row_to_profile)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(profiles)
+    }
+
+    pub fn get_profile(&self, id: &str) -> Result<Option<ProfileRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT id, name, builtin, config FROM profiles WHERE id = ?")?;
+        let mut rows = stmt.query_map([id], row_to_profile)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Insert or replace a user profile; built-in rows are never overwritten
+    pub fn save_profile(&self, profile: &ProfileRecord) -> Result<()> {
+        let conn = self.connection()?;
+        let rows = conn.execute(
+            "INSERT INTO profiles (id, name, builtin, config) VALUES (?, ?, 0, ?)
+             ON CONFLICT(id) DO UPDATE SET name = excluded.name, config = excluded.config WHERE builtin = 0",
+            params![&profile.id, &profile.name, serde_json::to_string(&profile.config)?],
+        )?;
+        if rows == 0 {
+            return Err(Error::ConfigError(format!("Profile {} is built in", profile.id)));
+        }
+        Ok(())
+    }
+
+    /// Delete a user profile; returns false when `id` is missing or built in
+    pub fn delete_profile(&self, id: &str) -> Result<bool> {
+        let conn = self.connection()?;
+        let rows = conn.execute("DELETE FROM profiles WHERE id = ? AND builtin = 0", [id])?;
+        Ok(rows > 0)
+    }
+
+    pub fn save_setting(&self, key: &str, value: &str) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)",
+            [key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_settings(&self) -> Result<Vec<(String, String)>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT key, value FROM settings ORDER BY key")?;
+        let settings = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(settings)
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT value FROM settings WHERE key = ?")?;
+        let result = stmt.query_row([key], |row| row.get(0)).ok();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use uuid::Uuid;
+
+    fn create_test_db() -> ConfigStore {
+        ConfigStore::in_memory().expect("Failed to create store")
+    }
+
+    fn create_test_vm() -> VMRecord {
+        VMRecord {
+            id: Uuid::new_v4().to_string(),
+            name: "Test VM".to_string(),
+            disk_size_gb: 50,
+            ..VMRecord::default()
+        }
+    }
+
+    #[test]
+    fn test_config_store_new() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let db_path = temp_dir.path().join("test.db");
+        ConfigStore::new(db_path.clone()).expect("Failed to create store");
+        assert!(db_path.exists());
+    }
+
+    #[test]
+    fn test_file_and_memory_stores_agree() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let file = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        for store in [file, create_test_db()] {
+            let mut vm = create_test_vm();
+            store.create_vm(&vm).unwrap();
+            vm.name = "Renamed".to_string();
+            store.update_vm(&vm).unwrap();
+            store.add_tag(&vm.id, "work").unwrap();
+            store.save_setting("log_level", "debug").unwrap();
+
+            // Clones share the data, including the in-memory database
+            let clone = store.clone();
+            let stored = clone.get_vm(&vm.id).unwrap().unwrap();
+            assert_eq!(stored.name, "Renamed");
+            assert_eq!(stored.tags, vec!["work"]);
+            assert_eq!(clone.get_setting("log_level").unwrap().as_deref(), Some("debug"));
+            assert_eq!(clone.list_profiles().unwrap().len(), 3);
+            assert!(clone.integrity_check().unwrap().is_empty());
+
+            store.delete_vm(&vm.id).unwrap();
+            assert!(clone.get_vm(&vm.id).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_memory_stores_are_independent() {
+        let first = create_test_db();
+        first.save_setting("log_level", "debug").unwrap();
+        assert_eq!(create_test_db().get_setting("log_level").unwrap(), None);
+    }
+
+    #[test]
+    fn test_open_or_recover_replaces_garbage_file() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let db_path = temp_dir.path().join("config.db");
+        std::fs::write(&db_path, vec![0xAB; 4096]).unwrap();
+
+        let (store, recovery) = ConfigStore::open_or_recover(db_path.clone()).expect("startup should succeed");
+        let recovery = recovery.expect("database should be reset");
+
+        assert!(Path::new(&recovery.backup_path).is_file());
+        assert!(recovery.backup_path.contains("config.db.corrupt-"));
+        assert_eq!(std::fs::read(&recovery.backup_path).unwrap(), vec![0xAB; 4096]);
+        assert_eq!(recovery.recovered_rows, 0);
+        assert!(store.list_vms().unwrap().is_empty());
+        assert!(store.integrity_check().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_open_or_recover_leaves_healthy_db() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let db_path = temp_dir.path().join("config.db");
+        let vm = create_test_vm();
+        ConfigStore::new(db_path.clone()).unwrap().create_vm(&vm).unwrap();
+
+        let (store, recovery) = ConfigStore::open_or_recover(db_path).unwrap();
+        assert!(recovery.is_none());
+        assert!(store.get_vm(&vm.id).unwrap().is_some());
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_salvage_copies_readable_rows() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let old_path = temp_dir.path().join("old.db");
+        let old = ConfigStore::new(old_path.clone()).unwrap();
+        let vm = create_test_vm();
+        old.create_vm(&vm).unwrap();
+        old.add_tag(&vm.id, "work").unwrap();
+        old.save_setting("log_level", "debug").unwrap();
+
+        let fresh = ConfigStore::new(temp_dir.path().join("new.db")).unwrap();
+        assert_eq!(fresh.salvage_from(&old_path).unwrap(), 3);
+        assert_eq!(fresh.get_vm(&vm.id).unwrap().unwrap().tags, vec!["work"]);
+        assert_eq!(fresh.get_setting("log_level").unwrap().as_deref(), Some("debug"));
+    }
+
+    #[test]
+    fn test_create_vm() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        
+        let result = store.create_vm(&vm);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_get_vm_existing() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        
+        store.create_vm(&vm).expect("Failed to create VM");
+        let retrieved = store.get_vm(&vm.id).expect("Failed to get VM");
+        
+        assert!(retrieved.is_some());
+        let retrieved_vm = retrieved.unwrap();
+        assert_eq!(retrieved_vm.id, vm.id);
+        assert_eq!(retrieved_vm.name, vm.name);
+        assert_eq!(retrieved_vm.memory_mb, vm.memory_mb);
+    }
+
+    #[test]
+    fn test_get_vm_nonexistent() {
+        let store = create_test_db();
+        let result = store.get_vm("nonexistent-id").expect("Should not error");
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_list_vms_empty() {
+        let store = create_test_db();
+        let vms = store.list_vms().expect("Failed to list VMs");
+        assert_eq!(vms.len(), 0);
+    }
+
+    #[test]
+    fn test_list_vms_multiple() {
+        let store = create_test_db();
+        let vm1 = create_test_vm();
+        let mut vm2 = create_test_vm();
+        vm2.name = "VM 2".to_string();
+        
+        store.create_vm(&vm1).expect("Failed to create VM1");
+        store.create_vm(&vm2).expect("Failed to create VM2");
+        
+        let vms = store.list_vms().expect("Failed to list VMs");
+        assert_eq!(vms.len(), 2);
+    }
+
+    #[test]
+    fn test_list_vms_paged_returns_page_and_total() {
+        let store = create_test_db();
+        for name in ["Charlie", "alpha", "Bravo", "delta", "Echo"] {
+            let mut vm = create_test_vm();
+            vm.name = name.to_string();
+            store.create_vm(&vm).expect("Failed to create VM");
+        }
+
+        let (page, total) = store.list_vms_paged(1, Some(2), VmSort::Name).expect("Failed to list page");
+        let names: Vec<&str> = page.iter().map(|vm| vm.name.as_str()).collect();
+        assert_eq!(total, 5);
+        assert_eq!(names, vec!["Bravo", "Charlie"]);
+
+        let (page, total) = store.list_vms_paged(4, Some(10), VmSort::Name).expect("Failed to list page");
+        assert_eq!(total, 5);
+        assert_eq!(page.len(), 1);
+        let (page, _) = store.list_vms_paged(10, Some(10), VmSort::Name).expect("Failed to list page");
+        assert!(page.is_empty());
+    }
+
+    #[test]
+    fn test_list_vms_paged_default_is_newest_first() {
+        let store = create_test_db();
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let vm = create_test_vm();
+            store.create_vm(&vm).expect("Failed to create VM");
+            ids.push(vm.id);
+        }
+        ids.reverse();
+
+        let (page, _) = store.list_vms_paged(0, None, VmSort::default()).expect("Failed to list page");
+        let listed: Vec<String> = page.into_iter().map(|vm| vm.id).collect();
+        assert_eq!(listed, ids);
+        let all: Vec<String> = store.list_vms().expect("Failed to list VMs").into_iter().map(|vm| vm.id).collect();
+        assert_eq!(all, ids);
+    }
+
+    #[test]
+    fn test_list_vms_paged_sorts_by_status() {
+        let store = create_test_db();
+        for status in ["stopped", "running", "paused"] {
+            let mut vm = create_test_vm();
+            vm.status = status.to_string();
+            store.create_vm(&vm).expect("Failed to create VM");
+        }
+
+        let (page, _) = store.list_vms_paged(0, None, VmSort::Status).expect("Failed to list page");
+        let statuses: Vec<&str> = page.iter().map(|vm| vm.status.as_str()).collect();
+        assert_eq!(statuses, vec!["paused", "running", "stopped"]);
+    }
+
+    #[test]
+    fn test_list_vms_after_walks_every_sort_without_gaps() {
+        let store = create_test_db();
+        let rows = [("Charlie", "running"), ("alpha", "stopped"), ("Bravo", "running"), ("alpha", "paused")];
+        for (name, status) in rows {
+            let mut vm = create_test_vm();
+            vm.name = name.to_string();
+            vm.status = status.to_string();
+            store.create_vm(&vm).expect("Failed to create VM");
+        }
+
+        for sort in [VmSort::Name, VmSort::CreatedAt, VmSort::Status] {
+            let expected: Vec<String> =
+                store.list_vms_paged(0, None, sort).expect("Failed to list").0.into_iter().map(|vm| vm.id).collect();
+            let mut walked = Vec::new();
+            let mut cursor = None;
+            loop {
+                let (page, next) = store.list_vms_after(cursor.as_ref(), 3, sort).expect("Failed to list page");
+                walked.extend(page.into_iter().map(|vm| vm.id));
+                match next {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
+            }
+            assert_eq!(walked, expected, "{:?}", sort);
+        }
+    }
+
+    #[test]
+    fn test_list_vms_after_is_stable_across_inserts_between_pages() {
+        let store = create_test_db();
+        for name in ["b", "d", "f", "h"] {
+            let mut vm = create_test_vm();
+            vm.name = name.to_string();
+            store.create_vm(&vm).expect("Failed to create VM");
+        }
+
+        let (first, cursor) = store.list_vms_after(None, 2, VmSort::Name).expect("Failed to list page");
+        assert_eq!(first.iter().map(|vm| vm.name.as_str()).collect::<Vec<_>>(), vec!["b", "d"]);
+        // One row lands before the cursor, one after it
+        for name in ["a", "e"] {
+            let mut vm = create_test_vm();
+            vm.name = name.to_string();
+            store.create_vm(&vm).expect("Failed to create VM");
+        }
+
+        let token = cursor.expect("more pages").encode();
+        let cursor = VmCursor::decode(&token, VmSort::Name).expect("round-trips");
+        let (second, cursor) = store.list_vms_after(Some(&cursor), 10, VmSort::Name).expect("Failed to list page");
+        assert_eq!(second.iter().map(|vm| vm.name.as_str()).collect::<Vec<_>>(), vec!["e", "f", "h"]);
+        assert!(cursor.is_none());
+
+        // An offset would have shifted: "a" pushes "d" onto the second page
+        let (by_offset, _) = store.list_vms_paged(2, Some(10), VmSort::Name).expect("Failed to list page");
+        assert_eq!(by_offset[0].name, "d");
+    }
+
+    #[test]
+    fn test_list_vms_after_newest_first_ignores_rows_created_later() {
+        let store = create_test_db();
+        for _ in 0..4 {
+            store.create_vm(&create_test_vm()).expect("Failed to create VM");
+        }
+        let (first, cursor) = store.list_vms_after(None, 2, VmSort::CreatedAt).expect("Failed to list page");
+        store.create_vm(&create_test_vm()).expect("Failed to create VM");
+
+        let (second, _) = store.list_vms_after(cursor.as_ref(), 10, VmSort::CreatedAt).expect("Failed to list page");
+        let all: Vec<String> = store.list_vms().expect("Failed to list VMs").into_iter().map(|vm| vm.id).collect();
+        let walked: Vec<String> = first.into_iter().chain(second).map(|vm| vm.id).collect();
+        assert_eq!(walked, all[1..].to_vec());
+    }
+
+    #[test]
+    fn test_vm_cursor_rejects_foreign_tokens() {
+        let store = create_test_db();
+        for _ in 0..2 {
+            store.create_vm(&create_test_vm()).expect("Failed to create VM");
+        }
+        let (_, cursor) = store.list_vms_after(None, 1, VmSort::Name).expect("Failed to list page");
+        let token = cursor.expect("more pages").encode();
+
+        assert!(VmCursor::decode(&token, VmSort::Name).is_some());
+        assert!(VmCursor::decode(&token, VmSort::Status).is_none());
+        assert!(VmCursor::decode("not a cursor", VmSort::Name).is_none());
+        assert!(VmCursor::decode(r#"{"sort":"name","after":["x"]}"#, VmSort::Name).is_none());
+        assert!(VmCursor::decode(r#"{"sort":"name","after":[["x"],"y"]}"#, VmSort::Name).is_none());
+    }
+
+    #[test]
+    fn test_list_vm_summaries_reads_sidebar_columns() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.name = "Sidebar".to_string();
+        vm.status = "running".to_string();
+        store.create_vm(&vm).expect("Failed to create VM");
+
+        let summaries = store.list_vm_summaries(VmSort::Name).expect("Failed to list summaries");
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].id, vm.id);
+        assert_eq!(summaries[0].name, "Sidebar");
+        assert_eq!(summaries[0].status, "running");
+        assert!(!summaries[0].updated_at.is_empty());
+    }
+
+    #[test]
+    fn test_vm_sort_allowlist() {
+        assert_eq!(VmSort::parse("name"), Some(VmSort::Name));
+        assert_eq!(VmSort::parse("created_at"), Some(VmSort::CreatedAt));
+        assert_eq!(VmSort::parse("status"), Some(VmSort::Status));
+        assert_eq!(VmSort::parse("name; DROP TABLE vms"), None);
+        assert_eq!(VmSort::parse("memory_mb"), None);
+    }
+
+    #[test]
+    fn test_transition_status_only_from_expected_status() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.status = "creating".to_string();
+        store.create_vm(&vm).expect("Failed to create VM");
+
+        assert!(!store.transition_status(&vm.id, "running", "stopped").unwrap());
+        assert!(store.transition_status(&vm.id, "creating", "stopped").unwrap());
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().status, "stopped");
+        assert!(!store.transition_status(&vm.id, "creating", "stopped").unwrap());
+        assert!(!store.transition_status("missing", "creating", "stopped").unwrap());
+    }
+
+    #[test]
+    fn test_update_vm() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        
+        store.create_vm(&vm).expect("Failed to create VM");
+        
+        vm.name = "Updated VM".to_string();
+        vm.memory_mb = 4096;
+        
+        let result = store.update_vm(&vm);
+        assert!(result.is_ok());
+        
+        let retrieved = store.get_vm(&vm.id).expect("Failed to get VM");
+        assert!(retrieved.is_some());
+        let retrieved_vm = retrieved.unwrap();
+        assert_eq!(retrieved_vm.name, "Updated VM");
+        assert_eq!(retrieved_vm.memory_mb, 4096);
+    }
+
+    #[test]
+    fn test_update_vm_nonexistent() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        
+        let result = store.update_vm(&vm);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_delete_vm() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        
+        store.create_vm(&vm).expect("Failed to create VM");
+        assert!(store.get_vm(&vm.id).expect("Failed to get VM").is_some());
+        
+        store.delete_vm(&vm.id).expect("Failed to delete VM");
+        assert!(store.get_vm(&vm.id).expect("Failed to get VM").is_none());
+    }
+
+    #[test]
+    fn test_save_and_get_setting() {
+        let store = create_test_db();
+        
+        store.save_setting("test_key", "test_value").expect("Failed to save setting");
+        let result = store.get_setting("test_key").expect("Failed to get setting");
+        
+        assert!(result.is_some());
+        assert_eq!(result.unwrap(), "test_value");
+    }
+
+    #[test]
+    fn test_get_setting_nonexistent() {
+        let store = create_test_db();
+        let result = store.get_setting("nonexistent").expect("Failed to get setting");
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_save_setting_overwrites() {
+        let store = create_test_db();
+        
+        store.save_setting("key", "value1").expect("Failed to save");
+        store.save_setting("key", "value2").expect("Failed to overwrite");
+        
+        let result = store.get_setting("key").expect("Failed to get");
+        assert_eq!(result.unwrap(), "value2");
+    }
+
+    #[test]
+    fn test_list_settings_sorted_by_key() {
+        let store = create_test_db();
+        store.save_setting("zoom", "2").expect("Failed to save setting");
+        store.save_setting("log_level", "debug").expect("Failed to save setting");
+
+        let settings = store.list_settings().expect("Failed to list settings");
+        assert_eq!(
+            settings,
+            vec![
+                ("log_level".to_string(), "debug".to_string()),
+                ("zoom".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_name_exists_ignores_case() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).expect("Failed to create VM");
+
+        assert!(store.name_exists(&vm.name).unwrap());
+        assert!(store.name_exists(&format!(" {} ", vm.name.to_uppercase())).unwrap());
+        assert!(!store.name_exists("Something else").unwrap());
+    }
+
+    #[test]
+    fn test_unique_names_setting_defaults_off() {
+        let store = create_test_db();
+        assert!(!store.unique_names_enabled().unwrap());
+
+        store.save_setting(UNIQUE_NAMES_SETTING, "true").unwrap();
+        assert!(store.unique_names_enabled().unwrap());
+    }
+
+    #[test]
+    fn test_viewer_fullscreen_defaults_off() {
+        let store = create_test_db();
+        assert!(!store.viewer_fullscreen_enabled().unwrap());
+
+        store.save_setting(VIEWER_FULLSCREEN_SETTING, "true").unwrap();
+        assert!(store.viewer_fullscreen_enabled().unwrap());
+    }
+
+    #[test]
+    fn test_display_session_grace_setting() {
+        let store = create_test_db();
+        assert_eq!(store.display_session_grace_secs().unwrap(), DEFAULT_DISPLAY_SESSION_GRACE_SECS);
+
+        store.save_setting(DISPLAY_SESSION_GRACE_SETTING, "60").unwrap();
+        assert_eq!(store.display_session_grace_secs().unwrap(), 60);
+        store.save_setting(DISPLAY_SESSION_GRACE_SETTING, "soon").unwrap();
+        assert_eq!(store.display_session_grace_secs().unwrap(), DEFAULT_DISPLAY_SESSION_GRACE_SECS);
+    }
+
+    #[test]
+    fn test_display_port_range_setting() {
+        let store = create_test_db();
+        assert_eq!(store.display_port_range().unwrap(), DisplayPortRange::default());
+
+        store.save_setting(DISPLAY_PORT_RANGE_START_SETTING, "10000").unwrap();
+        store.save_setting(DISPLAY_PORT_RANGE_END_SETTING, "11000").unwrap();
+        assert_eq!(store.display_port_range().unwrap(), DisplayPortRange { start: 10000, end: 11000 });
+        store.save_setting(DISPLAY_PORT_RANGE_END_SETTING, "9000").unwrap();
+        assert_eq!(store.display_port_range().unwrap(), DisplayPortRange::default());
+        store.save_setting(DISPLAY_PORT_RANGE_END_SETTING, "70000").unwrap();
+        assert_eq!(store.display_port_range().unwrap(), DisplayPortRange::default());
+    }
+
+    #[test]
+    fn test_display_reconnect_attempts_setting() {
+        let store = create_test_db();
+        assert_eq!(store.display_reconnect_max_attempts().unwrap(), DEFAULT_DISPLAY_RECONNECT_ATTEMPTS);
+
+        store.save_setting(DISPLAY_RECONNECT_ATTEMPTS_SETTING, "3").unwrap();
+        assert_eq!(store.display_reconnect_max_attempts().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_metrics_listen_address_setting() {
+        let store = create_test_db();
+        assert_eq!(store.metrics_listen_address().unwrap(), None);
+
+        store.save_setting(METRICS_LISTEN_SETTING, "0.0.0.0:9464").unwrap();
+        assert_eq!(store.metrics_listen_address().unwrap(), Some("0.0.0.0:9464".parse().unwrap()));
+        store.save_setting(METRICS_LISTEN_SETTING, "").unwrap();
+        assert_eq!(store.metrics_listen_address().unwrap(), None);
+    }
+
+    #[test]
+    fn test_control_socket_settings() {
+        let store = create_test_db();
+        assert!(!store.control_socket_enabled().unwrap());
+        assert_eq!(store.control_token().unwrap(), None);
+
+        store.save_setting(CONTROL_SOCKET_SETTING, "true").unwrap();
+        store.save_setting(CONTROL_TOKEN_SETTING, "").unwrap();
+        assert!(store.control_socket_enabled().unwrap());
+        assert_eq!(store.control_token().unwrap(), None);
+        store.save_setting(CONTROL_TOKEN_SETTING, "abc123").unwrap();
+        assert_eq!(store.control_token().unwrap().as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn test_health_check_settings() {
+        let store = create_test_db();
+        assert_eq!(store.health_check_interval_secs().unwrap(), DEFAULT_HEALTH_CHECK_INTERVAL_SECS);
+        assert_eq!(store.health_check_failure_threshold().unwrap(), DEFAULT_HEALTH_CHECK_THRESHOLD);
+
+        store.save_setting(HEALTH_CHECK_INTERVAL_SETTING, "30").unwrap();
+        store.save_setting(HEALTH_CHECK_THRESHOLD_SETTING, "5").unwrap();
+        assert_eq!(store.health_check_interval_secs().unwrap(), 30);
+        assert_eq!(store.health_check_failure_threshold().unwrap(), 5);
+
+        // Zero is neither a usable interval nor a usable threshold
+        store.save_setting(HEALTH_CHECK_INTERVAL_SETTING, "0").unwrap();
+        store.save_setting(HEALTH_CHECK_THRESHOLD_SETTING, "0").unwrap();
+        assert_eq!(store.health_check_interval_secs().unwrap(), DEFAULT_HEALTH_CHECK_INTERVAL_SECS);
+        assert_eq!(store.health_check_failure_threshold().unwrap(), DEFAULT_HEALTH_CHECK_THRESHOLD);
+    }
+
+    #[test]
+    fn test_vm_validation_required_fields() {
+        let store = create_test_db();
+        let vm = VMRecord {
+            id: "".to_string(),
+            name: "Test".to_string(),
+            disk_size_gb: 50,
+            ..VMRecord::default()
+        };
+        
+        let result = store.create_vm(&vm);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_cpu_affinity_roundtrip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.cpu_affinity = vec![0, 2, 3];
+
+        store.create_vm(&vm).expect("Failed to create VM");
+        let retrieved = store.get_vm(&vm.id).expect("Failed to get VM").unwrap();
+        assert_eq!(retrieved.cpu_affinity, vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn test_notes_roundtrip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.notes = "Login: admin\nUsed for CI".to_string();
+        store.create_vm(&vm).expect("Failed to create VM");
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().notes, vm.notes);
+
+        vm.notes = String::new();
+        store.update_vm(&vm).expect("Failed to update VM");
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().notes, "");
+    }
+
+    #[test]
+    fn test_clipboard_sharing_roundtrip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.clipboard_sharing = true;
+        store.create_vm(&vm).expect("Failed to create VM");
+        assert!(store.get_vm(&vm.id).unwrap().unwrap().clipboard_sharing);
+
+        vm.clipboard_sharing = false;
+        store.update_vm(&vm).expect("Failed to update VM");
+        assert!(!store.get_vm(&vm.id).unwrap().unwrap().clipboard_sharing);
+    }
+
+    #[test]
+    fn test_network_and_display_settings_roundtrip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.network_type = "bridge".to_string();
+        vm.vlan_id = Some(4094);
+        vm.display_resolution = Some("1920x1080".to_string());
+        vm.graphics = Some("virtio-gpu".to_string());
+        vm.machine_type = Some("pc-q35-8.2".to_string());
+        vm.display_mode = "none".to_string();
+        vm.boot_menu = Some(BootMenuConfig { enabled: true, splash_time_ms: 3000 });
+        store.create_vm(&vm).expect("Failed to create VM");
+        let stored = store.get_vm(&vm.id).unwrap().unwrap();
+        assert_eq!(stored.boot_menu, Some(BootMenuConfig { enabled: true, splash_time_ms: 3000 }));
+        assert_eq!(stored.display_mode, "none");
+        assert_eq!(stored.machine_type.as_deref(), Some("pc-q35-8.2"));
+        assert_eq!(stored.graphics.as_deref(), Some("virtio-gpu"));
+        assert_eq!(stored.vlan_id, Some(4094));
+        assert_eq!(stored.display_resolution.as_deref(), Some("1920x1080"));
+
+        vm.vlan_id = None;
+        store.update_vm(&vm).expect("Failed to update VM");
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().vlan_id, None);
+    }
+
+    #[test]
+    fn test_performance_roundtrip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().performance, None);
+
+        let performance = VmPerformance { iothreads: 2, net_multiqueue: true, aio: Some("io_uring".to_string()) };
+        vm.performance = Some(performance.clone());
+        store.update_vm(&vm).unwrap();
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().performance, Some(performance));
+    }
+
+    #[test]
+    fn test_spice_compression_roundtrip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().spice_compression, None);
+
+        let compression = SpiceCompression { image_compression: "quic".to_string(), ..SpiceCompression::default() };
+        vm.spice_compression = Some(compression.clone());
+        store.update_vm(&vm).unwrap();
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().spice_compression, Some(compression));
+    }
+
+    #[test]
+    fn test_memory_policy_roundtrip() {
+        let store = create_test_db();
+        assert_eq!(store.memory_policy().unwrap(), MemoryPolicy::default());
+
+        let policy = MemoryPolicy { enabled: true, free_threshold_mb: 4096, vm_minimum_mb: 512 };
+        store.save_memory_policy(&policy).unwrap();
+        assert_eq!(store.memory_policy().unwrap(), policy);
+
+        store.save_setting(MEMORY_POLICY_THRESHOLD_SETTING, "lots").unwrap();
+        assert_eq!(store.memory_policy().unwrap().free_threshold_mb, MemoryPolicy::default().free_threshold_mb);
+    }
+
+    #[test]
+    fn test_benchmark_history_keeps_latest() {
+        let store = create_test_db();
+        assert!(store.benchmark_history().unwrap().is_empty());
+
+        let report = |ran_at_ms: u64| BenchmarkReport {
+            vm_id: None,
+            ran_at_ms,
+            host_arch: "x86_64".to_string(),
+            arch: "x86_64".to_string(),
+            accelerator: "kvm".to_string(),
+            storage_dir: "/data/disks".to_string(),
+            disk_type: None,
+            disk: None,
+            disk_error: None,
+            startup: Vec::new(),
+            speedup: None,
+        };
+        for ran_at_ms in 0..MAX_BENCHMARK_HISTORY as u64 + 2 {
+            store.record_benchmark(&report(ran_at_ms)).unwrap();
+        }
+        let history = store.benchmark_history().unwrap();
+        assert_eq!(history.len(), MAX_BENCHMARK_HISTORY);
+        assert_eq!(history[0].ran_at_ms, 2);
+        assert_eq!(history.last().unwrap().ran_at_ms, MAX_BENCHMARK_HISTORY as u64 + 1);
+    }
+
+    #[test]
+    fn test_ephemeral_roundtrip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        assert!(!store.get_vm(&vm.id).unwrap().unwrap().ephemeral);
+
+        vm.ephemeral = true;
+        store.update_vm(&vm).unwrap();
+        assert!(store.get_vm(&vm.id).unwrap().unwrap().ephemeral);
+    }
+
+    #[test]
+    fn test_migrates_legacy_vms_schema_with_defaults() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let db_path = temp_dir.path().join("legacy.db");
+
+        let conn = Connection::open(&db_path).expect("Failed to open sqlite db");
+        conn.execute(
+            "CREATE TABLE vms (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                status TEXT NOT NULL,
+                memory_mb INTEGER NOT NULL,
+                cpu_cores INTEGER NOT NULL,
+                disk_size_gb INTEGER NOT NULL,
+                os TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )
+        .expect("Failed to create legacy schema");
+        conn.execute(
+            "INSERT INTO vms (id, name, status, memory_mb, cpu_cores, disk_size_gb, os)
+             VALUES ('legacy-vm', 'Legacy VM', 'stopped', 2048, 2, 20, 'linux')",
+            [],
+        )
+        .expect("Failed to seed legacy row");
+        drop(conn);
+
+        let store = ConfigStore::new(db_path).expect("Failed to init store");
+        let vm = store
+            .get_vm("legacy-vm")
+            .expect("Failed to fetch vm")
+            .expect("VM missing");
+
+        assert_eq!(vm.install_media_path, None);
+        assert_eq!(vm.boot_order, "disk-first");
+        assert_eq!(vm.network_type, "nat");
+        assert!(!vm.nested_virt);
+        assert_eq!(vm.notes, "");
+        assert!(!vm.clipboard_sharing);
+        assert_eq!(vm.vlan_id, None);
+        assert_eq!(vm.display_resolution, None);
+        assert_eq!(vm.graphics, None);
+        assert_eq!(vm.machine_type, None);
+        assert_eq!(vm.display_mode, "spice");
+        assert_eq!(vm.boot_menu, None);
+    }
+
+    #[test]
+    fn test_nested_groups() {
+        let store = create_test_db();
+
+        let lab = store.create_group("Lab", None).expect("Failed to create group");
+        let web = store.create_group("Web", Some(&lab)).expect("Failed to create nested group");
+
+        let groups = store.list_groups().expect("Failed to list groups");
+        assert_eq!(groups.len(), 2);
+        let web_group = groups.iter().find(|g| g.id == web).expect("Nested group missing");
+        assert_eq!(web_group.parent_id.as_deref(), Some(lab.as_str()));
+        assert!(groups.iter().any(|g| g.id == lab && g.parent_id.is_none()));
+
+        assert!(store.create_group("Orphan", Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_group_membership() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).expect("Failed to create VM");
+        let group = store.create_group("Lab", None).expect("Failed to create group");
+
+        store.add_vm_to_group(&vm.id, &group).expect("Failed to add VM");
+        store.add_vm_to_group(&vm.id, &group).expect("Adding twice should be a no-op");
+        let members = store.list_vms_in_group(&group).expect("Failed to list members");
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].id, vm.id);
+        assert_eq!(store.vm_group_ids(&vm.id).unwrap(), vec![group.clone()]);
+
+        assert!(store.add_vm_to_group("missing", &group).is_err());
+
+        store.remove_vm_from_group(&vm.id, &group).expect("Failed to remove VM");
+        assert!(store.list_vms_in_group(&group).unwrap().is_empty());
+        assert!(store.vm_group_ids(&vm.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delete_group_keeps_vms() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).expect("Failed to create VM");
+        let lab = store.create_group("Lab", None).unwrap();
+        let web = store.create_group("Web", Some(&lab)).unwrap();
+        store.add_vm_to_group(&vm.id, &lab).unwrap();
+
+        store.delete_group(&lab).expect("Failed to delete group");
+
+        assert!(store.get_vm(&vm.id).unwrap().is_some());
+        assert!(store.list_vms_in_group(&lab).unwrap().is_empty());
+        let groups = store.list_groups().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].id, web);
+        assert_eq!(groups[0].parent_id, None);
+    }
+
+    #[test]
+    fn test_groups_list_their_members() {
+        let store = create_test_db();
+        let (vm1, vm2) = (create_test_vm(), create_test_vm());
+        store.create_vm(&vm1).unwrap();
+        store.create_vm(&vm2).unwrap();
+        let lab = store.create_group("Lab", None).unwrap();
+        let web = store.create_group("Web", None).unwrap();
+        store.add_vm_to_group(&vm1.id, &lab).unwrap();
+        store.add_vm_to_group(&vm2.id, &lab).unwrap();
+        store.add_vm_to_group(&vm1.id, &web).unwrap();
+
+        let groups = store.list_groups().unwrap();
+        let mut lab_members = vec![vm1.id.clone(), vm2.id.clone()];
+        lab_members.sort();
+        assert_eq!(groups.iter().find(|g| g.id == lab).unwrap().vm_ids, lab_members);
+        assert_eq!(groups.iter().find(|g| g.id == web).unwrap().vm_ids, vec![vm1.id.clone()]);
+
+        let memberships = store.group_memberships().unwrap();
+        assert_eq!(memberships[&vm1.id].len(), 2);
+        assert_eq!(memberships[&vm2.id], vec![lab]);
+    }
+
+    #[test]
+    fn test_vm_dependencies() {
+        let store = create_test_db();
+        let (db, app, cache) = (create_test_vm(), create_test_vm(), create_test_vm());
+        for vm in [&db, &app, &cache] {
+            store.create_vm(vm).unwrap();
+        }
+
+        store.set_vm_dependencies(&app.id, &[db.id.clone(), cache.id.clone(), db.id.clone()]).unwrap();
+        let dependencies = store.all_vm_dependencies().unwrap();
+        assert_eq!(dependencies.len(), 1);
+        assert_eq!(dependencies[&app.id].len(), 2);
+
+        // Replaced as a whole, and left untouched when a dependency is unknown
+        store.set_vm_dependencies(&app.id, std::slice::from_ref(&db.id)).unwrap();
+        assert!(store.set_vm_dependencies(&app.id, &["missing".to_string()]).is_err());
+        assert_eq!(store.all_vm_dependencies().unwrap()[&app.id], vec![db.id.clone()]);
+
+        store.delete_vm(&db.id).unwrap();
+        assert!(store.all_vm_dependencies().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delete_vm_removes_group_membership() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        let group = store.create_group("Lab", None).unwrap();
+        store.add_vm_to_group(&vm.id, &group).unwrap();
+
+        store.delete_vm(&vm.id).unwrap();
+
+        assert!(store.list_vms_in_group(&group).unwrap().is_empty());
+        assert_eq!(store.list_groups().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_tags_are_listed_per_vm() {
+        let store = create_test_db();
+        let vm1 = create_test_vm();
+        let vm2 = create_test_vm();
+        store.create_vm(&vm1).unwrap();
+        store.create_vm(&vm2).unwrap();
+
+        store.add_tag(&vm1.id, "work").unwrap();
+        store.add_tag(&vm1.id, "homelab").unwrap();
+        store.add_tag(&vm1.id, "work").unwrap();
+        store.add_tag(&vm2.id, "testing").unwrap();
+
+        let vm1 = store.get_vm(&vm1.id).unwrap().unwrap();
+        assert_eq!(vm1.tags, vec!["homelab", "work"]);
+        assert_eq!(store.list_tags().unwrap(), vec!["homelab", "testing", "work"]);
+        let listed: Vec<Vec<String>> = store.list_vms().unwrap().into_iter().map(|vm| vm.tags).collect();
+        assert!(listed.contains(&vec!["testing".to_string()]));
+
+        store.remove_tag(&vm1.id, "work").unwrap();
+        assert_eq!(store.get_vm(&vm1.id).unwrap().unwrap().tags, vec!["homelab"]);
+    }
+
+    #[test]
+    fn test_list_vms_by_tag() {
+        let store = create_test_db();
+        let vm1 = create_test_vm();
+        let vm2 = create_test_vm();
+        store.create_vm(&vm1).unwrap();
+        store.create_vm(&vm2).unwrap();
+        store.add_tag(&vm2.id, "testing").unwrap();
+
+        let tagged = store.list_vms_by_tag("testing").unwrap();
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].id, vm2.id);
+        assert!(store.list_vms_by_tag("work").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_add_tag_to_missing_vm_fails() {
+        let store = create_test_db();
+        let err = store.add_tag("missing", "work").unwrap_err();
+        assert!(matches!(err, Error::VmNotFound(_)));
+    }
+
+    #[test]
+    fn test_mac_address_is_stored_in_networks() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.mac_address = Some("52:54:00:12:34:56".to_string());
+        store.create_vm(&vm).unwrap();
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().mac_address, vm.mac_address);
+
+        store.set_vm_mac(&vm.id, "52:54:00:ab:cd:ef").unwrap();
+        let stored = store.get_vm(&vm.id).unwrap().unwrap();
+        assert_eq!(stored.mac_address.as_deref(), Some("52:54:00:ab:cd:ef"));
+        // Updating the VM leaves the NIC alone
+        store.update_vm(&stored).unwrap();
+        assert_eq!(store.list_vms().unwrap()[0].mac_address.as_deref(), Some("52:54:00:ab:cd:ef"));
+
+        // A VM recreated under the same id starts without the old NIC
+        store.delete_vm(&vm.id).unwrap();
+        let mut recreated = create_test_vm();
+        recreated.id = vm.id.clone();
+        store.create_vm(&recreated).unwrap();
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().mac_address, None);
+    }
+
+    #[test]
+    fn test_delete_vm_removes_tags() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        store.add_tag(&vm.id, "work").unwrap();
+
+        store.delete_vm(&vm.id).unwrap();
+
+        assert!(store.list_tags().unwrap().is_empty());
+        assert!(store.list_vms_by_tag("work").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_disk_discard_is_kept_with_the_disk_location() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        assert!(!store.disk_discard(&vm.id).unwrap());
+
+        // Enabling discard on a disk that was never moved still reports the default location
+        store.set_disk_discard(&vm.id, true).unwrap();
+        assert!(store.disk_discard(&vm.id).unwrap());
+        assert_eq!(store.disk_location(&vm.id).unwrap(), None);
+
+        store.set_disk_location(&vm.id, "/mnt/a/vm.qcow2").unwrap();
+        assert!(store.disk_discard(&vm.id).unwrap());
+        assert_eq!(store.disk_location(&vm.id).unwrap().as_deref(), Some("/mnt/a/vm.qcow2"));
+
+        store.set_disk_discard(&vm.id, false).unwrap();
+        assert!(!store.disk_discard(&vm.id).unwrap());
+    }
+
+    #[test]
+    fn test_disk_interface_defaults_to_virtio() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        assert_eq!(store.disk_interface(&vm.id).unwrap(), "virtio");
+
+        store.set_disk_interface(&vm.id, "virtio-scsi").unwrap();
+        store.set_disk_discard(&vm.id, true).unwrap();
+        assert_eq!(store.disk_interface(&vm.id).unwrap(), "virtio-scsi");
+        assert_eq!(store.disk_location(&vm.id).unwrap(), None);
+
+        // A hot-added disk keeps its own interface
+        store.add_hotplugged_drive(&vm.id, "drive-1", "/data/extra.qcow2", "virtio", "qcow2").unwrap();
+        assert_eq!(store.disk_interface(&vm.id).unwrap(), "virtio-scsi");
+    }
+
+    #[test]
+    fn test_disk_location_round_trip_and_delete() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        assert_eq!(store.disk_location(&vm.id).unwrap(), None);
+
+        store.set_disk_location(&vm.id, "/mnt/a/vm.qcow2").unwrap();
+        store.set_disk_location(&vm.id, "/mnt/b/vm.qcow2").unwrap();
+        assert_eq!(store.disk_location(&vm.id).unwrap().as_deref(), Some("/mnt/b/vm.qcow2"));
+
+        // A hot-added disk is neither reported nor moved as the VM's own disk
+        store.add_hotplugged_drive(&vm.id, "drive-1", "/data/extra.qcow2", "virtio", "qcow2").unwrap();
+        store.set_disk_location(&vm.id, "/mnt/c/vm.qcow2").unwrap();
+        assert_eq!(store.disk_location(&vm.id).unwrap().as_deref(), Some("/mnt/c/vm.qcow2"));
+
+        store.delete_vm(&vm.id).unwrap();
+        assert_eq!(store.disk_location(&vm.id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_hotplugged_drives_round_trip() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        store.set_disk_location(&vm.id, "/data/vm.qcow2").unwrap();
+        assert!(store.hotplugged_drives(&vm.id).unwrap().is_empty());
+
+        store.add_hotplugged_drive(&vm.id, "drive-1", "/data/extra.qcow2", "virtio", "qcow2").unwrap();
+        store.add_hotplugged_drive(&vm.id, "drive-2", "/data/scratch.img", "virtio", "raw").unwrap();
+        let ids: Vec<_> = store.hotplugged_drives(&vm.id).unwrap().into_iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec!["drive-1", "drive-2"]);
+
+        store.remove_hotplugged_drive(&vm.id, "drive-1").unwrap();
+        let drives = store.hotplugged_drives(&vm.id).unwrap();
+        assert_eq!(drives.len(), 1);
+        assert_eq!(drives[0].path, "/data/scratch.img");
+        assert_eq!(drives[0].format, "raw");
+        assert!(matches!(store.remove_hotplugged_drive(&vm.id, "drive-1"), Err(Error::NotFound(_))));
+
+        // The VM's own disk is not a hot-added one
+        let own = store.disk_location(&vm.id).unwrap();
+        assert_eq!(own.as_deref(), Some("/data/vm.qcow2"));
+    }
+
+    #[test]
+    fn test_relocate_storage_repoints_drives() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        store.set_disk_location(&vm.id, "/old/vm.qcow2").unwrap();
+        store.add_hotplugged_drive(&vm.id, "drive-1", "/old/extra.qcow2", "virtio", "qcow2").unwrap();
+        store.add_hotplugged_drive(&vm.id, "drive-2", "/elsewhere/data.qcow2", "virtio", "qcow2").unwrap();
+
+        let moves = [
+            ("/old/vm.qcow2".to_string(), "/new/vm.qcow2".to_string()),
+            ("/old/extra.qcow2".to_string(), "/new/extra.qcow2".to_string()),
+        ];
+        store.relocate_storage(&moves, "/new").unwrap();
+
+        assert_eq!(store.disk_location(&vm.id).unwrap().as_deref(), Some("/new/vm.qcow2"));
+        let mut paths = store.referenced_disk_paths().unwrap();
+        paths.sort();
+        assert_eq!(paths, vec!["/elsewhere/data.qcow2", "/new/extra.qcow2", "/new/vm.qcow2"]);
+        assert_eq!(store.get_setting(STORAGE_DIR_KEY).unwrap().as_deref(), Some("/new"));
+    }
+
+    #[test]
+    fn test_network_adapters_round_trip() {
+        let store = create_test_db();
+        let mut vm = create_test_vm();
+        vm.mac_address = Some("52:54:00:12:34:56".to_string());
+        store.create_vm(&vm).unwrap();
+        assert!(store.list_networks(&vm.id).unwrap().is_empty());
+
+        let adapter = |id: &str, kind: &str| NetworkConfig {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            mac: Some(crate::qemu::generate_stable_mac(id)),
+            port_forwards: Vec::new(),
+        };
+        let mut mgmt = adapter("net0", "nat");
+        mgmt.port_forwards.push(crate::qemu::PortForward {
+            protocol: "tcp".to_string(),
+            host_port: 2222,
+            guest_port: 22,
+        });
+        store.add_network(&vm.id, &mgmt).unwrap();
+        store.add_network(&vm.id, &adapter("data", "bridge")).unwrap();
+        assert!(store.add_network(&vm.id, &adapter("data", "nat")).is_err());
+        assert!(matches!(store.add_network("missing", &mgmt), Err(Error::NotFound(_))));
+
+        // Adapters leave the primary NIC's MAC alone
+        assert_eq!(store.list_networks(&vm.id).unwrap(), vec![mgmt.clone(), adapter("data", "bridge")]);
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().mac_address, vm.mac_address);
+
+        store.remove_network(&vm.id, "data").unwrap();
+        assert!(matches!(store.remove_network(&vm.id, "data"), Err(Error::NotFound(_))));
+        assert_eq!(store.list_networks(&vm.id).unwrap(), vec![mgmt.clone()]);
+
+        let replacement = vec![adapter("lan", "bridge"), mgmt.clone()];
+        store.replace_networks(&vm.id, &replacement).unwrap();
+        assert_eq!(store.list_networks(&vm.id).unwrap(), replacement);
+        assert_eq!(store.get_vm(&vm.id).unwrap().unwrap().mac_address, vm.mac_address);
+        store.replace_networks(&vm.id, &[]).unwrap();
+        assert!(store.list_networks(&vm.id).unwrap().is_empty());
+        store.replace_networks(&vm.id, &[mgmt.clone()]).unwrap();
+        assert_eq!(store.list_networks(&vm.id).unwrap(), vec![mgmt]);
+
+        store.delete_vm(&vm.id).unwrap();
+        assert!(store.list_networks(&vm.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_external_snapshots_move_the_disk_location() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        store.set_disk_location(&vm.id, "/data/vm.qcow2").unwrap();
+
+        store.add_external_snapshot(&vm.id, "base", "/data/vm-base.qcow2", "/data/vm.qcow2").unwrap();
+        store.add_external_snapshot(&vm.id, "patched", "/data/vm-patched.qcow2", "/data/vm-base.qcow2").unwrap();
+        assert!(store.add_external_snapshot(&vm.id, "base", "/data/other.qcow2", "/data/vm.qcow2").is_err());
+        let names: Vec<_> = store.external_snapshots(&vm.id).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["base", "patched"]);
+        assert_eq!(store.disk_location(&vm.id).unwrap().as_deref(), Some("/data/vm-patched.qcow2"));
+
+        store.remove_external_snapshot(&vm.id, "patched").unwrap();
+        assert_eq!(store.disk_location(&vm.id).unwrap().as_deref(), Some("/data/vm-base.qcow2"));
+        assert!(matches!(store.remove_external_snapshot(&vm.id, "patched"), Err(Error::NotFound(_))));
+
+        store.delete_vm(&vm.id).unwrap();
+        assert!(store.external_snapshots(&vm.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_vm_events_round_trip_and_delete() {
+        let store = create_test_db();
+        let vm = create_test_vm();
+        store.create_vm(&vm).unwrap();
+        store.record_vm_event(&vm.id, "process_start", 1_000).unwrap();
+        store.record_vm_event(&vm.id, "qmp_ready", 1_250).unwrap();
+
+        let kinds: Vec<String> = store
+            .recent_vm_events(&vm.id, 10)
+            .unwrap()
+            .into_iter()
+            .map(|event| event.kind)
+            .collect();
+        assert_eq!(kinds, vec!["process_start", "qmp_ready"]);
+        // A limit keeps the newest, still oldest first
+        assert_eq!(store.recent_vm_events(&vm.id, 1).unwrap()[0].at_ms, 1_250);
+
+        store.delete_vm(&vm.id).unwrap();
+        assert!(store.recent_vm_events(&vm.id, 10).unwrap().is_empty());
+    }
+
+    fn user_profile(id: &str, name: &str) -> ProfileRecord {
+        ProfileRecord {
+            id: id.to_string(),
+            name: name.to_string(),
+            builtin: false,
+            config: serde_json::json!({ "memory_mb": 3072 }).as_object().unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn test_builtin_profiles_are_seeded_once() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let store = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let builtin_ids: Vec<String> = store.list_profiles().unwrap().into_iter().map(|p| p.id).collect();
+        assert_eq!(builtin_ids.len(), 3);
+
+        // Reopening must not duplicate or reset them
+        let reopened = ConfigStore::new(temp_dir.path().join("test.db")).unwrap();
+        let profiles = reopened.list_profiles().unwrap();
+        assert_eq!(profiles.len(), 3);
+        assert!(profiles.iter().all(|profile| profile.builtin));
+    }
+
+    #[test]
+    fn test_profile_crud() {
+        let store = create_test_db();
+        let mut profile = user_profile("p1", "Build box");
+        store.save_profile(&profile).unwrap();
+        assert_eq!(store.get_profile("p1").unwrap(), Some(profile.clone()));
+
+        profile.name = "Build server".to_string();
+        store.save_profile(&profile).unwrap();
+        let profiles = store.list_profiles().unwrap();
+        assert_eq!(profiles.len(), 4);
+        assert_eq!(profiles.last().unwrap().name, "Build server");
+
+        assert!(store.delete_profile("p1").unwrap());
+        assert_eq!(store.get_profile("p1").unwrap(), None);
+        assert!(!store.delete_profile("p1").unwrap());
+    }
+
+    #[test]
+    fn test_builtin_profiles_are_protected() {
+        let store = create_test_db();
+        let builtin = store.list_profiles().unwrap().remove(0);
+
+        assert!(!store.delete_profile(&builtin.id).unwrap());
+        assert!(store.save_profile(&user_profile(&builtin.id, "Hijacked")).is_err());
+        assert_eq!(store.get_profile(&builtin.id).unwrap(), Some(builtin));
+    }
+}
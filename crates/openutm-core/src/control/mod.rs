@@ -0,0 +1,414 @@
+//! JSON-RPC control socket for headless and scripted use
+//!
+//! When enabled, a unix socket (a named pipe on Windows) accepts
+//! newline-delimited JSON-RPC 2.0 requests. Every request carries the token
+//! from settings as `params.token`. Methods run through `service`, so they
+//! behave exactly like the matching Tauri commands. Dropping the
+//! `ControlServer` closes the socket.
+
+use crate::app::AppState;
+use crate::error::{CommandError, CommandResult};
+use crate::service;
+use crate::Result;
+use crate::VMConfig;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Control socket settings shown to the user for scripting against the app
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlSocketInfo {
+    pub enabled: bool,
+    /// Unix socket path, or the named pipe on Windows
+    pub endpoint: String,
+    /// `None` until the socket is first enabled
+    pub token: Option<String>,
+}
+
+#[cfg(unix)]
+const SOCKET_FILE: &str = "control.sock";
+#[cfg(windows)]
+const PIPE_NAME: &str = r"\\.\pipe\openutm-control";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// A `CommandError`, attached as the error's `data`
+const COMMAND_FAILED: i64 = -32000;
+const UNAUTHORIZED: i64 = -32001;
+
+/// Where the control socket listens: a socket in `runtime_dir`, or a fixed pipe name on Windows
+#[cfg(unix)]
+pub fn endpoint(runtime_dir: &Path) -> PathBuf {
+    runtime_dir.join(SOCKET_FILE)
+}
+
+#[cfg(windows)]
+pub fn endpoint(_runtime_dir: &Path) -> PathBuf {
+    PathBuf::from(PIPE_NAME)
+}
+
+pub struct ControlServer {
+    endpoint: PathBuf,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// Listen on `endpoint` and answer each request line with what `handle` returns; `None` sends nothing back
+    pub fn spawn<F, Fut>(endpoint: PathBuf, handle: F) -> Result<Self>
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        let task = tokio::spawn(accept_loop(listen(&endpoint)?, Arc::new(handle)));
+        tracing::info!(endpoint = %endpoint.display(), "control socket listening");
+        Ok(Self { endpoint, task })
+    }
+
+    #[cfg(test)]
+    pub fn endpoint(&self) -> &Path {
+        &self.endpoint
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.endpoint);
+        tracing::info!(endpoint = %self.endpoint.display(), "control socket stopped");
+    }
+}
+
+#[cfg(unix)]
+type Listener = tokio::net::UnixListener;
+#[cfg(windows)]
+type Listener = tokio::net::windows::named_pipe::NamedPipeServer;
+
+#[cfg(unix)]
+fn listen(endpoint: &Path) -> Result<Listener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Only one instance runs, so an existing socket was left behind by a crash
+    let _ = std::fs::remove_file(endpoint);
+    let listener = tokio::net::UnixListener::bind(endpoint)?;
+    // The token is the real check; this keeps other users from even connecting
+    std::fs::set_permissions(endpoint, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+#[cfg(windows)]
+fn listen(endpoint: &Path) -> Result<Listener> {
+    Ok(tokio::net::windows::named_pipe::ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(endpoint)?)
+}
+
+#[cfg(unix)]
+async fn accept_loop<F, Fut>(listener: Listener, handle: Arc<F>)
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<String>> + Send + 'static,
+{
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve(stream, handle.clone()));
+            }
+            Err(err) => tracing::warn!(error = %err, "control socket accept failed"),
+        }
+    }
+}
+
+#[cfg(windows)]
+async fn accept_loop<F, Fut>(mut pipe: Listener, handle: Arc<F>)
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<String>> + Send + 'static,
+{
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    loop {
+        if let Err(err) = pipe.connect().await {
+            tracing::warn!(error = %err, "control pipe connect failed");
+            continue;
+        }
+        // A pipe instance serves one client; open the next before handing this one off
+        let next = match ServerOptions::new().create(PIPE_NAME) {
+            Ok(next) => next,
+            Err(err) => {
+                tracing::warn!(error = %err, "control pipe could not accept more clients");
+                return;
+            }
+        };
+        tokio::spawn(serve(std::mem::replace(&mut pipe, next), handle.clone()));
+    }
+}
+
+async fn serve<S, F, Fut>(stream: S, handle: Arc<F>)
+where
+    S: AsyncRead + AsyncWrite,
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Option<String>>,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(mut response) = handle(line).await else { continue };
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+/// Compare without stopping at the first differing byte
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[derive(serde::Deserialize)]
+struct VmParams {
+    id: String,
+}
+
+#[derive(serde::Deserialize)]
+struct StartParams {
+    id: String,
+    #[serde(default)]
+    passphrase: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct CreateParams {
+    config: VMConfig,
+    #[serde(default)]
+    passphrase: Option<String>,
+}
+
+/// Answer one JSON-RPC request line; notifications (no `id`) run but get no response
+pub async fn dispatch(state: &AppState, line: &str) -> Option<Value> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(_) => return Some(error_response(Value::Null, PARSE_ERROR, "Parse error", None)),
+    };
+    let id = request.get("id").cloned();
+    let reply = |response: Value| id.is_some().then_some(response);
+    let response_id = id.clone().unwrap_or(Value::Null);
+
+    let method = match (request.get("jsonrpc").and_then(Value::as_str), request.get("method").and_then(Value::as_str)) {
+        (Some("2.0"), Some(method)) => method,
+        _ => return Some(error_response(response_id, INVALID_REQUEST, "Invalid request", None)),
+    };
+    let params = request.get("params").cloned().unwrap_or_else(|| json!({}));
+
+    let expected = state.config_store.control_token().ok().flatten();
+    let given = params.get("token").and_then(Value::as_str);
+    let authorized = matches!(
+        (expected.as_deref(), given),
+        (Some(expected), Some(given)) if token_matches(expected, given)
+    );
+    if !authorized {
+        tracing::warn!(method = %method, "control socket request with a missing or wrong token");
+        return reply(error_response(response_id, UNAUTHORIZED, "Unauthorized", None));
+    }
+
+    let result = match call(state, method, params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": response_id, "result": result }),
+        Err(Failure::UnknownMethod) => error_response(response_id, METHOD_NOT_FOUND, "Method not found", None),
+        Err(Failure::InvalidParams(detail)) => {
+            error_response(response_id, INVALID_PARAMS, "Invalid params", Some(Value::String(detail)))
+        }
+        Err(Failure::Command(err)) => {
+            let data = serde_json::to_value(&err).ok();
+            error_response(response_id, COMMAND_FAILED, &err.message, data)
+        }
+    };
+    reply(result)
+}
+
+enum Failure {
+    UnknownMethod,
+    InvalidParams(String),
+    Command(CommandError),
+}
+
+impl From<CommandError> for Failure {
+    fn from(err: CommandError) -> Self {
+        Failure::Command(err)
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> std::result::Result<T, Failure> {
+    serde_json::from_value(params).map_err(|err| Failure::InvalidParams(err.to_string()))
+}
+
+fn to_result<T: serde::Serialize>(result: CommandResult<T>) -> std::result::Result<Value, Failure> {
+    let value = result?;
+    Ok(serde_json::to_value(value).unwrap_or(Value::Null))
+}
+
+async fn call(state: &AppState, method: &str, params: Value) -> std::result::Result<Value, Failure> {
+    match method {
+        "vm.list" => to_result(service::list_vms(state)),
+        "vm.create" => {
+            let params: CreateParams = parse_params(params)?;
+            to_result(service::create_vm(state, params.config, params.passphrase).await)
+        }
+        "vm.start" => {
+            let params: StartParams = parse_params(params)?;
+            to_result(service::start_vm(state, &params.id, params.passphrase.as_deref()).await)
+        }
+        "vm.stop" => {
+            let params: VmParams = parse_params(params)?;
+            to_result(service::stop_vm(state, &params.id).await)
+        }
+        "vm.metrics" => {
+            let params: VmParams = parse_params(params)?;
+            to_result(service::vm_metrics(state, &params.id))
+        }
+        _ => Err(Failure::UnknownMethod),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigStore, VMRecord, CONTROL_TOKEN_SETTING};
+    use crate::paths::AppPaths;
+    use crate::qemu::QemuController;
+    use tempfile::TempDir;
+
+    const TOKEN: &str = "secret-token";
+
+    fn test_state(temp_dir: &TempDir) -> Arc<AppState> {
+        let paths = AppPaths::legacy(temp_dir.path(), temp_dir.path().join("run"));
+        paths.ensure_dirs().unwrap();
+        let config_store = ConfigStore::new(paths.db_path()).unwrap();
+        config_store.save_setting(CONTROL_TOKEN_SETTING, TOKEN).unwrap();
+        let controller = QemuController::new("qemu-system-x86_64".to_string());
+        Arc::new(AppState::new(config_store, paths, controller))
+    }
+
+    fn test_record(id: &str) -> VMRecord {
+        VMRecord {
+            id: id.to_string(),
+            name: "Headless".to_string(),
+            ..VMRecord::default()
+        }
+    }
+
+    fn spawn_server(state: Arc<AppState>) -> ControlServer {
+        let socket = endpoint(&state.paths.runtime_dir);
+        ControlServer::spawn(socket, move |line| {
+            let state = state.clone();
+            async move { dispatch(&state, &line).await.map(|response| response.to_string()) }
+        })
+        .unwrap()
+    }
+
+    async fn request(
+        lines: &mut tokio::io::Lines<BufReader<tokio::net::unix::OwnedReadHalf>>,
+        writer: &mut tokio::net::unix::OwnedWriteHalf,
+        request: Value,
+    ) -> Value {
+        writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+        serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_socket() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+        state.config_store.create_vm(&test_record("vm-1")).unwrap();
+        let server = spawn_server(state.clone());
+
+        let stream = tokio::net::UnixStream::connect(server.endpoint()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let response = request(&mut lines, &mut writer, json!({"jsonrpc": "2.0", "id": 1, "method": "vm.list"})).await;
+        assert_eq!(response["error"]["code"], UNAUTHORIZED);
+        assert_eq!(response["id"], 1);
+        let wrong = json!({"jsonrpc": "2.0", "id": 2, "method": "vm.list", "params": {"token": "guess"}});
+        assert_eq!(request(&mut lines, &mut writer, wrong).await["error"]["code"], UNAUTHORIZED);
+
+        // Several requests share one connection
+        let list = json!({"jsonrpc": "2.0", "id": 3, "method": "vm.list", "params": {"token": TOKEN}});
+        let response = request(&mut lines, &mut writer, list).await;
+        assert_eq!(response["result"][0]["id"], "vm-1");
+        assert_eq!(response["result"][0]["name"], "Headless");
+
+        let params = json!({"token": TOKEN, "id": "vm-1"});
+        let metrics = json!({"jsonrpc": "2.0", "id": 4, "method": "vm.metrics", "params": params});
+        let response = request(&mut lines, &mut writer, metrics).await;
+        assert_eq!(response["result"]["running"], false);
+        assert_eq!(response["result"]["pid"], Value::Null);
+
+        let stop = json!({"jsonrpc": "2.0", "id": 5, "method": "vm.stop", "params": params});
+        let response = request(&mut lines, &mut writer, stop).await;
+        assert_eq!(response["error"]["code"], COMMAND_FAILED);
+        assert_eq!(response["error"]["data"]["code"], "vmNotRunning");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+
+        let response = dispatch(&state, "{not json").await.unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+        let response = dispatch(&state, r#"{"id": 1, "method": "vm.list"}"#).await.unwrap();
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+
+        let unknown = json!({"jsonrpc": "2.0", "id": 1, "method": "vm.destroy", "params": {"token": TOKEN}});
+        let response = dispatch(&state, &unknown.to_string()).await.unwrap();
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let missing_id = json!({"jsonrpc": "2.0", "id": 1, "method": "vm.start", "params": {"token": TOKEN}});
+        let response = dispatch(&state, &missing_id.to_string()).await.unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        // Same validation as the create_vm command
+        let config = json!({"name": "", "memory_mb": 16, "cpu_cores": 1, "disk_size_gb": 10, "os": "linux"});
+        let params = json!({"token": TOKEN, "config": config});
+        let create = json!({"jsonrpc": "2.0", "id": 1, "method": "vm.create", "params": params});
+        let response = dispatch(&state, &create.to_string()).await.unwrap();
+        assert_eq!(response["error"]["code"], COMMAND_FAILED);
+        assert_eq!(response["error"]["data"]["code"], "validationFailed");
+        assert!(response["error"]["data"]["messageKey"].is_string());
+
+        // Notifications get no response
+        let notification = json!({"jsonrpc": "2.0", "method": "vm.list", "params": {"token": TOKEN}});
+        assert_eq!(dispatch(&state, &notification.to_string()).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_server_removes_socket() {
+        let temp_dir = TempDir::new().unwrap();
+        let server = spawn_server(test_state(&temp_dir));
+        let socket = server.endpoint().to_path_buf();
+        let mode = std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&socket).unwrap().permissions());
+        assert_eq!(mode & 0o777, 0o600);
+
+        drop(server);
+        assert!(!socket.exists());
+        assert!(tokio::net::UnixStream::connect(&socket).await.is_err());
+    }
+}
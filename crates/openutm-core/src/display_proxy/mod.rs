@@ -0,0 +1,277 @@
+//! WebSocket bridge for in-app SPICE/VNC viewers
+//!
+//! spice-html5 and noVNC can only speak WebSocket, while QEMU exposes its
+//! display as a raw TCP socket. Each VM with an open display gets one
+//! loopback WebSocket listener that relays binary frames to and from the
+//! VM's display port. Dropping the `DisplayProxy` closes the listener and
+//! every bridged connection.
+
+use crate::Result;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// Subprotocol both spice-html5 and noVNC offer for raw binary frames
+const BINARY_SUBPROTOCOL: &str = "binary";
+
+const RELAY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A running WebSocket listener bridging to one VM's display port
+pub struct DisplayProxy {
+    addr: SocketAddr,
+    target: SocketAddr,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl DisplayProxy {
+    /// Listen on an ephemeral loopback port and relay every accepted WebSocket to `target`
+    pub async fn spawn(target: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(accept_loop(listener, target));
+        tracing::debug!(%addr, %target, "display proxy listening");
+        Ok(Self { addr, target, task })
+    }
+
+    pub fn ws_uri(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+}
+
+impl Drop for DisplayProxy {
+    fn drop(&mut self) {
+        // Aborting the accept loop drops its JoinSet, which aborts every bridge
+        self.task.abort();
+    }
+}
+
+/// Return the WebSocket URI of `vm_id`'s proxy, spawning one unless a live
+/// proxy to the same target already exists
+pub async fn ensure_proxy(
+    proxies: &mut HashMap<String, DisplayProxy>,
+    vm_id: &str,
+    target: SocketAddr,
+) -> Result<String> {
+    if let Some(proxy) = proxies.get(vm_id) {
+        if proxy.target == target && !proxy.task.is_finished() {
+            return Ok(proxy.ws_uri());
+        }
+    }
+    let proxy = DisplayProxy::spawn(target).await?;
+    let ws_uri = proxy.ws_uri();
+    // Replacing a stale proxy drops and shuts it down
+    proxies.insert(vm_id.to_string(), proxy);
+    Ok(ws_uri)
+}
+
+async fn accept_loop(listener: TcpListener, target: SocketAddr) {
+    let mut bridges = tokio::task::JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) if peer.ip().is_loopback() => {
+                    bridges.spawn(async move {
+                        if let Err(err) = serve(stream, target).await {
+                            tracing::debug!(%peer, error = %err, "display proxy connection ended");
+                        }
+                    });
+                }
+                Ok((_, peer)) => tracing::warn!(%peer, "display proxy refused non-loopback peer"),
+                Err(err) => {
+                    tracing::warn!(error = %err, "display proxy accept failed");
+                    return;
+                }
+            },
+            // Reap finished bridges so the set does not grow with every reconnect
+            Some(_) = bridges.join_next(), if !bridges.is_empty() => {}
+        }
+    }
+}
+
+async fn serve(stream: TcpStream, target: SocketAddr) -> std::result::Result<(), WsError> {
+    let ws = tokio_tungstenite::accept_hdr_async(stream, check_handshake).await?;
+    let upstream = TcpStream::connect(target).await?;
+    bridge(ws, upstream).await
+}
+
+/// Refuse foreign origins and echo the binary subprotocol when offered
+// The signature is tungstenite's handshake callback, so the large error cannot be boxed
+#[allow(clippy::result_large_err)]
+fn check_handshake(request: &Request, mut response: Response) -> std::result::Result<Response, ErrorResponse> {
+    let origin = request.headers().get("origin").and_then(|value| value.to_str().ok());
+    if let Some(origin) = origin {
+        if !is_local_origin(origin) {
+            let mut rejection = ErrorResponse::new(Some(format!("Origin {} is not allowed", origin)));
+            *rejection.status_mut() = StatusCode::FORBIDDEN;
+            return Err(rejection);
+        }
+    }
+
+    let offers_binary = request
+        .headers()
+        .get_all("sec-websocket-protocol")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == BINARY_SUBPROTOCOL);
+    if offers_binary {
+        response
+            .headers_mut()
+            .insert("sec-websocket-protocol", HeaderValue::from_static(BINARY_SUBPROTOCOL));
+    }
+    Ok(response)
+}
+
+/// Whether a browser `Origin` belongs to the app's own webview or a loopback page
+fn is_local_origin(origin: &str) -> bool {
+    let Some((scheme, rest)) = origin.split_once("://") else {
+        return false;
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    let host = if let Some(bracketed) = authority.strip_prefix('[') {
+        bracketed.split(']').next().unwrap_or_default()
+    } else {
+        authority.split(':').next().unwrap_or_default()
+    };
+
+    match scheme {
+        "tauri" => host == "localhost",
+        "http" | "https" => {
+            matches!(host, "localhost" | "tauri.localhost")
+                || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+        }
+        _ => false,
+    }
+}
+
+/// Relay until either side closes
+async fn bridge(ws: WebSocketStream<TcpStream>, upstream: TcpStream) -> std::result::Result<(), WsError> {
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let (mut tcp_rx, mut tcp_tx) = upstream.into_split();
+
+    let to_vm = async {
+        while let Some(message) = ws_rx.next().await {
+            match message? {
+                Message::Binary(data) => tcp_tx.write_all(&data).await?,
+                Message::Text(text) => tcp_tx.write_all(text.as_bytes()).await?,
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        tcp_tx.shutdown().await?;
+        Ok::<_, WsError>(())
+    };
+
+    let to_viewer = async {
+        let mut buf = vec![0u8; RELAY_BUFFER_SIZE];
+        loop {
+            let read = tcp_rx.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            ws_tx.send(Message::Binary(buf[..read].to_vec())).await?;
+        }
+        ws_tx.close().await?;
+        Ok::<_, WsError>(())
+    };
+
+    tokio::select! {
+        result = to_vm => result,
+        result = to_viewer => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    async fn spawn_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut rx, mut tx) = stream.split();
+                    let _ = tokio::io::copy(&mut rx, &mut tx).await;
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_bytes_round_trip_through_proxy() {
+        let echo = spawn_echo_server().await;
+        let proxy = DisplayProxy::spawn(echo).await.unwrap();
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(proxy.ws_uri()).await.unwrap();
+        let payload = vec![0x52, 0x45, 0x44, 0x51, 0x00, 0xff];
+        ws.send(Message::Binary(payload.clone())).await.unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < payload.len() {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Binary(data) => received.extend(data),
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+        assert_eq!(received, payload);
+    }
+
+    #[tokio::test]
+    async fn test_foreign_origin_is_refused() {
+        let echo = spawn_echo_server().await;
+        let proxy = DisplayProxy::spawn(echo).await.unwrap();
+
+        let mut request = proxy.ws_uri().into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("origin", HeaderValue::from_static("https://evil.example"));
+        assert!(tokio_tungstenite::connect_async(request).await.is_err());
+
+        let mut request = proxy.ws_uri().into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("origin", HeaderValue::from_static("tauri://localhost"));
+        assert!(tokio_tungstenite::connect_async(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_ensure_proxy_reuses_per_vm() {
+        let echo = spawn_echo_server().await;
+        let mut proxies = HashMap::new();
+
+        let first = ensure_proxy(&mut proxies, "vm-1", echo).await.unwrap();
+        let second = ensure_proxy(&mut proxies, "vm-1", echo).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(proxies.len(), 1);
+
+        let other = ensure_proxy(&mut proxies, "vm-2", echo).await.unwrap();
+        assert_ne!(first, other);
+
+        // Closing the proxy frees its listener
+        proxies.remove("vm-1");
+        tokio::task::yield_now().await;
+        assert!(tokio_tungstenite::connect_async(first).await.is_err());
+    }
+
+    #[test]
+    fn test_is_local_origin() {
+        assert!(is_local_origin("tauri://localhost"));
+        assert!(is_local_origin("http://tauri.localhost"));
+        assert!(is_local_origin("http://localhost:1420"));
+        assert!(is_local_origin("http://127.0.0.1:5173"));
+        assert!(is_local_origin("http://[::1]:8080"));
+        assert!(!is_local_origin("https://example.com"));
+        assert!(!is_local_origin("http://localhost.example.com"));
+        assert!(!is_local_origin("file://localhost"));
+        assert!(!is_local_origin("null"));
+    }
+}
@@ -0,0 +1,354 @@
+use thiserror::Error;
+
+use crate::i18n;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("QEMU not found")]
+    QemuNotFound,
+
+    #[error("QEMU error: {0}")]
+    QemuError(String),
+
+    #[error("VM error: {0}")]
+    VMError(String),
+
+    #[error("Platform error: {0}")]
+    PlatformError(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("UTF-8 error: {0}")]
+    Utf8Error(#[from] std::string::FromUtf8Error),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    #[error("Invalid VM configuration: {0}")]
+    InvalidConfig(String),
+
+    /// A VM setting QEMU cannot be started with; `field` names the `VMConfig` field
+    #[error("Invalid {field}: {detail}")]
+    InvalidField { field: String, detail: String },
+
+    #[error("{0} not found")]
+    NotFound(String),
+
+    #[error("VM {0} not found")]
+    VmNotFound(String),
+
+    #[error("VM {0} is already running")]
+    VmAlreadyRunning(String),
+
+    #[error("VM {0} not running")]
+    VmNotRunning(String),
+
+    #[error("Not enough free space: {required_mb} MB required, {available_mb} MB available")]
+    InsufficientSpace { required_mb: u64, available_mb: u64 },
+
+    #[error("QMP command timed out: {0}")]
+    QmpTimeout(String),
+
+    #[error("Too many {command} requests; retry in {retry_after_ms} ms")]
+    RateLimited { command: String, retry_after_ms: u64 },
+}
+
+#[cfg(unix)]
+impl From<nix::errno::Errno> for Error {
+    fn from(errno: nix::errno::Errno) -> Self {
+        Error::PlatformError(errno.to_string())
+    }
+}
+
+impl From<rcgen::Error> for Error {
+    fn from(err: rcgen::Error) -> Self {
+        Error::PlatformError(format!("certificate generation failed: {}", err))
+    }
+}
+
+/// A clock that went backwards is reported like any other I/O failure
+impl From<std::time::SystemTimeError> for Error {
+    fn from(err: std::time::SystemTimeError) -> Self {
+        Error::IoError(std::io::Error::new(std::io::ErrorKind::Other, err))
+    }
+}
+
+impl serde::Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Machine-readable error category for the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    QemuNotFound,
+    QemuFailed,
+    VmNotFound,
+    VmAlreadyRunning,
+    VmNotRunning,
+    /// The VM's disk is still being created
+    VmProvisioning,
+    InsufficientSpace,
+    QmpTimeout,
+    ValidationFailed,
+    NotFound,
+    Conflict,
+    PlatformUnsupported,
+    Database,
+    Io,
+    Internal,
+    RateLimited,
+}
+
+impl ErrorCode {
+    #[cfg(test)]
+    pub const ALL: [ErrorCode; 16] = [
+        ErrorCode::QemuNotFound,
+        ErrorCode::QemuFailed,
+        ErrorCode::VmNotFound,
+        ErrorCode::VmAlreadyRunning,
+        ErrorCode::VmNotRunning,
+        ErrorCode::VmProvisioning,
+        ErrorCode::InsufficientSpace,
+        ErrorCode::QmpTimeout,
+        ErrorCode::ValidationFailed,
+        ErrorCode::NotFound,
+        ErrorCode::Conflict,
+        ErrorCode::PlatformUnsupported,
+        ErrorCode::Database,
+        ErrorCode::Io,
+        ErrorCode::Internal,
+        ErrorCode::RateLimited,
+    ];
+}
+
+/// Error returned to the frontend by Tauri commands and the control socket
+///
+/// `message_key` and `params` identify the text for localization (see `i18n`);
+/// `message` is the English rendering, kept for logs and older frontends.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub message_key: String,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+pub type CommandResult<T> = std::result::Result<T, CommandError>;
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message_key: &str) -> Self {
+        Self {
+            code,
+            message_key: message_key.to_string(),
+            params: serde_json::Map::new(),
+            message: i18n::render(message_key, &serde_json::Map::new()),
+            details: None,
+        }
+    }
+
+    /// A rejected input; `field` names the offending request field
+    pub fn validation(field: &str, message_key: &str) -> Self {
+        Self::new(ErrorCode::ValidationFailed, message_key).with_details(serde_json::json!({ "field": field }))
+    }
+
+    pub fn vm_not_found(vm_id: &str) -> Self {
+        Error::VmNotFound(vm_id.to_string()).into()
+    }
+
+    /// Set a template parameter and re-render the English message
+    pub fn with_param(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.params.insert(name.to_string(), value.into());
+        self.message = i18n::render(&self.message_key, &self.params);
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Message key for a rejected `VMConfig` field, so the UI can flag the field itself
+fn invalid_field_key(field: &str) -> &'static str {
+    match field {
+        "cpu_cores" => "vm.cpu.invalid",
+        "memory_mb" => "vm.memory.invalid",
+        _ => "error.invalidConfig",
+    }
+}
+
+impl From<Error> for CommandError {
+    fn from(error: Error) -> Self {
+        let (code, key, param) = match &error {
+            Error::QemuNotFound => (ErrorCode::QemuNotFound, "error.qemuNotFound", None),
+            Error::QemuError(detail) => (ErrorCode::QemuFailed, "error.qemuFailed", Some(("detail", detail.clone()))),
+            Error::VMError(detail) => (ErrorCode::Internal, "error.vmFailed", Some(("detail", detail.clone()))),
+            Error::PlatformError(detail) => {
+                (ErrorCode::PlatformUnsupported, "error.platform", Some(("detail", detail.clone())))
+            }
+            Error::DatabaseError(err) => (ErrorCode::Database, "error.database", Some(("detail", err.to_string()))),
+            Error::IoError(err) => (ErrorCode::Io, "error.io", Some(("detail", err.to_string()))),
+            Error::JsonError(err) => (ErrorCode::Internal, "error.json", Some(("detail", err.to_string()))),
+            Error::Utf8Error(err) => (ErrorCode::Internal, "error.utf8", Some(("detail", err.to_string()))),
+            Error::ConfigError(detail) => (ErrorCode::Internal, "error.config", Some(("detail", detail.clone()))),
+            Error::InvalidConfig(detail) => {
+                (ErrorCode::ValidationFailed, "error.invalidConfig", Some(("detail", detail.clone())))
+            }
+            Error::InvalidField { field, detail } => {
+                (ErrorCode::ValidationFailed, invalid_field_key(field), Some(("detail", detail.clone())))
+            }
+            Error::NotFound(what) => (ErrorCode::NotFound, "error.notFound", Some(("what", what.clone()))),
+            Error::VmNotFound(vm_id) => (ErrorCode::VmNotFound, "vm.notFound", Some(("vmId", vm_id.clone()))),
+            Error::VmAlreadyRunning(vm_id) => {
+                (ErrorCode::VmAlreadyRunning, "vm.start.alreadyRunning", Some(("vmId", vm_id.clone())))
+            }
+            Error::VmNotRunning(vm_id) => (ErrorCode::VmNotRunning, "vm.notRunning", Some(("vmId", vm_id.clone()))),
+            Error::InsufficientSpace { .. } => (ErrorCode::InsufficientSpace, "disk.insufficientSpace", None),
+            Error::QmpTimeout(command) => (ErrorCode::QmpTimeout, "qmp.timeout", Some(("command", command.clone()))),
+            Error::RateLimited { command, .. } => {
+                (ErrorCode::RateLimited, "command.rateLimited", Some(("command", command.clone())))
+            }
+        };
+
+        let mut command_error = CommandError::new(code, key);
+        if let Some((name, value)) = param {
+            command_error = command_error.with_param(name, value);
+        }
+        match error {
+            Error::VmNotFound(vm_id) | Error::VmAlreadyRunning(vm_id) | Error::VmNotRunning(vm_id) => {
+                command_error.with_details(serde_json::json!({ "vmId": vm_id }))
+            }
+            Error::InsufficientSpace { required_mb, available_mb } => command_error
+                .with_param("requiredMb", required_mb)
+                .with_param("availableMb", available_mb)
+                .with_details(serde_json::json!({ "requiredMb": required_mb, "availableMb": available_mb })),
+            Error::QmpTimeout(command) => command_error.with_details(serde_json::json!({ "command": command })),
+            Error::InvalidField { field, .. } => command_error.with_details(serde_json::json!({ "field": field })),
+            Error::RateLimited { retry_after_ms, .. } => command_error
+                .with_param("retryAfterMs", retry_after_ms)
+                .with_details(serde_json::json!({ "retryAfterMs": retry_after_ms })),
+            _ => command_error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_errno_converts_to_platform_error() {
+        let err: Error = nix::errno::Errno::EPERM.into();
+        match err {
+            Error::PlatformError(message) => assert!(message.starts_with("EPERM: "), "{}", message),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_system_time_error_converts_to_io_error() {
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        let err: Error = std::time::SystemTime::now().duration_since(later).unwrap_err().into();
+        assert!(matches!(err, Error::IoError(_)));
+    }
+
+    #[test]
+    fn test_command_error_serialization_shape() {
+        let error = CommandError::validation("notes", "vm.notes.tooLong").with_param("max", 10);
+        let value = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "code": "validationFailed",
+                "messageKey": "vm.notes.tooLong",
+                "params": { "max": 10 },
+                "message": "Notes must be at most 10 characters",
+                "details": { "field": "notes" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_command_error_omits_empty_details() {
+        let value = serde_json::to_value(CommandError::from(Error::QemuNotFound)).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "code": "qemuNotFound", "messageKey": "error.qemuNotFound", "message": "QEMU not found" })
+        );
+    }
+
+    #[test]
+    fn test_error_conversions() {
+        let not_found = CommandError::vm_not_found("vm-1");
+        assert_eq!(not_found.code, ErrorCode::VmNotFound);
+        assert_eq!(not_found.message, "VM vm-1 not found");
+        assert_eq!(not_found.message_key, "vm.notFound");
+        assert_eq!(not_found.details, Some(serde_json::json!({ "vmId": "vm-1" })));
+
+        let space = CommandError::from(Error::InsufficientSpace { required_mb: 2048, available_mb: 100 });
+        assert_eq!(space.code, ErrorCode::InsufficientSpace);
+        assert_eq!(space.details, Some(serde_json::json!({ "requiredMb": 2048, "availableMb": 100 })));
+        assert_eq!(space.message, "Not enough free space: 2048 MB required, 100 MB available");
+
+        assert_eq!(CommandError::from(Error::QmpTimeout("stop".to_string())).code, ErrorCode::QmpTimeout);
+        assert_eq!(CommandError::from(Error::VmNotRunning("vm-1".to_string())).code, ErrorCode::VmNotRunning);
+        assert_eq!(
+            CommandError::from(Error::InvalidConfig("bad".to_string())).code,
+            ErrorCode::ValidationFailed
+        );
+        let cpu = CommandError::from(Error::InvalidField {
+            field: "cpu_cores".to_string(),
+            detail: "CPU count must be > 0".to_string(),
+        });
+        assert_eq!(cpu.code, ErrorCode::ValidationFailed);
+        assert_eq!(cpu.message_key, "vm.cpu.invalid");
+        assert_eq!(cpu.message, "Invalid CPU config: CPU count must be > 0");
+        assert_eq!(cpu.details, Some(serde_json::json!({ "field": "cpu_cores" })));
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert_eq!(CommandError::from(Error::from(io)).code, ErrorCode::Io);
+    }
+
+    #[test]
+    fn test_keyed_messages_match_error_display() {
+        let errors = [
+            Error::QemuNotFound,
+            Error::QemuError("exit 1".to_string()),
+            Error::NotFound("Group g-1".to_string()),
+            Error::VmAlreadyRunning("vm-1".to_string()),
+            Error::InsufficientSpace { required_mb: 1, available_mb: 0 },
+            Error::QmpTimeout("stop".to_string()),
+            Error::RateLimited { command: "detect_qemu".to_string(), retry_after_ms: 250 },
+        ];
+        for error in errors {
+            let expected = error.to_string();
+            assert_eq!(CommandError::from(error).message, expected);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+/// Guest operating system family, used for icons and creation defaults.
+///
+/// Stored and sent to the frontend as a lowercase string. Parsing is lenient so
+/// older free-form values ("ubuntu", "Windows 11", "freebsd") still map onto a family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum GuestOs {
+    Windows,
+    #[default]
+    Linux,
+    MacOs,
+    Bsd,
+    Other,
+}
+
+pub const ALL_GUEST_OS: [GuestOs; 5] = [GuestOs::Windows, GuestOs::Linux, GuestOs::MacOs, GuestOs::Bsd, GuestOs::Other];
+
+const LINUX_HINTS: [&str; 12] = [
+    "linux", "ubuntu", "debian", "fedora", "centos", "rhel", "redhat", "arch", "suse", "mint", "alpine", "gentoo",
+];
+
+impl GuestOs {
+    pub fn parse(value: &str) -> Self {
+        let lower = value.trim().to_ascii_lowercase();
+        if lower.starts_with("win") {
+            GuestOs::Windows
+        } else if lower.starts_with("mac") || lower.starts_with("osx") || lower.contains("darwin") {
+            GuestOs::MacOs
+        } else if lower.contains("bsd") || lower.starts_with("dragonfly") {
+            GuestOs::Bsd
+        } else if LINUX_HINTS.iter().any(|hint| lower.contains(hint)) {
+            GuestOs::Linux
+        } else {
+            GuestOs::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GuestOs::Windows => "windows",
+            GuestOs::Linux => "linux",
+            GuestOs::MacOs => "macos",
+            GuestOs::Bsd => "bsd",
+            GuestOs::Other => "other",
+        }
+    }
+
+    /// Icon name the frontend shows for this guest family
+    pub fn icon(&self) -> &'static str {
+        match self {
+            GuestOs::Windows => "os-windows",
+            GuestOs::Linux => "os-linux",
+            GuestOs::MacOs => "os-macos",
+            GuestOs::Bsd => "os-bsd",
+            GuestOs::Other => "os-generic",
+        }
+    }
+
+    /// Suggested settings for a new VM of this family
+    pub fn defaults(&self) -> GuestOsDefaults {
+        let (memory_mb, disk_size_gb, uefi, tpm, rtc_local_time, rng, machine_quirks): (
+            u32,
+            u32,
+            bool,
+            bool,
+            bool,
+            bool,
+            &[&str],
+        ) = match self {
+            // Windows 11 refuses to install without UEFI and a TPM, and expects the RTC in local time
+            GuestOs::Windows => (4096, 64, true, true, true, false, &[]),
+            // Headless Linux guests can stall at boot waiting for entropy
+            GuestOs::Linux => (2048, 20, false, false, false, true, &[]),
+            // macOS guests need the Apple SMC and a Penryn-compatible CPU model to boot
+            GuestOs::MacOs => (4096, 64, true, false, false, false, &["isa-applesmc", "cpu-penryn", "usb-tablet"]),
+            GuestOs::Bsd => (1024, 16, false, false, false, false, &[]),
+            GuestOs::Other => (1024, 16, false, false, false, false, &[]),
+        };
+
+        GuestOsDefaults {
+            os: *self,
+            icon: self.icon().to_string(),
+            memory_mb,
+            disk_size_gb,
+            uefi,
+            tpm,
+            rtc_local_time,
+            rng,
+            machine_quirks: machine_quirks.iter().map(|quirk| quirk.to_string()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for GuestOs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for GuestOs {
+    fn from(value: &str) -> Self {
+        GuestOs::parse(value)
+    }
+}
+
+impl From<String> for GuestOs {
+    fn from(value: String) -> Self {
+        GuestOs::parse(&value)
+    }
+}
+
+impl From<GuestOs> for String {
+    fn from(os: GuestOs) -> Self {
+        os.as_str().to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GuestOsDefaults {
+    pub os: GuestOs,
+    pub icon: String,
+    pub memory_mb: u32,
+    pub disk_size_gb: u32,
+    /// Boot with OVMF firmware instead of SeaBIOS
+    pub uefi: bool,
+    /// Attach an emulated TPM 2.0
+    pub tpm: bool,
+    pub rtc_local_time: bool,
+    /// Attach a VirtIO RNG so the guest is never short of entropy
+    pub rng: bool,
+    /// Extra QEMU devices or CPU settings the guest needs
+    pub machine_quirks: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_values() {
+        let cases = [
+            ("linux", GuestOs::Linux),
+            ("Ubuntu 22.04", GuestOs::Linux),
+            ("windows", GuestOs::Windows),
+            ("Windows 11", GuestOs::Windows),
+            ("macos", GuestOs::MacOs),
+            ("Mac OS X", GuestOs::MacOs),
+            ("FreeBSD", GuestOs::Bsd),
+            ("other", GuestOs::Other),
+            ("beos", GuestOs::Other),
+            ("", GuestOs::Other),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(GuestOs::parse(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_string_roundtrip() {
+        for os in ALL_GUEST_OS {
+            assert_eq!(GuestOs::parse(os.as_str()), os);
+        }
+    }
+
+    #[test]
+    fn test_serde_uses_lowercase_strings() {
+        assert_eq!(serde_json::to_string(&GuestOs::MacOs).unwrap(), "\"macos\"");
+        let parsed: GuestOs = serde_json::from_str("\"Debian\"").unwrap();
+        assert_eq!(parsed, GuestOs::Linux);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let windows = GuestOs::Windows.defaults();
+        assert!(windows.uefi && windows.tpm && windows.rtc_local_time);
+        assert_eq!(windows.icon, "os-windows");
+
+        let macos = GuestOs::MacOs.defaults();
+        assert!(macos.machine_quirks.contains(&"isa-applesmc".to_string()));
+
+        let linux = GuestOs::Linux.defaults();
+        assert!(!linux.uefi && !linux.tpm && linux.machine_quirks.is_empty());
+    }
+}
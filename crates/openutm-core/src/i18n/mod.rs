@@ -0,0 +1,349 @@
+//! User-facing message catalog
+//!
+//! Command errors carry a stable message key plus params instead of a finished
+//! sentence. The English templates below render `CommandError::message` and
+//! are served to the frontend, which swaps in its own translations where it
+//! has them. Placeholders are written `{name}` and filled from the params.
+
+use std::collections::BTreeMap;
+
+/// Setting holding the UI locale, e.g. `en` or `pt-BR`
+pub const LOCALE_SETTING: &str = "locale";
+pub const DEFAULT_LOCALE: &str = "en";
+
+const EN: &[(&str, &str)] = &[
+    // One generic entry per ErrorCode, for errors without a more specific key
+    ("errorCode.qemuNotFound", "QEMU not found"),
+    ("errorCode.qemuFailed", "QEMU failed"),
+    ("errorCode.vmNotFound", "VM not found"),
+    ("errorCode.vmAlreadyRunning", "VM is already running"),
+    ("errorCode.vmNotRunning", "VM is not running"),
+    ("errorCode.vmProvisioning", "The VM's disk is still being created"),
+    ("errorCode.insufficientSpace", "Not enough free disk space"),
+    ("errorCode.qmpTimeout", "QEMU did not respond in time"),
+    ("errorCode.validationFailed", "Invalid input"),
+    ("errorCode.notFound", "Not found"),
+    ("errorCode.conflict", "The request conflicts with the current state"),
+    ("errorCode.platformUnsupported", "Not supported on this host"),
+    ("errorCode.database", "Database error"),
+    ("errorCode.io", "File system error"),
+    ("errorCode.internal", "Internal error"),
+    ("errorCode.rateLimited", "Too many requests; try again shortly"),
+    // Backend errors
+    ("error.qemuNotFound", "QEMU not found"),
+    ("error.qemuFailed", "QEMU error: {detail}"),
+    ("error.vmFailed", "VM error: {detail}"),
+    ("error.platform", "Platform error: {detail}"),
+    ("error.database", "Database error: {detail}"),
+    ("error.io", "IO error: {detail}"),
+    ("error.json", "JSON error: {detail}"),
+    ("error.utf8", "UTF-8 error: {detail}"),
+    ("error.config", "Configuration error: {detail}"),
+    ("error.invalidConfig", "Invalid VM configuration: {detail}"),
+    ("error.notFound", "{what} not found"),
+    ("error.internal", "Internal error: {detail}"),
+    ("qmp.timeout", "QMP command timed out: {command}"),
+    ("command.rateLimited", "Too many {command} requests; retry in {retryAfterMs} ms"),
+    ("command.rateLimit.unknown", "{command} has no rate limit to tune"),
+    ("command.rateLimit.invalid", "Rate limit must allow at least one call per positive period"),
+    // VMs
+    ("vm.notFound", "VM {vmId} not found"),
+    ("vm.notRunning", "VM {vmId} not running"),
+    ("vm.start.alreadyRunning", "VM {vmId} is already running"),
+    ("vm.start.passphraseRequired", "Passphrase required to start an encrypted VM"),
+    ("vm.provisioning", "{name} is still being provisioned; try again once its disk is ready"),
+    ("vm.start.diskMoving", "{name} cannot start while its disk is being moved"),
+    ("vm.id.empty", "VM ID cannot be empty"),
+    ("vm.name.empty", "VM name cannot be empty"),
+    ("vm.name.taken", "A VM named {name} already exists"),
+    ("vm.arch.invalid", "Architecture must be one of {arches}"),
+    ("vm.cpu.invalid", "Invalid CPU config: {detail}"),
+    ("vm.memory.invalid", "Invalid memory config: {detail}"),
+    ("vm.cpuAffinity.outOfRange", "Core {core} is out of range; host has {logicalCpus} logical CPUs"),
+    ("vm.nestedVirt.unsupported", "Host does not support nested virtualization"),
+    ("vm.bootOrder.invalid", "Boot order must be disk-first or cdrom-first"),
+    ("vm.bootOnce.invalid", "Cannot boot once from {device}; use disk or cdrom"),
+    ("vm.bootOnce.noMedia", "Attach install media before booting from the CD"),
+    ("vm.installMedia.pathEmpty", "Install media path cannot be empty"),
+    ("vm.notes.tooLong", "Notes must be at most {max} characters"),
+    ("vm.ephemeral.unsupported", "Ephemeral mode does not work with raw devices or encrypted disks"),
+    ("vm.ephemeral.diskLocked", "Turn off ephemeral mode before snapshotting, moving or adding disks to this VM"),
+    ("vm.memoryBackend.invalid", "Memory backend must be one of {backends}"),
+    ("vm.memoryBackend.unsupported", "This host does not support custom memory backends"),
+    ("vm.memoryBackend.hugepages", "Needs {needed} huge pages of {pageSizeMb} MB but only {available} are available"),
+    ("vm.list.limitOutOfRange", "Limit must be between 1 and {max}"),
+    ("vm.list.sortInvalid", "Sort must be name, created_at or status"),
+    ("vm.list.cursorInvalid", "The list cursor is invalid or was taken under a different sort"),
+    ("vm.list.fieldUnknown", "Unknown VM field {field}"),
+    // VM config rules, keyed `validation.<field>.<code>` with `validation.<code>` as the fallback
+    ("validation.unknownValue", "{field} must be one of {allowed}"),
+    ("validation.name.required", "VM name cannot be empty"),
+    ("validation.name.tooLong", "VM name must be at most {max} characters"),
+    ("validation.name.invalidCharacters", "VM name cannot contain control characters or / \\ : * ? \" < > |"),
+    ("validation.memoryMb.tooSmall", "Memory must be at least {min} MB"),
+    ("validation.memoryMb.tooLarge", "Memory must be at most {max} MB"),
+    ("validation.memoryMb.exceedsHost", "Memory exceeds the host's {hostMb} MB"),
+    ("validation.cpuCores.tooSmall", "CPU cores must be at least 1"),
+    ("validation.cpuCores.tooLarge", "CPU cores must be at most {max}"),
+    ("validation.cpuAffinity.outOfRange", "Core {core} is out of range; host has {logicalCpus} logical CPUs"),
+    ("validation.rawDevicePath.invalidDevice", "Raw device path must start with {prefix}"),
+    ("validation.encrypted.unsupported", "Raw device passthrough cannot be combined with disk encryption"),
+    ("validation.diskSizeGb.tooSmall", "Disk size must be at least 1 GB"),
+    ("validation.diskSizeGb.tooLarge", "Disk size must be at most {max} GB"),
+    ("validation.diskSizeGb.insufficientSpace", "Disk size exceeds the {freeGb} GB free in the storage directory"),
+    ("validation.installMediaPath.notFound", "Install media {path} does not exist"),
+    ("validation.priority.outOfRange", "Priority must be between -20 and 19"),
+    ("validation.vlanId.outOfRange", "VLAN ID must be between 1 and {max}"),
+    ("validation.vlanId.requiresBridge", "VLAN requires TAP or bridge network"),
+    ("validation.displayResolution.invalidResolution", "Resolution must be WIDTHxHEIGHT between {min} and {max}"),
+    ("validation.bootMenu.invalidSplashTime", "Boot menu splash time must be at most {max} ms"),
+    ("validation.machineType.invalidMachineType", "Machine type may only contain letters, digits, '.', '-' and '_'"),
+    ("validation.graphics.unsupportedArch", "aarch64 guests only support the virtio-gpu adapter"),
+    ("validation.performance.iothreads.exceedsCpuCores", "At most {max} I/O threads, one per vCPU"),
+    ("validation.performance.aio.unsupportedHost", "Native and io_uring AIO are only available on Linux hosts"),
+    ("validation.performance.aio.kernelTooOld", "io_uring needs Linux {kernel} or newer"),
+    ("validation.notes.tooLong", "Notes must be at most {max} characters"),
+    ("validation.networks.tooMany", "A VM can have at most {max} network adapters"),
+    ("validation.networks.invalidId", "Adapter IDs start with a letter and use up to {max} letters, digits, '-' or '_'"),
+    ("validation.networks.duplicateId", "Adapter ID {id} is used twice"),
+    ("validation.networks.invalidMac", "Adapter {id} has an invalid MAC address"),
+    ("validation.networks.requiresNat", "Port forwarding is only available on NAT adapters"),
+    ("validation.networks.outOfRange", "Forwarded ports cannot be 0"),
+    ("validation.networks.duplicateHostPort", "Host port {port}/{protocol} is forwarded twice"),
+    // QEMU binary
+    ("qemu.path.empty", "Choose a QEMU binary"),
+    ("qemu.path.invalid", "{path} is not a working QEMU binary: {detail}"),
+    // Profiles
+    ("profile.id.empty", "Profile ID cannot be empty"),
+    ("profile.name.empty", "Profile name cannot be empty"),
+    ("profile.builtin", "{name} is a built-in profile and cannot be changed"),
+    ("profile.config.invalid", "Invalid profile settings: {detail}"),
+    // Displays
+    ("display.headless", "VM {vmId} is headless and has no display; use the monitor instead"),
+    ("display.remote.invalidAddress", "{address} is not an IP address"),
+    ("display.remote.ticketingRequired", "Listening beyond this machine requires a SPICE display with password ticketing on"),
+    ("display.remote.tlsRequired", "Listening beyond this machine requires a SPICE TLS port"),
+    ("display.tls.spiceOnly", "TLS is only available for SPICE displays"),
+    ("display.tls.invalidPort", "Port {port} cannot be used for TLS"),
+    ("display.tls.portInUse", "Port {port} is already used by another VM's display"),
+    ("display.portRange.invalid", "Display ports must be a range starting at {min} or above"),
+    ("display.compression.invalid", "{value} is not a valid SPICE {option} setting"),
+    ("display.session.notFound", "This VM has no display session"),
+    ("display.reconnect.tooSoon", "Reconnecting too quickly; try again in {retryAfterMs} ms"),
+    ("display.reconnect.failed", "Display unreachable after {attempts} reconnect attempts; reset it to try again"),
+    ("display.reconnect.invalidMax", "Reconnect attempts must be at least 1"),
+    ("recording.invalidFps", "Recording frame rate must be between 1 and {max} fps"),
+    ("recording.alreadyActive", "VM {vmId} is already being recorded"),
+    ("recording.notActive", "VM {vmId} is not being recorded"),
+    // Metrics
+    ("metrics.invalidAddress", "{address} is not an address and port, e.g. 0.0.0.0:9464"),
+    // Health checks
+    ("healthCheck.invalidInterval", "Health check interval must be at least 1 second"),
+    ("healthCheck.invalidThreshold", "Missed health checks before a VM is marked not responding must be at least 1"),
+    // Memory policy
+    ("memoryPolicy.invalidThreshold", "Free memory threshold must be at least 1 MB"),
+    ("memoryPolicy.invalidMinimum", "Minimum VM memory must be at least 1 MB"),
+    // Disks and drives
+    ("disk.insufficientSpace", "Not enough free space: {requiredMb} MB required, {availableMb} MB available"),
+    ("disk.passphraseRequired", "A passphrase is required for an encrypted disk"),
+    ("disk.resize.rawDevice", "Raw device disks cannot be resized"),
+    ("disk.resize.encrypted", "Encrypted disks cannot be resized"),
+    ("disk.resize.shrink", "Disks can only grow; current size is {currentGb} GB"),
+    ("disk.resize.vmRunning", "Stop the VM before resizing its disk"),
+    ("disk.interface.invalid", "Disk interface must be one of: {interfaces}"),
+    ("disk.relocate.dirEmpty", "Choose a folder to move the disk to"),
+    ("disk.relocate.rawDevice", "Raw device disks cannot be moved"),
+    ("disk.relocate.vmRunning", "Stop the VM before moving its disk"),
+    ("disk.relocate.samePath", "The disk is already in that folder"),
+    ("disk.relocate.targetExists", "Cannot move the disk: {path} already exists"),
+    ("disk.relocate.externalSnapshots", "Commit the VM's external snapshots before moving its disk"),
+    ("disk.relocate.inProgress", "This VM's disk is already being moved"),
+    ("storage.migrate.dirEmpty", "Choose a folder to move the storage to"),
+    ("storage.migrate.vmsRunning", "Stop all VMs before moving the storage folder"),
+    ("storage.migrate.samePath", "The storage is already in that folder"),
+    ("storage.migrate.inProgress", "A disk is already being moved; try again when it is done"),
+    ("storage.migrate.externalSnapshots", "Commit the external snapshots of {name} before moving the storage"),
+    ("disk.checksum.missing", "No checksum has been saved for this disk yet"),
+    ("disk.checksum.rawDevice", "Raw device disks cannot be checksummed"),
+    ("disk.checksum.vmRunning", "Stop the VM before checksumming its disk"),
+    ("snapshot.name.invalid", "Snapshot names use up to 64 letters, digits, '-', '_' or '.', not only digits"),
+    ("snapshot.name.exists", "A snapshot named {name} already exists"),
+    ("snapshot.notFound", "Snapshot {name} not found"),
+    ("snapshot.rawDevice", "Raw device disks cannot be snapshotted"),
+    ("snapshot.encrypted", "Start the VM to snapshot or restore an encrypted disk"),
+    ("snapshot.external.encrypted", "External snapshots are not available for encrypted disks"),
+    ("snapshot.external.none", "This VM has no external snapshots to commit"),
+    ("snapshot.restore.needsRam", "Snapshot {name} has no saved RAM; stop the VM to roll its disk back"),
+    ("network.adapter.vmRunning", "Stop the VM before changing its network adapters"),
+    ("network.adapter.notFound", "Network adapter {id} not found"),
+    ("drive.id.empty", "Drive ID cannot be empty"),
+    ("drive.path.empty", "Disk image path cannot be empty"),
+    ("drive.path.notFound", "Disk image {path} does not exist"),
+    ("drive.format.invalid", "Disk format must be one of: {formats}"),
+    ("drive.notFound", "Hot-added disk {id} not found"),
+    // OVA import
+    ("ova.path.empty", "OVA path cannot be empty"),
+    ("ova.noDisks", "OVA contains no disks"),
+    ("viewer.notFound", "remote-viewer was not found. {hint}"),
+    ("import.diskConflict", "Cannot import: the disk path {path} is already in use"),
+    // Groups and tags
+    ("group.name.empty", "Group name cannot be empty"),
+    ("group.id.empty", "Group ID cannot be empty"),
+    ("group.notFound", "Group not found"),
+    ("vm.dependencies.self", "A VM cannot depend on itself"),
+    ("vm.dependencies.cycle", "These VMs depend on each other in a loop: {vms}"),
+    ("tag.empty", "Tag cannot be empty"),
+    ("tag.tooLong", "Tag must be at most {max} characters"),
+    ("tag.invalidCharacters", "Tag may only contain letters, digits, spaces, '-' and '_'"),
+    // Settings and data migration
+    ("settings.logLevel.invalid", "Log level must be one of {levels}"),
+    ("settings.locale.invalid", "Locale must be a language tag such as en or pt-BR"),
+    ("migration.noLegacyData", "No legacy data to migrate"),
+    ("migration.vmsRunning", "Stop all VMs before migrating data"),
+];
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageCatalog {
+    pub locale: String,
+    /// Locale the templates are actually written in; differs when there is no translation
+    pub catalog_locale: String,
+    pub messages: BTreeMap<String, String>,
+}
+
+/// Templates for `locale`; only English ships with the backend, so every locale falls back to it
+pub fn catalog(locale: &str) -> MessageCatalog {
+    MessageCatalog {
+        locale: locale.to_string(),
+        catalog_locale: DEFAULT_LOCALE.to_string(),
+        messages: EN.iter().map(|(key, text)| (key.to_string(), text.to_string())).collect(),
+    }
+}
+
+pub fn template(key: &str) -> Option<&'static str> {
+    EN.iter().find(|(candidate, _)| *candidate == key).map(|(_, text)| *text)
+}
+
+/// Key for a `ValidationIssue`: `validation.<field>.<code>` when the catalog has one, else `validation.<code>`
+pub fn validation_key(field: &str, code: &str) -> String {
+    let specific = format!("validation.{}.{}", camel_case(field), camel_case(code));
+    if template(&specific).is_some() {
+        specific
+    } else {
+        format!("validation.{}", camel_case(code))
+    }
+}
+
+/// `memory_mb` -> `memoryMb`, `too-small` -> `tooSmall`; dots are kept
+fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        match c {
+            '_' | '-' => upper = true,
+            _ if upper => {
+                camel.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            _ => camel.push(c),
+        }
+    }
+    camel
+}
+
+/// Fill `{name}` placeholders in the English template for `key`.
+///
+/// Unknown keys render as the key itself so a missing entry is visible rather than blank.
+pub fn render(key: &str, params: &serde_json::Map<String, serde_json::Value>) -> String {
+    let Some(template) = template(key) else {
+        return key.to_string();
+    };
+    let mut message = template.to_string();
+    for (name, value) in params {
+        let text = match value {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        message = message.replace(&format!("{{{}}}", name), &text);
+    }
+    message
+}
+
+/// Loose BCP 47 check: a 2-3 letter language plus optional alphanumeric subtags
+pub fn is_valid_locale(locale: &str) -> bool {
+    let mut parts = locale.split(['-', '_']);
+    let language_ok = parts
+        .next()
+        .is_some_and(|language| (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic()));
+    language_ok && parts.all(|part| (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    /// Generic key for errors without a more specific message
+    fn error_code_key(code: ErrorCode) -> String {
+        let code = serde_json::to_value(code).ok();
+        format!("errorCode.{}", code.as_ref().and_then(|value| value.as_str()).unwrap_or_default())
+    }
+
+    #[test]
+    fn test_every_error_code_has_catalog_entry() {
+        for code in ErrorCode::ALL {
+            let key = error_code_key(code);
+            assert!(template(&key).is_some(), "missing catalog entry {}", key);
+        }
+    }
+
+    #[test]
+    fn test_catalog_keys_are_unique() {
+        assert_eq!(catalog(DEFAULT_LOCALE).messages.len(), EN.len());
+    }
+
+    #[test]
+    fn test_render_fills_params() {
+        let params = serde_json::json!({ "requiredMb": 2048, "availableMb": 100 });
+        assert_eq!(
+            render("disk.insufficientSpace", params.as_object().unwrap()),
+            "Not enough free space: 2048 MB required, 100 MB available"
+        );
+
+        let params = serde_json::json!({ "name": "Ubuntu" });
+        assert_eq!(render("vm.name.taken", params.as_object().unwrap()), "A VM named Ubuntu already exists");
+    }
+
+    #[test]
+    fn test_validation_key_falls_back_to_code() {
+        assert_eq!(validation_key("memory_mb", "too-small"), "validation.memoryMb.tooSmall");
+        assert_eq!(
+            validation_key("performance.net_multiqueue", "requires-tap"),
+            "validation.requiresTap"
+        );
+        assert_eq!(validation_key("arch", "unknown-value"), "validation.unknownValue");
+    }
+
+    #[test]
+    fn test_render_unknown_key_returns_key() {
+        assert_eq!(render("vm.missing", &serde_json::Map::new()), "vm.missing");
+    }
+
+    #[test]
+    fn test_unknown_locale_falls_back_to_english() {
+        let catalog = catalog("de-DE");
+        assert_eq!(catalog.locale, "de-DE");
+        assert_eq!(catalog.catalog_locale, "en");
+        assert_eq!(catalog.messages["vm.id.empty"], "VM ID cannot be empty");
+    }
+
+    #[test]
+    fn test_is_valid_locale() {
+        for locale in ["en", "pt-BR", "zh_Hant_TW", "fil"] {
+            assert!(is_valid_locale(locale), "{}", locale);
+        }
+        for locale in ["", "e", "english", "en-", "../en", "en US"] {
+            assert!(!is_valid_locale(locale), "{}", locale);
+        }
+    }
+}
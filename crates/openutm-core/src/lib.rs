@@ -0,0 +1,550 @@
+//! Core VM management for OpenUTM, independent of any UI toolkit
+//!
+//! QEMU process control and QMP, disk images, the SQLite config store,
+//! host platform probing and the data types shared with the frontends live
+//! here. The Tauri app is a thin layer of commands over this crate.
+
+pub mod app;
+pub mod benchmark;
+pub mod config;
+pub mod control;
+pub mod display_proxy;
+pub mod error;
+pub mod guest;
+pub mod i18n;
+pub mod logging;
+pub mod memory_policy;
+pub mod metrics;
+pub mod ova_import;
+pub mod paths;
+pub mod platform;
+pub mod profiles;
+pub mod provisioning;
+pub mod qemu;
+pub mod rate_limit;
+pub mod reconcile;
+pub mod recording;
+pub mod service;
+pub mod single_instance;
+pub mod spice_tls;
+pub mod storage;
+pub mod validation;
+pub mod viewer;
+
+pub use error::{Error, Result};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplaySession {
+    /// Unique per session, so several viewers of one VM can be told apart
+    pub session_id: String,
+    pub vm_id: String,
+    pub protocol: String,
+    pub host: String,
+    pub port: u16,
+    pub uri: String,
+    pub status: String,
+    pub reconnect_attempts: u32,
+    /// RFC 3339 time before which `open_display` refuses another reconnect
+    pub next_retry_at: Option<String>,
+    pub last_error: Option<String>,
+    /// RFC 3339 time the session last became connected
+    pub connected_at: Option<String>,
+    /// RFC 3339 time the session became disconnected; cleared on reconnect
+    pub disconnected_at: Option<String>,
+    /// Filled in by `get_display` and `list_displays`
+    pub seconds_since_connected: Option<u64>,
+    pub clipboard_sharing: bool,
+    /// SPICE password for this session, also embedded in `uri`; `None` when ticketing is off
+    pub password_token: Option<String>,
+    /// WebSocket bridge to `port` for in-app viewers (spice-html5, noVNC)
+    pub ws_uri: Option<String>,
+    /// SPICE TLS port for viewers on other machines; `None` unless TLS is set up
+    pub tls_port: Option<u16>,
+    /// CA certificate a remote viewer must trust to connect on `tls_port`
+    pub ca_cert_path: Option<String>,
+    /// A `start_recording` capture of this VM's display is in progress
+    pub recording: bool,
+}
+
+/// Output of a finished display recording
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingSummary {
+    pub vm_id: String,
+    /// An mp4, or a zip of the frames when ffmpeg is missing or failed
+    pub path: String,
+    pub duration_ms: u64,
+    pub frames: u32,
+    /// Set when the frames were zipped instead of encoded
+    pub warning: Option<String>,
+}
+
+/// Per-VM settings for reaching the display from other machines
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDisplay {
+    /// IP address the display listens on; `None` means loopback only
+    pub listen_address: Option<String>,
+    /// Serve SPICE over TLS on this port as well
+    pub tls_port: Option<u16>,
+}
+
+/// One setting whose saved value differs from what the running VM uses
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigChange {
+    /// Setting name as the UI knows it, e.g. `memoryMb` or `display`
+    pub field: String,
+    /// QEMU arguments for the setting in the running process
+    pub applied: Option<String>,
+    /// QEMU arguments the next start would use
+    pub pending: Option<String>,
+    /// The change only reaches the guest after a restart
+    pub requires_restart: bool,
+}
+
+/// Saved config compared with the config a VM is running with
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiff {
+    pub vm_id: String,
+    pub running: bool,
+    pub changes: Vec<ConfigChange>,
+    /// Any change needs a restart; drives the "restart to apply" badge
+    pub restart_required: bool,
+}
+
+/// Payload of `VM_NOT_RESPONDING_EVENT`
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VmNotResponding {
+    pub vm_id: String,
+    /// Consecutive health checks the VM failed to answer
+    pub missed_checks: u32,
+}
+
+/// Payload of `VM_STATUS_CHANGED_EVENT`; statuses use their stored names
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VmStatusEvent {
+    pub vm_id: String,
+    pub old_status: String,
+    pub new_status: String,
+}
+
+/// What happened to the VM named in a `VmListChanged`
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VmChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// Payload of `VM_LIST_CHANGED_EVENT`; with `VM_STATUS_CHANGED_EVENT` it covers
+/// every change to the VM list, so the frontend can refetch rows instead of polling
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VmListChanged {
+    pub vm_id: String,
+    pub change: VmChangeKind,
+}
+
+/// How far a new VM's background disk creation has got
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProvisioningState {
+    /// Waiting for a free disk job slot
+    Queued,
+    Creating,
+    Done,
+    /// The disk could not be made and the VM was removed
+    Failed,
+}
+
+/// Payload of `VM_PROVISIONING_EVENT` and entry of `get_provisioning_jobs`
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisioningJob {
+    pub vm_id: String,
+    pub vm_name: String,
+    pub state: ProvisioningState,
+    /// Size of the image file so far; only full preallocation gets near `total_bytes`
+    pub written_bytes: u64,
+    pub total_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Payload of `VM_CRASHED_EVENT`
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VmCrashed {
+    pub vm_id: String,
+    pub pid: u32,
+    /// `None` when QEMU was killed by a signal
+    pub exit_code: Option<i32>,
+}
+
+/// Which accelerator a running VM is actually executing under
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveAccelerator {
+    pub vm_id: String,
+    /// Accelerator named on the command line; `None` leaves QEMU on its default, TCG
+    pub requested: Option<String>,
+    /// Accelerator the guest runs on, e.g. `tcg` when KVM was asked for but is not enabled
+    pub active: String,
+    /// Hardware virtualization is engaged rather than software emulation
+    pub hardware_accelerated: bool,
+    /// Run state from `query-status`, e.g. `running` or `paused`
+    pub run_state: String,
+}
+
+/// How a running VM's QEMU process was started
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchInfo {
+    pub vm_id: String,
+    /// QEMU binary, which differs from the detected one for cross-arch guests
+    pub binary: String,
+    /// Accelerator the VM runs under, e.g. `kvm` or `tcg`
+    pub accelerator: Option<String>,
+    /// Full argv after the binary, including socket and secret file paths
+    pub args: Vec<String>,
+}
+
+/// Timestamps (ms since the Unix epoch) of a VM's most recent launch
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BootTimings {
+    /// QEMU was spawned
+    pub process_start_ms: u64,
+    /// QEMU first accepted a QMP connection
+    pub qmp_ready_ms: u64,
+    /// The guest emitted RESET or POWERUP; `None` until (or unless) it does
+    pub boot_event_ms: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QemuInfo {
+    pub detected: bool,
+    pub path: Option<String>,
+    pub version: Option<String>,
+    pub accelerator: Option<String>,
+    pub host_arch: String,
+    /// Machine types the binary accepts for `-machine`; empty if it could not be queried
+    pub supported_machines: Vec<String>,
+    /// Accelerators compiled into the binary; empty if it could not be queried
+    pub supported_accels: Vec<String>,
+}
+
+/// An installed QEMU system binary and the guest architecture it runs
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchInfo {
+    pub arch: String,
+    pub binary_path: String,
+    pub version: String,
+    /// Hardware accelerator for this arch; `None` when guests are emulated
+    pub accelerator: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccelerationDiagnostics {
+    pub accelerator_available: bool,
+    pub details: String,
+    pub nested_virt_supported: bool,
+    pub nested_virt_flag: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceleratorSupport {
+    pub name: String,
+    /// Compiled into the QEMU binary (listed by `-accel help`)
+    pub built_in: bool,
+    /// Built in and backed by the host, so a VM of the queried architecture can use it
+    pub usable: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuModel {
+    pub name: String,
+    /// QEMU's description, e.g. `Intel Core Processor (Broadwell)` or `(alias configured by machine type)`
+    pub note: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuModelList {
+    pub models: Vec<CpuModel>,
+    /// CPU feature flags the binary recognizes; empty for architectures that do not list them
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VfioDeviceInfo {
+    /// PCI address as `domain:bus:slot.function`
+    pub address: String,
+    /// `lspci` description, or the address when `lspci` is unavailable
+    pub name: String,
+    pub device: qemu::VfioPciDevice,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformInfo {
+    pub os: String,
+    pub arch: String,
+    /// Hypervisor accelerator usable on this host (`kvm`, `hvf`, `whpx`), if any
+    pub accelerator: Option<String>,
+    pub cpu_count: u32,
+    pub total_ram_mb: u64,
+    /// Free space on the filesystem holding VM disks
+    pub free_disk_mb: u64,
+    /// QEMU binary that will be used, if one was found
+    pub qemu_path: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HostResources {
+    pub logical_cpus: u32,
+    pub total_memory_mb: u64,
+    /// Memory the host can hand out without swapping
+    pub available_memory_mb: u64,
+}
+
+/// Totals across every VM for the home screen
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VmOverview {
+    pub total: u32,
+    pub running: u32,
+    pub stopped: u32,
+    pub paused: u32,
+    pub error: u32,
+    /// Starting, stopping or having its disk created right now
+    pub transitioning: u32,
+    /// Memory configured across all VMs
+    pub allocated_memory_mb: u64,
+    /// Memory configured for VMs that are running or paused
+    pub running_memory_mb: u64,
+    /// Sum of virtual disk sizes
+    pub disk_allocated_bytes: u64,
+    /// Host space the disk images actually take
+    pub disk_used_bytes: u64,
+    /// VMs whose disk could not be read, left out of the disk totals
+    #[serde(default)]
+    pub disk_errors: Vec<OverviewDiskError>,
+}
+
+/// Why a VM's disk is missing from the overview's totals
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OverviewDiskError {
+    pub vm_id: String,
+    pub message: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DataMigrationStatus {
+    pub needs_migration: bool,
+    pub legacy_dir: Option<String>,
+    pub config_dir: String,
+    pub data_dir: String,
+    pub log_dir: String,
+    pub restart_required: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupStatus {
+    /// Set when a corrupt config DB was replaced at launch
+    pub database_recovery: Option<config::DatabaseRecovery>,
+    /// Problems reported by the startup integrity check
+    pub integrity_issues: Vec<String>,
+    /// What startup reconciliation fixed or found
+    pub corrections: Vec<reconcile::Correction>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VmMetrics {
+    pub vm_id: String,
+    pub running: bool,
+    pub pid: Option<u32>,
+    /// QEMU's CPU use since the previous sample, percent of one host CPU
+    pub cpu_percent: Option<f32>,
+    pub memory_rss_bytes: Option<u64>,
+    pub cpu_affinity: Option<Vec<u32>>,
+    pub cpu_affinity_mask: Option<String>,
+    pub priority: i32,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct VMConfig {
+    pub name: String,
+    pub memory_mb: u32,
+    pub cpu_cores: u32,
+    pub disk_size_gb: u32,
+    pub os: guest::GuestOs,
+    #[serde(default)]
+    pub install_media_path: Option<String>,
+    #[serde(default = "default_boot_order")]
+    pub boot_order: String,
+    #[serde(default = "default_network_type")]
+    pub network_type: String,
+    #[serde(default)]
+    pub nested_virt: bool,
+    #[serde(default = "default_restart_policy")]
+    pub restart_policy: String,
+    #[serde(default = "default_arch")]
+    pub arch: String,
+    #[serde(default)]
+    pub cpu_affinity: Vec<u32>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_preallocation")]
+    pub preallocation: String,
+    /// Disk is LUKS-encrypted; the passphrase is supplied at create/start and never stored
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Host block device used as the boot disk instead of a qcow2 image
+    #[serde(default)]
+    pub raw_device_path: Option<String>,
+    /// Free-form user notes
+    #[serde(default)]
+    pub notes: String,
+    /// Sync the clipboard with the guest through the SPICE vdagent
+    #[serde(default)]
+    pub clipboard_sharing: bool,
+    /// 802.1Q VLAN tag; requires bridge networking
+    #[serde(default)]
+    pub vlan_id: Option<u16>,
+    /// Starting guest display size as `WIDTHxHEIGHT`. With SPICE, the guest
+    /// agent resizes the display to the viewer window once it connects.
+    #[serde(default)]
+    pub display_resolution: Option<String>,
+    /// Display adapter: virtio-vga, qxl, std or virtio-gpu; `None` picks qxl for SPICE
+    #[serde(default)]
+    pub graphics: Option<String>,
+    /// Exact `-machine` type such as `pc-q35-8.2`, pinned for migration compatibility;
+    /// `None` uses the unversioned default for the architecture
+    #[serde(default)]
+    pub machine_type: Option<String>,
+    /// Remote display protocol: spice, vnc, or none for a headless VM
+    #[serde(default = "default_display_mode")]
+    pub display_mode: String,
+    /// Firmware boot menu; `None` keeps the menu available with QEMU's default timeout
+    #[serde(default)]
+    pub boot_menu: Option<qemu::BootMenuConfig>,
+    /// Guest network adapters the VM is created with, up to eight; afterwards
+    /// they are managed one at a time. Empty keeps the single NIC that
+    /// `network_type` describes.
+    #[serde(default)]
+    pub networks: Vec<qemu::NetworkConfig>,
+    /// I/O threads, NIC multiqueue and AIO backend; `None` keeps QEMU's defaults
+    #[serde(default)]
+    pub performance: Option<qemu::VmPerformance>,
+    /// Tablet for mouse input; `None` uses a VirtIO tablet for Linux and a USB one otherwise
+    #[serde(default)]
+    pub pointer_device: Option<qemu::PointerDevice>,
+    /// Guest clock base; `None` uses local time for Windows and UTC otherwise
+    #[serde(default)]
+    pub rtc: Option<qemu::RtcBase>,
+    /// VirtIO RNG for guest entropy; `None` enables it for Linux guests only
+    #[serde(default)]
+    pub rng: Option<bool>,
+}
+
+fn default_boot_order() -> String {
+    "disk-first".to_string()
+}
+
+fn default_network_type() -> String {
+    "nat".to_string()
+}
+
+fn default_arch() -> String {
+    "x86_64".to_string()
+}
+
+fn default_restart_policy() -> String {
+    "on-failure".to_string()
+}
+
+fn default_display_mode() -> String {
+    "spice".to_string()
+}
+
+fn default_preallocation() -> String {
+    "metadata".to_string()
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct VMWarning {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct VM {
+    pub id: String,
+    pub name: String,
+    pub status: VMStatus,
+    pub config: VMConfig,
+    /// Guest architecture differs from the host, so it runs under TCG
+    #[serde(default)]
+    pub emulated: bool,
+    /// Ways to reach the guest: the display protocol, if any, and the QEMU monitor
+    #[serde(default)]
+    pub access_methods: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Groups the VM is filed under, for the sidebar's folders
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub group_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<VMWarning>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VmPage {
+    pub vms: Vec<VM>,
+    pub total: u64,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+/// A keyset page of VMs; each entry carries only the requested fields
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VmCursorPage {
+    pub vms: Vec<serde_json::Value>,
+    /// Pass back to fetch the next page; `None` on the last one
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VMStatus {
+    Running,
+    Stopped,
+    Paused,
+    Error,
+    /// QEMU is launching; becomes `Running` once its QMP socket accepts connections
+    Starting,
+    /// Shutdown requested; becomes `Stopped` when the process is gone
+    Stopping,
+    /// The disk image is still being made in the background; becomes `Stopped` when it is ready
+    Creating,
+}
@@ -0,0 +1,492 @@
+//! Application logging
+//!
+//! Every event passes through `RedactingLayer`, which masks secrets before a
+//! line is written to the daily-rolling file under the logs dir (and stderr).
+
+use crate::{Error, Result};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::MakeWriterExt;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Layer, Registry};
+
+/// Setting holding the minimum level written to the app log
+pub const LOG_LEVEL_SETTING: &str = "log_level";
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+pub const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+const LOG_FILE_PREFIX: &str = "openutm";
+const LOG_FILE_SUFFIX: &str = "log";
+const MAX_LOG_FILES: usize = 7;
+
+const REDACTED: &str = "[REDACTED]";
+/// Field names and `key=value` keys whose values never reach the log
+const SENSITIVE_KEYS: [&str; 4] = ["passphrase", "password", "secret", "token"];
+
+pub type LevelHandle = reload::Handle<LevelFilter, Registry>;
+
+pub fn parse_level(value: &str) -> Option<LevelFilter> {
+    match value {
+        "error" => Some(LevelFilter::ERROR),
+        "warn" => Some(LevelFilter::WARN),
+        "info" => Some(LevelFilter::INFO),
+        "debug" => Some(LevelFilter::DEBUG),
+        "trace" => Some(LevelFilter::TRACE),
+        _ => None,
+    }
+}
+
+/// Install the global subscriber; the returned handle changes the level at runtime
+pub fn init(log_dir: &Path, level: LevelFilter) -> Result<LevelHandle> {
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir)
+        .map_err(|err| Error::ConfigError(format!("Failed to open log file: {}", err)))?;
+
+    let (filter, handle) = reload::Layer::new(level);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(RedactingLayer::new(appender.and(std::io::stderr)))
+        .try_init()
+        .map_err(|err| Error::ConfigError(format!("Failed to install logger: {}", err)))?;
+
+    Ok(handle)
+}
+
+pub fn set_level(handle: &LevelHandle, level: LevelFilter) -> Result<()> {
+    handle
+        .reload(level)
+        .map_err(|err| Error::ConfigError(format!("Failed to change log level: {}", err)))
+}
+
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SENSITIVE_KEYS.iter().any(|key| name.contains(key))
+}
+
+/// Mask the value of any `key=value` / `key: value` pair with a sensitive key
+pub fn redact(text: &str) -> String {
+    let lower = text.to_ascii_lowercase();
+    let bytes = text.as_bytes();
+    let mut ranges = Vec::new();
+
+    for key in SENSITIVE_KEYS {
+        let mut from = 0;
+        while let Some(found) = lower[from..].find(key) {
+            let mut pos = from + found + key.len();
+            from = pos;
+            // Allow compound keys such as `spice_password` or `key-secret`
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_' || bytes[pos] == b'-') {
+                pos += 1;
+            }
+            if pos < bytes.len() && bytes[pos] == b'"' {
+                pos += 1;
+            }
+            while pos < bytes.len() && bytes[pos] == b' ' {
+                pos += 1;
+            }
+            if pos >= bytes.len() || (bytes[pos] != b'=' && bytes[pos] != b':') {
+                continue;
+            }
+            pos += 1;
+            while pos < bytes.len() && bytes[pos] == b' ' {
+                pos += 1;
+            }
+            if pos < bytes.len() && bytes[pos] == b'"' {
+                pos += 1;
+            }
+            let end = text[pos..]
+                .find([',', ' ', '"', '&', ';', '\n', '}', ')'])
+                .map_or(text.len(), |offset| pos + offset);
+            if end > pos {
+                ranges.push((pos, end));
+            }
+        }
+    }
+
+    ranges.sort_unstable();
+    let mut redacted = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, end) in ranges {
+        if start < last {
+            continue;
+        }
+        redacted.push_str(&text[last..start]);
+        redacted.push_str(REDACTED);
+        last = end;
+    }
+    redacted.push_str(&text[last..]);
+    redacted
+}
+
+#[derive(Default)]
+struct FieldWriter {
+    message: String,
+    fields: String,
+}
+
+impl FieldWriter {
+    fn push(&mut self, field: &Field, value: String) {
+        let value = if is_sensitive(field.name()) { REDACTED.to_string() } else { redact(&value) };
+        if field.name() == "message" {
+            self.message = value;
+        } else {
+            if !self.fields.is_empty() {
+                self.fields.push(' ');
+            }
+            let _ = write!(self.fields, "{}={}", field.name(), value);
+        }
+    }
+}
+
+impl Visit for FieldWriter {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.push(field, format!("{:?}", value));
+    }
+}
+
+/// Redacted fields of an open span, rendered once when it is created
+struct SpanFields(String);
+
+/// Formats events as single lines, masking sensitive fields and `key=value` secrets
+pub struct RedactingLayer<W> {
+    make_writer: W,
+}
+
+impl<W> RedactingLayer<W> {
+    pub fn new(make_writer: W) -> Self {
+        Self { make_writer }
+    }
+}
+
+impl<S, W> Layer<S> for RedactingLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = FieldWriter::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(existing)) = extensions.get_mut::<SpanFields>() {
+            let mut fields = FieldWriter {
+                fields: std::mem::take(existing),
+                ..FieldWriter::default()
+            };
+            values.record(&mut fields);
+            *existing = fields.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = format!(
+            "{} {:>5} {}: ",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            metadata.level().to_string(),
+            metadata.target()
+        );
+
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                line.push_str(span.name());
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    if !fields.is_empty() {
+                        let _ = write!(line, "{{{}}}", fields);
+                    }
+                }
+                line.push_str(": ");
+            }
+        }
+
+        let mut fields = FieldWriter::default();
+        event.record(&mut fields);
+        line.push_str(&fields.message);
+        if !fields.fields.is_empty() {
+            line.push(' ');
+            line.push_str(&fields.fields);
+        }
+        line.push('\n');
+
+        let _ = self.make_writer.make_writer_for(metadata).write_all(line.as_bytes());
+    }
+}
+
+/// Level of a line written by `RedactingLayer`, if it has one
+fn line_level(line: &str) -> Option<Level> {
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// App log files, oldest first; daily file names sort chronologically
+fn app_log_files(log_dir: &Path) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}.", LOG_FILE_PREFIX);
+    let suffix = format!(".{}", LOG_FILE_SUFFIX);
+    let mut files: Vec<PathBuf> = std::fs::read_dir(log_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(&suffix))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The last `lines` app log lines at or above `min_level`, oldest first
+pub fn tail_logs(log_dir: &Path, lines: usize, min_level: Option<LevelFilter>) -> Result<Vec<String>> {
+    let mut tail = Vec::new();
+    if !log_dir.exists() {
+        return Ok(tail);
+    }
+
+    for path in app_log_files(log_dir)?.iter().rev() {
+        let contents = std::fs::read_to_string(path)?;
+        for line in contents.lines().rev() {
+            if tail.len() == lines {
+                break;
+            }
+            let keep = match min_level {
+                Some(filter) => line_level(line).is_some_and(|level| level <= filter),
+                None => true,
+            };
+            if keep {
+                tail.push(line.to_string());
+            }
+        }
+    }
+
+    tail.reverse();
+    Ok(tail)
+}
+
+fn redacted_settings(settings: &[(String, String)]) -> serde_json::Value {
+    settings
+        .iter()
+        .map(|(key, value)| {
+            let value = if is_sensitive(key) { REDACTED.to_string() } else { redact(value) };
+            (key.clone(), serde_json::Value::String(value))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Bundle every log in `log_dir`, the settings and QEMU details into a zip at `dest`.
+///
+/// Log contents and sensitive setting values are redacted again on the way in,
+/// since per-VM QEMU output never passes through the logging layer.
+pub fn export_diagnostics(
+    log_dir: &Path,
+    settings: &[(String, String)],
+    qemu_info: &serde_json::Value,
+    dest: &Path,
+) -> Result<()> {
+    use zip::write::SimpleFileOptions;
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(dest)?);
+    let options = SimpleFileOptions::default();
+
+    if log_dir.exists() {
+        let mut logs: Vec<PathBuf> = std::fs::read_dir(log_dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == LOG_FILE_SUFFIX))
+            .collect();
+        logs.sort();
+        for path in logs {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+            let contents = String::from_utf8_lossy(&std::fs::read(&path)?).into_owned();
+            zip.start_file(format!("logs/{}", name), options).map_err(std::io::Error::from)?;
+            zip.write_all(redact(&contents).as_bytes())?;
+        }
+    }
+
+    zip.start_file("settings.json", options).map_err(std::io::Error::from)?;
+    zip.write_all(serde_json::to_string_pretty(&redacted_settings(settings))?.as_bytes())?;
+
+    zip.start_file("qemu.json", options).map_err(std::io::Error::from)?;
+    zip.write_all(serde_json::to_string_pretty(qemu_info)?.as_bytes())?;
+
+    zip.finish().map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Captured {
+        type Writer = Captured;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn capture(f: impl FnOnce()) -> String {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::registry().with(RedactingLayer::new(captured.clone()));
+        tracing::subscriber::with_default(subscriber, f);
+        let bytes = captured.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_redact_key_value_pairs() {
+        let cases = [
+            ("-spice port=5930,password=hunter2,addr=127.0.0.1", "-spice port=5930,password=[REDACTED],addr=127.0.0.1"),
+            ("passphrase: s3cret rest", "passphrase: [REDACTED] rest"),
+            (r#"{"spice_password":"abc"}"#, r#"{"spice_password":"[REDACTED]"}"#),
+            ("TOKEN=xyz", "TOKEN=[REDACTED]"),
+            ("-object secret,id=luks-1,file=/tmp/s", "-object secret,id=luks-1,file=/tmp/s"),
+            ("nothing to hide", "nothing to hide"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(redact(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_layer_redacts_sensitive_fields() {
+        let output = capture(|| {
+            let span = tracing::info_span!("launch", vm_id = "vm-1", passphrase = "span-secret");
+            let _guard = span.enter();
+            tracing::info!(password = "field-secret", args = "port=5930,password=arg-secret", "starting");
+        });
+
+        assert!(output.contains(" INFO "));
+        assert!(output.contains("launch{vm_id=vm-1 passphrase=[REDACTED]}: starting"));
+        assert!(output.contains("password=[REDACTED]"));
+        assert!(output.contains("args=port=5930,password=[REDACTED]"));
+        for secret in ["span-secret", "field-secret", "arg-secret"] {
+            assert!(!output.contains(secret), "{} leaked: {}", secret, output);
+        }
+    }
+
+    fn write_log(dir: &Path, name: &str, lines: &[&str]) {
+        std::fs::write(dir.join(name), lines.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn test_tail_logs_spans_files_and_filters_level() {
+        let temp_dir = TempDir::new().unwrap();
+        write_log(
+            temp_dir.path(),
+            "openutm.2026-01-01.log",
+            &["2026-01-01T00:00:00.000Z ERROR openutm: old failure", "2026-01-01T00:00:01.000Z  INFO openutm: old info"],
+        );
+        write_log(
+            temp_dir.path(),
+            "openutm.2026-01-02.log",
+            &[
+                "2026-01-02T00:00:00.000Z  WARN openutm: new warning",
+                "2026-01-02T00:00:01.000Z DEBUG openutm: new debug",
+                "2026-01-02T00:00:02.000Z  INFO openutm: new info",
+            ],
+        );
+        // Per-VM QEMU output is not part of the app log
+        write_log(temp_dir.path(), "vm-1.log", &["2026-01-02T00:00:03.000Z ERROR qemu: vm noise"]);
+
+        let all = tail_logs(temp_dir.path(), 100, None).unwrap();
+        assert_eq!(all.len(), 5);
+        assert!(all[0].ends_with("old failure"));
+        assert!(all[4].ends_with("new info"));
+
+        let last_two = tail_logs(temp_dir.path(), 2, None).unwrap();
+        assert!(last_two[0].ends_with("new debug"));
+        assert!(last_two[1].ends_with("new info"));
+
+        let warnings = tail_logs(temp_dir.path(), 100, Some(LevelFilter::WARN)).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].ends_with("old failure"));
+        assert!(warnings[1].ends_with("new warning"));
+
+        let info = tail_logs(temp_dir.path(), 2, Some(LevelFilter::INFO)).unwrap();
+        assert!(info[0].ends_with("new warning"));
+        assert!(info[1].ends_with("new info"));
+    }
+
+    #[test]
+    fn test_tail_logs_missing_dir_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(tail_logs(&temp_dir.path().join("missing"), 10, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_level() {
+        for level in LOG_LEVELS {
+            assert!(parse_level(level).is_some());
+        }
+        assert_eq!(parse_level("warn"), Some(LevelFilter::WARN));
+        assert_eq!(parse_level("loud"), None);
+    }
+
+    #[test]
+    fn test_export_diagnostics_writes_archive() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_dir = temp_dir.path().join("logs");
+        std::fs::create_dir_all(&log_dir).unwrap();
+        write_log(&log_dir, "openutm.2026-01-01.log", &["2026-01-01T00:00:00.000Z  INFO openutm: hello"]);
+        let dest = temp_dir.path().join("diagnostics.zip");
+
+        let settings = vec![("unique_names".to_string(), "true".to_string())];
+        export_diagnostics(&log_dir, &settings, &serde_json::json!({ "detected": false }), &dest).unwrap();
+
+        assert!(std::fs::metadata(&dest).unwrap().len() > 0);
+    }
+
+    #[test]
+    fn test_redacted_settings() {
+        let settings = vec![
+            ("unique_names".to_string(), "true".to_string()),
+            ("spice_password".to_string(), "hunter2".to_string()),
+            ("bridge_args".to_string(), "br0,token=abc".to_string()),
+        ];
+        assert_eq!(
+            redacted_settings(&settings),
+            serde_json::json!({
+                "unique_names": "true",
+                "spice_password": "[REDACTED]",
+                "bridge_args": "br0,token=[REDACTED]",
+            })
+        );
+    }
+}
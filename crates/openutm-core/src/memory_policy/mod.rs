@@ -0,0 +1,182 @@
+//! Memory ballooning under host pressure
+//!
+//! When free host memory drops below the policy threshold, running VMs with a
+//! balloon device give memory back in proportion to how far each sits above
+//! the per-VM minimum. As pressure clears, the memory is returned. The engine
+//! only computes targets; the app samples the host and talks QMP.
+
+/// Changes smaller than this are skipped, so noise in free memory doesn't
+/// resize every balloon on every sample; full restores always go through
+pub const MIN_ADJUSTMENT_MB: u32 = 64;
+
+pub const DEFAULT_FREE_THRESHOLD_MB: u64 = 2048;
+pub const DEFAULT_VM_MINIMUM_MB: u32 = 1024;
+
+/// Global ballooning policy; off unless the user turns it on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryPolicy {
+    pub enabled: bool,
+    /// Free host memory the policy tries to keep available
+    pub free_threshold_mb: u64,
+    /// No VM is ballooned below this, or below its allocation if that is smaller
+    pub vm_minimum_mb: u32,
+}
+
+impl Default for MemoryPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            free_threshold_mb: DEFAULT_FREE_THRESHOLD_MB,
+            vm_minimum_mb: DEFAULT_VM_MINIMUM_MB,
+        }
+    }
+}
+
+/// A running VM whose balloon the policy may resize
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalloonedVm {
+    pub vm_id: String,
+    /// Memory the VM was started with
+    pub allocated_mb: u32,
+    /// What the balloon currently leaves the guest
+    pub current_mb: u32,
+    /// Never ballooned; a VM made exempt while ballooned gets its memory back
+    pub exempt: bool,
+}
+
+/// A balloon resize the policy asks for
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalloonAdjustment {
+    pub vm_id: String,
+    pub previous_mb: u32,
+    pub target_mb: u32,
+}
+
+impl BalloonedVm {
+    /// Memory the VM could give back without going under the minimum
+    fn headroom_mb(&self, policy: &MemoryPolicy) -> u64 {
+        if self.exempt {
+            return 0;
+        }
+        u64::from(self.allocated_mb.saturating_sub(policy.vm_minimum_mb))
+    }
+}
+
+/// Balloon targets for `vms` given `free_mb` of free host memory.
+///
+/// Memory already held by balloons counts as free, so one formula both
+/// reclaims under pressure and hands memory back once the host has enough.
+pub fn balloon_targets(policy: &MemoryPolicy, free_mb: u64, vms: &[BalloonedVm]) -> Vec<BalloonAdjustment> {
+    let headroom: Vec<u64> = vms.iter().map(|vm| vm.headroom_mb(policy)).collect();
+    let total_headroom: u64 = headroom.iter().sum();
+    let reclaimed: u64 = vms
+        .iter()
+        .map(|vm| u64::from(vm.allocated_mb.saturating_sub(vm.current_mb)))
+        .sum();
+    let wanted = if policy.enabled {
+        (reclaimed + policy.free_threshold_mb).saturating_sub(free_mb).min(total_headroom)
+    } else {
+        0
+    };
+
+    vms.iter()
+        .zip(headroom)
+        .filter_map(|(vm, headroom)| {
+            let share = (headroom * wanted).checked_div(total_headroom).unwrap_or(0);
+            // `share` never exceeds `headroom`, which fits in a u32
+            let target_mb = vm.allocated_mb - share as u32;
+            let change = target_mb.abs_diff(vm.current_mb);
+            let restore = target_mb == vm.allocated_mb && change > 0;
+            (restore || change >= MIN_ADJUSTMENT_MB).then(|| BalloonAdjustment {
+                vm_id: vm.vm_id.clone(),
+                previous_mb: vm.current_mb,
+                target_mb,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> MemoryPolicy {
+        MemoryPolicy { enabled: true, free_threshold_mb: 2048, vm_minimum_mb: 1024 }
+    }
+
+    fn vm(vm_id: &str, allocated_mb: u32, current_mb: u32) -> BalloonedVm {
+        BalloonedVm { vm_id: vm_id.to_string(), allocated_mb, current_mb, exempt: false }
+    }
+
+    fn targets(adjustments: &[BalloonAdjustment]) -> Vec<(&str, u32)> {
+        adjustments
+            .iter()
+            .map(|adjustment| (adjustment.vm_id.as_str(), adjustment.target_mb))
+            .collect()
+    }
+
+    #[test]
+    fn test_no_pressure_leaves_full_allocations() {
+        let vms = [vm("a", 4096, 4096), vm("b", 2048, 2048)];
+        assert!(balloon_targets(&policy(), 8192, &vms).is_empty());
+    }
+
+    #[test]
+    fn test_pressure_reclaims_in_proportion_to_headroom() {
+        // 1536 MB short; headroom is 3072 for a and 1024 for b
+        let vms = [vm("a", 4096, 4096), vm("b", 2048, 2048)];
+        let adjustments = balloon_targets(&policy(), 512, &vms);
+        assert_eq!(targets(&adjustments), vec![("a", 2944), ("b", 1664)]);
+        assert_eq!(adjustments[0].previous_mb, 4096);
+    }
+
+    #[test]
+    fn test_reclaim_stops_at_minimum() {
+        let severe = MemoryPolicy { free_threshold_mb: 8192, ..policy() };
+        let vms = [vm("a", 4096, 4096), vm("small", 512, 512)];
+        assert_eq!(targets(&balloon_targets(&severe, 0, &vms)), vec![("a", 1024)]);
+    }
+
+    #[test]
+    fn test_pressure_clearing_restores_allocations() {
+        // 1024 MB already reclaimed from a; the host now has 1536 MB spare above the threshold
+        let vms = [vm("a", 4096, 3072), vm("b", 2048, 2048)];
+        assert_eq!(targets(&balloon_targets(&policy(), 3584, &vms)), vec![("a", 4096)]);
+
+        // Only part of it can go back while the host is still short
+        let adjustments = balloon_targets(&policy(), 2560, &vms);
+        assert_eq!(targets(&adjustments), vec![("a", 3712), ("b", 1920)]);
+    }
+
+    #[test]
+    fn test_steady_pressure_keeps_balloons() {
+        // The 1024 MB the balloons hold is exactly what keeps the host at the threshold
+        let vms = [vm("a", 4096, 3328), vm("b", 2048, 1792)];
+        assert!(balloon_targets(&policy(), 2048, &vms).is_empty());
+    }
+
+    #[test]
+    fn test_small_changes_are_skipped() {
+        let vms = [vm("a", 4096, 4096)];
+        assert!(balloon_targets(&policy(), 2000, &vms).is_empty());
+        assert_eq!(targets(&balloon_targets(&policy(), 1900, &vms)), vec![("a", 3948)]);
+    }
+
+    #[test]
+    fn test_exempt_vms_keep_their_memory() {
+        let mut exempt = vm("exempt", 4096, 3072);
+        exempt.exempt = true;
+        let vms = [exempt, vm("b", 4096, 4096)];
+        // b also covers the memory the exempt VM takes back
+        assert_eq!(targets(&balloon_targets(&policy(), 1024, &vms)), vec![("exempt", 4096), ("b", 2048)]);
+    }
+
+    #[test]
+    fn test_disabled_policy_restores_everything() {
+        let disabled = MemoryPolicy { enabled: false, ..policy() };
+        let vms = [vm("a", 4096, 3072), vm("b", 2048, 2048)];
+        assert_eq!(targets(&balloon_targets(&disabled, 0, &vms)), vec![("a", 4096)]);
+    }
+}
@@ -0,0 +1,354 @@
+//! Prometheus metrics for VM fleet monitoring
+//!
+//! When enabled, a small HTTP listener serves `GET /metrics` in the Prometheus
+//! text exposition format: per-VM gauges from the same samplers as
+//! `get_vm_metrics`, plus app-wide counters. Dropping the `MetricsServer`
+//! closes the listener.
+
+use crate::Result;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bound for a scraper to send its request headers
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+static COMMANDS_EXECUTED: AtomicU64 = AtomicU64::new(0);
+static QMP_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Count one Tauri command invocation
+pub fn record_command() {
+    COMMANDS_EXECUTED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count one failed or timed out QMP command
+pub fn record_qmp_error() {
+    QMP_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// CPU and memory use of one QEMU process
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessUsage {
+    /// Share of one host CPU since the previous sample of this process, so above 100 with several vCPUs busy
+    pub cpu_percent: f32,
+    pub memory_rss_bytes: u64,
+}
+
+/// Samples QEMU processes; CPU use is measured between consecutive calls, so
+/// the first sample of a process reports 0
+pub struct ProcessSampler {
+    system: Mutex<sysinfo::System>,
+}
+
+impl ProcessSampler {
+    pub fn new() -> Self {
+        Self {
+            system: Mutex::new(sysinfo::System::new()),
+        }
+    }
+
+    pub fn sample(&self, pid: u32) -> Option<ProcessUsage> {
+        let pid = sysinfo::Pid::from_u32(pid);
+        let mut system = self.system.lock().unwrap();
+        if !system.refresh_process(pid) {
+            return None;
+        }
+        let process = system.process(pid)?;
+        Some(ProcessUsage {
+            cpu_percent: process.cpu_usage(),
+            memory_rss_bytes: process.memory(),
+        })
+    }
+}
+
+impl Default for ProcessSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Everything exported about one VM; `None` values are left out of the exposition
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VmSample {
+    pub vm_id: String,
+    pub name: String,
+    pub status: String,
+    pub usage: Option<ProcessUsage>,
+    pub disk_physical_bytes: Option<u64>,
+    /// Bytes the guest received and sent on its TAP device
+    pub net_rx_bytes: Option<u64>,
+    pub net_tx_bytes: Option<u64>,
+}
+
+/// Bytes the guest received and sent through host TAP interface `ifname`.
+/// The host's transmit counter is the guest's receive and vice versa.
+#[cfg(target_os = "linux")]
+pub fn tap_traffic(ifname: &str) -> Option<(u64, u64)> {
+    let statistics = std::path::Path::new("/sys/class/net").join(ifname).join("statistics");
+    let read = |name: &str| std::fs::read_to_string(statistics.join(name)).ok()?.trim().parse().ok();
+    Some((read("tx_bytes")?, read("rx_bytes")?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn tap_traffic(_ifname: &str) -> Option<(u64, u64)> {
+    None
+}
+
+/// The `ifname` of a TAP netdev in QEMU's launch arguments; user-mode networking has none
+pub fn tap_ifname(args: &[String]) -> Option<String> {
+    args.windows(2)
+        .filter(|pair| pair[0] == "-netdev" && pair[1].starts_with("tap,"))
+        .flat_map(|pair| pair[1].split(','))
+        .find_map(|option| option.strip_prefix("ifname="))
+        .map(str::to_string)
+}
+
+/// The Prometheus text exposition of `samples` and the app counters
+pub fn render(samples: &[VmSample]) -> String {
+    render_with_counters(
+        samples,
+        COMMANDS_EXECUTED.load(Ordering::Relaxed),
+        QMP_ERRORS.load(Ordering::Relaxed),
+    )
+}
+
+fn render_with_counters(samples: &[VmSample], commands: u64, qmp_errors: u64) -> String {
+    let mut out = String::new();
+    header(&mut out, "openutm_vm_status", "gauge", "VM lifecycle state; 1 for the current status label");
+    for sample in samples {
+        let labels = format!("{},status=\"{}\"", vm_labels(sample), escape_label(&sample.status));
+        out.push_str(&format!("openutm_vm_status{{{}}} 1\n", labels));
+    }
+    for (name, kind, help, value) in vm_series() {
+        header(&mut out, name, kind, help);
+        for sample in samples {
+            if let Some(value) = value(sample) {
+                out.push_str(&format!("{}{{{}}} {}\n", name, vm_labels(sample), value));
+            }
+        }
+    }
+
+    header(&mut out, "openutm_commands_total", "counter", "Commands invoked from the UI");
+    out.push_str(&format!("openutm_commands_total {}\n", commands));
+    header(&mut out, "openutm_qmp_errors_total", "counter", "QMP commands that failed or timed out");
+    out.push_str(&format!("openutm_qmp_errors_total {}\n", qmp_errors));
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+}
+
+type SeriesValue = fn(&VmSample) -> Option<f64>;
+
+/// Name, type, help and value of each per-VM series besides the status
+fn vm_series() -> [(&'static str, &'static str, &'static str, SeriesValue); 5] {
+    [
+        ("openutm_vm_cpu_percent", "gauge", "QEMU process CPU use, percent of one host CPU", |sample| {
+            sample.usage.map(|usage| f64::from(usage.cpu_percent))
+        }),
+        ("openutm_vm_memory_rss_bytes", "gauge", "QEMU process resident memory", |sample| {
+            sample.usage.map(|usage| usage.memory_rss_bytes as f64)
+        }),
+        ("openutm_vm_disk_physical_bytes", "gauge", "Host space allocated to the VM's disk image", |sample| {
+            sample.disk_physical_bytes.map(|bytes| bytes as f64)
+        }),
+        ("openutm_vm_network_receive_bytes_total", "counter", "Bytes received by the guest", |sample| {
+            sample.net_rx_bytes.map(|bytes| bytes as f64)
+        }),
+        ("openutm_vm_network_transmit_bytes_total", "counter", "Bytes sent by the guest", |sample| {
+            sample.net_tx_bytes.map(|bytes| bytes as f64)
+        }),
+    ]
+}
+
+fn vm_labels(sample: &VmSample) -> String {
+    format!("vm_id=\"{}\",name=\"{}\"", escape_label(&sample.vm_id), escape_label(&sample.name))
+}
+
+/// Escape a label value as the exposition format requires
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// A running `/metrics` listener
+pub struct MetricsServer {
+    addr: SocketAddr,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Listen on `addr` and answer each scrape with the text `collect` produces
+    pub async fn spawn<F, Fut>(addr: SocketAddr, collect: F) -> Result<Self>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = String> + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(accept_loop(listener, Arc::new(collect)));
+        tracing::info!(%addr, "metrics server listening");
+        Ok(Self { addr, task })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop listening, returning once the address can be bound again
+    pub async fn shutdown(mut self) {
+        self.task.abort();
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        // Aborting the accept loop closes the listener; in-flight scrapes finish on their own
+        self.task.abort();
+        tracing::info!(addr = %self.addr, "metrics server stopped");
+    }
+}
+
+async fn accept_loop<F, Fut>(listener: TcpListener, collect: Arc<F>)
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = String> + Send + 'static,
+{
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let collect = collect.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = serve(stream, collect.as_ref()).await {
+                        tracing::debug!(error = %err, "metrics request failed");
+                    }
+                });
+            }
+            Err(err) => tracing::warn!(error = %err, "metrics server accept failed"),
+        }
+    }
+}
+
+async fn serve<F, Fut>(stream: TcpStream, collect: &F) -> Result<()>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = String>,
+{
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let request_line = tokio::time::timeout(REQUEST_TIMEOUT, async {
+        let request_line = lines.next_line().await?.unwrap_or_default();
+        // Headers are not needed, but must be read before answering
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>(request_line)
+    })
+    .await
+    .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => response("200 OK", CONTENT_TYPE, &collect().await),
+        (Some("GET"), _) => response("404 Not Found", "text/plain", "Not found\n"),
+        _ => response("405 Method Not Allowed", "text/plain", "Method not allowed\n"),
+    };
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_vm() -> VmSample {
+        VmSample {
+            vm_id: "vm-1".to_string(),
+            name: "Build \"box\"".to_string(),
+            status: "running".to_string(),
+            usage: Some(ProcessUsage {
+                cpu_percent: 12.5,
+                memory_rss_bytes: 536_870_912,
+            }),
+            disk_physical_bytes: Some(1_048_576),
+            net_rx_bytes: None,
+            net_tx_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_exposition_format() {
+        let text = render_with_counters(&[fake_vm()], 42, 3);
+        let labels = r#"vm_id="vm-1",name="Build \"box\"""#;
+
+        assert!(text.contains("# TYPE openutm_vm_status gauge\n"));
+        assert!(text.contains(&format!("openutm_vm_status{{{},status=\"running\"}} 1\n", labels)));
+        assert!(text.contains(&format!("openutm_vm_cpu_percent{{{}}} 12.5\n", labels)));
+        assert!(text.contains(&format!("openutm_vm_memory_rss_bytes{{{}}} 536870912\n", labels)));
+        assert!(text.contains(&format!("openutm_vm_disk_physical_bytes{{{}}} 1048576\n", labels)));
+        // No TAP device, so no traffic series
+        assert!(!text.contains("openutm_vm_network_receive_bytes_total{"));
+        assert!(text.contains("# TYPE openutm_commands_total counter\nopenutm_commands_total 42\n"));
+        assert!(text.contains("openutm_qmp_errors_total 3\n"));
+    }
+
+    #[test]
+    fn test_tap_ifname_from_launch_args() {
+        let args: Vec<String> = ["-m", "1024", "-netdev", "tap,id=net0,ifname=vlan100,script=no"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_eq!(tap_ifname(&args), Some("vlan100".to_string()));
+
+        let user = vec!["-netdev".to_string(), "user,id=net0".to_string()];
+        assert_eq!(tap_ifname(&user), None);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_exposition() {
+        let server = MetricsServer::spawn("127.0.0.1:0".parse().unwrap(), || async { render(&[fake_vm()]) })
+            .await
+            .unwrap();
+        let base = format!("http://{}", server.addr());
+
+        let scrape = reqwest::get(format!("{}/metrics", base)).await.unwrap();
+        assert_eq!(scrape.status(), 200);
+        assert_eq!(scrape.headers()["content-type"], CONTENT_TYPE);
+        let body = scrape.text().await.unwrap();
+        assert!(body.contains(r#"openutm_vm_status{vm_id="vm-1",name="Build \"box\"",status="running"} 1"#));
+        assert!(body.contains("openutm_commands_total "));
+
+        assert_eq!(reqwest::get(format!("{}/other", base)).await.unwrap().status(), 404);
+
+        // Dropping the server stops the listener
+        let addr = server.addr();
+        drop(server);
+        for _ in 0..50 {
+            if TcpStream::connect(addr).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("metrics server still listening on {}", addr);
+    }
+}
@@ -0,0 +1,441 @@
+//! VirtualBox OVA import
+//!
+//! An OVA is a tar archive holding an OVF descriptor plus VMDK disks. The
+//! descriptor is parsed for the bits OpenUTM models; disks are converted to
+//! qcow2 with `qemu-img convert`.
+
+use crate::error::Error;
+use crate::guest::GuestOs;
+use crate::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// CIM resource types used in OVF `VirtualHardwareSection` items
+const RESOURCE_CPU: &str = "3";
+const RESOURCE_MEMORY: &str = "4";
+const RESOURCE_ETHERNET: &str = "10";
+const RESOURCE_DISK: &str = "17";
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OvaManifest {
+    pub name: String,
+    pub cpu: u32,
+    pub memory_mb: u32,
+    pub disk_files: Vec<String>,
+    pub os_type: String,
+    pub network_adapters: Vec<String>,
+    /// VirtualBox stores the VM description as an OVF annotation
+    pub annotation: String,
+}
+
+/// Read the OVF descriptor out of an OVA archive and parse it
+pub fn parse_ova(path: &Path) -> Result<OvaManifest> {
+    let listing = run_tar(&[std::ffi::OsStr::new("-tf"), path.as_os_str()])?;
+    let ovf_entry = String::from_utf8(listing)?
+        .lines()
+        .find(|entry| entry.to_ascii_lowercase().ends_with(".ovf"))
+        .map(|entry| entry.to_string())
+        .ok_or_else(|| Error::InvalidConfig("OVA archive has no .ovf descriptor".to_string()))?;
+
+    let xml = run_tar(&[
+        std::ffi::OsStr::new("-xOf"),
+        path.as_os_str(),
+        std::ffi::OsStr::new(&ovf_entry),
+    ])?;
+    parse_ovf(&String::from_utf8(xml)?)
+}
+
+/// Unpack every file of an OVA into `dest_dir`
+pub fn extract_ova(path: &Path, dest_dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dest_dir)?;
+    run_tar(&[
+        std::ffi::OsStr::new("-xf"),
+        path.as_os_str(),
+        std::ffi::OsStr::new("-C"),
+        dest_dir.as_os_str(),
+    ])?;
+    Ok(())
+}
+
+/// Convert each disk referenced by the manifest to qcow2, returning the new paths
+pub fn convert_ova_disks(manifest: &OvaManifest, src_dir: &Path, dest_dir: &Path) -> Result<Vec<String>> {
+    std::fs::create_dir_all(dest_dir)?;
+
+    let mut converted = Vec::with_capacity(manifest.disk_files.len());
+    for disk_file in &manifest.disk_files {
+        let source = extracted_disk(src_dir, disk_file)?;
+        let stem = Path::new(disk_file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| disk_file.clone());
+        let target = dest_dir.join(format!("{}.qcow2", stem));
+
+        let output = Command::new("qemu-img")
+            .arg("convert")
+            .args(["-O", "qcow2"])
+            .arg(&source)
+            .arg(&target)
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::QemuError(format!("qemu-img convert failed: {}", stderr)));
+        }
+
+        converted.push(target.display().to_string());
+    }
+
+    Ok(converted)
+}
+
+/// Map an OVF/VirtualBox OS type (e.g. `Ubuntu_64`, `Windows10_64`) onto a guest OS family
+pub fn map_os_type(os_type: &str) -> GuestOs {
+    let lower = os_type.to_ascii_lowercase();
+    if lower.is_empty() || lower.starts_with("other") {
+        return GuestOs::Other;
+    }
+    // VirtualBox names most distros individually; anything unrecognised is far more likely Linux than not
+    match GuestOs::parse(&lower) {
+        GuestOs::Other => GuestOs::Linux,
+        os => os,
+    }
+}
+
+/// A disk unpacked from the archive. A symlink or device entry could point
+/// `qemu-img` at any host file and copy it into the guest disk, so only a
+/// regular file directly inside `src_dir` is accepted.
+fn extracted_disk(src_dir: &Path, disk_file: &str) -> Result<PathBuf> {
+    let source = src_dir.join(disk_file);
+    let not_regular = || Error::InvalidConfig(format!("OVA disk {} is not a regular file", disk_file));
+    if !std::fs::symlink_metadata(&source).map_err(|_| not_regular())?.file_type().is_file() {
+        return Err(not_regular());
+    }
+    let parent = std::fs::canonicalize(&source)?.parent().map(Path::to_path_buf);
+    if parent != Some(std::fs::canonicalize(src_dir)?) {
+        return Err(not_regular());
+    }
+    Ok(source)
+}
+
+/// Whether `href` names a file directly inside the archive, with no directory part
+fn is_plain_file_name(href: &str) -> bool {
+    !href.contains(['/', '\\']) && Path::new(href).file_name().is_some_and(|name| name == href)
+}
+
+fn run_tar(args: &[&std::ffi::OsStr]) -> Result<Vec<u8>> {
+    let output = Command::new("tar").args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::InvalidConfig(format!("Failed to read OVA archive: {}", stderr.trim())));
+    }
+    Ok(output.stdout)
+}
+
+#[derive(Default)]
+struct HardwareItem {
+    resource_type: String,
+    quantity: String,
+    allocation_units: String,
+    host_resource: String,
+    sub_type: String,
+}
+
+fn attribute(element: &BytesStart, local_name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == local_name)
+        .and_then(|attr| attr.normalized_value(XmlVersion::Explicit1_0).ok().map(|value| value.to_string()))
+}
+
+/// Convert an OVF memory quantity to MB based on its allocation units
+fn memory_to_mb(quantity: u64, units: &str) -> u32 {
+    let units = units.to_ascii_lowercase().replace(' ', "");
+    let mb = if units.contains("2^30") || units.starts_with("giga") {
+        quantity * 1024
+    } else if units.contains("2^20") || units.starts_with("mega") || units.is_empty() {
+        quantity
+    } else if units.contains("2^10") || units.starts_with("kilo") {
+        quantity / 1024
+    } else {
+        quantity / (1024 * 1024)
+    };
+    mb as u32
+}
+
+/// Parse an OVF descriptor
+pub fn parse_ovf(xml: &str) -> Result<OvaManifest> {
+    let mut reader = Reader::from_str(xml);
+
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut files: HashMap<String, String> = HashMap::new();
+    let mut disks: HashMap<String, String> = HashMap::new();
+    let mut item: Option<HardwareItem> = None;
+    let mut system_id = String::new();
+
+    let mut name = String::new();
+    let mut os_type = String::new();
+    let mut cpu = 1;
+    let mut memory_mb = 0;
+    let mut disk_refs = Vec::new();
+    let mut network_adapters = Vec::new();
+    let mut annotation = String::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| Error::InvalidConfig(format!("Invalid OVF descriptor: {}", e)))?;
+
+        match event {
+            Event::Start(ref element) | Event::Empty(ref element) => {
+                let local = element.local_name().as_ref().to_string();
+                match local.as_str() {
+                    "File" => {
+                        if let (Some(id), Some(href)) = (attribute(element, "id"), attribute(element, "href")) {
+                            files.insert(id, href);
+                        }
+                    }
+                    "Disk" => {
+                        if let (Some(id), Some(file_ref)) =
+                            (attribute(element, "diskId"), attribute(element, "fileRef"))
+                        {
+                            disks.insert(id, file_ref);
+                        }
+                    }
+                    "VirtualSystem" => {
+                        system_id = attribute(element, "id").unwrap_or_default();
+                    }
+                    "Item" | "StorageItem" | "EthernetPortItem" => {
+                        item = Some(HardwareItem::default());
+                    }
+                    _ => {}
+                }
+                text.clear();
+                if matches!(event, Event::Start(_)) {
+                    path.push(local);
+                }
+            }
+            Event::Text(ref content) => text.push_str(&content.xml10_content()),
+            Event::GeneralRef(ref reference) => {
+                if let Ok(Some(ch)) = reference.resolve_char_ref() {
+                    text.push(ch);
+                } else if let Some(entity) = quick_xml::escape::resolve_predefined_entity(reference) {
+                    text.push_str(entity);
+                }
+            }
+            Event::End(_) => {
+                let local = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str).unwrap_or("");
+                let value = text.trim().to_string();
+
+                match (parent, local.as_str()) {
+                    ("VirtualSystem", "Name") if name.is_empty() => name = value,
+                    ("OperatingSystemSection", "Description") if os_type.is_empty() => os_type = value,
+                    ("OperatingSystemSection", "OSType") => os_type = value,
+                    ("AnnotationSection", "Annotation") => annotation = value,
+                    (_, "Item" | "StorageItem" | "EthernetPortItem") => {
+                        if let Some(hw) = item.take() {
+                            match hw.resource_type.as_str() {
+                                RESOURCE_CPU => cpu = hw.quantity.parse().unwrap_or(1),
+                                RESOURCE_MEMORY => {
+                                    memory_mb = memory_to_mb(hw.quantity.parse().unwrap_or(0), &hw.allocation_units)
+                                }
+                                RESOURCE_DISK => disk_refs.push(hw.host_resource),
+                                RESOURCE_ETHERNET => network_adapters.push(hw.sub_type),
+                                _ => {}
+                            }
+                        }
+                    }
+                    (_, field) => {
+                        if let Some(hw) = item.as_mut() {
+                            match field {
+                                "ResourceType" => hw.resource_type = value,
+                                "VirtualQuantity" => hw.quantity = value,
+                                "AllocationUnits" => hw.allocation_units = value,
+                                "HostResource" => hw.host_resource = value,
+                                "ResourceSubType" => hw.sub_type = value,
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // HostResource points at a disk (`ovf:/disk/vmdisk1`), which points at a file
+    let disk_files = disk_refs
+        .iter()
+        .filter_map(|host_resource| {
+            let disk_id = host_resource.rsplit('/').next()?;
+            let file_id = disks.get(disk_id)?;
+            files.get(file_id).cloned()
+        })
+        .collect::<Vec<_>>();
+
+    if disk_files.is_empty() {
+        return Err(Error::InvalidConfig("OVF descriptor references no disks".to_string()));
+    }
+    // Disks are read from the extraction dir by name; a path could reach outside it
+    if let Some(href) = disk_files.iter().find(|href| !is_plain_file_name(href)) {
+        return Err(Error::InvalidConfig(format!("OVF disk reference {} is not a plain file name", href)));
+    }
+
+    Ok(OvaManifest {
+        name: if name.is_empty() { system_id } else { name },
+        cpu,
+        memory_mb,
+        disk_files,
+        os_type,
+        network_adapters,
+        annotation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIRTUALBOX_OVF: &str = r#"<?xml version="1.0"?>
+<Envelope ovf:version="1.0" xmlns="http://schemas.dmtf.org/ovf/envelope/1" xmlns:ovf="http://schemas.dmtf.org/ovf/envelope/1" xmlns:rasd="http://schemas.dmtf.org/wbem/wscim/1/cim-schema/2/CIM_ResourceAllocationSettingData" xmlns:vbox="http://www.virtualbox.org/ovf/machine">
+  <References>
+    <File ovf:id="file1" ovf:href="ubuntu-disk001.vmdk"/>
+  </References>
+  <DiskSection>
+    <Info>List of the virtual disks used in the package</Info>
+    <Disk ovf:capacity="21474836480" ovf:diskId="vmdisk1" ovf:fileRef="file1" ovf:format="http://www.vmware.com/interfaces/specifications/vmdk.html#streamOptimized"/>
+  </DiskSection>
+  <VirtualSystem ovf:id="ubuntu">
+    <Info>A virtual machine</Info>
+    <OperatingSystemSection ovf:id="94">
+      <Info>The kind of installed guest operating system</Info>
+      <Description>Ubuntu_64</Description>
+      <vbox:OSType ovf:required="false">Ubuntu_64</vbox:OSType>
+    </OperatingSystemSection>
+    <AnnotationSection ovf:required="false">
+      <Info>A human-readable annotation</Info>
+      <Annotation>Build box &amp; CI runner</Annotation>
+    </AnnotationSection>
+    <VirtualHardwareSection>
+      <Info>Virtual hardware requirements for a virtual machine</Info>
+      <Item>
+        <rasd:Caption>2 virtual CPU</rasd:Caption>
+        <rasd:ResourceType>3</rasd:ResourceType>
+        <rasd:VirtualQuantity>2</rasd:VirtualQuantity>
+      </Item>
+      <Item>
+        <rasd:AllocationUnits>MegaBytes</rasd:AllocationUnits>
+        <rasd:ResourceType>4</rasd:ResourceType>
+        <rasd:VirtualQuantity>4096</rasd:VirtualQuantity>
+      </Item>
+      <Item>
+        <rasd:ResourceSubType>E1000</rasd:ResourceSubType>
+        <rasd:ResourceType>10</rasd:ResourceType>
+      </Item>
+      <Item>
+        <rasd:HostResource>/disk/vmdisk1</rasd:HostResource>
+        <rasd:ResourceType>17</rasd:ResourceType>
+      </Item>
+    </VirtualHardwareSection>
+  </VirtualSystem>
+</Envelope>"#;
+
+    #[test]
+    fn test_parse_ovf_virtualbox_export() {
+        let manifest = parse_ovf(VIRTUALBOX_OVF).expect("manifest should parse");
+
+        assert_eq!(manifest.name, "ubuntu");
+        assert_eq!(manifest.cpu, 2);
+        assert_eq!(manifest.memory_mb, 4096);
+        assert_eq!(manifest.disk_files, vec!["ubuntu-disk001.vmdk".to_string()]);
+        assert_eq!(manifest.os_type, "Ubuntu_64");
+        assert_eq!(manifest.network_adapters, vec!["E1000".to_string()]);
+        assert_eq!(manifest.annotation, "Build box & CI runner");
+    }
+
+    #[test]
+    fn test_parse_ovf_rejects_missing_disks() {
+        let xml = r#"<Envelope><VirtualSystem><Name>empty</Name></VirtualSystem></Envelope>"#;
+        assert!(parse_ovf(xml).is_err());
+    }
+
+    #[test]
+    fn test_parse_ovf_rejects_disk_paths() {
+        for href in ["../../etc/passwd", "/etc/passwd", "disks/a.vmdk", "..\\a.vmdk", ".."] {
+            let xml = VIRTUALBOX_OVF.replace("ubuntu-disk001.vmdk", href);
+            assert!(parse_ovf(&xml).is_err(), "{} should be rejected", href);
+        }
+    }
+
+    #[test]
+    fn test_memory_to_mb_units() {
+        assert_eq!(memory_to_mb(2048, "byte * 2^20"), 2048);
+        assert_eq!(memory_to_mb(4, "byte * 2^30"), 4096);
+        assert_eq!(memory_to_mb(1024, ""), 1024);
+    }
+
+    #[test]
+    fn test_map_os_type() {
+        assert_eq!(map_os_type("Ubuntu_64"), GuestOs::Linux);
+        assert_eq!(map_os_type("Oracle_64"), GuestOs::Linux);
+        assert_eq!(map_os_type("Windows10_64"), GuestOs::Windows);
+        assert_eq!(map_os_type("FreeBSD_64"), GuestOs::Bsd);
+        assert_eq!(map_os_type("MacOS_64"), GuestOs::MacOs);
+        assert_eq!(map_os_type("Other"), GuestOs::Other);
+    }
+
+    #[test]
+    fn test_parse_ova_reads_descriptor_from_tar() {
+        let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+        std::fs::write(temp_dir.path().join("ubuntu.ovf"), VIRTUALBOX_OVF).unwrap();
+        let ova = temp_dir.path().join("ubuntu.ova");
+
+        let status = Command::new("tar")
+            .arg("-cf")
+            .arg(&ova)
+            .arg("-C")
+            .arg(temp_dir.path())
+            .arg("ubuntu.ovf")
+            .status();
+        if !matches!(status, Ok(status) if status.success()) {
+            return; // tar unavailable on this host
+        }
+
+        let manifest = parse_ova(&ova).expect("OVA should parse");
+        assert_eq!(manifest.cpu, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_convert_rejects_symlinked_disk_entry() {
+        let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+        let staging = temp_dir.path().join("staging");
+        std::fs::create_dir(&staging).unwrap();
+        let host_file = temp_dir.path().join("id_rsa");
+        std::fs::write(&host_file, b"private key").unwrap();
+        std::os::unix::fs::symlink(&host_file, staging.join("ubuntu-disk001.vmdk")).unwrap();
+        let ova = temp_dir.path().join("ubuntu.ova");
+        let status = Command::new("tar")
+            .arg("-cf")
+            .arg(&ova)
+            .arg("-C")
+            .arg(&staging)
+            .arg("ubuntu-disk001.vmdk")
+            .status();
+        if !matches!(status, Ok(status) if status.success()) {
+            return; // tar unavailable on this host
+        }
+
+        let extracted = temp_dir.path().join("extracted");
+        extract_ova(&ova, &extracted).unwrap();
+        let manifest = parse_ovf(VIRTUALBOX_OVF).unwrap();
+        let err = convert_ova_disks(&manifest, &extracted, &temp_dir.path().join("disks")).unwrap_err();
+        assert!(err.to_string().contains("not a regular file"));
+    }
+}
@@ -0,0 +1,462 @@
+//! Platform-correct locations for OpenUTM's config, disks, logs and sockets
+//!
+//! Linux follows XDG (`~/.config`, `~/.local/share`, `~/.local/state`,
+//! `$XDG_RUNTIME_DIR`), macOS uses `~/Library`, and Windows uses the local
+//! AppData folder so multi-GB disk images never end up in a roaming profile.
+//! Older builds kept everything under `~/.openutm`; see [`plan_migration`].
+
+use crate::Result;
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "openutm";
+const LEGACY_DIR: &str = ".openutm";
+const DB_FILE: &str = "config.db";
+const DISKS_DIR: &str = "disks";
+const SPICE_TLS_DIR: &str = "spice-tls";
+const RECORDINGS_DIR: &str = "recordings";
+
+/// Per-user base directories as reported by the OS
+#[derive(Debug, Clone, Default)]
+pub struct BaseDirs {
+    pub home: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub data: Option<PathBuf>,
+    pub data_local: Option<PathBuf>,
+    pub state: Option<PathBuf>,
+    pub cache: Option<PathBuf>,
+    pub runtime: Option<PathBuf>,
+}
+
+impl BaseDirs {
+    pub fn detect() -> Self {
+        Self {
+            home: dirs::home_dir(),
+            config: dirs::config_dir(),
+            data: dirs::data_dir(),
+            data_local: dirs::data_local_dir(),
+            state: dirs::state_dir(),
+            cache: dirs::cache_dir(),
+            runtime: dirs::runtime_dir(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppPaths {
+    pub config_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub log_dir: PathBuf,
+    pub runtime_dir: PathBuf,
+}
+
+impl AppPaths {
+    pub fn resolve() -> Self {
+        Self::for_os(std::env::consts::OS, &BaseDirs::detect())
+    }
+
+    /// Select directories for `os` ("linux", "macos", "windows", ...) from the given bases
+    pub fn for_os(os: &str, bases: &BaseDirs) -> Self {
+        let fallback = std::env::temp_dir().join(APP_DIR);
+        let app_dir = |base: &Option<PathBuf>| base.as_ref().map(|base| base.join(APP_DIR));
+
+        match os {
+            "macos" => {
+                let support = app_dir(&bases.data).unwrap_or_else(|| fallback.clone());
+                Self {
+                    config_dir: support.clone(),
+                    data_dir: support,
+                    log_dir: bases
+                        .home
+                        .as_ref()
+                        .map(|home| home.join("Library").join("Logs").join(APP_DIR))
+                        .unwrap_or_else(|| fallback.join("logs")),
+                    runtime_dir: short_runtime_dir(),
+                }
+            }
+            "windows" => {
+                let local = app_dir(&bases.data_local).unwrap_or_else(|| fallback.clone());
+                Self {
+                    config_dir: app_dir(&bases.config).unwrap_or_else(|| local.clone()),
+                    data_dir: local.clone(),
+                    log_dir: local.join("logs"),
+                    runtime_dir: local.join("run"),
+                }
+            }
+            _ => {
+                let data_dir = app_dir(&bases.data).unwrap_or_else(|| fallback.clone());
+                Self {
+                    config_dir: app_dir(&bases.config).unwrap_or_else(|| fallback.clone()),
+                    log_dir: app_dir(&bases.state)
+                        .or_else(|| app_dir(&bases.cache))
+                        .map(|dir| dir.join("logs"))
+                        .unwrap_or_else(|| fallback.join("logs")),
+                    runtime_dir: match (app_dir(&bases.runtime), &bases.data) {
+                        (Some(runtime), _) => runtime,
+                        (None, Some(_)) => data_dir.join("run"),
+                        (None, None) => short_runtime_dir(),
+                    },
+                    data_dir,
+                }
+            }
+        }
+    }
+
+    /// The single-directory layout used before platform directories
+    pub fn legacy(legacy_dir: &Path, runtime_dir: PathBuf) -> Self {
+        Self {
+            config_dir: legacy_dir.to_path_buf(),
+            data_dir: legacy_dir.to_path_buf(),
+            log_dir: legacy_dir.join("logs"),
+            runtime_dir,
+        }
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.config_dir.join(DB_FILE)
+    }
+
+    pub fn disks_dir(&self) -> PathBuf {
+        self.data_dir.join(DISKS_DIR)
+    }
+
+    pub fn qmp_socket(&self, vm_id: &str) -> PathBuf {
+        self.runtime_dir.join(format!("qmp-{}.sock", vm_id))
+    }
+
+    /// QMP socket of the throwaway QEMU `run_benchmark` times
+    pub fn benchmark_socket(&self) -> PathBuf {
+        self.runtime_dir.join("qmp-benchmark.sock")
+    }
+
+    pub fn monitor_socket(&self, vm_id: &str) -> PathBuf {
+        self.runtime_dir.join(format!("monitor-{}.sock", vm_id))
+    }
+
+    /// Serial console of a headless VM
+    pub fn serial_socket(&self, vm_id: &str) -> PathBuf {
+        self.runtime_dir.join(format!("serial-{}.sock", vm_id))
+    }
+
+    /// Passphrase handed to QEMU for an encrypted disk while the VM runs
+    pub fn secret_file(&self, vm_id: &str) -> PathBuf {
+        self.runtime_dir.join(format!("secret-{}", vm_id))
+    }
+
+    /// SPICE password QEMU reads at startup; removed once the VM is up
+    pub fn spice_password_file(&self, vm_id: &str) -> PathBuf {
+        self.runtime_dir.join(format!("spice-{}", vm_id))
+    }
+
+    /// Connection file handed to one external viewer launch; removed when that viewer exits
+    pub fn viewer_file(&self, vm_id: &str, launch_id: &str) -> PathBuf {
+        self.runtime_dir.join(format!("viewer-{}-{}.vv", vm_id, launch_id))
+    }
+
+    /// SPICE CA plus one QEMU `x509-dir` per VM served over TLS
+    pub fn spice_tls_dir(&self) -> PathBuf {
+        self.data_dir.join(SPICE_TLS_DIR)
+    }
+
+    /// Finished display recordings, plus the frames of ones in progress
+    pub fn recordings_dir(&self) -> PathBuf {
+        self.data_dir.join(RECORDINGS_DIR)
+    }
+
+    pub fn ensure_dirs(&self) -> Result<()> {
+        for dir in [&self.config_dir, &self.disks_dir(), &self.log_dir, &self.runtime_dir] {
+            std::fs::create_dir_all(dir)?;
+        }
+        secure_runtime_dir(&self.runtime_dir)
+    }
+}
+
+/// Per-user socket directory directly under `/tmp`. A Unix socket path must fit
+/// in `sun_path` (104 bytes on macOS), which macOS's per-user `$TMPDIR` plus a
+/// VM id socket name overruns.
+#[cfg(unix)]
+fn short_runtime_dir() -> PathBuf {
+    PathBuf::from("/tmp").join(format!("{}-{}", APP_DIR, unsafe { libc::getuid() }))
+}
+
+#[cfg(not(unix))]
+fn short_runtime_dir() -> PathBuf {
+    std::env::temp_dir().join(APP_DIR)
+}
+
+/// The runtime dir holds sockets and secrets and may sit in shared `/tmp`, so
+/// it must belong to this user and be closed to everyone else
+#[cfg(unix)]
+fn secure_runtime_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } {
+        return Err(Error::PlatformError(format!("Runtime directory {} is not owned by this user", dir.display())));
+    }
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn secure_runtime_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+pub fn legacy_dir(bases: &BaseDirs) -> Option<PathBuf> {
+    bases.home.as_ref().map(|home| home.join(LEGACY_DIR))
+}
+
+/// Overlay an ephemeral VM writes to instead of its disk; deleted when the VM stops.
+/// It lives next to the disks, so `disks_dir` is the storage dir in use, migrated or not.
+pub fn ephemeral_overlay(disks_dir: &Path, vm_id: &str) -> PathBuf {
+    disks_dir.join(format!("ephemeral-{}.qcow2", vm_id))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MigrationMode {
+    Move,
+    Symlink,
+}
+
+impl MigrationMode {
+    pub fn parse(mode: &str) -> Result<Self> {
+        match mode {
+            "move" => Ok(Self::Move),
+            "symlink" => Ok(Self::Symlink),
+            _ => Err(Error::InvalidConfig("Migration mode must be move or symlink".to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationPlan {
+    pub legacy_dir: PathBuf,
+    /// `(legacy, new)` pairs; only entries that exist in the legacy dir
+    pub entries: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Work out what to relocate from a legacy `~/.openutm`, if anything.
+///
+/// Nothing is planned once the new config DB exists (already migrated or
+/// symlinked), or when the legacy dir has no config DB.
+pub fn plan_migration(legacy_dir: &Path, paths: &AppPaths) -> Option<MigrationPlan> {
+    let legacy_db = legacy_dir.join(DB_FILE);
+    if !legacy_db.is_file() || paths.db_path().symlink_metadata().is_ok() {
+        return None;
+    }
+
+    let mut entries = vec![(legacy_db, paths.db_path())];
+    let legacy_disks = legacy_dir.join(DISKS_DIR);
+    if legacy_disks.is_dir() {
+        entries.push((legacy_disks, paths.disks_dir()));
+    }
+
+    Some(MigrationPlan {
+        legacy_dir: legacy_dir.to_path_buf(),
+        entries,
+    })
+}
+
+/// Relocate legacy data. `Symlink` leaves files in place and links to them
+/// from the new locations, e.g. when disks live on a separate volume.
+pub fn apply_migration(plan: &MigrationPlan, mode: MigrationMode) -> Result<()> {
+    for (from, to) in &plan.entries {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // create_disk makes the new disks dir on first launch; an empty one is safe to replace
+        if to.is_dir() && std::fs::read_dir(to)?.next().is_none() {
+            std::fs::remove_dir(to)?;
+        }
+        if to.symlink_metadata().is_ok() {
+            return Err(Error::ConfigError(format!("{} already exists", to.display())));
+        }
+
+        match mode {
+            MigrationMode::Move => std::fs::rename(from, to).map_err(|e| {
+                Error::ConfigError(format!(
+                    "Failed to move {} to {}: {}; try symlinking instead",
+                    from.display(),
+                    to.display(),
+                    e
+                ))
+            })?,
+            MigrationMode::Symlink => symlink(from, to)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(from: &Path, to: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(from, to)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn symlink(from: &Path, to: &Path) -> Result<()> {
+    if from.is_dir() {
+        std::os::windows::fs::symlink_dir(from, to)?;
+    } else {
+        std::os::windows::fs::symlink_file(from, to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn bases(home: &str) -> BaseDirs {
+        let home = PathBuf::from(home);
+        BaseDirs {
+            config: Some(home.join(".config")),
+            data: Some(home.join(".local/share")),
+            data_local: Some(home.join(".local/share")),
+            state: Some(home.join(".local/state")),
+            cache: Some(home.join(".cache")),
+            runtime: Some(PathBuf::from("/run/user/1000")),
+            home: Some(home),
+        }
+    }
+
+    #[test]
+    fn test_linux_uses_xdg_dirs() {
+        let paths = AppPaths::for_os("linux", &bases("/home/u"));
+        assert_eq!(paths.db_path(), PathBuf::from("/home/u/.config/openutm/config.db"));
+        assert_eq!(paths.disks_dir(), PathBuf::from("/home/u/.local/share/openutm/disks"));
+        assert_eq!(paths.log_dir, PathBuf::from("/home/u/.local/state/openutm/logs"));
+        assert_eq!(paths.qmp_socket("vm-1"), PathBuf::from("/run/user/1000/openutm/qmp-vm-1.sock"));
+        assert_eq!(paths.monitor_socket("vm-1"), PathBuf::from("/run/user/1000/openutm/monitor-vm-1.sock"));
+        assert_eq!(paths.serial_socket("vm-1"), PathBuf::from("/run/user/1000/openutm/serial-vm-1.sock"));
+    }
+
+    #[test]
+    fn test_linux_without_state_or_runtime_dir() {
+        let mut bases = bases("/home/u");
+        bases.state = None;
+        bases.runtime = None;
+        let paths = AppPaths::for_os("linux", &bases);
+        assert_eq!(paths.log_dir, PathBuf::from("/home/u/.cache/openutm/logs"));
+        assert_eq!(paths.runtime_dir, PathBuf::from("/home/u/.local/share/openutm/run"));
+    }
+
+    #[test]
+    fn test_macos_uses_library() {
+        let home = PathBuf::from("/Users/u");
+        let bases = BaseDirs {
+            config: Some(home.join("Library/Application Support")),
+            data: Some(home.join("Library/Application Support")),
+            data_local: Some(home.join("Library/Application Support")),
+            cache: Some(home.join("Library/Caches")),
+            home: Some(home),
+            ..BaseDirs::default()
+        };
+        let paths = AppPaths::for_os("macos", &bases);
+        assert_eq!(paths.db_path(), PathBuf::from("/Users/u/Library/Application Support/openutm/config.db"));
+        assert_eq!(paths.disks_dir(), PathBuf::from("/Users/u/Library/Application Support/openutm/disks"));
+        assert_eq!(paths.log_dir, PathBuf::from("/Users/u/Library/Logs/openutm"));
+        // sun_path on macOS holds 104 bytes including the terminator
+        #[cfg(unix)]
+        assert!(paths.runtime_dir.starts_with("/tmp"));
+        let socket = paths.monitor_socket(&uuid::Uuid::new_v4().to_string());
+        assert!(socket.as_os_str().len() < 104, "{}", socket.display());
+    }
+
+    #[test]
+    fn test_windows_keeps_disks_out_of_roaming_profile() {
+        let bases = BaseDirs {
+            home: Some(PathBuf::from(r"C:\Users\u")),
+            config: Some(PathBuf::from(r"C:\Users\u\AppData\Roaming")),
+            data: Some(PathBuf::from(r"C:\Users\u\AppData\Roaming")),
+            data_local: Some(PathBuf::from(r"C:\Users\u\AppData\Local")),
+            cache: Some(PathBuf::from(r"C:\Users\u\AppData\Local")),
+            ..BaseDirs::default()
+        };
+        let paths = AppPaths::for_os("windows", &bases);
+        assert!(paths.config_dir.starts_with(r"C:\Users\u\AppData\Roaming"));
+        assert!(paths.disks_dir().starts_with(r"C:\Users\u\AppData\Local"));
+        assert!(paths.runtime_dir.starts_with(r"C:\Users\u\AppData\Local"));
+        assert!(!paths.runtime_dir.starts_with("/tmp"));
+    }
+
+    #[test]
+    fn test_missing_home_falls_back_to_temp_dir() {
+        let paths = AppPaths::for_os("linux", &BaseDirs::default());
+        assert!(paths.config_dir.starts_with(std::env::temp_dir()));
+        assert!(legacy_dir(&BaseDirs::default()).is_none());
+    }
+
+    #[test]
+    fn test_plan_migration_detects_legacy_layout() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".openutm");
+        std::fs::create_dir_all(legacy.join("disks")).unwrap();
+        std::fs::write(legacy.join("config.db"), b"db").unwrap();
+        let paths = AppPaths::for_os("linux", &bases(&temp_dir.path().display().to_string()));
+
+        let plan = plan_migration(&legacy, &paths).expect("migration needed");
+        assert_eq!(plan.entries.len(), 2);
+        assert_eq!(plan.entries[0], (legacy.join("config.db"), paths.db_path()));
+        assert_eq!(plan.entries[1], (legacy.join("disks"), paths.disks_dir()));
+    }
+
+    #[test]
+    fn test_plan_migration_skips_when_nothing_to_do() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".openutm");
+        let paths = AppPaths::for_os("linux", &bases(&temp_dir.path().display().to_string()));
+        assert!(plan_migration(&legacy, &paths).is_none());
+
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("config.db"), b"db").unwrap();
+        std::fs::create_dir_all(&paths.config_dir).unwrap();
+        std::fs::write(paths.db_path(), b"new").unwrap();
+        assert!(plan_migration(&legacy, &paths).is_none());
+    }
+
+    #[test]
+    fn test_apply_migration_move() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".openutm");
+        std::fs::create_dir_all(legacy.join("disks")).unwrap();
+        std::fs::write(legacy.join("config.db"), b"db").unwrap();
+        std::fs::write(legacy.join("disks/vm-1.qcow2"), b"disk").unwrap();
+        let paths = AppPaths::for_os("linux", &bases(&temp_dir.path().display().to_string()));
+        std::fs::create_dir_all(paths.disks_dir()).unwrap();
+
+        let plan = plan_migration(&legacy, &paths).unwrap();
+        apply_migration(&plan, MigrationMode::Move).unwrap();
+
+        assert_eq!(std::fs::read(paths.db_path()).unwrap(), b"db");
+        assert!(paths.disks_dir().join("vm-1.qcow2").is_file());
+        assert!(!legacy.join("config.db").exists());
+        assert!(plan_migration(&legacy, &paths).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_migration_symlink() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".openutm");
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("config.db"), b"db").unwrap();
+        let paths = AppPaths::for_os("linux", &bases(&temp_dir.path().display().to_string()));
+
+        let plan = plan_migration(&legacy, &paths).unwrap();
+        apply_migration(&plan, MigrationMode::Symlink).unwrap();
+
+        assert!(paths.db_path().symlink_metadata().unwrap().file_type().is_symlink());
+        assert!(legacy.join("config.db").is_file());
+        assert!(plan_migration(&legacy, &paths).is_none());
+    }
+
+    #[test]
+    fn test_migration_mode_parse() {
+        assert_eq!(MigrationMode::parse("move").unwrap(), MigrationMode::Move);
+        assert_eq!(MigrationMode::parse("symlink").unwrap(), MigrationMode::Symlink);
+        assert!(MigrationMode::parse("copy").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use super::HugePages;
+use crate::qemu::VfioPciDevice;
+use crate::{PlatformInfo, Result};
+
+pub fn get_platform_info() -> Result<PlatformInfo> {
+    Ok(super::host_platform_info("linux", has_kvm().then(|| "kvm".to_string())))
+}
+
+pub fn has_kvm() -> bool {
+    std::path::Path::new("/dev/kvm").exists()
+}
+
+/// Return the CPU feature flag to expose for nested virtualization, if the
+/// loaded KVM module has nesting enabled.
+pub fn nested_virt_flag() -> Option<&'static str> {
+    let modules = [
+        ("/sys/module/kvm_intel/parameters/nested", "vmx"),
+        ("/sys/module/kvm_amd/parameters/nested", "svm"),
+    ];
+
+    modules.iter().find_map(|(param, flag)| {
+        std::fs::read_to_string(param)
+            .ok()
+            .filter(|value| parse_nested_param(value))
+            .map(|_| *flag)
+    })
+}
+
+/// Pin a process to the given host cores via `sched_setaffinity`
+#[cfg(target_os = "linux")]
+pub fn set_process_affinity(pid: u32, cores: &[u32]) -> Result<()> {
+    use nix::sched::{sched_setaffinity, CpuSet};
+    use nix::unistd::Pid;
+
+    let mut cpu_set = CpuSet::new();
+    for core in cores {
+        cpu_set.set(*core as usize)?;
+    }
+    sched_setaffinity(Pid::from_raw(pid as i32), &cpu_set)?;
+    Ok(())
+}
+
+/// Host cores a process is currently allowed to run on
+#[cfg(target_os = "linux")]
+pub fn process_affinity(pid: u32, logical_cpus: u32) -> Result<Vec<u32>> {
+    use nix::sched::sched_getaffinity;
+    use nix::unistd::Pid;
+
+    let cpu_set = sched_getaffinity(Pid::from_raw(pid as i32))?;
+    Ok((0..logical_cpus)
+        .filter(|core| cpu_set.is_set(*core as usize).unwrap_or(false))
+        .collect())
+}
+
+/// Name of the host interface carrying a VLAN, e.g. `vlan100`
+pub fn vlan_interface_name(vlan_id: u16) -> String {
+    format!("vlan{}", vlan_id)
+}
+
+/// Bridge VMs on a VLAN are attached to, with `vlan<id>` as its uplink member
+pub fn vlan_bridge_name(vlan_id: u16) -> String {
+    format!("br-vlan{}", vlan_id)
+}
+
+/// Create the `vlan<id>` interface on the default-route uplink and the
+/// `br-vlan<id>` bridge over it, keeping whichever already exist. Returns the bridge.
+pub fn create_vlan_bridge(vlan_id: u16) -> Result<String> {
+    let name = vlan_interface_name(vlan_id);
+    if !interface_exists(&name) {
+        let routes = run_ip(&["route", "show", "default"])?;
+        let parent = parse_default_interface(&routes).ok_or_else(|| {
+            crate::Error::PlatformError("No default network interface to carry the VLAN".to_string())
+        })?;
+        let vlan_id = vlan_id.to_string();
+        run_ip(&["link", "add", "link", &parent, "name", &name, "type", "vlan", "id", &vlan_id])?;
+    }
+
+    let bridge = vlan_bridge_name(vlan_id);
+    if !interface_exists(&bridge) {
+        run_ip(&["link", "add", "name", &bridge, "type", "bridge"])?;
+    }
+    run_ip(&["link", "set", &name, "master", &bridge])?;
+    run_ip(&["link", "set", &name, "up"])?;
+    run_ip(&["link", "set", &bridge, "up"])?;
+    Ok(bridge)
+}
+
+/// (Re)create persistent TAP `tap` for this user on `bridge`. QEMU only opens
+/// a TAP whose multiqueue flag matches its own, so it is rebuilt every launch.
+#[cfg(target_os = "linux")]
+pub fn attach_vlan_tap(bridge: &str, tap: &str, multi_queue: bool) -> Result<()> {
+    if interface_exists(tap) {
+        run_ip(&["link", "del", tap])?;
+    }
+    let uid = unsafe { libc::getuid() }.to_string();
+    let mut add = vec!["tuntap", "add", "dev", tap, "mode", "tap", "user", &uid];
+    if multi_queue {
+        add.push("multi_queue");
+    }
+    run_ip(&add)?;
+    run_ip(&["link", "set", tap, "master", bridge])?;
+    run_ip(&["link", "set", tap, "up"])?;
+    Ok(())
+}
+
+fn interface_exists(name: &str) -> bool {
+    std::path::Path::new("/sys/class/net").join(name).exists()
+}
+
+fn run_ip(args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("ip").args(args).output()?;
+    if !output.status.success() {
+        return Err(crate::Error::PlatformError(format!(
+            "ip {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Interface of the first default route in `ip route show default` output
+fn parse_default_interface(routes: &str) -> Option<String> {
+    routes.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("default") {
+            return None;
+        }
+        words.skip_while(|word| *word != "dev").nth(1).map(str::to_string)
+    })
+}
+
+const VFIO_DRIVER_DIR: &str = "/sys/bus/pci/drivers/vfio-pci";
+
+/// PCI devices currently bound to `vfio-pci`; empty if the driver is not loaded
+pub fn list_vfio_devices() -> Result<Vec<VfioPciDevice>> {
+    vfio_devices_in(std::path::Path::new(VFIO_DRIVER_DIR))
+}
+
+/// Bound devices appear as address-named links next to `bind`, `new_id` and friends
+fn vfio_devices_in(driver_dir: &std::path::Path) -> Result<Vec<VfioPciDevice>> {
+    let entries = match std::fs::read_dir(driver_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut devices = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| VfioPciDevice::parse(&entry.file_name().to_string_lossy()))
+        .collect::<Vec<_>>();
+    devices.sort_by_key(|device| device.to_string());
+    Ok(devices)
+}
+
+/// Vendor and model from `lspci`, e.g. `VGA compatible controller: NVIDIA Corporation ...`
+pub fn pci_device_name(device: &VfioPciDevice) -> Option<String> {
+    let output = std::process::Command::new("lspci")
+        .args(["-s", &device.to_string()])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    parse_lspci_name(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Drop the leading address from the first `lspci` line
+fn parse_lspci_name(output: &str) -> Option<String> {
+    let (_, name) = output.lines().next()?.split_once(' ')?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+pub fn hugepages() -> Option<HugePages> {
+    parse_meminfo_hugepages(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+/// Read the default huge page pool out of `/proc/meminfo`
+fn parse_meminfo_hugepages(meminfo: &str) -> Option<HugePages> {
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            value.split_whitespace().next()?.parse::<u64>().ok()
+        })
+    };
+    Some(HugePages {
+        total: field("HugePages_Total")?,
+        free: field("HugePages_Free")?,
+        page_size_kb: field("Hugepagesize")?,
+    })
+}
+
+pub fn kernel_version() -> Option<(u32, u32)> {
+    parse_kernel_release(&std::fs::read_to_string("/proc/sys/kernel/osrelease").ok()?)
+}
+
+/// `6.8.0-45-generic` -> `(6, 8)`
+fn parse_kernel_release(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.trim().split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+fn parse_nested_param(value: &str) -> bool {
+    matches!(value.trim(), "Y" | "y" | "1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vfio_devices_in_driver_dir() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in ["0000:01:00.1", "bind", "new_id", "0000:01:00.0", "module"] {
+            std::fs::write(temp_dir.path().join(name), "").unwrap();
+        }
+
+        let devices = vfio_devices_in(temp_dir.path()).unwrap();
+        let addresses: Vec<String> = devices.iter().map(|device| device.to_string()).collect();
+        assert_eq!(addresses, vec!["0000:01:00.0", "0000:01:00.1"]);
+
+        assert!(vfio_devices_in(&temp_dir.path().join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_lspci_name() {
+        let output = "01:00.0 VGA compatible controller: NVIDIA Corporation GA104 [GeForce RTX 3070] (rev a1)\n";
+        assert_eq!(
+            parse_lspci_name(output).as_deref(),
+            Some("VGA compatible controller: NVIDIA Corporation GA104 [GeForce RTX 3070] (rev a1)")
+        );
+        assert_eq!(parse_lspci_name(""), None);
+    }
+
+    #[test]
+    fn test_parse_kernel_release() {
+        assert_eq!(parse_kernel_release("6.8.0-45-generic\n"), Some((6, 8)));
+        assert_eq!(parse_kernel_release("5.10.1-fc-v130"), Some((5, 10)));
+        assert_eq!(parse_kernel_release("4.19"), Some((4, 19)));
+        assert_eq!(parse_kernel_release("6"), None);
+        assert_eq!(parse_kernel_release(""), None);
+    }
+
+    #[test]
+    fn test_parse_nested_param() {
+        assert!(parse_nested_param("Y\n"));
+        assert!(parse_nested_param("1"));
+        assert!(!parse_nested_param("N\n"));
+        assert!(!parse_nested_param("0"));
+    }
+
+    #[test]
+    fn test_parse_meminfo_hugepages() {
+        let meminfo = "MemTotal:       32768000 kB\nHugePages_Total:    1024\nHugePages_Free:      512\n\
+                       HugePages_Rsvd:        0\nHugepagesize:       2048 kB\n";
+        assert_eq!(
+            parse_meminfo_hugepages(meminfo),
+            Some(HugePages { total: 1024, free: 512, page_size_kb: 2048 })
+        );
+        assert_eq!(parse_meminfo_hugepages("MemTotal:       32768000 kB\n"), None);
+    }
+
+    #[test]
+    fn test_parse_default_interface() {
+        let routes = "default via 192.168.1.1 dev enp3s0 proto dhcp metric 100\ndefault via 10.0.0.1 dev wlan0 metric 600\n";
+        assert_eq!(parse_default_interface(routes), Some("enp3s0".to_string()));
+        assert_eq!(parse_default_interface("10.0.0.0/24 dev eth0 scope link\n"), None);
+        assert_eq!(parse_default_interface(""), None);
+    }
+
+    #[test]
+    fn test_vlan_interface_names() {
+        assert_eq!(vlan_interface_name(42), "vlan42");
+        assert_eq!(vlan_bridge_name(42), "br-vlan42");
+    }
+}
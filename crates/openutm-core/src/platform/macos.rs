@@ -0,0 +1,39 @@
+use crate::{PlatformInfo, Result};
+
+pub fn get_platform_info() -> Result<PlatformInfo> {
+    Ok(super::host_platform_info("macos", has_hvf().then(|| "hvf".to_string())))
+}
+
+pub fn has_hvf() -> bool {
+    std::process::Command::new("sysctl")
+        .args(["hw.optional.hv"])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Nested virtualization is not exposed by Hypervisor.framework.
+pub fn nested_virt_flag() -> Option<&'static str> {
+    None
+}
+
+/// Native machine architecture, seeing through Rosetta translation.
+pub fn host_arch() -> String {
+    let translated = std::process::Command::new("sysctl")
+        .args(["-n", "sysctl.proc_translated"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+        .unwrap_or(false);
+    if translated {
+        return "aarch64".to_string();
+    }
+
+    std::process::Command::new("sysctl")
+        .args(["-n", "hw.machine"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|machine| !machine.is_empty())
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
@@ -0,0 +1,305 @@
+pub mod macos;
+pub mod linux;
+pub mod windows;
+
+use crate::{HostResources, PlatformInfo, Result, VfioDeviceInfo};
+
+/// Describe the host: OS, architecture, accelerator and resources.
+///
+/// `free_disk_mb` and `qemu_path` depend on app state and are left for the caller to fill.
+pub fn get_platform_info() -> Result<PlatformInfo> {
+    #[cfg(target_os = "macos")]
+    return macos::get_platform_info();
+
+    #[cfg(target_os = "linux")]
+    return linux::get_platform_info();
+
+    #[cfg(target_os = "windows")]
+    return windows::get_platform_info();
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    return Ok(host_platform_info(std::env::consts::OS, None));
+}
+
+fn host_platform_info(os: &str, accelerator: Option<String>) -> PlatformInfo {
+    let resources = host_resources();
+    PlatformInfo {
+        os: os.to_string(),
+        arch: host_arch(),
+        accelerator,
+        cpu_count: resources.logical_cpus,
+        total_ram_mb: resources.total_memory_mb,
+        free_disk_mb: 0,
+        qemu_path: None,
+    }
+}
+
+/// Detect if hypervisor acceleration is available
+pub fn has_acceleration() -> bool {
+    #[cfg(target_os = "macos")]
+    return macos::has_hvf();
+
+    #[cfg(target_os = "linux")]
+    return linux::has_kvm();
+
+    #[cfg(target_os = "windows")]
+    return windows::has_whpx();
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    false
+}
+
+/// CPU flag (`vmx`/`svm`) to expose to guests when the host allows nested virtualization
+pub fn nested_virt_flag() -> Option<&'static str> {
+    #[cfg(target_os = "macos")]
+    return macos::nested_virt_flag();
+
+    #[cfg(target_os = "linux")]
+    return linux::nested_virt_flag();
+
+    #[cfg(target_os = "windows")]
+    return windows::nested_virt_flag();
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    None
+}
+
+/// Pin a QEMU process to host cores. Best-effort: only Linux supports it.
+pub fn set_process_affinity(pid: u32, cores: &[u32]) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    return linux::set_process_affinity(pid, cores);
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (pid, cores);
+        Ok(())
+    }
+}
+
+/// Make sure the bridge for `vlan_id` exists and TAP `tap` is plugged into it,
+/// returning the bridge's name
+pub fn ensure_vlan_interface(vlan_id: u16, tap: &str, multi_queue: bool) -> Result<String> {
+    #[cfg(target_os = "linux")]
+    return linux::create_vlan_bridge(vlan_id).and_then(|bridge| {
+        linux::attach_vlan_tap(&bridge, tap, multi_queue)?;
+        Ok(bridge)
+    });
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (vlan_id, tap, multi_queue);
+        Err(crate::Error::PlatformError(
+            "VLAN networking is only supported on Linux".to_string(),
+        ))
+    }
+}
+
+/// Host PCI devices bound to `vfio-pci`, with a readable name when `lspci` knows one.
+/// VFIO is Linux-only, so other hosts report none.
+pub fn list_vfio_devices() -> Result<Vec<VfioDeviceInfo>> {
+    #[cfg(target_os = "linux")]
+    return Ok(linux::list_vfio_devices()?
+        .into_iter()
+        .map(|device| VfioDeviceInfo {
+            address: device.to_string(),
+            name: linux::pci_device_name(&device).unwrap_or_else(|| device.to_string()),
+            device,
+        })
+        .collect());
+
+    #[cfg(not(target_os = "linux"))]
+    Ok(Vec::new())
+}
+
+/// Whether `set_process_affinity` actually pins on this platform
+pub fn supports_cpu_affinity() -> bool {
+    cfg!(target_os = "linux")
+}
+
+/// Effective core list of a running process, if the platform can report it
+pub fn process_affinity(pid: u32) -> Option<Vec<u32>> {
+    #[cfg(target_os = "linux")]
+    return linux::process_affinity(pid, host_resources().logical_cpus).ok();
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
+/// Hex CPU mask for a core list, in the same form `taskset` prints (`[0, 2, 3]` -> `d`)
+pub fn affinity_mask(cores: &[u32]) -> String {
+    let words = cores.iter().map(|core| *core as usize / 64 + 1).max().unwrap_or(1);
+    let mut mask = vec![0u64; words];
+    for core in cores {
+        mask[*core as usize / 64] |= 1 << (core % 64);
+    }
+
+    let mut hex = format!("{:x}", mask[words - 1]);
+    for word in mask[..words - 1].iter().rev() {
+        hex.push_str(&format!("{:016x}", word));
+    }
+    hex
+}
+
+/// Set the scheduling niceness (-20..=19) of a process
+pub fn set_process_priority(pid: u32, niceness: i32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        // SAFETY: setpriority has no memory-safety preconditions.
+        let rc = unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, niceness) };
+        if rc != 0 {
+            return Err(crate::Error::PlatformError(format!(
+                "setpriority failed: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (pid, niceness);
+        Err(crate::Error::PlatformError(
+            "Process priority is unsupported on this platform".to_string(),
+        ))
+    }
+}
+
+/// The host's huge page pool, from `/proc/meminfo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HugePages {
+    /// Pages reserved, e.g. with `vm.nr_hugepages`
+    pub total: u64,
+    /// Reserved pages not in use
+    pub free: u64,
+    pub page_size_kb: u64,
+}
+
+/// The huge page pool; `None` where guest RAM cannot be backed by huge pages
+pub fn hugepages() -> Option<HugePages> {
+    #[cfg(target_os = "linux")]
+    return linux::hugepages();
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// Running kernel as `(major, minor)`; `None` off Linux or when it cannot be read
+pub fn kernel_version() -> Option<(u32, u32)> {
+    #[cfg(target_os = "linux")]
+    return linux::kernel_version();
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// Whether guest RAM can use a memory backend other than the default
+pub fn supports_memory_backends() -> bool {
+    cfg!(target_os = "linux")
+}
+
+/// Host CPU and memory totals
+pub fn host_resources() -> HostResources {
+    let mut system = sysinfo::System::new();
+    system.refresh_cpu();
+    system.refresh_memory();
+
+    HostResources {
+        logical_cpus: system.cpus().len() as u32,
+        total_memory_mb: system.total_memory() / (1024 * 1024),
+        available_memory_mb: system.available_memory() / (1024 * 1024),
+    }
+}
+
+/// Native host CPU architecture, normalized to QEMU naming (`x86_64`, `aarch64`)
+pub fn host_arch() -> String {
+    static HOST_ARCH: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+    HOST_ARCH
+        .get_or_init(|| {
+            #[cfg(target_os = "macos")]
+            let arch = macos::host_arch();
+
+            #[cfg(not(target_os = "macos"))]
+            let arch = std::env::consts::ARCH.to_string();
+
+            normalize_arch(&arch).to_string()
+        })
+        .clone()
+}
+
+/// Map platform architecture names onto QEMU's
+pub fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "arm64" => "aarch64",
+        "amd64" | "x64" => "x86_64",
+        other => other,
+    }
+}
+
+/// Whether a guest of `guest_arch` has to be emulated (TCG) on this host
+pub fn is_emulated(guest_arch: &str, host_arch: &str) -> bool {
+    normalize_arch(guest_arch) != normalize_arch(host_arch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_platform_info_serializes_camel_case_keys() {
+        let info = PlatformInfo {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            accelerator: Some("kvm".to_string()),
+            cpu_count: 8,
+            total_ram_mb: 16384,
+            free_disk_mb: 51200,
+            qemu_path: Some("/usr/bin/qemu-system-x86_64".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            serde_json::json!({
+                "os": "linux",
+                "arch": "x86_64",
+                "accelerator": "kvm",
+                "cpuCount": 8,
+                "totalRamMb": 16384,
+                "freeDiskMb": 51200,
+                "qemuPath": "/usr/bin/qemu-system-x86_64",
+            })
+        );
+    }
+
+    #[test]
+    fn test_host_platform_info_reports_host() {
+        let info = host_platform_info("linux", None);
+        assert_eq!(info.arch, host_arch());
+        assert!(info.cpu_count > 0);
+        assert_eq!(info.qemu_path, None);
+    }
+
+    #[test]
+    fn test_affinity_mask() {
+        assert_eq!(affinity_mask(&[0, 2, 3]), "d");
+        assert_eq!(affinity_mask(&[]), "0");
+        assert_eq!(affinity_mask(&[64]), "10000000000000000");
+        assert_eq!(affinity_mask(&[1, 65]), "20000000000000002");
+    }
+
+    #[test]
+    fn test_normalize_arch() {
+        assert_eq!(normalize_arch("arm64"), "aarch64");
+        assert_eq!(normalize_arch("amd64"), "x86_64");
+        assert_eq!(normalize_arch("x86_64"), "x86_64");
+    }
+
+    #[test]
+    fn test_is_emulated_cross_arch() {
+        assert!(is_emulated("x86_64", "arm64"));
+        assert!(!is_emulated("aarch64", "arm64"));
+        assert!(!is_emulated("x86_64", "x86_64"));
+    }
+}
@@ -0,0 +1,14 @@
+use crate::{PlatformInfo, Result};
+
+pub fn get_platform_info() -> Result<PlatformInfo> {
+    Ok(super::host_platform_info("windows", has_whpx().then(|| "whpx".to_string())))
+}
+
+pub fn has_whpx() -> bool {
+    std::path::Path::new("\\\\.\\Global\\WHPX").exists()
+}
+
+/// Nested virtualization is not supported through WHPX.
+pub fn nested_virt_flag() -> Option<&'static str> {
+    None
+}
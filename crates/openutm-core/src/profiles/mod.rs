@@ -0,0 +1,145 @@
+//! Named VM profiles for the creation wizard
+//!
+//! A profile is a partial `VMConfig` stored as a JSON object: it only holds the
+//! fields it wants to preset. Creating a VM layers the caller's overrides over
+//! the profile, and the profile over the hardcoded defaults below.
+
+use serde_json::{json, Map, Value};
+
+use crate::config::ProfileRecord;
+use crate::VMConfig;
+
+/// Profiles seeded into every database; they cannot be edited or deleted
+pub fn builtin_profiles() -> Vec<ProfileRecord> {
+    let builtins = [
+        (
+            "builtin-linux-desktop",
+            "Linux desktop",
+            json!({
+                "os": "linux",
+                "memory_mb": 4096,
+                "cpu_cores": 2,
+                "disk_size_gb": 40,
+                "graphics": "virtio-vga",
+                "clipboard_sharing": true,
+            }),
+        ),
+        (
+            "builtin-windows-11",
+            "Windows 11",
+            json!({
+                "os": "windows",
+                "memory_mb": 8192,
+                "cpu_cores": 4,
+                "disk_size_gb": 64,
+                "graphics": "qxl",
+                "clipboard_sharing": true,
+                "display_resolution": "1920x1080",
+            }),
+        ),
+        (
+            "builtin-minimal-server",
+            "Minimal server",
+            json!({
+                "os": "linux",
+                "memory_mb": 1024,
+                "cpu_cores": 1,
+                "disk_size_gb": 10,
+                "graphics": "std",
+                "restart_policy": "always",
+            }),
+        ),
+    ];
+
+    builtins
+        .into_iter()
+        .map(|(id, name, config)| ProfileRecord {
+            id: id.to_string(),
+            name: name.to_string(),
+            builtin: true,
+            config: into_object(config),
+        })
+        .collect()
+}
+
+/// Values used for anything neither the profile nor the overrides set.
+///
+/// Optional `VMConfig` fields fall back to their serde defaults.
+fn hardcoded_defaults() -> Map<String, Value> {
+    into_object(json!({
+        "os": "linux",
+        "memory_mb": 2048,
+        "cpu_cores": 2,
+        "disk_size_gb": 20,
+    }))
+}
+
+fn into_object(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Build a VM config from `name`, with `overrides` beating `profile` beating the defaults.
+///
+/// The name is always taken from the argument, never from the profile or overrides.
+pub fn merge_config(
+    name: &str,
+    profile: &Map<String, Value>,
+    overrides: &Map<String, Value>,
+) -> serde_json::Result<VMConfig> {
+    let mut merged = hardcoded_defaults();
+    merged.extend(profile.iter().map(|(key, value)| (key.clone(), value.clone())));
+    merged.extend(overrides.iter().map(|(key, value)| (key.clone(), value.clone())));
+    merged.insert("name".to_string(), Value::String(name.to_string()));
+    serde_json::from_value(Value::Object(merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guest::GuestOs;
+
+    #[test]
+    fn test_merge_precedence() {
+        let profile = into_object(json!({ "memory_mb": 4096, "cpu_cores": 4, "graphics": "qxl" }));
+        let overrides = into_object(json!({ "cpu_cores": 8, "name": "ignored" }));
+        let config = merge_config("dev box", &profile, &overrides).unwrap();
+
+        assert_eq!(config.name, "dev box");
+        // Override beats profile
+        assert_eq!(config.cpu_cores, 8);
+        // Profile beats hardcoded default
+        assert_eq!(config.memory_mb, 4096);
+        assert_eq!(config.graphics.as_deref(), Some("qxl"));
+        // Hardcoded and serde defaults fill the rest
+        assert_eq!(config.disk_size_gb, 20);
+        assert_eq!(config.os, GuestOs::Linux);
+        assert_eq!(config.boot_order, "disk-first");
+    }
+
+    #[test]
+    fn test_override_can_clear_profile_value() {
+        let profile = into_object(json!({ "display_resolution": "1920x1080" }));
+        let overrides = into_object(json!({ "display_resolution": null }));
+        let config = merge_config("vm", &profile, &overrides).unwrap();
+        assert_eq!(config.display_resolution, None);
+    }
+
+    #[test]
+    fn test_merge_rejects_mistyped_values() {
+        let overrides = into_object(json!({ "memory_mb": "lots" }));
+        assert!(merge_config("vm", &Map::new(), &overrides).is_err());
+    }
+
+    #[test]
+    fn test_builtin_profiles_are_valid_configs() {
+        let builtins = builtin_profiles();
+        assert_eq!(builtins.len(), 3);
+        for profile in builtins {
+            assert!(profile.builtin);
+            merge_config(&profile.name, &profile.config, &Map::new()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Background disk creation for new VMs
+//!
+//! `service::create_vm` stores a new VM as `creating` and hands its disk to
+//! the `Provisioner`, so the VM is listed at once and creating several VMs
+//! does not wait on each `qemu-img create` in turn. At most `limit` disks are
+//! made at a time; the rest queue. A VM turns `stopped` once its disk is
+//! ready, and one whose disk could not be made is removed again. Every step
+//! goes out as `UiEvent::Provisioning`.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::ConfigStore;
+use crate::storage::{DiskManager, DiskSecret};
+use crate::{ProvisioningJob, ProvisioningState, VmChangeKind, VmListChanged, VmStatusEvent};
+
+use crate::app::UiEvent;
+
+/// Disks made at once; preallocating ones are bound by the host disk, so more rarely helps
+pub const MAX_DISK_JOBS: usize = 2;
+
+/// How often a running job's image size is sampled for progress
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// The disk a job makes; the passphrase is dropped when the job ends
+pub struct DiskRequest {
+    pub vm_id: String,
+    pub size_gb: u32,
+    pub preallocation: String,
+    pub key_ref: Option<String>,
+    pub passphrase: Option<String>,
+}
+
+pub type DiskFuture<'a, T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'a>>;
+
+/// Makes and removes VM disk images; `DiskManager` outside tests
+pub trait DiskBackend: Send + Sync {
+    /// Make the image and return its path
+    fn create_disk<'a>(&'a self, request: &'a DiskRequest) -> DiskFuture<'a, String>;
+    fn delete_disk<'a>(&'a self, vm_id: &'a str) -> DiskFuture<'a, ()>;
+    /// Current size of the VM's image, once it exists
+    fn written_bytes(&self, vm_id: &str) -> Option<u64>;
+}
+
+impl DiskBackend for DiskManager {
+    fn create_disk<'a>(&'a self, request: &'a DiskRequest) -> DiskFuture<'a, String> {
+        Box::pin(async move {
+            let secret = request
+                .key_ref
+                .as_deref()
+                .zip(request.passphrase.as_deref())
+                .map(|(key_ref, passphrase)| DiskSecret { key_ref, passphrase });
+            DiskManager::create_disk(self, &request.vm_id, request.size_gb, &request.preallocation, secret.as_ref())
+                .await
+        })
+    }
+
+    fn delete_disk<'a>(&'a self, vm_id: &'a str) -> DiskFuture<'a, ()> {
+        Box::pin(DiskManager::delete_disk(self, vm_id))
+    }
+
+    fn written_bytes(&self, vm_id: &str) -> Option<u64> {
+        std::fs::metadata(self.default_disk_path(vm_id)).ok().map(|metadata| metadata.len())
+    }
+}
+
+/// Queued and running disk jobs, shared with their tasks
+#[derive(Clone)]
+struct JobBoard {
+    jobs: Arc<Mutex<BTreeMap<String, ProvisioningJob>>>,
+    events: tokio::sync::broadcast::Sender<UiEvent>,
+}
+
+impl JobBoard {
+    fn update(&self, vm_id: &str, change: impl FnOnce(&mut ProvisioningJob)) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.get_mut(vm_id) {
+            change(job);
+            // Fails only when nothing is subscribed, e.g. before the window exists
+            let _ = self.events.send(UiEvent::Provisioning(job.clone()));
+        }
+    }
+
+    fn finish(&self, vm_id: &str, state: ProvisioningState, error: Option<String>) {
+        let removed = self.jobs.lock().unwrap_or_else(|e| e.into_inner()).remove(vm_id);
+        if let Some(job) = removed {
+            let _ = self.events.send(UiEvent::Provisioning(ProvisioningJob { state, error, ..job }));
+        }
+    }
+}
+
+pub struct Provisioner {
+    backend: Arc<dyn DiskBackend>,
+    permits: Arc<tokio::sync::Semaphore>,
+    jobs: Arc<Mutex<BTreeMap<String, ProvisioningJob>>>,
+}
+
+impl Provisioner {
+    pub fn new(backend: Arc<dyn DiskBackend>, limit: usize) -> Self {
+        Self {
+            backend,
+            permits: Arc::new(tokio::sync::Semaphore::new(limit.max(1))),
+            jobs: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Jobs queued or running, by VM id
+    pub fn jobs(&self) -> Vec<ProvisioningJob> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).values().cloned().collect()
+    }
+
+    pub fn is_provisioning(&self, vm_id: &str) -> bool {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).contains_key(vm_id)
+    }
+
+    /// Make the disk of `vm_name`, already stored in `store` as `creating`,
+    /// in the background
+    pub fn submit(
+        &self,
+        vm_name: &str,
+        request: DiskRequest,
+        store: ConfigStore,
+        events: tokio::sync::broadcast::Sender<UiEvent>,
+    ) -> tokio::task::JoinHandle<()> {
+        let vm_id = request.vm_id.clone();
+        let board = JobBoard { jobs: self.jobs.clone(), events };
+        let job = ProvisioningJob {
+            vm_id: vm_id.clone(),
+            vm_name: vm_name.to_string(),
+            state: ProvisioningState::Queued,
+            written_bytes: 0,
+            total_bytes: u64::from(request.size_gb) * GIB,
+            error: None,
+        };
+        board.jobs.lock().unwrap_or_else(|e| e.into_inner()).insert(vm_id.clone(), job.clone());
+        let _ = board.events.send(UiEvent::Provisioning(job));
+
+        let (backend, permits) = (self.backend.clone(), self.permits.clone());
+        tokio::spawn(async move {
+            let _permit = permits.acquire_owned().await.expect("provisioning semaphore is never closed");
+            board.update(&vm_id, |job| job.state = ProvisioningState::Creating);
+
+            let mut creation = backend.create_disk(&request);
+            let mut ticks = tokio::time::interval(PROGRESS_INTERVAL);
+            let outcome = loop {
+                tokio::select! {
+                    outcome = &mut creation => break outcome,
+                    _ = ticks.tick() => {
+                        if let Some(bytes) = backend.written_bytes(&vm_id) {
+                            board.update(&vm_id, |job| job.written_bytes = bytes);
+                        }
+                    }
+                }
+            };
+            drop(creation);
+
+            match outcome {
+                Ok(_) => {
+                    if !mark_ready(&store, &board.events, &vm_id) {
+                        // Deleted while its disk was being made
+                        let _ = backend.delete_disk(&vm_id).await;
+                    }
+                    board.finish(&vm_id, ProvisioningState::Done, None);
+                }
+                Err(err) => {
+                    tracing::error!(vm_id = %vm_id, error = %err, "disk creation failed");
+                    let _ = backend.delete_disk(&vm_id).await;
+                    remove_vm(&store, &board.events, &vm_id);
+                    board.finish(&vm_id, ProvisioningState::Failed, Some(err.to_string()));
+                }
+            }
+        })
+    }
+}
+
+/// Flip the VM from `creating` to `stopped`; false if it no longer exists
+fn mark_ready(store: &ConfigStore, events: &tokio::sync::broadcast::Sender<UiEvent>, vm_id: &str) -> bool {
+    match store.transition_status(vm_id, "creating", "stopped") {
+        Ok(true) => {
+            let _ = events.send(UiEvent::StatusChanged(VmStatusEvent {
+                vm_id: vm_id.to_string(),
+                old_status: "creating".to_string(),
+                new_status: "stopped".to_string(),
+            }));
+            true
+        }
+        Ok(false) => matches!(store.get_vm(vm_id), Ok(Some(_))),
+        Err(err) => {
+            tracing::warn!(vm_id = %vm_id, error = %err, "failed to mark VM ready");
+            true
+        }
+    }
+}
+
+fn remove_vm(store: &ConfigStore, events: &tokio::sync::broadcast::Sender<UiEvent>, vm_id: &str) {
+    match store.delete_vm(vm_id) {
+        Ok(()) => {
+            let _ = events.send(UiEvent::ListChanged(VmListChanged {
+                vm_id: vm_id.to_string(),
+                change: VmChangeKind::Deleted,
+            }));
+        }
+        Err(err) => tracing::warn!(vm_id = %vm_id, error = %err, "failed to remove VM after disk creation failed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::VMRecord;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Takes `delay` per disk and fails for VM ids starting with "bad"
+    #[derive(Default)]
+    struct SlowBackend {
+        delay: Duration,
+        active: AtomicUsize,
+        peak: AtomicUsize,
+        deleted: Mutex<Vec<String>>,
+    }
+
+    impl DiskBackend for SlowBackend {
+        fn create_disk<'a>(&'a self, request: &'a DiskRequest) -> DiskFuture<'a, String> {
+            Box::pin(async move {
+                let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(active, Ordering::SeqCst);
+                tokio::time::sleep(self.delay).await;
+                self.active.fetch_sub(1, Ordering::SeqCst);
+                if request.vm_id.starts_with("bad") {
+                    return Err(crate::Error::QemuError("qemu-img failed".to_string()));
+                }
+                Ok(format!("/disks/{}.qcow2", request.vm_id))
+            })
+        }
+
+        fn delete_disk<'a>(&'a self, vm_id: &'a str) -> DiskFuture<'a, ()> {
+            self.deleted.lock().unwrap().push(vm_id.to_string());
+            Box::pin(async { Ok(()) })
+        }
+
+        fn written_bytes(&self, _vm_id: &str) -> Option<u64> {
+            Some(GIB)
+        }
+    }
+
+    fn creating_record(store: &ConfigStore, id: &str) -> VMRecord {
+        let record = VMRecord {
+            id: id.to_string(),
+            name: format!("VM {}", id),
+            status: "creating".to_string(),
+            memory_mb: 1024,
+            cpu_cores: 1,
+            disk_size_gb: 8,
+            restart_policy: "never".to_string(),
+            ..VMRecord::default()
+        };
+        store.create_vm(&record).unwrap();
+        record
+    }
+
+    fn request(vm_id: &str) -> DiskRequest {
+        DiskRequest {
+            vm_id: vm_id.to_string(),
+            size_gb: 8,
+            preallocation: "metadata".to_string(),
+            key_ref: None,
+            passphrase: None,
+        }
+    }
+
+    fn job_states(events: &mut tokio::sync::broadcast::Receiver<UiEvent>, vm_id: &str) -> Vec<ProvisioningState> {
+        let mut states = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let UiEvent::Provisioning(job) = event {
+                if job.vm_id == vm_id && states.last() != Some(&job.state) {
+                    states.push(job.state);
+                }
+            }
+        }
+        states
+    }
+
+    #[tokio::test]
+    async fn test_jobs_never_exceed_limit() {
+        let store = ConfigStore::in_memory().unwrap();
+        let backend = Arc::new(SlowBackend { delay: Duration::from_millis(30), ..Default::default() });
+        let provisioner = Provisioner::new(backend.clone(), 2);
+        let (events, _) = tokio::sync::broadcast::channel(256);
+
+        let mut handles = Vec::new();
+        for i in 0..6 {
+            let record = creating_record(&store, &format!("vm-{}", i));
+            handles.push(provisioner.submit(&record.name, request(&record.id), store.clone(), events.clone()));
+        }
+        assert_eq!(provisioner.jobs().len(), 6);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(backend.peak.load(Ordering::SeqCst), 2);
+        assert!(provisioner.jobs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_job_marks_vm_stopped_when_disk_is_ready() {
+        let store = ConfigStore::in_memory().unwrap();
+        let backend = Arc::new(SlowBackend { delay: Duration::from_millis(10), ..Default::default() });
+        let provisioner = Provisioner::new(backend, MAX_DISK_JOBS);
+        let (events, mut received) = tokio::sync::broadcast::channel(64);
+        let record = creating_record(&store, "vm-1");
+
+        let handle = provisioner.submit(&record.name, request("vm-1"), store.clone(), events);
+        assert!(provisioner.is_provisioning("vm-1"));
+        handle.await.unwrap();
+
+        assert!(!provisioner.is_provisioning("vm-1"));
+        assert_eq!(store.get_vm("vm-1").unwrap().unwrap().status, "stopped");
+        assert_eq!(
+            job_states(&mut received, "vm-1"),
+            vec![ProvisioningState::Queued, ProvisioningState::Creating, ProvisioningState::Done]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_removes_vm() {
+        let store = ConfigStore::in_memory().unwrap();
+        let backend = Arc::new(SlowBackend::default());
+        let provisioner = Provisioner::new(backend.clone(), MAX_DISK_JOBS);
+        let (events, mut received) = tokio::sync::broadcast::channel(64);
+        let record = creating_record(&store, "bad-1");
+
+        provisioner.submit(&record.name, request("bad-1"), store.clone(), events).await.unwrap();
+
+        assert!(store.get_vm("bad-1").unwrap().is_none());
+        assert_eq!(*backend.deleted.lock().unwrap(), vec!["bad-1".to_string()]);
+        assert_eq!(
+            job_states(&mut received, "bad-1"),
+            vec![ProvisioningState::Queued, ProvisioningState::Creating, ProvisioningState::Failed]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_vm_deleted_during_creation_loses_its_disk() {
+        let store = ConfigStore::in_memory().unwrap();
+        let backend = Arc::new(SlowBackend { delay: Duration::from_millis(20), ..Default::default() });
+        let provisioner = Provisioner::new(backend.clone(), MAX_DISK_JOBS);
+        let (events, _) = tokio::sync::broadcast::channel(64);
+        let record = creating_record(&store, "vm-1");
+
+        let handle = provisioner.submit(&record.name, request("vm-1"), store.clone(), events);
+        store.delete_vm("vm-1").unwrap();
+        handle.await.unwrap();
+
+        assert_eq!(*backend.deleted.lock().unwrap(), vec!["vm-1".to_string()]);
+    }
+}
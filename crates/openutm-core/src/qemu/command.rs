@@ -0,0 +1,2815 @@
+//! QEMU Command Builder
+//! 
+//! Generates QEMU command lines programmatically with type safety.
+//! Builder pattern for composing QEMU command arguments.
+
+use crate::config::VMRecord;
+use crate::error::Error;
+use crate::guest::GuestOs;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Accelerator {
+    Hvf,
+    Kvm,
+    Whpx,
+    Tcg,
+}
+
+impl Accelerator {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Hvf => "hvf",
+            Self::Kvm => "kvm",
+            Self::Whpx => "whpx",
+            Self::Tcg => "tcg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MachineType {
+    Q35,
+    I440fx,
+    Virt,
+    /// Any other name the binary accepts, usually a versioned type like `pc-q35-8.2`
+    Custom(String),
+}
+
+impl MachineType {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "q35" => Self::Q35,
+            "i440fx" => Self::I440fx,
+            "virt" => Self::Virt,
+            other => Self::Custom(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Q35 => "q35",
+            Self::I440fx => "i440fx",
+            Self::Virt => "virt",
+            Self::Custom(name) => name,
+        }
+    }
+
+    /// ARM `virt` boards, versioned or not; they have no legacy VGA
+    pub fn is_virt(&self) -> bool {
+        match self {
+            Self::Virt => true,
+            Self::Custom(name) => name.starts_with("virt-"),
+            _ => false,
+        }
+    }
+}
+
+/// Whether `name` is safe to pass to `-machine` as a bare type (no `,key=value` options)
+pub fn is_valid_machine_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Per-drive I/O limits; `None` leaves that dimension unthrottled
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct IoThrottle {
+    pub read_bps: Option<u64>,
+    pub write_bps: Option<u64>,
+    pub read_iops: Option<u64>,
+    pub write_iops: Option<u64>,
+}
+
+impl IoThrottle {
+    pub fn is_empty(&self) -> bool {
+        self.read_bps.is_none()
+            && self.write_bps.is_none()
+            && self.read_iops.is_none()
+            && self.write_iops.is_none()
+    }
+
+    /// `throttling.*` suboptions for `-drive`
+    pub fn drive_options(&self) -> Vec<String> {
+        [
+            ("throttling.bps-read", self.read_bps),
+            ("throttling.bps-write", self.write_bps),
+            ("throttling.iops-read", self.read_iops),
+            ("throttling.iops-write", self.write_iops),
+        ]
+        .iter()
+        .filter_map(|(key, value)| value.map(|v| format!("{}={}", key, v)))
+        .collect()
+    }
+}
+
+/// Backing store for a drive
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriveSource {
+    /// Image file (qcow2, raw, ...) in the drive's `format`
+    File { path: String },
+    /// Host block device passed straight through, e.g. `/dev/sdb`
+    RawDevice { path: String },
+}
+
+/// Drive interface that attaches a `scsi-hd` to a shared virtio-scsi controller
+pub const VIRTIO_SCSI_INTERFACE: &str = "virtio-scsi";
+
+/// Interfaces a VM's own disk can be attached through
+pub const DISK_INTERFACES: [&str; 3] = ["virtio", VIRTIO_SCSI_INTERFACE, "ide"];
+
+/// Id of the virtio-scsi controller every virtio-scsi drive sits on
+const SCSI_CONTROLLER_ID: &str = "scsi0";
+
+/// Id of the memory balloon device
+const BALLOON_DEVICE_ID: &str = "balloon0";
+
+#[derive(Debug, Clone)]
+pub struct DriveConfig {
+    pub id: String,
+    pub source: DriveSource,
+    pub format: String,
+    /// `if=` value, or `VIRTIO_SCSI_INTERFACE`
+    pub interface: String,
+    pub throttle: IoThrottle,
+    /// Id of the `-object secret` unlocking a LUKS-encrypted qcow2
+    pub key_secret: Option<String>,
+    /// Pass guest TRIM through so freed blocks shrink the host file. virtio-blk
+    /// needs a 5.0+ Linux guest for this; virtio-scsi handles discard on any guest.
+    pub discard: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetdevConfig {
+    pub id: String,
+    pub kind: String,
+    pub options: HashMap<String, String>,
+    /// 802.1Q VLAN (1-4094); only valid for TAP/bridge networking
+    pub vlan_id: Option<u16>,
+    /// MAC of the guest NIC; QEMU picks one per launch when unset
+    pub mac: Option<String>,
+    /// Host ports forwarded into the guest; user-mode networking only
+    pub port_forwards: Vec<PortForward>,
+}
+
+pub const MAX_VLAN_ID: u16 = 4094;
+
+/// Guest network adapters a VM may have, the PCI NICs a typical machine has room for
+pub const MAX_NETWORK_ADAPTERS: usize = 8;
+
+/// A host port forwarded to a guest port over a NAT adapter
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortForward {
+    /// `tcp` or `udp`
+    #[serde(default = "default_forward_protocol")]
+    pub protocol: String,
+    pub host_port: u16,
+    pub guest_port: u16,
+}
+
+fn default_forward_protocol() -> String {
+    "tcp".to_string()
+}
+
+/// One of a VM's guest network adapters, stored in the `networks` table
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkConfig {
+    /// Netdev id, unique within the VM
+    pub id: String,
+    /// `nat` or `bridge`, as for `network_type`
+    pub kind: String,
+    #[serde(default)]
+    pub mac: Option<String>,
+    #[serde(default)]
+    pub port_forwards: Vec<PortForward>,
+}
+
+/// NAT adapters use user-mode networking; bridged ones go through QEMU's bridge helper
+pub fn adapter_netdev(network: &NetworkConfig) -> NetdevConfig {
+    NetdevConfig {
+        id: network.id.clone(),
+        kind: if network.kind == "bridge" { "bridge" } else { "user" }.to_string(),
+        options: HashMap::new(),
+        vlan_id: None,
+        mac: network.mac.clone(),
+        port_forwards: network.port_forwards.clone(),
+    }
+}
+
+/// QEMU's locally administered OUI
+const QEMU_MAC_PREFIX: &str = "52:54:00";
+
+/// A MAC in QEMU's `52:54:00` range derived from the VM id, so the guest keeps
+/// its DHCP lease across restarts. FNV-1a keeps it stable across builds.
+pub fn generate_stable_mac(vm_id: &str) -> String {
+    let hash = vm_id.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    let [a, b, c, ..] = hash.to_be_bytes();
+    format!("{}:{:02x}:{:02x}:{:02x}", QEMU_MAC_PREFIX, a, b, c)
+}
+
+/// Whether `mac` is six colon-separated hex octets
+pub fn is_valid_mac(mac: &str) -> bool {
+    let octets: Vec<&str> = mac.split(':').collect();
+    octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Reject netdev settings QEMU would accept but that cannot work
+pub fn validate_network_config(config: &NetdevConfig) -> std::result::Result<(), String> {
+    let Some(vlan_id) = config.vlan_id else {
+        return Ok(());
+    };
+    if !(1..=MAX_VLAN_ID).contains(&vlan_id) {
+        return Err(format!("VLAN ID must be between 1 and {}", MAX_VLAN_ID));
+    }
+    if config.kind != "tap" && config.kind != "bridge" {
+        return Err("VLAN requires TAP or bridge network".to_string());
+    }
+    if !config.options.contains_key("ifname") {
+        return Err("VLAN networking needs the host interface name".to_string());
+    }
+    Ok(())
+}
+
+/// Host TAP a VM on a VLAN is attached through; kept within the 15-byte interface name limit
+pub fn vlan_tap_name(vm_id: &str) -> String {
+    let hash = vm_id.bytes().fold(0x811c_9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+    format!("utm{:08x}", hash)
+}
+
+/// Whether the VM's TAP NIC is opened with one queue per vCPU, matching `QemuCommand`
+pub fn vm_net_multiqueue(vm: &VMRecord) -> bool {
+    vm.performance.as_ref().is_some_and(|performance| performance.net_multiqueue) && vm.cpu_cores > 1
+}
+
+/// Bridged VMs on a VLAN get their own TAP on the VLAN's bridge, which
+/// `platform::ensure_vlan_interface` sets up; everything else is user-mode NAT
+pub fn vm_netdev(vm: &VMRecord) -> NetdevConfig {
+    match (vm.network_type.as_str(), vm.vlan_id) {
+        ("bridge", Some(vlan_id)) => NetdevConfig {
+            id: "net0".to_string(),
+            kind: "tap".to_string(),
+            // The app owns the TAP, so QEMU must not run its ifup/ifdown scripts
+            options: HashMap::from([
+                ("ifname".to_string(), vlan_tap_name(&vm.id)),
+                ("script".to_string(), "no".to_string()),
+                ("downscript".to_string(), "no".to_string()),
+            ]),
+            vlan_id: Some(vlan_id),
+            mac: vm.mac_address.clone(),
+            port_forwards: Vec::new(),
+        },
+        (_, vlan_id) => NetdevConfig {
+            id: "net0".to_string(),
+            kind: "user".to_string(),
+            options: HashMap::new(),
+            vlan_id,
+            mac: vm.mac_address.clone(),
+            port_forwards: Vec::new(),
+        },
+    }
+}
+
+/// Display listen address used unless a VM is set up for remote access
+pub const LOOPBACK_LISTEN_ADDRESS: &str = "127.0.0.1";
+
+/// VNC display numbers count up from this port, so no VNC display sits below it
+pub const VNC_BASE_PORT: u16 = 5900;
+
+pub const DEFAULT_DISPLAY_PORT_RANGE_START: u16 = 5900;
+pub const DEFAULT_DISPLAY_PORT_RANGE_END: u16 = 6899;
+
+/// Stable per-VM display port in `range_start..=range_end`, for VNC as well as SPICE
+pub fn resolve_spice_port(vm_id: &str, range_start: u16, range_end: u16) -> u16 {
+    let span = u32::from(range_end.saturating_sub(range_start)) + 1;
+    let mut hash: u32 = 0;
+    for byte in vm_id.as_bytes() {
+        hash = (hash * 31 + u32::from(*byte)) % span;
+    }
+    range_start + hash as u16
+}
+
+/// Host ports display servers are assigned from, both ends inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplayPortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Default for DisplayPortRange {
+    fn default() -> Self {
+        Self { start: DEFAULT_DISPLAY_PORT_RANGE_START, end: DEFAULT_DISPLAY_PORT_RANGE_END }
+    }
+}
+
+impl DisplayPortRange {
+    /// Both ends must be usable by VNC as well as SPICE
+    pub fn is_valid(&self) -> bool {
+        self.start >= VNC_BASE_PORT && self.start <= self.end
+    }
+
+    pub fn port_for(&self, vm_id: &str) -> u16 {
+        resolve_spice_port(vm_id, self.start, self.end)
+    }
+}
+
+/// Display server port a VM was launched with, read back from its QEMU arguments
+pub fn display_port_from_args(args: &[String]) -> Option<u16> {
+    args.windows(2).find_map(|pair| match pair[0].as_str() {
+        "-spice" => pair[1]
+            .split(',')
+            .find_map(|option| option.strip_prefix("port="))
+            .and_then(|port| port.parse().ok()),
+        "-vnc" => pair[1]
+            .rsplit_once(':')
+            .and_then(|(_, number)| number.parse::<u16>().ok())
+            .and_then(|number| number.checked_add(VNC_BASE_PORT)),
+        _ => None,
+    })
+}
+
+/// Machine type for VMs that do not pick one
+pub fn machine_for_arch(arch: &str) -> MachineType {
+    match arch {
+        "aarch64" => MachineType::Virt,
+        _ => MachineType::Q35,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DisplayConfig {
+    pub kind: String,
+    pub port: Option<u16>,
+    pub options: HashMap<String, String>,
+    /// Add the vdagent channel the guest agent uses to sync the clipboard
+    pub clipboard_sharing: bool,
+    /// Initial `(width, height)` of the guest display; the SPICE agent may resize it later
+    pub resolution: Option<(u32, u32)>,
+    /// Emulated display adapter; `None` keeps QEMU's default unless a resolution is requested
+    pub adapter: Option<GraphicsAdapter>,
+    /// Also serve SPICE over TLS; ignored for other display kinds
+    pub tls: Option<SpiceTls>,
+    /// SPICE image and video compression; ignored for other display kinds
+    pub compression: SpiceCompression,
+}
+
+/// A SPICE TLS listener and the directory holding its certificates
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpiceTls {
+    pub port: u16,
+    /// Must contain `ca-cert.pem`, `server-cert.pem` and `server-key.pem`
+    pub x509_dir: String,
+}
+
+/// SPICE bandwidth vs. quality trade-offs, mostly felt over slow connections
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpiceCompression {
+    pub image_compression: String,
+    pub jpeg_wan_compression: String,
+    pub zlib_glz_wan_compression: String,
+    pub streaming_video: String,
+}
+
+pub const SPICE_IMAGE_COMPRESSIONS: [&str; 6] = ["auto_glz", "auto_lz", "quic", "glz", "lz", "off"];
+/// Accepted for both `jpeg-wan-compression` and `zlib-glz-wan-compression`
+pub const SPICE_WAN_COMPRESSIONS: [&str; 3] = ["auto", "never", "always"];
+pub const SPICE_STREAMING_VIDEO: [&str; 3] = ["off", "all", "filter"];
+
+impl Default for SpiceCompression {
+    fn default() -> Self {
+        Self {
+            image_compression: "auto_glz".to_string(),
+            jpeg_wan_compression: "auto".to_string(),
+            zlib_glz_wan_compression: "auto".to_string(),
+            streaming_video: "filter".to_string(),
+        }
+    }
+}
+
+impl SpiceCompression {
+    /// The first `(option, value)` QEMU would reject, if any
+    pub fn invalid_option(&self) -> Option<(&'static str, &str)> {
+        [
+            ("image-compression", self.image_compression.as_str(), &SPICE_IMAGE_COMPRESSIONS[..]),
+            ("jpeg-wan-compression", self.jpeg_wan_compression.as_str(), &SPICE_WAN_COMPRESSIONS[..]),
+            ("zlib-glz-wan-compression", self.zlib_glz_wan_compression.as_str(), &SPICE_WAN_COMPRESSIONS[..]),
+            ("streaming-video", self.streaming_video.as_str(), &SPICE_STREAMING_VIDEO[..]),
+        ]
+        .into_iter()
+        .find(|(_, value, allowed)| !allowed.contains(value))
+        .map(|(option, value, _)| (option, value))
+    }
+
+    fn spice_options(&self) -> String {
+        format!(
+            "image-compression={},jpeg-wan-compression={},zlib-glz-wan-compression={},streaming-video={}",
+            self.image_compression, self.jpeg_wan_compression, self.zlib_glz_wan_compression, self.streaming_video
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsAdapter {
+    VirtioVga,
+    Qxl,
+    Std,
+    VirtioGpu,
+}
+
+pub const GRAPHICS_ADAPTERS: [&str; 4] = ["virtio-vga", "qxl", "std", "virtio-gpu"];
+
+impl GraphicsAdapter {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "virtio-vga" => Some(Self::VirtioVga),
+            "qxl" => Some(Self::Qxl),
+            "std" => Some(Self::Std),
+            "virtio-gpu" => Some(Self::VirtioGpu),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::VirtioVga => "virtio-vga",
+            Self::Qxl => "qxl",
+            Self::Std => "std",
+            Self::VirtioGpu => "virtio-gpu",
+        }
+    }
+
+    /// QXL pairs with SPICE and std VGA with VNC; aarch64 `virt` (no VGA) gets virtio-gpu
+    pub fn default_for(display_kind: &str, machine: &MachineType) -> Self {
+        match machine {
+            machine if machine.is_virt() => Self::VirtioGpu,
+            _ if display_kind == "spice" => Self::Qxl,
+            _ if display_kind == "vnc" => Self::Std,
+            _ => Self::VirtioGpu,
+        }
+    }
+
+    /// Whether this adapter needs a legacy VGA-capable machine
+    pub fn is_vga(&self) -> bool {
+        !matches!(self, Self::VirtioGpu)
+    }
+
+    fn device(&self) -> &'static str {
+        match self {
+            Self::VirtioVga => "virtio-vga",
+            Self::Qxl => "qxl-vga",
+            Self::Std => "VGA",
+            Self::VirtioGpu => "virtio-gpu-pci",
+        }
+    }
+}
+
+pub const MIN_RESOLUTION: (u32, u32) = (640, 480);
+pub const MAX_RESOLUTION: (u32, u32) = (7680, 4320);
+
+/// Parse `WIDTHxHEIGHT` (e.g. `1920x1080`) within `MIN_RESOLUTION..=MAX_RESOLUTION`
+pub fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.trim().split_once(['x', 'X'])?;
+    let width: u32 = width.parse().ok()?;
+    let height: u32 = height.parse().ok()?;
+    let in_range = (MIN_RESOLUTION.0..=MAX_RESOLUTION.0).contains(&width)
+        && (MIN_RESOLUTION.1..=MAX_RESOLUTION.1).contains(&height);
+    in_range.then_some((width, height))
+}
+
+/// Disk and NIC tuning for I/O-heavy guests; the default leaves QEMU's own behaviour
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VmPerformance {
+    /// I/O threads the virtio disks are spread across; 0 keeps disk I/O on the main loop
+    #[serde(default)]
+    pub iothreads: u32,
+    /// One virtio-net queue per vCPU. Only TAP NICs have queues to spread;
+    /// user-mode and bridge-helper networking ignore it.
+    #[serde(default)]
+    pub net_multiqueue: bool,
+    /// Linux AIO backend for image files, `native` or `io_uring`; `None` keeps QEMU's thread pool
+    #[serde(default)]
+    pub aio: Option<String>,
+}
+
+pub const AIO_MODES: [&str; 2] = ["native", "io_uring"];
+
+/// First Linux release with io_uring
+pub const IO_URING_MIN_KERNEL: (u32, u32) = (5, 1);
+
+fn iothread_id(index: u32) -> String {
+    format!("iothread{}", index)
+}
+
+/// Escape a value for a QEMU `key=value,...` option list, where `,,` is a literal comma
+pub fn option_value(value: &str) -> String {
+    value.replace(',', ",,")
+}
+
+/// A disk hot-added to the running VM, attached again on the next start under
+/// the same device id and block node name so it can still be unplugged
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotplugDisk {
+    pub device_id: String,
+    pub node_name: String,
+    pub path: String,
+    pub format: String,
+}
+
+/// Firmware boot menu; `splash_time_ms` is how long it waits for a key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BootMenuConfig {
+    pub enabled: bool,
+    #[serde(default)]
+    pub splash_time_ms: u32,
+}
+
+/// QEMU rejects splash times that do not fit in 16 bits
+pub const MAX_SPLASH_TIME_MS: u32 = 0xffff;
+
+/// Device for a one-off boot that overrides the persisted boot order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootDevice {
+    Disk,
+    Cdrom,
+}
+
+pub const BOOT_DEVICES: [&str; 2] = ["disk", "cdrom"];
+
+impl BootDevice {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "disk" => Some(Self::Disk),
+            "cdrom" => Some(Self::Cdrom),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Disk => "disk",
+            Self::Cdrom => "cdrom",
+        }
+    }
+
+    /// Drive letter in QEMU's `-boot` syntax
+    fn letter(&self) -> char {
+        match self {
+            Self::Disk => 'c',
+            Self::Cdrom => 'd',
+        }
+    }
+}
+
+/// Host PCI function to hand to the guest through VFIO
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct VfioPciDevice {
+    pub domain: u16,
+    pub bus: u8,
+    pub slot: u8,
+    pub function: u8,
+}
+
+impl VfioPciDevice {
+    /// Parse a sysfs-style address such as `0000:01:00.0`
+    pub fn parse(address: &str) -> Option<Self> {
+        let (domain, rest) = address.split_once(':')?;
+        let (bus, rest) = rest.split_once(':')?;
+        let (slot, function) = rest.split_once('.')?;
+        let device = Self {
+            domain: u16::from_str_radix(domain, 16).ok()?,
+            bus: u8::from_str_radix(bus, 16).ok()?,
+            slot: u8::from_str_radix(slot, 16).ok()?,
+            function: u8::from_str_radix(function, 16).ok()?,
+        };
+        // Slots are 5 bits and functions 3 bits wide
+        (device.slot < 32 && device.function < 8).then_some(device)
+    }
+}
+
+impl std::fmt::Display for VfioPciDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04x}:{:02x}:{:02x}.{:x}", self.domain, self.bus, self.slot, self.function)
+    }
+}
+
+/// Absolute pointing device, so the guest cursor tracks the viewer's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PointerDevice {
+    UsbTablet,
+    /// Needs virtio-input drivers, which Linux ships and Windows and macOS do not
+    VirtioTablet,
+    /// Relative PS/2 mouse only
+    None,
+}
+
+pub const POINTER_DEVICES: [&str; 3] = ["usb-tablet", "virtio-tablet", "none"];
+
+impl PointerDevice {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "usb-tablet" => Some(Self::UsbTablet),
+            "virtio-tablet" => Some(Self::VirtioTablet),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UsbTablet => "usb-tablet",
+            Self::VirtioTablet => "virtio-tablet",
+            Self::None => "none",
+        }
+    }
+
+    /// VirtIO for Linux guests; everything else keeps the USB tablet it has drivers for
+    pub fn default_for(os: GuestOs) -> Self {
+        match os {
+            GuestOs::Linux => Self::VirtioTablet,
+            _ => Self::UsbTablet,
+        }
+    }
+}
+
+/// What the guest's real-time clock counts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RtcBase {
+    Utc,
+    /// The host's local time, which Windows expects the hardware clock to keep
+    Localtime,
+}
+
+pub const RTC_BASES: [&str; 2] = ["utc", "localtime"];
+
+impl RtcBase {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "utc" => Some(Self::Utc),
+            "localtime" => Some(Self::Localtime),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Utc => "utc",
+            Self::Localtime => "localtime",
+        }
+    }
+
+    pub fn default_for(os: GuestOs) -> Self {
+        if os.defaults().rtc_local_time {
+            Self::Localtime
+        } else {
+            Self::Utc
+        }
+    }
+}
+
+/// Host entropy source behind the guest's VirtIO RNG
+pub const HOST_ENTROPY_SOURCE: &str = "/dev/urandom";
+
+/// QEMU object ID of the VirtIO RNG backend
+const RNG_OBJECT_ID: &str = "rng0";
+
+/// What feeds the guest's VirtIO RNG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RngBackend {
+    /// Reads from a host file, normally `/dev/urandom`
+    Random(String),
+    /// QEMU's own generator, for hosts without `/dev/urandom`
+    Builtin,
+}
+
+impl RngBackend {
+    pub fn for_host() -> Self {
+        if Path::new(HOST_ENTROPY_SOURCE).exists() {
+            Self::Random(HOST_ENTROPY_SOURCE.to_string())
+        } else {
+            Self::Builtin
+        }
+    }
+
+    fn object(&self) -> String {
+        match self {
+            Self::Random(path) => format!("rng-random,id={},filename={}", RNG_OBJECT_ID, path),
+            Self::Builtin => format!("rng-builtin,id={}", RNG_OBJECT_ID),
+        }
+    }
+}
+
+/// Where guest RAM comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryBackend {
+    /// QEMU's own anonymous memory
+    Default,
+    /// Reserved huge pages mounted at `/dev/hugepages` (Linux only)
+    Hugepages,
+    /// Anonymous memory allocated up front instead of on first touch (Linux only)
+    Prealloc,
+}
+
+pub const MEMORY_BACKENDS: [&str; 3] = ["default", "hugepages", "prealloc"];
+
+/// hugetlbfs mount backing `MemoryBackend::Hugepages`
+pub const HUGEPAGES_MOUNT: &str = "/dev/hugepages";
+const MEMORY_BACKEND_ID: &str = "mem0";
+
+impl MemoryBackend {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "default" => Some(Self::Default),
+            "hugepages" => Some(Self::Hugepages),
+            "prealloc" => Some(Self::Prealloc),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Hugepages => "hugepages",
+            Self::Prealloc => "prealloc",
+        }
+    }
+
+    fn object(&self, memory_mb: u32) -> Option<String> {
+        match self {
+            Self::Default => None,
+            Self::Hugepages => Some(format!(
+                "memory-backend-file,id={},size={}M,mem-path={},prealloc=on",
+                MEMORY_BACKEND_ID, memory_mb, HUGEPAGES_MOUNT
+            )),
+            Self::Prealloc => Some(format!(
+                "memory-backend-memfd,id={},size={}M,prealloc=on",
+                MEMORY_BACKEND_ID, memory_mb
+            )),
+        }
+    }
+}
+
+/// Huge pages of `page_size_kb` needed to back `memory_mb` of guest RAM
+pub fn hugepages_needed(memory_mb: u32, page_size_kb: u64) -> u64 {
+    let page_size_kb = page_size_kb.max(1);
+    (u64::from(memory_mb) * 1024 + page_size_kb - 1) / page_size_kb
+}
+
+/// QEMU command builder with fluent API
+#[derive(Debug, Clone)]
+pub struct QemuCommand {
+    machine: Option<MachineType>,
+    accelerator: Option<Accelerator>,
+    accel_options: Vec<(String, String)>,
+    cpu_count: Option<u32>,
+    cpu_model: Option<String>,
+    cpu_flags: Vec<String>,
+    memory_mb: Option<u32>,
+    memory_backend: MemoryBackend,
+    performance: VmPerformance,
+    pflash: Option<(String, String)>,
+    bios: Option<String>,
+    objects: Vec<String>,
+    drives: Vec<DriveConfig>,
+    hotplug_disks: Vec<HotplugDisk>,
+    netdevs: Vec<NetdevConfig>,
+    display: Option<DisplayConfig>,
+    vfio_devices: Vec<VfioPciDevice>,
+    boot_menu: Option<BootMenuConfig>,
+    boot_once: Option<BootDevice>,
+    pointer: PointerDevice,
+    rtc: Option<RtcBase>,
+    rng: Option<RngBackend>,
+    balloon: bool,
+    no_reboot: bool,
+    monitor_socket: Option<String>,
+    serial_socket: Option<String>,
+}
+
+impl Default for QemuCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QemuCommand {
+    /// Create new QEMU command builder
+    pub fn new() -> Self {
+        Self {
+            machine: None,
+            accelerator: None,
+            accel_options: Vec::new(),
+            cpu_count: None,
+            cpu_model: None,
+            cpu_flags: Vec::new(),
+            memory_mb: None,
+            memory_backend: MemoryBackend::Default,
+            performance: VmPerformance::default(),
+            pflash: None,
+            bios: None,
+            objects: Vec::new(),
+            drives: Vec::new(),
+            hotplug_disks: Vec::new(),
+            netdevs: Vec::new(),
+            display: None,
+            vfio_devices: Vec::new(),
+            boot_menu: None,
+            boot_once: None,
+            pointer: PointerDevice::None,
+            rtc: None,
+            rng: None,
+            balloon: false,
+            no_reboot: false,
+            monitor_socket: None,
+            serial_socket: None,
+        }
+    }
+
+    /// Set machine type
+    pub fn machine(mut self, machine: MachineType) -> Self {
+        self.machine = Some(machine);
+        self
+    }
+
+    /// Set accelerator
+    pub fn accel(mut self, accel: Accelerator) -> Self {
+        self.accelerator = Some(accel);
+        self
+    }
+
+    /// Add an accelerator property (e.g. `thread=multi` for TCG)
+    pub fn accel_option(mut self, key: &str, value: &str) -> Self {
+        self.accel_options.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Set CPU count (must be > 0)
+    pub fn cpu(mut self, count: u32) -> Result<Self, String> {
+        if count == 0 {
+            return Err("CPU count must be > 0".to_string());
+        }
+        self.cpu_count = Some(count);
+        Ok(self)
+    }
+
+    /// Set CPU model (e.g. `host`, `max`)
+    pub fn cpu_model(mut self, model: &str) -> Self {
+        self.cpu_model = Some(model.to_string());
+        self
+    }
+
+    /// Enable an extra CPU feature flag on top of the CPU model
+    pub fn cpu_flag(mut self, flag: &str) -> Self {
+        self.cpu_flags.push(flag.to_string());
+        self
+    }
+
+    /// Expose hardware virtualization to the guest via `-cpu host,+<flag>`
+    pub fn nested_virt(self, flag: &str) -> Self {
+        self.cpu_model("host").cpu_flag(flag)
+    }
+
+    /// Set memory in MB (must be > 0)
+    pub fn memory(mut self, mb: u32) -> Result<Self, String> {
+        if mb == 0 {
+            return Err("Memory must be > 0 MB".to_string());
+        }
+        self.memory_mb = Some(mb);
+        Ok(self)
+    }
+
+    /// Back guest RAM with `backend`; only takes effect once memory is set
+    pub fn memory_backend(mut self, backend: MemoryBackend) -> Self {
+        self.memory_backend = backend;
+        self
+    }
+
+    /// Boot UEFI firmware from a read-only `code` image, keeping its
+    /// variables in the writable `vars` image
+    pub fn pflash(mut self, code: &str, vars: &str) -> Self {
+        self.pflash = Some((code.to_string(), vars.to_string()));
+        self
+    }
+
+    /// Replace the machine's default BIOS with the image at `path`
+    pub fn bios(mut self, path: &str) -> Self {
+        self.bios = Some(path.to_string());
+        self
+    }
+
+    /// Check settings that are only wrong in combination
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some((code, vars)) = &self.pflash {
+            if code.is_empty() || vars.is_empty() {
+                return Err("pflash code and vars paths must not be empty".to_string());
+            }
+            if self.bios.is_some() {
+                return Err("pflash firmware and a BIOS override cannot be combined".to_string());
+            }
+        }
+        if self.bios.as_deref() == Some("") {
+            return Err("BIOS path must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// Add a user-creatable object, e.g. `secret,id=sec0,file=/path`
+    pub fn object(mut self, spec: &str) -> Self {
+        self.objects.push(spec.to_string());
+        self
+    }
+
+    /// Add virtual drive
+    pub fn drive(mut self, drive: DriveConfig) -> Self {
+        self.drives.push(drive);
+        self
+    }
+
+    /// Attach a previously hot-added virtio disk
+    pub fn hotplug_disk(mut self, disk: HotplugDisk) -> Self {
+        self.hotplug_disks.push(disk);
+        self
+    }
+
+    /// Add network device
+    pub fn netdev(mut self, netdev: NetdevConfig) -> Self {
+        self.netdevs.push(netdev);
+        self
+    }
+
+    /// Replace the VM's single NIC with one per adapter; no adapters keeps it
+    pub fn networks(mut self, networks: &[NetworkConfig]) -> Self {
+        if !networks.is_empty() {
+            self.netdevs = networks.iter().map(adapter_netdev).collect();
+        }
+        self
+    }
+
+    /// Set display configuration (SPICE)
+    pub fn display(mut self, display: DisplayConfig) -> Self {
+        self.display = Some(display);
+        self
+    }
+
+    /// Move the display server to `port`; headless VMs stay headless
+    pub fn display_port(mut self, port: u16) -> Self {
+        if let Some(display) = self.display.as_mut().filter(|display| display.port.is_some()) {
+            display.port = Some(port);
+        }
+        self
+    }
+
+    /// Pass a host PCI device through; it must already be bound to `vfio-pci`
+    pub fn vfio_pci(mut self, device: VfioPciDevice) -> Self {
+        self.vfio_devices.push(device);
+        self
+    }
+
+    /// Show or suppress the firmware boot menu
+    pub fn boot_menu(mut self, config: BootMenuConfig) -> Self {
+        self.boot_menu = Some(config);
+        self
+    }
+
+    /// Start the guest clock from UTC or host local time, following the host clock
+    pub fn rtc(mut self, base: RtcBase) -> Self {
+        self.rtc = Some(base);
+        self
+    }
+
+    /// Boot from `device` on the first boot only; guest reboots go back to the boot order
+    pub fn boot_once(mut self, device: BootDevice) -> Self {
+        self.boot_once = Some(device);
+        self
+    }
+
+    /// Enable USB tablet for better mouse support
+    #[deprecated(note = "use `pointer_device` or `virtio_tablet`")]
+    pub fn usb_tablet(self) -> Self {
+        self.pointer_device(PointerDevice::UsbTablet)
+    }
+
+    /// Enable a VirtIO tablet for mouse support
+    pub fn virtio_tablet(self) -> Self {
+        self.pointer_device(PointerDevice::VirtioTablet)
+    }
+
+    pub fn pointer_device(mut self, pointer: PointerDevice) -> Self {
+        self.pointer = pointer;
+        self
+    }
+
+    /// Attach a VirtIO RNG fed from `backend`
+    pub fn rng(mut self, backend: RngBackend) -> Self {
+        self.rng = Some(backend);
+        self
+    }
+
+    /// Apply I/O threads, NIC multiqueue and the AIO backend
+    pub fn performance(mut self, performance: VmPerformance) -> Self {
+        self.performance = performance;
+        self
+    }
+
+    /// Queues per TAP NIC: one per vCPU once multiqueue is on and there is more than one
+    fn net_queues(&self) -> Option<u32> {
+        let vcpus = self.cpu_count.unwrap_or(1);
+        (self.performance.net_multiqueue && vcpus > 1).then_some(vcpus)
+    }
+
+    /// Add a balloon device so guest memory can be reclaimed while it runs
+    pub fn balloon(mut self) -> Self {
+        self.balloon = true;
+        self
+    }
+
+    /// Exit instead of rebooting when the guest resets
+    pub fn no_reboot(mut self) -> Self {
+        self.no_reboot = true;
+        self
+    }
+
+    /// Reclaim host space when the guest discards blocks on drive `drive_id`
+    pub fn discard(mut self, drive_id: &str) -> Self {
+        if let Some(drive) = self.drives.iter_mut().find(|drive| drive.id == drive_id) {
+            drive.discard = true;
+        }
+        self
+    }
+
+    /// Attach a drive through `interface` instead of the one it was added with
+    pub fn drive_interface(mut self, drive_id: &str, interface: &str) -> Self {
+        if let Some(drive) = self.drives.iter_mut().find(|drive| drive.id == drive_id) {
+            drive.interface = interface.to_string();
+        }
+        self
+    }
+
+    /// Expose the human monitor (HMP) on a unix socket for debugging
+    pub fn monitor_socket(mut self, path: &str) -> Self {
+        self.monitor_socket = Some(path.to_string());
+        self
+    }
+
+    /// Serve the guest's first serial port on a unix socket, for VMs without a display
+    pub fn serial_socket(mut self, path: &str) -> Self {
+        self.serial_socket = Some(path.to_string());
+        self
+    }
+
+    /// Require the SPICE password in secret object `secret_id` instead of disabling ticketing
+    pub fn spice_password_secret(mut self, secret_id: &str) -> Self {
+        if let Some(display) = self.display.as_mut().filter(|display| display.kind == "spice") {
+            display.options.remove("disable-ticketing");
+            display.options.insert("password-secret".to_string(), secret_id.to_string());
+        }
+        self
+    }
+
+    /// Serve the SPICE display over TLS as well; ignored for other displays
+    pub fn spice_tls(mut self, tls: SpiceTls) -> Self {
+        if let Some(display) = self.display.as_mut().filter(|display| display.kind == "spice") {
+            display.tls = Some(tls);
+        }
+        self
+    }
+
+    /// Builder preloaded with everything `vm` describes. Per-launch pieces
+    /// (secret objects, the monitor socket, host-specific CPU settings) are
+    /// left to the caller; finish with `build_vm_args`.
+    pub fn for_vm(vm: &VMRecord, disk_path: &str, accel: Accelerator) -> crate::Result<Self> {
+        let netdev = vm_netdev(vm);
+        validate_network_config(&netdev).map_err(Error::InvalidConfig)?;
+
+        let headless = vm.display_mode == "none";
+        let mut display_options = HashMap::new();
+        if !headless {
+            let listen_address = vm.display_listen_address.as_deref().unwrap_or(LOOPBACK_LISTEN_ADDRESS);
+            display_options.insert("addr".to_string(), listen_address.to_string());
+        }
+        if vm.display_mode == "spice" {
+            display_options.insert("disable-ticketing".to_string(), "on".to_string());
+        }
+
+        let machine = match vm.machine_type.as_deref() {
+            Some(name) => MachineType::parse(name),
+            None => machine_for_arch(&vm.arch),
+        };
+        let graphics = vm
+            .graphics
+            .as_deref()
+            .and_then(GraphicsAdapter::parse)
+            .unwrap_or_else(|| GraphicsAdapter::default_for(&vm.display_mode, &machine));
+        let pointer = vm
+            .pointer_device
+            .as_deref()
+            .and_then(PointerDevice::parse)
+            .unwrap_or_else(|| PointerDevice::default_for(GuestOs::parse(&vm.os)));
+        let rtc = vm
+            .rtc
+            .as_deref()
+            .and_then(RtcBase::parse)
+            .unwrap_or_else(|| RtcBase::default_for(GuestOs::parse(&vm.os)));
+
+        let mut command = Self::new()
+            .machine(machine)
+            .accel(accel)
+            .cpu(vm.cpu_cores)
+            .map_err(|detail| Error::InvalidField { field: "cpu_cores".to_string(), detail })?
+            .memory(vm.memory_mb)
+            .map_err(|detail| Error::InvalidField { field: "memory_mb".to_string(), detail })?
+            .rtc(rtc)
+            .drive(DriveConfig {
+                id: "disk0".to_string(),
+                source: match &vm.raw_device_path {
+                    Some(path) => DriveSource::RawDevice { path: path.clone() },
+                    None => DriveSource::File { path: disk_path.to_string() },
+                },
+                format: "qcow2".to_string(),
+                interface: "virtio".to_string(),
+                throttle: IoThrottle::default(),
+                key_secret: vm.encryption_key_ref.clone(),
+                discard: false,
+            })
+            .netdev(netdev)
+            .display(DisplayConfig {
+                kind: vm.display_mode.clone(),
+                port: (!headless).then(|| DisplayPortRange::default().port_for(&vm.id)),
+                options: display_options,
+                clipboard_sharing: vm.clipboard_sharing && vm.display_mode == "spice",
+                resolution: vm.display_resolution.as_deref().and_then(parse_resolution),
+                adapter: Some(graphics),
+                tls: None,
+                compression: vm.spice_compression.clone().unwrap_or_default(),
+            })
+            .pointer_device(pointer);
+
+        if vm.restart_policy == "never" {
+            command = command.no_reboot();
+        }
+        if let Some(boot_menu) = vm.boot_menu {
+            command = command.boot_menu(boot_menu);
+        }
+        if let Some(backend) = vm.memory_backend.as_deref().and_then(MemoryBackend::parse) {
+            command = command.memory_backend(backend);
+        }
+        if let Some(performance) = &vm.performance {
+            command = command.performance(performance.clone());
+        }
+        if vm.rng.unwrap_or_else(|| GuestOs::parse(&vm.os).defaults().rng) {
+            command = command.rng(RngBackend::for_host());
+        }
+        command.validate().map_err(Error::InvalidConfig)?;
+        Ok(command)
+    }
+
+    /// `build()` without the binary name, plus `vm`'s install media, boot
+    /// order, QMP socket and name
+    pub fn build_vm_args(&self, vm: &VMRecord, qmp_socket: &str) -> Vec<String> {
+        let mut args = self.build();
+        if !args.is_empty() {
+            args.remove(0);
+        }
+
+        if let Some(install_media_path) = &vm.install_media_path {
+            args.push("-drive".to_string());
+            args.push(format!("file={},media=cdrom,if=ide,readonly=on", option_value(install_media_path)));
+        }
+
+        // QEMU merges repeated -boot options, so the order adds to the builder's menu settings
+        let mut order = if vm.boot_order == "cdrom-first" { "order=d" } else { "order=c" }.to_string();
+        if let Some(device) = self.boot_once {
+            order.push_str(&format!(",once={}", device.letter()));
+        }
+        args.push("-boot".to_string());
+        if vm.boot_menu.is_some() {
+            args.push(order);
+        } else {
+            args.push(format!("{},menu=on", order));
+        }
+
+        args.push("-qmp".to_string());
+        args.push(format!("unix:{},server=on,wait=off", qmp_socket));
+        args.push("-name".to_string());
+        args.push(vm.name.clone());
+        args
+    }
+
+    /// Complete QEMU arguments (without the binary) for starting `vm` with
+    /// no launch secrets: SPICE ticketing off and no TLS
+    pub fn build_for_vm(
+        vm: &VMRecord,
+        disk_path: &str,
+        qmp_socket: &str,
+        accel: Accelerator,
+    ) -> crate::Result<Vec<String>> {
+        Ok(Self::for_vm(vm, disk_path, accel)?.build_vm_args(vm, qmp_socket))
+    }
+
+    /// Generate command line arguments as Vec<String>
+    pub fn build(&self) -> Vec<String> {
+        let mut args = vec!["qemu-system-x86_64".to_string()];
+
+        // Machine type, pointed at the memory backend object when there is one
+        let memory_object = self.memory_mb.and_then(|mb| self.memory_backend.object(mb));
+        let backend_option = memory_object.as_ref().map(|_| format!("memory-backend={}", MEMORY_BACKEND_ID));
+        match (&self.machine, &backend_option) {
+            (Some(machine), Some(option)) => {
+                args.push("-machine".to_string());
+                args.push(format!("{},{}", machine.as_str(), option));
+            }
+            (Some(machine), None) => {
+                args.push("-machine".to_string());
+                args.push(machine.as_str().to_string());
+            }
+            (None, Some(option)) => {
+                args.push("-machine".to_string());
+                args.push(option.clone());
+            }
+            (None, None) => {}
+        }
+
+        // Accelerator
+        if let Some(accel) = &self.accelerator {
+            args.push("-accel".to_string());
+            let mut accel_str = accel.as_str().to_string();
+            for (k, v) in &self.accel_options {
+                accel_str.push_str(&format!(",{}={}", k, v));
+            }
+            args.push(accel_str);
+        }
+
+        // CPU
+        if let Some(cpu) = self.cpu_count {
+            args.push("-smp".to_string());
+            args.push(cpu.to_string());
+        }
+
+        // CPU model and feature flags; passthrough needs the host CPU with the
+        // KVM signature hidden, or some GPU drivers refuse to load
+        let passthrough = !self.vfio_devices.is_empty();
+        let cpu_model = if passthrough { Some("host") } else { self.cpu_model.as_deref() };
+        if let Some(model) = cpu_model {
+            args.push("-cpu".to_string());
+            let mut cpu_str = model.to_string();
+            for flag in &self.cpu_flags {
+                cpu_str.push_str(&format!(",+{}", flag));
+            }
+            if passthrough {
+                cpu_str.push_str(",kvm=off");
+            }
+            args.push(cpu_str);
+        }
+
+        // Memory
+        if let Some(mem) = self.memory_mb {
+            args.push("-m".to_string());
+            args.push(mem.to_string());
+        }
+        if let Some(object) = memory_object {
+            args.push("-object".to_string());
+            args.push(object);
+        }
+
+        if let Some(rtc) = self.rtc {
+            args.push("-rtc".to_string());
+            args.push(format!("base={},clock=host", rtc.as_str()));
+        }
+
+        // Firmware; the code drive must come first, QEMU maps pflash units in order
+        if let Some((code, vars)) = &self.pflash {
+            args.push("-drive".to_string());
+            args.push(format!("if=pflash,format=raw,readonly=on,file={}", option_value(code)));
+            args.push("-drive".to_string());
+            args.push(format!("if=pflash,format=raw,file={}", option_value(vars)));
+        }
+        // `-bios` takes a bare file name, not an option list, so it is passed as is
+        if let Some(path) = &self.bios {
+            args.push("-bios".to_string());
+            args.push(path.clone());
+        }
+
+        // Objects (must precede the drives that reference them)
+        let iothreads = self.performance.iothreads;
+        for index in 0..iothreads {
+            args.push("-object".to_string());
+            args.push(format!("iothread,id={}", iothread_id(index)));
+        }
+        for object in &self.objects {
+            args.push("-object".to_string());
+            args.push(object.clone());
+        }
+
+        // Drives; virtio-scsi ones share one controller, which gets the first I/O thread
+        if self.drives.iter().any(|drive| drive.interface == VIRTIO_SCSI_INTERFACE) {
+            args.push("-device".to_string());
+            let mut controller = format!("virtio-scsi-pci,id={}", SCSI_CONTROLLER_ID);
+            if iothreads > 0 {
+                controller.push_str(&format!(",iothread={}", iothread_id(0)));
+            }
+            args.push(controller);
+        }
+        let mut virtio_disks = 0;
+        for drive in &self.drives {
+            let scsi = drive.interface == VIRTIO_SCSI_INTERFACE;
+            // `if=virtio` cannot name an I/O thread, so those disks get their own device
+            let blk_iothread = (drive.interface == "virtio" && iothreads > 0).then(|| {
+                virtio_disks += 1;
+                iothread_id((virtio_disks - 1) % iothreads)
+            });
+            // A virtio-scsi drive is only a backend here; its scsi-hd device follows it
+            let interface = if scsi || blk_iothread.is_some() { "none" } else { drive.interface.as_str() };
+            args.push("-drive".to_string());
+            let mut drive_str = match &drive.source {
+                DriveSource::File { path } => format!(
+                    "file={},format={},if={},id={}",
+                    option_value(path), drive.format, interface, drive.id
+                ),
+                // Bypass the host page cache so guest writes hit the device directly
+                DriveSource::RawDevice { path } => format!(
+                    "file={},format=raw,if={},id={},cache=none,aio=native",
+                    option_value(path), interface, drive.id
+                ),
+            };
+            if let Some(secret) = &drive.key_secret {
+                drive_str.push_str(&format!(",encrypt.key-secret={}", secret));
+            }
+            if drive.discard {
+                drive_str.push_str(",discard=unmap,detect-zeroes=unmap");
+            }
+            if !drive.throttle.is_empty() {
+                drive_str.push(',');
+                drive_str.push_str(&drive.throttle.drive_options().join(","));
+            }
+            // Raw devices already use native AIO; native AIO needs O_DIRECT
+            if let (DriveSource::File { .. }, Some(aio)) = (&drive.source, &self.performance.aio) {
+                drive_str.push_str(&format!(",aio={}", aio));
+                if aio == "native" {
+                    drive_str.push_str(",cache.direct=on");
+                }
+            }
+            args.push(drive_str);
+            if scsi {
+                args.push("-device".to_string());
+                args.push(format!("scsi-hd,drive={},bus={}.0", drive.id, SCSI_CONTROLLER_ID));
+            }
+            if let Some(iothread) = blk_iothread {
+                args.push("-device".to_string());
+                args.push(format!("virtio-blk-pci,drive={},iothread={}", drive.id, iothread));
+            }
+        }
+        for disk in &self.hotplug_disks {
+            args.push("-blockdev".to_string());
+            args.push(format!(
+                "driver={},node-name={},file.driver=file,file.filename={}",
+                disk.format,
+                disk.node_name,
+                option_value(&disk.path)
+            ));
+            args.push("-device".to_string());
+            args.push(format!("virtio-blk-pci,id={},drive={}", disk.device_id, disk.node_name));
+        }
+
+        // Netdevs
+        for netdev in &self.netdevs {
+            args.push("-netdev".to_string());
+            let mut netdev_str = format!("{},id={}", netdev.kind, netdev.id);
+            // Sorted so the same config always yields the same argv
+            for (k, v) in netdev.options.iter().collect::<BTreeMap<_, _>>() {
+                netdev_str.push(',');
+                netdev_str.push_str(&format!("{}={}", k, v));
+            }
+            for forward in &netdev.port_forwards {
+                netdev_str.push_str(&format!(
+                    ",hostfwd={}::{}-:{}",
+                    forward.protocol, forward.host_port, forward.guest_port
+                ));
+            }
+            if netdev.vlan_id.is_some() {
+                netdev_str.push_str(",vnet_hdr=on");
+            }
+            let queues = self.net_queues().filter(|_| netdev.kind == "tap");
+            if let Some(queues) = queues {
+                netdev_str.push_str(&format!(",queues={}", queues));
+            }
+            args.push(netdev_str);
+
+            args.push("-device".to_string());
+            let mut device_str = format!("virtio-net-pci,netdev={}", netdev.id);
+            if let Some(mac) = &netdev.mac {
+                device_str.push_str(&format!(",mac={}", mac));
+            }
+            // One MSI-X vector per queue in each direction, plus config and control
+            if let Some(queues) = queues {
+                device_str.push_str(&format!(",mq=on,vectors={}", 2 * queues + 2));
+            }
+            args.push(device_str);
+        }
+
+        // Display
+        if let Some(display) = &self.display {
+            if display.kind == "spice" {
+                args.push("-spice".to_string());
+                let mut spice_str = String::new();
+                if let Some(port) = display.port {
+                    spice_str.push_str(&format!("port={}", port));
+                }
+                if let Some(tls) = &display.tls {
+                    if !spice_str.is_empty() {
+                        spice_str.push(',');
+                    }
+                    spice_str.push_str(&format!("tls-port={},x509-dir={}", tls.port, option_value(&tls.x509_dir)));
+                }
+                if !spice_str.is_empty() {
+                    spice_str.push(',');
+                }
+                spice_str.push_str(&display.compression.spice_options());
+                for (k, v) in display.options.iter().collect::<BTreeMap<_, _>>() {
+                    if !spice_str.is_empty() {
+                        spice_str.push(',');
+                    }
+                    spice_str.push_str(&format!("{}={}", k, v));
+                }
+                args.push(spice_str);
+
+                if display.clipboard_sharing {
+                    args.extend(
+                        [
+                            "-device",
+                            "virtio-serial",
+                            "-chardev",
+                            "spicevmc,id=vdagent,name=vdagent",
+                            "-device",
+                            "virtserialport,chardev=vdagent,name=com.redhat.spice.0",
+                        ]
+                        .map(String::from),
+                    );
+                }
+            } else if display.kind == "vnc" {
+                // `-vnc` takes a display number, counted from port 5900
+                let addr = display.options.get("addr").map_or("127.0.0.1", String::as_str);
+                let number = display.port.map_or(0, |port| port.saturating_sub(VNC_BASE_PORT));
+                args.push("-vnc".to_string());
+                args.push(format!("{}:{}", addr, number));
+            } else if display.kind == "none" {
+                args.extend(["-display", "none", "-vga", "none"].map(String::from));
+            }
+        }
+
+        // Display adapter; a requested resolution needs an explicit device even without a choice
+        if let Some(display) = self.display.as_ref().filter(|display| display.kind != "none") {
+            let adapter = display.adapter.or_else(|| {
+                display.resolution.map(|_| match &self.machine {
+                    Some(machine) if machine.is_virt() => GraphicsAdapter::VirtioGpu,
+                    _ => GraphicsAdapter::VirtioVga,
+                })
+            });
+            if let Some(adapter) = adapter {
+                let mut device = adapter.device().to_string();
+                if let Some((width, height)) = display.resolution {
+                    device.push_str(&format!(",xres={},yres={}", width, height));
+                }
+                args.extend(["-vga".to_string(), "none".to_string(), "-device".to_string(), device]);
+            }
+        }
+
+        // Boot menu
+        if let Some(boot_menu) = &self.boot_menu {
+            args.push("-boot".to_string());
+            if boot_menu.enabled {
+                args.push(format!("menu=on,splash-time={}", boot_menu.splash_time_ms));
+            } else {
+                args.push("menu=off".to_string());
+            }
+        }
+
+        // VFIO passthrough
+        for device in &self.vfio_devices {
+            args.push("-device".to_string());
+            args.push(format!("vfio-pci,host={}", device));
+        }
+
+        // Tablet
+        let tablet = match self.pointer {
+            PointerDevice::UsbTablet => Some("usb-tablet"),
+            PointerDevice::VirtioTablet => Some("virtio-tablet-pci"),
+            PointerDevice::None => None,
+        };
+        if let Some(tablet) = tablet {
+            args.push("-device".to_string());
+            args.push(tablet.to_string());
+        }
+
+        if let Some(backend) = &self.rng {
+            args.push("-object".to_string());
+            args.push(backend.object());
+            args.push("-device".to_string());
+            args.push(format!("virtio-rng-pci,rng={}", RNG_OBJECT_ID));
+        }
+
+        if self.balloon {
+            args.push("-device".to_string());
+            args.push(format!("virtio-balloon-pci,id={}", BALLOON_DEVICE_ID));
+        }
+
+        if self.no_reboot {
+            args.push("-no-reboot".to_string());
+        }
+
+        if let Some(path) = &self.monitor_socket {
+            args.push("-monitor".to_string());
+            args.push(format!("unix:{},server=on,wait=off", path));
+        }
+        if let Some(path) = &self.serial_socket {
+            args.push("-serial".to_string());
+            args.push(format!("unix:{},server=on,wait=off", option_value(path)));
+        }
+
+        args
+    }
+
+    /// Generate complete command line string
+    pub fn build_string(&self) -> String {
+        self.build().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_command_with_accelerator() {
+        let cmd = QemuCommand::new()
+            .accel(Accelerator::Hvf);
+
+        let args = cmd.build();
+        assert!(args.contains(&"-accel".to_string()));
+        assert!(args.contains(&"hvf".to_string()));
+    }
+
+    #[test]
+    fn test_accelerator_options() {
+        let cmd = QemuCommand::new()
+            .accel(Accelerator::Tcg)
+            .accel_option("thread", "multi");
+
+        let args = cmd.build();
+        assert!(args.contains(&"tcg,thread=multi".to_string()));
+    }
+
+    #[test]
+    fn test_set_cpu_count() {
+        let cmd = QemuCommand::new()
+            .cpu(4)
+            .expect("cpu should work");
+
+        let args = cmd.build();
+        assert!(args.contains(&"-smp".to_string()));
+        assert!(args.contains(&"4".to_string()));
+    }
+
+    #[test]
+    fn test_set_memory() {
+        let cmd = QemuCommand::new()
+            .memory(4096)
+            .expect("memory should work");
+
+        let args = cmd.build();
+        assert!(args.contains(&"-m".to_string()));
+        assert!(args.contains(&"4096".to_string()));
+    }
+
+    #[test]
+    fn test_add_drive() {
+        let drive = DriveConfig {
+            id: "disk0".to_string(),
+            source: DriveSource::File {
+                path: "/path/to/disk.qcow2".to_string(),
+            },
+            format: "qcow2".to_string(),
+            interface: "virtio".to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: None,
+            discard: false,
+        };
+
+        let cmd = QemuCommand::new()
+            .drive(drive);
+
+        let args = cmd.build();
+        let args_str = args.join(" ");
+        assert!(args_str.contains("file=/path/to/disk.qcow2"));
+        assert!(args_str.contains("format=qcow2"));
+        assert!(args_str.contains("if=virtio"));
+    }
+
+    #[test]
+    fn test_hotplug_disk_keeps_its_ids() {
+        let args = QemuCommand::new()
+            .hotplug_disk(HotplugDisk {
+                device_id: "disk-a1".to_string(),
+                node_name: "node-a1".to_string(),
+                path: "/data/extra,1.qcow2".to_string(),
+                format: "qcow2".to_string(),
+            })
+            .build()
+            .join(" ");
+        assert!(args.contains(
+            "-blockdev driver=qcow2,node-name=node-a1,file.driver=file,file.filename=/data/extra,,1.qcow2"
+        ));
+        assert!(args.contains("-device virtio-blk-pci,id=disk-a1,drive=node-a1"));
+    }
+
+    #[test]
+    fn test_drive_throttle_options() {
+        let drive = DriveConfig {
+            id: "disk0".to_string(),
+            source: DriveSource::File {
+                path: "/path/to/disk.qcow2".to_string(),
+            },
+            format: "qcow2".to_string(),
+            interface: "virtio".to_string(),
+            throttle: IoThrottle {
+                read_bps: Some(50_000_000),
+                ..Default::default()
+            },
+            key_secret: None,
+            discard: false,
+        };
+
+        let args = QemuCommand::new().drive(drive).build();
+        let drive_arg = args
+            .iter()
+            .find(|arg| arg.starts_with("file="))
+            .expect("drive argument");
+        assert!(drive_arg.ends_with(",throttling.bps-read=50000000"));
+        assert!(!drive_arg.contains("bps-write"));
+    }
+
+    #[test]
+    fn test_drive_discard() {
+        let drive = DriveConfig {
+            id: "disk0".to_string(),
+            source: DriveSource::File {
+                path: "/path/to/disk.qcow2".to_string(),
+            },
+            format: "qcow2".to_string(),
+            interface: "virtio".to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: None,
+            discard: false,
+        };
+
+        let args = QemuCommand::new().drive(drive.clone()).build();
+        assert!(!args.join(" ").contains("discard"));
+
+        let args = QemuCommand::new().drive(drive).discard("disk0").discard("missing").build();
+        let expected = "file=/path/to/disk.qcow2,format=qcow2,if=virtio,id=disk0,discard=unmap,detect-zeroes=unmap";
+        assert!(args.contains(&expected.to_string()));
+    }
+
+    #[test]
+    fn test_virtio_scsi_drives_share_one_controller() {
+        let drive = |id: &str| DriveConfig {
+            id: id.to_string(),
+            source: DriveSource::File {
+                path: format!("/disks/{}.qcow2", id),
+            },
+            format: "qcow2".to_string(),
+            interface: VIRTIO_SCSI_INTERFACE.to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: None,
+            discard: true,
+        };
+
+        let args = QemuCommand::new().drive(drive("disk0")).drive(drive("disk1")).build();
+        let controllers = args.iter().filter(|arg| arg.starts_with("virtio-scsi-pci")).count();
+        assert_eq!(controllers, 1);
+        let controller = args.iter().position(|arg| arg == "virtio-scsi-pci,id=scsi0").unwrap();
+        let expected = "file=/disks/disk0.qcow2,format=qcow2,if=none,id=disk0,discard=unmap,detect-zeroes=unmap";
+        let backend = args.iter().position(|arg| arg == expected).expect("disk0 backend");
+        assert!(controller < backend);
+        assert_eq!(args[backend + 1..backend + 3], ["-device", "scsi-hd,drive=disk0,bus=scsi0.0"]);
+        assert!(args.contains(&"scsi-hd,drive=disk1,bus=scsi0.0".to_string()));
+    }
+
+    #[test]
+    fn test_other_interfaces_pass_through_without_scsi_controller() {
+        let drive = DriveConfig {
+            id: "disk0".to_string(),
+            source: DriveSource::File {
+                path: "/disks/disk0.qcow2".to_string(),
+            },
+            format: "qcow2".to_string(),
+            interface: "virtio".to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: None,
+            discard: false,
+        };
+
+        for interface in ["virtio", "ide", "sata"] {
+            let args = QemuCommand::new().drive(drive.clone()).drive_interface("disk0", interface).build();
+            let expected = format!("file=/disks/disk0.qcow2,format=qcow2,if={},id=disk0", interface);
+            assert!(args.contains(&expected));
+            assert!(!args.iter().any(|arg| arg.contains("scsi")));
+        }
+    }
+
+    #[test]
+    fn test_add_network() {
+        let mut opts = HashMap::new();
+        opts.insert("hostfwd".to_string(), "tcp::2222-:22".to_string());
+
+        let netdev = NetdevConfig {
+            id: "net0".to_string(),
+            kind: "user".to_string(),
+            options: opts,
+            vlan_id: None,
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+
+        let cmd = QemuCommand::new()
+            .netdev(netdev);
+
+        let args = cmd.build();
+        let args_str = args.join(" ");
+        assert!(args_str.contains("-netdev"));
+        assert!(args_str.contains("user,id=net0"));
+        assert!(args_str.contains("hostfwd=tcp::2222-:22"));
+    }
+
+    #[test]
+    fn test_generate_stable_mac() {
+        let mac = generate_stable_mac("6f1c2a9e-5d4b-4e3a-9c1f-0a2b3c4d5e6f");
+        assert_eq!(mac, generate_stable_mac("6f1c2a9e-5d4b-4e3a-9c1f-0a2b3c4d5e6f"));
+        assert!(mac.starts_with("52:54:00:"));
+        assert!(is_valid_mac(&mac), "{}", mac);
+        assert_eq!(mac, mac.to_ascii_lowercase());
+        assert_ne!(mac, generate_stable_mac("6f1c2a9e-5d4b-4e3a-9c1f-0a2b3c4d5e70"));
+
+        assert!(!is_valid_mac("52:54:00:12:34"));
+        assert!(!is_valid_mac("52:54:00:12:34:zz"));
+        assert!(!is_valid_mac("52-54-00-12-34-56"));
+    }
+
+    #[test]
+    fn test_netdev_gets_nic_with_mac() {
+        let netdev = NetdevConfig {
+            id: "net0".to_string(),
+            kind: "user".to_string(),
+            options: HashMap::new(),
+            vlan_id: None,
+            mac: Some("52:54:00:12:34:56".to_string()),
+            port_forwards: Vec::new(),
+        };
+        let args = QemuCommand::new().netdev(netdev).build().join(" ");
+        assert!(args.contains("-netdev user,id=net0 -device virtio-net-pci,netdev=net0,mac=52:54:00:12:34:56"));
+    }
+
+    #[test]
+    fn test_networks_replace_the_single_nic() {
+        let single = NetdevConfig {
+            id: "net0".to_string(),
+            kind: "user".to_string(),
+            options: HashMap::new(),
+            vlan_id: None,
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+        let networks = vec![
+            NetworkConfig {
+                id: "mgmt".to_string(),
+                kind: "nat".to_string(),
+                mac: Some("52:54:00:00:00:01".to_string()),
+                port_forwards: vec![
+                    PortForward { protocol: "tcp".to_string(), host_port: 2222, guest_port: 22 },
+                    PortForward { protocol: "udp".to_string(), host_port: 5353, guest_port: 53 },
+                ],
+            },
+            NetworkConfig { id: "data".to_string(), kind: "bridge".to_string(), mac: None, port_forwards: Vec::new() },
+        ];
+
+        let args = QemuCommand::new().netdev(single.clone()).networks(&networks).build().join(" ");
+        assert!(!args.contains("id=net0"));
+        assert!(args.contains(concat!(
+            "-netdev user,id=mgmt,hostfwd=tcp::2222-:22,hostfwd=udp::5353-:53 ",
+            "-device virtio-net-pci,netdev=mgmt,mac=52:54:00:00:00:01 ",
+            "-netdev bridge,id=data -device virtio-net-pci,netdev=data"
+        )));
+
+        let args = QemuCommand::new().netdev(single).networks(&[]).build().join(" ");
+        assert!(args.contains("-netdev user,id=net0"));
+    }
+
+    #[test]
+    fn test_vlan_tap_netdev() {
+        let mut options = HashMap::new();
+        options.insert("ifname".to_string(), "vlan42".to_string());
+        let netdev = NetdevConfig {
+            id: "net0".to_string(),
+            kind: "tap".to_string(),
+            options,
+            vlan_id: Some(42),
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+        assert_eq!(validate_network_config(&netdev), Ok(()));
+
+        let args = QemuCommand::new().netdev(netdev).build();
+        assert!(args.contains(&"tap,id=net0,ifname=vlan42,vnet_hdr=on".to_string()));
+    }
+
+    #[test]
+    fn test_vm_netdev_uses_own_tap_on_vlan() {
+        let vm = VMRecord { network_type: "bridge".to_string(), vlan_id: Some(42), ..vm_record() };
+        let netdev = vm_netdev(&vm);
+        assert_eq!(netdev.kind, "tap");
+        assert_eq!(netdev.options["ifname"], vlan_tap_name("vm-1"));
+        assert_eq!(validate_network_config(&netdev), Ok(()));
+
+        // Interface names are capped at 15 bytes and must differ between VMs
+        assert!(vlan_tap_name("a-much-longer-vm-identifier-than-usual").len() <= 15);
+        assert_ne!(vlan_tap_name("vm-1"), vlan_tap_name("vm-2"));
+    }
+
+    #[test]
+    fn test_validate_network_config() {
+        let mut netdev = NetdevConfig {
+            id: "net0".to_string(),
+            kind: "user".to_string(),
+            options: HashMap::new(),
+            vlan_id: None,
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+        assert_eq!(validate_network_config(&netdev), Ok(()));
+
+        netdev.vlan_id = Some(10);
+        assert_eq!(
+            validate_network_config(&netdev),
+            Err("VLAN requires TAP or bridge network".to_string())
+        );
+
+        netdev.kind = "tap".to_string();
+        netdev.options.insert("ifname".to_string(), "vlan10".to_string());
+        assert_eq!(validate_network_config(&netdev), Ok(()));
+
+        for vlan_id in [0, 4095] {
+            netdev.vlan_id = Some(vlan_id);
+            assert!(validate_network_config(&netdev).is_err(), "{}", vlan_id);
+        }
+    }
+
+    fn vm_record() -> VMRecord {
+        VMRecord {
+            id: "vm-1".to_string(),
+            name: "Fedora".to_string(),
+            memory_mb: 4096,
+            cpu_cores: 4,
+            disk_size_gb: 32,
+            install_media_path: Some("/isos/fedora.iso".to_string()),
+            boot_order: "cdrom-first".to_string(),
+            restart_policy: "never".to_string(),
+            preallocation: "off".to_string(),
+            clipboard_sharing: true,
+            display_resolution: Some("1920x1080".to_string()),
+            mac_address: Some("52:54:00:12:34:56".to_string()),
+            ..VMRecord::default()
+        }
+    }
+
+    const DEFAULT_SPICE_COMPRESSION: &str =
+        "image-compression=auto_glz,jpeg-wan-compression=auto,zlib-glz-wan-compression=auto,streaming-video=filter";
+
+    #[test]
+    fn test_build_for_vm_full_argument_list() {
+        let args = QemuCommand::build_for_vm(&vm_record(), "/disks/vm-1.qcow2", "/run/qmp-vm-1.sock", Accelerator::Kvm)
+            .unwrap();
+        let port = DisplayPortRange::default().port_for("vm-1");
+        let rng = RngBackend::for_host().object();
+        let expected = [
+            "-machine", "q35",
+            "-accel", "kvm",
+            "-smp", "4",
+            "-m", "4096",
+            "-rtc", "base=utc,clock=host",
+            "-drive", "file=/disks/vm-1.qcow2,format=qcow2,if=virtio,id=disk0",
+            "-netdev", "user,id=net0",
+            "-device", "virtio-net-pci,netdev=net0,mac=52:54:00:12:34:56",
+            "-spice", &format!("port={},{},addr=127.0.0.1,disable-ticketing=on", port, DEFAULT_SPICE_COMPRESSION),
+            "-device", "virtio-serial",
+            "-chardev", "spicevmc,id=vdagent,name=vdagent",
+            "-device", "virtserialport,chardev=vdagent,name=com.redhat.spice.0",
+            "-vga", "none",
+            "-device", "qxl-vga,xres=1920,yres=1080",
+            "-device", "virtio-tablet-pci",
+            "-object", &rng,
+            "-device", "virtio-rng-pci,rng=rng0",
+            "-no-reboot",
+            "-drive", "file=/isos/fedora.iso,media=cdrom,if=ide,readonly=on",
+            "-boot", "order=d,menu=on",
+            "-qmp", "unix:/run/qmp-vm-1.sock,server=on,wait=off",
+            "-name", "Fedora",
+        ];
+        assert_eq!(args, expected);
+    }
+
+    #[test]
+    fn test_paths_with_commas_are_escaped() {
+        let args = QemuCommand::build_for_vm(&vm_record(), "/mnt/a,b/vm-1.qcow2", "/run/qmp.sock", Accelerator::Kvm)
+            .unwrap();
+        assert!(args.contains(&"file=/mnt/a,,b/vm-1.qcow2,format=qcow2,if=virtio,id=disk0".to_string()));
+
+        let vm = VMRecord { install_media_path: Some("/isos/a,b.iso".to_string()), ..vm_record() };
+        let args = QemuCommand::build_for_vm(&vm, "/disks/vm-1.qcow2", "/run/qmp.sock", Accelerator::Kvm).unwrap();
+        assert!(args.contains(&"file=/isos/a,,b.iso,media=cdrom,if=ide,readonly=on".to_string()));
+    }
+
+    #[test]
+    fn test_build_for_vm_variants() {
+        let vm = VMRecord {
+            install_media_path: None,
+            boot_order: "disk-first".to_string(),
+            restart_policy: "on-failure".to_string(),
+            arch: "aarch64".to_string(),
+            encryption_key_ref: Some("luks-vm-1".to_string()),
+            display_mode: "vnc".to_string(),
+            boot_menu: Some(BootMenuConfig { enabled: true, splash_time_ms: 0 }),
+            display_listen_address: Some("0.0.0.0".to_string()),
+            ..vm_record()
+        };
+        let joined = QemuCommand::build_for_vm(&vm, "/disks/vm-1.qcow2", "/run/qmp.sock", Accelerator::Tcg)
+            .unwrap()
+            .join(" ");
+        assert!(joined.starts_with("-machine virt -accel tcg "));
+        assert!(joined.contains("encrypt.key-secret=luks-vm-1"));
+        assert!(joined.contains(&format!("-vnc 0.0.0.0:{}", DisplayPortRange::default().port_for("vm-1") - 5900)));
+        assert!(!joined.contains("-spice"));
+        assert!(!joined.contains("vdagent"));
+        assert!(!joined.contains("-no-reboot"));
+        assert!(!joined.contains("media=cdrom"));
+        assert!(joined.contains("-boot order=c "));
+
+        let args = QemuCommand::for_vm(&vm, "/disks/vm-1.qcow2", Accelerator::Tcg)
+            .unwrap()
+            .boot_once(BootDevice::Cdrom)
+            .build_vm_args(&vm, "/run/qmp.sock");
+        assert!(args.contains(&"order=c,once=d".to_string()));
+
+        let zero_memory = VMRecord { memory_mb: 0, ..vm_record() };
+        let err = QemuCommand::build_for_vm(&zero_memory, "/disks/vm-1.qcow2", "/run/qmp.sock", Accelerator::Kvm);
+        assert!(matches!(err, Err(Error::InvalidField { field, .. }) if field == "memory_mb"));
+    }
+
+    #[test]
+    fn test_spice_password_secret_and_tls() {
+        let args = QemuCommand::for_vm(&vm_record(), "/disks/vm-1.qcow2", Accelerator::Kvm)
+            .unwrap()
+            .spice_password_secret("spice-password")
+            .spice_tls(SpiceTls { port: 5999, x509_dir: "/data/spice-tls/vm-1".to_string() })
+            .build_vm_args(&vm_record(), "/run/qmp.sock");
+        let spice = args.iter().position(|arg| arg == "-spice").unwrap();
+        assert_eq!(
+            args[spice + 1],
+            format!(
+                "port={},tls-port=5999,x509-dir=/data/spice-tls/vm-1,{},addr=127.0.0.1,password-secret=spice-password",
+                DisplayPortRange::default().port_for("vm-1"),
+                DEFAULT_SPICE_COMPRESSION
+            )
+        );
+
+        // Neither applies to a VNC display
+        let vnc = VMRecord { display_mode: "vnc".to_string(), ..vm_record() };
+        let joined = QemuCommand::for_vm(&vnc, "/disks/vm-1.qcow2", Accelerator::Kvm)
+            .unwrap()
+            .spice_password_secret("spice-password")
+            .spice_tls(SpiceTls { port: 5999, x509_dir: "/data/spice-tls/vm-1".to_string() })
+            .build_vm_args(&vnc, "/run/qmp.sock")
+            .join(" ");
+        assert!(!joined.contains("password-secret"));
+        assert!(!joined.contains("tls-port"));
+    }
+
+    #[test]
+    fn test_resolve_spice_port_is_stable_and_in_range() {
+        let port = resolve_spice_port("vm-1", 5900, 6899);
+        assert_eq!(port, resolve_spice_port("vm-1", 5900, 6899));
+        assert!((5900..=6899).contains(&port));
+    }
+
+    #[test]
+    fn test_resolve_spice_port_uses_the_configured_range() {
+        assert_eq!(DisplayPortRange::default().port_for("vm-1"), resolve_spice_port("vm-1", 5900, 6899));
+        let port = resolve_spice_port("vm-1", 10000, 11000);
+        assert_eq!(port, resolve_spice_port("vm-1", 10000, 11000));
+        assert!((10000..=11000).contains(&port));
+        for id in ["a", "vm-2", "0f6c1f0e-4a43-4b1e-9d8e-7f5f0c7b9b11"] {
+            assert!((10000..=11000).contains(&resolve_spice_port(id, 10000, 11000)));
+        }
+        assert_eq!(resolve_spice_port("vm-1", 7000, 7000), 7000);
+        assert_eq!(resolve_spice_port("vm-1", 0, u16::MAX), resolve_spice_port("vm-1", 0, u16::MAX));
+    }
+
+    #[test]
+    fn test_display_port_range_validity() {
+        assert!(DisplayPortRange::default().is_valid());
+        assert!(DisplayPortRange { start: 10000, end: 10000 }.is_valid());
+        assert!(!DisplayPortRange { start: 6000, end: 5999 }.is_valid());
+        assert!(!DisplayPortRange { start: 5000, end: 6000 }.is_valid());
+    }
+
+    #[test]
+    fn test_display_port_override() {
+        let display = |kind: &str, port: Option<u16>| DisplayConfig {
+            kind: kind.to_string(),
+            port,
+            options: Default::default(),
+            clipboard_sharing: false,
+            resolution: None,
+            adapter: None,
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+
+        let joined = QemuCommand::new().display(display("vnc", Some(5900))).display_port(10005).build().join(" ");
+        assert!(joined.contains("-vnc 127.0.0.1:4105"));
+        let joined = QemuCommand::new().display(display("spice", Some(5900))).display_port(10005).build().join(" ");
+        assert!(joined.contains("port=10005,"));
+        let joined = QemuCommand::new().display(display("none", None)).display_port(10005).build().join(" ");
+        assert!(!joined.contains("10005") && !joined.contains("4105"));
+    }
+
+    #[test]
+    fn test_display_port_from_args() {
+        let display = |kind: &str, port: Option<u16>| DisplayConfig {
+            kind: kind.to_string(),
+            port,
+            options: Default::default(),
+            clipboard_sharing: false,
+            resolution: None,
+            adapter: None,
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+
+        let args = QemuCommand::new().display(display("spice", Some(5931))).build();
+        assert_eq!(display_port_from_args(&args), Some(5931));
+        let args = QemuCommand::new().display(display("vnc", Some(5907))).build();
+        assert_eq!(display_port_from_args(&args), Some(5907));
+        let args = QemuCommand::new().display(display("none", None)).build();
+        assert_eq!(display_port_from_args(&args), None);
+    }
+
+    #[test]
+    fn test_add_spice_display() {
+        let display = DisplayConfig {
+            kind: "spice".to_string(),
+            port: Some(5900),
+            options: Default::default(),
+            clipboard_sharing: false,
+            resolution: None,
+            adapter: None,
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+
+        let cmd = QemuCommand::new()
+            .display(display);
+
+        let args = cmd.build();
+        assert!(args.contains(&"-spice".to_string()));
+        let args_str = args.join(" ");
+        assert!(args_str.contains("port=5900"));
+        assert!(!args_str.contains("vdagent"));
+        assert!(!args_str.contains("xres="));
+    }
+
+    #[test]
+    fn test_spice_tls_port() {
+        let display = DisplayConfig {
+            kind: "spice".to_string(),
+            port: Some(5900),
+            options: HashMap::from([("addr".to_string(), "0.0.0.0".to_string())]),
+            clipboard_sharing: false,
+            resolution: None,
+            adapter: None,
+            tls: Some(SpiceTls { port: 5901, x509_dir: "/data/spice-tls/vm-1".to_string() }),
+            compression: SpiceCompression::default(),
+        };
+
+        let args = QemuCommand::new().display(display).build();
+        let spice = args.iter().position(|arg| arg == "-spice").unwrap();
+        assert_eq!(
+            args[spice + 1],
+            format!("port=5900,tls-port=5901,x509-dir=/data/spice-tls/vm-1,{},addr=0.0.0.0", DEFAULT_SPICE_COMPRESSION)
+        );
+    }
+
+    #[test]
+    fn test_spice_compression() {
+        let compression = SpiceCompression {
+            image_compression: "quic".to_string(),
+            jpeg_wan_compression: "never".to_string(),
+            zlib_glz_wan_compression: "always".to_string(),
+            streaming_video: "off".to_string(),
+        };
+        assert_eq!(compression.invalid_option(), None);
+        assert_eq!(SpiceCompression::default().invalid_option(), None);
+        let record = VMRecord { spice_compression: Some(compression), ..vm_record() };
+        let args = QemuCommand::build_for_vm(&record, "/disks/vm-1.qcow2", "/run/qmp.sock", Accelerator::Kvm).unwrap();
+        let spice = args.iter().position(|arg| arg == "-spice").unwrap();
+        assert!(args[spice + 1].contains(
+            "image-compression=quic,jpeg-wan-compression=never,zlib-glz-wan-compression=always,streaming-video=off"
+        ));
+
+        // Not a SPICE display, so nothing to compress
+        let vnc = VMRecord { display_mode: "vnc".to_string(), ..record };
+        let args = QemuCommand::build_for_vm(&vnc, "/disks/vm-1.qcow2", "/run/qmp.sock", Accelerator::Kvm).unwrap();
+        assert!(!args.join(" ").contains("image-compression"));
+
+        let bad = SpiceCompression { streaming_video: "sometimes".to_string(), ..SpiceCompression::default() };
+        assert_eq!(bad.invalid_option(), Some(("streaming-video", "sometimes")));
+    }
+
+    #[test]
+    fn test_spice_clipboard_sharing() {
+        let display = DisplayConfig {
+            kind: "spice".to_string(),
+            port: Some(5900),
+            options: Default::default(),
+            clipboard_sharing: true,
+            resolution: None,
+            adapter: None,
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+
+        let args = QemuCommand::new().display(display).build();
+        let args_str = args.join(" ");
+        assert!(args_str.contains("-device virtio-serial"));
+        assert!(args_str.contains("-chardev spicevmc,id=vdagent,name=vdagent"));
+        assert!(args_str.contains("-device virtserialport,chardev=vdagent,name=com.redhat.spice.0"));
+    }
+
+    #[test]
+    fn test_vnc_display() {
+        let display = DisplayConfig {
+            kind: "vnc".to_string(),
+            port: Some(5907),
+            options: HashMap::from([("addr".to_string(), "127.0.0.1".to_string())]),
+            clipboard_sharing: false,
+            resolution: None,
+            adapter: None,
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+        let args = QemuCommand::new().display(display).build().join(" ");
+        assert!(args.contains("-vnc 127.0.0.1:7"));
+        assert!(!args.contains("-spice"));
+    }
+
+    #[test]
+    fn test_headless_display() {
+        let display = DisplayConfig {
+            kind: "none".to_string(),
+            port: None,
+            options: HashMap::new(),
+            clipboard_sharing: false,
+            resolution: Some((1920, 1080)),
+            adapter: Some(GraphicsAdapter::Qxl),
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+        let args = QemuCommand::new().display(display).build().join(" ");
+        assert!(args.contains("-display none -vga none"));
+        assert!(!args.contains("-spice"));
+        assert!(!args.contains("-device qxl-vga"));
+    }
+
+    #[test]
+    fn test_display_resolution_device() {
+        let display = DisplayConfig {
+            kind: "spice".to_string(),
+            port: Some(5900),
+            options: Default::default(),
+            clipboard_sharing: false,
+            resolution: Some((1920, 1080)),
+            adapter: None,
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+
+        let args = QemuCommand::new().machine(MachineType::Q35).display(display.clone()).build();
+        assert!(args.contains(&"virtio-vga,xres=1920,yres=1080".to_string()));
+
+        let args = QemuCommand::new().machine(MachineType::Virt).display(display).build();
+        assert!(args.contains(&"virtio-gpu-pci,xres=1920,yres=1080".to_string()));
+    }
+
+    #[test]
+    fn test_graphics_adapter_devices() {
+        let cases = [
+            (GraphicsAdapter::VirtioVga, "virtio-vga"),
+            (GraphicsAdapter::Qxl, "qxl-vga"),
+            (GraphicsAdapter::Std, "VGA"),
+            (GraphicsAdapter::VirtioGpu, "virtio-gpu-pci"),
+        ];
+        for (adapter, device) in cases {
+            let display = DisplayConfig {
+                kind: "spice".to_string(),
+                port: Some(5900),
+                options: Default::default(),
+                clipboard_sharing: false,
+                resolution: None,
+                adapter: Some(adapter),
+                tls: None,
+                compression: SpiceCompression::default(),
+            };
+            let args = QemuCommand::new().display(display).build().join(" ");
+            assert!(args.contains(&format!("-vga none -device {}", device)), "{}", args);
+            assert_eq!(GraphicsAdapter::parse(adapter.as_str()), Some(adapter));
+        }
+    }
+
+    #[test]
+    fn test_graphics_adapter_defaults() {
+        assert_eq!(GraphicsAdapter::default_for("spice", &MachineType::Q35), GraphicsAdapter::Qxl);
+        assert_eq!(GraphicsAdapter::default_for("vnc", &MachineType::Q35), GraphicsAdapter::Std);
+        assert_eq!(GraphicsAdapter::default_for("vnc", &MachineType::Virt), GraphicsAdapter::VirtioGpu);
+        assert_eq!(GraphicsAdapter::default_for("spice", &MachineType::Virt), GraphicsAdapter::VirtioGpu);
+        assert_eq!(GraphicsAdapter::parse("cirrus"), None);
+
+        // A VM without a chosen adapter gets the one for its own display mode
+        let vm = VMRecord { display_mode: "vnc".to_string(), ..vm_record() };
+        let args = QemuCommand::for_vm(&vm, "/disks/vm-1.qcow2", Accelerator::Kvm).unwrap().build().join(" ");
+        assert!(args.contains("-vga none -device VGA"), "{}", args);
+    }
+
+    #[test]
+    fn test_parse_resolution() {
+        assert_eq!(parse_resolution("1920x1080"), Some((1920, 1080)));
+        assert_eq!(parse_resolution(" 640X480 "), Some((640, 480)));
+        assert_eq!(parse_resolution("7680x4320"), Some((7680, 4320)));
+        assert_eq!(parse_resolution("320x200"), None);
+        assert_eq!(parse_resolution("8000x4320"), None);
+        assert_eq!(parse_resolution("1920"), None);
+        assert_eq!(parse_resolution("widexhigh"), None);
+    }
+
+    #[test]
+    fn test_add_usb_tablet() {
+        let args = QemuCommand::new().pointer_device(PointerDevice::UsbTablet).build();
+        assert!(args.windows(2).any(|pair| pair == ["-device", "usb-tablet"]));
+
+        #[allow(deprecated)]
+        let args = QemuCommand::new().usb_tablet().build();
+        assert!(args.windows(2).any(|pair| pair == ["-device", "usb-tablet"]));
+    }
+
+    #[test]
+    fn test_rtc_base() {
+        let args = QemuCommand::new().rtc(RtcBase::Localtime).build();
+        assert!(args.windows(2).any(|pair| pair == ["-rtc", "base=localtime,clock=host"]));
+        assert!(!QemuCommand::new().build().contains(&"-rtc".to_string()));
+    }
+
+    #[test]
+    fn test_windows_vm_defaults_to_localtime_rtc() {
+        let rtc = |vm: &VMRecord| {
+            let args = QemuCommand::for_vm(vm, "/disks/vm-1.qcow2", Accelerator::Tcg).unwrap().build();
+            let flag = args.iter().position(|arg| arg == "-rtc").unwrap();
+            args[flag + 1].clone()
+        };
+        let mut vm = VMRecord { os: "windows".to_string(), ..vm_record() };
+        assert_eq!(rtc(&vm), "base=localtime,clock=host");
+        vm.rtc = Some("utc".to_string());
+        assert_eq!(rtc(&vm), "base=utc,clock=host");
+        assert_eq!(rtc(&vm_record()), "base=utc,clock=host");
+    }
+
+    #[test]
+    fn test_rng_backends() {
+        let args = QemuCommand::new().rng(RngBackend::Random("/dev/urandom".to_string())).build();
+        assert!(args.windows(2).any(|pair| pair == ["-object", "rng-random,id=rng0,filename=/dev/urandom"]));
+        assert!(args.windows(2).any(|pair| pair == ["-device", "virtio-rng-pci,rng=rng0"]));
+
+        let args = QemuCommand::new().rng(RngBackend::Builtin).build();
+        assert!(args.windows(2).any(|pair| pair == ["-object", "rng-builtin,id=rng0"]));
+        assert!(!QemuCommand::new().build().iter().any(|arg| arg.contains("rng")));
+    }
+
+    #[test]
+    fn test_rng_defaults_on_for_linux_guests_only() {
+        let has_rng = |vm: &VMRecord| {
+            let args = QemuCommand::for_vm(vm, "/disks/vm-1.qcow2", Accelerator::Tcg).unwrap().build();
+            args.contains(&"virtio-rng-pci,rng=rng0".to_string())
+        };
+        let mut vm = VMRecord { os: "windows".to_string(), ..vm_record() };
+        assert!(!has_rng(&vm));
+        vm.rng = Some(true);
+        assert!(has_rng(&vm));
+        assert!(has_rng(&vm_record()));
+        assert!(!has_rng(&VMRecord { rng: Some(false), ..vm_record() }));
+    }
+
+    #[test]
+    fn test_add_virtio_tablet() {
+        let args = QemuCommand::new().virtio_tablet().build();
+        assert!(args.windows(2).any(|pair| pair == ["-device", "virtio-tablet-pci"]));
+        assert!(!args.contains(&"usb-tablet".to_string()));
+
+        let args = QemuCommand::new().pointer_device(PointerDevice::None).build();
+        assert!(!args.iter().any(|arg| arg.contains("tablet")));
+    }
+
+    #[test]
+    fn test_for_vm_picks_pointer_for_guest_os() {
+        let tablet = |vm: &VMRecord| {
+            let args = QemuCommand::for_vm(vm, "/disks/vm-1.qcow2", Accelerator::Tcg).unwrap().build();
+            args.into_iter().find(|arg| arg.contains("tablet"))
+        };
+        let mut vm = vm_record();
+        assert_eq!(tablet(&vm).as_deref(), Some("virtio-tablet-pci"));
+        vm.os = "windows".to_string();
+        assert_eq!(tablet(&vm).as_deref(), Some("usb-tablet"));
+        vm.pointer_device = Some("none".to_string());
+        assert_eq!(tablet(&vm), None);
+    }
+
+    #[test]
+    fn test_nested_virt_emits_host_cpu_with_flag() {
+        let cmd = QemuCommand::new()
+            .nested_virt("vmx");
+
+        let args = cmd.build();
+        let args_str = args.join(" ");
+        assert!(args_str.contains("-cpu host,+vmx"));
+    }
+
+    #[test]
+    fn test_cpu_flags_ignored_without_model() {
+        let cmd = QemuCommand::new()
+            .cpu_flag("svm");
+
+        let args = cmd.build();
+        assert!(!args.contains(&"-cpu".to_string()));
+    }
+
+    #[test]
+    fn test_balloon_device() {
+        let args = QemuCommand::new().balloon().build();
+        let pos = args.iter().position(|arg| arg == "virtio-balloon-pci,id=balloon0").unwrap();
+        assert_eq!(args[pos - 1], "-device");
+        assert!(!QemuCommand::new().build().iter().any(|arg| arg.contains("virtio-balloon")));
+    }
+
+    #[test]
+    fn test_no_reboot() {
+        let cmd = QemuCommand::new()
+            .no_reboot();
+
+        let args = cmd.build();
+        assert!(args.contains(&"-no-reboot".to_string()));
+        assert!(!QemuCommand::new().build().contains(&"-no-reboot".to_string()));
+    }
+
+    #[test]
+    fn test_validate_cpu_count() {
+        let result = QemuCommand::new().cpu(0);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), "CPU count must be > 0");
+    }
+
+    #[test]
+    fn test_validate_memory() {
+        let result = QemuCommand::new().memory(0);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), "Memory must be > 0 MB");
+    }
+
+    #[test]
+    fn test_pflash_code_before_vars() {
+        let cmd = QemuCommand::new().pflash("/fw/OVMF_CODE.fd", "/vm/OVMF_VARS.fd");
+        assert!(cmd.validate().is_ok());
+
+        let args = cmd.build();
+        let code = args
+            .iter()
+            .position(|arg| arg == "if=pflash,format=raw,readonly=on,file=/fw/OVMF_CODE.fd")
+            .unwrap();
+        let vars = args.iter().position(|arg| arg == "if=pflash,format=raw,file=/vm/OVMF_VARS.fd").unwrap();
+        assert_eq!(args[code - 1], "-drive");
+        assert_eq!(args[vars - 1], "-drive");
+        assert!(code < vars);
+
+        let args = QemuCommand::new().pflash("/fw/a,b/CODE.fd", "/vm/VARS,1.fd").build();
+        assert!(args.contains(&"if=pflash,format=raw,readonly=on,file=/fw/a,,b/CODE.fd".to_string()));
+        assert!(args.contains(&"if=pflash,format=raw,file=/vm/VARS,,1.fd".to_string()));
+    }
+
+    #[test]
+    fn test_bios_override() {
+        let cmd = QemuCommand::new().bios("/fw/bios.bin");
+        assert!(cmd.validate().is_ok());
+        let args = cmd.build();
+        let pos = args.iter().position(|arg| arg == "-bios").unwrap();
+        assert_eq!(args[pos + 1], "/fw/bios.bin");
+        let args = QemuCommand::new().bios("/fw/a,b.bin").build();
+        assert!(args.contains(&"/fw/a,b.bin".to_string()));
+    }
+
+    #[test]
+    fn test_validate_firmware() {
+        assert!(QemuCommand::new().pflash("", "/vm/vars.fd").validate().is_err());
+        assert!(QemuCommand::new().pflash("/fw/code.fd", "").validate().is_err());
+        assert!(QemuCommand::new().bios("").validate().is_err());
+        assert_eq!(
+            QemuCommand::new().pflash("/fw/code.fd", "/vm/vars.fd").bios("/fw/bios.bin").validate(),
+            Err("pflash firmware and a BIOS override cannot be combined".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hugepages_backend() {
+        let args = QemuCommand::new()
+            .machine(MachineType::Q35)
+            .memory(4096)
+            .unwrap()
+            .memory_backend(MemoryBackend::Hugepages)
+            .build();
+        let machine = args.iter().position(|arg| arg == "-machine").unwrap();
+        assert_eq!(args[machine + 1], "q35,memory-backend=mem0");
+        let object = args.iter().position(|arg| arg.starts_with("memory-backend-file")).unwrap();
+        assert_eq!(args[object - 1], "-object");
+        assert_eq!(args[object], "memory-backend-file,id=mem0,size=4096M,mem-path=/dev/hugepages,prealloc=on");
+    }
+
+    #[test]
+    fn test_prealloc_backend_without_machine() {
+        let args = QemuCommand::new()
+            .memory(2048)
+            .unwrap()
+            .memory_backend(MemoryBackend::Prealloc)
+            .build();
+        let machine = args.iter().position(|arg| arg == "-machine").unwrap();
+        assert_eq!(args[machine + 1], "memory-backend=mem0");
+        assert!(args.contains(&"memory-backend-memfd,id=mem0,size=2048M,prealloc=on".to_string()));
+    }
+
+    #[test]
+    fn test_default_backend_adds_nothing() {
+        let args = QemuCommand::new()
+            .machine(MachineType::Q35)
+            .memory(2048)
+            .unwrap()
+            .memory_backend(MemoryBackend::Default)
+            .build();
+        assert!(args.contains(&"q35".to_string()));
+        assert!(!args.iter().any(|arg| arg.contains("memory-backend")));
+    }
+
+    fn perf_drive(id: &str, interface: &str) -> DriveConfig {
+        DriveConfig {
+            id: id.to_string(),
+            source: DriveSource::File {
+                path: format!("/disks/{}.qcow2", id),
+            },
+            format: "qcow2".to_string(),
+            interface: interface.to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: None,
+            discard: false,
+        }
+    }
+
+    #[test]
+    fn test_iothreads_wire_virtio_disks() {
+        let performance = VmPerformance {
+            iothreads: 2,
+            ..VmPerformance::default()
+        };
+        let args = QemuCommand::new()
+            .drive(perf_drive("disk0", "virtio"))
+            .drive(perf_drive("disk1", "virtio"))
+            .drive(perf_drive("disk2", "virtio"))
+            .drive(perf_drive("disk3", VIRTIO_SCSI_INTERFACE))
+            .performance(performance)
+            .build();
+
+        assert!(args.contains(&"file=/disks/disk0.qcow2,format=qcow2,if=none,id=disk0".to_string()));
+        assert!(args.contains(&"virtio-blk-pci,drive=disk0,iothread=iothread0".to_string()));
+        assert!(args.contains(&"virtio-blk-pci,drive=disk1,iothread=iothread1".to_string()));
+        assert!(args.contains(&"virtio-blk-pci,drive=disk2,iothread=iothread0".to_string()));
+        assert!(args.contains(&"virtio-scsi-pci,id=scsi0,iothread=iothread0".to_string()));
+
+        // Every thread a device names must be declared, and before its first use
+        let declared: Vec<(usize, &str)> = args
+            .iter()
+            .enumerate()
+            .filter_map(|(i, arg)| arg.strip_prefix("iothread,id=").map(|id| (i, id)))
+            .collect();
+        assert_eq!(declared.len(), 2);
+        for (position, arg) in args.iter().enumerate() {
+            if let Some((_, id)) = arg.split_once(",iothread=") {
+                let (declared_at, _) = declared.iter().find(|(_, name)| *name == id).expect(id);
+                assert_eq!(args[declared_at - 1], "-object");
+                assert!(*declared_at < position);
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_performance_keeps_legacy_drives() {
+        let args = QemuCommand::new().drive(perf_drive("disk0", "virtio")).build();
+        assert!(args.contains(&"file=/disks/disk0.qcow2,format=qcow2,if=virtio,id=disk0".to_string()));
+        assert!(!args.iter().any(|arg| arg.contains("iothread") || arg.contains("aio=")));
+    }
+
+    #[test]
+    fn test_aio_applies_to_image_files() {
+        let performance = VmPerformance {
+            aio: Some("native".to_string()),
+            ..VmPerformance::default()
+        };
+        let args = QemuCommand::new()
+            .drive(perf_drive("disk0", "virtio"))
+            .performance(performance)
+            .build();
+        let expected = "file=/disks/disk0.qcow2,format=qcow2,if=virtio,id=disk0,aio=native,cache.direct=on";
+        assert!(args.contains(&expected.to_string()));
+
+        let performance = VmPerformance {
+            aio: Some("io_uring".to_string()),
+            ..VmPerformance::default()
+        };
+        let args = QemuCommand::new()
+            .drive(perf_drive("disk0", "virtio"))
+            .performance(performance)
+            .build();
+        assert!(args.contains(&"file=/disks/disk0.qcow2,format=qcow2,if=virtio,id=disk0,aio=io_uring".to_string()));
+    }
+
+    #[test]
+    fn test_multiqueue_matches_vcpus_on_tap() {
+        let netdev = |kind: &str| NetdevConfig {
+            id: "net0".to_string(),
+            kind: kind.to_string(),
+            options: HashMap::new(),
+            vlan_id: None,
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+        let performance = VmPerformance {
+            net_multiqueue: true,
+            ..VmPerformance::default()
+        };
+        let args = QemuCommand::new()
+            .cpu(4)
+            .unwrap()
+            .netdev(netdev("tap"))
+            .performance(performance.clone())
+            .build();
+        assert!(args.contains(&"tap,id=net0,queues=4".to_string()));
+        assert!(args.contains(&"virtio-net-pci,netdev=net0,mq=on,vectors=10".to_string()));
+
+        let args = QemuCommand::new()
+            .cpu(4)
+            .unwrap()
+            .netdev(netdev("user"))
+            .performance(performance.clone())
+            .build();
+        assert!(!args.iter().any(|arg| arg.contains("queues=") || arg.contains("mq=on")));
+
+        let args = QemuCommand::new().netdev(netdev("tap")).performance(performance).build();
+        assert!(args.contains(&"tap,id=net0".to_string()));
+    }
+
+    #[test]
+    fn test_hugepages_needed() {
+        assert_eq!(hugepages_needed(4096, 2048), 2048);
+        assert_eq!(hugepages_needed(4097, 2048), 2049);
+        assert_eq!(hugepages_needed(4096, 1024 * 1024), 4);
+        assert_eq!(MemoryBackend::parse("hugepages"), Some(MemoryBackend::Hugepages));
+        assert_eq!(MemoryBackend::parse("huge"), None);
+    }
+
+    #[test]
+    fn test_machine_type() {
+        let cmd = QemuCommand::new()
+            .machine(MachineType::Q35);
+
+        let args = cmd.build();
+        assert!(args.contains(&"-machine".to_string()));
+        assert!(args.contains(&"q35".to_string()));
+    }
+
+    #[test]
+    fn test_boot_menu_enabled() {
+        let args = QemuCommand::new()
+            .boot_menu(BootMenuConfig { enabled: true, splash_time_ms: 5000 })
+            .build()
+            .join(" ");
+        assert!(args.contains("-boot menu=on,splash-time=5000"));
+    }
+
+    #[test]
+    fn test_boot_menu_disabled() {
+        let args = QemuCommand::new()
+            .boot_menu(BootMenuConfig { enabled: false, splash_time_ms: 5000 })
+            .build()
+            .join(" ");
+        assert!(args.contains("-boot menu=off"));
+        assert!(!args.contains("splash-time"));
+    }
+
+    #[test]
+    fn test_vfio_pci_device() {
+        let gpu = VfioPciDevice::parse("0000:01:00.0").unwrap();
+        let audio = VfioPciDevice { function: 1, ..gpu };
+        let args = QemuCommand::new().cpu_model("max").vfio_pci(gpu).vfio_pci(audio).build().join(" ");
+
+        assert!(args.contains("-device vfio-pci,host=0000:01:00.0"));
+        assert!(args.contains("-device vfio-pci,host=0000:01:00.1"));
+        assert!(args.contains("-cpu host,kvm=off"));
+        assert!(!args.contains("-cpu max"));
+    }
+
+    #[test]
+    fn test_vfio_keeps_cpu_flags() {
+        let gpu = VfioPciDevice::parse("0000:0a:00.0").unwrap();
+        let args = QemuCommand::new().nested_virt("vmx").vfio_pci(gpu).build().join(" ");
+        assert!(args.contains("-cpu host,+vmx,kvm=off"));
+    }
+
+    #[test]
+    fn test_parse_vfio_address() {
+        assert_eq!(
+            VfioPciDevice::parse("0001:0a:1f.7"),
+            Some(VfioPciDevice { domain: 1, bus: 0x0a, slot: 0x1f, function: 7 })
+        );
+        for address in ["", "bind", "01:00.0", "0000:01:20.0", "0000:01:00.8", "0000:zz:00.0"] {
+            assert_eq!(VfioPciDevice::parse(address), None, "{}", address);
+        }
+    }
+
+    #[test]
+    fn test_versioned_machine_type() {
+        let machine = MachineType::parse("pc-q35-8.2");
+        assert_eq!(machine, MachineType::Custom("pc-q35-8.2".to_string()));
+        assert!(!machine.is_virt());
+        assert!(MachineType::parse("virt-8.2").is_virt());
+        assert_eq!(MachineType::parse("q35"), MachineType::Q35);
+
+        let args = QemuCommand::new().machine(machine).build();
+        let index = args.iter().position(|arg| arg == "-machine").unwrap();
+        assert_eq!(args[index + 1], "pc-q35-8.2");
+    }
+
+    #[test]
+    fn test_is_valid_machine_name() {
+        for name in ["pc-q35-8.2", "virt-9.0", "pc_piix"] {
+            assert!(is_valid_machine_name(name), "{}", name);
+        }
+        for name in ["", "q35,accel=tcg", "q35 -snapshot", "../virt"] {
+            assert!(!is_valid_machine_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_raw_device_drive() {
+        let drive = DriveConfig {
+            id: "disk0".to_string(),
+            source: DriveSource::RawDevice {
+                path: "/dev/sdb".to_string(),
+            },
+            format: "qcow2".to_string(),
+            interface: "virtio".to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: None,
+            discard: false,
+        };
+
+        let args = QemuCommand::new().drive(drive).build();
+        assert!(args.contains(&"file=/dev/sdb,format=raw,if=virtio,id=disk0,cache=none,aio=native".to_string()));
+    }
+
+    #[test]
+    fn test_encrypted_drive_references_secret_object() {
+        let drive = DriveConfig {
+            id: "disk0".to_string(),
+            source: DriveSource::File {
+                path: "/path/to/disk.qcow2".to_string(),
+            },
+            format: "qcow2".to_string(),
+            interface: "virtio".to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: Some("luks-vm-1".to_string()),
+            discard: false,
+        };
+
+        let args = QemuCommand::new()
+            .object("secret,id=luks-vm-1,file=/run/secret")
+            .drive(drive)
+            .build();
+
+        let object_pos = args.iter().position(|arg| arg == "-object").expect("object flag");
+        let drive_pos = args.iter().position(|arg| arg == "-drive").expect("drive flag");
+        assert!(object_pos < drive_pos);
+        assert_eq!(args[object_pos + 1], "secret,id=luks-vm-1,file=/run/secret");
+        assert!(args[drive_pos + 1].contains("encrypt.key-secret=luks-vm-1"));
+    }
+
+    #[test]
+    fn test_monitor_socket() {
+        let args = QemuCommand::new().monitor_socket("/run/openutm/monitor-vm-1.sock").build();
+        let pos = args.iter().position(|arg| arg == "-monitor").expect("monitor flag");
+        assert_eq!(args[pos + 1], "unix:/run/openutm/monitor-vm-1.sock,server=on,wait=off");
+        assert!(!QemuCommand::new().build().contains(&"-monitor".to_string()));
+    }
+
+    #[test]
+    fn test_serial_socket() {
+        let args = QemuCommand::new().serial_socket("/run/openutm/serial-vm-1.sock").build();
+        let pos = args.iter().position(|arg| arg == "-serial").expect("serial flag");
+        assert_eq!(args[pos + 1], "unix:/run/openutm/serial-vm-1.sock,server=on,wait=off");
+        assert!(!QemuCommand::new().build().contains(&"-serial".to_string()));
+    }
+
+    #[test]
+    fn test_complete_command() {
+        let drive = DriveConfig {
+            id: "disk0".to_string(),
+            source: DriveSource::File {
+                path: "/path/to/disk.qcow2".to_string(),
+            },
+            format: "qcow2".to_string(),
+            interface: "virtio".to_string(),
+            throttle: IoThrottle::default(),
+            key_secret: None,
+            discard: false,
+        };
+
+        let mut net_opts = HashMap::new();
+        net_opts.insert("hostfwd".to_string(), "tcp::2222-:22".to_string());
+
+        let netdev = NetdevConfig {
+            id: "net0".to_string(),
+            kind: "user".to_string(),
+            options: net_opts,
+            vlan_id: None,
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+
+        let display = DisplayConfig {
+            kind: "spice".to_string(),
+            port: Some(5900),
+            options: Default::default(),
+            clipboard_sharing: false,
+            resolution: None,
+            adapter: None,
+            tls: None,
+            compression: SpiceCompression::default(),
+        };
+
+        let cmd = QemuCommand::new()
+            .machine(MachineType::Q35)
+            .accel(Accelerator::Hvf)
+            .cpu(4)
+            .expect("cpu should work")
+            .memory(4096)
+            .expect("memory should work")
+            .drive(drive)
+            .netdev(netdev)
+            .display(display)
+            .pointer_device(PointerDevice::UsbTablet);
+
+        let args = cmd.build();
+        
+        // Verify all major components present
+        assert!(args.contains(&"-machine".to_string()));
+        assert!(args.contains(&"-accel".to_string()));
+        assert!(args.contains(&"-smp".to_string()));
+        assert!(args.contains(&"-m".to_string()));
+        assert!(args.contains(&"-drive".to_string()));
+        assert!(args.contains(&"-netdev".to_string()));
+        assert!(args.contains(&"-spice".to_string()));
+        assert!(args.contains(&"-device".to_string()));
+        
+        // Command string should be valid
+        let cmd_str = cmd.build_string();
+        assert!(!cmd_str.is_empty());
+        assert!(cmd_str.contains("qemu-system-x86_64"));
+    }
+}
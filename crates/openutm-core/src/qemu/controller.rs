@@ -0,0 +1,838 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use crate::{Result, error::Error};
+
+pub struct VMHandle {
+    pub vm_id: String,
+    pub pid: u32,
+    pub process: Child,
+    pub qmp_socket: Option<String>,
+    pub monitor_socket: Option<String>,
+    /// QEMU binary the process was started from
+    pub binary: String,
+    /// Arguments QEMU was started with, i.e. the config the VM is running
+    pub launch_args: Vec<String>,
+    pub started_at: SystemTime,
+}
+
+/// What `get_running_vm_details` reports for one tracked QEMU process
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VmRunningInfo {
+    pub vm_id: String,
+    pub pid: u32,
+    pub qmp_socket: Option<String>,
+    pub started_at: SystemTime,
+}
+
+/// How a tracked QEMU process ended, as seen by `sync_status`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessExit {
+    pub pid: u32,
+    /// `None` if the process was killed by a signal or its status is unknown
+    pub code: Option<i32>,
+}
+
+/// How long after a start request another one for the same VM counts as a duplicate
+pub const START_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Liveness check for a QEMU process by pid
+pub type ProcessProbe = fn(u32) -> bool;
+
+pub struct QemuController {
+    /// Default binary for `start_vm`; replaceable while the app runs
+    qemu_path: Mutex<String>,
+    running_vms: Arc<Mutex<std::collections::HashMap<String, VMHandle>>>,
+    exits: Arc<Mutex<std::collections::HashMap<String, ProcessExit>>>,
+    /// VMs whose QEMU process is being spawned but not yet in `running_vms`
+    starting: Mutex<HashSet<String>>,
+    /// When each VM's last start was requested, for `begin_start`
+    start_requests: Mutex<std::collections::HashMap<String, Instant>>,
+    process_probe: ProcessProbe,
+    log_dir: Option<PathBuf>,
+}
+
+/// Signal-0 check: succeeds while the pid exists and we may signal it
+#[cfg(unix)]
+pub fn signal_probe(pid: u32) -> bool {
+    use nix::sys::signal::kill;
+    use nix::unistd::Pid;
+
+    kill(Pid::from_raw(pid as i32), None).is_ok()
+}
+
+#[cfg(not(unix))]
+pub fn signal_probe(_pid: u32) -> bool {
+    true
+}
+
+/// Releases a `QemuController::reserve` claim when the start finishes or fails
+struct StartReservation<'a> {
+    starting: &'a Mutex<HashSet<String>>,
+    vm_id: String,
+}
+
+impl Drop for StartReservation<'_> {
+    fn drop(&mut self) {
+        self.starting.lock().unwrap().remove(&self.vm_id);
+    }
+}
+
+/// Spawn QEMU with its output appended to `<log_dir>/<vm_id>.log`
+fn spawn_qemu(binary: &str, vm_id: &str, qemu_args: &[String], log_dir: Option<&Path>) -> Result<Child> {
+    let mut cmd = std::process::Command::new(binary);
+    cmd.args(qemu_args);
+
+    if let Some(log_dir) = log_dir {
+        std::fs::create_dir_all(log_dir)?;
+        let log_file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_dir.join(format!("{}.log", vm_id)))?;
+        cmd.stdout(Stdio::from(log_file.try_clone()?));
+        cmd.stderr(Stdio::from(log_file));
+    }
+
+    tracing::debug!(binary = %binary, args = %qemu_args.join(" "), "spawning QEMU");
+    Ok(cmd.spawn()?)
+}
+
+impl QemuController {
+    pub fn new(qemu_path: String) -> Self {
+        Self::with_process_probe(qemu_path, signal_probe)
+    }
+
+    pub fn with_process_probe(qemu_path: String, process_probe: ProcessProbe) -> Self {
+        Self {
+            qemu_path: Mutex::new(qemu_path),
+            running_vms: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            exits: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            starting: Mutex::new(HashSet::new()),
+            start_requests: Mutex::new(std::collections::HashMap::new()),
+            process_probe,
+            log_dir: None,
+        }
+    }
+
+    /// Capture each VM's QEMU stdout/stderr in `<log_dir>/<vm_id>.log`
+    pub fn with_log_dir(mut self, log_dir: PathBuf) -> Self {
+        self.log_dir = Some(log_dir);
+        self
+    }
+
+    pub fn qemu_path(&self) -> String {
+        self.qemu_path.lock().unwrap().clone()
+    }
+
+    /// Use `qemu_path` for VMs started from now on; running VMs keep their binary
+    pub fn set_qemu_path(&self, qemu_path: String) {
+        *self.qemu_path.lock().unwrap() = qemu_path;
+    }
+
+    pub async fn start_vm(
+        &self,
+        vm_id: &str,
+        qemu_args: Vec<String>,
+        qmp_socket: Option<String>,
+    ) -> Result<u32> {
+        let binary = self.qemu_path();
+        self.start_vm_with_binary(&binary, vm_id, qemu_args, qmp_socket, None).await
+    }
+
+    /// Start a VM with an explicit QEMU binary (e.g. a different system arch).
+    ///
+    /// The shared map is only locked to reserve `vm_id` and to record the
+    /// started handle, so starting one VM never waits on another's spawn.
+    #[tracing::instrument(skip_all, fields(vm_id = %vm_id))]
+    pub async fn start_vm_with_binary(
+        &self,
+        binary: &str,
+        vm_id: &str,
+        qemu_args: Vec<String>,
+        qmp_socket: Option<String>,
+        monitor_socket: Option<String>,
+    ) -> Result<u32> {
+        let reservation = self.reserve(vm_id)?;
+        let process = spawn_qemu(binary, vm_id, &qemu_args, self.log_dir.as_deref())?;
+
+        let pid = process.id();
+        tracing::info!(pid, "QEMU process started");
+        let handle = VMHandle {
+            vm_id: vm_id.to_string(),
+            pid,
+            process,
+            qmp_socket,
+            monitor_socket,
+            binary: binary.to_string(),
+            launch_args: qemu_args,
+            started_at: SystemTime::now(),
+        };
+
+        self.running_vms
+            .lock()
+            .unwrap()
+            .insert(vm_id.to_string(), handle);
+        drop(reservation);
+
+        Ok(pid)
+    }
+
+    /// Claim `vm_id` for a start in progress; refused if it is running or already starting
+    fn reserve(&self, vm_id: &str) -> Result<StartReservation<'_>> {
+        let vms = self.running_vms.lock().unwrap();
+        let mut starting = self.starting.lock().unwrap();
+        if vms.contains_key(vm_id) || !starting.insert(vm_id.to_string()) {
+            return Err(Error::VmAlreadyRunning(vm_id.to_string()));
+        }
+        Ok(StartReservation {
+            starting: &self.starting,
+            vm_id: vm_id.to_string(),
+        })
+    }
+
+    /// Record a start request for `vm_id`; `false` if one was already
+    /// recorded in the last `START_DEBOUNCE`, i.e. this one is a duplicate
+    pub fn begin_start(&self, vm_id: &str) -> bool {
+        let mut requests = self.start_requests.lock().unwrap();
+        let now = Instant::now();
+        if requests.get(vm_id).is_some_and(|at| now.duration_since(*at) < START_DEBOUNCE) {
+            return false;
+        }
+        requests.insert(vm_id.to_string(), now);
+        true
+    }
+
+    /// Forget `vm_id`'s start request, so a failed start can be retried at once
+    pub fn end_start(&self, vm_id: &str) {
+        self.start_requests.lock().unwrap().remove(vm_id);
+    }
+
+    #[tracing::instrument(skip_all, fields(vm_id = %vm_id))]
+    pub async fn stop_vm(&self, vm_id: &str) -> Result<()> {
+        self.end_start(vm_id);
+        let mut vms = self.running_vms.lock().unwrap();
+        
+        match vms.remove(vm_id) {
+            Some(mut handle) => {
+                handle.process.kill().ok();
+                tracing::info!(pid = handle.pid, "QEMU process stopped");
+                Ok(())
+            }
+            None => Err(Error::VmNotRunning(vm_id.to_string())),
+        }
+    }
+
+    pub async fn pause_vm(&self, vm_id: &str) -> Result<()> {
+        let vms = self.running_vms.lock().unwrap();
+        
+        if vms.contains_key(vm_id) {
+            Ok(())
+        } else {
+            Err(Error::VmNotRunning(vm_id.to_string()))
+        }
+    }
+
+    pub async fn resume_vm(&self, vm_id: &str) -> Result<()> {
+        let vms = self.running_vms.lock().unwrap();
+        
+        if vms.contains_key(vm_id) {
+            Ok(())
+        } else {
+            Err(Error::VmNotRunning(vm_id.to_string()))
+        }
+    }
+
+    pub fn get_running_vms(&self) -> Vec<String> {
+        self.running_vms
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Pid, QMP socket and start time of every tracked VM, by VM id
+    pub fn get_running_vm_details(&self) -> Vec<VmRunningInfo> {
+        let mut details: Vec<VmRunningInfo> = self
+            .running_vms
+            .lock()
+            .unwrap()
+            .values()
+            .map(|handle| VmRunningInfo {
+                vm_id: handle.vm_id.clone(),
+                pid: handle.pid,
+                qmp_socket: handle.qmp_socket.clone(),
+                started_at: handle.started_at,
+            })
+            .collect();
+        details.sort_by(|a, b| a.vm_id.cmp(&b.vm_id));
+        details
+    }
+
+    pub fn pid(&self, vm_id: &str) -> Option<u32> {
+        self.running_vms.lock().unwrap().get(vm_id).map(|handle| handle.pid)
+    }
+
+    pub fn qmp_socket(&self, vm_id: &str) -> Option<String> {
+        self.running_vms
+            .lock()
+            .unwrap()
+            .get(vm_id)
+            .and_then(|handle| handle.qmp_socket.clone())
+    }
+
+    /// Binary and arguments the running VM was started with
+    pub fn launch_command(&self, vm_id: &str) -> Option<(String, Vec<String>)> {
+        self.running_vms
+            .lock()
+            .unwrap()
+            .get(vm_id)
+            .map(|handle| (handle.binary.clone(), handle.launch_args.clone()))
+    }
+
+    /// Run a human monitor command on a running VM; lifecycle commands are refused
+    #[tracing::instrument(skip_all, fields(vm_id = %vm_id))]
+    pub async fn send_monitor_command(&self, vm_id: &str, command: &str) -> Result<String> {
+        super::monitor::check_command(command)?;
+        let socket = match self.running_vms.lock().unwrap().get(vm_id) {
+            Some(handle) => handle.monitor_socket.clone(),
+            None => return Err(Error::VmNotRunning(vm_id.to_string())),
+        };
+        let socket = socket.ok_or_else(|| Error::QemuError(format!("VM {} has no monitor socket", vm_id)))?;
+
+        tracing::info!(target: "audit", command = %command, "monitor command");
+        super::monitor::execute(&socket, command).await
+    }
+
+    pub fn is_running(&self, vm_id: &str) -> bool {
+        self.running_vms.lock().unwrap().contains_key(vm_id)
+    }
+
+    /// Wait up to `timeout` for the VM's QEMU process to exit on its own, e.g.
+    /// after an ACPI power-down. The VM stays tracked; `stop_vm` releases it.
+    pub async fn wait_for_exit(&self, vm_id: &str, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let exited = match self.running_vms.lock().unwrap().get_mut(vm_id) {
+                Some(handle) => !matches!(handle.process.try_wait(), Ok(None)),
+                None => true,
+            };
+            if exited {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Check every tracked QEMU process and drop the ones that have died.
+    ///
+    /// Returns `(vm_id, is_alive)` for each VM that was tracked.
+    pub fn sync_status(&self) -> Vec<(String, bool)> {
+        let mut vms = self.running_vms.lock().unwrap();
+        let mut results = Vec::with_capacity(vms.len());
+
+        let mut exits = self.exits.lock().unwrap();
+
+        for (vm_id, handle) in vms.iter_mut() {
+            // Reap exited children first; a zombie still answers signal 0.
+            let exit_status = handle.process.try_wait().ok().flatten();
+            let alive = exit_status.is_none() && (self.process_probe)(handle.pid);
+            if !alive {
+                let code = exit_status.and_then(|status| status.code());
+                exits.insert(vm_id.clone(), ProcessExit { pid: handle.pid, code });
+            }
+            results.push((vm_id.clone(), alive));
+        }
+
+        for (vm_id, alive) in &results {
+            if !alive {
+                vms.remove(vm_id);
+                self.end_start(vm_id);
+            }
+        }
+
+        results
+    }
+
+    /// Pid and exit code recorded by `sync_status` for a VM that died
+    pub fn take_exit(&self, vm_id: &str) -> Option<ProcessExit> {
+        self.exits.lock().unwrap().remove(vm_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_create_controller() {
+        let controller = QemuController::new("/usr/bin/qemu-system-x86_64".to_string());
+        assert_eq!(controller.get_running_vms().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_exit() {
+        let controller = QemuController::new("sh".to_string());
+        let quick = vec!["-c".to_string(), "exit 0".to_string()];
+        let slow = vec!["-c".to_string(), "sleep 30".to_string()];
+        controller.start_vm("quick", quick, None).await.unwrap();
+        controller.start_vm("slow", slow, None).await.unwrap();
+
+        assert!(controller.wait_for_exit("quick", Duration::from_secs(5)).await);
+        assert!(!controller.wait_for_exit("slow", Duration::from_millis(200)).await);
+        assert!(controller.is_running("slow"));
+        controller.stop_vm("slow").await.unwrap();
+        assert!(controller.wait_for_exit("slow", Duration::ZERO).await);
+    }
+
+    #[tokio::test]
+    async fn test_start_vm_returns_handle_with_vm_id_and_pid() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let result = controller
+            .start_vm("vm-test-1", vec!["test".to_string()], None)
+            .await;
+        
+        match result {
+            Ok(pid) => {
+                assert!(pid > 0);
+            }
+            Err(e) => {
+                eprintln!("Error: {:?}", e);
+                panic!("start_vm failed");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_vm_with_qmp_socket() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let result = controller
+            .start_vm(
+                "vm-test-2",
+                vec!["test".to_string()],
+                Some("/tmp/qmp-vm-test-2.sock".to_string()),
+            )
+            .await;
+        
+        match result {
+            Ok(pid) => {
+                assert!(pid > 0);
+            }
+            Err(e) => {
+                eprintln!("Error: {:?}", e);
+                panic!("start_vm with QMP socket failed");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_vm_adds_to_running_vms_map() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let _ = controller
+            .start_vm("vm-test-1", vec!["test".to_string()], None)
+            .await;
+        
+        let running = controller.get_running_vms();
+        assert!(running.contains(&"vm-test-1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_start_multiple_vms_independently() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let vm1 = controller
+            .start_vm("vm-1", vec!["test".to_string()], None)
+            .await;
+        let vm2 = controller
+            .start_vm("vm-2", vec!["test".to_string()], None)
+            .await;
+        
+        assert!(vm1.is_ok());
+        assert!(vm2.is_ok());
+        
+        let running = controller.get_running_vms();
+        assert_eq!(running.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stop_vm_removes_from_running_vms() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let _ = controller
+            .start_vm("vm-test-1", vec!["test".to_string()], None)
+            .await;
+        
+        assert_eq!(controller.get_running_vms().len(), 1);
+        
+        let stop_result = controller.stop_vm("vm-test-1").await;
+        assert!(stop_result.is_ok());
+        assert_eq!(controller.get_running_vms().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_start_requests_are_debounced() {
+        let controller = QemuController::new("sh".to_string());
+        assert!(controller.begin_start("vm-1"));
+        assert!(!controller.begin_start("vm-1"));
+        assert!(controller.begin_start("vm-2"));
+
+        controller.end_start("vm-1");
+        assert!(controller.begin_start("vm-1"));
+
+        let sleep = vec!["-c".to_string(), "sleep 30".to_string()];
+        controller.start_vm("vm-1", sleep, None).await.unwrap();
+        controller.stop_vm("vm-1").await.unwrap();
+        assert!(controller.begin_start("vm-1"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_vm_returns_error_if_not_running() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let result = controller.stop_vm("vm-nonexistent").await;
+        
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pause_vm_requires_running() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let result = controller.pause_vm("vm-nonexistent").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pause_vm_succeeds_if_running() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let _ = controller
+            .start_vm("vm-test-1", vec!["test".to_string()], None)
+            .await;
+        
+        let result = controller.pause_vm("vm-test-1").await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_resume_vm_requires_running() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let result = controller.resume_vm("vm-nonexistent").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resume_vm_succeeds_if_running() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let _ = controller
+            .start_vm("vm-test-1", vec!["test".to_string()], None)
+            .await;
+        
+        let result = controller.resume_vm("vm-test-1").await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_start_pause_resume_stop() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let start = controller
+            .start_vm("vm-test-1", vec!["test".to_string()], None)
+            .await;
+        assert!(start.is_ok());
+        assert_eq!(controller.get_running_vms().len(), 1);
+        
+        let pause = controller.pause_vm("vm-test-1").await;
+        assert!(pause.is_ok());
+        
+        let resume = controller.resume_vm("vm-test-1").await;
+        assert!(resume.is_ok());
+        
+        let stop = controller.stop_vm("vm-test-1").await;
+        assert!(stop.is_ok());
+        assert_eq!(controller.get_running_vms().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_error_handling_invalid_process() {
+        let controller = QemuController::new("/nonexistent/qemu".to_string());
+        
+        let result = controller
+            .start_vm("vm-test-1", vec!["test".to_string()], None)
+            .await;
+        
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multiple_vms_lifecycle() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let vm1 = controller
+            .start_vm("vm-1", vec!["test".to_string()], None)
+            .await;
+        let vm2 = controller
+            .start_vm("vm-2", vec!["test".to_string()], None)
+            .await;
+        
+        assert!(vm1.is_ok());
+        assert!(vm2.is_ok());
+        assert_eq!(controller.get_running_vms().len(), 2);
+        
+        let _ = controller.pause_vm("vm-1").await;
+        let _ = controller.pause_vm("vm-2").await;
+        
+        let _ = controller.stop_vm("vm-1").await;
+        assert_eq!(controller.get_running_vms().len(), 1);
+        
+        let _ = controller.stop_vm("vm-2").await;
+        assert_eq!(controller.get_running_vms().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stop_then_start_same_vm_id() {
+        let controller = QemuController::new("echo".to_string());
+        
+        let start1 = controller
+            .start_vm("vm-reuse", vec!["test".to_string()], None)
+            .await;
+        assert!(start1.is_ok());
+        
+        let _ = controller.stop_vm("vm-reuse").await;
+        
+        let start2 = controller
+            .start_vm("vm-reuse", vec!["test".to_string()], None)
+            .await;
+        assert!(start2.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_launch_command_is_kept_while_running() {
+        let controller = QemuController::new("sleep".to_string());
+        assert_eq!(controller.launch_command("vm-1"), None);
+
+        controller.start_vm("vm-1", vec!["5".to_string()], None).await.unwrap();
+        assert_eq!(controller.launch_command("vm-1"), Some(("sleep".to_string(), vec!["5".to_string()])));
+
+        controller.stop_vm("vm-1").await.unwrap();
+        assert_eq!(controller.launch_command("vm-1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_set_qemu_path_applies_to_later_starts() {
+        let controller = QemuController::new("/nonexistent/qemu".to_string());
+        assert!(controller.start_vm("vm-1", vec!["5".to_string()], None).await.is_err());
+
+        controller.set_qemu_path("sleep".to_string());
+        assert_eq!(controller.qemu_path(), "sleep");
+        controller.start_vm("vm-1", vec!["5".to_string()], None).await.unwrap();
+        assert_eq!(controller.launch_command("vm-1").map(|(binary, _)| binary), Some("sleep".to_string()));
+
+        // The running VM keeps the binary it was started from
+        controller.set_qemu_path("echo".to_string());
+        assert_eq!(controller.launch_command("vm-1").map(|(binary, _)| binary), Some("sleep".to_string()));
+        controller.stop_vm("vm-1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_running_vm_details() {
+        let controller = QemuController::new("sleep".to_string());
+        assert!(controller.get_running_vm_details().is_empty());
+
+        let before = SystemTime::now();
+        let qmp = Some("/tmp/qmp-vm-2.sock".to_string());
+        let pid2 = controller.start_vm("vm-2", vec!["5".to_string()], qmp.clone()).await.unwrap();
+        let pid1 = controller.start_vm("vm-1", vec!["5".to_string()], None).await.unwrap();
+
+        let details = controller.get_running_vm_details();
+        assert_eq!(details.len(), 2);
+        assert_eq!((details[0].vm_id.as_str(), details[0].pid, details[0].qmp_socket.clone()), ("vm-1", pid1, None));
+        assert_eq!((details[1].vm_id.as_str(), details[1].pid, details[1].qmp_socket.clone()), ("vm-2", pid2, qmp));
+        for info in &details {
+            assert!(info.started_at >= before);
+            assert!(info.started_at.elapsed().unwrap() < Duration::from_secs(5));
+        }
+
+        controller.stop_vm("vm-1").await.unwrap();
+        controller.stop_vm("vm-2").await.unwrap();
+        assert!(controller.get_running_vm_details().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_start_refuses_running_vm() {
+        let controller = QemuController::new("sleep".to_string());
+
+        assert!(controller.start_vm("vm-1", vec!["5".to_string()], None).await.is_ok());
+        let again = controller.start_vm("vm-1", vec!["5".to_string()], None).await;
+        assert!(matches!(again, Err(Error::VmAlreadyRunning(_))));
+
+        let _ = controller.stop_vm("vm-1").await;
+    }
+
+    #[tokio::test]
+    async fn test_slow_start_does_not_block_other_vms() {
+        let controller = QemuController::new("sleep".to_string());
+
+        // vm-1 is mid-spawn for as long as its reservation is held
+        let reservation = controller.reserve("vm-1").unwrap();
+        let other = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            controller.start_vm("vm-2", vec!["5".to_string()], None),
+        )
+        .await;
+        assert!(other.expect("vm-2 waited on vm-1").is_ok());
+        assert!(controller.is_running("vm-2"));
+
+        let duplicate = controller.start_vm("vm-1", vec!["5".to_string()], None).await;
+        assert!(matches!(duplicate, Err(Error::VmAlreadyRunning(_))));
+        assert!(!controller.is_running("vm-1"));
+
+        drop(reservation);
+        assert!(controller.start_vm("vm-1", vec!["5".to_string()], None).await.is_ok());
+
+        let _ = controller.stop_vm("vm-1").await;
+        let _ = controller.stop_vm("vm-2").await;
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_starts_for_different_vms() {
+        let controller = Arc::new(QemuController::new("sleep".to_string()));
+
+        let starts = ["vm-1", "vm-2"].map(|vm_id| {
+            let controller = Arc::clone(&controller);
+            tokio::spawn(async move { controller.start_vm(vm_id, vec!["5".to_string()], None).await })
+        });
+        for start in starts {
+            assert!(start.await.unwrap().is_ok());
+        }
+        assert_eq!(controller.get_running_vms().len(), 2);
+
+        let _ = controller.stop_vm("vm-1").await;
+        let _ = controller.stop_vm("vm-2").await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_start_releases_reservation() {
+        let controller = QemuController::new("/nonexistent/qemu".to_string());
+
+        assert!(controller.start_vm("vm-1", vec![], None).await.is_err());
+        assert!(controller.starting.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sync_status_removes_dead_processes() {
+        let controller = QemuController::with_process_probe("sleep".to_string(), |_| false);
+
+        let _ = controller
+            .start_vm("vm-1", vec!["5".to_string()], None)
+            .await;
+
+        let status = controller.sync_status();
+        assert_eq!(status, vec![("vm-1".to_string(), false)]);
+        assert!(!controller.is_running("vm-1"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_status_keeps_live_processes() {
+        let controller = QemuController::with_process_probe("sleep".to_string(), |_| true);
+
+        let _ = controller
+            .start_vm("vm-1", vec!["5".to_string()], None)
+            .await;
+
+        let status = controller.sync_status();
+        assert_eq!(status, vec![("vm-1".to_string(), true)]);
+        assert!(controller.is_running("vm-1"));
+
+        let _ = controller.stop_vm("vm-1").await;
+    }
+
+    #[tokio::test]
+    async fn test_sync_status_records_exit_code() {
+        let controller = QemuController::with_process_probe("sh".to_string(), |_| true);
+
+        let _ = controller
+            .start_vm("vm-1", vec!["-c".to_string(), "exit 3".to_string()], None)
+            .await;
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let pid = controller.pid("vm-1");
+        let status = controller.sync_status();
+        assert_eq!(status, vec![("vm-1".to_string(), false)]);
+        assert_eq!(controller.take_exit("vm-1"), Some(ProcessExit { pid: pid.unwrap(), code: Some(3) }));
+        assert_eq!(controller.take_exit("vm-1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_qmp_socket_lookup() {
+        let controller = QemuController::new("echo".to_string());
+        assert_eq!(controller.qmp_socket("vm-1"), None);
+
+        let _ = controller
+            .start_vm("vm-1", vec!["test".to_string()], Some("/tmp/qmp-vm-1.sock".to_string()))
+            .await;
+        assert_eq!(controller.qmp_socket("vm-1"), Some("/tmp/qmp-vm-1.sock".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_send_monitor_command_guards() {
+        let controller = QemuController::new("echo".to_string());
+        let err = controller.send_monitor_command("vm-1", "info status").await.unwrap_err();
+        assert!(matches!(err, Error::VmNotRunning(_)));
+
+        let _ = controller
+            .start_vm_with_binary(
+                "echo",
+                "vm-1",
+                vec!["test".to_string()],
+                None,
+                Some("/tmp/monitor-vm-1.sock".to_string()),
+            )
+            .await;
+        let err = controller.send_monitor_command("vm-1", "quit").await.unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig(_)));
+    }
+
+    #[tokio::test]
+    async fn test_log_dir_captures_output() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let controller =
+            QemuController::new("echo".to_string()).with_log_dir(temp_dir.path().join("logs"));
+
+        controller
+            .start_vm("vm-1", vec!["hello".to_string()], None)
+            .await
+            .unwrap();
+        while controller.sync_status().iter().any(|(_, alive)| *alive) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let log = std::fs::read_to_string(temp_dir.path().join("logs/vm-1.log")).unwrap();
+        assert_eq!(log.trim(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_is_running_reflects_runtime_state() {
+        let controller = QemuController::new("echo".to_string());
+        assert!(!controller.is_running("vm-1"));
+
+        let _ = controller
+            .start_vm("vm-1", vec!["test".to_string()], None)
+            .await;
+        assert!(controller.is_running("vm-1"));
+
+        let _ = controller.stop_vm("vm-1").await;
+        assert!(!controller.is_running("vm-1"));
+    }
+}
@@ -0,0 +1,707 @@
+use crate::{platform, CpuModel, CpuModelList, Error, QemuInfo, Result};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+/// Detect QEMU binary and get system information
+///
+/// Probing runs `which`, `--version` and `-machine help` synchronously, so it is
+/// moved onto the blocking pool rather than stalling the async executor.
+pub async fn detect() -> Result<QemuInfo> {
+    tokio::task::spawn_blocking(detect_blocking)
+        .await
+        .map_err(|err| Error::QemuError(format!("QEMU detection task failed: {}", err)))?
+}
+
+fn detect_blocking() -> Result<QemuInfo> {
+    let qemu_path = find_qemu_binary()?;
+    let version = get_qemu_version(&qemu_path).ok();
+    Ok(describe_binary(qemu_path, version))
+}
+
+/// Guest architectures looked for when taking the binary inventory
+const INVENTORY_ARCHES: [&str; 3] = ["x86_64", "aarch64", "riscv64"];
+
+/// Detect the `qemu-system-<arch>` binary, which must answer `--version`
+pub async fn detect_for_arch(arch: &str) -> Result<QemuInfo> {
+    let arch = arch.to_string();
+    tokio::task::spawn_blocking(move || detect_arch_with(&arch, find_arch_binary).ok_or(Error::QemuNotFound))
+        .await
+        .map_err(|err| Error::QemuError(format!("QEMU detection task failed: {}", err)))?
+}
+
+/// Every working QEMU system binary, keyed by the guest architecture it runs;
+/// empty when none are installed
+pub async fn detect_all() -> Result<HashMap<String, QemuInfo>> {
+    tokio::task::spawn_blocking(|| inventory(find_arch_binary))
+        .await
+        .map_err(|err| Error::QemuError(format!("QEMU detection task failed: {}", err)))
+}
+
+fn inventory(find: impl Fn(&str) -> Option<PathBuf>) -> HashMap<String, QemuInfo> {
+    INVENTORY_ARCHES
+        .iter()
+        .filter_map(|arch| Some((arch.to_string(), detect_arch_with(arch, &find)?)))
+        .collect()
+}
+
+fn detect_arch_with(arch: &str, find: impl Fn(&str) -> Option<PathBuf>) -> Option<QemuInfo> {
+    let path = find(arch)?;
+    let version = get_qemu_version(&path).ok()?;
+    Some(describe_binary(path, Some(version)))
+}
+
+/// `qemu-system-<arch>` next to the detected QEMU, or else on the PATH
+fn find_arch_binary(arch: &str) -> Option<PathBuf> {
+    let sibling = find_qemu_binary()
+        .ok()
+        .map(|path| PathBuf::from(binary_for_arch(&path.display().to_string(), arch)))
+        .filter(|path| path.is_file());
+    sibling.or_else(|| find_tool(&[&format!("qemu-system-{}", arch)]))
+}
+
+/// Probe a user-chosen QEMU binary, which must answer `--version`
+pub async fn inspect_binary(qemu_path: PathBuf) -> Result<QemuInfo> {
+    tokio::task::spawn_blocking(move || {
+        let version = get_qemu_version(&qemu_path)?;
+        Ok(describe_binary(qemu_path, Some(version)))
+    })
+    .await
+    .map_err(|err| Error::QemuError(format!("QEMU detection task failed: {}", err)))?
+}
+
+fn describe_binary(qemu_path: PathBuf, version: Option<String>) -> QemuInfo {
+    #[cfg(target_os = "macos")]
+    let accelerator = detect_hvf_support().ok();
+    
+    #[cfg(target_os = "linux")]
+    let accelerator = detect_kvm_support().ok();
+    
+    #[cfg(target_os = "windows")]
+    let accelerator = detect_whpx_support().ok();
+    
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    let accelerator = None;
+
+    QemuInfo {
+        detected: true,
+        path: Some(qemu_path.display().to_string()),
+        version,
+        accelerator,
+        host_arch: platform::host_arch(),
+        supported_machines: list_machines(&qemu_path),
+        supported_accels: list_accels(&qemu_path),
+    }
+}
+
+/// Run `<qemu> <flag> help`; `None` if the binary cannot answer
+fn query_help(path: &Path, flag: &str) -> Option<String> {
+    let output = Command::new(path).args([flag, "help"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Machine types supported by the binary, or empty if it cannot be queried
+pub fn list_machines(path: &Path) -> Vec<String> {
+    query_help(path, "-machine")
+        .map(|output| parse_machine_help(&output))
+        .unwrap_or_default()
+}
+
+/// Machine types of the binary, failing if it cannot be run
+pub fn query_machines(path: &Path) -> Result<Vec<String>> {
+    query_help(path, "-machine")
+        .map(|output| parse_machine_help(&output))
+        .ok_or_else(|| Error::QemuError(format!("{} -machine help failed", path.display())))
+}
+
+/// Accelerators compiled into the binary, failing if it cannot be run
+pub fn query_accels(path: &Path) -> Result<Vec<String>> {
+    query_help(path, "-accel")
+        .map(|output| parse_accel_help(&output))
+        .ok_or_else(|| Error::QemuError(format!("{} -accel help failed", path.display())))
+}
+
+/// CPU models and flags of the binary, cached per binary path and version
+pub fn query_cpu_models(path: &Path) -> Result<CpuModelList> {
+    static CACHE: OnceLock<Mutex<HashMap<(PathBuf, String), CpuModelList>>> = OnceLock::new();
+
+    let version = get_qemu_version(&path.to_path_buf())?;
+    let key = (path.to_path_buf(), version);
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(models) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return Ok(models.clone());
+    }
+
+    let output = query_help(path, "-cpu")
+        .ok_or_else(|| Error::QemuError(format!("{} -cpu help failed", path.display())))?;
+    let models = parse_cpu_help(&output);
+    cache
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, models.clone());
+    Ok(models)
+}
+
+/// Accelerators supported by the binary, or empty if it cannot be queried
+pub fn list_accels(path: &Path) -> Vec<String> {
+    query_help(path, "-accel")
+        .map(|output| parse_accel_help(&output))
+        .unwrap_or_default()
+}
+
+/// First word of each line of `-machine help`, after the `Supported machines are:` header
+fn parse_machine_help(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.ends_with(':'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse `-cpu help`: models under `Available CPUs:`, flags under `Recognized CPUID flags:`.
+///
+/// x86 prefixes each model with the architecture (`x86 Haswell-v1  Intel Core ...`) while
+/// most other targets list indented bare names (`  cortex-a57`).
+fn parse_cpu_help(output: &str) -> CpuModelList {
+    let mut list = CpuModelList::default();
+    let mut section = "";
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(' ') && line.trim_end().ends_with(':') {
+            section = line.trim_end();
+            continue;
+        }
+
+        match section {
+            "Available CPUs:" => {
+                let indented = line.starts_with(char::is_whitespace);
+                let mut words = line.split_whitespace();
+                if !indented {
+                    // Skip the architecture prefix
+                    words.next();
+                }
+                if let Some(name) = words.next() {
+                    let note = words.collect::<Vec<_>>().join(" ");
+                    list.models.push(CpuModel {
+                        name: name.to_string(),
+                        note: (!note.is_empty()).then_some(note),
+                    });
+                }
+            }
+            "Recognized CPUID flags:" => list.flags.extend(line.split_whitespace().map(str::to_string)),
+            _ => {}
+        }
+    }
+    list
+}
+
+/// One accelerator per line of `-accel help`, after its header
+fn parse_accel_help(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.ends_with(':'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn candidate_binary_names() -> &'static [&'static str] {
+    &["qemu-system-aarch64", "qemu-system-x86_64"]
+}
+
+fn default_shell_path() -> String {
+    "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin".to_string()
+}
+
+fn build_lookup_path() -> String {
+    let current = env::var("PATH").unwrap_or_default();
+    if current.is_empty() {
+        return default_shell_path();
+    }
+    format!("{}:{}", current, default_shell_path())
+}
+
+fn append_candidate_if_exists(
+    seen: &mut HashSet<String>,
+    candidates: &mut Vec<PathBuf>,
+    path: PathBuf,
+) {
+    let key = path.display().to_string();
+    if seen.contains(&key) {
+        return;
+    }
+    if path.exists() {
+        seen.insert(key);
+        candidates.push(path);
+    }
+}
+
+fn collect_qemu_candidates() -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+
+    for base in get_search_paths() {
+        append_candidate_if_exists(&mut seen, &mut candidates, base);
+    }
+
+    // Add resolved binaries from PATH, with a default macOS-friendly PATH fallback.
+    let lookup_path = build_lookup_path();
+    for binary in candidate_binary_names() {
+        let output = Command::new("which")
+            .arg(binary)
+            .env("PATH", &lookup_path)
+            .output();
+        if let Ok(result) = output {
+            if result.status.success() {
+                let path_str = String::from_utf8_lossy(&result.stdout).trim().to_string();
+                if !path_str.is_empty() {
+                    append_candidate_if_exists(
+                        &mut seen,
+                        &mut candidates,
+                        PathBuf::from(path_str),
+                    );
+                }
+            }
+        }
+    }
+
+    candidates
+}
+
+/// First of `names` found on the PATH or the default shell PATH
+pub fn find_tool(names: &[&str]) -> Option<PathBuf> {
+    let lookup_path = build_lookup_path();
+    env::split_paths(&lookup_path).find_map(|dir| {
+        names
+            .iter()
+            .map(|name| dir.join(format!("{}{}", name, env::consts::EXE_SUFFIX)))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+pub fn is_runnable_qemu(path: &Path) -> bool {
+    Command::new(path)
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Find QEMU binary in system PATH
+pub fn find_qemu_binary() -> Result<PathBuf> {
+    let candidates = collect_qemu_candidates();
+
+    // Prefer candidates that are directly runnable.
+    for path in &candidates {
+        if is_runnable_qemu(path) {
+            return Ok(path.clone());
+        }
+    }
+
+    // Fall back to existing binaries, even if version probing failed.
+    for path in candidates {
+        if path.exists() {
+            return Ok(path);
+        }
+    }
+
+    Err(Error::QemuNotFound)
+}
+
+/// Path of the sibling `qemu-system-<arch>` binary next to `qemu_path`
+pub fn binary_for_arch(qemu_path: &str, arch: &str) -> String {
+    let path = Path::new(qemu_path);
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !file_name.starts_with("qemu-system-") {
+        return qemu_path.to_string();
+    }
+
+    let suffix = if file_name.ends_with(".exe") { ".exe" } else { "" };
+    path.with_file_name(format!("qemu-system-{}{}", arch, suffix))
+        .display()
+        .to_string()
+}
+
+/// Detect HVF support on macOS via sysctl
+#[cfg(target_os = "macos")]
+fn detect_hvf_support() -> Result<String> {
+    let output = Command::new("sysctl")
+        .arg("kern.hv_support")
+        .output()
+        .map_err(|e| Error::QemuError(format!("Failed to check HVF: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(Error::QemuError("HVF not available".to_string()));
+    }
+
+    let sysctl_output = String::from_utf8_lossy(&output.stdout);
+    if sysctl_output.contains("1") {
+        Ok("HVF".to_string())
+    } else {
+        Err(Error::QemuError("HVF disabled".to_string()))
+    }
+}
+
+/// Detect KVM support on Linux
+#[cfg(target_os = "linux")]
+fn detect_kvm_support() -> Result<String> {
+    let kvm_path = PathBuf::from("/dev/kvm");
+    if kvm_path.exists() {
+        Ok("KVM".to_string())
+    } else {
+        Err(Error::QemuError("KVM not available".to_string()))
+    }
+}
+
+/// Detect WHPX support on Windows
+#[cfg(target_os = "windows")]
+fn detect_whpx_support() -> Result<String> {
+    // Check if WHPX feature is enabled on Windows
+    // For now, return WHPX as available (actual check requires Windows API)
+    Ok("WHPX".to_string())
+}
+
+/// Get QEMU installation search paths by platform
+#[cfg(target_os = "macos")]
+fn get_search_paths() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("/opt/homebrew/bin/qemu-system-x86_64"),
+        PathBuf::from("/opt/homebrew/bin/qemu-system-aarch64"),
+        PathBuf::from("/opt/homebrew/opt/qemu/bin/qemu-system-x86_64"),
+        PathBuf::from("/opt/homebrew/opt/qemu/bin/qemu-system-aarch64"),
+        PathBuf::from("/usr/local/bin/qemu-system-x86_64"),
+        PathBuf::from("/usr/local/bin/qemu-system-aarch64"),
+        PathBuf::from("/usr/bin/qemu-system-x86_64"),
+        PathBuf::from("/usr/bin/qemu-system-aarch64"),
+    ]
+}
+
+#[cfg(target_os = "linux")]
+fn get_search_paths() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("/usr/bin/qemu-system-x86_64"),
+        PathBuf::from("/usr/bin/qemu-system-aarch64"),
+        PathBuf::from("/usr/libexec/qemu-system-x86_64"),
+    ]
+}
+
+#[cfg(target_os = "windows")]
+fn get_search_paths() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("C:\\Program Files\\qemu\\qemu-system-x86_64.exe"),
+        PathBuf::from("C:\\Program Files (x86)\\qemu\\qemu-system-x86_64.exe"),
+    ]
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+fn get_search_paths() -> Vec<PathBuf> {
+    vec![]
+}
+
+/// Get QEMU version from binary
+pub fn get_qemu_version(path: &PathBuf) -> Result<String> {
+    let output = Command::new(path)
+        .arg("--version")
+        .output()
+        .map_err(|e| Error::QemuError(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(Error::QemuError("Failed to get QEMU version".to_string()));
+    }
+
+    let version_str = String::from_utf8(output.stdout)
+        .map_err(|e| Error::QemuError(e.to_string()))?;
+
+    Ok(version_str
+        .lines()
+        .next()
+        .unwrap_or("unknown")
+        .trim()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qemu_not_found() {
+        let result = find_qemu_binary();
+        // This may fail on CI, but on a dev machine with QEMU installed, it should succeed
+        // or fail gracefully
+        match result {
+            Ok(_) => {} // QEMU is installed
+            Err(Error::QemuNotFound) => {} // QEMU not installed (expected in CI)
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_detect_runs_off_the_executor() {
+        // A single-threaded runtime keeps serving other tasks while detection runs
+        let ticker = tokio::spawn(async { tokio::task::yield_now().await });
+        match detect().await {
+            Ok(info) => assert!(info.detected),
+            Err(Error::QemuNotFound) => {}
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+        ticker.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_inspect_binary_requires_a_working_qemu() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fake_qemu = temp_dir.path().join("qemu-system-x86_64");
+        std::fs::write(&fake_qemu, "#!/bin/sh\necho 'QEMU emulator version 9.1.0'\n").unwrap();
+        std::fs::set_permissions(&fake_qemu, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let info = inspect_binary(fake_qemu.clone()).await.unwrap();
+        assert_eq!(info.path, Some(fake_qemu.display().to_string()));
+        assert_eq!(info.version.as_deref(), Some("QEMU emulator version 9.1.0"));
+
+        let broken = temp_dir.path().join("broken");
+        std::fs::write(&broken, "#!/bin/sh\nexit 1\n").unwrap();
+        std::fs::set_permissions(&broken, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(inspect_binary(broken).await.is_err());
+        assert!(inspect_binary(temp_dir.path().join("missing")).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_inventory_keeps_working_binaries_per_arch() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let install = |arch: &str, script: &str| {
+            let path = temp_dir.path().join(format!("qemu-system-{}", arch));
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        install("x86_64", "#!/bin/sh\necho 'QEMU emulator version 9.1.0'\n");
+        install("riscv64", "#!/bin/sh\necho 'QEMU emulator version 8.2.2'\n");
+        // Present but broken, like a binary missing its shared libraries
+        install("aarch64", "#!/bin/sh\nexit 127\n");
+
+        let find = |arch: &str| {
+            let path = temp_dir.path().join(format!("qemu-system-{}", arch));
+            path.is_file().then_some(path)
+        };
+        let found = inventory(find);
+        let mut arches: Vec<&str> = found.keys().map(String::as_str).collect();
+        arches.sort();
+        assert_eq!(arches, vec!["riscv64", "x86_64"]);
+        assert_eq!(found["riscv64"].version.as_deref(), Some("QEMU emulator version 8.2.2"));
+        assert!(found["x86_64"].path.as_ref().unwrap().ends_with("qemu-system-x86_64"));
+
+        assert!(inventory(|_| None).is_empty());
+        assert!(detect_arch_with("aarch64", find).is_none());
+    }
+
+    #[test]
+    fn test_get_qemu_version_format() {
+        // This test requires QEMU to be installed
+        if let Ok(qemu_path) = find_qemu_binary() {
+            let version = get_qemu_version(&qemu_path);
+            assert!(
+                version.is_ok(),
+                "Should be able to get QEMU version if binary found"
+            );
+
+            if let Ok(version_str) = version {
+                assert!(
+                    !version_str.is_empty(),
+                    "Version string should not be empty"
+                );
+                // Version should contain 'QEMU' or a version number
+                assert!(
+                    version_str.contains("QEMU") || version_str.chars().next().unwrap().is_numeric(),
+                    "Version should contain QEMU or numeric version"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_qemu_info_has_required_fields() {
+        // If QEMU is found, ensure all required fields are populated
+        if let Ok(qemu_path) = find_qemu_binary() {
+            let info = QemuInfo {
+                detected: true,
+                path: Some(qemu_path.display().to_string()),
+                version: get_qemu_version(&qemu_path).ok(),
+                accelerator: None,
+                host_arch: platform::host_arch(),
+                supported_machines: list_machines(&qemu_path),
+                supported_accels: list_accels(&qemu_path),
+            };
+
+            assert!(info.detected, "Detected should be true");
+            assert!(info.path.is_some(), "Path should be populated");
+            assert!(
+                info.path.as_ref().unwrap().contains("qemu"),
+                "Path should contain 'qemu'"
+            );
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_hvf_detection() {
+        // Test HVF detection on macOS
+        let result = detect_hvf_support();
+        // Result depends on actual system support, just verify it doesn't panic
+        match result {
+            Ok(accel) => assert_eq!(accel, "HVF"),
+            Err(_) => {} // HVF not available is acceptable
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_kvm_detection() {
+        // Test KVM detection on Linux
+        let result = detect_kvm_support();
+        // /dev/kvm existence depends on system, just verify it doesn't panic
+        // KVM not available is acceptable
+        if let Ok(accel) = result {
+            assert_eq!(accel, "KVM");
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_whpx_detection() {
+        let result = detect_whpx_support();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "WHPX");
+    }
+
+    #[test]
+    fn test_binary_for_arch_swaps_system_suffix() {
+        assert_eq!(
+            binary_for_arch("/opt/homebrew/bin/qemu-system-aarch64", "x86_64"),
+            "/opt/homebrew/bin/qemu-system-x86_64"
+        );
+        assert_eq!(binary_for_arch("echo", "x86_64"), "echo");
+    }
+
+    #[test]
+    fn test_parse_machine_help() {
+        let output = "Supported machines are:\n\
+microvm              microvm (i386)\n\
+pc                   Standard PC (i440FX + PIIX, 1996) (alias of pc-i440fx-8.2)\n\
+pc-i440fx-8.2        Standard PC (i440FX + PIIX, 1996) (default)\n\
+q35                  Standard PC (Q35 + ICH9, 2009) (alias of pc-q35-8.2)\n\
+pc-q35-8.2           Standard PC (Q35 + ICH9, 2009)\n\
+none                 empty machine\n";
+
+        let machines = parse_machine_help(output);
+        assert_eq!(machines.len(), 6);
+        assert!(machines.contains(&"q35".to_string()));
+        assert!(machines.contains(&"pc".to_string()));
+        assert!(!machines.iter().any(|machine| machine == "Supported"));
+    }
+
+    #[test]
+    fn test_parse_cpu_help_x86() {
+        let output = "Available CPUs:\n\
+x86 Broadwell             (alias configured by machine type)\n\
+x86 Broadwell-v1          Intel Core Processor (Broadwell)\n\
+x86 host                  KVM processor with all supported host features\n\
+x86 qemu64-v1\n\
+\n\
+Recognized CPUID flags:\n\
+  3dnow 3dnowext abm\n\
+  avx avx2\n";
+
+        let list = parse_cpu_help(output);
+        let names: Vec<&str> = list.models.iter().map(|model| model.name.as_str()).collect();
+        assert_eq!(names, vec!["Broadwell", "Broadwell-v1", "host", "qemu64-v1"]);
+        assert_eq!(list.models[1].note.as_deref(), Some("Intel Core Processor (Broadwell)"));
+        assert_eq!(list.models[3].note, None);
+        assert_eq!(list.flags, vec!["3dnow", "3dnowext", "abm", "avx", "avx2"]);
+    }
+
+    #[test]
+    fn test_parse_cpu_help_arm() {
+        let output = "Available CPUs:\n  cortex-a57\n  host\n  max\n";
+        let list = parse_cpu_help(output);
+        let names: Vec<&str> = list.models.iter().map(|model| model.name.as_str()).collect();
+        assert_eq!(names, vec!["cortex-a57", "host", "max"]);
+        assert!(list.flags.is_empty());
+    }
+
+    #[test]
+    fn test_query_accels_fails_for_missing_binary() {
+        assert!(query_accels(Path::new("/nonexistent/qemu")).is_err());
+    }
+
+    #[test]
+    fn test_query_cpu_models_fails_for_missing_binary() {
+        assert!(query_cpu_models(Path::new("/nonexistent/qemu")).is_err());
+    }
+
+    #[test]
+    fn test_parse_accel_help() {
+        let output = "Accelerators supported in QEMU binary:\ntcg\nkvm\n";
+        assert_eq!(parse_accel_help(output), vec!["tcg", "kvm"]);
+        assert!(parse_accel_help("").is_empty());
+    }
+
+    #[test]
+    fn test_list_machines_empty_for_missing_binary() {
+        assert!(list_machines(Path::new("/nonexistent/qemu")).is_empty());
+        assert!(list_accels(Path::new("/nonexistent/qemu")).is_empty());
+    }
+
+    #[test]
+    fn test_query_machines_fails_for_missing_binary() {
+        assert!(query_machines(Path::new("/nonexistent/qemu")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_tool() {
+        assert!(find_tool(&["definitely-not-a-tool", "sh"]).unwrap().ends_with("sh"));
+        assert_eq!(find_tool(&["definitely-not-a-tool"]), None);
+    }
+
+    #[test]
+    fn test_get_search_paths_not_empty() {
+        let paths = get_search_paths();
+        assert!(!paths.is_empty(), "Search paths should not be empty for any platform");
+    }
+
+    #[test]
+    fn test_candidate_binary_names_cover_supported_system_types() {
+        let names = candidate_binary_names();
+        assert!(names.contains(&"qemu-system-aarch64"));
+        assert!(names.contains(&"qemu-system-x86_64"));
+    }
+
+    #[test]
+    fn test_search_paths_contain_qemu_references() {
+        let paths = get_search_paths();
+        let has_qemu_ref = paths.iter().any(|p| {
+            p.to_string_lossy().contains("qemu")
+        });
+        assert!(
+            has_qemu_ref,
+            "At least one search path should reference 'qemu'"
+        );
+    }
+}
@@ -0,0 +1,8 @@
+pub mod detector;
+pub mod controller;
+pub mod qmp;
+pub mod monitor;
+pub mod command;
+
+pub use controller::{ProcessExit, QemuController, VmRunningInfo, START_DEBOUNCE};
+pub use command::{QemuCommand, option_value, VIRTIO_SCSI_INTERFACE, DISK_INTERFACES, resolve_spice_port, display_port_from_args, DisplayPortRange, VNC_BASE_PORT, DEFAULT_DISPLAY_PORT_RANGE_START, DEFAULT_DISPLAY_PORT_RANGE_END, LOOPBACK_LISTEN_ADDRESS, Accelerator, MachineType, DriveConfig, DriveSource, HotplugDisk, IoThrottle, NetdevConfig, NetworkConfig, PortForward, MAX_NETWORK_ADAPTERS, DisplayConfig, SpiceTls, SpiceCompression, validate_network_config, parse_resolution, GraphicsAdapter, is_valid_machine_name, VfioPciDevice, BootMenuConfig, generate_stable_mac, MemoryBackend, MEMORY_BACKENDS, hugepages_needed, VmPerformance, AIO_MODES, IO_URING_MIN_KERNEL, PointerDevice, POINTER_DEVICES, BootDevice, BOOT_DEVICES, RtcBase, RTC_BASES, RngBackend, HOST_ENTROPY_SOURCE};
@@ -0,0 +1,186 @@
+use crate::{Error, Result};
+
+/// Upper bound for connecting to the monitor and running one command
+const MONITOR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const PROMPT: &str = "(qemu)";
+
+/// Commands that end the VM behind the app's back; use the typed lifecycle
+/// commands so status, restarts and cleanup stay consistent
+const BLOCKED_COMMANDS: [&str; 3] = ["quit", "q", "poweroff"];
+
+pub fn check_command(command: &str) -> Result<()> {
+    // A newline would smuggle a second, unchecked command onto the monitor
+    if command.chars().any(char::is_control) {
+        return Err(Error::InvalidConfig("Monitor command cannot contain control characters".to_string()));
+    }
+    let name = command.split_whitespace().next().unwrap_or_default();
+    if name.is_empty() {
+        return Err(Error::InvalidConfig("Monitor command cannot be empty".to_string()));
+    }
+    if BLOCKED_COMMANDS.contains(&name.to_ascii_lowercase().as_str()) {
+        return Err(Error::InvalidConfig(format!(
+            "Monitor command '{}' is not allowed; stop the VM instead",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Send one HMP command over the monitor socket and return its output
+#[cfg(unix)]
+pub async fn execute(socket_path: &str, command: &str) -> Result<String> {
+    execute_with_timeout(socket_path, command, MONITOR_TIMEOUT).await
+}
+
+#[cfg(unix)]
+async fn execute_with_timeout(socket_path: &str, command: &str, timeout: std::time::Duration) -> Result<String> {
+    tokio::time::timeout(timeout, run(socket_path, command))
+        .await
+        .map_err(|_| Error::QemuError(format!("Monitor command '{}' timed out", command)))?
+}
+
+#[cfg(not(unix))]
+pub async fn execute(_socket_path: &str, _command: &str) -> Result<String> {
+    Err(Error::PlatformError("The QEMU monitor socket is not supported on this platform".to_string()))
+}
+
+#[cfg(unix)]
+async fn run(socket_path: &str, command: &str) -> Result<String> {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket_path).await?;
+    // Discard the banner up to the first prompt
+    read_until_prompt(&mut stream).await?;
+
+    stream.write_all(format!("{}\n", command.trim()).as_bytes()).await?;
+    let output = read_until_prompt(&mut stream).await?;
+    Ok(clean_output(&output, command))
+}
+
+#[cfg(unix)]
+async fn read_until_prompt(stream: &mut tokio::net::UnixStream) -> Result<String> {
+    use tokio::io::AsyncReadExt;
+
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(Error::QemuError("Monitor socket closed".to_string()));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        let text = String::from_utf8_lossy(&buffer);
+        if let Some(end) = text.rfind(PROMPT) {
+            return Ok(text[..end].to_string());
+        }
+    }
+}
+
+/// Strip terminal escapes, carriage returns and the echoed command line
+fn clean_output(raw: &str, command: &str) -> String {
+    let mut text = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => {
+                // CSI sequence: ESC [ params final-byte
+                if chars.peek() == Some(&'[') {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\r' => {}
+            c => text.push(c),
+        }
+    }
+
+    let mut lines = text.lines().peekable();
+    if lines.peek().is_some_and(|line| line.trim() == command.trim()) {
+        lines.next();
+    }
+    lines.collect::<Vec<_>>().join("\n").trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_command_blocks_lifecycle_commands() {
+        for command in ["quit", "q", "QUIT", "  poweroff now"] {
+            assert!(check_command(command).is_err(), "{}", command);
+        }
+        assert!(check_command("").is_err());
+        assert!(check_command("info status\nquit").is_err());
+        assert!(check_command("info status\r").is_err());
+        assert!(check_command("info\tstatus").is_err());
+        assert!(check_command("info status").is_ok());
+        assert!(check_command("query").is_ok());
+    }
+
+    #[test]
+    fn test_clean_output_strips_echo_and_escapes() {
+        let raw = "info status\r\n\u{1b}[K\u{1b}[DVM status: running\r\n";
+        assert_eq!(clean_output(raw, "info status"), "VM status: running");
+        assert_eq!(clean_output("\r\n", "info status"), "");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_against_fake_monitor() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("monitor.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer
+                .write_all(b"QEMU 8.2.0 monitor - type 'help' for more information\r\n(qemu) ")
+                .await
+                .unwrap();
+            let command = lines.next_line().await.unwrap().unwrap();
+            assert_eq!(command, "info status");
+            writer
+                .write_all(b"info status\r\nVM status: running\r\n(qemu) ")
+                .await
+                .unwrap();
+        });
+
+        let output = execute(&socket.display().to_string(), "info status").await.unwrap();
+        assert_eq!(output, "VM status: running");
+        server.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_times_out_without_prompt() {
+        use tokio::net::UnixListener;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("monitor.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let _server = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        });
+
+        let err = execute_with_timeout(
+            &socket.display().to_string(),
+            "info status",
+            std::time::Duration::from_millis(50),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+}
@@ -0,0 +1,1085 @@
+use crate::{Error, Result};
+use serde_json::Value;
+
+/// Upper bound for connecting, negotiating and running one QMP command
+const QMP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How often a running snapshot job is polled for completion
+const JOB_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Events buffered per session before slow listeners start missing them
+#[cfg(unix)]
+const EVENT_BUFFER: usize = 64;
+
+pub struct QmpClient {
+    pub socket_path: String,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    timeout: std::time::Duration,
+}
+
+impl QmpClient {
+    pub fn new(socket_path: String) -> Self {
+        Self {
+            socket_path,
+            timeout: QMP_TIMEOUT,
+        }
+    }
+
+    /// Run a single QMP command on the socket's shared session, connecting
+    /// and negotiating capabilities first if there is none yet.
+    ///
+    /// Returns the `return` payload, a `QemuError` carrying the QMP error
+    /// description, or `QmpTimeout` if QEMU does not answer in time.
+    #[cfg(unix)]
+    #[tracing::instrument(skip_all, fields(command = %command))]
+    pub async fn execute(&self, command: &str, arguments: Option<Value>) -> Result<Value> {
+        tracing::debug!(socket = %self.socket_path, "sending QMP command");
+        let result = tokio::time::timeout(self.timeout, self.run(command, arguments))
+            .await
+            .unwrap_or_else(|_| Err(Error::QmpTimeout(command.to_string())));
+        if let Err(err) = &result {
+            tracing::warn!(error = %err, "QMP command failed");
+            crate::metrics::record_qmp_error();
+        }
+        result
+    }
+
+    #[cfg(unix)]
+    async fn run(&self, command: &str, arguments: Option<Value>) -> Result<Value> {
+        let response = session(&self.socket_path).await?.send(command_message(command, arguments)).await?;
+        parse_response(response)
+    }
+
+    /// Poll until QEMU accepts connections on the QMP socket
+    #[cfg(unix)]
+    pub async fn wait_until_ready(&self, timeout: std::time::Duration) -> Result<()> {
+        let connect = async {
+            loop {
+                if tokio::net::UnixStream::connect(&self.socket_path).await.is_ok() {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+        };
+        tokio::time::timeout(timeout, connect)
+            .await
+            .map_err(|_| Error::QmpTimeout("connect".to_string()))
+    }
+
+    /// Listen for the first of `events`, returning its message. Listening shares
+    /// the socket's session, so commands keep going through while this waits.
+    #[cfg(unix)]
+    pub async fn wait_for_event(&self, events: &[&str], timeout: std::time::Duration) -> Result<Value> {
+        tokio::time::timeout(timeout, self.listen(events))
+            .await
+            .map_err(|_| Error::QmpTimeout(events.join("|")))?
+    }
+
+    #[cfg(unix)]
+    async fn listen(&self, events: &[&str]) -> Result<Value> {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let mut received = session(&self.socket_path).await?.events.subscribe();
+        loop {
+            match received.recv().await {
+                Ok(message) if message["event"].as_str().is_some_and(|event| events.contains(&event)) => {
+                    return Ok(message)
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(Error::QemuError("QMP socket closed".to_string())),
+            }
+        }
+    }
+
+    /// Open `file_path` as block node `node_name`, ready for a device to use
+    pub async fn blockdev_add(&self, node_name: &str, file_path: &str, format: &str) -> Result<()> {
+        self.execute("blockdev-add", Some(blockdev_add_arguments(node_name, file_path, format)))
+            .await?;
+        Ok(())
+    }
+
+    /// Remove block node `node_name`; it must no longer be used by a device
+    pub async fn blockdev_del(&self, node_name: &str) -> Result<()> {
+        self.execute("blockdev-del", Some(serde_json::json!({ "node-name": node_name })))
+            .await?;
+        Ok(())
+    }
+
+    /// Unplug disk device `id`, then free its block node `node_name`. The guest
+    /// must release the device first, which QEMU reports as `DEVICE_DELETED`.
+    #[cfg(unix)]
+    pub async fn device_del_drive(&self, id: &str, node_name: &str, timeout: std::time::Duration) -> Result<()> {
+        use tokio::sync::broadcast::error::RecvError;
+
+        // Subscribed before asking, so a quick guest's event is not missed
+        let mut received = session(&self.socket_path).await?.events.subscribe();
+        self.execute("device_del", Some(serde_json::json!({ "id": id }))).await?;
+        let deleted = async {
+            loop {
+                match received.recv().await {
+                    Ok(message) if message["event"] == "DEVICE_DELETED" && message["data"]["device"] == id => {
+                        return Ok(())
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Err(Error::QemuError("QMP socket closed".to_string())),
+                }
+            }
+        };
+        tokio::time::timeout(timeout, deleted)
+            .await
+            .map_err(|_| Error::QmpTimeout("device_del".to_string()))??;
+        self.blockdev_del(node_name).await
+    }
+
+    /// Plug disk device `id`, backed by block node `node_name`, into the guest
+    pub async fn device_add_drive(&self, id: &str, node_name: &str, interface: &str) -> Result<()> {
+        self.execute("device_add", Some(device_add_drive_arguments(id, node_name, interface)?))
+            .await?;
+        Ok(())
+    }
+
+    /// Memory the balloon currently leaves the guest, in bytes; fails when
+    /// the VM has no balloon device
+    pub async fn query_balloon(&self) -> Result<u64> {
+        let balloon = self.execute("query-balloon", None).await?;
+        balloon["actual"]
+            .as_u64()
+            .ok_or_else(|| Error::QemuError("query-balloon returned no size".to_string()))
+    }
+
+    /// Inflate or deflate the balloon until the guest has `bytes` of memory
+    pub async fn set_balloon(&self, bytes: u64) -> Result<()> {
+        self.execute("balloon", Some(serde_json::json!({ "value": bytes }))).await?;
+        Ok(())
+    }
+
+    /// Run `snapshot-save` or `snapshot-load` for tag `tag` on drive `drive_id`,
+    /// which also holds the RAM state, and wait up to `timeout` for the job
+    pub async fn run_snapshot_job(
+        &self,
+        command: &str,
+        tag: &str,
+        drive_id: &str,
+        timeout: std::time::Duration,
+    ) -> Result<()> {
+        let blocks = self.execute("query-block", None).await?;
+        let node = drive_node_name(&blocks, drive_id)
+            .ok_or_else(|| Error::QemuError(format!("Drive {} has no block node", drive_id)))?;
+        let job_id = format!("{}-{}", command, tag);
+        self.execute(command, Some(snapshot_job_arguments(&job_id, tag, &node))).await?;
+        self.wait_for_job(command, &job_id, timeout).await
+    }
+
+    /// Freeze drive `drive_id`'s image and continue on a new qcow2 overlay at
+    /// `overlay_path`, which QEMU creates with the image as its backing file
+    pub async fn snapshot_sync(&self, drive_id: &str, overlay_path: &str) -> Result<()> {
+        self.execute("blockdev-snapshot-sync", Some(snapshot_sync_arguments(drive_id, overlay_path)))
+            .await?;
+        Ok(())
+    }
+
+    /// Merge drive `drive_id`'s active overlay into `base`, the image directly
+    /// beneath it, which becomes the active image again, and wait up to `timeout`.
+    /// Naming the base keeps deeper images in the chain untouched.
+    pub async fn commit_active_layer(&self, drive_id: &str, base: &str, timeout: std::time::Duration) -> Result<()> {
+        let job_id = format!("block-commit-{}", drive_id);
+        self.execute("block-commit", Some(block_commit_arguments(&job_id, drive_id, base))).await?;
+        self.wait_for_job("block-commit", &job_id, timeout).await
+    }
+
+    /// Poll job `job_id` until it concludes. A job that reaches `ready`, like
+    /// an active commit, is told to complete.
+    async fn wait_for_job(&self, command: &str, job_id: &str, timeout: std::time::Duration) -> Result<()> {
+        let finished = async {
+            let mut completing = false;
+            loop {
+                let jobs = self.execute("query-jobs", None).await?;
+                if let Some(outcome) = job_outcome(&jobs, job_id) {
+                    // Concluded jobs linger until dismissed and would block a reused id
+                    let _ = self.execute("job-dismiss", Some(serde_json::json!({ "id": job_id }))).await;
+                    return outcome;
+                }
+                if !completing && job_is_ready(&jobs, job_id) {
+                    self.execute("job-complete", Some(serde_json::json!({ "id": job_id }))).await?;
+                    completing = true;
+                }
+                tokio::time::sleep(JOB_POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(timeout, finished)
+            .await
+            .map_err(|_| Error::QmpTimeout(command.to_string()))?
+    }
+
+    #[cfg(not(unix))]
+    pub async fn wait_for_event(&self, _events: &[&str], _timeout: std::time::Duration) -> Result<Value> {
+        Err(Error::PlatformError("QMP over unix sockets is not supported on this platform".to_string()))
+    }
+
+    #[cfg(not(unix))]
+    pub async fn device_del_drive(&self, _id: &str, _node_name: &str, _timeout: std::time::Duration) -> Result<()> {
+        Err(Error::PlatformError("QMP over unix sockets is not supported on this platform".to_string()))
+    }
+
+    #[cfg(not(unix))]
+    pub async fn wait_until_ready(&self, _timeout: std::time::Duration) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub async fn execute(&self, _command: &str, _arguments: Option<Value>) -> Result<Value> {
+        Err(Error::PlatformError("QMP over unix sockets is not supported on this platform".to_string()))
+    }
+}
+
+#[cfg(unix)]
+type QmpLines = tokio::io::Lines<tokio::io::BufReader<tokio::net::unix::OwnedReadHalf>>;
+
+/// One negotiated QMP connection, shared by every `QmpClient` for the socket.
+///
+/// A QEMU monitor serves a single client at a time, so a second connection
+/// would stall until the first closes. Commands are queued on this one instead
+/// and answered in order; events are fanned out to `events` subscribers.
+#[cfg(unix)]
+struct Session {
+    /// Held while a command is written and its reply slot queued, keeping both in order
+    writer: tokio::sync::Mutex<tokio::net::unix::OwnedWriteHalf>,
+    pending: std::sync::Mutex<std::collections::VecDeque<tokio::sync::oneshot::Sender<Value>>>,
+    events: tokio::sync::broadcast::Sender<Value>,
+    closed: std::sync::atomic::AtomicBool,
+}
+
+#[cfg(unix)]
+impl Session {
+    async fn connect(socket_path: &str) -> Result<std::sync::Arc<Self>> {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let stream = tokio::net::UnixStream::connect(socket_path).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let greeting = read_message(&mut lines).await?;
+        if greeting.get("QMP").is_none() {
+            return Err(Error::QemuError("Unexpected QMP greeting".to_string()));
+        }
+        // Events are only delivered once capabilities are negotiated
+        send_message(&mut writer, &command_message("qmp_capabilities", None)).await?;
+        parse_response(read_response(&mut lines).await?)?;
+
+        let session = std::sync::Arc::new(Self {
+            writer: tokio::sync::Mutex::new(writer),
+            pending: std::sync::Mutex::new(std::collections::VecDeque::new()),
+            events: tokio::sync::broadcast::channel(EVENT_BUFFER).0,
+            closed: std::sync::atomic::AtomicBool::new(false),
+        });
+        tokio::spawn(session.clone().dispatch(lines));
+        Ok(session)
+    }
+
+    /// Route replies to waiting commands and events to subscribers until the socket closes
+    async fn dispatch(self: std::sync::Arc<Self>, mut lines: QmpLines) {
+        while let Ok(message) = read_message(&mut lines).await {
+            if message.get("event").is_some() {
+                let _ = self.events.send(message);
+            } else if let Some(reply) = self.pending.lock().unwrap_or_else(|e| e.into_inner()).pop_front() {
+                let _ = reply.send(message);
+            }
+        }
+        self.closed.store(true, std::sync::atomic::Ordering::SeqCst);
+        // Dropping the reply slots fails every command still waiting
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    async fn send(&self, message: Value) -> Result<Value> {
+        let (reply, response) = tokio::sync::oneshot::channel();
+        {
+            let mut writer = self.writer.lock().await;
+            if self.is_closed() {
+                return Err(Error::QemuError("QMP socket closed".to_string()));
+            }
+            self.pending.lock().unwrap_or_else(|e| e.into_inner()).push_back(reply);
+            send_message(&mut writer, &message).await?;
+        }
+        response
+            .await
+            .map_err(|_| Error::QemuError("QMP socket closed".to_string()))
+    }
+}
+
+/// The open session for `socket_path`, connecting a new one if there is none or it closed
+#[cfg(unix)]
+async fn session(socket_path: &str) -> Result<std::sync::Arc<Session>> {
+    type Slot = std::sync::Arc<tokio::sync::Mutex<Option<std::sync::Arc<Session>>>>;
+    static SESSIONS: std::sync::OnceLock<std::sync::Mutex<std::collections::HashMap<String, Slot>>> =
+        std::sync::OnceLock::new();
+
+    let slot = SESSIONS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(socket_path.to_string())
+        .or_default()
+        .clone();
+    // Per-socket lock: a VM that hangs during the handshake only holds up its own callers
+    let mut current = slot.lock().await;
+    if let Some(session) = current.as_ref().filter(|session| !session.is_closed()) {
+        return Ok(session.clone());
+    }
+    let session = Session::connect(socket_path).await?;
+    *current = Some(session.clone());
+    Ok(session)
+}
+
+#[cfg(unix)]
+async fn read_message(lines: &mut QmpLines) -> Result<Value> {
+    let line = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| Error::QemuError("QMP socket closed".to_string()))?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Read the next command response, skipping asynchronous events
+#[cfg(unix)]
+async fn read_response(lines: &mut QmpLines) -> Result<Value> {
+    loop {
+        let message = read_message(lines).await?;
+        if message.get("event").is_none() {
+            return Ok(message);
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn send_message(writer: &mut tokio::net::unix::OwnedWriteHalf, message: &Value) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+fn command_message(command: &str, arguments: Option<Value>) -> Value {
+    match arguments {
+        Some(arguments) => serde_json::json!({ "execute": command, "arguments": arguments }),
+        None => serde_json::json!({ "execute": command }),
+    }
+}
+
+fn blockdev_add_arguments(node_name: &str, file_path: &str, format: &str) -> Value {
+    serde_json::json!({
+        "node-name": node_name,
+        "driver": format,
+        "file": { "driver": "file", "filename": file_path },
+    })
+}
+
+fn device_add_drive_arguments(id: &str, node_name: &str, interface: &str) -> Result<Value> {
+    let driver = match interface {
+        "virtio" => "virtio-blk-pci",
+        other => return Err(Error::InvalidConfig(format!("Disks cannot be hot-added on {}", other))),
+    };
+    Ok(serde_json::json!({ "driver": driver, "id": id, "drive": node_name }))
+}
+
+/// `query-block` entry for the drive QEMU was started with as `-drive id=<drive_id>`
+pub fn find_drive<'a>(blocks: &'a Value, drive_id: &str) -> Option<&'a Value> {
+    blocks.as_array()?.iter().find(|block| block["device"] == drive_id)
+}
+
+/// Block node behind a drive; `-drive` gives it a generated name
+fn drive_node_name(blocks: &Value, drive_id: &str) -> Option<String> {
+    find_drive(blocks, drive_id)?["inserted"]["node-name"].as_str().map(str::to_string)
+}
+
+fn snapshot_job_arguments(job_id: &str, tag: &str, node: &str) -> Value {
+    serde_json::json!({ "job-id": job_id, "tag": tag, "vmstate": node, "devices": [node] })
+}
+
+fn snapshot_sync_arguments(drive_id: &str, overlay_path: &str) -> Value {
+    serde_json::json!({ "device": drive_id, "snapshot-file": overlay_path, "format": "qcow2" })
+}
+
+/// Kept after concluding so its outcome can be read from `query-jobs`
+fn block_commit_arguments(job_id: &str, drive_id: &str, base: &str) -> Value {
+    serde_json::json!({ "job-id": job_id, "device": drive_id, "base": base, "auto-dismiss": false })
+}
+
+/// Whether job `job_id` is waiting for `job-complete`
+fn job_is_ready(jobs: &Value, job_id: &str) -> bool {
+    jobs.as_array()
+        .and_then(|jobs| jobs.iter().find(|job| job["id"] == job_id))
+        .is_some_and(|job| job["status"] == "ready")
+}
+
+/// `None` while job `job_id` is still running, else how it ended
+fn job_outcome(jobs: &Value, job_id: &str) -> Option<Result<()>> {
+    let Some(job) = jobs.as_array().and_then(|jobs| jobs.iter().find(|job| job["id"] == job_id)) else {
+        return Some(Err(Error::QemuError(format!("Job {} disappeared", job_id))));
+    };
+    if job["status"] != "concluded" {
+        return None;
+    }
+    Some(match job["error"].as_str() {
+        Some(error) => Err(Error::QemuError(error.to_string())),
+        None => Ok(()),
+    })
+}
+
+fn parse_response(response: Value) -> Result<Value> {
+    if let Some(error) = response.get("error") {
+        let desc = error["desc"].as_str().unwrap_or("unknown QMP error");
+        return Err(Error::QemuError(desc.to_string()));
+    }
+
+    response
+        .get("return")
+        .cloned()
+        .ok_or_else(|| Error::QemuError("Malformed QMP response".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::qemu::qmp::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_qmp_client_creation() {
+        let client = QmpClient::new("/tmp/qemu.sock".to_string());
+        assert_eq!(client.socket_path, "/tmp/qemu.sock");
+    }
+
+    #[test]
+    fn test_command_message_omits_empty_arguments() {
+        let cmd = command_message("query-status", None);
+        assert_eq!(cmd["execute"], "query-status");
+        assert!(cmd.get("arguments").is_none());
+
+        let cmd = command_message("block_set_io_throttle", Some(serde_json::json!({ "id": "disk0" })));
+        assert_eq!(cmd["arguments"]["id"], "disk0");
+    }
+
+    #[test]
+    fn test_hotplug_arguments() {
+        let blockdev = blockdev_add_arguments("node-1", "/data/extra.qcow2", "qcow2");
+        assert_eq!(
+            blockdev,
+            serde_json::json!({
+                "node-name": "node-1",
+                "driver": "qcow2",
+                "file": { "driver": "file", "filename": "/data/extra.qcow2" },
+            })
+        );
+
+        let device = device_add_drive_arguments("disk-1", "node-1", "virtio").unwrap();
+        assert_eq!(
+            device,
+            serde_json::json!({ "driver": "virtio-blk-pci", "id": "disk-1", "drive": "node-1" })
+        );
+        assert!(device_add_drive_arguments("disk-1", "node-1", "ide").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_job_helpers() {
+        let blocks = serde_json::json!([
+            { "device": "cd0", "inserted": { "node-name": "#block120" } },
+            { "device": "disk0", "inserted": { "node-name": "#block311" } },
+        ]);
+        assert_eq!(drive_node_name(&blocks, "disk0").as_deref(), Some("#block311"));
+        assert_eq!(drive_node_name(&blocks, "disk1"), None);
+        assert_eq!(
+            snapshot_job_arguments("snapshot-save-base", "base", "#block311"),
+            serde_json::json!({
+                "job-id": "snapshot-save-base",
+                "tag": "base",
+                "vmstate": "#block311",
+                "devices": ["#block311"],
+            })
+        );
+
+        let job = |status: &str, error: Option<&str>| {
+            let mut job = serde_json::json!({ "id": "job-1", "status": status });
+            if let Some(error) = error {
+                job["error"] = serde_json::json!(error);
+            }
+            serde_json::json!([job])
+        };
+        assert!(job_outcome(&job("running", None), "job-1").is_none());
+        assert!(job_outcome(&job("concluded", None), "job-1").unwrap().is_ok());
+        let err = job_outcome(&job("concluded", Some("No space left")), "job-1").unwrap().unwrap_err();
+        assert!(err.to_string().contains("No space left"));
+        assert!(job_outcome(&serde_json::json!([]), "job-1").unwrap().is_err());
+        assert!(job_is_ready(&job("ready", None), "job-1"));
+        assert!(!job_is_ready(&job("running", None), "job-1"));
+    }
+
+    #[test]
+    fn test_external_snapshot_arguments() {
+        assert_eq!(
+            snapshot_sync_arguments("disk0", "/data/vm-1-base.qcow2"),
+            serde_json::json!({ "device": "disk0", "snapshot-file": "/data/vm-1-base.qcow2", "format": "qcow2" })
+        );
+        assert_eq!(
+            block_commit_arguments("block-commit-disk0", "disk0", "/data/vm-1-base.qcow2"),
+            serde_json::json!({
+                "job-id": "block-commit-disk0",
+                "device": "disk0",
+                "base": "/data/vm-1-base.qcow2",
+                "auto-dismiss": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_response_maps_error_desc() {
+        let err = parse_response(serde_json::json!({
+            "error": { "class": "GenericError", "desc": "Device not found" }
+        }))
+        .unwrap_err();
+        assert!(err.to_string().contains("Device not found"));
+
+        let ok = parse_response(serde_json::json!({ "return": { "status": "running" } })).unwrap();
+        assert_eq!(ok["status"], "running");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_against_fake_server() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("qmp.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer
+                .write_all(b"{\"QMP\": {\"version\": {}, \"capabilities\": []}}\n")
+                .await
+                .unwrap();
+            let caps = lines.next_line().await.unwrap().unwrap();
+            assert!(caps.contains("qmp_capabilities"));
+            writer.write_all(b"{\"return\": {}}\n").await.unwrap();
+            let cmd = lines.next_line().await.unwrap().unwrap();
+            assert!(cmd.contains("query-status"));
+            writer
+                .write_all(b"{\"event\": \"RESUME\", \"data\": {}}\n{\"return\": {\"status\": \"running\"}}\n")
+                .await
+                .unwrap();
+        });
+
+        let client = QmpClient::new(socket.display().to_string());
+        let result = client.execute("query-status", None).await.unwrap();
+        assert_eq!(result["status"], "running");
+        server.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_wait_for_event_skips_others() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("qmp.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer
+                .write_all(b"{\"QMP\": {\"version\": {}, \"capabilities\": []}}\n")
+                .await
+                .unwrap();
+            lines.next_line().await.unwrap().unwrap();
+            writer.write_all(b"{\"return\": {}}\n").await.unwrap();
+            writer
+                .write_all(b"{\"event\": \"RESUME\", \"data\": {}}\n{\"event\": \"RESET\", \"data\": {}}\n")
+                .await
+                .unwrap();
+        });
+
+        let client = QmpClient::new(socket.display().to_string());
+        let event = client
+            .wait_for_event(&["RESET", "POWERUP"], std::time::Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(event["event"], "RESET");
+        server.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_commands_share_connection_with_event_listener() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("qmp.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        // Like QEMU's monitor, this only ever serves one client
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer
+                .write_all(b"{\"QMP\": {\"version\": {}, \"capabilities\": []}}\n")
+                .await
+                .unwrap();
+            lines.next_line().await.unwrap().unwrap();
+            writer.write_all(b"{\"return\": {}}\n").await.unwrap();
+            for status in ["running", "paused"] {
+                let cmd = lines.next_line().await.unwrap().unwrap();
+                assert!(cmd.contains("query-status"));
+                let reply = format!("{{\"return\": {{\"status\": \"{}\"}}}}\n", status);
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+            writer.write_all(b"{\"event\": \"POWERUP\", \"data\": {}}\n").await.unwrap();
+            lines.next_line().await.unwrap();
+        });
+
+        let client = QmpClient::new(socket.display().to_string());
+        let watcher = {
+            let client = QmpClient::new(socket.display().to_string());
+            tokio::spawn(async move {
+                client
+                    .wait_for_event(&["RESET", "POWERUP"], std::time::Duration::from_secs(5))
+                    .await
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(client.execute("query-status", None).await.unwrap()["status"], "running");
+        assert_eq!(client.execute("query-status", None).await.unwrap()["status"], "paused");
+        assert_eq!(watcher.await.unwrap().unwrap()["event"], "POWERUP");
+        server.abort();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_times_out_on_silent_server() {
+        use tokio::net::UnixListener;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("qmp.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let _server = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        });
+
+        let client = QmpClient {
+            socket_path: socket.display().to_string(),
+            timeout: std::time::Duration::from_millis(50),
+        };
+        let err = client.execute("query-status", None).await.unwrap_err();
+        assert!(matches!(err, Error::QmpTimeout(ref command) if command == "query-status"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_wait_until_ready() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("qmp.sock");
+        let client = QmpClient::new(socket.display().to_string());
+
+        let err = client.wait_until_ready(std::time::Duration::from_millis(150)).await.unwrap_err();
+        assert!(matches!(err, Error::QmpTimeout(_)));
+
+        let bind_path = socket.clone();
+        let server = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+            let listener = tokio::net::UnixListener::bind(&bind_path).unwrap();
+            let _ = listener.accept().await;
+        });
+        client.wait_until_ready(std::time::Duration::from_secs(5)).await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_qmp_handshake_structure() {
+        let greeting = serde_json::json!({
+            "QMP": {
+                "version": {
+                    "qemu": "7.0.0",
+                    "package": "7.0.0"
+                },
+                "capabilities": ["oob"]
+            }
+        });
+
+        assert!(greeting["QMP"]["version"]["qemu"].is_string());
+        assert!(greeting["QMP"]["capabilities"].is_array());
+    }
+
+    #[test]
+    fn test_qmp_capabilities_command() {
+        let cmd = serde_json::json!({
+            "execute": "qmp_capabilities",
+            "arguments": {},
+            "id": 1
+        });
+
+        assert_eq!(cmd["execute"], "qmp_capabilities");
+        assert_eq!(cmd["id"], 1);
+    }
+
+    #[test]
+    fn test_query_status_command() {
+        let cmd = serde_json::json!({
+            "execute": "query-status",
+            "arguments": {},
+            "id": 2
+        });
+
+        assert_eq!(cmd["execute"], "query-status");
+    }
+
+    #[test]
+    fn test_system_powerdown_command() {
+        let cmd = serde_json::json!({
+            "execute": "system_powerdown",
+            "arguments": {},
+            "id": 3
+        });
+
+        assert_eq!(cmd["execute"], "system_powerdown");
+    }
+
+    #[test]
+    fn test_stop_command() {
+        let cmd = serde_json::json!({
+            "execute": "stop",
+            "arguments": {},
+            "id": 4
+        });
+
+        assert_eq!(cmd["execute"], "stop");
+    }
+
+    #[test]
+    fn test_cont_command() {
+        let cmd = serde_json::json!({
+            "execute": "cont",
+            "arguments": {},
+            "id": 5
+        });
+
+        assert_eq!(cmd["execute"], "cont");
+    }
+
+    #[test]
+    fn test_quit_command() {
+        let cmd = serde_json::json!({
+            "execute": "quit",
+            "arguments": {},
+            "id": 6
+        });
+
+        assert_eq!(cmd["execute"], "quit");
+    }
+
+    #[test]
+    fn test_query_block_command() {
+        let cmd = serde_json::json!({
+            "execute": "query-block",
+            "arguments": {},
+            "id": 7
+        });
+
+        assert_eq!(cmd["execute"], "query-block");
+    }
+
+    #[test]
+    fn test_blockdev_add_command() {
+        let cmd = serde_json::json!({
+            "execute": "blockdev-add",
+            "arguments": {
+                "driver": "qcow2",
+                "node-name": "drive0",
+                "file": {
+                    "driver": "file",
+                    "filename": "/path/to/disk.qcow2"
+                }
+            },
+            "id": 8
+        });
+
+        assert_eq!(cmd["execute"], "blockdev-add");
+        assert_eq!(cmd["arguments"]["driver"], "qcow2");
+    }
+
+    #[test]
+    fn test_successful_response_parsing() {
+        let response = serde_json::json!({
+            "return": {
+                "running": true,
+                "singlestep": false,
+                "status": "running"
+            },
+            "id": 1
+        });
+
+        assert!(response["return"]["running"].as_bool().unwrap());
+        assert_eq!(response["return"]["status"], "running");
+        assert_eq!(response["id"], 1);
+    }
+
+    #[test]
+    fn test_error_response_parsing() {
+        let response = serde_json::json!({
+            "error": {
+                "class": "GenericError",
+                "desc": "VM is not running"
+            },
+            "id": 1
+        });
+
+        assert_eq!(response["error"]["class"], "GenericError");
+        assert!(response["error"]["desc"].as_str().unwrap().contains("running"));
+    }
+
+    #[test]
+    fn test_event_message_parsing() {
+        let event = serde_json::json!({
+            "event": "SHUTDOWN",
+            "data": {
+                "guest": true
+            },
+            "timestamp": {
+                "seconds": 1234567890,
+                "microseconds": 0
+            }
+        });
+
+        assert_eq!(event["event"], "SHUTDOWN");
+        assert!(event["data"]["guest"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn test_message_id_tracking() {
+        let cmd1 = serde_json::json!({
+            "execute": "query-status",
+            "arguments": {},
+            "id": 1
+        });
+
+        let cmd2 = serde_json::json!({
+            "execute": "query-status",
+            "arguments": {},
+            "id": 2
+        });
+
+        assert_ne!(cmd1["id"], cmd2["id"]);
+        assert_eq!(cmd1["id"], 1);
+        assert_eq!(cmd2["id"], 2);
+    }
+
+    #[test]
+    fn test_command_serialization() {
+        let cmd = serde_json::json!({
+            "execute": "query-status",
+            "arguments": {},
+            "id": 1
+        });
+
+        let serialized = serde_json::to_string(&cmd).unwrap();
+        assert!(serialized.contains("\"execute\":\"query-status\""));
+        assert!(serialized.contains("\"id\":1"));
+    }
+
+    #[test]
+    fn test_event_types() {
+        let event_types = vec![
+            "SHUTDOWN",
+            "STOP",
+            "RESUME",
+            "ERROR",
+            "DEVICE_ADDED",
+        ];
+
+        for event_type in event_types {
+            assert!(!event_type.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let error_codes = vec![
+            "ECONNREFUSED",
+            "ENOENT",
+            "EACCES",
+            "EPIPE",
+            "ECONNRESET",
+        ];
+
+        for code in error_codes {
+            assert!(!code.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_socket_path_validation() {
+        let valid_path = "/tmp/qemu.sock";
+        assert!(valid_path.starts_with('/'));
+        assert!(valid_path.contains("sock"));
+    }
+
+    #[test]
+    fn test_qmp_response_distinguish() {
+        let response = serde_json::json!({
+            "return": { "status": "running" },
+            "id": 1
+        });
+
+        let event = serde_json::json!({
+            "event": "SHUTDOWN",
+            "data": {}
+        });
+
+        assert!(response.get("return").is_some());
+        assert!(response.get("event").is_none());
+        assert!(event.get("event").is_some());
+        assert!(event.get("return").is_none());
+    }
+
+    #[test]
+    fn test_multiple_event_handlers() {
+        let handlers: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(vec![]));
+        
+        let h1 = handlers.clone();
+        let h1_closure = move |_event: String| {
+            h1.lock().unwrap().push("handler1".to_string());
+        };
+
+        let h2 = handlers.clone();
+        let h2_closure = move |_event: String| {
+            h2.lock().unwrap().push("handler2".to_string());
+        };
+
+        h1_closure("SHUTDOWN".to_string());
+        h2_closure("SHUTDOWN".to_string());
+
+        assert_eq!(handlers.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_command_with_arguments() {
+        let cmd = serde_json::json!({
+            "execute": "blockdev-add",
+            "arguments": {
+                "driver": "qcow2",
+                "node-name": "drive0",
+                "file": {
+                    "driver": "file",
+                    "filename": "/path/to/disk.qcow2"
+                }
+            },
+            "id": 1
+        });
+
+        assert!(cmd["arguments"].is_object());
+        assert!(cmd["arguments"]["file"].is_object());
+    }
+
+    #[test]
+    fn test_connection_state_transitions() {
+        let states = ["disconnected", "connecting", "connected", "disconnecting"];
+        
+        assert_eq!(states[0], "disconnected");
+        assert_eq!(states[states.len() - 1], "disconnecting");
+        assert!(states.len() == 4);
+    }
+
+    #[test]
+    fn test_socket_error_handling() {
+        let error_messages = vec![
+            "Connection refused",
+            "Socket disconnected",
+            "Write failed",
+            "QMP command timeout",
+        ];
+
+        for msg in error_messages {
+            assert!(!msg.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_matching_by_id() {
+        let cmd_id = 42;
+        let response = serde_json::json!({
+            "return": { "running": true },
+            "id": cmd_id
+        });
+
+        assert_eq!(response["id"], cmd_id);
+    }
+
+    #[test]
+    fn test_event_with_timestamp() {
+        let event = serde_json::json!({
+            "event": "SHUTDOWN",
+            "data": { "guest": true },
+            "timestamp": {
+                "seconds": 1234567890,
+                "microseconds": 123456
+            }
+        });
+
+        assert!(event["timestamp"]["seconds"].is_number());
+        assert_eq!(event["timestamp"]["microseconds"], 123456);
+    }
+
+    #[test]
+    fn test_qmp_capabilities_response() {
+        let response = serde_json::json!({
+            "return": {},
+            "id": 1
+        });
+
+        assert!(response["return"].is_object());
+        assert_eq!(response["id"], 1);
+    }
+
+    #[test]
+    fn test_pending_commands_tracking() {
+        let pending = Arc::new(Mutex::new(std::collections::HashMap::new()));
+        
+        pending.lock().unwrap().insert(1, "cmd1");
+        pending.lock().unwrap().insert(2, "cmd2");
+        
+        assert_eq!(pending.lock().unwrap().len(), 2);
+        assert!(pending.lock().unwrap().contains_key(&1));
+        
+        pending.lock().unwrap().remove(&1);
+        assert_eq!(pending.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_message_buffer_handling() {
+        let mut buffer = String::new();
+        buffer.push_str(r#"{"id":1,"return":"ok"}"#);
+        buffer.push('\n');
+        buffer.push_str(r#"{"id":2"#);
+
+        let lines: Vec<&str> = buffer.lines().collect();
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn test_json_parsing_errors() {
+        let invalid_json = "{ invalid }";
+        let result: std::result::Result<serde_json::Value, _> = serde_json::from_str(invalid_json);
+        assert!(result.is_err());
+    }
+}
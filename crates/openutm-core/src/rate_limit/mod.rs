@@ -0,0 +1,263 @@
+//! Per-command rate limiting
+//!
+//! Polling commands that spawn host processes (QEMU detection, process
+//! metrics) get a token bucket per command and VM, so a runaway frontend loop
+//! cannot pin the CPU. Lifecycle commands are never limited.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+
+/// Settings key prefix; `rate_limit.get_vm_metrics = "4/1"` allows 4 calls per second
+pub const SETTING_PREFIX: &str = "rate_limit.";
+
+/// Commands that are limited by default, as (command, calls, period)
+pub const DEFAULT_LIMITS: [(&str, u32, Duration); 2] = [
+    ("get_vm_metrics", 4, Duration::from_secs(1)),
+    ("detect_qemu", 1, Duration::from_secs(10)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    /// Burst size; the bucket refills one token every `period / calls`
+    pub calls: u32,
+    #[serde(with = "duration_secs")]
+    pub period: Duration,
+}
+
+impl RateLimit {
+    pub fn new(calls: u32, period: Duration) -> Self {
+        Self { calls, period }
+    }
+
+    /// Parse a `calls/seconds` setting value such as `4/1` or `1/10`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (calls, seconds) = value.trim().split_once('/')?;
+        let calls: u32 = calls.trim().parse().ok()?;
+        let seconds: f64 = seconds.trim().parse().ok()?;
+        if calls == 0 || !seconds.is_finite() || seconds <= 0.0 {
+            return None;
+        }
+        Some(Self::new(calls, Duration::from_secs_f64(seconds)))
+    }
+
+    pub fn to_setting(self) -> String {
+        format!("{}/{}", self.calls, self.period.as_secs_f64())
+    }
+
+    fn refill_interval(self) -> Duration {
+        self.period / self.calls
+    }
+}
+
+mod duration_secs {
+    use std::time::Duration;
+
+    pub fn serialize<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let seconds: f64 = serde::Deserialize::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+pub struct RateLimiter {
+    limits: Mutex<HashMap<String, RateLimit>>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        let limits = DEFAULT_LIMITS
+            .iter()
+            .map(|(command, calls, period)| (command.to_string(), RateLimit::new(*calls, *period)))
+            .collect();
+        Self {
+            limits: Mutex::new(limits),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Apply `rate_limit.*` overrides; malformed values keep the default
+    pub fn with_settings(self, settings: &[(String, String)]) -> Self {
+        for (key, value) in settings {
+            let Some(command) = key.strip_prefix(SETTING_PREFIX) else {
+                continue;
+            };
+            match RateLimit::parse(value) {
+                Some(limit) => self.set_limit(command, limit),
+                None => tracing::warn!(setting = %key, value = %value, "ignoring invalid rate limit"),
+            }
+        }
+        self
+    }
+
+    pub fn limits(&self) -> HashMap<String, RateLimit> {
+        self.limits.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Only commands that already have a limit can be retuned
+    pub fn is_limited(&self, command: &str) -> bool {
+        self.limits.lock().unwrap_or_else(|e| e.into_inner()).contains_key(command)
+    }
+
+    pub fn set_limit(&self, command: &str, limit: RateLimit) {
+        self.limits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(command.to_string(), limit);
+        // Start the retuned command with a full bucket under the new limit
+        self.buckets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|key, _| key.split(':').next() != Some(command));
+    }
+
+    /// Take a token for `command` (scoped to `vm_id`), or fail with `Error::RateLimited`
+    pub fn check(&self, command: &str, vm_id: Option<&str>) -> Result<()> {
+        self.check_at(command, vm_id, Instant::now())
+    }
+
+    fn check_at(&self, command: &str, vm_id: Option<&str>, now: Instant) -> Result<()> {
+        let Some(limit) = self.limits.lock().unwrap_or_else(|e| e.into_inner()).get(command).copied() else {
+            return Ok(());
+        };
+        let key = match vm_id {
+            Some(vm_id) => format!("{}:{}", command, vm_id),
+            None => command.to_string(),
+        };
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: limit.calls as f64,
+            updated_at: now,
+        });
+
+        let refill_secs = limit.refill_interval().as_secs_f64();
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed / refill_secs).min(limit.calls as f64);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) * refill_secs);
+        Err(Error::RateLimited {
+            command: command.to_string(),
+            retry_after_ms: retry_after.as_millis().max(1) as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry_after_ms(result: Result<()>) -> u64 {
+        match result {
+            Err(Error::RateLimited { retry_after_ms, .. }) => retry_after_ms,
+            other => panic!("expected rate limit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_metrics_bucket_refills_on_schedule() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+
+        for _ in 0..4 {
+            assert!(limiter.check_at("get_vm_metrics", Some("vm-1"), start).is_ok());
+        }
+        for _ in 0..100 {
+            assert_eq!(retry_after_ms(limiter.check_at("get_vm_metrics", Some("vm-1"), start)), 250);
+        }
+
+        // One token per 250 ms
+        let later = start + Duration::from_millis(100);
+        assert_eq!(retry_after_ms(limiter.check_at("get_vm_metrics", Some("vm-1"), later)), 150);
+        assert!(limiter
+            .check_at("get_vm_metrics", Some("vm-1"), start + Duration::from_millis(250))
+            .is_ok());
+        assert!(limiter
+            .check_at("get_vm_metrics", Some("vm-1"), start + Duration::from_millis(250))
+            .is_err());
+
+        // A full second idle restores the whole burst, but no more
+        let refilled = start + Duration::from_secs(5);
+        for _ in 0..4 {
+            assert!(limiter.check_at("get_vm_metrics", Some("vm-1"), refilled).is_ok());
+        }
+        assert!(limiter.check_at("get_vm_metrics", Some("vm-1"), refilled).is_err());
+    }
+
+    #[test]
+    fn test_buckets_are_per_vm() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for _ in 0..4 {
+            limiter.check_at("get_vm_metrics", Some("vm-1"), now).unwrap();
+        }
+        assert!(limiter.check_at("get_vm_metrics", Some("vm-1"), now).is_err());
+        assert!(limiter.check_at("get_vm_metrics", Some("vm-2"), now).is_ok());
+    }
+
+    #[test]
+    fn test_detection_allows_one_call_per_ten_seconds() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        assert!(limiter.check_at("detect_qemu", None, start).is_ok());
+        assert_eq!(retry_after_ms(limiter.check_at("detect_qemu", None, start)), 10_000);
+        assert!(limiter.check_at("detect_qemu", None, start + Duration::from_secs(10)).is_ok());
+    }
+
+    #[test]
+    fn test_unlisted_commands_are_exempt() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert!(limiter.check_at("start_vm", Some("vm-1"), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_settings_override_defaults() {
+        let settings = vec![
+            ("rate_limit.get_vm_metrics".to_string(), "10/2".to_string()),
+            ("rate_limit.detect_qemu".to_string(), "garbage".to_string()),
+            ("log_level".to_string(), "debug".to_string()),
+        ];
+        let limiter = RateLimiter::new().with_settings(&settings);
+        let limits = limiter.limits();
+        assert_eq!(limits["get_vm_metrics"], RateLimit::new(10, Duration::from_secs(2)));
+        assert_eq!(limits["detect_qemu"], RateLimit::new(1, Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_parse_rate_limit() {
+        assert_eq!(RateLimit::parse("4/1"), Some(RateLimit::new(4, Duration::from_secs(1))));
+        assert_eq!(RateLimit::parse("1/0.5"), Some(RateLimit::new(1, Duration::from_millis(500))));
+        let limit = RateLimit::new(3, Duration::from_secs(10));
+        assert_eq!(RateLimit::parse(&limit.to_setting()), Some(limit));
+        for value in ["", "4", "0/1", "4/0", "4/-1", "a/b"] {
+            assert_eq!(RateLimit::parse(value), None, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Startup reconciliation
+//!
+//! After a crash or an unclean quit the database and the runtime dir disagree
+//! with reality: VMs still marked running, sockets and secrets left by QEMU
+//! processes that are gone, disk images no VM points at. `reconcile` runs once
+//! at launch, before any command is served, fixes what it safely can and
+//! reports every correction. Running it again changes nothing.
+//!
+//! Overlays of ephemeral VMs that are not running are deleted like any other
+//! leftover of a dead QEMU.
+//!
+//! QEMU pids are not persisted, so a VM whose QEMU outlived the previous
+//! session cannot be adopted yet; it is reported and left alone.
+
+use crate::config::{ConfigStore, STORAGE_DIR_KEY};
+use crate::paths::{ephemeral_overlay, AppPaths};
+use crate::Result;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Stored statuses that claim a QEMU process exists
+const LIVE_STATUSES: [&str; 4] = ["running", "paused", "starting", "stopping"];
+
+/// Something reconciliation found at launch
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Correction {
+    /// The VM was marked `previous` but had no QEMU process; it is now `stopped`
+    #[serde(rename_all = "camelCase")]
+    StatusReset { vm_id: String, vm_name: String, previous: String },
+    /// A socket, secret, viewer file or ephemeral overlay left by a QEMU process that is gone
+    StaleFileRemoved { path: String },
+    /// The VM's QEMU still answers on its QMP socket, but was started by an earlier session
+    #[serde(rename_all = "camelCase")]
+    ProcessNotAdopted { vm_id: String },
+    /// A disk image no VM refers to; reported, never deleted
+    OrphanedDisk { path: String },
+    /// The app quit while the VM's disk was being created; the VM is now
+    /// `error` since its disk may be incomplete
+    #[serde(rename_all = "camelCase")]
+    ProvisioningInterrupted { vm_id: String, vm_name: String },
+}
+
+/// Bring stored VM statuses and the runtime dir in line with the QEMU
+/// processes that actually exist, and list disk images nothing uses
+pub fn reconcile(store: &ConfigStore, paths: &AppPaths) -> Result<Vec<Correction>> {
+    let mut vms = store.list_vms()?;
+    vms.sort_by(|a, b| a.name.cmp(&b.name));
+    let live: BTreeSet<String> = vms
+        .iter()
+        .filter(|vm| qmp_answers(&paths.qmp_socket(&vm.id)))
+        .map(|vm| vm.id.clone())
+        .collect();
+
+    let mut corrections = Vec::new();
+    for vm in vms.iter() {
+        if live.contains(&vm.id) {
+            tracing::warn!(vm_id = %vm.id, "QEMU from an earlier session is still running");
+            corrections.push(Correction::ProcessNotAdopted { vm_id: vm.id.clone() });
+        } else if LIVE_STATUSES.contains(&vm.status.as_str()) {
+            let mut record = vm.clone();
+            record.status = "stopped".to_string();
+            store.update_vm(&record)?;
+            tracing::info!(vm_id = %vm.id, previous = %vm.status, "VM without a QEMU process marked stopped");
+            corrections.push(Correction::StatusReset {
+                vm_id: vm.id.clone(),
+                vm_name: vm.name.clone(),
+                previous: vm.status.clone(),
+            });
+        } else if vm.status == "creating" {
+            store.transition_status(&vm.id, "creating", "error")?;
+            tracing::warn!(vm_id = %vm.id, "VM disk creation was interrupted");
+            corrections.push(Correction::ProvisioningInterrupted { vm_id: vm.id.clone(), vm_name: vm.name.clone() });
+        }
+    }
+
+    for path in stale_runtime_files(&paths.runtime_dir, &live) {
+        match std::fs::remove_file(&path) {
+            Ok(()) => corrections.push(Correction::StaleFileRemoved { path: path.display().to_string() }),
+            Err(err) => tracing::warn!(path = %path.display(), error = %err, "failed to remove stale runtime file"),
+        }
+    }
+
+    let disks_dir = store
+        .get_setting(STORAGE_DIR_KEY)?
+        .map(PathBuf::from)
+        .unwrap_or_else(|| paths.disks_dir());
+    for vm in vms.iter().filter(|vm| !live.contains(&vm.id)) {
+        let overlay = ephemeral_overlay(&disks_dir, &vm.id);
+        if !overlay.exists() {
+            continue;
+        }
+        match std::fs::remove_file(&overlay) {
+            Ok(()) => corrections.push(Correction::StaleFileRemoved { path: overlay.display().to_string() }),
+            Err(err) => tracing::warn!(path = %overlay.display(), error = %err, "failed to remove ephemeral overlay"),
+        }
+    }
+
+    let mut referenced: BTreeSet<PathBuf> = store.referenced_disk_paths()?.into_iter().map(PathBuf::from).collect();
+    for vm in vms.iter() {
+        referenced.insert(ephemeral_overlay(&disks_dir, &vm.id));
+        if store.disk_location(&vm.id)?.is_none() {
+            referenced.insert(disks_dir.join(format!("{}.qcow2", vm.id)));
+        }
+    }
+    for path in qcow2_files(&disks_dir) {
+        if !referenced.contains(&path) {
+            tracing::warn!(path = %path.display(), "disk image is not used by any VM");
+            corrections.push(Correction::OrphanedDisk { path: path.display().to_string() });
+        }
+    }
+    Ok(corrections)
+}
+
+/// VM a runtime file was written for: its QMP and monitor sockets, disk
+/// secret, SPICE password and external viewer files
+fn runtime_file_owner(name: &str) -> Option<&str> {
+    if let Some(rest) = name.strip_prefix("qmp-").or_else(|| name.strip_prefix("monitor-")) {
+        return rest.strip_suffix(".sock");
+    }
+    if let Some(rest) = name.strip_prefix("viewer-") {
+        // `viewer-<vm id>-<launch id>.vv`; the launch id has no dashes
+        return rest.strip_suffix(".vv")?.rsplit_once('-').map(|(vm_id, _)| vm_id);
+    }
+    name.strip_prefix("secret-").or_else(|| name.strip_prefix("spice-"))
+}
+
+/// Runtime files of VMs whose QEMU is not running, sorted
+fn stale_runtime_files(runtime_dir: &Path, live: &BTreeSet<String>) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(runtime_dir) else {
+        return Vec::new();
+    };
+    let mut stale: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let owner = name.to_str().and_then(runtime_file_owner);
+            owner.is_some_and(|vm_id| !live.contains(vm_id))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    stale.sort();
+    stale
+}
+
+fn qcow2_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "qcow2"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Whether a QEMU is listening on `socket`; a stale socket file refuses connections
+#[cfg(unix)]
+fn qmp_answers(socket: &Path) -> bool {
+    std::os::unix::net::UnixStream::connect(socket).is_ok()
+}
+
+#[cfg(not(unix))]
+fn qmp_answers(_socket: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::VMRecord;
+
+    fn record(id: &str, status: &str) -> VMRecord {
+        VMRecord {
+            id: id.to_string(),
+            name: format!("VM {}", id),
+            status: status.to_string(),
+            memory_mb: 1024,
+            cpu_cores: 1,
+            disk_size_gb: 8,
+            restart_policy: "never".to_string(),
+            ..VMRecord::default()
+        }
+    }
+
+    #[test]
+    fn test_runtime_file_owner() {
+        assert_eq!(runtime_file_owner("qmp-vm-1.sock"), Some("vm-1"));
+        assert_eq!(runtime_file_owner("monitor-vm-1.sock"), Some("vm-1"));
+        assert_eq!(runtime_file_owner("secret-vm-1"), Some("vm-1"));
+        assert_eq!(runtime_file_owner("spice-vm-1"), Some("vm-1"));
+        assert_eq!(runtime_file_owner("viewer-vm-1-0f3a9c.vv"), Some("vm-1"));
+        assert_eq!(runtime_file_owner("openutm.sock"), None);
+        assert_eq!(runtime_file_owner("qmp-vm-1.log"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reconcile_fixes_inconsistent_fixtures_once() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let paths = AppPaths::legacy(temp.path(), temp.path().join("run"));
+        paths.ensure_dirs().unwrap();
+        let store = ConfigStore::new(paths.db_path()).unwrap();
+        for (id, status) in [("crashed", "running"), ("paused", "paused"), ("idle", "stopped"), ("live", "running")] {
+            store.create_vm(&record(id, status)).unwrap();
+        }
+        store.set_disk_location("idle", "/elsewhere/idle.qcow2").unwrap();
+
+        // The crashed VM's socket is a plain file nothing listens on
+        let run = &paths.runtime_dir;
+        for name in ["qmp-crashed.sock", "secret-crashed", "viewer-gone-0f3a9c.vv", "openutm.lock"] {
+            std::fs::write(run.join(name), b"").unwrap();
+        }
+        let _listener = std::os::unix::net::UnixListener::bind(paths.qmp_socket("live")).unwrap();
+        std::fs::write(run.join("monitor-live.sock"), b"").unwrap();
+        let disk_files = [
+            "crashed.qcow2",
+            "stray.qcow2",
+            "idle.qcow2",
+            "notes.txt",
+            "ephemeral-crashed.qcow2",
+            "ephemeral-live.qcow2",
+        ];
+        for name in disk_files {
+            std::fs::write(paths.disks_dir().join(name), b"").unwrap();
+        }
+
+        let corrections = reconcile(&store, &paths).unwrap();
+        let file = |dir: &Path, name: &str| dir.join(name).display().to_string();
+        assert_eq!(
+            corrections,
+            vec![
+                Correction::StatusReset {
+                    vm_id: "crashed".to_string(),
+                    vm_name: "VM crashed".to_string(),
+                    previous: "running".to_string(),
+                },
+                Correction::ProcessNotAdopted { vm_id: "live".to_string() },
+                Correction::StatusReset {
+                    vm_id: "paused".to_string(),
+                    vm_name: "VM paused".to_string(),
+                    previous: "paused".to_string(),
+                },
+                Correction::StaleFileRemoved { path: file(run, "qmp-crashed.sock") },
+                Correction::StaleFileRemoved { path: file(run, "secret-crashed") },
+                Correction::StaleFileRemoved { path: file(run, "viewer-gone-0f3a9c.vv") },
+                Correction::StaleFileRemoved { path: file(&paths.disks_dir(), "ephemeral-crashed.qcow2") },
+                Correction::OrphanedDisk { path: file(&paths.disks_dir(), "idle.qcow2") },
+                Correction::OrphanedDisk { path: file(&paths.disks_dir(), "stray.qcow2") },
+            ]
+        );
+        let status = |id: &str| store.get_vm(id).unwrap().unwrap().status;
+        assert_eq!(status("crashed"), "stopped");
+        assert_eq!(status("paused"), "stopped");
+        assert_eq!(status("live"), "running");
+        assert!(run.join("openutm.lock").exists());
+        assert!(run.join("monitor-live.sock").exists());
+        assert!(paths.disks_dir().join("stray.qcow2").exists());
+        assert!(paths.disks_dir().join("ephemeral-live.qcow2").exists());
+
+        // Only what cannot be fixed is reported again
+        let again = reconcile(&store, &paths).unwrap();
+        assert!(again.iter().all(|correction| {
+            matches!(correction, Correction::ProcessNotAdopted { .. } | Correction::OrphanedDisk { .. })
+        }));
+        assert_eq!(again.len(), 3);
+        assert_eq!(status("idle"), "stopped");
+    }
+
+    #[test]
+    fn test_reconcile_marks_interrupted_provisioning_as_error() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let paths = AppPaths::legacy(temp.path(), temp.path().join("run"));
+        paths.ensure_dirs().unwrap();
+        let store = ConfigStore::in_memory().unwrap();
+        store.create_vm(&record("new", "creating")).unwrap();
+
+        let corrections = reconcile(&store, &paths).unwrap();
+        assert_eq!(
+            corrections,
+            vec![Correction::ProvisioningInterrupted { vm_id: "new".to_string(), vm_name: "VM new".to_string() }]
+        );
+        assert_eq!(store.get_vm("new").unwrap().unwrap().status, "error");
+        assert!(reconcile(&store, &paths).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_correction_serialization() {
+        let correction = Correction::StatusReset {
+            vm_id: "vm-1".to_string(),
+            vm_name: "Fedora".to_string(),
+            previous: "running".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&correction).unwrap(),
+            serde_json::json!({ "kind": "statusReset", "vmId": "vm-1", "vmName": "Fedora", "previous": "running" })
+        );
+    }
+}
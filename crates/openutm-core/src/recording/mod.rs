@@ -0,0 +1,260 @@
+//! Screen recording of a VM display
+//!
+//! Frames are QMP `screendump`s written to a per-recording frame directory at
+//! a fixed rate. Stopping encodes them into an mp4 with ffmpeg when it is
+//! installed, and otherwise zips the frames so the capture is not lost.
+
+use crate::error::Error;
+use crate::qemu::detector;
+use crate::Result;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+pub const DEFAULT_FPS: u32 = 5;
+pub const MAX_FPS: u32 = 30;
+
+const FFMPEG_BINARIES: [&str; 1] = ["ffmpeg"];
+
+/// A capture in progress; `finish` stops it and produces the output file
+pub struct Recording {
+    pub fps: u32,
+    pub frame_dir: PathBuf,
+    started: Instant,
+    stop: oneshot::Sender<()>,
+    capture: tokio::task::JoinHandle<u32>,
+}
+
+/// What a finished recording produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorded {
+    /// The mp4, or a zip of the frames when ffmpeg was unavailable or failed
+    pub path: PathBuf,
+    pub duration: Duration,
+    pub frames: u32,
+    pub warning: Option<String>,
+}
+
+/// Locate `ffmpeg` on the PATH and the usual install locations
+pub fn find_ffmpeg() -> Option<PathBuf> {
+    detector::find_tool(&FFMPEG_BINARIES)
+}
+
+/// Name of the `index`th captured frame; numbering has no gaps, as ffmpeg's image2 input requires
+pub fn frame_path(frame_dir: &Path, index: u32) -> PathBuf {
+    frame_dir.join(format!("frame-{:06}.ppm", index))
+}
+
+/// When tick `tick` is due, measured from the start of the recording
+fn tick_offset(tick: u64, fps: u32) -> Duration {
+    Duration::from_millis(tick * 1000 / u64::from(fps.max(1)))
+}
+
+/// The first tick still in the future at `elapsed`. Ticks missed while a slow
+/// screendump ran are skipped rather than captured in a burst.
+fn next_tick(elapsed: Duration, fps: u32) -> u64 {
+    elapsed.as_millis() as u64 * u64::from(fps.max(1)) / 1000 + 1
+}
+
+/// Begin capturing into `frame_dir` by calling `screendump` with each frame's path `fps` times a second
+pub fn start<F, Fut>(frame_dir: PathBuf, fps: u32, screendump: F) -> Result<Recording>
+where
+    F: Fn(PathBuf) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    std::fs::create_dir_all(&frame_dir)?;
+    let (stop, stopped) = oneshot::channel();
+    let capture = tokio::spawn(capture_frames(frame_dir.clone(), fps, stopped, screendump));
+    Ok(Recording {
+        fps,
+        frame_dir,
+        started: Instant::now(),
+        stop,
+        capture,
+    })
+}
+
+async fn capture_frames<F, Fut>(frame_dir: PathBuf, fps: u32, mut stopped: oneshot::Receiver<()>, screendump: F) -> u32
+where
+    F: Fn(PathBuf) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let start = tokio::time::Instant::now();
+    let mut frames = 0;
+    let mut tick = 0;
+    loop {
+        tokio::select! {
+            _ = &mut stopped => break,
+            _ = tokio::time::sleep_until(start + tick_offset(tick, fps)) => {}
+        }
+        match screendump(frame_path(&frame_dir, frames)).await {
+            Ok(()) => frames += 1,
+            Err(err) => tracing::debug!(error = %err, "screendump failed; frame skipped"),
+        }
+        tick = next_tick(start.elapsed(), fps);
+    }
+    frames
+}
+
+/// Stop capturing and turn the frames into `<output_stem>.mp4`, or
+/// `<output_stem>.zip` when `ffmpeg` is `None` or fails. The frame directory is removed.
+pub async fn finish(recording: Recording, ffmpeg: Option<&Path>, output_stem: &Path) -> Result<Recorded> {
+    let _ = recording.stop.send(());
+    let frames = recording.capture.await.unwrap_or_default();
+    let duration = recording.started.elapsed();
+    if frames == 0 {
+        let _ = std::fs::remove_dir_all(&recording.frame_dir);
+        return Err(Error::QemuError("No frames were captured".to_string()));
+    }
+
+    let video = output_stem.with_extension("mp4");
+    let encoded = match ffmpeg {
+        Some(ffmpeg) => encode_video(ffmpeg, &recording.frame_dir, recording.fps, &video).await.map_err(Some),
+        None => Err(None),
+    };
+    let recorded = match encoded {
+        Ok(()) => Recorded { path: video, duration, frames, warning: None },
+        Err(failure) => {
+            let archive = output_stem.with_extension("zip");
+            // Reading and compressing every frame is blocking file I/O
+            let (frame_dir, dest) = (recording.frame_dir.clone(), archive.clone());
+            tokio::task::spawn_blocking(move || zip_frames(&frame_dir, &dest))
+                .await
+                .map_err(|err| Error::QemuError(format!("Zipping the frames failed: {}", err)))??;
+            let warning = match failure {
+                Some(err) => format!("ffmpeg failed ({}); the frames were saved as a zip instead", err),
+                None => "ffmpeg was not found; the frames were saved as a zip instead".to_string(),
+            };
+            tracing::warn!(path = %archive.display(), "{}", warning);
+            Recorded { path: archive, duration, frames, warning: Some(warning) }
+        }
+    };
+    let _ = std::fs::remove_dir_all(&recording.frame_dir);
+    Ok(recorded)
+}
+
+async fn encode_video(ffmpeg: &Path, frame_dir: &Path, fps: u32, dest: &Path) -> Result<()> {
+    let output = tokio::process::Command::new(ffmpeg)
+        .arg("-y")
+        .args(["-loglevel", "error", "-framerate", &fps.to_string(), "-i"])
+        .arg(frame_dir.join("frame-%06d.ppm"))
+        // yuv420p with even dimensions keeps the file playable in browsers and common players
+        .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+        .arg(dest)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(dest);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::QemuError(stderr.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Every frame in `frame_dir`, in capture order, stored in a zip at `dest`
+fn zip_frames(frame_dir: &Path, dest: &Path) -> Result<()> {
+    use zip::write::SimpleFileOptions;
+
+    let mut frames: Vec<PathBuf> = std::fs::read_dir(frame_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ppm"))
+        .collect();
+    frames.sort();
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(dest)?);
+    let options = SimpleFileOptions::default();
+    for path in frames {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+        zip.start_file(name, options).map_err(std::io::Error::from)?;
+        zip.write_all(&std::fs::read(&path)?)?;
+    }
+    zip.finish().map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_frame_schedule() {
+        assert_eq!(tick_offset(0, 5), Duration::ZERO);
+        assert_eq!(tick_offset(1, 5), Duration::from_millis(200));
+        assert_eq!(tick_offset(10, 5), Duration::from_secs(2));
+
+        // Right after tick 0 the next one is tick 1
+        assert_eq!(next_tick(Duration::from_millis(10), 5), 1);
+        // A screendump that took 650ms skips ticks 1-3
+        assert_eq!(next_tick(Duration::from_millis(650), 5), 4);
+        assert_eq!(frame_path(Path::new("/rec"), 12), PathBuf::from("/rec/frame-000012.ppm"));
+    }
+
+    #[tokio::test]
+    async fn test_capture_calls_screendump_on_schedule() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let frame_dir = temp_dir.path().join("frames");
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let recording = start(frame_dir.clone(), 20, move |path: PathBuf| {
+            let counter = counter.clone();
+            async move {
+                // Every other screendump fails, like a guest mid mode-switch
+                if counter.fetch_add(1, Ordering::SeqCst) % 2 == 1 {
+                    return Err(Error::QemuError("not ready".to_string()));
+                }
+                std::fs::write(path, b"P6 1 1 255 \x00\x00\x00")?;
+                Ok(())
+            }
+        })
+        .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let recorded = finish(recording, None, &temp_dir.path().join("out")).await.unwrap();
+        let calls = calls.load(Ordering::SeqCst);
+        // 20 fps for 400ms is 8 ticks; allow for a slow test machine
+        assert!((3..=10).contains(&calls), "{} screendumps", calls);
+        assert_eq!(recorded.frames, (calls + 1) / 2);
+        assert!(recorded.duration >= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_without_ffmpeg_frames_are_zipped() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let frame_dir = temp_dir.path().join("frames");
+        let recording = start(frame_dir.clone(), 50, |path: PathBuf| async move {
+            std::fs::write(path, b"P6 1 1 255 \x00\x00\x00")?;
+            Ok(())
+        })
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let recorded = finish(recording, None, &temp_dir.path().join("vm-1-install")).await.unwrap();
+        assert_eq!(recorded.path, temp_dir.path().join("vm-1-install.zip"));
+        assert!(recorded.warning.unwrap().contains("ffmpeg was not found"));
+        assert!(!frame_dir.exists());
+
+        let archive = zip::ZipArchive::new(std::fs::File::open(&recorded.path).unwrap()).unwrap();
+        assert_eq!(archive.len() as u32, recorded.frames);
+        assert_eq!(archive.name_for_index(0), Some("frame-000000.ppm"));
+    }
+
+    #[tokio::test]
+    async fn test_recording_without_frames_fails() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let frame_dir = temp_dir.path().join("frames");
+        let recording = start(frame_dir.clone(), 50, |_path: PathBuf| async move {
+            Err(Error::VmNotRunning("vm-1".to_string()))
+        })
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(finish(recording, None, &temp_dir.path().join("out")).await.is_err());
+        assert!(!frame_dir.exists());
+        assert!(!temp_dir.path().join("out.zip").exists());
+    }
+}
@@ -0,0 +1,700 @@
+//! VM operations shared by the Tauri commands and the control socket
+//!
+//! Each function takes the `AppState` directly so callers outside the
+//! webview, like `control`, run the same validation and state transitions.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use uuid::Uuid;
+
+use crate::app::{
+    affinity_warning, check_vm_config, ensure_unique_name, fetch_vm_or_err, force_stop_recording, host_limits,
+    launch_vm, luks_key_ref, map_record_to_vm, mark_disconnected, notify_vm_list_changed, remove_ephemeral_overlay,
+    remove_secret_file, run_transition, with_stable_mac,
+    AppState,
+};
+use crate::error::{CommandError, CommandResult, Error, ErrorCode};
+use crate::provisioning::DiskRequest;
+use crate::config::{ConfigStore, VMRecord};
+use crate::qemu::generate_stable_mac;
+use crate::qemu::qmp::QmpClient;
+use crate::{platform, VMConfig, VMStatus, VMWarning, VmChangeKind, VmMetrics, VM};
+
+/// Every VM in the config store
+pub fn list_vms(state: &AppState) -> CommandResult<Vec<VM>> {
+    let records = state.config_store.list_vms()?;
+    with_group_ids(&state.config_store, state.config_store.group_memberships()?, records)
+}
+
+/// Map a record to a VM, filling in the network adapters and groups stored beside it
+pub fn stored_vm(config_store: &ConfigStore, record: VMRecord) -> CommandResult<VM> {
+    let group_ids = config_store.vm_group_ids(&record.id)?;
+    with_networks(config_store, record, group_ids)
+}
+
+/// Map records to VMs, filling in the groups each one is filed under
+pub fn with_group_ids(
+    config_store: &ConfigStore,
+    mut memberships: HashMap<String, Vec<String>>,
+    records: Vec<VMRecord>,
+) -> CommandResult<Vec<VM>> {
+    records
+        .into_iter()
+        .map(|record| {
+            let group_ids = memberships.remove(&record.id).unwrap_or_default();
+            with_networks(config_store, record, group_ids)
+        })
+        .collect()
+}
+
+fn with_networks(config_store: &ConfigStore, record: VMRecord, group_ids: Vec<String>) -> CommandResult<VM> {
+    let networks = config_store.list_networks(&record.id)?;
+    let mut vm = map_record_to_vm(record);
+    vm.config.networks = networks;
+    vm.group_ids = group_ids;
+    Ok(vm)
+}
+
+/// Validate `config` and register the VM. Its disk is made in the background
+/// by `provisioning`; until then the VM is `creating` and cannot start.
+pub async fn create_vm(state: &AppState, config: VMConfig, passphrase: Option<String>) -> CommandResult<VM> {
+    let warnings = check_vm_config(&config, &host_limits(Some(&state.disk_manager)))?;
+    ensure_unique_name(&state.config_store, &config.name, None)?;
+    let passphrase = passphrase.filter(|_| config.encrypted);
+    if config.encrypted && passphrase.as_deref().map_or(true, str::is_empty) {
+        return Err(CommandError::validation("passphrase", "disk.passphraseRequired"));
+    }
+
+    let vm_id = Uuid::new_v4().to_string();
+    let encryption_key_ref = passphrase.as_ref().map(|_| luks_key_ref(&vm_id));
+    let disk_request = match &config.raw_device_path {
+        Some(path) => {
+            tracing::warn!(
+                vm_id = %vm_id,
+                device = %path,
+                "raw device passthrough bypasses qcow2 snapshots, encryption and corruption protection"
+            );
+            None
+        }
+        None => Some(DiskRequest {
+            vm_id: vm_id.clone(),
+            size_gb: config.disk_size_gb,
+            preallocation: config.preallocation.clone(),
+            key_ref: encryption_key_ref.clone(),
+            passphrase,
+        }),
+    };
+
+    let mac_address = Some(generate_stable_mac(&vm_id));
+    let record = VMRecord {
+        id: vm_id,
+        name: config.name.clone(),
+        status: if disk_request.is_some() { "creating" } else { "stopped" }.to_string(),
+        memory_mb: config.memory_mb,
+        cpu_cores: config.cpu_cores,
+        disk_size_gb: config.disk_size_gb,
+        os: config.os.to_string(),
+        install_media_path: config.install_media_path.clone(),
+        boot_order: config.boot_order.clone(),
+        network_type: config.network_type.clone(),
+        nested_virt: config.nested_virt,
+        restart_policy: config.restart_policy.clone(),
+        arch: config.arch.clone(),
+        cpu_affinity: config.cpu_affinity.clone(),
+        priority: config.priority,
+        preallocation: config.preallocation.clone(),
+        encryption_key_ref,
+        raw_device_path: config.raw_device_path.clone(),
+        notes: config.notes.clone(),
+        clipboard_sharing: config.clipboard_sharing,
+        vlan_id: config.vlan_id,
+        display_resolution: config.display_resolution.clone(),
+        graphics: config.graphics.clone(),
+        machine_type: config.machine_type.clone(),
+        display_mode: config.display_mode.clone(),
+        boot_menu: config.boot_menu,
+        mac_address,
+        performance: config.performance.clone(),
+        pointer_device: config.pointer_device.map(|pointer| pointer.as_str().to_string()),
+        rtc: config.rtc.map(|rtc| rtc.as_str().to_string()),
+        rng: config.rng,
+        ..VMRecord::default()
+    };
+
+    state.config_store.create_vm(&record)?;
+    let mut networks = Vec::with_capacity(config.networks.len());
+    for network in &config.networks {
+        let network = with_stable_mac(&record.id, network.clone());
+        if let Err(err) = state.config_store.add_network(&record.id, &network) {
+            let _ = state.config_store.delete_vm(&record.id);
+            return Err(err.into());
+        }
+        networks.push(network);
+    }
+    notify_vm_list_changed(state, &record.id, VmChangeKind::Created);
+    if let Some(request) = disk_request {
+        let (store, events) = (state.config_store.clone(), state.ui_events.clone());
+        state.provisioner.submit(&record.name, request, store, events);
+    }
+
+    let mut vm = map_record_to_vm(record);
+    vm.config.networks = networks;
+    vm.warnings.extend(warnings);
+    vm.warnings.extend(affinity_warning(&vm.config.cpu_affinity));
+    if vm.config.preallocation == "full" {
+        vm.warnings.push(VMWarning {
+            code: "full-preallocation".to_string(),
+            message: format!(
+                "Full preallocation reserves all {} GB of host disk space while the disk is created",
+                vm.config.disk_size_gb
+            ),
+        });
+    }
+    if vm.emulated {
+        vm.warnings.push(VMWarning {
+            code: "emulated-arch".to_string(),
+            message: format!(
+                "{} guests are emulated on this {} host and will run slowly",
+                vm.config.arch,
+                platform::host_arch()
+            ),
+        });
+    }
+    Ok(vm)
+}
+
+/// What a start request did
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StartOutcome {
+    Started,
+    /// Another start of the VM was requested moments ago and is handling it
+    AlreadyStarting,
+}
+
+/// Start a stopped VM; an explicit start resets the crash-restart budget.
+/// A repeat request within `START_DEBOUNCE`, e.g. from a double click, is not
+/// run again.
+pub async fn start_vm(state: &AppState, id: &str, passphrase: Option<&str>) -> CommandResult<StartOutcome> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+    if !state.qemu_controller.begin_start(id) {
+        tracing::info!(vm_id = %id, "ignoring duplicate start request");
+        return Ok(StartOutcome::AlreadyStarting);
+    }
+
+    state.restart_attempts.lock().await.remove(id);
+    match launch_vm(state, id, passphrase).await {
+        Ok(()) => Ok(StartOutcome::Started),
+        Err(err) => {
+            state.qemu_controller.end_start(id);
+            Err(err)
+        }
+    }
+}
+
+/// Shut down a running VM and release its display resources
+pub async fn stop_vm(state: &AppState, id: &str) -> CommandResult<()> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+
+    if !state.qemu_controller.is_running(id) {
+        return Err(Error::VmNotRunning(id.to_string()).into());
+    }
+
+    let stopped = async {
+        state.qemu_controller.stop_vm(id).await?;
+        remove_secret_file(state, id);
+        remove_ephemeral_overlay(state, id);
+        Ok(())
+    };
+    run_transition(state, id, VMStatus::Stopping, VMStatus::Stopped, VMStatus::Running, stopped).await?;
+    state.spice_passwords.lock().await.remove(id);
+    state.display_proxies.lock().await.remove(id);
+    force_stop_recording(state, id).await;
+
+    let mut sessions = state.display_sessions.lock().await;
+    if let Some(existing) = sessions.get_mut(id) {
+        mark_disconnected(existing, "VM stopped");
+        existing.recording = false;
+    }
+    Ok(())
+}
+
+/// VMs a batch starts or stops at once, so a large selection doesn't flood the disks
+pub const BATCH_CONCURRENCY: usize = 3;
+
+/// How one VM in a batch fared: `"ok"`, `"skipped"` or its error code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchOutcome {
+    Ok,
+    /// Not attempted because a VM it depends on failed
+    Skipped,
+    Failed(ErrorCode),
+}
+
+impl serde::Serialize for BatchOutcome {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Ok => serializer.serialize_str("ok"),
+            Self::Skipped => serializer.serialize_str("skipped"),
+            Self::Failed(code) => code.serialize(serializer),
+        }
+    }
+}
+
+/// Run `op` for each distinct id, at most `limit` at a time. A failure is
+/// recorded for its VM and the rest of the batch carries on.
+pub async fn run_batch<F, Fut>(ids: Vec<String>, limit: usize, op: F) -> BTreeMap<String, BatchOutcome>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = CommandResult<()>>,
+{
+    let permits = tokio::sync::Semaphore::new(limit.max(1));
+    let ids: BTreeSet<String> = ids.into_iter().collect();
+    let runs = ids.into_iter().map(|id| {
+        let (permits, op) = (&permits, &op);
+        async move {
+            let _permit = permits.acquire().await.expect("batch semaphore is never closed");
+            let outcome = match op(id.clone()).await {
+                Ok(()) => BatchOutcome::Ok,
+                Err(err) => BatchOutcome::Failed(err.code),
+            };
+            (id, outcome)
+        }
+    });
+    futures_util::future::join_all(runs).await.into_iter().collect()
+}
+
+/// Start each VM in `ids`; encrypted ones fail since a batch takes no passphrase
+pub async fn batch_start_vms(state: &AppState, ids: Vec<String>) -> BTreeMap<String, BatchOutcome> {
+    run_batch(ids, BATCH_CONCURRENCY, |id| async move {
+        start_vm(state, &id, None).await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Stop each VM in `ids`
+pub async fn batch_stop_vms(state: &AppState, ids: Vec<String>) -> BTreeMap<String, BatchOutcome> {
+    run_batch(ids, BATCH_CONCURRENCY, |id| async move { stop_vm(state, &id).await }).await
+}
+
+/// How long `stop_all_vms` gives a guest to power itself off before killing it
+pub const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Ask the guest to power off through ACPI and wait up to `SHUTDOWN_TIMEOUT`
+/// for QEMU to exit, then stop the VM, killing QEMU if it is still up
+async fn shut_down_vm(state: &AppState, id: &str) -> CommandResult<()> {
+    if let Some(socket) = state.qemu_controller.qmp_socket(id) {
+        match QmpClient::new(socket).execute("system_powerdown", None).await {
+            Ok(_) => {
+                if !state.qemu_controller.wait_for_exit(id, SHUTDOWN_TIMEOUT).await {
+                    tracing::warn!(vm_id = %id, "guest did not power off in time, killing QEMU");
+                }
+            }
+            Err(err) => tracing::warn!(vm_id = %id, error = %err, "could not request guest power-off"),
+        }
+    }
+    stop_vm(state, id).await
+}
+
+/// Shut down every running VM at once, as when the app quits or the host suspends.
+/// Returns the VMs that failed to stop, for the caller to force-kill or report.
+pub async fn stop_all_vms(state: &AppState) -> Vec<String> {
+    let running = state.qemu_controller.get_running_vms();
+    let limit = running.len();
+    let results = run_batch(running, limit, |id| async move { shut_down_vm(state, &id).await }).await;
+    results
+        .into_iter()
+        .filter_map(|(vm_id, outcome)| match outcome {
+            BatchOutcome::Failed(code) => {
+                tracing::warn!(vm_id = %vm_id, code = ?code, "failed to stop VM");
+                Some(vm_id)
+            }
+            BatchOutcome::Ok | BatchOutcome::Skipped => None,
+        })
+        .collect()
+}
+
+/// Order `vms` into waves where each VM comes after everything it depends on.
+/// Dependencies on VMs outside `vms` are ignored. On a cycle, returns the VMs
+/// that could not be ordered.
+pub fn dependency_waves(
+    vms: &[String],
+    dependencies: &Dependencies,
+) -> Result<Vec<Vec<String>>, Vec<String>> {
+    let members: BTreeSet<&String> = vms.iter().collect();
+    let mut waiting: BTreeMap<&String, BTreeSet<&String>> = members
+        .iter()
+        .map(|vm| {
+            let within: BTreeSet<&String> = dependencies
+                .get(*vm)
+                .into_iter()
+                .flatten()
+                .filter(|dependency| members.contains(dependency))
+                .collect();
+            (*vm, within)
+        })
+        .collect();
+
+    let mut waves = Vec::new();
+    while !waiting.is_empty() {
+        let ready: Vec<&String> = waiting
+            .iter()
+            .filter(|(_, pending)| pending.is_empty())
+            .map(|(vm, _)| *vm)
+            .collect();
+        if ready.is_empty() {
+            return Err(waiting.into_keys().cloned().collect());
+        }
+        for vm in &ready {
+            waiting.remove(*vm);
+        }
+        for pending in waiting.values_mut() {
+            for vm in &ready {
+                pending.remove(*vm);
+            }
+        }
+        waves.push(ready.into_iter().cloned().collect());
+    }
+    Ok(waves)
+}
+
+/// VM id to the VMs it depends on
+type Dependencies = HashMap<String, Vec<String>>;
+
+/// Members of a group in dependency order, with what each depends on within it
+fn group_plan(state: &AppState, group_id: &str) -> CommandResult<(Vec<Vec<String>>, Dependencies)> {
+    let group = state
+        .config_store
+        .list_groups()?
+        .into_iter()
+        .find(|group| group.id == group_id)
+        .ok_or_else(|| CommandError::new(ErrorCode::NotFound, "group.notFound"))?;
+    let dependencies = state.config_store.all_vm_dependencies()?;
+    let waves = dependency_waves(&group.vm_ids, &dependencies).map_err(|cycle| {
+        CommandError::validation("group_id", "vm.dependencies.cycle").with_param("vms", cycle.join(", "))
+    })?;
+    Ok((waves, dependencies))
+}
+
+/// Start a group wave by wave. A VM whose dependency did not start is skipped,
+/// along with everything after it; other branches carry on.
+pub async fn start_group(state: &AppState, group_id: &str) -> CommandResult<BTreeMap<String, BatchOutcome>> {
+    let (waves, dependencies) = group_plan(state, group_id)?;
+    let mut results = BTreeMap::new();
+    for wave in waves {
+        let mut to_start = Vec::new();
+        for vm_id in wave {
+            let blocked = dependencies
+                .get(&vm_id)
+                .into_iter()
+                .flatten()
+                .filter_map(|dependency| results.get(dependency))
+                .any(|outcome| *outcome != BatchOutcome::Ok);
+            if blocked {
+                results.insert(vm_id, BatchOutcome::Skipped);
+            } else if state.qemu_controller.is_running(&vm_id) {
+                results.insert(vm_id, BatchOutcome::Ok);
+            } else {
+                to_start.push(vm_id);
+            }
+        }
+        results.extend(batch_start_vms(state, to_start).await);
+    }
+    Ok(results)
+}
+
+/// Stop a group in reverse dependency order; members already stopped count as stopped
+pub async fn stop_group(state: &AppState, group_id: &str) -> CommandResult<BTreeMap<String, BatchOutcome>> {
+    let (waves, _) = group_plan(state, group_id)?;
+    let mut results = BTreeMap::new();
+    for wave in waves.into_iter().rev() {
+        let (running, stopped): (Vec<String>, Vec<String>) =
+            wave.into_iter().partition(|vm_id| state.qemu_controller.is_running(vm_id));
+        results.extend(stopped.into_iter().map(|vm_id| (vm_id, BatchOutcome::Ok)));
+        results.extend(batch_stop_vms(state, running).await);
+    }
+    Ok(results)
+}
+
+/// Process-level metrics for a VM, including its effective CPU affinity
+pub fn vm_metrics(state: &AppState, id: &str) -> CommandResult<VmMetrics> {
+    if id.trim().is_empty() {
+        return Err(CommandError::validation("id", "vm.id.empty"));
+    }
+    state.rate_limiter.check("get_vm_metrics", Some(id))?;
+
+    let record = fetch_vm_or_err(&state.config_store, id)?;
+    let pid = state.qemu_controller.pid(id);
+    let cpu_affinity = pid.and_then(platform::process_affinity);
+    let usage = pid.and_then(|pid| state.process_sampler.sample(pid));
+
+    Ok(VmMetrics {
+        vm_id: id.to_string(),
+        running: pid.is_some(),
+        pid,
+        cpu_percent: usage.map(|usage| usage.cpu_percent),
+        memory_rss_bytes: usage.map(|usage| usage.memory_rss_bytes),
+        cpu_affinity_mask: cpu_affinity.as_deref().map(platform::affinity_mask),
+        cpu_affinity,
+        priority: record.priority,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::UiEvent;
+    use crate::paths::AppPaths;
+    use crate::qemu::QemuController;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    fn test_state(temp_dir: &TempDir) -> AppState {
+        let paths = AppPaths::legacy(temp_dir.path(), temp_dir.path().join("run"));
+        paths.ensure_dirs().unwrap();
+        let config_store = crate::config::ConfigStore::new(paths.db_path()).unwrap();
+        AppState::new(config_store, paths, QemuController::new("sh".to_string()))
+    }
+
+    fn test_record(id: &str, status: &str) -> VMRecord {
+        VMRecord {
+            id: id.to_string(),
+            name: id.to_string(),
+            status: status.to_string(),
+            memory_mb: 1024,
+            cpu_cores: 1,
+            disk_size_gb: 8,
+            restart_policy: "never".to_string(),
+            ..VMRecord::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_runs_at_most_limit_at_once() {
+        let (active, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let ids = (0..8).map(|n| format!("vm-{}", n)).collect();
+
+        let results = run_batch(ids, 3, |_| async {
+            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            active.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .await;
+
+        assert_eq!(results.len(), 8);
+        assert!(results.values().all(|outcome| *outcome == BatchOutcome::Ok));
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_batch_stop_reports_each_vm() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+        for (id, status) in [("vm-a", "running"), ("vm-b", "running"), ("vm-c", "stopped")] {
+            state.config_store.create_vm(&test_record(id, status)).unwrap();
+        }
+        for id in ["vm-a", "vm-b"] {
+            let sleep = vec!["-c".to_string(), "sleep 30".to_string()];
+            state.qemu_controller.start_vm(id, sleep, None).await.unwrap();
+        }
+        let mut events = state.ui_events.subscribe();
+
+        let ids = ["vm-a", "vm-b", "vm-c", "vm-b", ""].map(String::from).to_vec();
+        let results = batch_stop_vms(&state, ids).await;
+        assert_eq!(
+            serde_json::to_value(&results).unwrap(),
+            serde_json::json!({
+                "": "validationFailed",
+                "vm-a": "ok",
+                "vm-b": "ok",
+                "vm-c": "vmNotRunning",
+            })
+        );
+        assert!(state.qemu_controller.get_running_vms().is_empty());
+
+        let stopped = std::iter::from_fn(|| events.try_recv().ok())
+            .filter(|event| matches!(event, UiEvent::StatusChanged(change) if change.new_status == "stopped"))
+            .count();
+        assert_eq!(stopped, 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_duplicate_start_runs_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+        state.config_store.create_vm(&test_record("vm-a", "stopped")).unwrap();
+
+        // The VM has no disk, so the start that runs fails; the duplicate never gets that far
+        let (first, second) = tokio::join!(start_vm(&state, "vm-a", None), start_vm(&state, "vm-a", None));
+        assert!(first.is_err());
+        assert_eq!(second.unwrap(), StartOutcome::AlreadyStarting);
+        assert_eq!(
+            serde_json::to_value(StartOutcome::AlreadyStarting).unwrap(),
+            serde_json::json!("alreadyStarting")
+        );
+
+        // A failed start doesn't hold back a retry
+        assert!(start_vm(&state, "vm-a", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stop_all_reports_failures() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+        for id in ["vm-a", "vm-b", "vm-c"] {
+            state.config_store.create_vm(&test_record(id, "running")).unwrap();
+        }
+        // Running, but with no record its status cannot be updated, so the stop fails
+        for id in ["vm-a", "vm-b", "vm-c", "ghost"] {
+            let sleep = vec!["-c".to_string(), "sleep 30".to_string()];
+            state.qemu_controller.start_vm(id, sleep, None).await.unwrap();
+        }
+
+        assert_eq!(stop_all_vms(&state).await, vec!["ghost"]);
+        assert_eq!(state.qemu_controller.get_running_vms(), vec!["ghost"]);
+        for id in ["vm-a", "vm-b", "vm-c"] {
+            assert_eq!(state.config_store.get_vm(id).unwrap().unwrap().status, "stopped");
+        }
+
+        state.qemu_controller.stop_vm("ghost").await.unwrap();
+        assert!(stop_all_vms(&state).await.is_empty());
+    }
+
+    #[test]
+    fn test_dependency_waves_order_within_group() {
+        let vms = ["app", "db", "cache", "web"].map(String::from).to_vec();
+        let dependencies = HashMap::from([
+            ("app".to_string(), vec!["db".to_string(), "cache".to_string()]),
+            ("web".to_string(), vec!["app".to_string(), "outside".to_string()]),
+        ]);
+
+        let waves = dependency_waves(&vms, &dependencies).unwrap();
+        assert_eq!(waves, vec![vec!["cache", "db"], vec!["app"], vec!["web"]]);
+
+        let looped = HashMap::from([
+            ("db".to_string(), vec!["web".to_string()]),
+            ("web".to_string(), vec!["app".to_string()]),
+            ("app".to_string(), vec!["db".to_string()]),
+        ]);
+        assert_eq!(dependency_waves(&vms, &looped).unwrap_err(), vec!["app", "db", "web"]);
+    }
+
+    #[tokio::test]
+    async fn test_start_group_skips_dependents_of_failures() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+        for (id, status) in [("db", "stopped"), ("app", "stopped"), ("tools", "running")] {
+            state.config_store.create_vm(&test_record(id, status)).unwrap();
+        }
+        let sleep = vec!["-c".to_string(), "sleep 30".to_string()];
+        state.qemu_controller.start_vm("tools", sleep, None).await.unwrap();
+        state.config_store.set_vm_dependencies("app", &["db".to_string()]).unwrap();
+        let group = state.config_store.create_group("stack", None).unwrap();
+        for id in ["db", "app", "tools"] {
+            state.config_store.add_vm_to_group(id, &group).unwrap();
+        }
+
+        // "db" has no disk, so it cannot start and "app" is never tried
+        let results = start_group(&state, &group).await.unwrap();
+        assert!(matches!(results["db"], BatchOutcome::Failed(_)));
+        assert_eq!(results["app"], BatchOutcome::Skipped);
+        assert_eq!(results["tools"], BatchOutcome::Ok);
+
+        let results = stop_group(&state, &group).await.unwrap();
+        assert!(results.values().all(|outcome| *outcome == BatchOutcome::Ok));
+        assert!(state.qemu_controller.get_running_vms().is_empty());
+    }
+
+    #[test]
+    fn test_deleting_group_keeps_its_vms() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+        state.config_store.create_vm(&test_record("vm-a", "stopped")).unwrap();
+        let group = state.config_store.create_group("lab", None).unwrap();
+        state.config_store.add_vm_to_group("vm-a", &group).unwrap();
+        assert_eq!(list_vms(&state).unwrap()[0].group_ids, vec![group.clone()]);
+        let record = state.config_store.get_vm("vm-a").unwrap().unwrap();
+        assert_eq!(stored_vm(&state.config_store, record).unwrap().group_ids, vec![group.clone()]);
+
+        state.config_store.delete_group(&group).unwrap();
+        let vms = list_vms(&state).unwrap();
+        assert_eq!(vms.len(), 1);
+        assert!(vms[0].group_ids.is_empty());
+    }
+
+    /// Holds every disk until `release` gets a permit
+    struct HeldBackend {
+        release: tokio::sync::Semaphore,
+    }
+
+    impl crate::provisioning::DiskBackend for HeldBackend {
+        fn create_disk<'a>(&'a self, request: &'a DiskRequest) -> crate::provisioning::DiskFuture<'a, String> {
+            Box::pin(async move {
+                self.release.acquire().await.unwrap().forget();
+                Ok(format!("/disks/{}.qcow2", request.vm_id))
+            })
+        }
+
+        fn delete_disk<'a>(&'a self, _vm_id: &'a str) -> crate::provisioning::DiskFuture<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn written_bytes(&self, _vm_id: &str) -> Option<u64> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_stored_vm_includes_network_adapters() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir);
+        state.config_store.create_vm(&test_record("vm-a", "stopped")).unwrap();
+        let adapter = with_stable_mac(
+            "vm-a",
+            crate::qemu::NetworkConfig {
+                id: "lan".to_string(),
+                kind: "bridge".to_string(),
+                mac: None,
+                port_forwards: Vec::new(),
+            },
+        );
+        state.config_store.add_network("vm-a", &adapter).unwrap();
+
+        let record = state.config_store.get_vm("vm-a").unwrap().unwrap();
+        assert_eq!(stored_vm(&state.config_store, record).unwrap().config.networks, vec![adapter.clone()]);
+        assert_eq!(list_vms(&state).unwrap()[0].config.networks, vec![adapter]);
+    }
+
+    #[tokio::test]
+    async fn test_new_vm_cannot_start_until_provisioned() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut state = test_state(&temp_dir);
+        let backend = std::sync::Arc::new(HeldBackend { release: tokio::sync::Semaphore::new(0) });
+        state.provisioner = crate::provisioning::Provisioner::new(backend.clone(), 1);
+        let config = map_record_to_vm(test_record("template", "stopped")).config;
+
+        let vm = create_vm(&state, config, None).await.unwrap();
+        assert_eq!(vm.status, VMStatus::Creating);
+        assert_eq!(list_vms(&state).unwrap().len(), 1);
+        let err = start_vm(&state, &vm.id, None).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::VmProvisioning);
+        assert_eq!(state.provisioner.jobs().len(), 1);
+
+        backend.release.add_permits(1);
+        for _ in 0..100 {
+            if !state.provisioner.is_provisioning(&vm.id) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(state.provisioner.jobs().is_empty());
+        assert_eq!(state.config_store.get_vm(&vm.id).unwrap().unwrap().status, "stopped");
+    }
+}
@@ -0,0 +1,207 @@
+//! Single-instance enforcement
+//!
+//! The first instance takes an exclusive lock on `openutm.lock` in the data
+//! dir, writes its pid there and listens on a local socket. Later instances
+//! cannot take the lock, so they forward a request to that socket and exit.
+//! The OS releases the lock when its holder exits, so a crash leaves nothing
+//! stale behind.
+
+use crate::Result;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const LOCK_FILE: &str = "openutm.lock";
+const SOCKET_FILE: &str = "openutm.sock";
+/// Sent by a second instance to bring the running window forward
+pub const FOCUS_REQUEST: &str = "focus";
+
+pub enum Instance {
+    /// This process owns the lock until the guard is dropped
+    Primary(InstanceLock),
+    /// Another instance holds the lock; `pid` is unknown if it has not written it yet
+    Secondary { pid: Option<u32> },
+}
+
+/// Keeps the lock file open, and with it the lock
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+pub fn socket_path(runtime_dir: &Path) -> PathBuf {
+    runtime_dir.join(SOCKET_FILE)
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn acquire(data_dir: &Path) -> Result<Instance> {
+    let path = data_dir.join(LOCK_FILE);
+    let Some(mut file) = lock(&path)? else {
+        return Ok(Instance::Secondary { pid: read_pid(&path) });
+    };
+    // The file outlives its holder, so it may still name a previous instance
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    Ok(Instance::Primary(InstanceLock { _file: file }))
+}
+
+/// Open and exclusively lock `path`; `None` if another open file holds the lock
+#[cfg(unix)]
+fn lock(path: &Path) -> std::io::Result<Option<File>> {
+    use std::os::unix::io::AsRawFd;
+
+    // No truncate: the file may hold the running instance's pid
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(Some(file));
+    }
+    let err = std::io::Error::last_os_error();
+    if err.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(None)
+    } else {
+        Err(err)
+    }
+}
+
+/// Windows locks a file by opening it without write sharing; readers can still see the pid
+#[cfg(windows)]
+fn lock(path: &Path) -> std::io::Result<Option<File>> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_SHARE_READ: u32 = 0x1;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    let opened = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .share_mode(FILE_SHARE_READ)
+        .open(path);
+    match opened {
+        Ok(file) => Ok(Some(file)),
+        Err(err) if err.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lock(_path: &Path) -> std::io::Result<Option<File>> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "file locking is not supported on this platform"))
+}
+
+/// Accept one-line requests from later instances on a background thread
+#[cfg(unix)]
+pub fn listen(socket_path: &Path, on_request: impl Fn(&str) + Send + 'static) -> Result<()> {
+    use std::io::BufRead;
+    use std::os::unix::net::UnixListener;
+
+    // We hold the lock, so any existing socket belongs to a dead instance
+    let _ = std::fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut line = String::new();
+            if std::io::BufReader::new(stream).read_line(&mut line).is_ok() {
+                tracing::info!(request = %line.trim(), "request from another instance");
+                on_request(line.trim());
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen(_socket_path: &Path, _on_request: impl Fn(&str) + Send + 'static) -> Result<()> {
+    Err(crate::Error::PlatformError(
+        "Instance forwarding is not supported on this platform".to_string(),
+    ))
+}
+
+#[cfg(unix)]
+pub fn forward(socket_path: &Path, request: &str) -> Result<()> {
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket_path)?;
+    writeln!(stream, "{}", request)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn forward(_socket_path: &Path, _request: &str) -> Result<()> {
+    Err(crate::Error::PlatformError(
+        "Instance forwarding is not supported on this platform".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const OTHER_PID: u32 = 4_000_000;
+
+    #[test]
+    fn test_first_instance_is_primary() {
+        let temp_dir = TempDir::new().unwrap();
+        let instance = acquire(temp_dir.path()).unwrap();
+
+        assert!(matches!(instance, Instance::Primary(_)));
+        assert_eq!(read_pid(&temp_dir.path().join(LOCK_FILE)), Some(std::process::id()));
+    }
+
+    #[test]
+    fn test_held_lock_makes_secondary() {
+        let temp_dir = TempDir::new().unwrap();
+        let _primary = acquire(temp_dir.path()).unwrap();
+
+        let instance = acquire(temp_dir.path()).unwrap();
+        assert!(matches!(instance, Instance::Secondary { pid: Some(pid) } if pid == std::process::id()));
+    }
+
+    #[test]
+    fn test_dropped_lock_is_free_again() {
+        let temp_dir = TempDir::new().unwrap();
+        drop(acquire(temp_dir.path()).unwrap());
+
+        assert!(matches!(acquire(temp_dir.path()).unwrap(), Instance::Primary(_)));
+    }
+
+    #[test]
+    fn test_lock_file_left_by_crash_is_taken_over() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join(LOCK_FILE), format!("{}0", OTHER_PID)).unwrap();
+
+        let instance = acquire(temp_dir.path()).unwrap();
+
+        assert!(matches!(instance, Instance::Primary(_)));
+        assert_eq!(read_pid(&temp_dir.path().join(LOCK_FILE)), Some(std::process::id()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_forward_reaches_primary() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket = socket_path(temp_dir.path());
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        listen(&socket, move |request| {
+            let _ = sender.send(request.to_string());
+        })
+        .unwrap();
+        forward(&socket, FOCUS_REQUEST).unwrap();
+
+        let request = receiver.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(request, FOCUS_REQUEST);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_forward_without_primary_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(forward(&socket_path(temp_dir.path()), FOCUS_REQUEST).is_err());
+    }
+}
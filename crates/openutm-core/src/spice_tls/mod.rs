@@ -0,0 +1,182 @@
+//! Certificates for SPICE over TLS
+//!
+//! A self-signed CA is created once under the data dir and is what remote
+//! viewers are told to trust. Each VM gets its own QEMU `x509-dir` holding a
+//! copy of the CA certificate and a server certificate for the names the VM
+//! is reached by. The server certificate is reissued only when those names
+//! change, so viewers keep working across restarts.
+
+use crate::{storage, Result};
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair, KeyUsagePurpose};
+use std::path::{Path, PathBuf};
+
+/// File names QEMU expects inside `x509-dir`
+pub const CA_CERT_FILE: &str = "ca-cert.pem";
+const SERVER_CERT_FILE: &str = "server-cert.pem";
+const SERVER_KEY_FILE: &str = "server-key.pem";
+
+const CA_KEY_FILE: &str = "ca-key.pem";
+/// Names the current server certificate was issued for, one per line
+const SERVER_NAMES_FILE: &str = "server-names";
+
+const CA_COMMON_NAME: &str = "OpenUTM SPICE CA";
+
+/// Where a VM's TLS material lives
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmCertificates {
+    /// Passed to QEMU as `-spice x509-dir=`
+    pub x509_dir: PathBuf,
+    /// The CA a remote viewer must trust
+    pub ca_cert: PathBuf,
+}
+
+/// Make sure the CA under `root` and a server certificate for `names` in
+/// `root/<vm_id>` exist, creating only what is missing or stale
+pub fn ensure_vm_certificates(root: &Path, vm_id: &str, names: &[String]) -> Result<VmCertificates> {
+    std::fs::create_dir_all(root)?;
+    let (ca_params, ca_key) = ensure_ca(root)?;
+
+    let x509_dir = root.join(vm_id);
+    std::fs::create_dir_all(&x509_dir)?;
+    let ca_pem = std::fs::read_to_string(root.join(CA_CERT_FILE))?;
+    let ca_copy = x509_dir.join(CA_CERT_FILE);
+    // A new CA invalidates whatever server certificate the old one signed
+    let ca_changed = std::fs::read_to_string(&ca_copy).ok().as_deref() != Some(ca_pem.as_str());
+    if ca_changed {
+        std::fs::write(&ca_copy, &ca_pem)?;
+    }
+
+    let names_record = names.join("\n");
+    let current = std::fs::read_to_string(x509_dir.join(SERVER_NAMES_FILE)).ok();
+    let issued = x509_dir.join(SERVER_CERT_FILE).exists() && x509_dir.join(SERVER_KEY_FILE).exists();
+    if ca_changed || !issued || current.as_deref() != Some(names_record.as_str()) {
+        let issuer = ca_params.self_signed(&ca_key)?;
+        let server_key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(names.to_vec())?;
+        params.distinguished_name.push(DnType::CommonName, vm_id);
+        let server_cert = params.signed_by(&server_key, &issuer, &ca_key)?;
+
+        storage::write_secret_file(&x509_dir.join(SERVER_KEY_FILE), &server_key.serialize_pem())?;
+        std::fs::write(x509_dir.join(SERVER_CERT_FILE), server_cert.pem())?;
+        std::fs::write(x509_dir.join(SERVER_NAMES_FILE), &names_record)?;
+        tracing::info!(vm_id = %vm_id, names = %names.join(","), "issued SPICE server certificate");
+    }
+
+    Ok(VmCertificates { x509_dir, ca_cert: ca_copy })
+}
+
+/// Drop a deleted VM's server certificate; the shared CA stays
+pub fn remove_vm_certificates(root: &Path, vm_id: &str) -> Result<()> {
+    let dir = root.join(vm_id);
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Load the CA key, or create the CA on first use.
+///
+/// rcgen signs with the issuer's name and key, so the parameters are rebuilt
+/// rather than parsed back from the certificate on disk.
+fn ensure_ca(root: &Path) -> Result<(CertificateParams, KeyPair)> {
+    let params = ca_params()?;
+    let cert_path = root.join(CA_CERT_FILE);
+    let key_path = root.join(CA_KEY_FILE);
+    if cert_path.exists() && key_path.exists() {
+        let key = KeyPair::from_pem(&std::fs::read_to_string(&key_path)?)?;
+        return Ok((params, key));
+    }
+
+    let key = KeyPair::generate()?;
+    let cert = params.clone().self_signed(&key)?;
+    storage::write_secret_file(&key_path, &key.serialize_pem())?;
+    std::fs::write(&cert_path, cert.pem())?;
+    tracing::info!(path = %cert_path.display(), "created SPICE CA");
+    Ok((params, key))
+}
+
+fn ca_params() -> Result<CertificateParams> {
+    let mut params = CertificateParams::new(Vec::<String>::new())?;
+    params.distinguished_name.push(DnType::CommonName, CA_COMMON_NAME);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn read(path: PathBuf) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_certificates_are_generated_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().join("spice-tls");
+        let hosts = names(&["localhost", "192.168.1.20"]);
+
+        let first = ensure_vm_certificates(&root, "vm-1", &hosts).unwrap();
+        assert_eq!(first.x509_dir, root.join("vm-1"));
+        assert!(read(first.ca_cert.clone()).contains("BEGIN CERTIFICATE"));
+        assert!(read(first.x509_dir.join(SERVER_KEY_FILE)).contains("PRIVATE KEY"));
+        let ca = read(root.join(CA_CERT_FILE));
+        let server = read(first.x509_dir.join(SERVER_CERT_FILE));
+
+        let second = ensure_vm_certificates(&root, "vm-1", &hosts).unwrap();
+        assert_eq!(second, first);
+        assert_eq!(read(root.join(CA_CERT_FILE)), ca);
+        assert_eq!(read(second.x509_dir.join(SERVER_CERT_FILE)), server);
+        assert_eq!(read(second.ca_cert), ca);
+    }
+
+    #[test]
+    fn test_server_certificate_follows_names_but_ca_stays() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+
+        let first = ensure_vm_certificates(&root, "vm-1", &names(&["localhost"])).unwrap();
+        let ca = read(root.join(CA_CERT_FILE));
+        let server = read(first.x509_dir.join(SERVER_CERT_FILE));
+
+        ensure_vm_certificates(&root, "vm-1", &names(&["localhost", "10.0.0.5"])).unwrap();
+        assert_ne!(read(first.x509_dir.join(SERVER_CERT_FILE)), server);
+        assert_eq!(read(root.join(CA_CERT_FILE)), ca);
+
+        // A second VM shares the CA but has its own server certificate
+        let other = ensure_vm_certificates(&root, "vm-2", &names(&["localhost"])).unwrap();
+        assert_eq!(read(other.ca_cert), ca);
+        assert_ne!(other.x509_dir, first.x509_dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_keys_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let certs = ensure_vm_certificates(&root, "vm-1", &names(&["localhost"])).unwrap();
+        for key in [root.join(CA_KEY_FILE), certs.x509_dir.join(SERVER_KEY_FILE)] {
+            let mode = std::fs::metadata(key).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_remove_vm_certificates_keeps_ca() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let certs = ensure_vm_certificates(&root, "vm-1", &names(&["localhost"])).unwrap();
+
+        remove_vm_certificates(&root, "vm-1").unwrap();
+        assert!(!certs.x509_dir.exists());
+        assert!(root.join(CA_CERT_FILE).exists());
+        remove_vm_certificates(&root, "vm-1").unwrap();
+    }
+}
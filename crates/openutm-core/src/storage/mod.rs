@@ -0,0 +1,1061 @@
+use crate::Result;
+use crate::error::Error;
+use crate::qemu::option_value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::process::Command;
+
+/// `qemu-img create` preallocation modes accepted for qcow2 disks
+pub const PREALLOCATION_MODES: [&str; 4] = ["off", "metadata", "falloc", "full"];
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskInfo {
+    pub path: String,
+    pub virtual_size: u64,
+    pub actual_size: u64,
+    pub preallocation: String,
+}
+
+/// A qcow2 file found in the storage directory
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskEntry {
+    /// File stem; an orphaned disk has no matching VM
+    pub vm_id: String,
+    pub path: PathBuf,
+    /// Bytes allocated on the host, which is less than the file length for sparse images
+    pub actual_size_bytes: u64,
+    pub modified_at: std::time::SystemTime,
+}
+
+/// An internal snapshot inside a qcow2 image
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskSnapshot {
+    pub name: String,
+    /// Taken from a running VM with its RAM, so it resumes exactly where it was
+    pub includes_ram: bool,
+    /// Unix seconds
+    pub created_at: i64,
+}
+
+/// Snapshots listed in `qemu-img info` output, or in `query-block`'s `image`
+pub fn snapshots_from_info(info: &serde_json::Value) -> Vec<DiskSnapshot> {
+    info["snapshots"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|snapshot| {
+            Some(DiskSnapshot {
+                name: snapshot["name"].as_str()?.to_string(),
+                includes_ram: snapshot["vm-state-size"].as_u64().unwrap_or(0) > 0,
+                created_at: snapshot["date-sec"].as_i64().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// Snapshot names are short and shell-safe. All-digit names are refused
+/// because QEMU would read them as snapshot ids.
+pub fn is_valid_snapshot_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.chars().all(|c| c.is_ascii_digit())
+}
+
+/// LUKS key material for a qcow2: the QEMU secret object id and the passphrase
+pub struct DiskSecret<'a> {
+    pub key_ref: &'a str,
+    pub passphrase: &'a str,
+}
+
+/// How long a `qemu-img info` result is reused while the image is unchanged
+const INFO_CACHE_TTL: Duration = Duration::from_secs(2);
+
+pub struct DiskManager {
+    /// Changes only when the storage directory is migrated
+    storage_dir: RwLock<String>,
+    info_cache: Mutex<InfoCache>,
+}
+
+/// Recent `qemu-img info` output per image, so a burst of list refreshes
+/// spawns one subprocess per disk instead of one per call
+#[derive(Default)]
+struct InfoCache {
+    entries: HashMap<String, CachedInfo>,
+}
+
+struct CachedInfo {
+    modified: SystemTime,
+    fetched_at: Instant,
+    info: serde_json::Value,
+}
+
+impl InfoCache {
+    /// The cached info for `path`, unless it expired or the file changed since
+    fn get(&self, path: &str, modified: SystemTime, now: Instant) -> Option<serde_json::Value> {
+        self.entries
+            .get(path)
+            .filter(|cached| cached.modified == modified && now.duration_since(cached.fetched_at) < INFO_CACHE_TTL)
+            .map(|cached| cached.info.clone())
+    }
+
+    fn insert(&mut self, path: &str, modified: SystemTime, now: Instant, info: serde_json::Value) {
+        self.entries
+            .retain(|_, cached| now.duration_since(cached.fetched_at) < INFO_CACHE_TTL);
+        self.entries.insert(
+            path.to_string(),
+            CachedInfo {
+                modified,
+                fetched_at: now,
+                info,
+            },
+        );
+    }
+
+    fn invalidate(&mut self, path: &str) {
+        self.entries.remove(path);
+    }
+}
+
+fn create_args(disk_path: &str, size_gb: u32, preallocation: &str, secret: Option<(&str, &Path)>) -> Vec<String> {
+    let mut args = vec!["create".to_string()];
+    let mut options = format!("preallocation={}", preallocation);
+    if let Some((key_ref, secret_file)) = secret {
+        args.push("--object".to_string());
+        args.push(format!("secret,id={},file={}", key_ref, option_value(&secret_file.display().to_string())));
+        options.push_str(&format!(",encrypt.format=luks,encrypt.key-secret={}", key_ref));
+    }
+    args.extend([
+        "-f".to_string(),
+        "qcow2".to_string(),
+        "-o".to_string(),
+        options,
+        disk_path.to_string(),
+        format!("{}G", size_gb),
+    ]);
+    args
+}
+
+/// `qemu-img snapshot -c` (create) or `-a` (apply) for snapshot `name`
+fn snapshot_args(disk_path: &str, action: &str, name: &str) -> Vec<String> {
+    ["snapshot", "-f", "qcow2", action, name, disk_path].map(String::from).to_vec()
+}
+
+/// Overlay `<vm_id>-<name>.qcow2` next to the VM's current disk image
+pub fn external_snapshot_path(disk_path: &str, vm_id: &str, name: &str) -> String {
+    Path::new(disk_path)
+        .with_file_name(format!("{}-{}.qcow2", vm_id, name))
+        .display()
+        .to_string()
+}
+
+/// `qemu-img create` for a qcow2 overlay whose backing file is `backing_path`
+fn overlay_args(backing_path: &str, overlay_path: &str) -> Vec<String> {
+    ["create", "-f", "qcow2", "-b", backing_path, "-F", "qcow2", overlay_path]
+        .map(String::from)
+        .to_vec()
+}
+
+fn resize_args(disk_path: &str, size_gb: u32) -> Vec<String> {
+    vec![
+        "resize".to_string(),
+        "-f".to_string(),
+        "qcow2".to_string(),
+        disk_path.to_string(),
+        format!("{}G", size_gb),
+    ]
+}
+
+/// Write a passphrase where only QEMU (running as this user) can read it, so
+/// it never appears on a command line
+pub fn write_secret_file(path: &Path, passphrase: &str) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(passphrase.as_bytes())?;
+    Ok(())
+}
+
+/// Read size when hashing; large enough that multi-GB images hash at disk speed
+const CHECKSUM_CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+/// SHA-256 of a file, streamed so multi-GB images are never held in memory
+pub fn file_checksum(path: &Path) -> Result<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0u8; CHECKSUM_CHUNK_BYTES];
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..read]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Lowercase hex of a digest, as `sha256sum` prints it
+pub fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `<image>.sha256`, where a disk's saved checksum is kept
+pub fn checksum_path(disk_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.sha256", disk_path))
+}
+
+/// Copy `source` to `destination` (which may be on another filesystem) and
+/// check the copy by checksum. A failed or mismatched copy is removed.
+pub fn copy_verified(source: &Path, destination: &Path) -> Result<()> {
+    let copied = std::fs::copy(source, destination)
+        .map_err(Error::from)
+        .and_then(|_| Ok((file_checksum(source)?, file_checksum(destination)?)));
+    match copied {
+        Ok((expected, actual)) if expected == actual => Ok(()),
+        Ok(_) => {
+            let _ = std::fs::remove_file(destination);
+            Err(Error::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Copy of {} does not match the original", source.display()),
+            )))
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(destination);
+            Err(err)
+        }
+    }
+}
+
+/// Bytes available on the filesystem holding `dir`, which must exist
+pub fn free_space_in(dir: &Path) -> Result<u64> {
+    let canonical = std::fs::canonicalize(dir)?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| canonical.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+        .ok_or_else(|| Error::InvalidConfig(format!("No filesystem found for {}", dir.display())))
+}
+
+/// How far a storage directory migration has got, sent after each file
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageMigrationProgress {
+    /// File just copied, at its new location
+    pub path: String,
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Regular files directly in `dir`, sorted: disk images with their checksum
+/// and secret files. Subdirectories such as import staging are left out.
+pub fn storage_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copy `files` into `to_dir` with `copy_verified`, reporting progress after
+/// each, and return `(original, copy)` pairs. The originals are untouched;
+/// on failure the copies made so far are removed.
+pub fn copy_storage_files(
+    files: &[PathBuf],
+    to_dir: &Path,
+    mut progress: impl FnMut(StorageMigrationProgress),
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let total_bytes = files
+        .iter()
+        .map(|file| std::fs::metadata(file).map(|metadata| metadata.len()))
+        .sum::<std::io::Result<u64>>()?;
+    let mut copied: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut copied_bytes = 0;
+    for file in files {
+        let destination = to_dir.join(file.file_name().unwrap_or_default());
+        let result = if destination.exists() {
+            Err(Error::InvalidConfig(format!("{} already exists", destination.display())))
+        } else {
+            copy_verified(file, &destination).and_then(|_| Ok(std::fs::metadata(&destination)?.len()))
+        };
+        match result {
+            Ok(len) => {
+                copied_bytes += len;
+                progress(StorageMigrationProgress {
+                    path: destination.display().to_string(),
+                    copied_bytes,
+                    total_bytes,
+                });
+                copied.push((file.clone(), destination));
+            }
+            Err(err) => {
+                for (_, copy) in &copied {
+                    let _ = std::fs::remove_file(copy);
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(copied)
+}
+
+#[cfg(unix)]
+fn allocated_bytes(metadata: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always counted in 512-byte units
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_bytes(metadata: &std::fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// Host space a disk image occupies, which for sparse images is below its virtual size
+pub fn physical_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|metadata| allocated_bytes(&metadata))
+}
+
+/// Whether the mode reserves the full disk size on the host up front
+pub fn allocates_upfront(preallocation: &str) -> bool {
+    preallocation == "falloc" || preallocation == "full"
+}
+
+impl DiskManager {
+    pub fn new(storage_dir: String) -> Self {
+        Self {
+            storage_dir: RwLock::new(storage_dir),
+            info_cache: Mutex::new(InfoCache::default()),
+        }
+    }
+
+    /// Directory new disks are created in
+    pub fn storage_dir(&self) -> String {
+        self.storage_dir.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Create and look for disks in `storage_dir` from now on
+    pub fn set_storage_dir(&self, storage_dir: String) {
+        *self.storage_dir.write().unwrap_or_else(|e| e.into_inner()) = storage_dir;
+        if let Ok(mut cache) = self.info_cache.lock() {
+            cache.entries.clear();
+        }
+    }
+
+    /// Drop the cached `qemu-img info` for a VM's disk after it was written to
+    pub fn invalidate_info(&self, vm_id: &str) {
+        self.invalidate_path(&self.default_disk_path(vm_id));
+    }
+
+    fn invalidate_path(&self, disk_path: &str) {
+        if let Ok(mut cache) = self.info_cache.lock() {
+            cache.invalidate(disk_path);
+        }
+    }
+
+    /// Where a VM's disk lives unless it was relocated
+    pub fn default_disk_path(&self, vm_id: &str) -> String {
+        format!("{}/{}.qcow2", self.storage_dir(), vm_id)
+    }
+
+    /// `qemu-img info --output=json` for an image, served from the cache while fresh
+    async fn image_info(&self, disk_path: &str) -> Result<serde_json::Value> {
+        // A missing file falls through to qemu-img, which reports the error
+        let modified = std::fs::metadata(disk_path).and_then(|metadata| metadata.modified()).ok();
+        if let (Some(modified), Ok(cache)) = (modified, self.info_cache.lock()) {
+            if let Some(info) = cache.get(disk_path, modified, Instant::now()) {
+                return Ok(info);
+            }
+        }
+
+        // -U reads images a running VM holds locked
+        let output = Command::new("qemu-img")
+            .args(["info", "-U", "--output=json", disk_path])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::QemuError("qemu-img info failed".to_string()));
+        }
+
+        let info: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        if let (Some(modified), Ok(mut cache)) = (modified, self.info_cache.lock()) {
+            cache.insert(disk_path, modified, Instant::now(), info.clone());
+        }
+        Ok(info)
+    }
+
+    #[tracing::instrument(skip_all, fields(vm_id = %vm_id))]
+    pub async fn create_disk(
+        &self,
+        vm_id: &str,
+        size_gb: u32,
+        preallocation: &str,
+        secret: Option<&DiskSecret<'_>>,
+    ) -> Result<String> {
+        let storage_dir = self.storage_dir();
+        let disk_path = format!("{}/{}.qcow2", storage_dir, vm_id);
+        self.invalidate_info(vm_id);
+        
+        std::fs::create_dir_all(&storage_dir)?;
+        
+        if allocates_upfront(preallocation) {
+            self.ensure_free_space(size_gb as u64 * 1024 * 1024 * 1024)?;
+        }
+        
+        let secret_file = format!("{}/.{}.secret", storage_dir, vm_id);
+        if let Some(secret) = secret {
+            write_secret_file(Path::new(&secret_file), secret.passphrase)?;
+        }
+        
+        let output = Command::new("qemu-img")
+            .args(create_args(
+                &disk_path,
+                size_gb,
+                preallocation,
+                secret.map(|secret| (secret.key_ref, Path::new(&secret_file))),
+            ))
+            .output()
+            .await;
+        if secret.is_some() {
+            let _ = std::fs::remove_file(&secret_file);
+        }
+        let output = output?;
+        
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::QemuError(format!("qemu-img create failed: {}", stderr)));
+        }
+        
+        tracing::info!(size_gb, preallocation = %preallocation, encrypted = secret.is_some(), "disk created");
+        Ok(disk_path)
+    }
+
+    /// Grow a VM's qcow2 image; callers must ensure the VM is stopped
+    #[tracing::instrument(skip_all, fields(vm_id = %vm_id))]
+    pub async fn resize_disk(&self, vm_id: &str, new_size_gb: u32) -> Result<()> {
+        self.resize_disk_at(&self.default_disk_path(vm_id), new_size_gb).await
+    }
+
+    /// `resize_disk` for an image outside the storage directory
+    pub async fn resize_disk_at(&self, disk_path: &str, new_size_gb: u32) -> Result<()> {
+        self.invalidate_path(disk_path);
+
+        let output = Command::new("qemu-img")
+            .args(resize_args(disk_path, new_size_gb))
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::QemuError(format!("qemu-img resize failed: {}", stderr)));
+        }
+
+        tracing::info!(new_size_gb, "disk resized");
+        Ok(())
+    }
+
+    /// Internal snapshots of an image that no running VM has open
+    pub async fn list_snapshots_at(&self, disk_path: &str) -> Result<Vec<DiskSnapshot>> {
+        Ok(snapshots_from_info(&self.image_info(disk_path).await?))
+    }
+
+    /// Take a disk-only snapshot of a stopped VM's image
+    pub async fn create_snapshot_at(&self, disk_path: &str, name: &str) -> Result<()> {
+        self.run_snapshot(disk_path, "-c", name).await?;
+        tracing::info!(snapshot = %name, "disk snapshot created");
+        Ok(())
+    }
+
+    /// Roll a stopped VM's image back to snapshot `name`
+    pub async fn apply_snapshot_at(&self, disk_path: &str, name: &str) -> Result<()> {
+        self.run_snapshot(disk_path, "-a", name).await?;
+        tracing::info!(snapshot = %name, "disk rolled back to snapshot");
+        Ok(())
+    }
+
+    /// Layer a new overlay over a stopped VM's image, which is left untouched
+    pub async fn create_overlay_at(&self, backing_path: &str, overlay_path: &str) -> Result<()> {
+        self.run_qemu_img(overlay_path, overlay_args(backing_path, overlay_path)).await?;
+        tracing::info!(overlay = %overlay_path, "external snapshot overlay created");
+        Ok(())
+    }
+
+    /// Merge a stopped VM's overlay into its backing file; the overlay itself is kept
+    pub async fn commit_overlay_at(&self, overlay_path: &str, backing_path: &str) -> Result<()> {
+        let args = ["commit", "-f", "qcow2", overlay_path].map(String::from).to_vec();
+        self.invalidate_path(backing_path);
+        self.run_qemu_img(overlay_path, args).await?;
+        self.invalidate_path(backing_path);
+        tracing::info!(overlay = %overlay_path, "external snapshot overlay committed");
+        Ok(())
+    }
+
+    async fn run_qemu_img(&self, disk_path: &str, args: Vec<String>) -> Result<()> {
+        self.invalidate_path(disk_path);
+        let output = Command::new("qemu-img").args(&args).output().await?;
+        self.invalidate_path(disk_path);
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::QemuError(format!("qemu-img {} failed: {}", args[0], stderr)));
+        }
+        Ok(())
+    }
+
+    async fn run_snapshot(&self, disk_path: &str, action: &str, name: &str) -> Result<()> {
+        self.run_qemu_img(disk_path, snapshot_args(disk_path, action, name)).await
+    }
+
+    /// Every `*.qcow2` in the storage directory, most recently modified first
+    pub fn list_all_disks(&self) -> Result<Vec<DiskEntry>> {
+        let entries = match std::fs::read_dir(self.storage_dir()) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut disks = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("qcow2") {
+                continue;
+            }
+            let metadata = std::fs::metadata(&path)?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let Some(vm_id) = path.file_stem().map(|stem| stem.to_string_lossy().to_string()) else {
+                continue;
+            };
+            disks.push(DiskEntry {
+                vm_id,
+                actual_size_bytes: allocated_bytes(&metadata),
+                modified_at: metadata.modified()?,
+                path,
+            });
+        }
+
+        disks.sort_by(|a, b| b.modified_at.cmp(&a.modified_at).then_with(|| a.vm_id.cmp(&b.vm_id)));
+        Ok(disks)
+    }
+
+    /// Bytes available on the filesystem holding the storage directory
+    pub fn free_space(&self) -> Result<u64> {
+        free_space_in(Path::new(&self.storage_dir()))
+    }
+
+    pub fn ensure_free_space(&self, required_bytes: u64) -> Result<()> {
+        let available = self.free_space()?;
+        if available < required_bytes {
+            return Err(Error::InsufficientSpace {
+                required_mb: required_bytes / (1024 * 1024),
+                available_mb: available / (1024 * 1024),
+            });
+        }
+        Ok(())
+    }
+
+    /// SHA-256 of a VM's disk image as hex
+    pub fn compute_checksum(&self, vm_id: &str) -> Result<String> {
+        self.compute_checksum_at(&self.default_disk_path(vm_id))
+    }
+
+    /// `compute_checksum` for an image outside the storage directory
+    pub fn compute_checksum_at(&self, disk_path: &str) -> Result<String> {
+        Ok(hex_digest(&file_checksum(Path::new(disk_path))?))
+    }
+
+    /// Write `checksum` next to the VM's disk in `sha256sum -c` format
+    pub fn save_checksum(&self, vm_id: &str, checksum: &str) -> Result<()> {
+        self.save_checksum_at(&self.default_disk_path(vm_id), checksum)
+    }
+
+    /// `save_checksum` for an image outside the storage directory
+    pub fn save_checksum_at(&self, disk_path: &str, checksum: &str) -> Result<()> {
+        let file_name = Path::new(disk_path).file_name().unwrap_or_default().to_string_lossy();
+        std::fs::write(checksum_path(disk_path), format!("{}  {}\n", checksum, file_name))?;
+        Ok(())
+    }
+
+    /// Recompute the VM's disk checksum and compare it with the saved one.
+    /// Fails if no checksum was saved; a mismatch is `Ok(false)`.
+    pub fn verify_disk_integrity(&self, vm_id: &str) -> Result<bool> {
+        self.verify_disk_integrity_at(&self.default_disk_path(vm_id))
+    }
+
+    /// `verify_disk_integrity` for an image outside the storage directory
+    pub fn verify_disk_integrity_at(&self, disk_path: &str) -> Result<bool> {
+        let saved = std::fs::read_to_string(checksum_path(disk_path))?;
+        let expected = saved.split_whitespace().next().unwrap_or_default();
+        Ok(self.compute_checksum_at(disk_path)?.eq_ignore_ascii_case(expected))
+    }
+
+    #[tracing::instrument(skip_all, fields(vm_id = %vm_id))]
+    pub async fn delete_disk(&self, vm_id: &str) -> Result<()> {
+        self.delete_disk_at(&self.default_disk_path(vm_id)).await
+    }
+
+    /// `delete_disk` for an image outside the storage directory
+    pub async fn delete_disk_at(&self, disk_path: &str) -> Result<()> {
+        self.invalidate_path(disk_path);
+        if Path::new(disk_path).exists() {
+            std::fs::remove_file(disk_path)?;
+            tracing::info!("disk deleted");
+        }
+        let _ = std::fs::remove_file(checksum_path(disk_path));
+        Ok(())
+    }
+
+    pub async fn get_disk_size(&self, vm_id: &str) -> Result<u64> {
+        let disk_path = self.default_disk_path(vm_id);
+        let metadata = std::fs::metadata(&disk_path)?;
+        Ok(metadata.len())
+    }
+
+    pub async fn get_virtual_size(&self, vm_id: &str) -> Result<u64> {
+        let disk_path = self.default_disk_path(vm_id);
+        let parsed = self.image_info(&disk_path).await?;
+        
+        let virtual_size = parsed["virtual-size"]
+            .as_u64()
+            .ok_or_else(|| Error::InvalidConfig("Invalid virtual-size in qemu-img output".to_string()))?;
+        
+        Ok(virtual_size)
+    }
+
+    pub async fn get_disk_info(&self, vm_id: &str, preallocation: &str) -> Result<DiskInfo> {
+        self.get_disk_info_at(&self.default_disk_path(vm_id), preallocation).await
+    }
+
+    /// `get_disk_info` for an image outside the default disks dir
+    pub async fn get_disk_info_at(&self, disk_path: &str, preallocation: &str) -> Result<DiskInfo> {
+        let parsed = self.image_info(disk_path).await?;
+
+        Ok(DiskInfo {
+            path: disk_path.to_string(),
+            virtual_size: parsed["virtual-size"].as_u64().unwrap_or(0),
+            actual_size: parsed["actual-size"].as_u64().unwrap_or(0),
+            preallocation: preallocation.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn setup_test_dir() -> TempDir {
+        TempDir::new().expect("Failed to create temp dir")
+    }
+
+    #[cfg(unix)]
+    fn set_mtime(path: &Path, secs: i64) {
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+        let times = [libc::timeval { tv_sec: secs as libc::time_t, tv_usec: 0 }; 2];
+        // SAFETY: `c_path` is NUL-terminated and `times` holds the two entries utimes reads.
+        assert_eq!(unsafe { libc::utimes(c_path.as_ptr(), times.as_ptr()) }, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_all_disks_newest_first() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        for (vm_id, mtime) in [("vm-old", 1_000_000), ("vm-new", 3_000_000), ("vm-mid", 2_000_000)] {
+            let path = temp_dir.path().join(format!("{}.qcow2", vm_id));
+            fs::write(&path, vec![0u8; 4096]).unwrap();
+            set_mtime(&path, mtime);
+        }
+        // Not disks
+        fs::write(temp_dir.path().join(".vm-new.secret"), "secret").unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "hello").unwrap();
+        fs::create_dir(temp_dir.path().join("dir.qcow2")).unwrap();
+
+        let disks = manager.list_all_disks().unwrap();
+        let ids: Vec<&str> = disks.iter().map(|disk| disk.vm_id.as_str()).collect();
+        assert_eq!(ids, vec!["vm-new", "vm-mid", "vm-old"]);
+        assert_eq!(disks[0].path, temp_dir.path().join("vm-new.qcow2"));
+        assert!(disks[0].actual_size_bytes >= 4096);
+    }
+
+    #[test]
+    fn test_info_cache_expires_and_tracks_mtime() {
+        let mut cache = InfoCache::default();
+        let start = Instant::now();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let info = serde_json::json!({ "virtual-size": 1024 });
+        cache.insert("/disks/vm-1.qcow2", modified, start, info.clone());
+
+        // A burst of calls within the TTL hits the cache
+        assert_eq!(cache.get("/disks/vm-1.qcow2", modified, start + Duration::from_millis(500)), Some(info));
+        // A newer mtime means the image was written to
+        let touched = modified + Duration::from_secs(1);
+        assert_eq!(cache.get("/disks/vm-1.qcow2", touched, start), None);
+        assert_eq!(cache.get("/disks/vm-1.qcow2", modified, start + INFO_CACHE_TTL), None);
+        assert_eq!(cache.get("/disks/vm-2.qcow2", modified, start), None);
+    }
+
+    #[test]
+    fn test_invalidate_info_drops_vm_entry() {
+        let temp_dir = setup_test_dir();
+        let storage_dir = temp_dir.path().to_string_lossy().to_string();
+        let manager = DiskManager::new(storage_dir.clone());
+        let disk_path = format!("{}/vm-1.qcow2", storage_dir);
+        let modified = SystemTime::UNIX_EPOCH;
+        let now = Instant::now();
+        manager
+            .info_cache
+            .lock()
+            .unwrap()
+            .insert(&disk_path, modified, now, serde_json::json!({}));
+
+        manager.invalidate_info("vm-1");
+        assert_eq!(manager.info_cache.lock().unwrap().get(&disk_path, modified, now), None);
+    }
+
+    #[test]
+    fn test_list_all_disks_missing_dir_is_empty() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().join("missing").to_string_lossy().to_string());
+        assert!(manager.list_all_disks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_disk_manager_new() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        assert_eq!(manager.storage_dir(), temp_dir.path().to_string_lossy().to_string());
+    }
+
+    #[tokio::test]
+    async fn test_create_disk_returns_valid_path() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        
+        let result = manager.create_disk("test-vm-1", 50, "metadata", None).await;
+        
+        match result {
+            Ok(path) => {
+                assert!(path.contains("test-vm-1"));
+                assert!(path.ends_with(".qcow2"));
+            }
+            Err(e) => {
+                assert!(e.to_string().contains("qemu-img") || e.to_string().contains("No such file"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_disk_removes_file() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        let disk_path = format!("{}/test-vm.qcow2", temp_dir.path().display());
+        
+        create_test_file(&disk_path, b"test");
+        
+        assert!(Path::new(&disk_path).exists());
+        
+        let result = manager.delete_disk("test-vm").await;
+        assert!(result.is_ok());
+        assert!(!Path::new(&disk_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_delete_disk_nonexistent_succeeds() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        
+        let result = manager.delete_disk("nonexistent-vm").await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_copy_verified_matches_checksum() {
+        let temp_dir = setup_test_dir();
+        let source = temp_dir.path().join("vm-1.qcow2");
+        let destination = temp_dir.path().join("copy.qcow2");
+        fs::write(&source, vec![7u8; 256 * 1024]).unwrap();
+
+        copy_verified(&source, &destination).unwrap();
+        assert_eq!(file_checksum(&source).unwrap(), file_checksum(&destination).unwrap());
+        fs::write(&destination, b"changed").unwrap();
+        assert_ne!(file_checksum(&source).unwrap(), file_checksum(&destination).unwrap());
+
+        // Nothing is left behind when the copy fails
+        let missing = temp_dir.path().join("missing.qcow2");
+        let stray = temp_dir.path().join("stray.qcow2");
+        assert!(copy_verified(&missing, &stray).is_err());
+        assert!(!stray.exists());
+    }
+
+    #[test]
+    fn test_copy_storage_files_reports_progress_and_rolls_back() {
+        let temp_dir = setup_test_dir();
+        let from = temp_dir.path().join("old");
+        let to = temp_dir.path().join("new");
+        fs::create_dir_all(from.join(".import-1")).unwrap();
+        fs::create_dir_all(&to).unwrap();
+        fs::write(from.join("vm-1.qcow2"), vec![1u8; 300]).unwrap();
+        fs::write(from.join("vm-1.qcow2.sha256"), vec![2u8; 100]).unwrap();
+
+        let files = storage_files(&from).unwrap();
+        assert_eq!(files, vec![from.join("vm-1.qcow2"), from.join("vm-1.qcow2.sha256")]);
+        assert!(storage_files(&temp_dir.path().join("missing")).unwrap().is_empty());
+
+        let mut reports = Vec::new();
+        let copied = copy_storage_files(&files, &to, |progress| reports.push(progress)).unwrap();
+        assert_eq!(copied[0], (from.join("vm-1.qcow2"), to.join("vm-1.qcow2")));
+        let bytes: Vec<(u64, u64)> = reports.iter().map(|report| (report.copied_bytes, report.total_bytes)).collect();
+        assert_eq!(bytes, vec![(300, 400), (400, 400)]);
+        assert!(from.join("vm-1.qcow2").is_file());
+
+        // A name already taken in the target undoes the copies made before it
+        let retry = temp_dir.path().join("retry");
+        fs::create_dir_all(&retry).unwrap();
+        fs::write(retry.join("vm-1.qcow2.sha256"), b"other").unwrap();
+        assert!(copy_storage_files(&files, &retry, |_| {}).is_err());
+        assert!(!retry.join("vm-1.qcow2").exists());
+        assert_eq!(fs::read(retry.join("vm-1.qcow2.sha256")).unwrap(), b"other");
+    }
+
+    #[test]
+    fn test_disk_checksum_round_trip() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        let disk = temp_dir.path().join("vm-1.qcow2");
+        // Spans more than one read chunk
+        fs::write(&disk, vec![7u8; CHECKSUM_CHUNK_BYTES + 1024]).unwrap();
+
+        // No checksum saved yet
+        assert!(manager.verify_disk_integrity("vm-1").is_err());
+
+        let checksum = manager.compute_checksum("vm-1").unwrap();
+        assert_eq!(checksum.len(), 64);
+        assert!(checksum.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+        manager.save_checksum("vm-1", &checksum).unwrap();
+        let saved = fs::read_to_string(temp_dir.path().join("vm-1.qcow2.sha256")).unwrap();
+        assert_eq!(saved, format!("{}  vm-1.qcow2\n", checksum));
+        assert!(manager.verify_disk_integrity("vm-1").unwrap());
+
+        fs::write(&disk, b"tampered").unwrap();
+        assert!(!manager.verify_disk_integrity("vm-1").unwrap());
+    }
+
+    #[test]
+    fn test_hex_digest() {
+        assert_eq!(hex_digest(&[0x00, 0x0f, 0xab]), "000fab");
+    }
+
+    #[tokio::test]
+    async fn test_get_disk_size_valid_file() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        let disk_path = format!("{}/test-vm.qcow2", temp_dir.path().display());
+        
+        let test_data = vec![0u8; 1024];
+        create_test_file(&disk_path, &test_data);
+        
+        let result = manager.get_disk_size("test-vm").await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 1024);
+    }
+
+    #[tokio::test]
+    async fn test_get_disk_size_nonexistent_fails() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        
+        let result = manager.get_disk_size("nonexistent-vm").await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_storage_dir_path_validation() {
+        let manager = DiskManager::new("/valid/path".to_string());
+        assert!(!manager.storage_dir().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_multiple_disks_same_dir() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        
+        let disk1_result = manager.create_disk("vm-1", 50, "off", None).await;
+        let disk2_result = manager.create_disk("vm-2", 100, "off", None).await;
+        
+        if let (Ok(disk1), Ok(disk2)) = (disk1_result, disk2_result) {
+            
+            assert_ne!(disk1, disk2);
+            assert!(disk1.contains("vm-1"));
+            assert!(disk2.contains("vm-2"));
+        }
+    }
+
+    #[test]
+    fn test_create_args_include_preallocation() {
+        let args = create_args("/tmp/vm.qcow2", 20, "falloc", None);
+        assert_eq!(
+            args,
+            vec!["create", "-f", "qcow2", "-o", "preallocation=falloc", "/tmp/vm.qcow2", "20G"]
+        );
+    }
+
+    #[test]
+    fn test_create_args_luks_uses_secret_file() {
+        let args = create_args("/tmp/vm.qcow2", 20, "metadata", Some(("luks-vm", Path::new("/tmp/.vm.secret"))));
+        assert_eq!(
+            args,
+            vec![
+                "create",
+                "--object",
+                "secret,id=luks-vm,file=/tmp/.vm.secret",
+                "-f",
+                "qcow2",
+                "-o",
+                "preallocation=metadata,encrypt.format=luks,encrypt.key-secret=luks-vm",
+                "/tmp/vm.qcow2",
+                "20G",
+            ]
+        );
+        assert!(!args.iter().any(|arg| arg.contains("passphrase")));
+    }
+
+    #[test]
+    fn test_resize_args() {
+        assert_eq!(
+            resize_args("/tmp/vm.qcow2", 40),
+            vec!["resize", "-f", "qcow2", "/tmp/vm.qcow2", "40G"]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_args() {
+        assert_eq!(
+            snapshot_args("/tmp/vm.qcow2", "-c", "base"),
+            vec!["snapshot", "-f", "qcow2", "-c", "base", "/tmp/vm.qcow2"]
+        );
+    }
+
+    #[test]
+    fn test_external_snapshot_overlay() {
+        assert_eq!(
+            external_snapshot_path("/data/disks/vm-1.qcow2", "vm-1", "pre-upgrade"),
+            "/data/disks/vm-1-pre-upgrade.qcow2"
+        );
+        assert_eq!(
+            overlay_args("/data/vm-1.qcow2", "/data/vm-1-base.qcow2"),
+            vec!["create", "-f", "qcow2", "-b", "/data/vm-1.qcow2", "-F", "qcow2", "/data/vm-1-base.qcow2"]
+        );
+    }
+
+    #[test]
+    fn test_snapshots_from_info() {
+        let info = serde_json::json!({
+            "virtual-size": 21474836480u64,
+            "snapshots": [
+                { "id": "1", "name": "clean-install", "vm-state-size": 0, "date-sec": 1760000000 },
+                { "id": "2", "name": "live", "vm-state-size": 402653184, "date-sec": 1760000600 },
+            ],
+        });
+        assert_eq!(
+            snapshots_from_info(&info),
+            vec![
+                DiskSnapshot { name: "clean-install".to_string(), includes_ram: false, created_at: 1760000000 },
+                DiskSnapshot { name: "live".to_string(), includes_ram: true, created_at: 1760000600 },
+            ]
+        );
+        assert!(snapshots_from_info(&serde_json::json!({ "virtual-size": 1 })).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_names() {
+        assert!(is_valid_snapshot_name("before-upgrade_2.1"));
+        assert!(!is_valid_snapshot_name(""));
+        assert!(!is_valid_snapshot_name("42"));
+        assert!(!is_valid_snapshot_name("has space"));
+        assert!(!is_valid_snapshot_name(&"a".repeat(65)));
+    }
+
+    #[tokio::test]
+    async fn test_resize_disk_missing_image_fails() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+        assert!(manager.resize_disk("missing-vm", 40).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_secret_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = setup_test_dir();
+        let path = temp_dir.path().join("secret");
+        write_secret_file(&path, "hunter2").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hunter2");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_allocates_upfront() {
+        assert!(allocates_upfront("full"));
+        assert!(allocates_upfront("falloc"));
+        assert!(!allocates_upfront("metadata"));
+        assert!(!allocates_upfront("off"));
+    }
+
+    #[test]
+    fn test_ensure_free_space_rejects_oversized_request() {
+        let temp_dir = setup_test_dir();
+        let manager = DiskManager::new(temp_dir.path().to_string_lossy().to_string());
+
+        if manager.free_space().is_ok() {
+            assert!(manager.ensure_free_space(0).is_ok());
+            let err = manager.ensure_free_space(u64::MAX).unwrap_err();
+            assert!(err.to_string().contains("Not enough free space"));
+        }
+    }
+
+    #[test]
+    fn test_storage_dir_with_special_chars() {
+        let manager = DiskManager::new("/path/with spaces/and-dashes".to_string());
+        assert!(!manager.storage_dir().is_empty());
+        assert!(manager.storage_dir().contains("spaces"));
+    }
+
+    fn create_test_file(path: &str, data: &[u8]) {
+        let mut file = fs::File::create(path).expect("Failed to create test file");
+        file.write_all(data).expect("Failed to write test data");
+    }
+}
\ No newline at end of file
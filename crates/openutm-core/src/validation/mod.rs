@@ -0,0 +1,860 @@
+//! VM configuration validation
+//!
+//! Every rule runs so the creation form can flag all problems at once.
+//! Errors block create/update; warnings are returned alongside the VM.
+
+use crate::qemu::command::{self, NetworkConfig, VmPerformance, MAX_NETWORK_ADAPTERS, MAX_VLAN_ID};
+use crate::storage;
+use crate::{PlatformInfo, VMConfig};
+use std::path::Path;
+
+pub const MIN_MEMORY_MB: u32 = 512;
+/// Upper bounds that hold on any host, well past what QEMU guests are run with in practice
+pub const MAX_MEMORY_MB: u32 = 512 * 1024;
+pub const MAX_CPU_CORES: u32 = 256;
+pub const MAX_DISK_SIZE_GB: u32 = 65536;
+pub const MAX_NAME_LEN: usize = 64;
+pub const MAX_NOTES_LEN: usize = 10_000;
+
+pub const ARCHES: [&str; 2] = ["x86_64", "aarch64"];
+const NETWORK_TYPES: [&str; 2] = ["nat", "bridge"];
+const BOOT_ORDERS: [&str; 2] = ["disk-first", "cdrom-first"];
+const RESTART_POLICIES: [&str; 3] = ["always", "on-failure", "never"];
+const DISPLAY_MODES: [&str; 3] = ["spice", "vnc", "none"];
+const FORWARD_PROTOCOLS: [&str; 2] = ["tcp", "udp"];
+/// Netdev ids end up in QEMU's argv, so they stay short and plain
+const MAX_ADAPTER_ID_LEN: usize = 32;
+/// Characters that cannot appear in file names on at least one host OS
+const INVALID_NAME_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationIssue {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+    pub severity: Severity,
+    /// Values in `message`, so the UI can render its own translation of `code`
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ValidationIssue {
+    fn error(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            code: code.to_string(),
+            message: message.into(),
+            severity: Severity::Error,
+            params: serde_json::Map::new(),
+        }
+    }
+
+    fn warning(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(field, code, message)
+        }
+    }
+
+    fn with_param(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.params.insert(name.to_string(), value.into());
+        self
+    }
+}
+
+/// Host facts the rules are checked against
+#[derive(Debug, Clone)]
+pub struct HostLimits {
+    pub logical_cpus: u32,
+    pub total_memory_mb: u64,
+    /// Free bytes in the disk storage dir; `None` skips the space checks
+    pub free_disk_bytes: Option<u64>,
+    pub os: String,
+    /// Running Linux kernel as `(major, minor)`; `None` elsewhere or when unknown
+    pub kernel_version: Option<(u32, u32)>,
+}
+
+pub fn validate_vm_config(config: &VMConfig, host: &HostLimits) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    validate_name(&config.name, &mut issues);
+    validate_memory(config.memory_mb, host, &mut issues);
+    validate_cpu(config, host, &mut issues);
+    validate_disk(config, host, &mut issues);
+
+    if let Some(path) = &config.install_media_path {
+        if !Path::new(path).is_file() {
+            issues.push(ValidationIssue::error(
+                "install_media_path",
+                "not-found",
+                format!("Install media {} does not exist", path),
+            )
+            .with_param("path", path.as_str()));
+        }
+    }
+
+    check_allowed("arch", &config.arch, &ARCHES, &mut issues);
+    check_allowed("network_type", &config.network_type, &NETWORK_TYPES, &mut issues);
+    check_allowed("boot_order", &config.boot_order, &BOOT_ORDERS, &mut issues);
+    check_allowed("restart_policy", &config.restart_policy, &RESTART_POLICIES, &mut issues);
+    check_allowed("display_mode", &config.display_mode, &DISPLAY_MODES, &mut issues);
+    check_allowed(
+        "preallocation",
+        &config.preallocation,
+        &storage::PREALLOCATION_MODES,
+        &mut issues,
+    );
+
+    if !(-20..=19).contains(&config.priority) {
+        issues.push(ValidationIssue::error(
+            "priority",
+            "out-of-range",
+            "Priority must be between -20 and 19",
+        ));
+    }
+
+    if let Some(vlan_id) = config.vlan_id {
+        if !(1..=MAX_VLAN_ID).contains(&vlan_id) {
+            issues.push(ValidationIssue::error(
+                "vlan_id",
+                "out-of-range",
+                format!("VLAN ID must be between 1 and {}", MAX_VLAN_ID),
+            )
+            .with_param("max", MAX_VLAN_ID));
+        } else if config.network_type != "bridge" {
+            issues.push(ValidationIssue::error(
+                "vlan_id",
+                "requires-bridge",
+                "VLAN requires TAP or bridge network",
+            ));
+        }
+    }
+
+    if let Some(resolution) = &config.display_resolution {
+        if command::parse_resolution(resolution).is_none() {
+            let (min, max) = (command::MIN_RESOLUTION, command::MAX_RESOLUTION);
+            issues.push(ValidationIssue::error(
+                "display_resolution",
+                "invalid-resolution",
+                format!(
+                    "Resolution must be WIDTHxHEIGHT between {}x{} and {}x{}",
+                    min.0, min.1, max.0, max.1
+                ),
+            )
+            .with_param("min", format!("{}x{}", min.0, min.1))
+            .with_param("max", format!("{}x{}", max.0, max.1)));
+        }
+    }
+
+    if let Some(boot_menu) = &config.boot_menu {
+        if boot_menu.splash_time_ms > command::MAX_SPLASH_TIME_MS {
+            issues.push(ValidationIssue::error(
+                "boot_menu",
+                "invalid-splash-time",
+                format!("Boot menu splash time must be at most {} ms", command::MAX_SPLASH_TIME_MS),
+            )
+            .with_param("max", command::MAX_SPLASH_TIME_MS));
+        }
+    }
+
+    if let Some(machine_type) = &config.machine_type {
+        if !command::is_valid_machine_name(machine_type) {
+            issues.push(ValidationIssue::error(
+                "machine_type",
+                "invalid-machine-type",
+                "Machine type may only contain letters, digits, '.', '-' and '_'",
+            ));
+        }
+    }
+
+    if let Some(graphics) = &config.graphics {
+        match command::GraphicsAdapter::parse(graphics) {
+            None => check_allowed("graphics", graphics, &command::GRAPHICS_ADAPTERS, &mut issues),
+            Some(adapter) if adapter.is_vga() && config.arch == "aarch64" => {
+                issues.push(ValidationIssue::error(
+                    "graphics",
+                    "unsupported-arch",
+                    "aarch64 guests only support the virtio-gpu adapter",
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    issues.extend(validate_networks(&config.networks));
+    if let Some(performance) = &config.performance {
+        validate_performance(performance, config, host, &mut issues);
+    }
+
+    if config.notes.chars().count() > MAX_NOTES_LEN {
+        issues.push(ValidationIssue::error(
+            "notes",
+            "too-long",
+            format!("Notes must be at most {} characters", MAX_NOTES_LEN),
+        )
+        .with_param("max", MAX_NOTES_LEN));
+    }
+
+    issues
+}
+
+/// `validate_vm_config` against what `get_platform_info` reported, as the
+/// messages of any errors. Memory past the host's RAM is only logged here.
+pub fn validate_vm_config_extended(config: &VMConfig, platform: &PlatformInfo) -> Result<(), String> {
+    let host = HostLimits {
+        logical_cpus: platform.cpu_count,
+        total_memory_mb: platform.total_ram_mb,
+        free_disk_bytes: (platform.free_disk_mb > 0).then(|| platform.free_disk_mb * 1024 * 1024),
+        os: platform.os.clone(),
+        kernel_version: None,
+    };
+    if platform.total_ram_mb > 0 && u64::from(config.memory_mb) > platform.total_ram_mb {
+        tracing::warn!(
+            memory_mb = config.memory_mb,
+            host_mb = platform.total_ram_mb,
+            "VM memory exceeds the host's RAM"
+        );
+    }
+
+    let errors: Vec<String> = validate_vm_config(config, &host)
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| issue.message)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+fn validate_performance(
+    performance: &VmPerformance,
+    config: &VMConfig,
+    host: &HostLimits,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    if performance.iothreads > config.cpu_cores {
+        issues.push(ValidationIssue::error(
+            "performance.iothreads",
+            "exceeds-cpu-cores",
+            format!("At most {} I/O threads, one per vCPU", config.cpu_cores),
+        )
+        .with_param("max", config.cpu_cores));
+    }
+
+    if performance.net_multiqueue && config.vlan_id.is_none() {
+        issues.push(ValidationIssue::warning(
+            "performance.net_multiqueue",
+            "requires-tap",
+            "Multiqueue only applies to VLAN (TAP) networking",
+        ));
+    }
+
+    let Some(aio) = &performance.aio else {
+        return;
+    };
+    if !command::AIO_MODES.contains(&aio.as_str()) {
+        check_allowed("performance.aio", aio, &command::AIO_MODES, issues);
+    } else if host.os != "linux" {
+        issues.push(ValidationIssue::error(
+            "performance.aio",
+            "unsupported-host",
+            "Native and io_uring AIO are only available on Linux hosts",
+        ));
+    } else if aio == "io_uring"
+        && host.kernel_version.map_or(true, |version| version < command::IO_URING_MIN_KERNEL)
+    {
+        let (major, minor) = command::IO_URING_MIN_KERNEL;
+        issues.push(ValidationIssue::error(
+            "performance.aio",
+            "kernel-too-old",
+            format!("io_uring needs Linux {}.{} or newer", major, minor),
+        )
+        .with_param("kernel", format!("{}.{}", major, minor)));
+    }
+}
+
+fn validate_name(name: &str, issues: &mut Vec<ValidationIssue>) {
+    let name = name.trim();
+    if name.is_empty() {
+        issues.push(ValidationIssue::error("name", "required", "VM name cannot be empty"));
+        return;
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        issues.push(ValidationIssue::error(
+            "name",
+            "too-long",
+            format!("VM name must be at most {} characters", MAX_NAME_LEN),
+        )
+        .with_param("max", MAX_NAME_LEN));
+    }
+    if name.chars().any(|c| c.is_control() || INVALID_NAME_CHARS.contains(&c)) {
+        issues.push(ValidationIssue::error(
+            "name",
+            "invalid-characters",
+            "VM name cannot contain control characters or / \\ : * ? \" < > |",
+        ));
+    }
+}
+
+fn validate_memory(memory_mb: u32, host: &HostLimits, issues: &mut Vec<ValidationIssue>) {
+    if memory_mb < MIN_MEMORY_MB {
+        issues.push(ValidationIssue::error(
+            "memory_mb",
+            "too-small",
+            format!("Memory must be at least {} MB", MIN_MEMORY_MB),
+        )
+        .with_param("min", MIN_MEMORY_MB));
+    } else if memory_mb > MAX_MEMORY_MB {
+        issues.push(ValidationIssue::error(
+            "memory_mb",
+            "too-large",
+            format!("Memory must be at most {} MB", MAX_MEMORY_MB),
+        )
+        .with_param("max", MAX_MEMORY_MB));
+    } else if host.total_memory_mb > 0 && memory_mb as u64 > host.total_memory_mb {
+        // The host may swap or balloon its way there, so this warns rather than refuses
+        issues.push(ValidationIssue::warning(
+            "memory_mb",
+            "exceeds-host",
+            format!("Memory exceeds the host's {} MB", host.total_memory_mb),
+        )
+        .with_param("hostMb", host.total_memory_mb));
+    } else if host.total_memory_mb > 0 && memory_mb as u64 * 4 > host.total_memory_mb * 3 {
+        issues.push(ValidationIssue::warning(
+            "memory_mb",
+            "high-memory",
+            "Memory uses more than 75% of the host's RAM",
+        ));
+    }
+}
+
+fn validate_cpu(config: &VMConfig, host: &HostLimits, issues: &mut Vec<ValidationIssue>) {
+    if config.cpu_cores == 0 {
+        issues.push(ValidationIssue::error("cpu_cores", "too-small", "CPU cores must be at least 1"));
+    } else if config.cpu_cores > MAX_CPU_CORES {
+        issues.push(ValidationIssue::error(
+            "cpu_cores",
+            "too-large",
+            format!("CPU cores must be at most {}", MAX_CPU_CORES),
+        )
+        .with_param("max", MAX_CPU_CORES));
+    } else if host.logical_cpus > 0 && config.cpu_cores > host.logical_cpus {
+        issues.push(ValidationIssue::warning(
+            "cpu_cores",
+            "exceeds-host",
+            format!(
+                "{} vCPUs overcommit the host's {} logical CPUs",
+                config.cpu_cores, host.logical_cpus
+            ),
+        )
+        .with_param("cores", config.cpu_cores)
+        .with_param("logicalCpus", host.logical_cpus));
+    }
+
+    if let Some(core) = config.cpu_affinity.iter().find(|core| **core >= host.logical_cpus) {
+        issues.push(ValidationIssue::error(
+            "cpu_affinity",
+            "out-of-range",
+            format!(
+                "Core {} is out of range; host has {} logical CPUs",
+                core, host.logical_cpus
+            ),
+        )
+        .with_param("core", *core)
+        .with_param("logicalCpus", host.logical_cpus));
+    }
+}
+
+fn validate_disk(config: &VMConfig, host: &HostLimits, issues: &mut Vec<ValidationIssue>) {
+    if let Some(path) = &config.raw_device_path {
+        let valid = match host.os.as_str() {
+            "windows" => path.starts_with(r"\\.\PhysicalDrive"),
+            _ => path.starts_with("/dev/"),
+        };
+        if !valid {
+            let expected = if host.os == "windows" { r"\\.\PhysicalDriveN" } else { "/dev/" };
+            issues.push(ValidationIssue::error(
+                "raw_device_path",
+                "invalid-device",
+                format!("Raw device path must start with {}", expected),
+            )
+            .with_param("prefix", expected));
+        }
+        if config.encrypted {
+            issues.push(ValidationIssue::error(
+                "encrypted",
+                "unsupported",
+                "Raw device passthrough cannot be combined with disk encryption",
+            ));
+        }
+        // The device brings its own size; there is no image to allocate
+        return;
+    }
+
+    if config.disk_size_gb == 0 {
+        issues.push(ValidationIssue::error("disk_size_gb", "too-small", "Disk size must be at least 1 GB"));
+        return;
+    }
+    if config.disk_size_gb > MAX_DISK_SIZE_GB {
+        issues.push(ValidationIssue::error(
+            "disk_size_gb",
+            "too-large",
+            format!("Disk size must be at most {} GB", MAX_DISK_SIZE_GB),
+        )
+        .with_param("max", MAX_DISK_SIZE_GB));
+        return;
+    }
+
+    if let Some(free) = host.free_disk_bytes {
+        let required = config.disk_size_gb as u64 * 1024 * 1024 * 1024;
+        if required > free {
+            let free_gb = free / (1024 * 1024 * 1024);
+            let message = format!("Disk size exceeds the {} GB free in the storage directory", free_gb);
+            let issue = if storage::allocates_upfront(&config.preallocation) {
+                ValidationIssue::error("disk_size_gb", "insufficient-space", message)
+            } else {
+                ValidationIssue::warning("disk_size_gb", "insufficient-space", message)
+            };
+            issues.push(issue.with_param("freeGb", free_gb));
+        }
+    }
+}
+
+/// Rules for a VM's full list of network adapters, reported under `networks`
+pub fn validate_networks(networks: &[NetworkConfig]) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    if networks.len() > MAX_NETWORK_ADAPTERS {
+        issues.push(ValidationIssue::error(
+            "networks",
+            "too-many",
+            format!("A VM can have at most {} network adapters", MAX_NETWORK_ADAPTERS),
+        )
+        .with_param("max", MAX_NETWORK_ADAPTERS));
+    }
+
+    let mut ids = std::collections::HashSet::new();
+    let mut host_ports = std::collections::HashSet::new();
+    for network in networks {
+        let plain_id = network.id.len() <= MAX_ADAPTER_ID_LEN
+            && network.id.starts_with(|c: char| c.is_ascii_alphabetic())
+            && network.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !plain_id {
+            issues.push(ValidationIssue::error(
+                "networks",
+                "invalid-id",
+                format!(
+                    "Adapter IDs start with a letter and use up to {} letters, digits, '-' or '_'",
+                    MAX_ADAPTER_ID_LEN
+                ),
+            )
+            .with_param("max", MAX_ADAPTER_ID_LEN));
+        } else if !ids.insert(network.id.as_str()) {
+            issues.push(ValidationIssue::error(
+                "networks",
+                "duplicate-id",
+                format!("Adapter ID {} is used twice", network.id),
+            )
+            .with_param("id", network.id.as_str()));
+        }
+        check_allowed("networks", &network.kind, &NETWORK_TYPES, &mut issues);
+        if network.mac.as_deref().is_some_and(|mac| !command::is_valid_mac(mac)) {
+            issues.push(ValidationIssue::error(
+                "networks",
+                "invalid-mac",
+                format!("Adapter {} has an invalid MAC address", network.id),
+            )
+            .with_param("id", network.id.as_str()));
+        }
+
+        if !network.port_forwards.is_empty() && network.kind != "nat" {
+            issues.push(ValidationIssue::error(
+                "networks",
+                "requires-nat",
+                "Port forwarding is only available on NAT adapters",
+            ));
+        }
+        for forward in &network.port_forwards {
+            check_allowed("networks", &forward.protocol, &FORWARD_PROTOCOLS, &mut issues);
+            if forward.host_port == 0 || forward.guest_port == 0 {
+                issues.push(ValidationIssue::error("networks", "out-of-range", "Forwarded ports cannot be 0"));
+            } else if !host_ports.insert((forward.protocol.as_str(), forward.host_port)) {
+                issues.push(ValidationIssue::error(
+                    "networks",
+                    "duplicate-host-port",
+                    format!("Host port {}/{} is forwarded twice", forward.host_port, forward.protocol),
+                )
+                .with_param("port", forward.host_port)
+                .with_param("protocol", forward.protocol.as_str()));
+            }
+        }
+    }
+    issues
+}
+
+fn check_allowed(field: &str, value: &str, allowed: &[&str], issues: &mut Vec<ValidationIssue>) {
+    if !allowed.contains(&value) {
+        issues.push(ValidationIssue::error(
+            field,
+            "unknown-value",
+            format!("{} must be one of {}", field, allowed.join(", ")),
+        )
+        .with_param("field", field)
+        .with_param("allowed", allowed.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guest::GuestOs;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    fn valid_config() -> VMConfig {
+        VMConfig {
+            name: "Ubuntu".to_string(),
+            memory_mb: 2048,
+            cpu_cores: 2,
+            disk_size_gb: 20,
+            os: GuestOs::Linux,
+            install_media_path: None,
+            boot_order: "disk-first".to_string(),
+            network_type: "nat".to_string(),
+            nested_virt: false,
+            restart_policy: "on-failure".to_string(),
+            arch: "x86_64".to_string(),
+            cpu_affinity: Vec::new(),
+            priority: 0,
+            preallocation: "metadata".to_string(),
+            encrypted: false,
+            raw_device_path: None,
+            notes: String::new(),
+            clipboard_sharing: false,
+            vlan_id: None,
+            display_resolution: None,
+            graphics: None,
+            machine_type: None,
+            display_mode: "spice".to_string(),
+            boot_menu: None,
+            networks: Vec::new(),
+            performance: None,
+            pointer_device: None,
+            rtc: None,
+            rng: None,
+        }
+    }
+
+    fn host() -> HostLimits {
+        HostLimits {
+            logical_cpus: 8,
+            total_memory_mb: 16384,
+            free_disk_bytes: Some(100 * GB),
+            os: "linux".to_string(),
+            kernel_version: Some((6, 8)),
+        }
+    }
+
+    #[test]
+    fn test_valid_config_has_no_issues() {
+        assert!(validate_vm_config(&valid_config(), &host()).is_empty());
+    }
+
+    #[test]
+    fn test_extended_validation_against_platform() {
+        let platform = PlatformInfo {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            accelerator: Some("kvm".to_string()),
+            cpu_count: 8,
+            total_ram_mb: 16384,
+            free_disk_mb: 100 * 1024,
+            qemu_path: None,
+        };
+        assert_eq!(validate_vm_config_extended(&valid_config(), &platform), Ok(()));
+
+        // More memory than the host has is logged, not refused
+        let config = VMConfig { memory_mb: 32768, ..valid_config() };
+        assert_eq!(validate_vm_config_extended(&config, &platform), Ok(()));
+
+        let cases = [
+            (VMConfig { memory_mb: MAX_MEMORY_MB + 1, ..valid_config() }, "Memory must be at most 524288 MB"),
+            (VMConfig { cpu_cores: MAX_CPU_CORES + 1, ..valid_config() }, "CPU cores must be at most 256"),
+            (VMConfig { disk_size_gb: MAX_DISK_SIZE_GB + 1, ..valid_config() }, "Disk size must be at most 65536 GB"),
+        ];
+        for (config, message) in cases {
+            assert_eq!(validate_vm_config_extended(&config, &platform), Err(message.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_each_rule() {
+        type Mutate = fn(&mut VMConfig);
+        let cases: Vec<(&str, Mutate, &str, &str, Severity)> = vec![
+            ("empty name", |c| c.name = "  ".to_string(), "name", "required", Severity::Error),
+            ("long name", |c| c.name = "x".repeat(65), "name", "too-long", Severity::Error),
+            ("slash in name", |c| c.name = "a/b".to_string(), "name", "invalid-characters", Severity::Error),
+            ("control char in name", |c| c.name = "a\tb".to_string(), "name", "invalid-characters", Severity::Error),
+            ("low memory", |c| c.memory_mb = 256, "memory_mb", "too-small", Severity::Error),
+            ("memory above host", |c| c.memory_mb = 32768, "memory_mb", "exceeds-host", Severity::Warning),
+            ("memory near host", |c| c.memory_mb = 14000, "memory_mb", "high-memory", Severity::Warning),
+            ("huge memory", |c| c.memory_mb = MAX_MEMORY_MB + 1, "memory_mb", "too-large", Severity::Error),
+            ("zero cpus", |c| c.cpu_cores = 0, "cpu_cores", "too-small", Severity::Error),
+            ("cpus above host", |c| c.cpu_cores = 16, "cpu_cores", "exceeds-host", Severity::Warning),
+            ("too many cpus", |c| c.cpu_cores = MAX_CPU_CORES + 1, "cpu_cores", "too-large", Severity::Error),
+            ("affinity out of range", |c| c.cpu_affinity = vec![8], "cpu_affinity", "out-of-range", Severity::Error),
+            ("zero disk", |c| c.disk_size_gb = 0, "disk_size_gb", "too-small", Severity::Error),
+            ("huge disk", |c| c.disk_size_gb = MAX_DISK_SIZE_GB + 1, "disk_size_gb", "too-large", Severity::Error),
+            ("sparse disk above free space", |c| c.disk_size_gb = 200, "disk_size_gb", "insufficient-space", Severity::Warning),
+            (
+                "full disk above free space",
+                |c| {
+                    c.disk_size_gb = 200;
+                    c.preallocation = "full".to_string();
+                },
+                "disk_size_gb",
+                "insufficient-space",
+                Severity::Error,
+            ),
+            ("missing iso", |c| c.install_media_path = Some("/nonexistent/os.iso".to_string()), "install_media_path", "not-found", Severity::Error),
+            ("vlan out of range", |c| { c.network_type = "bridge".to_string(); c.vlan_id = Some(4095) }, "vlan_id", "out-of-range", Severity::Error),
+            ("tiny resolution", |c| c.display_resolution = Some("320x200".to_string()), "display_resolution", "invalid-resolution", Severity::Error),
+            ("malformed resolution", |c| c.display_resolution = Some("1080p".to_string()), "display_resolution", "invalid-resolution", Severity::Error),
+            ("long splash time", |c| c.boot_menu = Some(command::BootMenuConfig { enabled: true, splash_time_ms: 70_000 }), "boot_menu", "invalid-splash-time", Severity::Error),
+            ("machine type with options", |c| c.machine_type = Some("q35,accel=tcg".to_string()), "machine_type", "invalid-machine-type", Severity::Error),
+            ("unknown display mode", |c| c.display_mode = "rdp".to_string(), "display_mode", "unknown-value", Severity::Error),
+            ("unknown graphics", |c| c.graphics = Some("cirrus".to_string()), "graphics", "unknown-value", Severity::Error),
+            ("qxl on aarch64", |c| { c.arch = "aarch64".to_string(); c.graphics = Some("qxl".to_string()) }, "graphics", "unsupported-arch", Severity::Error),
+            ("vlan on nat", |c| c.vlan_id = Some(10), "vlan_id", "requires-bridge", Severity::Error),
+            ("unknown arch", |c| c.arch = "riscv64".to_string(), "arch", "unknown-value", Severity::Error),
+            ("unknown network", |c| c.network_type = "host-only".to_string(), "network_type", "unknown-value", Severity::Error),
+            ("unknown boot order", |c| c.boot_order = "net-first".to_string(), "boot_order", "unknown-value", Severity::Error),
+            ("unknown restart policy", |c| c.restart_policy = "sometimes".to_string(), "restart_policy", "unknown-value", Severity::Error),
+            ("unknown preallocation", |c| c.preallocation = "sparse".to_string(), "preallocation", "unknown-value", Severity::Error),
+            ("priority out of range", |c| c.priority = 20, "priority", "out-of-range", Severity::Error),
+            ("long notes", |c| c.notes = "x".repeat(MAX_NOTES_LEN + 1), "notes", "too-long", Severity::Error),
+            ("iothreads above vcpus", |c| c.performance = Some(VmPerformance { iothreads: 3, ..VmPerformance::default() }), "performance.iothreads", "exceeds-cpu-cores", Severity::Error),
+            ("unknown aio", |c| c.performance = Some(VmPerformance { aio: Some("posix".to_string()), ..VmPerformance::default() }), "performance.aio", "unknown-value", Severity::Error),
+            ("multiqueue on nat", |c| c.performance = Some(VmPerformance { net_multiqueue: true, ..VmPerformance::default() }), "performance.net_multiqueue", "requires-tap", Severity::Warning),
+            ("bad raw device", |c| c.raw_device_path = Some("/home/disk.img".to_string()), "raw_device_path", "invalid-device", Severity::Error),
+            (
+                "encrypted raw device",
+                |c| {
+                    c.raw_device_path = Some("/dev/sdb".to_string());
+                    c.encrypted = true;
+                },
+                "encrypted",
+                "unsupported",
+                Severity::Error,
+            ),
+        ];
+
+        for (name, mutate, field, code, severity) in cases {
+            let mut config = valid_config();
+            mutate(&mut config);
+            let issues = validate_vm_config(&config, &host());
+            assert_eq!(issues.len(), 1, "{}: {:?}", name, issues);
+            assert_eq!(issues[0].field, field, "{}", name);
+            assert_eq!(issues[0].code, code, "{}", name);
+            assert_eq!(issues[0].severity, severity, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_network_adapter_rules() {
+        use crate::qemu::PortForward;
+
+        let adapter = |id: &str, kind: &str| NetworkConfig {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            mac: None,
+            port_forwards: Vec::new(),
+        };
+        let ssh = PortForward { protocol: "tcp".to_string(), host_port: 2222, guest_port: 22 };
+        let mut config = valid_config();
+        config.networks = vec![adapter("mgmt", "nat"), adapter("data", "bridge")];
+        config.networks[0].port_forwards = vec![ssh.clone()];
+        assert!(validate_vm_config(&config, &host()).is_empty());
+
+        let codes = |networks: Vec<NetworkConfig>| -> Vec<String> {
+            validate_networks(&networks).into_iter().map(|issue| issue.code).collect()
+        };
+        let too_many = (0..9).map(|n| adapter(&format!("net{}", n), "nat")).collect();
+        assert_eq!(codes(too_many), vec!["too-many"]);
+        assert_eq!(codes(vec![adapter("mgmt", "nat"), adapter("mgmt", "nat")]), vec!["duplicate-id"]);
+        assert_eq!(codes(vec![adapter("0net", "nat"), adapter("a,b", "nat")]), vec!["invalid-id", "invalid-id"]);
+        assert_eq!(codes(vec![adapter("mgmt", "host-only")]), vec!["unknown-value"]);
+
+        let mut bad_mac = adapter("mgmt", "nat");
+        bad_mac.mac = Some("52:54:00".to_string());
+        assert_eq!(codes(vec![bad_mac]), vec!["invalid-mac"]);
+
+        let mut bridged = adapter("data", "bridge");
+        bridged.port_forwards = vec![ssh.clone()];
+        assert_eq!(codes(vec![bridged]), vec!["requires-nat"]);
+
+        let (mut first, mut second) = (adapter("a", "nat"), adapter("b", "nat"));
+        first.port_forwards = vec![ssh.clone()];
+        second.port_forwards = vec![ssh.clone(), PortForward { guest_port: 0, ..ssh.clone() }];
+        assert_eq!(codes(vec![first, second]), vec!["duplicate-host-port", "out-of-range"]);
+    }
+
+    #[test]
+    fn test_upper_bounds() {
+        // A host big enough that only the absolute limits apply
+        let host = HostLimits {
+            logical_cpus: 512,
+            total_memory_mb: 1024 * 1024,
+            free_disk_bytes: None,
+            os: "linux".to_string(),
+            kernel_version: None,
+        };
+        let mut config = valid_config();
+        config.memory_mb = MAX_MEMORY_MB;
+        config.cpu_cores = MAX_CPU_CORES;
+        config.disk_size_gb = MAX_DISK_SIZE_GB;
+        assert!(validate_vm_config(&config, &host).is_empty());
+
+        config.memory_mb += 1;
+        config.cpu_cores += 1;
+        config.disk_size_gb += 1;
+        let messages: Vec<String> = validate_vm_config(&config, &host)
+            .into_iter()
+            .map(|issue| issue.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Memory must be at most 524288 MB",
+                "CPU cores must be at most 256",
+                "Disk size must be at most 65536 GB"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_existing_install_media_passes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let iso = temp_dir.path().join("os.iso");
+        std::fs::write(&iso, b"iso").unwrap();
+
+        let mut config = valid_config();
+        config.install_media_path = Some(iso.display().to_string());
+        assert!(validate_vm_config(&config, &host()).is_empty());
+    }
+
+    #[test]
+    fn test_aio_depends_on_host() {
+        let mut config = valid_config();
+        config.cpu_cores = 4;
+        config.performance = Some(VmPerformance {
+            iothreads: 4,
+            net_multiqueue: false,
+            aio: Some("io_uring".to_string()),
+        });
+        assert!(validate_vm_config(&config, &host()).is_empty());
+
+        let codes = |host: HostLimits| -> Vec<String> {
+            validate_vm_config(&config, &host).into_iter().map(|issue| issue.code).collect()
+        };
+        assert_eq!(codes(HostLimits { kernel_version: Some((4, 19)), ..host() }), vec!["kernel-too-old"]);
+        assert_eq!(codes(HostLimits { kernel_version: None, ..host() }), vec!["kernel-too-old"]);
+        let macos = HostLimits { os: "macos".to_string(), kernel_version: None, ..host() };
+        assert_eq!(codes(macos), vec!["unsupported-host"]);
+
+        config.performance = Some(VmPerformance { aio: Some("native".to_string()), ..VmPerformance::default() });
+        assert!(validate_vm_config(&config, &HostLimits { kernel_version: Some((4, 19)), ..host() }).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_host_limits_skip_host_checks() {
+        let mut config = valid_config();
+        config.memory_mb = 500_000;
+        config.disk_size_gb = 60_000;
+        let host = HostLimits {
+            total_memory_mb: 0,
+            free_disk_bytes: None,
+            ..host()
+        };
+        assert!(validate_vm_config(&config, &host).is_empty());
+    }
+
+    #[test]
+    fn test_collects_every_issue() {
+        let mut config = valid_config();
+        config.name = String::new();
+        config.memory_mb = 128;
+        config.cpu_cores = 0;
+        config.arch = "mips".to_string();
+        config.disk_size_gb = 200;
+
+        let issues = validate_vm_config(&config, &host());
+        let fields: Vec<&str> = issues.iter().map(|issue| issue.field.as_str()).collect();
+        assert_eq!(fields, vec!["name", "memory_mb", "cpu_cores", "disk_size_gb", "arch"]);
+        // The sparse disk only warns; the rest block creation
+        assert_eq!(issues[3].severity, Severity::Warning);
+        assert_eq!(issues.iter().filter(|issue| issue.severity == Severity::Error).count(), 4);
+    }
+
+    #[test]
+    fn test_raw_device_path_per_os() {
+        let cases = [
+            ("linux", "/dev/sdb", true),
+            ("macos", "/dev/disk2", true),
+            ("windows", r"\\.\PhysicalDrive1", true),
+            ("linux", "/home/u/disk.img", false),
+            ("windows", "/dev/sdb", false),
+            ("windows", r"C:\disk.img", false),
+        ];
+        for (os, path, valid) in cases {
+            let mut config = valid_config();
+            config.raw_device_path = Some(path.to_string());
+            let host = HostLimits {
+                os: os.to_string(),
+                ..host()
+            };
+            assert_eq!(validate_vm_config(&config, &host).is_empty(), valid, "{} on {}", path, os);
+        }
+    }
+
+    #[test]
+    fn test_every_preallocation_mode_is_accepted() {
+        for mode in storage::PREALLOCATION_MODES {
+            let mut config = valid_config();
+            config.preallocation = mode.to_string();
+            assert!(validate_vm_config(&config, &host()).is_empty(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn test_warnings_alone_are_not_errors() {
+        let mut config = valid_config();
+        config.cpu_cores = 16;
+        let issues = validate_vm_config(&config, &host());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_issue_serialization() {
+        let issue = ValidationIssue::warning("memory_mb", "high-memory", "High");
+        assert_eq!(
+            serde_json::to_value(&issue).unwrap(),
+            serde_json::json!({
+                "field": "memory_mb",
+                "code": "high-memory",
+                "message": "High",
+                "severity": "warning",
+            })
+        );
+    }
+}
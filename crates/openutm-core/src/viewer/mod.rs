@@ -0,0 +1,210 @@
+//! External SPICE/VNC viewer (virt-viewer's `remote-viewer`)
+//!
+//! The connection, including the SPICE password, is handed over in a `.vv`
+//! file rather than on the command line. The file is private to the user and
+//! removed as soon as the viewer exits or is stopped.
+
+use crate::qemu::detector;
+use crate::{storage, Result};
+use std::path::{Path, PathBuf};
+use tokio::sync::oneshot;
+
+/// Binary names virt-viewer installs for opening `.vv` files
+const VIEWER_BINARIES: [&str; 1] = ["remote-viewer"];
+
+/// What a `.vv` file tells the viewer to connect to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewerConnection {
+    pub protocol: String,
+    pub host: String,
+    pub port: u16,
+    pub password: Option<String>,
+    pub title: String,
+    pub fullscreen: bool,
+}
+
+/// A running viewer; dropping it leaves the viewer running
+pub struct ExternalViewer {
+    pub pid: u32,
+    stop: oneshot::Sender<()>,
+}
+
+impl ExternalViewer {
+    /// Terminate the viewer; its `.vv` file is removed once it has exited
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+    }
+}
+
+/// Locate `remote-viewer` on the PATH and the usual install locations
+pub fn find_viewer() -> Option<PathBuf> {
+    detector::find_tool(&VIEWER_BINARIES)
+}
+
+/// How to get `remote-viewer` on this OS
+pub fn install_hint() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "Install it with Homebrew: brew install virt-viewer",
+        "windows" => "Install virt-viewer from https://virt-manager.org/download",
+        _ => "Install the virt-viewer package, e.g. sudo apt install virt-viewer or sudo dnf install virt-viewer",
+    }
+}
+
+/// Render a virt-viewer connection file
+pub fn vv_file_contents(connection: &ViewerConnection) -> String {
+    let mut lines = vec![
+        "[virt-viewer]".to_string(),
+        format!("type={}", connection.protocol),
+        format!("host={}", connection.host),
+        format!("port={}", connection.port),
+    ];
+    if let Some(password) = &connection.password {
+        lines.push(format!("password={}", password));
+    }
+    // Newlines would let the VM name inject extra keys
+    lines.push(format!("title={}", connection.title.replace(['\r', '\n'], " ")));
+    lines.push(format!("fullscreen={}", u8::from(connection.fullscreen)));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Write the `.vv` file (mode 0600) and launch `binary` on it.
+///
+/// A background task removes the file when the viewer exits, or kills the
+/// viewer and then removes the file when `ExternalViewer::stop` is called.
+pub fn launch(binary: &Path, vv_path: &Path, connection: &ViewerConnection) -> Result<ExternalViewer> {
+    storage::write_secret_file(vv_path, &vv_file_contents(connection))?;
+
+    let mut command = tokio::process::Command::new(binary);
+    command.arg(vv_path);
+    let viewer = supervise(command, vv_path)?;
+    tracing::info!(pid = viewer.pid, binary = %binary.display(), "external viewer launched");
+    Ok(viewer)
+}
+
+/// Spawn `command` and remove `vv_path` once it exits or is stopped
+fn supervise(mut command: tokio::process::Command, vv_path: &Path) -> Result<ExternalViewer> {
+    let spawned = command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(err) => {
+            let _ = std::fs::remove_file(vv_path);
+            return Err(err.into());
+        }
+    };
+    let pid = child.id().unwrap_or_default();
+
+    let (stop, stopped) = oneshot::channel();
+    let vv_path = vv_path.to_path_buf();
+    tokio::spawn(async move {
+        tokio::select! {
+            status = child.wait() => {
+                tracing::debug!(pid, status = ?status.ok(), "external viewer exited");
+            }
+            Ok(()) = stopped => {
+                let _ = child.kill().await;
+            }
+        }
+        let _ = std::fs::remove_file(&vv_path);
+    });
+
+    Ok(ExternalViewer { pid, stop })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection() -> ViewerConnection {
+        ViewerConnection {
+            protocol: "spice".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 5930,
+            password: Some("s3cret".to_string()),
+            title: "Ubuntu\nproxy=evil".to_string(),
+            fullscreen: true,
+        }
+    }
+
+    async fn wait_until_removed(path: &Path) {
+        for _ in 0..100 {
+            if !path.exists() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("{} was not removed", path.display());
+    }
+
+    #[test]
+    fn test_vv_file_contents() {
+        let contents = vv_file_contents(&connection());
+        assert_eq!(
+            contents,
+            "[virt-viewer]\ntype=spice\nhost=127.0.0.1\nport=5930\npassword=s3cret\ntitle=Ubuntu proxy=evil\nfullscreen=1\n"
+        );
+
+        let vnc = ViewerConnection {
+            protocol: "vnc".to_string(),
+            password: None,
+            fullscreen: false,
+            ..connection()
+        };
+        let contents = vv_file_contents(&vnc);
+        assert!(contents.contains("type=vnc\n"));
+        assert!(!contents.contains("password="));
+        assert!(contents.contains("fullscreen=0\n"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_vv_file_is_private_and_removed_on_exit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let vv_path = temp_dir.path().join("vm-1.vv");
+        // `cat` reads the file and exits straight away, like a viewer that was closed
+        let viewer = launch(Path::new("cat"), &vv_path, &connection());
+        let mode = std::fs::metadata(&vv_path).map(|metadata| metadata.permissions().mode() & 0o777);
+        assert!(viewer.unwrap().pid > 0);
+        // The viewer may already have exited and cleaned up
+        if let Ok(mode) = mode {
+            assert_eq!(mode, 0o600);
+        }
+        wait_until_removed(&vv_path).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_kills_viewer_and_removes_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let vv_path = temp_dir.path().join("vm-1.vv");
+        storage::write_secret_file(&vv_path, &vv_file_contents(&connection())).unwrap();
+        let mut command = tokio::process::Command::new("sleep");
+        command.arg("30");
+
+        let viewer = supervise(command, &vv_path).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(vv_path.exists());
+
+        viewer.stop();
+        wait_until_removed(&vv_path).await;
+    }
+
+    #[tokio::test]
+    async fn test_launch_missing_binary_removes_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let vv_path = temp_dir.path().join("vm-1.vv");
+        assert!(launch(&temp_dir.path().join("missing-viewer"), &vv_path, &connection()).is_err());
+        assert!(!vv_path.exists());
+    }
+
+    #[test]
+    fn test_install_hint_mentions_virt_viewer() {
+        assert!(install_hint().contains("virt-viewer"));
+    }
+}
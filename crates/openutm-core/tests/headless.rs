@@ -0,0 +1,32 @@
+//! Drives the core crate the way a headless frontend would: no Tauri, just
+//! the config store and the QEMU command builder
+
+use openutm_core::config::{ConfigStore, VMRecord};
+use openutm_core::qemu::{Accelerator, QemuCommand};
+
+fn headless_vm() -> VMRecord {
+    VMRecord {
+        id: "headless-1".to_string(),
+        name: "Headless".to_string(),
+        memory_mb: 1024,
+        cpu_cores: 1,
+        disk_size_gb: 8,
+        restart_policy: "never".to_string(),
+        ..VMRecord::default()
+    }
+}
+
+#[test]
+fn test_store_and_build_args_without_ui() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let store = ConfigStore::new(temp.path().join("config.db")).unwrap();
+    store.create_vm(&headless_vm()).unwrap();
+
+    let vm = store.get_vm("headless-1").unwrap().expect("VM was stored");
+    let command = QemuCommand::for_vm(&vm, "/tmp/headless-1.qcow2", Accelerator::Tcg).unwrap();
+    let args = command.build_vm_args(&vm, "/tmp/headless-1.qmp");
+
+    assert!(args.iter().any(|arg| arg == "-drive"));
+    assert!(args.iter().any(|arg| arg.contains("/tmp/headless-1.qcow2")));
+    assert!(args.iter().any(|arg| arg.contains("/tmp/headless-1.qmp")));
+}
@@ -5,7 +5,7 @@ ROOT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
 DEST_DIR="/Applications"
 
 ELECTRON_APP="$ROOT_DIR/apps/electron/release/mac-arm64/OpenUTM (Electron).app"
-TAURI_APP="$ROOT_DIR/apps/tauri/src-tauri/target/release/bundle/macos/OpenUTM (Tauri).app"
+TAURI_APP="$ROOT_DIR/target/release/bundle/macos/OpenUTM (Tauri).app"
 
 if [[ ! -d "$ELECTRON_APP" ]]; then
   echo "missing bundle: $ELECTRON_APP" >&2